@@ -0,0 +1,148 @@
+//! The operations the admin client sends in IncrementalAlterConfigs requests, and the
+//! versions of the requests it sends depending on what the broker supports.
+
+use crate::clients::api_versions::{NodeApiVersions, VersionedFeature};
+use crate::common::protocol::api_keys::ApiKeys;
+
+/// Creating topics with the number of partitions or the replication factor of the broker,
+/// set to -1 in the request.
+pub const CREATE_TOPICS_WITH_BROKER_DEFAULTS: VersionedFeature = VersionedFeature {
+    api: ApiKeys::CreateTopics,
+    min_version: 4,
+    description: "creating topics with the default partitions or replication factor of the broker",
+};
+
+/// The features a CreateTopics request for topics of `(num_partitions, replication_factor)`
+/// needs, either of them -1 to use the default of the broker.
+pub fn create_topics_features(
+    topics: impl IntoIterator<Item = (i32, i16)>,
+) -> Vec<VersionedFeature> {
+    let mut topics = topics.into_iter();
+    match topics.any(|(num_partitions, replication_factor)| {
+        num_partitions == -1 || replication_factor == -1
+    }) {
+        true => vec![CREATE_TOPICS_WITH_BROKER_DEFAULTS],
+        false => vec![],
+    }
+}
+
+/// The API to describe topics with: DescribeTopicPartitions, which pages through the
+/// partitions of large topics, or Metadata on the brokers which don't support it.
+pub fn describe_topics_api(versions: &NodeApiVersions) -> ApiKeys {
+    match versions.supports(ApiKeys::DescribeTopicPartitions) {
+        true => ApiKeys::DescribeTopicPartitions,
+        false => ApiKeys::Metadata,
+    }
+}
+
+/// The type of an operation on a config, with its id in IncrementalAlterConfigs requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlterConfigOpType {
+    /// Sets the value of the config.
+    Set,
+    /// Reverts the config to its default value.
+    Delete,
+    /// Adds values to a list config, keeping those it has.
+    Append,
+    /// Removes values from a list config.
+    Subtract,
+}
+
+impl AlterConfigOpType {
+    pub fn id(&self) -> i8 {
+        match self {
+            AlterConfigOpType::Set => 0,
+            AlterConfigOpType::Delete => 1,
+            AlterConfigOpType::Append => 2,
+            AlterConfigOpType::Subtract => 3,
+        }
+    }
+
+    pub fn for_id(id: i8) -> Option<Self> {
+        match id {
+            0 => Some(AlterConfigOpType::Set),
+            1 => Some(AlterConfigOpType::Delete),
+            2 => Some(AlterConfigOpType::Append),
+            3 => Some(AlterConfigOpType::Subtract),
+            _ => None,
+        }
+    }
+}
+
+/// An operation on a config of a resource.
+///
+/// The value of an APPEND or SUBTRACT is a comma-separated list of the values to add or
+/// remove, e.g. `0:1,0:2` for `leader.replication.throttled.replicas`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlterConfigOp {
+    pub name: String,
+    /// The value of the operation, `None` for a DELETE.
+    pub value: Option<String>,
+    pub op_type: AlterConfigOpType,
+}
+
+impl AlterConfigOp {
+    pub fn set(name: &str, value: &str) -> Self {
+        Self::new(name, Some(value), AlterConfigOpType::Set)
+    }
+
+    pub fn delete(name: &str) -> Self {
+        Self::new(name, None, AlterConfigOpType::Delete)
+    }
+
+    pub fn append(name: &str, values: &str) -> Self {
+        Self::new(name, Some(values), AlterConfigOpType::Append)
+    }
+
+    pub fn subtract(name: &str, values: &str) -> Self {
+        Self::new(name, Some(values), AlterConfigOpType::Subtract)
+    }
+
+    fn new(name: &str, value: Option<&str>, op_type: AlterConfigOpType) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.map(str::to_string),
+            op_type,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::requests::api_versions_response::ApiVersion;
+
+    #[test]
+    fn test_versions_for_older_brokers() {
+        let broker = NodeApiVersions::new([
+            ApiVersion {
+                api_key: ApiKeys::CreateTopics.id(),
+                min_version: 2,
+                max_version: 3,
+            },
+            ApiKeys::Metadata.into(),
+        ]);
+        assert_eq!(ApiKeys::Metadata, describe_topics_api(&broker));
+        let features = create_topics_features([(3, 1)]);
+        assert_eq!(
+            Ok(3),
+            broker.latest_usable_version(ApiKeys::CreateTopics, &features)
+        );
+        let features = create_topics_features([(3, 1), (-1, 1)]);
+        assert!(
+            broker
+                .latest_usable_version(ApiKeys::CreateTopics, &features)
+                .is_err()
+        );
+
+        let broker = NodeApiVersions::new(ApiKeys::ALL.iter().copied().map(ApiVersion::from));
+        assert_eq!(
+            ApiKeys::DescribeTopicPartitions,
+            describe_topics_api(&broker)
+        );
+        assert_eq!(
+            Ok(ApiKeys::CreateTopics.latest_version()),
+            broker.latest_usable_version(ApiKeys::CreateTopics, &features)
+        );
+    }
+}
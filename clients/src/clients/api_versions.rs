@@ -0,0 +1,236 @@
+//! The versions of the APIs each broker supports, as it told the client in its ApiVersions
+//! response, from which the client picks the version of every request it sends.
+//!
+//! A client sends the latest version both it and the broker support, so that it keeps working
+//! against older brokers. A feature of a request which only newer versions carry is a
+//! [VersionedFeature]: if the broker is too old for it, the request fails before it is sent,
+//! with an error naming the feature, rather than being sent at a version which silently drops
+//! it.
+
+use crate::common::protocol::api_keys::ApiKeys;
+use crate::common::protocol::errors::{ApiError, Errors};
+use crate::common::requests::api_versions_response::{ApiVersion, ApiVersionsResponse};
+use std::collections::HashMap;
+
+/// A feature of a request carried from a version of its API on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionedFeature {
+    pub api: ApiKeys,
+    pub min_version: i16,
+    /// What the feature does, for the errors of the requests needing it.
+    pub description: &'static str,
+}
+
+/// The versions of the APIs a broker supports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeApiVersions {
+    /// The supported versions by API key, including the APIs this client does not know.
+    supported: HashMap<i16, ApiVersion>,
+}
+
+impl NodeApiVersions {
+    pub fn new(api_versions: impl IntoIterator<Item = ApiVersion>) -> Self {
+        Self {
+            supported: api_versions
+                .into_iter()
+                .map(|api| (api.api_key, api))
+                .collect(),
+        }
+    }
+
+    /// The versions in the ApiVersions `response` of a broker, or its error.
+    pub fn from_response(response: &ApiVersionsResponse) -> Result<Self, ApiError> {
+        if response.error != Errors::None {
+            return Err(ApiError::new(
+                response.error,
+                format!(
+                    "The ApiVersions request failed: {}",
+                    response.error.message()
+                ),
+            ));
+        }
+        Ok(Self::new(response.api_keys.iter().copied()))
+    }
+
+    /// The versions of `api` the broker supports, `None` if it does not support it at all.
+    pub fn api_version(&self, api: ApiKeys) -> Option<&ApiVersion> {
+        self.supported.get(&api.id())
+    }
+
+    /// Whether the broker supports a version of `api` this client can send.
+    pub fn supports(&self, api: ApiKeys) -> bool {
+        self.latest_usable_version(api, &[]).is_ok()
+    }
+
+    /// The latest version of `api` both this client and the broker support and which carries
+    /// every one of `features` for the API. Fails with `UNSUPPORTED_VERSION` if there is none,
+    /// naming the first feature the broker is too old for.
+    pub fn latest_usable_version(
+        &self,
+        api: ApiKeys,
+        features: &[VersionedFeature],
+    ) -> Result<i16, ApiError> {
+        let Some(supported) = self.api_version(api) else {
+            return Err(ApiError::new(
+                Errors::UnsupportedVersion,
+                format!("The broker does not support {}", api.name()),
+            ));
+        };
+        let max_version = supported.max_version.min(api.latest_version());
+        let min_version = supported.min_version.max(api.oldest_version());
+        if min_version > max_version {
+            return Err(ApiError::new(
+                Errors::UnsupportedVersion,
+                format!(
+                    "The broker supports {} versions {} to {}, none of which this client \
+                    supports: it supports versions {} to {}",
+                    api.name(),
+                    supported.min_version,
+                    supported.max_version,
+                    api.oldest_version(),
+                    api.latest_version()
+                ),
+            ));
+        }
+        if let Some(feature) = features
+            .iter()
+            .find(|f| f.api == api && f.min_version > max_version)
+        {
+            return Err(ApiError::new(
+                Errors::UnsupportedVersion,
+                format!(
+                    "The broker does not support {}: it needs {} version {} or above, but the \
+                    broker supports up to version {}",
+                    feature.description,
+                    api.name(),
+                    feature.min_version,
+                    supported.max_version
+                ),
+            ));
+        }
+        Ok(max_version)
+    }
+}
+
+/// The versions of the APIs of every broker the client is connected to.
+///
+/// The versions of a broker are negotiated on every new connection, before any other request
+/// is sent to it, since the broker may have been upgraded or downgraded meanwhile.
+#[derive(Debug, Default)]
+pub struct ApiVersions {
+    nodes: HashMap<i32, NodeApiVersions>,
+}
+
+impl ApiVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the versions `node_id` sent in its ApiVersions response.
+    pub fn update(&mut self, node_id: i32, versions: NodeApiVersions) {
+        self.nodes.insert(node_id, versions);
+    }
+
+    /// Forgets the versions of `node_id`, when its connection is closed.
+    pub fn remove(&mut self, node_id: i32) {
+        self.nodes.remove(&node_id);
+    }
+
+    /// The versions of `node_id`, `None` until they are negotiated.
+    pub fn get(&self, node_id: i32) -> Option<&NodeApiVersions> {
+        self.nodes.get(&node_id)
+    }
+
+    /// The version of `api` to send to `node_id` with `features`, as
+    /// [NodeApiVersions::latest_usable_version]. Fails if the versions of the node are not
+    /// negotiated yet.
+    pub fn latest_usable_version(
+        &self,
+        node_id: i32,
+        api: ApiKeys,
+        features: &[VersionedFeature],
+    ) -> Result<i16, ApiError> {
+        self.get(node_id)
+            .ok_or_else(|| {
+                ApiError::new(
+                    Errors::UnsupportedVersion,
+                    format!("The API versions of node {node_id} are not negotiated yet"),
+                )
+            })?
+            .latest_usable_version(api, features)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(api: ApiKeys, min_version: i16, max_version: i16) -> NodeApiVersions {
+        NodeApiVersions::new([ApiVersion {
+            api_key: api.id(),
+            min_version,
+            max_version,
+        }])
+    }
+
+    #[test]
+    fn test_latest_usable_version() {
+        // An older broker: the request is downgraded to its latest version.
+        let old = versions(ApiKeys::Fetch, 0, 12);
+        assert_eq!(Ok(12), old.latest_usable_version(ApiKeys::Fetch, &[]));
+        // A newer broker: the request is sent at the latest version of the client.
+        let new = versions(ApiKeys::Fetch, 4, 100);
+        assert_eq!(
+            Ok(ApiKeys::Fetch.latest_version()),
+            new.latest_usable_version(ApiKeys::Fetch, &[])
+        );
+
+        let error = old
+            .latest_usable_version(ApiKeys::Produce, &[])
+            .unwrap_err();
+        assert_eq!(Errors::UnsupportedVersion, error.error());
+        assert!(!old.supports(ApiKeys::Produce));
+        // No version in common.
+        let error = versions(ApiKeys::Fetch, 0, 3)
+            .latest_usable_version(ApiKeys::Fetch, &[])
+            .unwrap_err();
+        assert!(
+            error.message().contains("versions 0 to 3"),
+            "{}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_features_fail_on_older_brokers() {
+        let feature = VersionedFeature {
+            api: ApiKeys::ListOffsets,
+            min_version: 7,
+            description: "looking up the offset of the max timestamp",
+        };
+        assert_eq!(
+            Ok(9),
+            versions(ApiKeys::ListOffsets, 0, 9)
+                .latest_usable_version(ApiKeys::ListOffsets, &[feature])
+        );
+        let error = versions(ApiKeys::ListOffsets, 0, 6)
+            .latest_usable_version(ApiKeys::ListOffsets, &[feature])
+            .unwrap_err();
+        assert_eq!(Errors::UnsupportedVersion, error.error());
+        assert!(error.message().contains(feature.description));
+
+        let mut api_versions = ApiVersions::new();
+        assert!(
+            api_versions
+                .latest_usable_version(1, ApiKeys::ListOffsets, &[])
+                .is_err()
+        );
+        api_versions.update(1, versions(ApiKeys::ListOffsets, 0, 6));
+        assert_eq!(
+            Ok(6),
+            api_versions.latest_usable_version(1, ApiKeys::ListOffsets, &[])
+        );
+        api_versions.remove(1);
+        assert_eq!(None, api_versions.get(1));
+    }
+}
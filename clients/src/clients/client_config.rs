@@ -0,0 +1,346 @@
+//! The configuration shared by the clients, which accepts the property names of the Java
+//! clients as well as the ones of librdkafka, so that the configuration of an existing
+//! application can be reused as is.
+//!
+//! The Java names are the canonical ones: a librdkafka name is resolved to the Java name of
+//! the same property through [PROPERTY_ALIASES]. The few properties librdkafka has no Java
+//! counterpart for, e.g. `sasl.username`, are accepted under their librdkafka names.
+
+use crate::clients::producer::MAX_REQUEST_SIZE_CONFIG;
+use crate::clients::record_accumulator::LINGER_MS_CONFIG;
+use crate::common::security_protocol::SecurityProtocol;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub const BOOTSTRAP_SERVERS_CONFIG: &str = "bootstrap.servers";
+pub const CLIENT_ID_CONFIG: &str = "client.id";
+pub const GROUP_ID_CONFIG: &str = "group.id";
+pub const ACKS_CONFIG: &str = "acks";
+pub const COMPRESSION_TYPE_CONFIG: &str = "compression.type";
+pub const METADATA_MAX_AGE_CONFIG: &str = "metadata.max.age.ms";
+pub const SECURITY_PROTOCOL_CONFIG: &str = "security.protocol";
+pub const SASL_MECHANISM_CONFIG: &str = "sasl.mechanism";
+pub const SASL_JAAS_CONFIG: &str = "sasl.jaas.config";
+/// The user name of the PLAIN and SCRAM mechanisms, which the Java clients take from
+/// `sasl.jaas.config` instead.
+pub const SASL_USERNAME_CONFIG: &str = "sasl.username";
+/// The password of the PLAIN and SCRAM mechanisms, which the Java clients take from
+/// `sasl.jaas.config` instead.
+pub const SASL_PASSWORD_CONFIG: &str = "sasl.password";
+
+/// The librdkafka names of properties, with the Java name they stand for.
+pub const PROPERTY_ALIASES: &[(&str, &str)] = &[
+    ("metadata.broker.list", BOOTSTRAP_SERVERS_CONFIG),
+    ("sasl.mechanisms", SASL_MECHANISM_CONFIG),
+    ("request.required.acks", ACKS_CONFIG),
+    ("compression.codec", COMPRESSION_TYPE_CONFIG),
+    ("queue.buffering.max.ms", LINGER_MS_CONFIG),
+    ("message.max.bytes", MAX_REQUEST_SIZE_CONFIG),
+    (
+        "topic.metadata.refresh.interval.ms",
+        METADATA_MAX_AGE_CONFIG,
+    ),
+];
+
+/// The scheme a connection string may start with.
+const CONNECTION_STRING_SCHEME: &str = "kafka://";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ClientConfigError {
+    #[error("Missing required configuration {0} which has no default value")]
+    MissingConfig(&'static str),
+
+    #[error("Invalid value {value} for configuration {name}: {reason}")]
+    InvalidValue {
+        name: String,
+        value: String,
+        reason: String,
+    },
+
+    #[error("{alias} is an alias of {name}, they cannot be set to different values")]
+    ConflictingAlias { alias: String, name: String },
+}
+
+/// The Java name of the property `name`, which is `name` itself unless it is a librdkafka
+/// alias.
+pub fn canonical_name(name: &str) -> &str {
+    PROPERTY_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, canonical)| canonical)
+}
+
+/// The properties of a client, keyed by their Java names.
+///
+/// Properties the clients don't know are kept, as the Java clients do, so that a
+/// configuration written for another client is accepted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientConfig {
+    properties: BTreeMap<String, String>,
+    /// The name each property was set under, to tell a conflicting alias from an update.
+    names: BTreeMap<String, String>,
+}
+
+impl ClientConfig {
+    /// The configuration of `properties`, named either way. A property set both under its
+    /// Java name and a librdkafka alias must have the same value.
+    pub fn from_properties<'a>(
+        properties: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, ClientConfigError> {
+        let mut config = Self::default();
+        for (name, value) in properties {
+            config.set(name, value)?;
+        }
+        Ok(config)
+    }
+
+    /// Parses a connection string in the form
+    /// `[kafka://]<host>:<port>[,<host>:<port>...][?<name>=<value>[&<name>=<value>...]]`,
+    /// e.g. `kafka://broker1:9092,broker2:9092?security.protocol=SASL_SSL&sasl.mechanism=PLAIN`.
+    pub fn from_connection_string(connection_string: &str) -> Result<Self, ClientConfigError> {
+        let connection_string = connection_string.trim();
+        let connection_string = connection_string
+            .strip_prefix(CONNECTION_STRING_SCHEME)
+            .unwrap_or(connection_string);
+        let (servers, query) = connection_string
+            .split_once('?')
+            .unwrap_or((connection_string, ""));
+        let mut config = Self::default();
+        config.set(BOOTSTRAP_SERVERS_CONFIG, servers)?;
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            let (name, value) =
+                parameter
+                    .split_once('=')
+                    .ok_or_else(|| ClientConfigError::InvalidValue {
+                        name: "connection string".to_string(),
+                        value: parameter.to_string(),
+                        reason: "expected <name>=<value>".to_string(),
+                    })?;
+            config.set(name, value)?;
+        }
+        Ok(config)
+    }
+
+    /// Sets the property `name`, which may be a librdkafka alias.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ClientConfigError> {
+        let name = name.trim();
+        let value = value.trim();
+        let canonical = canonical_name(name);
+        if let Some(set_as) = self.names.get(canonical)
+            && set_as != name
+            && self.properties[canonical] != value
+        {
+            let alias = if canonical == name { set_as } else { name };
+            return Err(ClientConfigError::ConflictingAlias {
+                alias: alias.to_string(),
+                name: canonical.to_string(),
+            });
+        }
+        self.properties
+            .insert(canonical.to_string(), value.to_string());
+        self.names.insert(canonical.to_string(), name.to_string());
+        Ok(())
+    }
+
+    /// The value of the property `name`, which may be a librdkafka alias.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.properties
+            .get(canonical_name(name))
+            .map(String::as_str)
+    }
+
+    /// The properties, keyed by their Java names.
+    pub fn properties(&self) -> &BTreeMap<String, String> {
+        &self.properties
+    }
+
+    /// The `bootstrap.servers` as hosts and ports, without the brackets of an IPv6 literal.
+    pub fn bootstrap_servers(&self) -> Result<Vec<(String, u16)>, ClientConfigError> {
+        let servers = self
+            .get(BOOTSTRAP_SERVERS_CONFIG)
+            .ok_or(ClientConfigError::MissingConfig(BOOTSTRAP_SERVERS_CONFIG))?;
+        let servers = servers
+            .split(',')
+            .map(str::trim)
+            .filter(|server| !server.is_empty())
+            .map(parse_server)
+            .collect::<Result<Vec<_>, _>>()?;
+        if servers.is_empty() {
+            return Err(ClientConfigError::MissingConfig(BOOTSTRAP_SERVERS_CONFIG));
+        }
+        Ok(servers)
+    }
+
+    /// The `security.protocol`, `PLAINTEXT` by default. The name is case-insensitive, as
+    /// librdkafka takes it in lower case.
+    pub fn security_protocol(&self) -> Result<SecurityProtocol, ClientConfigError> {
+        let Some(protocol) = self.get(SECURITY_PROTOCOL_CONFIG) else {
+            return Ok(SecurityProtocol::Plaintext);
+        };
+        SecurityProtocol::for_name(protocol).ok_or_else(|| ClientConfigError::InvalidValue {
+            name: SECURITY_PROTOCOL_CONFIG.to_string(),
+            value: protocol.to_string(),
+            reason: format!("expected one of {}", SecurityProtocol::names().join(", ")),
+        })
+    }
+
+    /// The user name and password of the PLAIN and SCRAM mechanisms, from `sasl.username`
+    /// and `sasl.password` or else from the `username` and `password` options of
+    /// `sasl.jaas.config`.
+    pub fn sasl_credentials(&self) -> Option<(String, String)> {
+        if let (Some(username), Some(password)) = (
+            self.get(SASL_USERNAME_CONFIG),
+            self.get(SASL_PASSWORD_CONFIG),
+        ) {
+            return Some((username.to_string(), password.to_string()));
+        }
+        let jaas = self.get(SASL_JAAS_CONFIG)?;
+        Some((
+            jaas_option(jaas, "username")?,
+            jaas_option(jaas, "password")?,
+        ))
+    }
+}
+
+/// Parses a bootstrap server in the form `<host>:<port>`, the host of an IPv6 literal being
+/// in brackets.
+fn parse_server(server: &str) -> Result<(String, u16), ClientConfigError> {
+    let invalid = || ClientConfigError::InvalidValue {
+        name: BOOTSTRAP_SERVERS_CONFIG.to_string(),
+        value: server.to_string(),
+        reason: "expected <host>:<port>".to_string(),
+    };
+    let (host, port) = server.rsplit_once(':').ok_or_else(invalid)?;
+    let host = match host.strip_prefix('[') {
+        Some(host) => host.strip_suffix(']').ok_or_else(invalid)?,
+        None if host.contains(':') => return Err(invalid()),
+        None => host,
+    };
+    let port = port.parse::<u16>().map_err(|_| invalid())?;
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), port))
+}
+
+/// The value of the option `key="<value>"` of a JAAS configuration.
+fn jaas_option(jaas: &str, key: &str) -> Option<String> {
+    jaas.split_whitespace()
+        .filter_map(|option| option.strip_prefix(key)?.strip_prefix("=\""))
+        .map(|value| value.trim_end_matches(';').trim_end_matches('"'))
+        .next()
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_librdkafka_names_resolve_to_java_names() {
+        let config = ClientConfig::from_properties([
+            ("metadata.broker.list", "broker1:9092, [::1]:9093"),
+            ("security.protocol", "sasl_ssl"),
+            ("sasl.mechanisms", "SCRAM-SHA-256"),
+            ("sasl.username", "alice"),
+            ("sasl.password", "secret"),
+            ("queue.buffering.max.ms", "20"),
+            ("client.id", "app"),
+        ])
+        .unwrap();
+        assert_eq!(Some("SCRAM-SHA-256"), config.get(SASL_MECHANISM_CONFIG));
+        assert_eq!(Some("20"), config.get(LINGER_MS_CONFIG));
+        assert_eq!(Some("20"), config.get("queue.buffering.max.ms"));
+        assert_eq!(Some("app"), config.get(CLIENT_ID_CONFIG));
+        assert_eq!(
+            vec![("broker1".to_string(), 9092), ("::1".to_string(), 9093)],
+            config.bootstrap_servers().unwrap()
+        );
+        assert_eq!(
+            SecurityProtocol::SaslSsl,
+            config.security_protocol().unwrap()
+        );
+        assert_eq!(
+            Some(("alice".to_string(), "secret".to_string())),
+            config.sasl_credentials()
+        );
+
+        // The same property under both names must agree.
+        assert!(
+            ClientConfig::from_properties([("linger.ms", "5"), ("queue.buffering.max.ms", "5")])
+                .is_ok()
+        );
+        assert_eq!(
+            Err(ClientConfigError::ConflictingAlias {
+                alias: "queue.buffering.max.ms".to_string(),
+                name: LINGER_MS_CONFIG.to_string(),
+            }),
+            ClientConfig::from_properties([("linger.ms", "5"), ("queue.buffering.max.ms", "10")])
+        );
+        assert!(
+            ClientConfig::from_properties([("queue.buffering.max.ms", "10"), ("linger.ms", "5")])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_java_names() {
+        let config = ClientConfig::from_properties([
+            (BOOTSTRAP_SERVERS_CONFIG, "broker1:9092"),
+            (SECURITY_PROTOCOL_CONFIG, "SASL_PLAINTEXT"),
+            (
+                SASL_JAAS_CONFIG,
+                "org.apache.kafka.common.security.plain.PlainLoginModule required \
+                 username=\"bob\" password=\"pw\";",
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            SecurityProtocol::SaslPlaintext,
+            config.security_protocol().unwrap()
+        );
+        assert_eq!(
+            Some(("bob".to_string(), "pw".to_string())),
+            config.sasl_credentials()
+        );
+
+        let config = ClientConfig::from_properties([(SECURITY_PROTOCOL_CONFIG, "TLS")]).unwrap();
+        assert_eq!(
+            Err(ClientConfigError::MissingConfig(BOOTSTRAP_SERVERS_CONFIG)),
+            config.bootstrap_servers()
+        );
+        assert!(matches!(
+            config.security_protocol(),
+            Err(ClientConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_connection_string() {
+        let config = ClientConfig::from_connection_string(
+            "kafka://broker1:9092,broker2:9092?security.protocol=SSL&request.required.acks=all",
+        )
+        .unwrap();
+        assert_eq!(2, config.bootstrap_servers().unwrap().len());
+        assert_eq!(SecurityProtocol::Ssl, config.security_protocol().unwrap());
+        assert_eq!(Some("all"), config.get(ACKS_CONFIG));
+
+        let config = ClientConfig::from_connection_string("localhost:9092").unwrap();
+        assert_eq!(
+            vec![("localhost".to_string(), 9092)],
+            config.bootstrap_servers().unwrap()
+        );
+        assert_eq!(
+            SecurityProtocol::Plaintext,
+            config.security_protocol().unwrap()
+        );
+
+        assert!(
+            ClientConfig::from_connection_string("localhost")
+                .unwrap()
+                .bootstrap_servers()
+                .is_err()
+        );
+        assert!(ClientConfig::from_connection_string("localhost:9092?acks").is_err());
+    }
+}
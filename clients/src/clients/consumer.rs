@@ -0,0 +1,726 @@
+use crate::clients::api_versions::VersionedFeature;
+use crate::common::isolation_level::IsolationLevel;
+use crate::common::protocol::api_keys::ApiKeys;
+use crate::common::protocol::errors::{ApiError, Errors};
+use crate::common::requests::list_offsets_request::{
+    EARLIEST_LOCAL_TIMESTAMP, LATEST_TIERED_TIMESTAMP, LATEST_TIMESTAMP, MAX_TIMESTAMP,
+};
+use crate::common::topic_partition::TopicPartition;
+use crate::common::utils::time::Time;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+pub const ENABLE_AUTO_COMMIT_CONFIG: &str = "enable.auto.commit";
+pub const AUTO_COMMIT_INTERVAL_MS_CONFIG: &str = "auto.commit.interval.ms";
+pub const DEFAULT_AUTO_COMMIT_INTERVAL_MS: i64 = 5_000;
+
+/// The default timeout of the commits made before partitions are revoked, that of
+/// `default.api.timeout.ms` in the Java consumer: 60 seconds.
+pub const DEFAULT_REVOCATION_COMMIT_TIMEOUT_MS: i64 = 60_000;
+
+/// The default timeout of [ConsumerSession::close], as in the Java consumer: 30 seconds.
+pub const DEFAULT_CLOSE_TIMEOUT_MS: i64 = 30_000;
+
+/// The reason sent with the LeaveGroup of a closing consumer, shown in the logs of the group
+/// coordinator.
+pub const CLOSE_LEAVE_GROUP_REASON: &str = "the consumer is being closed";
+
+/// The requests a consumer sends to its group coordinator.
+pub trait GroupCoordinatorClient {
+    /// Commits `offsets`, waiting up to `timeout_ms` for the response.
+    fn commit_offsets(
+        &mut self,
+        offsets: &BTreeMap<TopicPartition, i64>,
+        timeout_ms: i64,
+    ) -> Result<(), ApiError>;
+
+    /// Sends a LeaveGroup with `reason`, waiting up to `timeout_ms` for the response.
+    fn leave_group(&mut self, reason: &str, timeout_ms: i64) -> Result<(), ApiError>;
+}
+
+/// The first offset of a partition whose record has a timestamp at or after the one looked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OffsetAndTimestamp {
+    pub offset: i64,
+    pub timestamp: i64,
+    pub leader_epoch: Option<i32>,
+}
+
+impl OffsetAndTimestamp {
+    pub fn new(offset: i64, timestamp: i64, leader_epoch: Option<i32>) -> Self {
+        Self {
+            offset,
+            timestamp,
+            leader_epoch,
+        }
+    }
+}
+
+/// The ListOffsets requests a consumer sends to the leaders of its partitions.
+pub trait ListOffsetsClient {
+    /// Looks up the offset of each partition of `timestamps` for its timestamp, which may be
+    /// one of the special timestamps of
+    /// [list_offsets_request](crate::common::requests::list_offsets_request), waiting up to
+    /// `timeout_ms` for the responses. A partition is `None`, or missing, if no record has a
+    /// timestamp at or after the one looked up.
+    fn list_offsets(
+        &mut self,
+        timestamps: &BTreeMap<TopicPartition, i64>,
+        timeout_ms: i64,
+    ) -> Result<BTreeMap<TopicPartition, Option<OffsetAndTimestamp>>, ApiError>;
+}
+
+/// Looking up offsets bounded by the last stable offset, for `isolation.level=read_committed`.
+pub const READ_COMMITTED_LIST_OFFSETS: VersionedFeature = VersionedFeature {
+    api: ApiKeys::ListOffsets,
+    min_version: 2,
+    description: "listing offsets with isolation.level=read_committed",
+};
+
+pub const MAX_TIMESTAMP_LIST_OFFSETS: VersionedFeature = VersionedFeature {
+    api: ApiKeys::ListOffsets,
+    min_version: 7,
+    description: "looking up the offset of the max timestamp",
+};
+
+pub const EARLIEST_LOCAL_TIMESTAMP_LIST_OFFSETS: VersionedFeature = VersionedFeature {
+    api: ApiKeys::ListOffsets,
+    min_version: 8,
+    description: "looking up the earliest local offset",
+};
+
+pub const LATEST_TIERED_TIMESTAMP_LIST_OFFSETS: VersionedFeature = VersionedFeature {
+    api: ApiKeys::ListOffsets,
+    min_version: 9,
+    description: "looking up the latest tiered offset",
+};
+
+/// Fetching the committed offsets only once the transactions committing them completed, for
+/// `isolation.level=read_committed`.
+pub const STABLE_OFFSET_FETCH: VersionedFeature = VersionedFeature {
+    api: ApiKeys::OffsetFetch,
+    min_version: 7,
+    description: "fetching committed offsets with isolation.level=read_committed",
+};
+
+/// The features a ListOffsets request looking up `timestamps` at `isolation_level` needs
+/// from the version it is sent at.
+pub fn list_offsets_features(
+    isolation_level: IsolationLevel,
+    timestamps: impl IntoIterator<Item = i64>,
+) -> Vec<VersionedFeature> {
+    let mut features = vec![];
+    if isolation_level == IsolationLevel::ReadCommitted {
+        features.push(READ_COMMITTED_LIST_OFFSETS);
+    }
+    for timestamp in timestamps {
+        let feature = match timestamp {
+            MAX_TIMESTAMP => MAX_TIMESTAMP_LIST_OFFSETS,
+            EARLIEST_LOCAL_TIMESTAMP => EARLIEST_LOCAL_TIMESTAMP_LIST_OFFSETS,
+            LATEST_TIERED_TIMESTAMP => LATEST_TIERED_TIMESTAMP_LIST_OFFSETS,
+            _ => continue,
+        };
+        if !features.contains(&feature) {
+            features.push(feature);
+        }
+    }
+    features
+}
+
+/// The features an OffsetFetch request of a consumer at `isolation_level` needs.
+pub fn offset_fetch_features(isolation_level: IsolationLevel) -> Vec<VersionedFeature> {
+    match isolation_level {
+        IsolationLevel::ReadUncommitted => vec![],
+        IsolationLevel::ReadCommitted => vec![STABLE_OFFSET_FETCH],
+    }
+}
+
+/// Looks up, for each partition of `timestamps`, the first offset whose record has a
+/// timestamp at or after the one given, or `None` if there is none, e.g. the timestamp is
+/// later than the last record of the partition.
+///
+/// The timestamps must not be negative: the special timestamps are not looked up here.
+pub fn offsets_for_times<L: ListOffsetsClient>(
+    client: &mut L,
+    timestamps: &BTreeMap<TopicPartition, i64>,
+    timeout_ms: i64,
+) -> Result<BTreeMap<TopicPartition, Option<OffsetAndTimestamp>>, ApiError> {
+    if let Some((tp, timestamp)) = timestamps.iter().find(|(_, timestamp)| **timestamp < 0) {
+        return Err(ApiError::new(
+            Errors::InvalidRequest,
+            format!("The timestamp {timestamp} to look up for {tp} is negative"),
+        ));
+    }
+    if timestamps.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let mut offsets = client.list_offsets(timestamps, timeout_ms)?;
+    Ok(timestamps
+        .keys()
+        .map(|tp| (tp.clone(), offsets.remove(tp).flatten()))
+        .collect())
+}
+
+/// Wakes up the polls of a consumer waiting for records once it is closed.
+///
+/// A poll blocked on another thread would otherwise wait out its whole timeout on a consumer
+/// which no longer fetches, holding up the shutdown of the application.
+#[derive(Debug, Clone, Default)]
+pub struct PollWakeup {
+    closed: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl PollWakeup {
+    /// Waits up to `timeout` for records, returning whether the consumer was closed meanwhile.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let (closed, condvar) = &*self.closed;
+        let closed = closed.lock().expect("poll wakeup lock poisoned");
+        let (closed, _) = condvar
+            .wait_timeout_while(closed, timeout, |closed| !*closed)
+            .expect("poll wakeup lock poisoned");
+        *closed
+    }
+
+    pub fn is_closed(&self) -> bool {
+        *self.closed.0.lock().expect("poll wakeup lock poisoned")
+    }
+
+    fn close(&self) {
+        let (closed, condvar) = &*self.closed;
+        *closed.lock().expect("poll wakeup lock poisoned") = true;
+        condvar.notify_all();
+    }
+}
+
+/// The membership of a consumer in its group and the positions it consumed up to, which it
+/// commits while it polls if `enable.auto.commit` is set, before its partitions are revoked
+/// and on close.
+pub struct ConsumerSession<C> {
+    coordinator: C,
+    time: Arc<dyn Time>,
+    enable_auto_commit: bool,
+    auto_commit_interval_ms: i64,
+    next_auto_commit_ms: i64,
+    revocation_commit_timeout_ms: i64,
+    close_timeout_ms: i64,
+    /// The offset of the next record to consume, for each assigned partition.
+    positions: BTreeMap<TopicPartition, i64>,
+    /// The offsets last committed, for each assigned partition.
+    committed: BTreeMap<TopicPartition, i64>,
+    member_id: Option<String>,
+    wakeup: PollWakeup,
+}
+
+impl<C: GroupCoordinatorClient> ConsumerSession<C> {
+    pub fn new(coordinator: C, enable_auto_commit: bool, time: Arc<dyn Time>) -> Self {
+        let next_auto_commit_ms = time
+            .milliseconds()
+            .saturating_add(DEFAULT_AUTO_COMMIT_INTERVAL_MS);
+        Self {
+            coordinator,
+            time,
+            enable_auto_commit,
+            auto_commit_interval_ms: DEFAULT_AUTO_COMMIT_INTERVAL_MS,
+            next_auto_commit_ms,
+            revocation_commit_timeout_ms: DEFAULT_REVOCATION_COMMIT_TIMEOUT_MS,
+            close_timeout_ms: DEFAULT_CLOSE_TIMEOUT_MS,
+            positions: BTreeMap::new(),
+            committed: BTreeMap::new(),
+            member_id: None,
+            wakeup: PollWakeup::default(),
+        }
+    }
+
+    pub fn with_auto_commit_interval_ms(mut self, auto_commit_interval_ms: i64) -> Self {
+        self.auto_commit_interval_ms = auto_commit_interval_ms.max(0);
+        self.next_auto_commit_ms = self
+            .time
+            .milliseconds()
+            .saturating_add(self.auto_commit_interval_ms);
+        self
+    }
+
+    pub fn with_revocation_commit_timeout_ms(mut self, timeout_ms: i64) -> Self {
+        self.revocation_commit_timeout_ms = timeout_ms.max(0);
+        self
+    }
+
+    pub fn with_close_timeout_ms(mut self, close_timeout_ms: i64) -> Self {
+        self.close_timeout_ms = close_timeout_ms.max(0);
+        self
+    }
+
+    pub fn coordinator(&self) -> &C {
+        &self.coordinator
+    }
+
+    /// The handle polls wait on, so that closing the consumer unblocks them.
+    pub fn poll_wakeup(&self) -> PollWakeup {
+        self.wakeup.clone()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.wakeup.is_closed()
+    }
+
+    /// Records that the records of `topic_partition` before `offset` were consumed.
+    pub fn update_position(&mut self, topic_partition: TopicPartition, offset: i64) {
+        self.positions.insert(topic_partition, offset);
+    }
+
+    /// The offset of the next record to consume from `topic_partition`, if it is assigned.
+    pub fn position(&self, topic_partition: &TopicPartition) -> Option<i64> {
+        self.positions.get(topic_partition).copied()
+    }
+
+    /// Moves the positions of `partitions` to their first record with a timestamp at or after
+    /// `timestamp`, to replay them from that point in time. The partitions without such a
+    /// record are moved to their end, so that only the records appended from now on are
+    /// consumed from them.
+    ///
+    /// Both lookups are bounded by `timeout_ms` as a whole. No position moves unless they all
+    /// can.
+    pub fn seek_to_timestamp<L: ListOffsetsClient>(
+        &mut self,
+        client: &mut L,
+        partitions: &[TopicPartition],
+        timestamp: i64,
+        timeout_ms: i64,
+    ) -> Result<(), ApiError> {
+        let deadline_ms = self.time.milliseconds().saturating_add(timeout_ms.max(0));
+        let timestamps = partitions
+            .iter()
+            .map(|tp| (tp.clone(), timestamp))
+            .collect();
+        let mut seeks = BTreeMap::new();
+        let mut past_end = BTreeMap::new();
+        for (tp, offset) in offsets_for_times(client, &timestamps, timeout_ms)? {
+            match offset {
+                Some(offset) => seeks.insert(tp, offset.offset),
+                None => past_end.insert(tp, LATEST_TIMESTAMP),
+            };
+        }
+        if !past_end.is_empty() {
+            let remaining_ms = deadline_ms - self.time.milliseconds();
+            if remaining_ms <= 0 {
+                return Err(ApiError::new(
+                    Errors::RequestTimedOut,
+                    "The timeout expired before the end offsets could be looked up",
+                ));
+            }
+            let mut end_offsets = client.list_offsets(&past_end, remaining_ms)?;
+            for tp in past_end.into_keys() {
+                let offset = end_offsets.remove(&tp).flatten().ok_or_else(|| {
+                    ApiError::new(
+                        Errors::UnknownTopicOrPartition,
+                        format!("No end offset was returned for {tp}"),
+                    )
+                })?;
+                seeks.insert(tp, offset.offset);
+            }
+        }
+        self.positions.extend(seeks);
+        Ok(())
+    }
+
+    /// Commits the consumed positions not committed yet if `enable.auto.commit` is set and
+    /// `auto.commit.interval.ms` passed since the last auto-commit, as every poll does.
+    ///
+    /// A failed commit is retried on the first poll of the next interval; its positions are
+    /// committed anyway before the partitions are revoked or on close.
+    pub fn maybe_auto_commit(&mut self) -> Result<(), ApiError> {
+        let now_ms = self.time.milliseconds();
+        if !self.enable_auto_commit || now_ms < self.next_auto_commit_ms {
+            return Ok(());
+        }
+        self.next_auto_commit_ms = now_ms.saturating_add(self.auto_commit_interval_ms);
+        let offsets = self.uncommitted(|_| true);
+        self.commit(offsets, self.revocation_commit_timeout_ms)
+    }
+
+    /// Wraps up `partitions` before the consumer gives them up in a cooperative rebalance:
+    /// commits their consumed positions if `enable.auto.commit` is set, so that the member
+    /// they move to resumes from where this one stopped rather than reprocessing the records
+    /// consumed since the last auto-commit, then forgets them.
+    ///
+    /// The partitions are forgotten even if the commit fails, since the rebalance proceeds
+    /// anyway; the failure is returned.
+    pub fn on_partitions_revoked(&mut self, partitions: &[TopicPartition]) -> Result<(), ApiError> {
+        let result = match self.enable_auto_commit {
+            true => {
+                let offsets = self.uncommitted(|tp| partitions.contains(tp));
+                self.commit(offsets, self.revocation_commit_timeout_ms)
+            }
+            false => Ok(()),
+        };
+        self.forget(partitions);
+        result
+    }
+
+    /// Forgets `partitions` without committing their positions, since they were lost: the
+    /// member was fenced out of its group, which already gave them to other members.
+    pub fn on_partitions_lost(&mut self, partitions: &[TopicPartition]) {
+        self.forget(partitions);
+    }
+
+    /// Records the member id the consumer joined its group with, `None` once it left.
+    pub fn set_member_id(&mut self, member_id: Option<String>) {
+        self.member_id = member_id;
+    }
+
+    /// Closes the consumer within the close timeout, see [close_with_timeout](Self::close_with_timeout).
+    pub fn close(&mut self) -> Result<(), ApiError> {
+        self.close_with_timeout(self.close_timeout_ms)
+    }
+
+    /// Closes the consumer: wakes up the polls waiting for records, commits the consumed
+    /// positions if `enable.auto.commit` is set, and leaves the group so that its partitions
+    /// are reassigned right away rather than once the session of the member expires.
+    ///
+    /// Every step is bounded by `timeout_ms` as a whole; a step which fails or has no time
+    /// left does not prevent the next one. The first failure is returned. Closing a closed
+    /// consumer does nothing.
+    pub fn close_with_timeout(&mut self, timeout_ms: i64) -> Result<(), ApiError> {
+        if self.wakeup.is_closed() {
+            return Ok(());
+        }
+        self.wakeup.close();
+        let deadline_ms = self.time.milliseconds().saturating_add(timeout_ms.max(0));
+        let mut first_error = None;
+
+        let offsets = self.uncommitted(|_| true);
+        if self.enable_auto_commit && !offsets.is_empty() {
+            let result = self
+                .remaining_ms(deadline_ms, "commit the consumed offsets")
+                .and_then(|remaining_ms| self.commit(offsets, remaining_ms));
+            first_error = first_error.or(result.err());
+        }
+        if self.member_id.is_some() {
+            let result =
+                self.remaining_ms(deadline_ms, "leave the group")
+                    .and_then(|remaining_ms| {
+                        self.coordinator
+                            .leave_group(CLOSE_LEAVE_GROUP_REASON, remaining_ms)
+                    });
+            first_error = first_error.or(result.err());
+            self.member_id = None;
+        }
+        self.positions.clear();
+        self.committed.clear();
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// The positions of the partitions matching `filter` which changed since they were last
+    /// committed.
+    fn uncommitted(
+        &self,
+        filter: impl Fn(&TopicPartition) -> bool,
+    ) -> BTreeMap<TopicPartition, i64> {
+        self.positions
+            .iter()
+            .filter(|(tp, offset)| filter(tp) && self.committed.get(*tp) != Some(*offset))
+            .map(|(tp, offset)| (tp.clone(), *offset))
+            .collect()
+    }
+
+    fn commit(
+        &mut self,
+        offsets: BTreeMap<TopicPartition, i64>,
+        timeout_ms: i64,
+    ) -> Result<(), ApiError> {
+        if offsets.is_empty() {
+            return Ok(());
+        }
+        self.coordinator.commit_offsets(&offsets, timeout_ms)?;
+        self.committed.extend(offsets);
+        Ok(())
+    }
+
+    fn forget(&mut self, partitions: &[TopicPartition]) {
+        for tp in partitions {
+            self.positions.remove(tp);
+            self.committed.remove(tp);
+        }
+    }
+
+    /// The time left before `deadline_ms` to `step`, failing if there is none.
+    fn remaining_ms(&self, deadline_ms: i64, step: &str) -> Result<i64, ApiError> {
+        let remaining_ms = deadline_ms - self.time.milliseconds();
+        if remaining_ms <= 0 {
+            return Err(ApiError::new(
+                Errors::RequestTimedOut,
+                format!("The close timeout expired before the consumer could {step}"),
+            ));
+        }
+        Ok(remaining_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::utils::time::MockTime;
+    use std::thread;
+
+    /// Records the requests sent to the coordinator, each taking `request_ms`.
+    struct RecordingCoordinator {
+        time: Arc<MockTime>,
+        request_ms: i64,
+        commit_error: Option<Errors>,
+        commits: Vec<(BTreeMap<TopicPartition, i64>, i64)>,
+        leaves: Vec<(String, i64)>,
+    }
+
+    impl GroupCoordinatorClient for RecordingCoordinator {
+        fn commit_offsets(
+            &mut self,
+            offsets: &BTreeMap<TopicPartition, i64>,
+            timeout_ms: i64,
+        ) -> Result<(), ApiError> {
+            self.time.sleep(self.request_ms);
+            self.commits.push((offsets.clone(), timeout_ms));
+            match self.commit_error {
+                Some(error) => Err(ApiError::new(error, "commit failed")),
+                None => Ok(()),
+            }
+        }
+
+        fn leave_group(&mut self, reason: &str, timeout_ms: i64) -> Result<(), ApiError> {
+            self.time.sleep(self.request_ms);
+            self.leaves.push((reason.to_string(), timeout_ms));
+            Ok(())
+        }
+    }
+
+    fn new_session(
+        enable_auto_commit: bool,
+        request_ms: i64,
+        commit_error: Option<Errors>,
+    ) -> ConsumerSession<RecordingCoordinator> {
+        let time = Arc::new(MockTime::with_start(0, 0, 0));
+        let coordinator = RecordingCoordinator {
+            time: Arc::clone(&time),
+            request_ms,
+            commit_error,
+            commits: Vec::new(),
+            leaves: Vec::new(),
+        };
+        let mut session = ConsumerSession::new(coordinator, enable_auto_commit, time)
+            .with_close_timeout_ms(1_000);
+        session.set_member_id(Some("member-1".to_string()));
+        session.update_position(TopicPartition::new("foo", 0), 42);
+        session
+    }
+
+    #[test]
+    fn test_close_commits_leaves_and_wakes_up_polls() {
+        let mut session = new_session(true, 100, None);
+        let wakeup = session.poll_wakeup();
+        let poll = thread::spawn(move || wakeup.wait(Duration::from_secs(60)));
+
+        assert_eq!(Ok(()), session.close());
+        assert!(poll.join().unwrap());
+        assert!(session.is_closed());
+        let coordinator = session.coordinator();
+        assert_eq!(
+            vec![(BTreeMap::from([(TopicPartition::new("foo", 0), 42)]), 1_000)],
+            coordinator.commits
+        );
+        assert_eq!(
+            vec![(CLOSE_LEAVE_GROUP_REASON.to_string(), 900)],
+            coordinator.leaves
+        );
+
+        // Closing again sends nothing.
+        assert_eq!(Ok(()), session.close());
+        assert_eq!(1, session.coordinator().leaves.len());
+    }
+
+    #[test]
+    fn test_close_without_auto_commit() {
+        let mut session = new_session(false, 0, None);
+        assert_eq!(Ok(()), session.close());
+        assert!(session.coordinator().commits.is_empty());
+        assert_eq!(1, session.coordinator().leaves.len());
+    }
+
+    #[test]
+    fn test_auto_commit_and_commit_before_revocation() {
+        let mut session = new_session(true, 0, None).with_auto_commit_interval_ms(100);
+        let time = Arc::clone(&session.coordinator().time);
+        let foo = TopicPartition::new("foo", 0);
+        let bar = TopicPartition::new("bar", 0);
+        session.update_position(bar.clone(), 7);
+
+        assert_eq!(Ok(()), session.maybe_auto_commit());
+        assert!(session.coordinator().commits.is_empty());
+        time.sleep(100);
+        assert_eq!(Ok(()), session.maybe_auto_commit());
+        assert_eq!(
+            BTreeMap::from([(bar.clone(), 7), (foo.clone(), 42)]),
+            session.coordinator().commits[0].0
+        );
+
+        // Only the positions which moved since are committed, on revocation of their partition.
+        session.update_position(foo.clone(), 50);
+        session.update_position(bar.clone(), 9);
+        assert_eq!(
+            Ok(()),
+            session.on_partitions_revoked(std::slice::from_ref(&foo))
+        );
+        assert_eq!(
+            (
+                BTreeMap::from([(foo.clone(), 50)]),
+                DEFAULT_REVOCATION_COMMIT_TIMEOUT_MS
+            ),
+            session.coordinator().commits[1]
+        );
+        // A lost partition is forgotten without a commit.
+        session.update_position(foo.clone(), 60);
+        session.on_partitions_lost(std::slice::from_ref(&foo));
+        assert_eq!(Ok(()), session.on_partitions_revoked(&[foo]));
+        assert_eq!(2, session.coordinator().commits.len());
+
+        // Nor is it committed on close.
+        assert_eq!(Ok(()), session.close());
+        assert_eq!(
+            BTreeMap::from([(bar, 9)]),
+            session.coordinator().commits[2].0
+        );
+    }
+
+    /// A partition whose records have the timestamps `10 * offset`, up to `end_offset`.
+    struct TimestampedLog {
+        end_offset: i64,
+        requests: Vec<BTreeMap<TopicPartition, i64>>,
+    }
+
+    impl ListOffsetsClient for TimestampedLog {
+        fn list_offsets(
+            &mut self,
+            timestamps: &BTreeMap<TopicPartition, i64>,
+            _timeout_ms: i64,
+        ) -> Result<BTreeMap<TopicPartition, Option<OffsetAndTimestamp>>, ApiError> {
+            self.requests.push(timestamps.clone());
+            Ok(timestamps
+                .iter()
+                .map(|(tp, timestamp)| {
+                    let offset = match *timestamp {
+                        LATEST_TIMESTAMP => Some(self.end_offset),
+                        timestamp => {
+                            Some((timestamp + 9) / 10).filter(|offset| *offset < self.end_offset)
+                        }
+                    };
+                    let offset =
+                        offset.map(|offset| OffsetAndTimestamp::new(offset, offset * 10, None));
+                    (tp.clone(), offset)
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_offsets_for_times_and_seek_to_timestamp() {
+        let mut log = TimestampedLog {
+            end_offset: 100,
+            requests: Vec::new(),
+        };
+        let foo = TopicPartition::new("foo", 0);
+        let bar = TopicPartition::new("bar", 0);
+        let offsets = offsets_for_times(
+            &mut log,
+            &BTreeMap::from([(foo.clone(), 255), (bar.clone(), 5_000)]),
+            1_000,
+        )
+        .unwrap();
+        assert_eq!(
+            BTreeMap::from([
+                (bar.clone(), None),
+                (foo.clone(), Some(OffsetAndTimestamp::new(26, 260, None)))
+            ]),
+            offsets
+        );
+        assert_eq!(
+            Errors::InvalidRequest,
+            offsets_for_times(&mut log, &BTreeMap::from([(foo.clone(), -1)]), 1_000)
+                .unwrap_err()
+                .error()
+        );
+
+        // A partition without records that late is moved to its end.
+        let mut session = new_session(false, 0, None);
+        session
+            .seek_to_timestamp(&mut log, std::slice::from_ref(&foo), 100, 1_000)
+            .unwrap();
+        assert_eq!(Some(10), session.position(&foo));
+        session
+            .seek_to_timestamp(&mut log, std::slice::from_ref(&foo), 5_000, 1_000)
+            .unwrap();
+        assert_eq!(Some(100), session.position(&foo));
+        assert_eq!(
+            Some(&BTreeMap::from([(foo, LATEST_TIMESTAMP)])),
+            log.requests.last()
+        );
+    }
+
+    #[test]
+    fn test_close_leaves_the_group_despite_a_failed_commit() {
+        let mut session = new_session(true, 0, Some(Errors::RebalanceInProgress));
+        assert_eq!(
+            Errors::RebalanceInProgress,
+            session.close().unwrap_err().error()
+        );
+        assert_eq!(1, session.coordinator().leaves.len());
+
+        // A commit using up the close timeout leaves no time to leave the group.
+        let mut session = new_session(true, 1_000, None);
+        assert_eq!(
+            Errors::RequestTimedOut,
+            session.close().unwrap_err().error()
+        );
+        assert!(session.coordinator().leaves.is_empty());
+    }
+
+    #[test]
+    fn test_list_offsets_features_gate_the_version() {
+        use crate::clients::api_versions::NodeApiVersions;
+        use crate::common::requests::api_versions_response::ApiVersion;
+
+        let broker = NodeApiVersions::new([ApiVersion {
+            api_key: ApiKeys::ListOffsets.id(),
+            min_version: 0,
+            max_version: 6,
+        }]);
+        let version = |isolation_level, timestamps: &[i64]| {
+            let features = list_offsets_features(isolation_level, timestamps.iter().copied());
+            broker.latest_usable_version(ApiKeys::ListOffsets, &features)
+        };
+        assert_eq!(Ok(6), version(IsolationLevel::ReadUncommitted, &[1_000]));
+        assert_eq!(
+            Ok(6),
+            version(IsolationLevel::ReadCommitted, &[LATEST_TIMESTAMP])
+        );
+        let error = version(IsolationLevel::ReadUncommitted, &[MAX_TIMESTAMP]).unwrap_err();
+        assert_eq!(Errors::UnsupportedVersion, error.error());
+        assert!(
+            error.message().contains("max timestamp"),
+            "{}",
+            error.message()
+        );
+        assert_eq!(
+            vec![
+                READ_COMMITTED_LIST_OFFSETS,
+                EARLIEST_LOCAL_TIMESTAMP_LIST_OFFSETS
+            ],
+            list_offsets_features(
+                IsolationLevel::ReadCommitted,
+                [EARLIEST_LOCAL_TIMESTAMP, EARLIEST_LOCAL_TIMESTAMP]
+            )
+        );
+        assert_eq!(
+            vec![STABLE_OFFSET_FETCH],
+            offset_fetch_features(IsolationLevel::ReadCommitted)
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! The records a consumer fetched but not polled yet, and which partitions to fetch next.
+//!
+//! A partition is fetched only while less than `max.partition.fetch.bytes` of it is buffered,
+//! so an application processing slower than its partitions fill holds at most that much of
+//! each in memory, and no more than one fetch of it is in flight. A paused partition is not
+//! fetched and its buffered records are not returned by polls until it is resumed, including
+//! those of a fetch which was in flight when it was paused.
+
+use crate::common::record::record_batch::RecordBatch;
+use crate::common::topic_partition::TopicPartition;
+use std::collections::{BTreeMap, VecDeque};
+
+pub const MAX_PARTITION_FETCH_BYTES_CONFIG: &str = "max.partition.fetch.bytes";
+/// The default `max.partition.fetch.bytes`: 1 MiB.
+pub const DEFAULT_MAX_PARTITION_FETCH_BYTES: usize = 1024 * 1024;
+
+/// A fetch to send for a partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionFetch {
+    pub topic_partition: TopicPartition,
+    pub fetch_offset: i64,
+    pub max_bytes: usize,
+}
+
+#[derive(Debug)]
+struct PartitionState {
+    /// The offset of the next record to return from a poll.
+    position: i64,
+    paused: bool,
+    /// The offset the fetch in flight was sent for.
+    in_flight: Option<i64>,
+    /// The fetched batches, with their encoded size.
+    buffered: VecDeque<(RecordBatch, usize)>,
+    buffered_bytes: usize,
+}
+
+impl PartitionState {
+    /// The offset to fetch from next: after the buffered batches.
+    fn fetch_offset(&self) -> i64 {
+        self.buffered
+            .back()
+            .map_or(self.position, |(batch, _)| batch.next_offset())
+    }
+
+    fn clear(&mut self) {
+        self.buffered.clear();
+        self.buffered_bytes = 0;
+    }
+}
+
+/// The fetch state of the partitions assigned to a consumer.
+#[derive(Debug)]
+pub struct FetchBuffer {
+    max_partition_fetch_bytes: usize,
+    partitions: BTreeMap<TopicPartition, PartitionState>,
+}
+
+impl FetchBuffer {
+    pub fn new(max_partition_fetch_bytes: usize) -> Self {
+        Self {
+            max_partition_fetch_bytes,
+            partitions: BTreeMap::new(),
+        }
+    }
+
+    /// Starts fetching `topic_partition` from `position`, or moves its position there,
+    /// dropping what was buffered from the previous one.
+    pub fn seek(&mut self, topic_partition: TopicPartition, position: i64) {
+        let state = self
+            .partitions
+            .entry(topic_partition)
+            .or_insert_with(|| PartitionState {
+                position,
+                paused: false,
+                in_flight: None,
+                buffered: VecDeque::new(),
+                buffered_bytes: 0,
+            });
+        state.position = position;
+        state.clear();
+    }
+
+    /// Stops fetching `topic_partition`, dropping what was buffered of it.
+    pub fn unassign(&mut self, topic_partition: &TopicPartition) {
+        self.partitions.remove(topic_partition);
+    }
+
+    pub fn position(&self, topic_partition: &TopicPartition) -> Option<i64> {
+        self.partitions.get(topic_partition).map(|s| s.position)
+    }
+
+    /// Stops fetching the assigned partitions of `partitions` and returning their records.
+    pub fn pause(&mut self, partitions: &[TopicPartition]) {
+        self.set_paused(partitions, true);
+    }
+
+    pub fn resume(&mut self, partitions: &[TopicPartition]) {
+        self.set_paused(partitions, false);
+    }
+
+    pub fn is_paused(&self, topic_partition: &TopicPartition) -> bool {
+        self.partitions
+            .get(topic_partition)
+            .is_some_and(|s| s.paused)
+    }
+
+    pub fn paused(&self) -> Vec<TopicPartition> {
+        self.partitions
+            .iter()
+            .filter(|(_, s)| s.paused)
+            .map(|(tp, _)| tp.clone())
+            .collect()
+    }
+
+    /// The size of the buffered batches of all the partitions.
+    pub fn buffered_bytes(&self) -> usize {
+        self.partitions.values().map(|s| s.buffered_bytes).sum()
+    }
+
+    /// The fetches to send: one for each partition neither paused, nor with a fetch in
+    /// flight, nor with `max.partition.fetch.bytes` buffered, asking for what it has room
+    /// left for. They are marked in flight until [complete_fetch](Self::complete_fetch) or
+    /// [fail_fetch](Self::fail_fetch).
+    pub fn fetches(&mut self) -> Vec<PartitionFetch> {
+        let max_bytes = self.max_partition_fetch_bytes;
+        self.partitions
+            .iter_mut()
+            .filter(|(_, s)| !s.paused && s.in_flight.is_none() && s.buffered_bytes < max_bytes)
+            .map(|(tp, state)| {
+                let fetch_offset = state.fetch_offset();
+                state.in_flight = Some(fetch_offset);
+                PartitionFetch {
+                    topic_partition: tp.clone(),
+                    fetch_offset,
+                    max_bytes: max_bytes - state.buffered_bytes,
+                }
+            })
+            .collect()
+    }
+
+    /// Buffers the batches, with their encoded size, fetched for `topic_partition` from
+    /// `fetch_offset`. They are dropped if the partition was unassigned or seeked since the
+    /// fetch was sent, and kept if it was paused, to be returned once it is resumed.
+    pub fn complete_fetch(
+        &mut self,
+        topic_partition: &TopicPartition,
+        fetch_offset: i64,
+        batches: Vec<(RecordBatch, usize)>,
+    ) {
+        let Some(state) = self.partitions.get_mut(topic_partition) else {
+            return;
+        };
+        if state.in_flight.take() != Some(fetch_offset) || state.fetch_offset() != fetch_offset {
+            return;
+        }
+        for (batch, size) in batches {
+            // The first batch may start before the fetch offset.
+            if batch.next_offset() > state.fetch_offset() {
+                state.buffered_bytes += size;
+                state.buffered.push_back((batch, size));
+            }
+        }
+    }
+
+    /// Allows `topic_partition` to be fetched again after its fetch failed.
+    pub fn fail_fetch(&mut self, topic_partition: &TopicPartition) {
+        if let Some(state) = self.partitions.get_mut(topic_partition) {
+            state.in_flight = None;
+        }
+    }
+
+    /// Returns the buffered batches of the partitions which are not paused, up to
+    /// `max_records` records but at least one batch, and moves their positions past them.
+    pub fn poll(&mut self, max_records: usize) -> Vec<(TopicPartition, RecordBatch)> {
+        let mut polled = Vec::new();
+        let mut records = 0;
+        for (tp, state) in self.partitions.iter_mut().filter(|(_, s)| !s.paused) {
+            while let Some((batch, _)) = state.buffered.front() {
+                if !polled.is_empty() && records + batch.count() > max_records {
+                    return polled;
+                }
+                let (batch, size) = state.buffered.pop_front().expect("a batch was just seen");
+                state.buffered_bytes -= size;
+                state.position = batch.next_offset();
+                records += batch.count();
+                polled.push((tp.clone(), batch));
+            }
+        }
+        polled
+    }
+
+    fn set_paused(&mut self, partitions: &[TopicPartition], paused: bool) {
+        for tp in partitions {
+            if let Some(state) = self.partitions.get_mut(tp) {
+                state.paused = paused;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::record::record_batch::Record;
+
+    /// A batch of `count` records from `offset`, and its size.
+    fn new_batch(offset: i64, count: usize) -> (RecordBatch, usize) {
+        let records = (0..count)
+            .map(|_| Record::new(0, None, Some(&[0; 100])))
+            .collect();
+        let batch = RecordBatch::new(offset, records);
+        let size = batch.size_in_bytes();
+        (batch, size)
+    }
+
+    #[test]
+    fn test_partitions_are_fetched_while_their_buffer_has_room() {
+        let size = new_batch(0, 1).1;
+        let foo = TopicPartition::new("foo", 0);
+        let mut buffer = FetchBuffer::new(size * 2 + 1);
+        buffer.seek(foo.clone(), 0);
+
+        let fetches = buffer.fetches();
+        assert_eq!(
+            vec![PartitionFetch {
+                topic_partition: foo.clone(),
+                fetch_offset: 0,
+                max_bytes: size * 2 + 1
+            }],
+            fetches
+        );
+        // A single fetch of a partition is in flight at a time.
+        assert!(buffer.fetches().is_empty());
+        buffer.complete_fetch(&foo, 0, vec![new_batch(0, 1), new_batch(1, 1)]);
+        assert_eq!(size * 2, buffer.buffered_bytes());
+        assert_eq!(2, buffer.fetches()[0].fetch_offset);
+        buffer.complete_fetch(&foo, 2, vec![new_batch(2, 1)]);
+        // The buffer is full until the application polls.
+        assert!(buffer.fetches().is_empty());
+
+        let polled = buffer.poll(2);
+        assert_eq!(2, polled.len());
+        assert_eq!(Some(2), buffer.position(&foo));
+        assert_eq!(3, buffer.fetches()[0].fetch_offset);
+
+        // A fetch sent before a seek is dropped.
+        buffer.seek(foo.clone(), 10);
+        buffer.complete_fetch(&foo, 3, vec![new_batch(3, 1)]);
+        assert_eq!(0, buffer.buffered_bytes());
+        assert_eq!(10, buffer.fetches()[0].fetch_offset);
+    }
+
+    #[test]
+    fn test_pause_holds_back_in_flight_fetches() {
+        let foo = TopicPartition::new("foo", 0);
+        let bar = TopicPartition::new("bar", 0);
+        let mut buffer = FetchBuffer::new(DEFAULT_MAX_PARTITION_FETCH_BYTES);
+        buffer.seek(foo.clone(), 0);
+        buffer.seek(bar.clone(), 0);
+        assert_eq!(2, buffer.fetches().len());
+
+        buffer.pause(std::slice::from_ref(&foo));
+        assert_eq!(vec![foo.clone()], buffer.paused());
+        buffer.complete_fetch(&foo, 0, vec![new_batch(0, 3)]);
+        buffer.complete_fetch(&bar, 0, vec![new_batch(0, 3)]);
+        let polled = buffer.poll(500);
+        assert_eq!(
+            vec![bar.clone()],
+            polled.into_iter().map(|(tp, _)| tp).collect::<Vec<_>>()
+        );
+        // A paused partition is not fetched.
+        assert_eq!(
+            vec![bar],
+            buffer
+                .fetches()
+                .into_iter()
+                .map(|f| f.topic_partition)
+                .collect::<Vec<_>>()
+        );
+
+        buffer.resume(std::slice::from_ref(&foo));
+        assert!(!buffer.is_paused(&foo));
+        let polled = buffer.poll(500);
+        assert_eq!(
+            vec![foo.clone()],
+            polled.into_iter().map(|(tp, _)| tp).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(3), buffer.position(&foo));
+    }
+}
@@ -0,0 +1,1129 @@
+//! Replicating the topics of a source cluster to a target cluster, as MirrorMaker 2 does.
+//!
+//! A [MirrorTask] consumes the records of the source topics and produces them to the remote
+//! topics its [ReplicationPolicy] names after them, e.g. `topic` of cluster `us-west` to
+//! `us-west.topic`. As the offsets of a record differ between the clusters, it also produces
+//! [OffsetSync]s pairing an upstream offset with the downstream one it was appended at. From
+//! those, [checkpoints] translates the offsets the consumer groups committed on the source
+//! into [Checkpoint]s of the target, which [translate_offsets] reads back to move a group over
+//! to the target cluster.
+//!
+//! When exactly-once is enabled and the target supports transactions, the records of a poll,
+//! their offset syncs and the source positions reached are produced in one transaction, so
+//! that a restarted task resumes from the positions of its last committed transaction
+//! instead of the committed offsets of its source consumer.
+
+use crate::common::protocol::errors::{ApiError, Errors};
+use crate::common::record::record_batch::Record;
+use crate::common::topic_partition::TopicPartition;
+use crate::common::utils::time::Time;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+pub const REPLICATION_POLICY_SEPARATOR_CONFIG: &str = "replication.policy.separator";
+pub const DEFAULT_REPLICATION_POLICY_SEPARATOR: &str = ".";
+
+pub const OFFSET_LAG_MAX_CONFIG: &str = "offset.lag.max";
+/// The default `offset.lag.max`: how far the downstream offsets may drift from those the last
+/// offset sync predicts before another is produced.
+pub const DEFAULT_OFFSET_LAG_MAX: i64 = 100;
+
+/// The number of offset syncs an [OffsetSyncStore] keeps for each partition.
+pub const DEFAULT_OFFSET_SYNCS_PER_PARTITION: usize = 64;
+
+const INTERNAL_TOPIC_SUFFIX: &str = ".internal";
+
+/// Names the topics of a target cluster after the topics and the cluster they replicate.
+pub trait ReplicationPolicy {
+    /// The name of the topic replicating `topic` of cluster `source_cluster_alias`.
+    fn format_remote_topic(&self, source_cluster_alias: &str, topic: &str) -> String;
+
+    /// The alias of the cluster `topic` replicates, `None` for a topic which isn't remote.
+    fn topic_source(&self, topic: &str) -> Option<String>;
+
+    /// The name of the topic `topic` replicates, `None` for a topic which isn't remote.
+    fn upstream_topic(&self, topic: &str) -> Option<String>;
+
+    /// The topic the checkpoints of the groups of cluster `cluster_alias` are produced to.
+    fn checkpoints_topic(&self, cluster_alias: &str) -> String {
+        format!("{cluster_alias}.checkpoints{INTERNAL_TOPIC_SUFFIX}")
+    }
+
+    /// The topic the offset syncs of the records replicated to `cluster_alias` are produced to.
+    fn offset_syncs_topic(&self, cluster_alias: &str) -> String {
+        format!("mm2-offset-syncs.{cluster_alias}{INTERNAL_TOPIC_SUFFIX}")
+    }
+
+    /// The topic a task replicating cluster `cluster_alias` with exactly-once produces its
+    /// source positions to.
+    fn source_offsets_topic(&self, cluster_alias: &str) -> String {
+        format!("mm2-offsets.{cluster_alias}{INTERNAL_TOPIC_SUFFIX}")
+    }
+
+    /// Whether `topic` is internal to a cluster or to the replication, and never replicated.
+    fn is_internal_topic(&self, topic: &str) -> bool {
+        topic.ends_with(INTERNAL_TOPIC_SUFFIX) || topic.starts_with("__") || topic.starts_with('.')
+    }
+
+    /// The topic `topic` replicates through any number of clusters, or `topic` itself.
+    fn original_topic(&self, topic: &str) -> String {
+        let mut topic = topic.to_string();
+        while let Some(upstream) = self.upstream_topic(&topic) {
+            if upstream == topic {
+                break;
+            }
+            topic = upstream;
+        }
+        topic
+    }
+}
+
+/// Prefixes the remote topics with the alias of their source cluster and a separator, e.g.
+/// `us-west.topic`, so that the topics of several clusters can be replicated to one.
+#[derive(Debug, Clone)]
+pub struct DefaultReplicationPolicy {
+    separator: String,
+}
+
+impl Default for DefaultReplicationPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_REPLICATION_POLICY_SEPARATOR)
+    }
+}
+
+impl DefaultReplicationPolicy {
+    pub fn new(separator: &str) -> Self {
+        Self {
+            separator: separator.to_string(),
+        }
+    }
+}
+
+impl ReplicationPolicy for DefaultReplicationPolicy {
+    fn format_remote_topic(&self, source_cluster_alias: &str, topic: &str) -> String {
+        format!("{source_cluster_alias}{}{topic}", self.separator)
+    }
+
+    fn topic_source(&self, topic: &str) -> Option<String> {
+        topic
+            .split_once(&self.separator)
+            .map(|(source, _)| source.to_string())
+    }
+
+    fn upstream_topic(&self, topic: &str) -> Option<String> {
+        topic
+            .split_once(&self.separator)
+            .map(|(_, upstream)| upstream.to_string())
+    }
+}
+
+/// Keeps the names of the topics, for a target cluster replicating a single source cluster.
+///
+/// A remote topic can't be told from a local one by its name, so the source of every topic
+/// is the source cluster the policy is configured with, if any.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityReplicationPolicy {
+    source_cluster_alias: Option<String>,
+}
+
+impl IdentityReplicationPolicy {
+    pub fn with_source_cluster_alias(mut self, source_cluster_alias: &str) -> Self {
+        self.source_cluster_alias = Some(source_cluster_alias.to_string());
+        self
+    }
+}
+
+impl ReplicationPolicy for IdentityReplicationPolicy {
+    fn format_remote_topic(&self, _source_cluster_alias: &str, topic: &str) -> String {
+        topic.to_string()
+    }
+
+    fn topic_source(&self, _topic: &str) -> Option<String> {
+        self.source_cluster_alias.clone()
+    }
+
+    fn upstream_topic(&self, topic: &str) -> Option<String> {
+        self.source_cluster_alias
+            .as_ref()
+            .map(|_| topic.to_string())
+    }
+}
+
+/// The offset a record of a source partition was appended at in the remote partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetSync {
+    /// The source partition.
+    pub topic_partition: TopicPartition,
+    pub upstream_offset: i64,
+    pub downstream_offset: i64,
+}
+
+impl OffsetSync {
+    pub fn record_key(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_topic_partition(&mut buf, &self.topic_partition);
+        buf
+    }
+
+    pub fn record_value(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(16);
+        buf.extend_from_slice(&self.upstream_offset.to_be_bytes());
+        buf.extend_from_slice(&self.downstream_offset.to_be_bytes());
+        buf
+    }
+
+    /// Reads back an offset sync from the record it was produced as.
+    pub fn from_record(record: &Record) -> Result<Self, ApiError> {
+        let (mut key, mut value) = key_and_value(record, "offset sync")?;
+        Ok(Self {
+            topic_partition: key.topic_partition()?,
+            upstream_offset: value.i64()?,
+            downstream_offset: value.i64()?,
+        })
+    }
+}
+
+/// The version of the value of a [Checkpoint] record.
+const CHECKPOINT_VERSION: i16 = 0;
+
+/// The offset a consumer group committed on a source partition, translated to the remote
+/// partition replicating it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub consumer_group_id: String,
+    /// The remote partition.
+    pub topic_partition: TopicPartition,
+    pub upstream_offset: i64,
+    pub downstream_offset: i64,
+    pub metadata: String,
+}
+
+impl Checkpoint {
+    pub fn record_key(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_string(&mut buf, &self.consumer_group_id);
+        write_topic_partition(&mut buf, &self.topic_partition);
+        buf
+    }
+
+    pub fn record_value(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&CHECKPOINT_VERSION.to_be_bytes());
+        buf.extend_from_slice(&self.upstream_offset.to_be_bytes());
+        buf.extend_from_slice(&self.downstream_offset.to_be_bytes());
+        write_string(&mut buf, &self.metadata);
+        buf
+    }
+
+    /// Reads back a checkpoint from the record it was produced as.
+    pub fn from_record(record: &Record) -> Result<Self, ApiError> {
+        let (mut key, mut value) = key_and_value(record, "checkpoint")?;
+        let consumer_group_id = key.string()?;
+        let topic_partition = key.topic_partition()?;
+        let version = value.i16()?;
+        if version != CHECKPOINT_VERSION {
+            return Err(ApiError::new(
+                Errors::CorruptMessage,
+                format!("Unknown checkpoint version {version}"),
+            ));
+        }
+        Ok(Self {
+            consumer_group_id,
+            topic_partition,
+            upstream_offset: value.i64()?,
+            downstream_offset: value.i64()?,
+            metadata: value.string()?,
+        })
+    }
+}
+
+/// The latest offset syncs of each source partition, translating the offsets of its records
+/// to those of the remote partition.
+///
+/// Only a few syncs are kept per partition, so an offset long behind the latest sync may not
+/// be translatable any more.
+#[derive(Debug)]
+pub struct OffsetSyncStore {
+    max_syncs_per_partition: usize,
+    /// The downstream offset of each synced upstream offset, by source partition.
+    syncs: HashMap<TopicPartition, BTreeMap<i64, i64>>,
+}
+
+impl Default for OffsetSyncStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_OFFSET_SYNCS_PER_PARTITION)
+    }
+}
+
+impl OffsetSyncStore {
+    pub fn new(max_syncs_per_partition: usize) -> Self {
+        Self {
+            max_syncs_per_partition: max_syncs_per_partition.max(1),
+            syncs: HashMap::new(),
+        }
+    }
+
+    /// Adds `sync`, read from the offset syncs topic in the order they were produced.
+    ///
+    /// A sync at or before one already kept means that the source partition was truncated
+    /// or recreated, so the syncs after it no longer hold and are dropped.
+    pub fn sync(&mut self, sync: OffsetSync) {
+        let syncs = self.syncs.entry(sync.topic_partition).or_default();
+        syncs.split_off(&sync.upstream_offset);
+        syncs.insert(sync.upstream_offset, sync.downstream_offset);
+        while syncs.len() > self.max_syncs_per_partition {
+            syncs.pop_first();
+        }
+    }
+
+    /// The remote offset to resume consuming from for `upstream_offset` of the source
+    /// partition `topic_partition`, or `None` if it is before every sync kept.
+    ///
+    /// An offset past the latest sync before it is translated to the one after the synced
+    /// downstream offset: the records between them may have been replicated anywhere after it,
+    /// and resuming from there risks duplicates but never loses a record.
+    pub fn translate_downstream(
+        &self,
+        topic_partition: &TopicPartition,
+        upstream_offset: i64,
+    ) -> Option<i64> {
+        let (synced_upstream, synced_downstream) = self
+            .syncs
+            .get(topic_partition)?
+            .range(..=upstream_offset)
+            .next_back()?;
+        match upstream_offset == *synced_upstream {
+            true => Some(*synced_downstream),
+            false => Some(synced_downstream + 1),
+        }
+    }
+}
+
+/// The checkpoints of the offsets `group` committed on the source cluster
+/// `source_cluster_alias`, skipping the partitions which aren't replicated or whose offset
+/// can't be translated.
+pub fn checkpoints(
+    policy: &dyn ReplicationPolicy,
+    source_cluster_alias: &str,
+    offset_syncs: &OffsetSyncStore,
+    group: &str,
+    committed: &BTreeMap<TopicPartition, i64>,
+) -> Vec<Checkpoint> {
+    committed
+        .iter()
+        .filter(|(tp, _)| !policy.is_internal_topic(tp.topic()))
+        .filter_map(|(tp, upstream_offset)| {
+            let downstream_offset = offset_syncs.translate_downstream(tp, *upstream_offset)?;
+            Some(Checkpoint {
+                consumer_group_id: group.to_string(),
+                topic_partition: TopicPartition::new(
+                    policy.format_remote_topic(source_cluster_alias, tp.topic()),
+                    tp.partition(),
+                ),
+                upstream_offset: *upstream_offset,
+                downstream_offset,
+                metadata: String::new(),
+            })
+        })
+        .collect()
+}
+
+/// Reads the records of a topic of the target cluster.
+pub trait TopicReader {
+    /// The records of `topic` from its beginning to its end, in order, waiting up to
+    /// `timeout_ms` for them.
+    fn read_to_end(&mut self, topic: &str, timeout_ms: i64) -> Result<Vec<Record>, ApiError>;
+}
+
+/// The offsets of the remote partitions for `group` to resume consuming from on this cluster,
+/// translated from those it committed on the cluster `remote_cluster_alias`, as the latest
+/// checkpoints of the group read from the checkpoints topic tell.
+pub fn translate_offsets<R: TopicReader>(
+    reader: &mut R,
+    policy: &dyn ReplicationPolicy,
+    remote_cluster_alias: &str,
+    group: &str,
+    timeout_ms: i64,
+) -> Result<BTreeMap<TopicPartition, i64>, ApiError> {
+    let records =
+        reader.read_to_end(&policy.checkpoints_topic(remote_cluster_alias), timeout_ms)?;
+    let mut offsets = BTreeMap::new();
+    for record in &records {
+        let checkpoint = Checkpoint::from_record(record)?;
+        if checkpoint.consumer_group_id == group {
+            offsets.insert(checkpoint.topic_partition, checkpoint.downstream_offset);
+        }
+    }
+    Ok(offsets)
+}
+
+/// A record consumed from a source partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumedRecord {
+    pub topic_partition: TopicPartition,
+    pub offset: i64,
+    pub record: Record,
+}
+
+/// The consumer of a [MirrorTask] on the source cluster.
+pub trait MirrorConsumer {
+    /// The records consumed since the last poll, waiting up to `timeout_ms` for some.
+    fn poll(&mut self, timeout_ms: i64) -> Result<Vec<ConsumedRecord>, ApiError>;
+
+    /// Moves the position of `topic_partition` to `offset`.
+    fn seek(&mut self, topic_partition: &TopicPartition, offset: i64);
+
+    /// Commits `offsets`, the offsets of the next records to consume.
+    fn commit(&mut self, offsets: &BTreeMap<TopicPartition, i64>) -> Result<(), ApiError>;
+}
+
+/// The producer of a [MirrorTask] on the target cluster.
+pub trait MirrorProducer {
+    /// Sends `record` to `topic_partition` and returns the offset it was appended at.
+    fn send(&mut self, topic_partition: &TopicPartition, record: Record) -> Result<i64, ApiError>;
+
+    /// Initializes the transactions of the producer, failing with `UnsupportedVersion` if the
+    /// target cluster doesn't support them.
+    fn init_transactions(&mut self) -> Result<(), ApiError>;
+
+    fn begin_transaction(&mut self) -> Result<(), ApiError>;
+
+    fn commit_transaction(&mut self) -> Result<(), ApiError>;
+
+    fn abort_transaction(&mut self) -> Result<(), ApiError>;
+}
+
+/// The offsets last replicated of a source partition, and those of its last offset sync.
+#[derive(Debug, Clone, Copy)]
+struct PartitionState {
+    upstream_offset: i64,
+    downstream_offset: i64,
+    synced_upstream_offset: i64,
+    synced_downstream_offset: i64,
+}
+
+impl PartitionState {
+    fn new(upstream_offset: i64, downstream_offset: i64) -> Self {
+        Self {
+            upstream_offset,
+            downstream_offset,
+            synced_upstream_offset: upstream_offset,
+            synced_downstream_offset: downstream_offset,
+        }
+    }
+
+    /// Records the replication of `upstream_offset` at `downstream_offset`, returning whether
+    /// to produce an offset sync for it: when records were skipped on either side, or when
+    /// the downstream offsets drifted `offset_lag_max` or more from the last sync.
+    fn update(
+        &mut self,
+        upstream_offset: i64,
+        downstream_offset: i64,
+        offset_lag_max: i64,
+    ) -> bool {
+        let expected_downstream_offset =
+            self.synced_downstream_offset + (upstream_offset - self.synced_upstream_offset);
+        let should_sync = upstream_offset - self.upstream_offset != 1
+            || downstream_offset < self.downstream_offset
+            || downstream_offset - expected_downstream_offset >= offset_lag_max;
+        self.upstream_offset = upstream_offset;
+        self.downstream_offset = downstream_offset;
+        if should_sync {
+            self.synced_upstream_offset = upstream_offset;
+            self.synced_downstream_offset = downstream_offset;
+        }
+        should_sync
+    }
+}
+
+/// Replicates the records of the source partitions assigned to its consumer to the target
+/// cluster, with the offset syncs of their offsets.
+///
+/// Without exactly-once, the positions are committed to the source cluster once the records
+/// were produced, so a task restarting after a failure replicates some records again.
+pub struct MirrorTask<C, P> {
+    consumer: C,
+    producer: P,
+    policy: Arc<dyn ReplicationPolicy + Send + Sync>,
+    time: Arc<dyn Time>,
+    source_cluster_alias: String,
+    target_cluster_alias: String,
+    offset_lag_max: i64,
+    exactly_once: bool,
+    partitions: HashMap<TopicPartition, PartitionState>,
+}
+
+impl<C: MirrorConsumer, P: MirrorProducer> MirrorTask<C, P> {
+    pub fn new(
+        consumer: C,
+        producer: P,
+        policy: Arc<dyn ReplicationPolicy + Send + Sync>,
+        time: Arc<dyn Time>,
+        source_cluster_alias: &str,
+        target_cluster_alias: &str,
+    ) -> Self {
+        Self {
+            consumer,
+            producer,
+            policy,
+            time,
+            source_cluster_alias: source_cluster_alias.to_string(),
+            target_cluster_alias: target_cluster_alias.to_string(),
+            offset_lag_max: DEFAULT_OFFSET_LAG_MAX,
+            exactly_once: false,
+            partitions: HashMap::new(),
+        }
+    }
+
+    pub fn with_offset_lag_max(mut self, offset_lag_max: i64) -> Self {
+        self.offset_lag_max = offset_lag_max.max(0);
+        self
+    }
+
+    /// Replicates with exactly-once if the target cluster supports transactions.
+    pub fn with_exactly_once(mut self, exactly_once: bool) -> Self {
+        self.exactly_once = exactly_once;
+        self
+    }
+
+    pub fn consumer(&self) -> &C {
+        &self.consumer
+    }
+
+    pub fn producer(&self) -> &P {
+        &self.producer
+    }
+
+    /// Whether the records are replicated with exactly-once, once started.
+    pub fn is_exactly_once(&self) -> bool {
+        self.exactly_once
+    }
+
+    /// Initializes the transactions of the producer if exactly-once is enabled, falling back
+    /// to at-least-once if the target cluster doesn't support them, and then moves the
+    /// consumer to the source positions last committed with the records, read with `reader`.
+    pub fn start<R: TopicReader>(
+        &mut self,
+        reader: &mut R,
+        timeout_ms: i64,
+    ) -> Result<(), ApiError> {
+        if !self.exactly_once {
+            return Ok(());
+        }
+        match self.producer.init_transactions() {
+            Ok(()) => {}
+            Err(e) if e.error() == Errors::UnsupportedVersion => {
+                self.exactly_once = false;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        }
+        let topic = self.policy.source_offsets_topic(&self.source_cluster_alias);
+        let mut positions = BTreeMap::new();
+        for record in reader.read_to_end(&topic, timeout_ms)? {
+            let (mut key, mut value) = key_and_value(&record, "source offset")?;
+            positions.insert(key.topic_partition()?, value.i64()?);
+        }
+        for (tp, offset) in &positions {
+            self.consumer.seek(tp, *offset);
+        }
+        Ok(())
+    }
+
+    /// Replicates the records of one poll of the consumer, waiting up to `timeout_ms` for
+    /// some, and returns how many were replicated.
+    ///
+    /// On a failure, the consumer is moved back to the first record of the poll of each
+    /// partition, so that the next poll replicates them again. With exactly-once, the
+    /// transaction is aborted first, so none of them made it to the target.
+    pub fn poll_once(&mut self, timeout_ms: i64) -> Result<usize, ApiError> {
+        let records = self.consumer.poll(timeout_ms)?;
+        if records.is_empty() {
+            return Ok(0);
+        }
+        let count = records.len();
+        let mut polled_from = BTreeMap::new();
+        for record in &records {
+            polled_from
+                .entry(record.topic_partition.clone())
+                .or_insert(record.offset);
+        }
+        let result = match self.exactly_once {
+            true => self.replicate_in_transaction(records),
+            false => self
+                .replicate(records)
+                .and_then(|positions| self.consumer.commit(&positions)),
+        };
+        if let Err(e) = result {
+            for (tp, offset) in &polled_from {
+                // The offsets replicated next don't follow those of the last sync any more.
+                self.partitions.remove(tp);
+                self.consumer.seek(tp, *offset);
+            }
+            return Err(e);
+        }
+        Ok(count)
+    }
+
+    fn replicate_in_transaction(&mut self, records: Vec<ConsumedRecord>) -> Result<(), ApiError> {
+        self.producer.begin_transaction()?;
+        let result = self.replicate(records).and_then(|positions| {
+            self.produce_source_offsets(&positions)?;
+            self.producer.commit_transaction()
+        });
+        if let Err(e) = result {
+            self.producer.abort_transaction()?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Produces `records` to their remote partitions, with the offset syncs they need, and
+    /// returns the positions reached on their source partitions.
+    fn replicate(
+        &mut self,
+        records: Vec<ConsumedRecord>,
+    ) -> Result<BTreeMap<TopicPartition, i64>, ApiError> {
+        let offset_syncs_topic = TopicPartition::new(
+            self.policy.offset_syncs_topic(&self.target_cluster_alias),
+            0,
+        );
+        let mut positions = BTreeMap::new();
+        for ConsumedRecord {
+            topic_partition,
+            offset,
+            record,
+        } in records
+        {
+            let remote = TopicPartition::new(
+                self.policy
+                    .format_remote_topic(&self.source_cluster_alias, topic_partition.topic()),
+                topic_partition.partition(),
+            );
+            let downstream_offset = self.producer.send(&remote, record)?;
+            let should_sync = match self.partitions.get_mut(&topic_partition) {
+                Some(state) => state.update(offset, downstream_offset, self.offset_lag_max),
+                None => {
+                    self.partitions.insert(
+                        topic_partition.clone(),
+                        PartitionState::new(offset, downstream_offset),
+                    );
+                    true
+                }
+            };
+            if should_sync {
+                let sync = OffsetSync {
+                    topic_partition: topic_partition.clone(),
+                    upstream_offset: offset,
+                    downstream_offset,
+                };
+                let record = self.internal_record(sync.record_key(), sync.record_value());
+                self.producer.send(&offset_syncs_topic, record)?;
+            }
+            positions.insert(topic_partition, offset + 1);
+        }
+        Ok(positions)
+    }
+
+    fn produce_source_offsets(
+        &mut self,
+        positions: &BTreeMap<TopicPartition, i64>,
+    ) -> Result<(), ApiError> {
+        let topic = TopicPartition::new(
+            self.policy.source_offsets_topic(&self.source_cluster_alias),
+            0,
+        );
+        for (tp, offset) in positions {
+            let mut key = Vec::new();
+            write_topic_partition(&mut key, tp);
+            let record = self.internal_record(key, offset.to_be_bytes().to_vec());
+            self.producer.send(&topic, record)?;
+        }
+        Ok(())
+    }
+
+    fn internal_record(&self, key: Vec<u8>, value: Vec<u8>) -> Record {
+        Record {
+            timestamp: self.time.milliseconds(),
+            key: Some(key),
+            value: Some(value),
+            headers: vec![],
+        }
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as i16).to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn write_topic_partition(buf: &mut Vec<u8>, topic_partition: &TopicPartition) {
+    write_string(buf, topic_partition.topic());
+    buf.extend_from_slice(&topic_partition.partition().to_be_bytes());
+}
+
+/// Reads the fields of the key or the value of a record produced by the replication.
+struct FieldReader<'a> {
+    buf: &'a [u8],
+    what: &'static str,
+}
+
+impl<'a> FieldReader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ApiError> {
+        let bytes = self.bytes(N)?;
+        Ok(bytes.try_into().expect("took N bytes"))
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ApiError> {
+        if self.buf.len() < len {
+            return Err(ApiError::new(
+                Errors::CorruptMessage,
+                format!("The {} record is truncated", self.what),
+            ));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn i16(&mut self) -> Result<i16, ApiError> {
+        self.take().map(i16::from_be_bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, ApiError> {
+        self.take().map(i32::from_be_bytes)
+    }
+
+    fn i64(&mut self) -> Result<i64, ApiError> {
+        self.take().map(i64::from_be_bytes)
+    }
+
+    fn string(&mut self) -> Result<String, ApiError> {
+        let len = self.i16()?;
+        let bytes = self.bytes(len.max(0) as usize)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| {
+            ApiError::new(
+                Errors::CorruptMessage,
+                format!("The {} record holds a string which isn't UTF-8", self.what),
+            )
+        })
+    }
+
+    fn topic_partition(&mut self) -> Result<TopicPartition, ApiError> {
+        let topic = self.string()?;
+        Ok(TopicPartition::new(topic, self.i32()?))
+    }
+}
+
+fn key_and_value<'a>(
+    record: &'a Record,
+    what: &'static str,
+) -> Result<(FieldReader<'a>, FieldReader<'a>), ApiError> {
+    match (&record.key, &record.value) {
+        (Some(key), Some(value)) => Ok((
+            FieldReader { buf: key, what },
+            FieldReader { buf: value, what },
+        )),
+        _ => Err(ApiError::new(
+            Errors::CorruptMessage,
+            format!("The {what} record has no key or no value"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::utils::time::MockTime;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn test_default_replication_policy() {
+        let policy = DefaultReplicationPolicy::default();
+        assert_eq!("us-west.foo", policy.format_remote_topic("us-west", "foo"));
+        assert_eq!(
+            Some("us-west".to_string()),
+            policy.topic_source("us-west.foo")
+        );
+        assert_eq!(
+            Some("foo".to_string()),
+            policy.upstream_topic("us-west.foo")
+        );
+        assert_eq!(None, policy.topic_source("foo"));
+        assert_eq!("foo", policy.original_topic("us-east.us-west.foo"));
+        assert_eq!("foo", policy.original_topic("foo"));
+
+        let policy = DefaultReplicationPolicy::new("_");
+        assert_eq!("us-west_foo", policy.format_remote_topic("us-west", "foo"));
+        assert_eq!(
+            Some("us-west".to_string()),
+            policy.topic_source("us-west_foo")
+        );
+
+        assert_eq!(
+            "us-west.checkpoints.internal",
+            policy.checkpoints_topic("us-west")
+        );
+        assert!(policy.is_internal_topic(&policy.checkpoints_topic("us-west")));
+        assert!(policy.is_internal_topic(&policy.offset_syncs_topic("us-east")));
+        assert!(policy.is_internal_topic("__consumer_offsets"));
+        assert!(!policy.is_internal_topic("foo"));
+    }
+
+    #[test]
+    fn test_identity_replication_policy() {
+        let policy = IdentityReplicationPolicy::default();
+        assert_eq!("foo", policy.format_remote_topic("us-west", "foo"));
+        assert_eq!(None, policy.topic_source("foo"));
+        assert_eq!(None, policy.upstream_topic("foo"));
+
+        let policy = policy.with_source_cluster_alias("us-west");
+        assert_eq!(Some("us-west".to_string()), policy.topic_source("foo"));
+        assert_eq!(Some("foo".to_string()), policy.upstream_topic("foo"));
+        assert_eq!("foo", policy.original_topic("foo"));
+    }
+
+    fn record(key: Vec<u8>, value: Vec<u8>) -> Record {
+        Record {
+            key: Some(key),
+            value: Some(value),
+            ..Record::default()
+        }
+    }
+
+    #[test]
+    fn test_records() {
+        let sync = OffsetSync {
+            topic_partition: TopicPartition::new("foo", 1),
+            upstream_offset: 2,
+            downstream_offset: 3,
+        };
+        #[rustfmt::skip]
+        let key = vec![
+            0, 3, b'f', b'o', b'o',
+            0, 0, 0, 1,
+        ];
+        assert_eq!(key, sync.record_key());
+        #[rustfmt::skip]
+        let value = vec![
+            0, 0, 0, 0, 0, 0, 0, 2,
+            0, 0, 0, 0, 0, 0, 0, 3,
+        ];
+        assert_eq!(value, sync.record_value());
+        let sync_record = record(sync.record_key(), sync.record_value());
+        assert_eq!(Ok(sync.clone()), OffsetSync::from_record(&sync_record));
+
+        let checkpoint = Checkpoint {
+            consumer_group_id: "g".to_string(),
+            topic_partition: TopicPartition::new("us-west.foo", 1),
+            upstream_offset: 2,
+            downstream_offset: 3,
+            metadata: "m".to_string(),
+        };
+        #[rustfmt::skip]
+        let value = vec![
+            // version
+            0, 0,
+            0, 0, 0, 0, 0, 0, 0, 2,
+            0, 0, 0, 0, 0, 0, 0, 3,
+            0, 1, b'm',
+        ];
+        assert_eq!(value, checkpoint.record_value());
+        let checkpoint_record = record(checkpoint.record_key(), checkpoint.record_value());
+        assert_eq!(Ok(checkpoint), Checkpoint::from_record(&checkpoint_record));
+
+        let truncated = record(sync.record_key(), vec![0; 8]);
+        assert_eq!(
+            Errors::CorruptMessage,
+            OffsetSync::from_record(&truncated).unwrap_err().error()
+        );
+        let unknown_version = record(checkpoint_record.key.clone().unwrap(), vec![0, 1]);
+        assert_eq!(
+            "Unknown checkpoint version 1",
+            Checkpoint::from_record(&unknown_version)
+                .unwrap_err()
+                .message()
+        );
+    }
+
+    fn sync(upstream_offset: i64, downstream_offset: i64) -> OffsetSync {
+        OffsetSync {
+            topic_partition: TopicPartition::new("foo", 0),
+            upstream_offset,
+            downstream_offset,
+        }
+    }
+
+    #[test]
+    fn test_offset_sync_store() {
+        let tp = TopicPartition::new("foo", 0);
+        let mut store = OffsetSyncStore::new(3);
+        assert_eq!(None, store.translate_downstream(&tp, 0));
+
+        store.sync(sync(5, 100));
+        store.sync(sync(10, 110));
+        assert_eq!(None, store.translate_downstream(&tp, 4));
+        assert_eq!(Some(100), store.translate_downstream(&tp, 5));
+        // Past a sync, the records may have been replicated anywhere after it.
+        assert_eq!(Some(101), store.translate_downstream(&tp, 7));
+        assert_eq!(Some(110), store.translate_downstream(&tp, 10));
+        assert_eq!(Some(111), store.translate_downstream(&tp, 1000));
+        assert_eq!(
+            None,
+            store.translate_downstream(&TopicPartition::new("foo", 1), 5)
+        );
+
+        // Only the latest syncs are kept.
+        store.sync(sync(20, 120));
+        store.sync(sync(30, 130));
+        assert_eq!(None, store.translate_downstream(&tp, 5));
+        assert_eq!(Some(110), store.translate_downstream(&tp, 10));
+
+        // The source partition was truncated.
+        store.sync(sync(15, 140));
+        assert_eq!(Some(141), store.translate_downstream(&tp, 30));
+    }
+
+    /// The partitions of a target cluster, appended to by the producer and read by the reader.
+    #[derive(Default)]
+    struct TestCluster {
+        partitions: BTreeMap<TopicPartition, Vec<Record>>,
+        supports_transactions: bool,
+        /// The lengths of the partitions when the transaction began.
+        transaction: Option<BTreeMap<TopicPartition, usize>>,
+        committed_transactions: usize,
+        fail_sends_to: Option<String>,
+    }
+
+    impl TestCluster {
+        /// The offsets of the partitions start at 100, unlike those of the source.
+        const BASE_OFFSET: i64 = 100;
+
+        fn records(&self, topic: &str, partition: i32) -> &[Record] {
+            self.partitions
+                .get(&TopicPartition::new(topic, partition))
+                .map_or(&[], Vec::as_slice)
+        }
+    }
+
+    impl MirrorProducer for TestCluster {
+        fn send(
+            &mut self,
+            topic_partition: &TopicPartition,
+            record: Record,
+        ) -> Result<i64, ApiError> {
+            if self.fail_sends_to.as_deref() == Some(topic_partition.topic()) {
+                return Err(ApiError::new(Errors::NotEnoughReplicas, "unavailable"));
+            }
+            let records = self.partitions.entry(topic_partition.clone()).or_default();
+            if let Some(transaction) = &mut self.transaction {
+                transaction
+                    .entry(topic_partition.clone())
+                    .or_insert(records.len());
+            }
+            records.push(record);
+            Ok(Self::BASE_OFFSET + records.len() as i64 - 1)
+        }
+
+        fn init_transactions(&mut self) -> Result<(), ApiError> {
+            match self.supports_transactions {
+                true => Ok(()),
+                false => Err(ApiError::new(Errors::UnsupportedVersion, "no transactions")),
+            }
+        }
+
+        fn begin_transaction(&mut self) -> Result<(), ApiError> {
+            self.transaction = Some(BTreeMap::new());
+            Ok(())
+        }
+
+        fn commit_transaction(&mut self) -> Result<(), ApiError> {
+            self.transaction = None;
+            self.committed_transactions += 1;
+            Ok(())
+        }
+
+        fn abort_transaction(&mut self) -> Result<(), ApiError> {
+            for (tp, len) in self.transaction.take().unwrap_or_default() {
+                self.partitions.get_mut(&tp).unwrap().truncate(len);
+            }
+            Ok(())
+        }
+    }
+
+    impl TopicReader for TestCluster {
+        fn read_to_end(&mut self, topic: &str, _timeout_ms: i64) -> Result<Vec<Record>, ApiError> {
+            Ok(self.records(topic, 0).to_vec())
+        }
+    }
+
+    #[derive(Default)]
+    struct TestConsumer {
+        polls: VecDeque<Vec<ConsumedRecord>>,
+        seeks: Vec<(TopicPartition, i64)>,
+        commits: Vec<BTreeMap<TopicPartition, i64>>,
+    }
+
+    impl MirrorConsumer for TestConsumer {
+        fn poll(&mut self, _timeout_ms: i64) -> Result<Vec<ConsumedRecord>, ApiError> {
+            Ok(self.polls.pop_front().unwrap_or_default())
+        }
+
+        fn seek(&mut self, topic_partition: &TopicPartition, offset: i64) {
+            self.seeks.push((topic_partition.clone(), offset));
+        }
+
+        fn commit(&mut self, offsets: &BTreeMap<TopicPartition, i64>) -> Result<(), ApiError> {
+            self.commits.push(offsets.clone());
+            Ok(())
+        }
+    }
+
+    fn consumed(topic: &str, offsets: impl IntoIterator<Item = i64>) -> Vec<ConsumedRecord> {
+        offsets
+            .into_iter()
+            .map(|offset| ConsumedRecord {
+                topic_partition: TopicPartition::new(topic, 0),
+                offset,
+                record: Record::new(offset, None, Some(&offset.to_be_bytes())),
+            })
+            .collect()
+    }
+
+    fn task(
+        polls: Vec<Vec<ConsumedRecord>>,
+        cluster: TestCluster,
+    ) -> MirrorTask<TestConsumer, TestCluster> {
+        let consumer = TestConsumer {
+            polls: polls.into(),
+            ..TestConsumer::default()
+        };
+        MirrorTask::new(
+            consumer,
+            cluster,
+            Arc::new(DefaultReplicationPolicy::default()),
+            Arc::new(MockTime::default()),
+            "us-west",
+            "us-east",
+        )
+    }
+
+    fn offset_syncs(cluster: &TestCluster) -> Vec<(i64, i64)> {
+        cluster
+            .records("mm2-offset-syncs.us-east.internal", 0)
+            .iter()
+            .map(|record| {
+                let sync = OffsetSync::from_record(record).unwrap();
+                (sync.upstream_offset, sync.downstream_offset)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_mirror_task_at_least_once() {
+        let mut task = task(
+            vec![consumed("foo", 0..3), consumed("foo", [5, 6]), vec![]],
+            TestCluster::default(),
+        )
+        .with_offset_lag_max(10);
+        task.start(&mut TestCluster::default(), 0).unwrap();
+        assert!(!task.is_exactly_once());
+
+        assert_eq!(3, task.poll_once(0).unwrap());
+        assert_eq!(2, task.poll_once(0).unwrap());
+        assert_eq!(0, task.poll_once(0).unwrap());
+        let cluster = task.producer();
+        assert_eq!(5, cluster.records("us-west.foo", 0).len());
+        // A sync for the first record, and for the one after the skipped offsets.
+        assert_eq!(vec![(0, 100), (5, 103)], offset_syncs(cluster));
+        assert_eq!(
+            vec![
+                BTreeMap::from([(TopicPartition::new("foo", 0), 3)]),
+                BTreeMap::from([(TopicPartition::new("foo", 0), 7)]),
+            ],
+            task.consumer().commits
+        );
+
+        // The committed offsets of the groups translate to the remote partitions.
+        let mut store = OffsetSyncStore::default();
+        for (upstream_offset, downstream_offset) in offset_syncs(cluster) {
+            store.sync(sync(upstream_offset, downstream_offset));
+        }
+        let policy = DefaultReplicationPolicy::default();
+        let committed = BTreeMap::from([
+            (TopicPartition::new("foo", 0), 5),
+            (TopicPartition::new("__consumer_offsets", 0), 1),
+        ]);
+        let checkpoints = checkpoints(&policy, "us-west", &store, "g", &committed);
+        assert_eq!(
+            vec![Checkpoint {
+                consumer_group_id: "g".to_string(),
+                topic_partition: TopicPartition::new("us-west.foo", 0),
+                upstream_offset: 5,
+                downstream_offset: 103,
+                metadata: String::new(),
+            }],
+            checkpoints
+        );
+
+        let mut target = TestCluster::default();
+        let checkpoints_topic = TopicPartition::new(policy.checkpoints_topic("us-west"), 0);
+        let other_group = Checkpoint {
+            consumer_group_id: "h".to_string(),
+            ..checkpoints[0].clone()
+        };
+        for checkpoint in [&checkpoints[0], &other_group] {
+            let record = record(checkpoint.record_key(), checkpoint.record_value());
+            target.send(&checkpoints_topic, record).unwrap();
+        }
+        assert_eq!(
+            BTreeMap::from([(TopicPartition::new("us-west.foo", 0), 103)]),
+            translate_offsets(&mut target, &policy, "us-west", "g", 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_mirror_task_exactly_once() {
+        let cluster = TestCluster {
+            supports_transactions: true,
+            ..TestCluster::default()
+        };
+        let mut task = task(vec![consumed("foo", 0..3), consumed("foo", 3..5)], cluster)
+            .with_exactly_once(true);
+        task.start(&mut TestCluster::default(), 0).unwrap();
+        assert!(task.is_exactly_once());
+
+        assert_eq!(3, task.poll_once(0).unwrap());
+        assert_eq!(1, task.producer().committed_transactions);
+        // The positions go to the target, within the transaction, instead of the source.
+        assert!(task.consumer().commits.is_empty());
+
+        task.producer.fail_sends_to = Some("mm2-offsets.us-west.internal".to_string());
+        assert_eq!(
+            Errors::NotEnoughReplicas,
+            task.poll_once(0).unwrap_err().error()
+        );
+        // The aborted records are gone, and are consumed again.
+        assert_eq!(3, task.producer().records("us-west.foo", 0).len());
+        assert_eq!(
+            vec![(TopicPartition::new("foo", 0), 3)],
+            task.consumer().seeks
+        );
+
+        // A restarted task resumes from the positions of the last transaction.
+        let mut target = std::mem::take(&mut task.producer);
+        target.fail_sends_to = None;
+        let mut restarted = self::task(vec![], TestCluster::default()).with_exactly_once(true);
+        restarted.producer.supports_transactions = true;
+        restarted.start(&mut target, 0).unwrap();
+        assert_eq!(
+            vec![(TopicPartition::new("foo", 0), 3)],
+            restarted.consumer().seeks
+        );
+
+        // Without transactions on the target, the task falls back to at-least-once.
+        let mut task = self::task(vec![], TestCluster::default()).with_exactly_once(true);
+        task.start(&mut target, 0).unwrap();
+        assert!(!task.is_exactly_once());
+    }
+}
@@ -0,0 +1,10 @@
+pub mod admin;
+pub mod api_versions;
+pub mod client_config;
+pub mod consumer;
+pub mod fetch_buffer;
+pub mod mirror;
+pub mod node_throttles;
+pub mod producer;
+pub mod rebalance_protocol;
+pub mod record_accumulator;
@@ -0,0 +1,120 @@
+use crate::common::requests::abstract_response::AbstractResponse;
+use std::collections::HashMap;
+
+/// The brokers a client must not send requests to because they throttled it.
+///
+/// When a response reports a throttle time, the client holds back its produce, fetch and other
+/// requests to the broker until the throttle time has passed, rather than piling more requests
+/// onto a broker which is enforcing a quota on it.
+#[derive(Debug, Default)]
+pub struct NodeThrottles {
+    throttle_until_ms: HashMap<i32, i64>,
+}
+
+impl NodeThrottles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Throttles `node_id` until `throttle_until_ms`, unless it is already throttled for longer.
+    pub fn throttle(&mut self, node_id: i32, throttle_until_ms: i64) {
+        let until = self
+            .throttle_until_ms
+            .entry(node_id)
+            .or_insert(throttle_until_ms);
+        *until = (*until).max(throttle_until_ms);
+    }
+
+    /// Applies the throttle time of a `response` of `version` received from `node_id` at
+    /// `now_ms`.
+    pub fn maybe_throttle(
+        &mut self,
+        node_id: i32,
+        response: &dyn AbstractResponse,
+        version: i16,
+        now_ms: i64,
+    ) {
+        let throttle_time_ms = response.throttle_time_ms();
+        if throttle_time_ms > 0 && response.should_client_throttle(version) {
+            self.throttle(node_id, now_ms + throttle_time_ms as i64);
+        }
+    }
+
+    /// How long requests to `node_id` must still be held back for, 0 if it is not throttled.
+    pub fn throttle_delay_ms(&self, node_id: i32, now_ms: i64) -> i64 {
+        self.throttle_until_ms
+            .get(&node_id)
+            .map_or(0, |until| (until - now_ms).max(0))
+    }
+
+    pub fn is_throttled(&self, node_id: i32, now_ms: i64) -> bool {
+        self.throttle_delay_ms(node_id, now_ms) > 0
+    }
+
+    /// The time until the first throttled node can be sent to again, to bound how long a
+    /// client polls for, or `None` if no node is throttled.
+    pub fn min_throttle_delay_ms(&self, now_ms: i64) -> Option<i64> {
+        self.throttle_until_ms
+            .values()
+            .map(|until| until - now_ms)
+            .filter(|delay| *delay > 0)
+            .min()
+    }
+
+    /// Forgets the throttle of `node_id`, when its connection is closed.
+    pub fn remove(&mut self, node_id: i32) {
+        self.throttle_until_ms.remove(&node_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Response {
+        throttle_time_ms: i32,
+    }
+
+    impl AbstractResponse for Response {
+        fn throttle_time_ms(&self) -> i32 {
+            self.throttle_time_ms
+        }
+
+        fn maybe_set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+            self.throttle_time_ms = throttle_time_ms;
+        }
+
+        fn should_client_throttle(&self, version: i16) -> bool {
+            version >= 2
+        }
+    }
+
+    #[test]
+    fn test_throttle_from_response() {
+        let mut throttles = NodeThrottles::new();
+        let mut response = Response {
+            throttle_time_ms: 0,
+        };
+        throttles.maybe_throttle(1, &response, 2, 0);
+        assert!(!throttles.is_throttled(1, 0));
+
+        response.maybe_set_throttle_time_ms(100);
+        // The broker delayed old versions of the response itself.
+        throttles.maybe_throttle(1, &response, 1, 0);
+        assert!(!throttles.is_throttled(1, 0));
+
+        throttles.maybe_throttle(1, &response, 2, 0);
+        assert_eq!(60, throttles.throttle_delay_ms(1, 40));
+        assert_eq!(Some(60), throttles.min_throttle_delay_ms(40));
+        assert!(!throttles.is_throttled(2, 40));
+        assert!(!throttles.is_throttled(1, 100));
+        assert_eq!(None, throttles.min_throttle_delay_ms(100));
+
+        // A shorter throttle does not cut an ongoing one short.
+        throttles.throttle(1, 200);
+        throttles.throttle(1, 150);
+        assert_eq!(100, throttles.throttle_delay_ms(1, 100));
+        throttles.remove(1);
+        assert!(!throttles.is_throttled(1, 100));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::common::protocol::errors::{ApiError, Errors};
+use crate::common::record::record_batch::{Header, Record, RecordBatch};
+
+pub const MAX_REQUEST_SIZE_CONFIG: &str = "max.request.size";
+/// The default `max.request.size`: 1 MiB.
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+pub const BUFFER_MEMORY_CONFIG: &str = "buffer.memory";
+/// The default `buffer.memory`: 32 MiB.
+pub const DEFAULT_BUFFER_MEMORY: usize = 32 * 1024 * 1024;
+
+/// The size a record takes in a batch of its own, which bounds what it adds to any batch.
+pub fn estimate_size_in_bytes_upper_bound(
+    timestamp: i64,
+    key: Option<&[u8]>,
+    value: Option<&[u8]>,
+    headers: &[Header],
+) -> usize {
+    let mut record = Record::new(timestamp, key, value);
+    record.headers = headers.to_vec();
+    RecordBatch::new(0, vec![record]).encode().len()
+}
+
+/// Fails the send of a record of `size` bytes, as estimated by
+/// [estimate_size_in_bytes_upper_bound], which could never be sent: a record is never split,
+/// so one larger than `max.request.size` or `buffer.memory` can't fit in any request.
+///
+/// The broker enforces `max.message.bytes` on the batches it appends, answering
+/// `MESSAGE_TOO_LARGE`. Checking on the producer fails the send right away instead of after
+/// a round trip, and tells the application to split the record or raise the limits.
+pub fn ensure_valid_record_size(
+    size: usize,
+    max_request_size: usize,
+    buffer_memory: usize,
+) -> Result<(), ApiError> {
+    if size > max_request_size {
+        return Err(ApiError::new(
+            Errors::MessageTooLarge,
+            format!(
+                "The message is {size} bytes when serialized which is larger than \
+                {max_request_size}, which is the value of the {MAX_REQUEST_SIZE_CONFIG} \
+                configuration."
+            ),
+        ));
+    }
+    if size > buffer_memory {
+        return Err(ApiError::new(
+            Errors::MessageTooLarge,
+            format!(
+                "The message is {size} bytes when serialized which is larger than the total \
+                memory buffer you have configured with the {BUFFER_MEMORY_CONFIG} \
+                configuration."
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_valid_record_size() {
+        let value = vec![0; 1000];
+        let size = estimate_size_in_bytes_upper_bound(0, Some(b"key"), Some(&value), &[]);
+        assert!(size > 1003);
+        assert!(
+            ensure_valid_record_size(size, DEFAULT_MAX_REQUEST_SIZE, DEFAULT_BUFFER_MEMORY).is_ok()
+        );
+
+        let error = ensure_valid_record_size(size, 1000, DEFAULT_BUFFER_MEMORY).unwrap_err();
+        assert_eq!(Errors::MessageTooLarge, error.error());
+        assert!(error.message().contains(MAX_REQUEST_SIZE_CONFIG));
+        let error = ensure_valid_record_size(size, DEFAULT_MAX_REQUEST_SIZE, 1000).unwrap_err();
+        assert!(error.message().contains(BUFFER_MEMORY_CONFIG));
+    }
+}
@@ -0,0 +1,200 @@
+//! The rebalance protocols of the classic consumer groups.
+//!
+//! With the EAGER protocol, every member revokes all its partitions before it rejoins the
+//! group, so that no partition is ever owned by two members, and consumption stops for the
+//! whole rebalance. With the COOPERATIVE protocol, the members keep consuming their partitions
+//! while the group rebalances and revoke only those which move: the leader holds the partitions
+//! still owned by another member back from their new owner, the owner revokes them once it
+//! receives its assignment and rejoins, and a second rebalance hands them over.
+
+use crate::common::topic_partition::TopicPartition;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+pub const PARTITION_ASSIGNMENT_STRATEGY_CONFIG: &str = "partition.assignment.strategy";
+
+pub const RANGE_ASSIGNOR_NAME: &str = "range";
+pub const ROUND_ROBIN_ASSIGNOR_NAME: &str = "roundrobin";
+pub const STICKY_ASSIGNOR_NAME: &str = "sticky";
+pub const COOPERATIVE_STICKY_ASSIGNOR_NAME: &str = "cooperative-sticky";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RebalanceProtocol {
+    Eager,
+    Cooperative,
+}
+
+impl RebalanceProtocol {
+    /// The protocols the assignor named `assignor` supports. The assignors other than
+    /// `cooperative-sticky` only support EAGER.
+    pub fn supported_by(assignor: &str) -> &'static [RebalanceProtocol] {
+        match assignor {
+            COOPERATIVE_STICKY_ASSIGNOR_NAME => {
+                &[RebalanceProtocol::Cooperative, RebalanceProtocol::Eager]
+            }
+            _ => &[RebalanceProtocol::Eager],
+        }
+    }
+
+    /// The protocol a consumer with the assignors of `partition.assignment.strategy` follows:
+    /// COOPERATIVE if they all support it, which lets them be upgraded one consumer at a time
+    /// by listing `cooperative-sticky` first and the previous assignor second, and EAGER
+    /// otherwise.
+    pub fn of_assignors<'a>(assignors: impl IntoIterator<Item = &'a str>) -> RebalanceProtocol {
+        let all_cooperative = assignors
+            .into_iter()
+            .all(|assignor| Self::supported_by(assignor).contains(&RebalanceProtocol::Cooperative));
+        match all_cooperative {
+            true => RebalanceProtocol::Cooperative,
+            false => RebalanceProtocol::Eager,
+        }
+    }
+
+    /// The partitions a member owning `owned` revokes before it rejoins its group: all of
+    /// them with EAGER, none with COOPERATIVE.
+    pub fn revoked_on_join(&self, owned: &BTreeSet<TopicPartition>) -> BTreeSet<TopicPartition> {
+        match self {
+            RebalanceProtocol::Eager => owned.clone(),
+            RebalanceProtocol::Cooperative => BTreeSet::new(),
+        }
+    }
+
+    /// How the partitions of a member owning `owned` change once it is assigned `assigned`.
+    pub fn assignment_change(
+        &self,
+        owned: &BTreeSet<TopicPartition>,
+        assigned: &BTreeSet<TopicPartition>,
+    ) -> AssignmentChange {
+        let revoked: BTreeSet<TopicPartition> = owned.difference(assigned).cloned().collect();
+        AssignmentChange {
+            added: assigned.difference(owned).cloned().collect(),
+            // With EAGER, the partitions which moved were revoked before the member rejoined.
+            rejoin: *self == RebalanceProtocol::Cooperative && !revoked.is_empty(),
+            revoked,
+        }
+    }
+}
+
+impl fmt::Display for RebalanceProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RebalanceProtocol::Eager => write!(f, "EAGER"),
+            RebalanceProtocol::Cooperative => write!(f, "COOPERATIVE"),
+        }
+    }
+}
+
+/// How the partitions of a member change with its new assignment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssignmentChange {
+    /// The partitions to start consuming.
+    pub added: BTreeSet<TopicPartition>,
+    /// The partitions to revoke, committing their positions first.
+    pub revoked: BTreeSet<TopicPartition>,
+    /// Whether the member must rejoin the group once it revoked them, so that a second
+    /// rebalance hands them to their new owner.
+    pub rejoin: bool,
+}
+
+/// Holds back, in the `assignment` a COOPERATIVE leader computed, the partitions which move to
+/// a member while another one still owns them according to `owned`, so that no partition is
+/// consumed by two members at once. Returns whether any was held back, in which case the group
+/// rebalances again once their owners revoked them.
+pub fn hold_back_transferring_partitions(
+    owned: &BTreeMap<String, BTreeSet<TopicPartition>>,
+    assignment: &mut BTreeMap<String, BTreeSet<TopicPartition>>,
+) -> bool {
+    let owners: BTreeMap<&TopicPartition, &str> = owned
+        .iter()
+        .flat_map(|(member, partitions)| partitions.iter().map(|tp| (tp, member.as_str())))
+        .collect();
+    let mut held_back = false;
+    for (member, partitions) in assignment.iter_mut() {
+        partitions.retain(|tp| {
+            let transferring = owners.get(tp).is_some_and(|owner| owner != member);
+            held_back |= transferring;
+            !transferring
+        });
+    }
+    held_back
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partitions(ids: &[i32]) -> BTreeSet<TopicPartition> {
+        ids.iter()
+            .map(|id| TopicPartition::new("foo", *id))
+            .collect()
+    }
+
+    #[test]
+    fn test_protocol_of_assignors() {
+        assert_eq!(
+            RebalanceProtocol::Cooperative,
+            RebalanceProtocol::of_assignors([COOPERATIVE_STICKY_ASSIGNOR_NAME])
+        );
+        assert_eq!(
+            RebalanceProtocol::Eager,
+            RebalanceProtocol::of_assignors([
+                COOPERATIVE_STICKY_ASSIGNOR_NAME,
+                RANGE_ASSIGNOR_NAME
+            ])
+        );
+        assert_eq!(
+            partitions(&[0]),
+            RebalanceProtocol::Eager.revoked_on_join(&partitions(&[0]))
+        );
+        assert!(
+            RebalanceProtocol::Cooperative
+                .revoked_on_join(&partitions(&[0]))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_cooperative_rebalance_revokes_in_two_phases() {
+        // a owns the two partitions, and b joins.
+        let mut owned = BTreeMap::from([
+            ("a".to_string(), partitions(&[0, 1])),
+            ("b".to_string(), partitions(&[])),
+        ]);
+        let target = BTreeMap::from([
+            ("a".to_string(), partitions(&[0])),
+            ("b".to_string(), partitions(&[1])),
+        ]);
+
+        // First rebalance: a keeps consuming 0 and revokes 1, which b does not get yet.
+        let mut assignment = target.clone();
+        assert!(hold_back_transferring_partitions(&owned, &mut assignment));
+        assert_eq!(partitions(&[]), assignment["b"]);
+        let protocol = RebalanceProtocol::Cooperative;
+        let a = protocol.assignment_change(&owned["a"], &assignment["a"]);
+        assert_eq!(
+            AssignmentChange {
+                added: partitions(&[]),
+                revoked: partitions(&[1]),
+                rejoin: true,
+            },
+            a
+        );
+        assert!(
+            !protocol
+                .assignment_change(&owned["b"], &assignment["b"])
+                .rejoin
+        );
+        owned.insert("a".to_string(), assignment["a"].clone());
+
+        // Second rebalance, once a rejoined: b gets 1.
+        let mut assignment = target;
+        assert!(!hold_back_transferring_partitions(&owned, &mut assignment));
+        let b = protocol.assignment_change(&owned["b"], &assignment["b"]);
+        assert_eq!(partitions(&[1]), b.added);
+        assert!(
+            !protocol
+                .assignment_change(&owned["a"], &assignment["a"])
+                .rejoin
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use crate::clients::producer::{
+    DEFAULT_BUFFER_MEMORY, DEFAULT_MAX_REQUEST_SIZE, ensure_valid_record_size,
+    estimate_size_in_bytes_upper_bound,
+};
+use crate::common::protocol::errors::{ApiError, Errors};
+use crate::common::record::record_batch::{Record, RecordBatch};
+use crate::common::topic_partition::TopicPartition;
+use crate::common::utils::time::Time;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, Sender, channel};
+
+pub const BATCH_SIZE_CONFIG: &str = "batch.size";
+/// The default `batch.size`: 16 KiB.
+pub const DEFAULT_BATCH_SIZE: usize = 16 * 1024;
+
+pub const LINGER_MS_CONFIG: &str = "linger.ms";
+/// The default `linger.ms`.
+pub const DEFAULT_LINGER_MS: i64 = 5;
+
+pub const DELIVERY_TIMEOUT_MS_CONFIG: &str = "delivery.timeout.ms";
+/// The default `delivery.timeout.ms`: 2 minutes.
+pub const DEFAULT_DELIVERY_TIMEOUT_MS: i64 = 120_000;
+
+/// Where a record was appended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordMetadata {
+    pub topic_partition: TopicPartition,
+    pub offset: i64,
+    pub timestamp: i64,
+}
+
+/// The outcome of the send of a record.
+pub type SendResult = Result<RecordMetadata, ApiError>;
+
+/// Sends the batches of the producer to the leaders of their partitions.
+pub trait BatchSender {
+    /// Sends `batch` to `topic_partition`, waiting up to `timeout_ms` for the response, and
+    /// returns the offset the leader appended it at.
+    fn send(
+        &mut self,
+        topic_partition: &TopicPartition,
+        batch: RecordBatch,
+        timeout_ms: i64,
+    ) -> Result<i64, ApiError>;
+}
+
+/// Records of a partition sent together, and the senders waiting for them.
+struct ProducerBatch {
+    created_ms: i64,
+    size: usize,
+    records: Vec<Record>,
+    completions: Vec<Sender<SendResult>>,
+}
+
+/// Accumulates the records of the producer into batches per partition and sends them.
+///
+/// A record joins the last batch of its partition while it fits in `batch.size`, and a batch
+/// is sent once it is full or `linger.ms` after it was started. [flush](Self::flush) sends
+/// every batch without waiting for the linger, and [close](Self::close) stops taking records
+/// and flushes within a timeout, failing the batches it could not send in time. Either way,
+/// every record sent before gets its outcome once the call returns.
+pub struct RecordAccumulator<S> {
+    sender: S,
+    time: Arc<dyn Time>,
+    batch_size: usize,
+    linger_ms: i64,
+    max_request_size: usize,
+    buffer_memory: usize,
+    batches: BTreeMap<TopicPartition, VecDeque<ProducerBatch>>,
+    closed: bool,
+}
+
+impl<S: BatchSender> RecordAccumulator<S> {
+    pub fn new(sender: S, time: Arc<dyn Time>) -> Self {
+        Self {
+            sender,
+            time,
+            batch_size: DEFAULT_BATCH_SIZE,
+            linger_ms: DEFAULT_LINGER_MS,
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            buffer_memory: DEFAULT_BUFFER_MEMORY,
+            batches: BTreeMap::new(),
+            closed: false,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn with_linger_ms(mut self, linger_ms: i64) -> Self {
+        self.linger_ms = linger_ms;
+        self
+    }
+
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// The number of records waiting to be sent.
+    pub fn pending_records(&self) -> usize {
+        self.batches
+            .values()
+            .flatten()
+            .map(|batch| batch.records.len())
+            .sum()
+    }
+
+    /// Adds `record` to the batches of `topic_partition`. The receiver gets the outcome once
+    /// the batch is sent.
+    ///
+    /// Fails with `MessageTooLarge` if the record can never be sent, and with
+    /// `UnknownServerError` once the producer is closed.
+    pub fn append(
+        &mut self,
+        topic_partition: TopicPartition,
+        record: Record,
+    ) -> Result<Receiver<SendResult>, ApiError> {
+        if self.closed {
+            return Err(ApiError::new(
+                Errors::UnknownServerError,
+                "Cannot perform operation after producer has been closed",
+            ));
+        }
+        let size = estimate_size_in_bytes_upper_bound(
+            record.timestamp,
+            record.key.as_deref(),
+            record.value.as_deref(),
+            &record.headers,
+        );
+        ensure_valid_record_size(size, self.max_request_size, self.buffer_memory)?;
+
+        let now_ms = self.time.milliseconds();
+        let (sender, receiver) = channel();
+        let batches = self.batches.entry(topic_partition).or_default();
+        let batch = match batches.back_mut() {
+            Some(batch) if batch.size + size <= self.batch_size => batch,
+            _ => {
+                batches.push_back(ProducerBatch {
+                    created_ms: now_ms,
+                    size: 0,
+                    records: Vec::new(),
+                    completions: Vec::new(),
+                });
+                batches.back_mut().expect("a batch was just added")
+            }
+        };
+        batch.size += size;
+        batch.records.push(record);
+        batch.completions.push(sender);
+        Ok(receiver)
+    }
+
+    /// Sends the batches which are full or lingered for `linger.ms`.
+    pub fn send_ready(&mut self) {
+        let linger_ms = self.linger_ms;
+        let batch_size = self.batch_size;
+        let deadline_ms = self.time.milliseconds() + DEFAULT_DELIVERY_TIMEOUT_MS;
+        self.send_while(deadline_ms, |batches, now_ms| {
+            batches.front().is_some_and(|batch| {
+                batches.len() > 1
+                    || batch.size >= batch_size
+                    || now_ms - batch.created_ms >= linger_ms
+            })
+        });
+    }
+
+    /// Sends every batch without waiting for the linger, returning once each record sent
+    /// before got its outcome.
+    pub fn flush(&mut self) {
+        let deadline_ms = self.time.milliseconds() + DEFAULT_DELIVERY_TIMEOUT_MS;
+        self.send_while(deadline_ms, |batches, _| !batches.is_empty());
+    }
+
+    /// Stops taking records and sends the pending batches within `timeout_ms`. The batches
+    /// still pending once it expired are failed, so that every record gets its outcome; with
+    /// a timeout of 0, all of them are.
+    pub fn close(&mut self, timeout_ms: i64) {
+        self.closed = true;
+        let deadline_ms = self.time.milliseconds().saturating_add(timeout_ms.max(0));
+        self.send_while(deadline_ms, |batches, now_ms| {
+            !batches.is_empty() && now_ms < deadline_ms
+        });
+        for (topic_partition, batches) in std::mem::take(&mut self.batches) {
+            for batch in batches {
+                complete(
+                    &topic_partition,
+                    batch,
+                    Err(ApiError::new(
+                        Errors::UnknownServerError,
+                        "Producer is closed forcefully.",
+                    )),
+                );
+            }
+        }
+    }
+
+    /// Sends the batches of each partition in order, as long as `should_send` holds for the
+    /// partition's batches at the current time. Each send waits for the response up to
+    /// `deadline_ms`.
+    fn send_while(
+        &mut self,
+        deadline_ms: i64,
+        should_send: impl Fn(&VecDeque<ProducerBatch>, i64) -> bool,
+    ) {
+        let partitions: Vec<TopicPartition> = self.batches.keys().cloned().collect();
+        for topic_partition in partitions {
+            loop {
+                let now_ms = self.time.milliseconds();
+                let Some(batches) = self.batches.get_mut(&topic_partition) else {
+                    break;
+                };
+                if !should_send(batches, now_ms) {
+                    break;
+                }
+                let batch = batches.pop_front().expect("should_send saw a batch");
+                if batches.is_empty() {
+                    self.batches.remove(&topic_partition);
+                }
+                let result = self.sender.send(
+                    &topic_partition,
+                    RecordBatch::new(0, batch.records.clone()),
+                    (deadline_ms - now_ms).max(0),
+                );
+                complete(&topic_partition, batch, result);
+            }
+        }
+    }
+}
+
+/// Hands the outcome of the send of `batch` to the record senders.
+fn complete(topic_partition: &TopicPartition, batch: ProducerBatch, result: Result<i64, ApiError>) {
+    for (index, (record, completion)) in batch.records.iter().zip(batch.completions).enumerate() {
+        let outcome = result.clone().map(|base_offset| RecordMetadata {
+            topic_partition: topic_partition.clone(),
+            offset: base_offset + index as i64,
+            timestamp: record.timestamp,
+        });
+        // The application may not wait for the outcome of the send.
+        let _ = completion.send(outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::utils::time::MockTime;
+
+    /// Records the batches sent, each taking `send_ms`.
+    struct RecordingSender {
+        time: Arc<MockTime>,
+        send_ms: i64,
+        sent: Vec<(TopicPartition, usize, i64)>,
+        next_offset: i64,
+    }
+
+    impl BatchSender for RecordingSender {
+        fn send(
+            &mut self,
+            topic_partition: &TopicPartition,
+            batch: RecordBatch,
+            timeout_ms: i64,
+        ) -> Result<i64, ApiError> {
+            self.time.sleep(self.send_ms);
+            let count = batch.records().len();
+            self.sent.push((topic_partition.clone(), count, timeout_ms));
+            let base_offset = self.next_offset;
+            self.next_offset += count as i64;
+            Ok(base_offset)
+        }
+    }
+
+    fn new_accumulator(send_ms: i64) -> (Arc<MockTime>, RecordAccumulator<RecordingSender>) {
+        let time = Arc::new(MockTime::with_start(0, 0, 0));
+        let sender = RecordingSender {
+            time: Arc::clone(&time),
+            send_ms,
+            sent: Vec::new(),
+            next_offset: 0,
+        };
+        let accumulator = RecordAccumulator::new(sender, time.clone()).with_linger_ms(10);
+        (time, accumulator)
+    }
+
+    fn record(timestamp: i64) -> Record {
+        Record::new(timestamp, None, Some(&[0; 100]))
+    }
+
+    #[test]
+    fn test_batches_are_sent_when_full_or_lingered() {
+        let (time, accumulator) = new_accumulator(0);
+        let mut accumulator = accumulator.with_batch_size(400);
+        let foo = TopicPartition::new("foo", 0);
+        let bar = TopicPartition::new("bar", 0);
+        let first = accumulator.append(foo.clone(), record(1)).unwrap();
+        for timestamp in 2..=4 {
+            accumulator.append(foo.clone(), record(timestamp)).unwrap();
+        }
+        accumulator.append(bar.clone(), record(5)).unwrap();
+        accumulator.send_ready();
+        // Only the first batch of foo is full.
+        assert_eq!(1, accumulator.sender().sent.len());
+        assert_eq!(
+            Ok(RecordMetadata {
+                topic_partition: foo.clone(),
+                offset: 0,
+                timestamp: 1,
+            }),
+            first.recv().unwrap()
+        );
+
+        time.sleep(10);
+        accumulator.send_ready();
+        assert_eq!(0, accumulator.pending_records());
+        let sent: Vec<_> = accumulator
+            .sender()
+            .sent
+            .iter()
+            .map(|(tp, n, _)| (tp, *n))
+            .collect();
+        assert_eq!(3, sent.len());
+        assert!(sent.contains(&(&bar, 1)));
+    }
+
+    #[test]
+    fn test_flush_sends_every_batch() {
+        let (_, mut accumulator) = new_accumulator(0);
+        let receivers: Vec<_> = (0..3)
+            .map(|i| {
+                accumulator
+                    .append(TopicPartition::new("foo", i), record(0))
+                    .unwrap()
+            })
+            .collect();
+        accumulator.flush();
+        assert_eq!(0, accumulator.pending_records());
+        for receiver in receivers {
+            assert!(receiver.try_recv().unwrap().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_close_fails_the_batches_it_could_not_send() {
+        let (_, mut accumulator) = new_accumulator(60);
+        let receivers: Vec<_> = (0..3)
+            .map(|i| {
+                accumulator
+                    .append(TopicPartition::new("foo", i), record(0))
+                    .unwrap()
+            })
+            .collect();
+        accumulator.close(100);
+        assert!(accumulator.is_closed());
+        // The second send starts with 40 ms left, after which the timeout expired.
+        let timeouts: Vec<i64> = accumulator
+            .sender()
+            .sent
+            .iter()
+            .map(|(_, _, t)| *t)
+            .collect();
+        assert_eq!(vec![100, 40], timeouts);
+        let outcomes: Vec<bool> = receivers
+            .iter()
+            .map(|receiver| receiver.try_recv().unwrap().is_ok())
+            .collect();
+        assert_eq!(vec![true, true, false], outcomes);
+
+        assert_eq!(
+            Errors::UnknownServerError,
+            accumulator
+                .append(TopicPartition::new("foo", 0), record(0))
+                .unwrap_err()
+                .error()
+        );
+
+        let (_, mut accumulator) = new_accumulator(0);
+        let receiver = accumulator
+            .append(TopicPartition::new("foo", 0), record(0))
+            .unwrap();
+        accumulator.close(0);
+        assert!(accumulator.sender().sent.is_empty());
+        assert!(receiver.try_recv().unwrap().is_err());
+    }
+}
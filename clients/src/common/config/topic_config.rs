@@ -32,3 +32,23 @@ pub const PREALLOCATE_CONFIG: &str = "preallocate";
 pub const MESSAGE_TIMESTAMP_TYPE_CONFIG: &str = "message.timestamp.type";
 pub const MESSAGE_TIMESTAMP_BEFORE_MAX_MS_CONFIG: &str = "message.timestamp.before.max.ms";
 pub const MESSAGE_TIMESTAMP_AFTER_MAX_MS_CONFIG: &str = "message.timestamp.after.max.ms";
+pub const LEADER_REPLICATION_THROTTLED_REPLICAS_CONFIG: &str =
+    "leader.replication.throttled.replicas";
+pub const FOLLOWER_REPLICATION_THROTTLED_REPLICAS_CONFIG: &str =
+    "follower.replication.throttled.replicas";
+
+/// The configs whose value is a comma-separated list, which IncrementalAlterConfigs can
+/// append values to and subtract values from, with their default value.
+pub const LIST_CONFIGS: &[(&str, &str)] = &[
+    (CLEANUP_POLICY_CONFIG, CLEANUP_POLICY_DELETE),
+    (LEADER_REPLICATION_THROTTLED_REPLICAS_CONFIG, ""),
+    (FOLLOWER_REPLICATION_THROTTLED_REPLICAS_CONFIG, ""),
+];
+
+/// The default value of the list config `name`, or `None` if it is not a list config.
+pub fn list_config_default(name: &str) -> Option<&'static str> {
+    LIST_CONFIGS
+        .iter()
+        .find(|(config, _)| *config == name)
+        .map(|(_, default)| *default)
+}
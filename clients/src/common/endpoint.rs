@@ -0,0 +1,191 @@
+use crate::common::security_protocol::SecurityProtocol;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EndpointError {
+    #[error("Unable to parse {0} to a listener, expected <listener name>://<host>:<port>")]
+    InvalidListener(String),
+
+    #[error("Unable to parse {0} to a listener security protocol map entry")]
+    InvalidSecurityProtocolMapEntry(String),
+
+    #[error("Unknown security protocol {0}")]
+    UnknownSecurityProtocol(String),
+
+    #[error("No security protocol is defined for listener {0}")]
+    UnmappedListener(String),
+
+    #[error("Each listener must have a different name, {0} appears more than once")]
+    DuplicateListenerName(String),
+
+    #[error(
+        "Each listener must have a different port unless exactly one is an IPv4 address and \
+        the other an IPv6 address, port {0} is used by {1}"
+    )]
+    DuplicatePort(u16, String),
+
+    #[error("Advertised listener {0} must not bind to the wildcard address {1}")]
+    UnroutableAdvertisedHost(String, String),
+}
+
+/// Normalizes a listener name, which is case-insensitive.
+pub fn normalize_listener_name(name: &str) -> String {
+    name.to_uppercase()
+}
+
+/// Parses a `listener.security.protocol.map` config, e.g. `INTERNAL:SSL,EXTERNAL:SASL_SSL`,
+/// into a map keyed by normalized listener name.
+pub fn parse_listener_security_protocol_map(
+    map: &str,
+) -> Result<HashMap<String, SecurityProtocol>, EndpointError> {
+    let mut protocols = HashMap::new();
+    for entry in map.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, protocol) = entry
+            .split_once(':')
+            .filter(|(name, _)| !name.trim().is_empty())
+            .ok_or_else(|| EndpointError::InvalidSecurityProtocolMapEntry(entry.to_string()))?;
+        let protocol = SecurityProtocol::for_name(protocol.trim())
+            .ok_or_else(|| EndpointError::UnknownSecurityProtocol(protocol.to_string()))?;
+        let name = normalize_listener_name(name.trim());
+        if protocols.insert(name.clone(), protocol).is_some() {
+            return Err(EndpointError::DuplicateListenerName(name));
+        }
+    }
+    Ok(protocols)
+}
+
+/// A listener of a broker: where it accepts connections and with which security protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    /// The normalized listener name, e.g. `PLAINTEXT` or `INTERNAL`.
+    pub listener_name: String,
+    pub security_protocol: SecurityProtocol,
+    /// The host, without the brackets of an IPv6 literal. Empty to bind to the default
+    /// interface.
+    pub host: String,
+    pub port: u16,
+}
+
+impl Endpoint {
+    /// Parses a listener in the form `<listener name>://<host>:<port>`, e.g.
+    /// `PLAINTEXT://myhost:9092`, `SSL://:9091` or `EXTERNAL://[::1]:9092`, resolving its
+    /// security protocol with `protocol_map`.
+    pub fn parse(
+        listener: &str,
+        protocol_map: &HashMap<String, SecurityProtocol>,
+    ) -> Result<Self, EndpointError> {
+        let invalid = || EndpointError::InvalidListener(listener.to_string());
+        let (name, address) = listener.trim().split_once("://").ok_or_else(invalid)?;
+        let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+        let host = match host.strip_prefix('[') {
+            Some(host) => host.strip_suffix(']').ok_or_else(invalid)?,
+            // An IPv6 literal must be in brackets, or its port can't be told apart.
+            None if host.contains(':') => return Err(invalid()),
+            None => host,
+        };
+        let port = port.parse::<u16>().map_err(|_| invalid())?;
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        let listener_name = normalize_listener_name(name);
+        let security_protocol = *protocol_map
+            .get(&listener_name)
+            .ok_or_else(|| EndpointError::UnmappedListener(listener_name.clone()))?;
+        Ok(Self {
+            listener_name,
+            security_protocol,
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// The host parsed as an IP address, if it is an IP literal.
+    pub fn ip_address(&self) -> Option<IpAddr> {
+        self.host.parse().ok()
+    }
+
+    /// Whether the host is the wildcard address, e.g. `0.0.0.0` or `::`, which binds to all
+    /// interfaces.
+    pub fn is_wildcard(&self) -> bool {
+        self.ip_address().is_some_and(|ip| ip.is_unspecified())
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "{}://[{}]:{}", self.listener_name, self.host, self.port)
+        } else {
+            write!(f, "{}://{}:{}", self.listener_name, self.host, self.port)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protocol_map() -> HashMap<String, SecurityProtocol> {
+        parse_listener_security_protocol_map("PLAINTEXT:PLAINTEXT, internal:SSL").unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let endpoint = Endpoint::parse("internal://[::1]:9093", &protocol_map()).unwrap();
+        assert_eq!("INTERNAL", endpoint.listener_name);
+        assert_eq!(SecurityProtocol::Ssl, endpoint.security_protocol);
+        assert_eq!("::1", endpoint.host);
+        assert_eq!(9093, endpoint.port);
+        assert_eq!("INTERNAL://[::1]:9093", endpoint.to_string());
+
+        let endpoint = Endpoint::parse("PLAINTEXT://:9092", &protocol_map()).unwrap();
+        assert_eq!("", endpoint.host);
+        assert!(!endpoint.is_wildcard());
+        assert!(
+            Endpoint::parse("PLAINTEXT://0.0.0.0:9092", &protocol_map())
+                .unwrap()
+                .is_wildcard()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for listener in [
+            "PLAINTEXT:localhost:9092",
+            "PLAINTEXT://localhost",
+            "PLAINTEXT://localhost:-1",
+            "PLAINTEXT://::1:9092",
+            "://localhost:9092",
+        ] {
+            assert_eq!(
+                Err(EndpointError::InvalidListener(listener.to_string())),
+                Endpoint::parse(listener, &protocol_map())
+            );
+        }
+        assert_eq!(
+            Err(EndpointError::UnmappedListener("EXTERNAL".to_string())),
+            Endpoint::parse("external://localhost:9092", &protocol_map())
+        );
+    }
+
+    #[test]
+    fn test_parse_security_protocol_map_errors() {
+        assert_eq!(
+            Err(EndpointError::UnknownSecurityProtocol("TLS".to_string())),
+            parse_listener_security_protocol_map("A:TLS")
+        );
+        assert_eq!(
+            Err(EndpointError::DuplicateListenerName("A".to_string())),
+            parse_listener_security_protocol_map("A:SSL,a:PLAINTEXT")
+        );
+        assert_eq!(
+            Err(EndpointError::InvalidSecurityProtocolMapEntry(
+                "PLAINTEXT".to_string()
+            )),
+            parse_listener_security_protocol_map("PLAINTEXT")
+        );
+    }
+}
@@ -0,0 +1,10 @@
+/// The topic the group coordinator stores offsets and group metadata in.
+pub const GROUP_METADATA_TOPIC_NAME: &str = "__consumer_offsets";
+
+/// The topic the transaction coordinator stores the transaction states in.
+pub const TRANSACTION_STATE_TOPIC_NAME: &str = "__transaction_state";
+
+/// Whether `topic` is written by a coordinator rather than by clients.
+pub fn is_internal(topic: &str) -> bool {
+    topic == GROUP_METADATA_TOPIC_NAME || topic == TRANSACTION_STATE_TOPIC_NAME
+}
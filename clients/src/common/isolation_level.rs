@@ -0,0 +1,55 @@
+//! Which records a fetch returns from partitions being written to by transactions.
+
+/// The config of the consumer setting its isolation level.
+pub const ISOLATION_LEVEL_CONFIG: &str = "isolation.level";
+
+/// Which records a consumer reads: all those below the high watermark, or only those below the
+/// last stable offset, i.e. those of the transactions already committed or aborted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IsolationLevel {
+    #[default]
+    ReadUncommitted,
+    ReadCommitted,
+}
+
+impl IsolationLevel {
+    /// The id of the level in Fetch and ListOffsets requests.
+    pub fn id(&self) -> i8 {
+        match self {
+            IsolationLevel::ReadUncommitted => 0,
+            IsolationLevel::ReadCommitted => 1,
+        }
+    }
+
+    pub fn for_id(id: i8) -> Option<Self> {
+        match id {
+            0 => Some(IsolationLevel::ReadUncommitted),
+            1 => Some(IsolationLevel::ReadCommitted),
+            _ => None,
+        }
+    }
+
+    /// The name of the level in the `isolation.level` config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "read_uncommitted",
+            IsolationLevel::ReadCommitted => "read_committed",
+        }
+    }
+
+    pub fn for_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "read_uncommitted" => Some(IsolationLevel::ReadUncommitted),
+            "read_committed" => Some(IsolationLevel::ReadCommitted),
+            _ => None,
+        }
+    }
+
+    /// The offset a fetch at this level reads up to, exclusive.
+    pub fn max_offset(&self, high_watermark: i64, last_stable_offset: i64) -> i64 {
+        match self {
+            IsolationLevel::ReadUncommitted => high_watermark,
+            IsolationLevel::ReadCommitted => last_stable_offset,
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::common::metrics::{MetricName, Metrics};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The number of values a reservoir keeps by default, which offers a 99.9% confidence level
+/// with a 5% margin of error assuming a normal distribution.
+pub const DEFAULT_RESERVOIR_SIZE: usize = 1028;
+/// The default decay of the weight of values, per second, which heavily biases the reservoir
+/// to the past 5 minutes of measurements.
+pub const DEFAULT_ALPHA: f64 = 0.015;
+
+const RESCALE_THRESHOLD_MS: i64 = 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy)]
+struct WeightedSample {
+    value: f64,
+    weight: f64,
+}
+
+/// A random sample of a stream of values, biased towards recent values by a forward decay
+/// priority: a value recorded `t` seconds after the landmark weighs `exp(alpha * t)`.
+#[derive(Debug)]
+struct ExponentiallyDecayingReservoir {
+    size: usize,
+    alpha: f64,
+    /// The samples keyed by priority, as priorities are positive their bits sort like them.
+    values: BTreeMap<u64, WeightedSample>,
+    start_time_ms: i64,
+    next_scale_time_ms: i64,
+}
+
+impl ExponentiallyDecayingReservoir {
+    fn new(size: usize, alpha: f64, now_ms: i64) -> Self {
+        Self {
+            size,
+            alpha,
+            values: BTreeMap::new(),
+            start_time_ms: now_ms,
+            next_scale_time_ms: now_ms + RESCALE_THRESHOLD_MS,
+        }
+    }
+
+    fn update(&mut self, value: f64, now_ms: i64) {
+        self.rescale_if_needed(now_ms);
+        let weight = (self.alpha * (now_ms - self.start_time_ms) as f64 / 1000.0).exp();
+        // `random` is in [0, 1), the priority must stay finite.
+        let priority = weight / (1.0 - rand::random::<f64>());
+        if !priority.is_finite() {
+            return;
+        }
+        let sample = WeightedSample { value, weight };
+        if self.values.len() < self.size {
+            self.values.insert(priority.to_bits(), sample);
+        } else if let Some((&first, _)) = self.values.first_key_value()
+            && first < priority.to_bits()
+            && self.values.insert(priority.to_bits(), sample).is_none()
+        {
+            self.values.remove(&first);
+        }
+    }
+
+    /// Moves the landmark to now, so the weights of new values don't overflow.
+    fn rescale_if_needed(&mut self, now_ms: i64) {
+        if now_ms < self.next_scale_time_ms {
+            return;
+        }
+        let old_start_time_ms = self.start_time_ms;
+        self.start_time_ms = now_ms;
+        self.next_scale_time_ms = now_ms + RESCALE_THRESHOLD_MS;
+        let factor = (-self.alpha * (now_ms - old_start_time_ms) as f64 / 1000.0).exp();
+        if factor == 0.0 {
+            self.values.clear();
+            return;
+        }
+        self.values = std::mem::take(&mut self.values)
+            .into_iter()
+            .filter_map(|(priority, sample)| {
+                let weight = sample.weight * factor;
+                (weight != 0.0).then(|| {
+                    let priority = f64::from_bits(priority) * factor;
+                    (
+                        priority.to_bits(),
+                        WeightedSample {
+                            value: sample.value,
+                            weight,
+                        },
+                    )
+                })
+            })
+            .collect();
+    }
+
+    fn snapshot(&mut self, now_ms: i64) -> HistogramSnapshot {
+        self.rescale_if_needed(now_ms);
+        HistogramSnapshot::new(self.values.values().copied().collect())
+    }
+}
+
+/// A histogram of the distribution of recorded values, for latencies whose tail an average
+/// hides.
+///
+/// The histogram keeps an exponentially decaying reservoir of the values, as the Yammer
+/// metrics library does: a fixed size sample which represents the last minutes of values,
+/// from which percentiles are measured.
+#[derive(Debug)]
+pub struct Histogram {
+    reservoir: Mutex<ExponentiallyDecayingReservoir>,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    pub fn new(now_ms: i64) -> Self {
+        Self::with_reservoir(DEFAULT_RESERVOIR_SIZE, DEFAULT_ALPHA, now_ms)
+    }
+
+    pub fn with_reservoir(size: usize, alpha: f64, now_ms: i64) -> Self {
+        Self {
+            reservoir: Mutex::new(ExponentiallyDecayingReservoir::new(size, alpha, now_ms)),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn update(&self, value: f64, now_ms: i64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.reservoir
+            .lock()
+            .expect("histogram lock poisoned")
+            .update(value, now_ms);
+    }
+
+    /// The number of values recorded since the histogram was created.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self, now_ms: i64) -> HistogramSnapshot {
+        self.reservoir
+            .lock()
+            .expect("histogram lock poisoned")
+            .snapshot(now_ms)
+    }
+
+    /// Registers a gauge for each of `percentiles`, between 0 and 1, named after `name` and
+    /// the percentile, e.g. `request-latency-p99` or `request-latency-p99.9`.
+    pub fn add_percentile_metrics(
+        self: &Arc<Self>,
+        metrics: &Metrics,
+        name: &str,
+        group: &str,
+        tags: &[(&str, &str)],
+        percentiles: &[f64],
+    ) -> Vec<MetricName> {
+        percentiles
+            .iter()
+            .map(|&percentile| {
+                let metric_name = metrics.metric_name(
+                    &format!("{name}-p{}", percentile * 100.0),
+                    group,
+                    &format!("The {}th percentile of {name}", percentile * 100.0),
+                    tags,
+                );
+                let histogram = Arc::clone(self);
+                metrics.add_gauge(metric_name.clone(), move |now_ms| {
+                    histogram.snapshot(now_ms).quantile(percentile)
+                });
+                metric_name
+            })
+            .collect()
+    }
+}
+
+/// The values of a [Histogram] at a point in time, sorted, with their normalized weights.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    values: Vec<f64>,
+    weights: Vec<f64>,
+    /// The sum of the weights of the values before each value.
+    quantiles: Vec<f64>,
+}
+
+impl HistogramSnapshot {
+    fn new(mut samples: Vec<WeightedSample>) -> Self {
+        samples.sort_by(|a, b| a.value.total_cmp(&b.value));
+        let total: f64 = samples.iter().map(|s| s.weight).sum();
+        let weights: Vec<f64> = samples
+            .iter()
+            .map(|s| if total == 0.0 { 0.0 } else { s.weight / total })
+            .collect();
+        let quantiles = weights
+            .iter()
+            .scan(0.0, |sum, weight| {
+                let quantile = *sum;
+                *sum += weight;
+                Some(quantile)
+            })
+            .collect();
+        Self {
+            values: samples.iter().map(|s| s.value).collect(),
+            weights,
+            quantiles,
+        }
+    }
+
+    /// The value at `quantile`, between 0 and 1, or 0 if the histogram is empty.
+    pub fn quantile(&self, quantile: f64) -> f64 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let position = self.quantiles.partition_point(|q| *q <= quantile);
+        self.values[position.saturating_sub(1).min(self.values.len() - 1)]
+    }
+
+    pub fn median(&self) -> f64 {
+        self.quantile(0.5)
+    }
+
+    pub fn min(&self) -> f64 {
+        self.values.first().copied().unwrap_or(0.0)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.values.last().copied().unwrap_or(0.0)
+    }
+
+    /// The weighted mean of the values.
+    pub fn mean(&self) -> f64 {
+        self.values
+            .iter()
+            .zip(&self.weights)
+            .map(|(value, weight)| value * weight)
+            .sum()
+    }
+
+    /// The number of values in the snapshot, at most the size of the reservoir.
+    pub fn size(&self) -> usize {
+        self.values.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metrics::MetricConfig;
+    use crate::common::utils::time::MockTime;
+
+    #[test]
+    fn test_percentiles_of_uniform_values() {
+        let histogram = Histogram::new(0);
+        for value in 1..=1000 {
+            histogram.update(value as f64, 0);
+        }
+        let snapshot = histogram.snapshot(0);
+        assert_eq!(1000, histogram.count());
+        assert_eq!(1000, snapshot.size());
+        assert_eq!(1.0, snapshot.min());
+        assert_eq!(1000.0, snapshot.max());
+        // The weights sum up with rounding errors, the quantiles may be off by a value.
+        assert!((500.0..=501.0).contains(&snapshot.median()));
+        assert!((990.0..=991.0).contains(&snapshot.quantile(0.99)));
+        assert!((snapshot.mean() - 500.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_reservoir_is_bounded_and_biased_to_recent_values() {
+        let histogram = Histogram::with_reservoir(100, DEFAULT_ALPHA, 0);
+        for _ in 0..1000 {
+            histogram.update(1.0, 0);
+        }
+        // Ten minutes later, recent values weigh far more than the old ones.
+        for _ in 0..1000 {
+            histogram.update(1000.0, 600_000);
+        }
+        let snapshot = histogram.snapshot(600_000);
+        assert_eq!(100, snapshot.size());
+        assert_eq!(1000.0, snapshot.median());
+        assert_eq!(2000, histogram.count());
+    }
+
+    #[test]
+    fn test_rescale_keeps_the_distribution() {
+        let histogram = Histogram::with_reservoir(10, DEFAULT_ALPHA, 0);
+        histogram.update(5.0, 0);
+        let snapshot = histogram.snapshot(2 * RESCALE_THRESHOLD_MS);
+        assert_eq!(1, snapshot.size());
+        assert_eq!(5.0, snapshot.quantile(0.99));
+        assert_eq!(0.0, Histogram::new(0).snapshot(0).quantile(0.5));
+    }
+
+    #[test]
+    fn test_percentile_metrics() {
+        let metrics = Metrics::new(
+            MetricConfig::default(),
+            Arc::new(MockTime::with_start(0, 0, 0)),
+        );
+        let histogram = Arc::new(Histogram::new(0));
+        let names =
+            histogram.add_percentile_metrics(&metrics, "latency", "test", &[], &[0.5, 0.999]);
+        assert_eq!("latency-p50", names[0].name());
+        assert_eq!("latency-p99.9", names[1].name());
+        for value in 1..=1000 {
+            histogram.update(value as f64, 0);
+        }
+        assert!((500.0..=501.0).contains(&metrics.metric_value(&names[0]).unwrap()));
+        assert!((999.0..=1000.0).contains(&metrics.metric_value(&names[1]).unwrap()));
+    }
+}
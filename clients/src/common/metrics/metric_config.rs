@@ -0,0 +1,41 @@
+/// Configuration values for metrics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricConfig {
+    samples: usize,
+    time_window_ms: i64,
+}
+
+impl Default for MetricConfig {
+    fn default() -> Self {
+        Self {
+            samples: 2,
+            time_window_ms: 30_000,
+        }
+    }
+}
+
+impl MetricConfig {
+    /// The number of samples a sampled stat keeps in memory.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `samples` is zero.
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        assert!(samples >= 1, "The number of samples must be at least 1.");
+        self.samples = samples;
+        self
+    }
+
+    /// The length of the window covered by a single sample.
+    pub fn time_window_ms(&self) -> i64 {
+        self.time_window_ms
+    }
+
+    pub fn with_time_window_ms(mut self, time_window_ms: i64) -> Self {
+        self.time_window_ms = time_window_ms;
+        self
+    }
+}
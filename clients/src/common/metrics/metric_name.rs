@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// The name of a metric.
+///
+/// A metric is identified by its name, group and tags. The description is informational only
+/// and does not take part in equality.
+#[derive(Debug, Clone)]
+pub struct MetricName {
+    name: String,
+    group: String,
+    description: String,
+    tags: BTreeMap<String, String>,
+}
+
+impl MetricName {
+    pub fn new(
+        name: impl Into<String>,
+        group: impl Into<String>,
+        description: impl Into<String>,
+        tags: BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            group: group.into(),
+            description: description.into(),
+            tags,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn tags(&self) -> &BTreeMap<String, String> {
+        &self.tags
+    }
+}
+
+impl PartialEq for MetricName {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.group == other.group && self.tags == other.tags
+    }
+}
+
+impl Eq for MetricName {}
+
+impl Hash for MetricName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.group.hash(state);
+        self.tags.hash(state);
+    }
+}
+
+impl fmt::Display for MetricName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MetricName [name={}, group={}, description={}, tags={:?}]",
+            self.name, self.group, self.description, self.tags
+        )
+    }
+}
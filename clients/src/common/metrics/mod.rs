@@ -0,0 +1,12 @@
+pub use histogram::{Histogram, HistogramSnapshot};
+pub use metric_config::MetricConfig;
+pub use metric_name::MetricName;
+pub use registry::{Gauge, KafkaMetric, Metrics};
+pub use sensor::Sensor;
+
+mod histogram;
+mod metric_config;
+mod metric_name;
+mod registry;
+mod sensor;
+pub mod stats;
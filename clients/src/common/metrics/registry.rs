@@ -0,0 +1,283 @@
+use crate::common::metrics::stats::MeasurableStat;
+use crate::common::metrics::{MetricConfig, MetricName, Sensor};
+use crate::common::utils::time::{SystemTime, Time};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A function computing the value of a gauge at the given time in milliseconds.
+pub type Gauge = Arc<dyn Fn(i64) -> f64 + Send + Sync>;
+
+pub(crate) type SharedStat = Arc<Mutex<dyn MeasurableStat>>;
+
+#[derive(Clone)]
+pub(crate) enum Measurable {
+    Stat(SharedStat),
+    Gauge(Gauge),
+}
+
+/// A metric registered with [Metrics], either backed by a stat of a [Sensor] or by a gauge.
+#[derive(Clone)]
+pub struct KafkaMetric {
+    metric_name: MetricName,
+    measurable: Measurable,
+    config: MetricConfig,
+}
+
+impl KafkaMetric {
+    pub(crate) fn new(
+        metric_name: MetricName,
+        measurable: Measurable,
+        config: MetricConfig,
+    ) -> Self {
+        Self {
+            metric_name,
+            measurable,
+            config,
+        }
+    }
+
+    pub fn metric_name(&self) -> &MetricName {
+        &self.metric_name
+    }
+
+    /// Measures the metric at `now_ms`.
+    pub fn metric_value(&self, now_ms: i64) -> f64 {
+        match &self.measurable {
+            Measurable::Stat(stat) => stat
+                .lock()
+                .expect("metric lock poisoned")
+                .measure(&self.config, now_ms),
+            Measurable::Gauge(gauge) => gauge(now_ms),
+        }
+    }
+
+    /// The window a rate metric is measured over at `now_ms`, or `None` for other metrics.
+    pub fn window_size_ms(&self, now_ms: i64) -> Option<i64> {
+        match &self.measurable {
+            Measurable::Stat(stat) => stat
+                .lock()
+                .expect("metric lock poisoned")
+                .window_size_ms(&self.config, now_ms),
+            Measurable::Gauge(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for KafkaMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KafkaMetric")
+            .field("metric_name", &self.metric_name)
+            .finish()
+    }
+}
+
+pub(crate) struct MetricsRegistry {
+    pub(crate) config: MetricConfig,
+    pub(crate) time: Arc<dyn Time>,
+    metrics: Mutex<HashMap<MetricName, KafkaMetric>>,
+    sensors: Mutex<HashMap<String, Arc<Sensor>>>,
+}
+
+impl MetricsRegistry {
+    fn metrics(&self) -> MutexGuard<'_, HashMap<MetricName, KafkaMetric>> {
+        self.metrics.lock().expect("metrics lock poisoned")
+    }
+
+    /// Registers `metric`, returning false if a metric with the same name already exists.
+    pub(crate) fn register_metric(&self, metric: KafkaMetric) -> bool {
+        let mut metrics = self.metrics();
+        if metrics.contains_key(metric.metric_name()) {
+            return false;
+        }
+        metrics.insert(metric.metric_name().clone(), metric);
+        true
+    }
+}
+
+/// A registry of sensors and metrics.
+///
+/// A metric is a named, numerical measurement. A sensor is a handle to record numerical
+/// measurements as they occur; each sensor has zero or more associated metrics. For example
+/// a sensor might record message sizes, with metrics for the average and maximum size.
+///
+/// The handle is cheap to clone and all clones share the same registry.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Arc<MetricsRegistry>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new(MetricConfig::default(), Arc::new(SystemTime))
+    }
+}
+
+impl Metrics {
+    pub fn new(config: MetricConfig, time: Arc<dyn Time>) -> Self {
+        Self {
+            registry: Arc::new(MetricsRegistry {
+                config,
+                time,
+                metrics: Mutex::new(HashMap::new()),
+                sensors: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &MetricConfig {
+        &self.registry.config
+    }
+
+    pub fn time(&self) -> &Arc<dyn Time> {
+        &self.registry.time
+    }
+
+    /// Creates a metric name with the given tags.
+    pub fn metric_name(
+        &self,
+        name: &str,
+        group: &str,
+        description: &str,
+        tags: &[(&str, &str)],
+    ) -> MetricName {
+        let tags: BTreeMap<String, String> = tags
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        MetricName::new(name, group, description, tags)
+    }
+
+    /// Returns the sensor with the given name, creating it with the registry's default
+    /// config if it does not exist yet.
+    pub fn sensor(&self, name: &str) -> Arc<Sensor> {
+        self.sensor_with_config(name, self.registry.config.clone())
+    }
+
+    /// Returns the sensor with the given name, creating it with `config` if it does not
+    /// exist yet.
+    pub fn sensor_with_config(&self, name: &str, config: MetricConfig) -> Arc<Sensor> {
+        let mut sensors = self.registry.sensors.lock().expect("sensors lock poisoned");
+        Arc::clone(
+            sensors
+                .entry(name.to_string())
+                .or_insert_with(|| Arc::new(Sensor::new(name, config, &self.registry))),
+        )
+    }
+
+    pub fn get_sensor(&self, name: &str) -> Option<Arc<Sensor>> {
+        self.registry
+            .sensors
+            .lock()
+            .expect("sensors lock poisoned")
+            .get(name)
+            .cloned()
+    }
+
+    /// Removes a sensor and all the metrics it registered.
+    pub fn remove_sensor(&self, name: &str) {
+        let removed = self
+            .registry
+            .sensors
+            .lock()
+            .expect("sensors lock poisoned")
+            .remove(name);
+        if let Some(sensor) = removed {
+            let mut metrics = self.registry.metrics();
+            for metric_name in sensor.metric_names() {
+                metrics.remove(&metric_name);
+            }
+        }
+    }
+
+    /// Registers a gauge, returning false if a metric with the same name already exists.
+    pub fn add_gauge(
+        &self,
+        metric_name: MetricName,
+        gauge: impl Fn(i64) -> f64 + Send + Sync + 'static,
+    ) -> bool {
+        self.registry.register_metric(KafkaMetric::new(
+            metric_name,
+            Measurable::Gauge(Arc::new(gauge)),
+            self.registry.config.clone(),
+        ))
+    }
+
+    pub fn remove_metric(&self, metric_name: &MetricName) -> Option<KafkaMetric> {
+        self.registry.metrics().remove(metric_name)
+    }
+
+    pub fn metric(&self, metric_name: &MetricName) -> Option<KafkaMetric> {
+        self.registry.metrics().get(metric_name).cloned()
+    }
+
+    /// Measures the metric with the given name at the current time.
+    pub fn metric_value(&self, metric_name: &MetricName) -> Option<f64> {
+        let now_ms = self.registry.time.milliseconds();
+        self.metric(metric_name).map(|m| m.metric_value(now_ms))
+    }
+
+    /// Returns a snapshot of all registered metrics.
+    pub fn metrics(&self) -> Vec<KafkaMetric> {
+        self.registry.metrics().values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metrics::stats::{Avg, Max, Rate};
+    use crate::common::utils::time::MockTime;
+
+    fn metrics() -> (Metrics, Arc<MockTime>) {
+        let time = Arc::new(MockTime::with_start(0, 0, 0));
+        let config = MetricConfig::default()
+            .with_samples(2)
+            .with_time_window_ms(1_000);
+        (Metrics::new(config, time.clone()), time)
+    }
+
+    #[test]
+    fn test_sensor_records_into_all_its_stats() {
+        let (metrics, _) = metrics();
+        let sensor = metrics.sensor("request-size");
+        let avg = metrics.metric_name("request-size-avg", "test", "", &[("client-id", "c1")]);
+        let max = metrics.metric_name("request-size-max", "test", "", &[("client-id", "c1")]);
+        assert!(sensor.add(avg.clone(), Avg::new()));
+        assert!(sensor.add(max.clone(), Max::new()));
+        // Adding the same metric twice is rejected.
+        assert!(!sensor.add(max.clone(), Max::new()));
+
+        sensor.record(10.0);
+        sensor.record(20.0);
+        assert_eq!(Some(15.0), metrics.metric_value(&avg));
+        assert_eq!(Some(20.0), metrics.metric_value(&max));
+
+        // The same sensor is returned for the same name.
+        metrics.sensor("request-size").record(60.0);
+        assert_eq!(Some(30.0), metrics.metric_value(&avg));
+    }
+
+    #[test]
+    fn test_remove_sensor_removes_its_metrics() {
+        let (metrics, _) = metrics();
+        let sensor = metrics.sensor("bytes-in");
+        let rate = metrics.metric_name("bytes-in-rate", "test", "", &[]);
+        sensor.add(rate.clone(), Rate::new());
+        assert!(metrics.metric(&rate).is_some());
+
+        metrics.remove_sensor("bytes-in");
+        assert!(metrics.metric(&rate).is_none());
+        assert!(metrics.get_sensor("bytes-in").is_none());
+    }
+
+    #[test]
+    fn test_gauge_uses_registry_time() {
+        let (metrics, time) = metrics();
+        let name = metrics.metric_name("uptime", "test", "", &[]);
+        assert!(metrics.add_gauge(name.clone(), |now_ms| now_ms as f64));
+        time.sleep(42);
+        assert_eq!(Some(42.0), metrics.metric_value(&name));
+        assert_eq!(1, metrics.metrics().len());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::common::metrics::registry::{KafkaMetric, Measurable, MetricsRegistry, SharedStat};
+use crate::common::metrics::stats::MeasurableStat;
+use crate::common::metrics::{MetricConfig, MetricName};
+use std::sync::{Arc, Mutex, Weak};
+
+/// A handle to record numerical measurements into a set of stats.
+///
+/// Sensors are created and owned by [Metrics](super::Metrics). Every stat added to a sensor
+/// is registered as a metric and is updated on each call to [Sensor::record].
+pub struct Sensor {
+    name: String,
+    config: MetricConfig,
+    registry: Weak<MetricsRegistry>,
+    stats: Mutex<Vec<(MetricName, SharedStat)>>,
+}
+
+impl Sensor {
+    pub(crate) fn new(name: &str, config: MetricConfig, registry: &Arc<MetricsRegistry>) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+            registry: Arc::downgrade(registry),
+            stats: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds a stat to this sensor and registers it under `metric_name`.
+    ///
+    /// Returns false if a metric with the same name is already registered.
+    pub fn add(&self, metric_name: MetricName, stat: impl MeasurableStat + 'static) -> bool {
+        let Some(registry) = self.registry.upgrade() else {
+            return false;
+        };
+        let stat: SharedStat = Arc::new(Mutex::new(stat));
+        let metric = KafkaMetric::new(
+            metric_name.clone(),
+            Measurable::Stat(Arc::clone(&stat)),
+            self.config.clone(),
+        );
+        if !registry.register_metric(metric) {
+            return false;
+        }
+        self.stats
+            .lock()
+            .expect("sensor lock poisoned")
+            .push((metric_name, stat));
+        true
+    }
+
+    /// Records a value at the current time.
+    pub fn record(&self, value: f64) {
+        let now_ms = self
+            .registry
+            .upgrade()
+            .map_or(0, |registry| registry.time.milliseconds());
+        self.record_at(value, now_ms);
+    }
+
+    /// Records a value at the given time.
+    pub fn record_at(&self, value: f64, time_ms: i64) {
+        for (_, stat) in self.stats.lock().expect("sensor lock poisoned").iter() {
+            stat.lock()
+                .expect("metric lock poisoned")
+                .record(&self.config, value, time_ms);
+        }
+    }
+
+    pub(crate) fn metric_names(&self) -> Vec<MetricName> {
+        self.stats
+            .lock()
+            .expect("sensor lock poisoned")
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
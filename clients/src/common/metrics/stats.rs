@@ -0,0 +1,418 @@
+//! Statistics which can be attached to a [Sensor](super::Sensor).
+
+use crate::common::metrics::MetricConfig;
+
+/// A stat which can be both recorded into and measured.
+pub trait MeasurableStat: Send {
+    /// Records the given value at `time_ms`.
+    fn record(&mut self, config: &MetricConfig, value: f64, time_ms: i64);
+
+    /// Measures the stat at `now_ms`. Sampled stats drop samples which have fallen out of
+    /// the configured window, hence the mutable receiver.
+    fn measure(&mut self, config: &MetricConfig, now_ms: i64) -> f64;
+
+    /// The elapsed window in milliseconds the stat is measured over, for rates.
+    fn window_size_ms(&mut self, _config: &MetricConfig, _now_ms: i64) -> Option<i64> {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Sample {
+    initial_value: f64,
+    event_count: u64,
+    last_window_ms: i64,
+    value: f64,
+}
+
+impl Sample {
+    fn new(initial_value: f64, now_ms: i64) -> Self {
+        Self {
+            initial_value,
+            event_count: 0,
+            last_window_ms: now_ms,
+            value: initial_value,
+        }
+    }
+
+    fn reset(&mut self, now_ms: i64) {
+        self.last_window_ms = now_ms;
+        self.event_count = 0;
+        self.value = self.initial_value;
+    }
+
+    fn is_complete(&self, time_ms: i64, config: &MetricConfig) -> bool {
+        time_ms - self.last_window_ms >= config.time_window_ms()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Combine {
+    Avg,
+    Max,
+    Min,
+    Sum,
+    Count,
+}
+
+/// A stat computed over a fixed number of rolling time windows.
+///
+/// A new sample is started whenever the current one has covered a full time window, and
+/// samples older than `samples * time_window_ms` are discarded before measuring.
+#[derive(Debug, Clone)]
+struct SampledStat {
+    initial_value: f64,
+    current: usize,
+    samples: Vec<Sample>,
+    combine: Combine,
+}
+
+impl SampledStat {
+    fn new(combine: Combine) -> Self {
+        let initial_value = match combine {
+            Combine::Max => f64::NEG_INFINITY,
+            Combine::Min => f64::INFINITY,
+            _ => 0.0,
+        };
+        Self {
+            initial_value,
+            current: 0,
+            samples: Vec::new(),
+            combine,
+        }
+    }
+
+    fn current_sample(&mut self, time_ms: i64) -> &mut Sample {
+        if self.samples.is_empty() {
+            self.samples.push(Sample::new(self.initial_value, time_ms));
+        }
+        &mut self.samples[self.current]
+    }
+
+    fn advance(&mut self, config: &MetricConfig, time_ms: i64) {
+        self.current = (self.current + 1) % config.samples();
+        if self.current >= self.samples.len() {
+            self.samples.push(Sample::new(self.initial_value, time_ms));
+        } else {
+            self.samples[self.current].reset(time_ms);
+        }
+    }
+
+    fn record(&mut self, config: &MetricConfig, value: f64, time_ms: i64) {
+        if self.current_sample(time_ms).is_complete(time_ms, config) {
+            self.advance(config, time_ms);
+        }
+        let combine = self.combine;
+        let sample = self.current_sample(time_ms);
+        sample.value = match combine {
+            Combine::Avg | Combine::Sum => sample.value + value,
+            Combine::Max => sample.value.max(value),
+            Combine::Min => sample.value.min(value),
+            Combine::Count => sample.value + 1.0,
+        };
+        sample.event_count += 1;
+    }
+
+    fn purge_obsolete_samples(&mut self, config: &MetricConfig, now_ms: i64) {
+        let expire_age = config.samples() as i64 * config.time_window_ms();
+        for sample in self.samples.iter_mut() {
+            if now_ms - sample.last_window_ms >= expire_age {
+                sample.reset(now_ms);
+            }
+        }
+    }
+
+    fn measure(&mut self, config: &MetricConfig, now_ms: i64) -> f64 {
+        self.purge_obsolete_samples(config, now_ms);
+        let count: u64 = self.samples.iter().map(|s| s.event_count).sum();
+        match self.combine {
+            Combine::Avg => {
+                if count == 0 {
+                    f64::NAN
+                } else {
+                    self.samples.iter().map(|s| s.value).sum::<f64>() / count as f64
+                }
+            }
+            Combine::Max if count == 0 => f64::NAN,
+            Combine::Max => self
+                .samples
+                .iter()
+                .filter(|s| s.event_count > 0)
+                .map(|s| s.value)
+                .fold(f64::NEG_INFINITY, f64::max),
+            Combine::Min if count == 0 => f64::NAN,
+            Combine::Min => self
+                .samples
+                .iter()
+                .filter(|s| s.event_count > 0)
+                .map(|s| s.value)
+                .fold(f64::INFINITY, f64::min),
+            Combine::Sum | Combine::Count => self.samples.iter().map(|s| s.value).sum(),
+        }
+    }
+
+    fn oldest(&self, now_ms: i64) -> Option<&Sample> {
+        self.samples
+            .iter()
+            .filter(|s| s.last_window_ms <= now_ms)
+            .min_by_key(|s| s.last_window_ms)
+    }
+}
+
+macro_rules! sampled_stat {
+    ($(#[$doc:meta])* $name:ident, $combine:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone)]
+        pub struct $name(SampledStat);
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self(SampledStat::new($combine))
+            }
+        }
+
+        impl $name {
+            pub fn new() -> Self {
+                Self::default()
+            }
+        }
+
+        impl MeasurableStat for $name {
+            fn record(&mut self, config: &MetricConfig, value: f64, time_ms: i64) {
+                self.0.record(config, value, time_ms);
+            }
+
+            fn measure(&mut self, config: &MetricConfig, now_ms: i64) -> f64 {
+                self.0.measure(config, now_ms)
+            }
+        }
+    };
+}
+
+sampled_stat!(
+    /// The simple arithmetic average over the sampled window.
+    Avg,
+    Combine::Avg
+);
+sampled_stat!(
+    /// The maximum value recorded over the sampled window.
+    Max,
+    Combine::Max
+);
+sampled_stat!(
+    /// The minimum value recorded over the sampled window.
+    Min,
+    Combine::Min
+);
+sampled_stat!(
+    /// The sum of the values recorded over the sampled window.
+    WindowedSum,
+    Combine::Sum
+);
+sampled_stat!(
+    /// The number of values recorded over the sampled window.
+    WindowedCount,
+    Combine::Count
+);
+
+/// The rate per second of the recorded values, i.e. their windowed sum divided by the
+/// elapsed window.
+#[derive(Debug, Clone)]
+pub struct Rate {
+    stat: SampledStat,
+}
+
+impl Default for Rate {
+    fn default() -> Self {
+        Self {
+            stat: SampledStat::new(Combine::Sum),
+        }
+    }
+}
+
+impl Rate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a rate of occurrences per second rather than of the recorded values.
+    pub fn occurrences() -> Self {
+        Self {
+            stat: SampledStat::new(Combine::Count),
+        }
+    }
+
+    /// The elapsed window in milliseconds.
+    ///
+    /// Until enough samples have been collected, the window is padded to `samples - 1` full
+    /// windows so that a burst right after startup does not report an inflated rate.
+    pub fn window_size(&mut self, config: &MetricConfig, now_ms: i64) -> i64 {
+        self.stat.purge_obsolete_samples(config, now_ms);
+        let oldest = self
+            .stat
+            .oldest(now_ms)
+            .map_or(now_ms, |s| s.last_window_ms);
+        let mut total_elapsed_time_ms = now_ms - oldest;
+        let num_full_windows = total_elapsed_time_ms / config.time_window_ms();
+        let min_full_windows = config.samples() as i64 - 1;
+        if num_full_windows < min_full_windows {
+            total_elapsed_time_ms +=
+                (min_full_windows - num_full_windows) * config.time_window_ms();
+        }
+        // A zero-length window would make the rate infinite.
+        total_elapsed_time_ms.max(1)
+    }
+}
+
+impl MeasurableStat for Rate {
+    fn record(&mut self, config: &MetricConfig, value: f64, time_ms: i64) {
+        self.stat.record(config, value, time_ms);
+    }
+
+    fn measure(&mut self, config: &MetricConfig, now_ms: i64) -> f64 {
+        let value = self.stat.measure(config, now_ms);
+        value / (self.window_size(config, now_ms) as f64 / 1000.0)
+    }
+
+    fn window_size_ms(&mut self, config: &MetricConfig, now_ms: i64) -> Option<i64> {
+        Some(self.window_size(config, now_ms))
+    }
+}
+
+/// The sum of all recorded values since the stat was created.
+#[derive(Debug, Clone, Default)]
+pub struct CumulativeSum {
+    total: f64,
+}
+
+impl CumulativeSum {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MeasurableStat for CumulativeSum {
+    fn record(&mut self, _config: &MetricConfig, value: f64, _time_ms: i64) {
+        self.total += value;
+    }
+
+    fn measure(&mut self, _config: &MetricConfig, _now_ms: i64) -> f64 {
+        self.total
+    }
+}
+
+/// The number of values recorded since the stat was created.
+#[derive(Debug, Clone, Default)]
+pub struct CumulativeCount {
+    count: f64,
+}
+
+impl CumulativeCount {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MeasurableStat for CumulativeCount {
+    fn record(&mut self, _config: &MetricConfig, _value: f64, _time_ms: i64) {
+        self.count += 1.0;
+    }
+
+    fn measure(&mut self, _config: &MetricConfig, _now_ms: i64) -> f64 {
+        self.count
+    }
+}
+
+/// The most recently recorded value.
+#[derive(Debug, Clone, Default)]
+pub struct Value {
+    value: f64,
+}
+
+impl Value {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MeasurableStat for Value {
+    fn record(&mut self, _config: &MetricConfig, value: f64, _time_ms: i64) {
+        self.value = value;
+    }
+
+    fn measure(&mut self, _config: &MetricConfig, _now_ms: i64) -> f64 {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> MetricConfig {
+        MetricConfig::default()
+            .with_samples(2)
+            .with_time_window_ms(1_000)
+    }
+
+    #[test]
+    fn test_avg_max_min() {
+        let config = config();
+        let mut avg = Avg::new();
+        let mut max = Max::new();
+        let mut min = Min::new();
+        assert!(avg.measure(&config, 0).is_nan());
+        assert!(max.measure(&config, 0).is_nan());
+        for value in [1.0, 2.0, 6.0] {
+            avg.record(&config, value, 0);
+            max.record(&config, value, 0);
+            min.record(&config, value, 0);
+        }
+        assert_eq!(3.0, avg.measure(&config, 0));
+        assert_eq!(6.0, max.measure(&config, 0));
+        assert_eq!(1.0, min.measure(&config, 0));
+    }
+
+    #[test]
+    fn test_old_samples_are_purged() {
+        let config = config();
+        let mut max = Max::new();
+        max.record(&config, 10.0, 0);
+        max.record(&config, 3.0, 1_000);
+        assert_eq!(10.0, max.measure(&config, 1_500));
+        // The first sample is now older than samples * window.
+        assert_eq!(3.0, max.measure(&config, 2_000));
+        assert!(max.measure(&config, 3_000).is_nan());
+    }
+
+    #[test]
+    fn test_rate_pads_the_initial_window() {
+        let config = config();
+        let mut rate = Rate::new();
+        rate.record(&config, 100.0, 0);
+        // Only 500ms elapsed, but the window is padded to one full window of 1s.
+        assert_eq!(1_500, rate.window_size(&config, 500));
+        assert!((rate.measure(&config, 500) - 100.0 / 1.5).abs() < 1e-9);
+
+        let mut occurrences = Rate::occurrences();
+        occurrences.record(&config, 100.0, 0);
+        occurrences.record(&config, 100.0, 0);
+        assert!((occurrences.measure(&config, 1_000) - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cumulative_stats() {
+        let config = config();
+        let mut sum = CumulativeSum::new();
+        let mut count = CumulativeCount::new();
+        let mut value = Value::new();
+        for v in [1.0, 2.0, 3.0] {
+            sum.record(&config, v, 0);
+            count.record(&config, v, 0);
+            value.record(&config, v, 0);
+        }
+        assert_eq!(6.0, sum.measure(&config, 100_000));
+        assert_eq!(3.0, count.measure(&config, 100_000));
+        assert_eq!(3.0, value.measure(&config, 100_000));
+    }
+}
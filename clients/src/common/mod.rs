@@ -1,7 +1,18 @@
 pub use network::connection_mode::ConnectionMode;
+pub use network::dialer;
 pub use security::security_protocol;
 
 pub mod config;
+pub mod endpoint;
+pub mod internals;
+pub mod isolation_level;
+pub mod metrics;
 mod network;
+pub mod protocol;
+pub mod quota;
+pub mod record;
+pub mod requests;
 mod security;
+pub mod topic_partition;
 pub mod utils;
+pub mod uuid;
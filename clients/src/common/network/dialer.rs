@@ -0,0 +1,205 @@
+//! How the clients open their connections to the brokers.
+//!
+//! A client resolves and connects through a [Dialer], so that an application can route the
+//! connections through a proxy, resolve the hosts with its own DNS, or, in tests, connect to
+//! brokers listening on unix sockets. [TcpDialer] is the default one.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// A connection to a broker.
+pub trait Connection: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Connection for T {}
+
+/// Resolves the host of a broker to the addresses to connect to.
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolves the hosts with the resolver of the operating system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+/// Resolves the hosts from a fixed table, e.g. to give the brokers of a test cluster the
+/// names they advertise.
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<SocketAddr>>,
+}
+
+impl StaticResolver {
+    pub fn with_host(mut self, host: impl Into<String>, addresses: Vec<SocketAddr>) -> Self {
+        self.hosts.insert(host.into(), addresses);
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    /// The addresses of `host`, with their port replaced by `port` if they have none.
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let addresses = self.hosts.get(host).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("Unknown host {host}"))
+        })?;
+        Ok(addresses
+            .iter()
+            .map(|address| match address.port() {
+                0 => SocketAddr::new(address.ip(), port),
+                _ => *address,
+            })
+            .collect())
+    }
+}
+
+/// Opens the connections of a client.
+pub trait Dialer: Send + Sync {
+    /// Connects to the broker at `host` and `port`, giving up after `timeout`.
+    fn dial(&self, host: &str, port: u16, timeout: Duration) -> io::Result<Box<dyn Connection>>;
+}
+
+/// Connects over TCP to the addresses `host` resolves to, in turn, until one accepts the
+/// connection.
+#[derive(Debug, Clone, Default)]
+pub struct TcpDialer<R = SystemResolver> {
+    resolver: R,
+}
+
+impl<R: Resolver> TcpDialer<R> {
+    pub fn new(resolver: R) -> Self {
+        Self { resolver }
+    }
+
+    /// Connects to the first address of `host` which accepts the connection within
+    /// `timeout`, returning the error of the last one otherwise.
+    pub fn connect(&self, host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+        let mut last_error = io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No address found for {host}:{port}"),
+        );
+        for address in self.resolver.resolve(host, port)? {
+            match TcpStream::connect_timeout(&address, timeout) {
+                Ok(stream) => {
+                    // The requests are small and sent right away, don't hold them back.
+                    stream.set_nodelay(true)?;
+                    return Ok(stream);
+                }
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+impl<R: Resolver> Dialer for TcpDialer<R> {
+    fn dial(&self, host: &str, port: u16, timeout: Duration) -> io::Result<Box<dyn Connection>> {
+        Ok(Box::new(self.connect(host, port, timeout)?))
+    }
+}
+
+/// Connects every broker to the same unix socket, e.g. a test broker or a local proxy.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct UnixSocketDialer {
+    path: std::path::PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocketDialer {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[cfg(unix)]
+impl Dialer for UnixSocketDialer {
+    fn dial(&self, _host: &str, _port: u16, _timeout: Duration) -> io::Result<Box<dyn Connection>> {
+        Ok(Box::new(std::os::unix::net::UnixStream::connect(
+            &self.path,
+        )?))
+    }
+}
+
+/// Connects to the first of `servers` which accepts the connection, e.g. the
+/// `bootstrap.servers` of a client, returning the error of the last one otherwise.
+pub fn dial_any(
+    dialer: &dyn Dialer,
+    servers: &[(String, u16)],
+    timeout: Duration,
+) -> io::Result<((String, u16), Box<dyn Connection>)> {
+    let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, "No server to connect to");
+    for (host, port) in servers {
+        match dialer.dial(host, *port, timeout) {
+            Ok(connection) => return Ok(((host.clone(), *port), connection)),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_tcp_dialer_with_custom_resolver() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let resolver =
+            StaticResolver::default().with_host("broker1", vec!["127.0.0.1:0".parse().unwrap()]);
+        let dialer = TcpDialer::new(resolver);
+
+        let mut connection = dialer.dial("broker1", port, TIMEOUT).unwrap();
+        connection.write_all(b"ping").unwrap();
+        let (mut accepted, _) = listener.accept().unwrap();
+        let mut buf = [0; 4];
+        accepted.read_exact(&mut buf).unwrap();
+        assert_eq!(b"ping", &buf);
+
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            dialer.dial("broker2", port, TIMEOUT).err().unwrap().kind()
+        );
+    }
+
+    #[test]
+    fn test_dial_any_skips_unreachable_servers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let resolver = StaticResolver::default()
+            .with_host("up", vec!["127.0.0.1:0".parse().unwrap()])
+            .with_host("unknown", vec![]);
+        let servers = vec![("unknown".to_string(), port), ("up".to_string(), port)];
+
+        let (server, _) = dial_any(&TcpDialer::new(resolver), &servers, TIMEOUT).unwrap();
+        assert_eq!(("up".to_string(), port), server);
+        assert!(dial_any(&TcpDialer::new(SystemResolver), &[], TIMEOUT).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_dialer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broker.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+        let mut connection = UnixSocketDialer::new(&path)
+            .dial("broker1", 9092, TIMEOUT)
+            .unwrap();
+        connection.write_all(b"ping").unwrap();
+        let (mut accepted, _) = listener.accept().unwrap();
+        let mut buf = [0; 4];
+        accepted.read_exact(&mut buf).unwrap();
+        assert_eq!(b"ping", &buf);
+    }
+}
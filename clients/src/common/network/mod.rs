@@ -1 +1,2 @@
-pub mod connection_mode;
\ No newline at end of file
+pub mod connection_mode;
+pub mod dialer;
@@ -0,0 +1,134 @@
+//! The APIs of the Kafka protocol the broker serves, with the versions it supports.
+//!
+//! Keys are part of the wire format and must match `org.apache.kafka.common.protocol.ApiKeys`.
+
+macro_rules! api_keys {
+    ($($variant:ident = $id:expr, $name:expr, $oldest:expr, $latest:expr, $first_flexible:expr;)*) => {
+        /// An API a request can be sent for.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum ApiKeys {
+            $($variant,)*
+        }
+
+        impl ApiKeys {
+            /// All the APIs, ordered by key.
+            pub const ALL: &'static [ApiKeys] = &[$(ApiKeys::$variant,)*];
+
+            /// The key sent in the request header.
+            pub fn id(&self) -> i16 {
+                match self {
+                    $(ApiKeys::$variant => $id,)*
+                }
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(ApiKeys::$variant => $name,)*
+                }
+            }
+
+            pub const fn oldest_version(&self) -> i16 {
+                match self {
+                    $(ApiKeys::$variant => $oldest,)*
+                }
+            }
+
+            pub const fn latest_version(&self) -> i16 {
+                match self {
+                    $(ApiKeys::$variant => $latest,)*
+                }
+            }
+
+            /// The first version using the flexible encoding with tagged fields, if any.
+            fn first_flexible_version(&self) -> Option<i16> {
+                match self {
+                    $(ApiKeys::$variant => $first_flexible,)*
+                }
+            }
+
+            /// Returns the API with the given key, `None` if the broker does not know it.
+            pub fn for_id(id: i16) -> Option<Self> {
+                match id {
+                    $($id => Some(ApiKeys::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+api_keys! {
+    Produce = 0, "Produce", 3, 12, Some(9);
+    Fetch = 1, "Fetch", 4, 17, Some(12);
+    ListOffsets = 2, "ListOffsets", 1, 10, Some(6);
+    Metadata = 3, "Metadata", 0, 13, Some(9);
+    OffsetCommit = 8, "OffsetCommit", 2, 9, Some(8);
+    OffsetFetch = 9, "OffsetFetch", 1, 9, Some(6);
+    FindCoordinator = 10, "FindCoordinator", 0, 6, Some(3);
+    JoinGroup = 11, "JoinGroup", 0, 9, Some(6);
+    Heartbeat = 12, "Heartbeat", 0, 4, Some(4);
+    LeaveGroup = 13, "LeaveGroup", 0, 5, Some(4);
+    SyncGroup = 14, "SyncGroup", 0, 5, Some(4);
+    DescribeGroups = 15, "DescribeGroups", 0, 6, Some(5);
+    ListGroups = 16, "ListGroups", 0, 5, Some(3);
+    SaslHandshake = 17, "SaslHandshake", 0, 1, None;
+    ApiVersions = 18, "ApiVersions", 0, 4, Some(3);
+    CreateTopics = 19, "CreateTopics", 2, 7, Some(5);
+    DeleteTopics = 20, "DeleteTopics", 1, 6, Some(4);
+    SaslAuthenticate = 36, "SaslAuthenticate", 0, 2, Some(2);
+    GetTelemetrySubscriptions = 71, "GetTelemetrySubscriptions", 0, 0, Some(0);
+    PushTelemetry = 72, "PushTelemetry", 0, 0, Some(0);
+    DescribeTopicPartitions = 75, "DescribeTopicPartitions", 0, 0, Some(0);
+}
+
+impl ApiKeys {
+    pub fn is_version_supported(&self, version: i16) -> bool {
+        (self.oldest_version()..=self.latest_version()).contains(&version)
+    }
+
+    pub fn is_flexible(&self, version: i16) -> bool {
+        self.first_flexible_version()
+            .is_some_and(|first| version >= first)
+    }
+
+    /// The version of the request header: 2 for flexible versions, which end the header with
+    /// tagged fields, and 1 otherwise.
+    pub fn request_header_version(&self, version: i16) -> i16 {
+        if self.is_flexible(version) { 2 } else { 1 }
+    }
+
+    /// The version of the response header: 1 for flexible versions, except for ApiVersions
+    /// whose response header never has tagged fields, so that a client can always parse the
+    /// response telling it which versions the broker supports.
+    pub fn response_header_version(&self, version: i16) -> i16 {
+        if self.is_flexible(version) && *self != ApiKeys::ApiVersions {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_id() {
+        for api in ApiKeys::ALL {
+            assert_eq!(Some(*api), ApiKeys::for_id(api.id()));
+        }
+        assert_eq!(None, ApiKeys::for_id(-1));
+        assert_eq!(None, ApiKeys::for_id(1000));
+    }
+
+    #[test]
+    fn test_header_versions() {
+        assert_eq!(1, ApiKeys::Metadata.request_header_version(8));
+        assert_eq!(2, ApiKeys::Metadata.request_header_version(9));
+        assert_eq!(1, ApiKeys::Metadata.response_header_version(9));
+        assert_eq!(2, ApiKeys::ApiVersions.request_header_version(3));
+        assert_eq!(0, ApiKeys::ApiVersions.response_header_version(3));
+        assert_eq!(1, ApiKeys::SaslHandshake.request_header_version(1));
+    }
+}
@@ -0,0 +1,214 @@
+//! The versions of the APIs which the broker only enables under some condition: unstable
+//! versions still under development, and versions relying on a `metadata.version` or on
+//! another feature being finalized in the cluster.
+//!
+//! The conditions are listed in [VERSION_GATES], checked at compile time against the versions
+//! in [ApiKeys]. The broker enforces them through [EnabledVersions] both when it advertises
+//! its versions in an ApiVersions response and when it accepts a request, so that a client
+//! never uses a version the cluster cannot handle yet, and enabling
+//! `unstable.api.versions.enable` or finalizing a feature changes what is advertised.
+
+use crate::common::protocol::api_keys::ApiKeys;
+use std::collections::BTreeMap;
+
+/// The name of the feature which carries the metadata version.
+pub const METADATA_VERSION_FEATURE: &str = "metadata.version";
+
+/// The name of the feature enabling the consumer group protocol of KIP-848.
+pub const GROUP_VERSION_FEATURE: &str = "group.version";
+
+/// What a range of versions of an API requires to be enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionRequirement {
+    /// The versions are still under development and only enabled with
+    /// `unstable.api.versions.enable`.
+    Unstable,
+    /// The versions need the `metadata.version` to be finalized at this feature level or
+    /// above.
+    MetadataVersion(i16),
+    /// The versions need the feature to be finalized at this level or above.
+    Feature(&'static str, i16),
+}
+
+/// A range of versions of an API and what they require.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionGate {
+    pub api: ApiKeys,
+    pub min_version: i16,
+    pub max_version: i16,
+    pub requirement: VersionRequirement,
+}
+
+/// The versions the broker supports but does not always enable. A version not listed here is
+/// enabled whenever the broker supports it.
+pub const VERSION_GATES: &[VersionGate] = &[
+    // Produce v12 is part of the second phase of KIP-890, not complete yet.
+    VersionGate {
+        api: ApiKeys::Produce,
+        min_version: 12,
+        max_version: 12,
+        requirement: VersionRequirement::Unstable,
+    },
+    // ListOffsets v10 is still under development.
+    VersionGate {
+        api: ApiKeys::ListOffsets,
+        min_version: 10,
+        max_version: 10,
+        requirement: VersionRequirement::Unstable,
+    },
+    // The member ids of the KIP-848 consumer groups.
+    VersionGate {
+        api: ApiKeys::OffsetCommit,
+        min_version: 9,
+        max_version: 9,
+        requirement: VersionRequirement::Feature(GROUP_VERSION_FEATURE, 1),
+    },
+    VersionGate {
+        api: ApiKeys::OffsetFetch,
+        min_version: 9,
+        max_version: 9,
+        requirement: VersionRequirement::Feature(GROUP_VERSION_FEATURE, 1),
+    },
+    // The eligible leader replicas of KIP-966 are only recorded from 3.7-IV4.
+    VersionGate {
+        api: ApiKeys::DescribeTopicPartitions,
+        min_version: 0,
+        max_version: 0,
+        requirement: VersionRequirement::MetadataVersion(19),
+    },
+];
+
+// Every gate must cover versions the broker supports, so that a new version of an API, or a
+// version dropped from it, is not left out of the matrix by mistake.
+const _: () = {
+    let mut i = 0;
+    while i < VERSION_GATES.len() {
+        let gate = &VERSION_GATES[i];
+        assert!(
+            gate.min_version <= gate.max_version
+                && gate.min_version >= gate.api.oldest_version()
+                && gate.max_version <= gate.api.latest_version(),
+            "a version gate covers versions the broker does not support"
+        );
+        i += 1;
+    }
+};
+
+/// The versions the broker enables, given its configs and the features finalized in the
+/// cluster.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnabledVersions {
+    unstable_api_versions_enable: bool,
+    finalized_features: BTreeMap<String, i16>,
+}
+
+impl EnabledVersions {
+    /// The versions enabled by `unstable.api.versions.enable`, before the finalized features
+    /// are known.
+    pub fn new(unstable_api_versions_enable: bool) -> Self {
+        Self {
+            unstable_api_versions_enable,
+            finalized_features: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_finalized_features(mut self, finalized_features: BTreeMap<String, i16>) -> Self {
+        self.finalized_features = finalized_features;
+        self
+    }
+
+    /// Updates the finalized features, once the broker replayed a change of them.
+    pub fn set_finalized_features(&mut self, finalized_features: BTreeMap<String, i16>) {
+        self.finalized_features = finalized_features;
+    }
+
+    /// Whether `requirement` is met.
+    pub fn meets(&self, requirement: VersionRequirement) -> bool {
+        let finalized = |feature| self.finalized_features.get(feature).copied();
+        match requirement {
+            VersionRequirement::Unstable => self.unstable_api_versions_enable,
+            VersionRequirement::MetadataVersion(level) => {
+                finalized(METADATA_VERSION_FEATURE).is_some_and(|finalized| finalized >= level)
+            }
+            VersionRequirement::Feature(feature, level) => {
+                finalized(feature).is_some_and(|finalized| finalized >= level)
+            }
+        }
+    }
+
+    /// Whether the broker accepts requests for `api` at `version`.
+    pub fn is_version_enabled(&self, api: ApiKeys, version: i16) -> bool {
+        api.is_version_supported(version)
+            && VERSION_GATES
+                .iter()
+                .filter(|gate| gate.api == api)
+                .filter(|gate| (gate.min_version..=gate.max_version).contains(&version))
+                .all(|gate| self.meets(gate.requirement))
+    }
+
+    /// The latest version of `api` the broker enables, the versions from the oldest one up to
+    /// the first disabled one. `None` if even the oldest version is disabled, in which case the
+    /// API is not advertised at all.
+    pub fn latest_enabled_version(&self, api: ApiKeys) -> Option<i16> {
+        (api.oldest_version()..=api.latest_version())
+            .take_while(|version| self.is_version_enabled(api, *version))
+            .last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unstable_versions() {
+        let stable = EnabledVersions::new(false);
+        assert_eq!(Some(11), stable.latest_enabled_version(ApiKeys::Produce));
+        assert!(!stable.is_version_enabled(ApiKeys::Produce, 12));
+        assert_eq!(Some(9), stable.latest_enabled_version(ApiKeys::ListOffsets));
+        // APIs without gates are enabled up to their latest version.
+        assert_eq!(Some(17), stable.latest_enabled_version(ApiKeys::Fetch));
+
+        let unstable = EnabledVersions::new(true);
+        assert_eq!(Some(12), unstable.latest_enabled_version(ApiKeys::Produce));
+        assert!(unstable.is_version_enabled(ApiKeys::Produce, 12));
+        assert!(!unstable.is_version_enabled(ApiKeys::Produce, 13));
+    }
+
+    #[test]
+    fn test_versions_gated_by_features() {
+        let mut enabled = EnabledVersions::new(true);
+        assert_eq!(
+            None,
+            enabled.latest_enabled_version(ApiKeys::DescribeTopicPartitions)
+        );
+        assert_eq!(
+            Some(8),
+            enabled.latest_enabled_version(ApiKeys::OffsetCommit)
+        );
+
+        enabled
+            .set_finalized_features(BTreeMap::from([(METADATA_VERSION_FEATURE.to_string(), 14)]));
+        assert_eq!(
+            None,
+            enabled.latest_enabled_version(ApiKeys::DescribeTopicPartitions)
+        );
+
+        let enabled = EnabledVersions::new(false).with_finalized_features(BTreeMap::from([
+            (METADATA_VERSION_FEATURE.to_string(), 21),
+            (GROUP_VERSION_FEATURE.to_string(), 1),
+        ]));
+        assert_eq!(
+            Some(0),
+            enabled.latest_enabled_version(ApiKeys::DescribeTopicPartitions)
+        );
+        assert_eq!(
+            Some(9),
+            enabled.latest_enabled_version(ApiKeys::OffsetCommit)
+        );
+        assert_eq!(
+            Some(9),
+            enabled.latest_enabled_version(ApiKeys::OffsetFetch)
+        );
+    }
+}
@@ -0,0 +1,4 @@
+pub mod api_keys;
+pub mod api_version_matrix;
+pub mod errors;
+pub mod raft_api_keys;
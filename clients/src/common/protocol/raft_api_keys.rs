@@ -0,0 +1,109 @@
+//! The APIs of the Raft quorum, which the controllers send each other.
+//!
+//! They are served on the controller listeners only, so they are kept out of [ApiKeys] and the
+//! versions a broker advertises to its clients. Keys and versions must match
+//! `org.apache.kafka.common.protocol.ApiKeys`, so that rafka and Kafka controllers can form a
+//! quorum.
+//!
+//! [ApiKeys]: crate::common::protocol::api_keys::ApiKeys
+
+/// An API of the Raft quorum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RaftApiKeys {
+    Vote,
+    BeginQuorumEpoch,
+    EndQuorumEpoch,
+    FetchSnapshot,
+}
+
+impl RaftApiKeys {
+    pub const ALL: &'static [RaftApiKeys] = &[
+        RaftApiKeys::Vote,
+        RaftApiKeys::BeginQuorumEpoch,
+        RaftApiKeys::EndQuorumEpoch,
+        RaftApiKeys::FetchSnapshot,
+    ];
+
+    /// The key sent in the request header.
+    pub fn id(&self) -> i16 {
+        match self {
+            RaftApiKeys::Vote => 52,
+            RaftApiKeys::BeginQuorumEpoch => 53,
+            RaftApiKeys::EndQuorumEpoch => 54,
+            RaftApiKeys::FetchSnapshot => 59,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RaftApiKeys::Vote => "Vote",
+            RaftApiKeys::BeginQuorumEpoch => "BeginQuorumEpoch",
+            RaftApiKeys::EndQuorumEpoch => "EndQuorumEpoch",
+            RaftApiKeys::FetchSnapshot => "FetchSnapshot",
+        }
+    }
+
+    pub const fn oldest_version(&self) -> i16 {
+        0
+    }
+
+    /// Version 1 of every API adds the directory ids and endpoints of KIP-853, and version 2
+    /// of Vote the pre-votes of KIP-996.
+    pub const fn latest_version(&self) -> i16 {
+        match self {
+            RaftApiKeys::Vote => 2,
+            RaftApiKeys::BeginQuorumEpoch
+            | RaftApiKeys::EndQuorumEpoch
+            | RaftApiKeys::FetchSnapshot => 1,
+        }
+    }
+
+    /// The first version using the flexible encoding with tagged fields.
+    fn first_flexible_version(&self) -> i16 {
+        match self {
+            RaftApiKeys::Vote | RaftApiKeys::FetchSnapshot => 0,
+            RaftApiKeys::BeginQuorumEpoch | RaftApiKeys::EndQuorumEpoch => 1,
+        }
+    }
+
+    /// Returns the API with the given key, `None` if it is not a Raft API.
+    pub fn for_id(id: i16) -> Option<Self> {
+        Self::ALL.iter().copied().find(|api| api.id() == id)
+    }
+
+    pub fn is_version_supported(&self, version: i16) -> bool {
+        (self.oldest_version()..=self.latest_version()).contains(&version)
+    }
+
+    pub fn is_flexible(&self, version: i16) -> bool {
+        version >= self.first_flexible_version()
+    }
+
+    /// The version of the request header: 2 for flexible versions, and 1 otherwise.
+    pub fn request_header_version(&self, version: i16) -> i16 {
+        if self.is_flexible(version) { 2 } else { 1 }
+    }
+
+    /// The version of the response header: 1 for flexible versions, and 0 otherwise.
+    pub fn response_header_version(&self, version: i16) -> i16 {
+        if self.is_flexible(version) { 1 } else { 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::protocol::api_keys::ApiKeys;
+
+    #[test]
+    fn test_raft_apis_are_not_broker_apis() {
+        for api in RaftApiKeys::ALL {
+            assert_eq!(Some(*api), RaftApiKeys::for_id(api.id()));
+            assert_eq!(None, ApiKeys::for_id(api.id()));
+        }
+        assert_eq!(None, RaftApiKeys::for_id(ApiKeys::Fetch.id()));
+        assert!(!RaftApiKeys::BeginQuorumEpoch.is_flexible(0));
+        assert_eq!(0, RaftApiKeys::EndQuorumEpoch.response_header_version(0));
+        assert_eq!(2, RaftApiKeys::Vote.request_header_version(0));
+    }
+}
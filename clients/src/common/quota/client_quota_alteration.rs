@@ -0,0 +1,30 @@
+use crate::common::quota::client_quota_entity::ClientQuotaEntity;
+
+/// Sets a quota to `value`, or removes it if `value` is `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Op {
+    pub key: String,
+    pub value: Option<f64>,
+}
+
+impl Op {
+    pub fn new(key: impl Into<String>, value: Option<f64>) -> Self {
+        Self {
+            key: key.into(),
+            value,
+        }
+    }
+}
+
+/// The quota changes of an AlterClientQuotas request for one entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientQuotaAlteration {
+    pub entity: ClientQuotaEntity,
+    pub ops: Vec<Op>,
+}
+
+impl ClientQuotaAlteration {
+    pub fn new(entity: ClientQuotaEntity, ops: Vec<Op>) -> Self {
+        Self { entity, ops }
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The entity type of quotas applying to a user principal.
+pub const USER: &str = "user";
+/// The entity type of quotas applying to a client id.
+pub const CLIENT_ID: &str = "client-id";
+/// The entity type of quotas applying to the connections of an IP address.
+pub const IP: &str = "ip";
+
+/// Whether `entity_type` is one of the types quotas can be configured for.
+pub fn is_valid_entity_type(entity_type: &str) -> bool {
+    matches!(entity_type, USER | CLIENT_ID | IP)
+}
+
+/// The entity a client quota is configured for, as a map of entity types to names. A `None`
+/// name stands for the default entity of its type, e.g. all users.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientQuotaEntity {
+    entries: BTreeMap<String, Option<String>>,
+}
+
+impl ClientQuotaEntity {
+    pub fn new(entries: BTreeMap<String, Option<String>>) -> Self {
+        Self { entries }
+    }
+
+    /// The entity of a single type, e.g. the user `alice` or the default IP address.
+    pub fn of(entity_type: &str, name: Option<&str>) -> Self {
+        Self::new(BTreeMap::from([(
+            entity_type.to_string(),
+            name.map(str::to_string),
+        )]))
+    }
+
+    pub fn entries(&self) -> &BTreeMap<String, Option<String>> {
+        &self.entries
+    }
+
+    /// The name of the entity of `entity_type`: `None` if the entity has no such type,
+    /// `Some(None)` if it is the default entity of the type.
+    pub fn get(&self, entity_type: &str) -> Option<Option<&str>> {
+        self.entries.get(entity_type).map(Option::as_deref)
+    }
+}
+
+impl fmt::Display for ClientQuotaEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(entity_type, name)| match name {
+                Some(name) => format!("{entity_type}={name}"),
+                None => format!("{entity_type}=<default>"),
+            })
+            .collect();
+        write!(f, "({})", entries.join(", "))
+    }
+}
@@ -0,0 +1,2 @@
+pub mod client_quota_alteration;
+pub mod client_quota_entity;
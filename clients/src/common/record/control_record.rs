@@ -0,0 +1,131 @@
+use crate::common::record::record_batch::{NO_SEQUENCE, Record, RecordBatch, RecordError, Result};
+
+const CURRENT_CONTROL_RECORD_KEY_VERSION: i16 = 0;
+const CURRENT_END_TXN_MARKER_VERSION: i16 = 0;
+
+/// The type of a control record, stored in its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlRecordType {
+    Abort,
+    Commit,
+}
+
+impl ControlRecordType {
+    pub fn id(&self) -> i16 {
+        match self {
+            ControlRecordType::Abort => 0,
+            ControlRecordType::Commit => 1,
+        }
+    }
+
+    pub fn from_id(id: i16) -> Option<Self> {
+        match id {
+            0 => Some(ControlRecordType::Abort),
+            1 => Some(ControlRecordType::Commit),
+            _ => None,
+        }
+    }
+}
+
+/// The marker a transaction coordinator writes to every partition of a transaction when it
+/// completes, telling consumers whether the transactional records before it were committed or
+/// aborted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndTransactionMarker {
+    pub control_type: ControlRecordType,
+    pub coordinator_epoch: i32,
+}
+
+impl EndTransactionMarker {
+    pub fn new(control_type: ControlRecordType, coordinator_epoch: i32) -> Self {
+        Self {
+            control_type,
+            coordinator_epoch,
+        }
+    }
+
+    pub fn to_record(&self, timestamp: i64) -> Record {
+        let mut key = Vec::with_capacity(4);
+        key.extend_from_slice(&CURRENT_CONTROL_RECORD_KEY_VERSION.to_be_bytes());
+        key.extend_from_slice(&self.control_type.id().to_be_bytes());
+        let mut value = Vec::with_capacity(6);
+        value.extend_from_slice(&CURRENT_END_TXN_MARKER_VERSION.to_be_bytes());
+        value.extend_from_slice(&self.coordinator_epoch.to_be_bytes());
+        Record::new(timestamp, Some(&key), Some(&value))
+    }
+
+    pub fn from_record(record: &Record) -> Result<Self> {
+        let invalid =
+            |reason: &str| RecordError::Corrupt(format!("invalid control record: {reason}"));
+        let key = record
+            .key
+            .as_deref()
+            .ok_or_else(|| invalid("the key is null"))?;
+        if key.len() < 4 {
+            return Err(invalid("the key is too short"));
+        }
+        let version = i16::from_be_bytes([key[0], key[1]]);
+        if version < 0 {
+            return Err(invalid("the key version is negative"));
+        }
+        let type_id = i16::from_be_bytes([key[2], key[3]]);
+        let control_type = ControlRecordType::from_id(type_id)
+            .ok_or_else(|| invalid(&format!("unknown control record type {type_id}")))?;
+
+        let value = record
+            .value
+            .as_deref()
+            .ok_or_else(|| invalid("the value is null"))?;
+        if value.len() < 6 {
+            return Err(invalid("the value is too short"));
+        }
+        let version = i16::from_be_bytes([value[0], value[1]]);
+        if version < 0 {
+            return Err(invalid("the marker version is negative"));
+        }
+        Ok(Self {
+            control_type,
+            coordinator_epoch: i32::from_be_bytes(value[2..6].try_into().expect("4 bytes")),
+        })
+    }
+}
+
+impl RecordBatch {
+    /// A transactional control batch holding a single end transaction marker.
+    pub fn end_transaction_marker(
+        producer_id: i64,
+        producer_epoch: i16,
+        marker: EndTransactionMarker,
+        timestamp: i64,
+    ) -> RecordBatch {
+        let mut batch = RecordBatch::new(0, vec![marker.to_record(timestamp)]).with_producer(
+            producer_id,
+            producer_epoch,
+            NO_SEQUENCE,
+        );
+        batch.set_transactional(true);
+        batch.set_control(true);
+        batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_end_transaction_marker() {
+        let marker = EndTransactionMarker::new(ControlRecordType::Commit, 5);
+        let batch = RecordBatch::end_transaction_marker(1, 2, marker, 1_000);
+        let (read, _) = RecordBatch::read_from(&batch.encode()).unwrap();
+        assert!(read.is_control());
+        assert!(read.is_transactional());
+        assert_eq!(
+            marker,
+            EndTransactionMarker::from_record(&read.records()[0]).unwrap()
+        );
+
+        let record = Record::new(1_000, Some(&[0, 0, 0, 7]), Some(&[0, 0, 0, 0, 0, 5]));
+        assert!(EndTransactionMarker::from_record(&record).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::common::record::legacy_record::{LegacyRecord, MAGIC_VALUE_V0};
+use crate::common::record::record_batch::{LOG_OVERHEAD, NO_TIMESTAMP, RecordBatch, TimestampType};
+
+/// How many bytes of converted messages a [DownConversionChunks] returns at once by default.
+pub const DEFAULT_MAX_CHUNK_BYTES: usize = 128 * 1024;
+
+/// Batches read for a client which only understands an older message format, converted to
+/// it only as they are sent.
+///
+/// Converting a whole fetch response up front would hold both the batches and their
+/// conversion in memory. Instead, [LazyDownConversionRecords::chunks] converts a batch at a
+/// time and returns the messages in chunks, so no more than a chunk and a converted batch are
+/// held at once.
+///
+/// The size of the response is fixed before the conversion: it is the size of the batches,
+/// or of the conversion of the first one if that is larger, so that a consumer always gets
+/// at least one message. The conversion is cut at that size, leaving a partial message which
+/// the consumer ignores, or padded with a partial message up to it.
+#[derive(Debug, Clone)]
+pub struct LazyDownConversionRecords {
+    batches: Vec<RecordBatch>,
+    to_magic: i8,
+    first_offset: i64,
+    size_in_bytes: usize,
+}
+
+impl LazyDownConversionRecords {
+    /// Converts `batches` to the v0 or v1 message format `to_magic`, leaving out the records
+    /// before `first_offset`, which the fetch did not ask for, and the control batches, which
+    /// old clients know nothing about.
+    pub fn new(batches: Vec<RecordBatch>, to_magic: i8, first_offset: i64) -> Self {
+        let size: usize = batches.iter().map(|b| b.size_in_bytes()).sum();
+        let first_converted = batches
+            .iter()
+            .map(|b| converted_size(b, to_magic, first_offset))
+            .find(|size| *size > 0)
+            .unwrap_or(0);
+        Self {
+            batches,
+            to_magic,
+            first_offset,
+            size_in_bytes: size.max(first_converted),
+        }
+    }
+
+    pub fn to_magic(&self) -> i8 {
+        self.to_magic
+    }
+
+    /// The number of bytes the chunks add up to.
+    pub fn size_in_bytes(&self) -> usize {
+        self.size_in_bytes
+    }
+
+    /// The converted message set, in chunks of at most `max_chunk_bytes` bytes.
+    pub fn chunks(&self, max_chunk_bytes: usize) -> DownConversionChunks<'_> {
+        DownConversionChunks {
+            records: self,
+            max_chunk_bytes: max_chunk_bytes.max(1),
+            next_batch: 0,
+            pending: vec![],
+            pending_records: 0,
+            remaining: self.size_in_bytes,
+            padding: false,
+        }
+    }
+
+    fn convert(&self, batch: &RecordBatch, buf: &mut Vec<u8>) -> usize {
+        let mut converted = 0;
+        for record in legacy_records(batch, self.to_magic, self.first_offset) {
+            record.write_to(buf);
+            converted += 1;
+        }
+        converted
+    }
+}
+
+/// A chunk of a converted message set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertedChunk {
+    pub bytes: Vec<u8>,
+    /// The number of records converted to produce the chunk.
+    pub converted_records: usize,
+}
+
+/// The chunks of a [LazyDownConversionRecords], converted as they are iterated.
+pub struct DownConversionChunks<'a> {
+    records: &'a LazyDownConversionRecords,
+    max_chunk_bytes: usize,
+    next_batch: usize,
+    /// The converted bytes not returned yet.
+    pending: Vec<u8>,
+    pending_records: usize,
+    /// The bytes left to return to reach the size of the records.
+    remaining: usize,
+    /// Whether the conversion ran out and the rest is padding.
+    padding: bool,
+}
+
+impl Iterator for DownConversionChunks<'_> {
+    type Item = ConvertedChunk;
+
+    fn next(&mut self) -> Option<ConvertedChunk> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let batches = &self.records.batches;
+        while self.pending.len() < self.max_chunk_bytes && self.next_batch < batches.len() {
+            self.pending_records += self
+                .records
+                .convert(&batches[self.next_batch], &mut self.pending);
+            self.next_batch += 1;
+        }
+        if self.pending.is_empty() {
+            self.pad();
+        }
+        let len = self
+            .pending
+            .len()
+            .min(self.max_chunk_bytes)
+            .min(self.remaining);
+        self.remaining -= len;
+        let bytes = self.pending.drain(..len).collect();
+        Some(ConvertedChunk {
+            bytes,
+            converted_records: std::mem::take(&mut self.pending_records),
+        })
+    }
+}
+
+impl DownConversionChunks<'_> {
+    /// Fills `pending` with the next bytes of a message larger than what is left, so that the
+    /// consumer takes it for a partial message and ignores it.
+    fn pad(&mut self) {
+        let len = self.remaining.min(self.max_chunk_bytes);
+        if !self.padding && self.remaining >= LOG_OVERHEAD {
+            self.pending.extend_from_slice(&(-1i64).to_be_bytes());
+            let size = (self.remaining - LOG_OVERHEAD + 1) as i32;
+            self.pending.extend_from_slice(&size.to_be_bytes());
+        }
+        self.padding = true;
+        self.pending.resize(len.max(self.pending.len()), 0);
+    }
+}
+
+/// The records of `batch` from `first_offset` on, in the message format `to_magic`.
+fn legacy_records(
+    batch: &RecordBatch,
+    to_magic: i8,
+    first_offset: i64,
+) -> impl Iterator<Item = LegacyRecord> + '_ {
+    let records = if batch.is_control() {
+        None
+    } else {
+        Some(batch.iter())
+    };
+    records
+        .into_iter()
+        .flatten()
+        .filter(move |(offset, _)| *offset >= first_offset)
+        .map(move |(offset, record)| {
+            let (timestamp_type, timestamp) = if to_magic == MAGIC_VALUE_V0 {
+                (TimestampType::CreateTime, NO_TIMESTAMP)
+            } else {
+                (batch.timestamp_type(), record.timestamp)
+            };
+            LegacyRecord {
+                offset,
+                magic: to_magic,
+                timestamp_type,
+                timestamp,
+                key: record.key.clone(),
+                value: record.value.clone(),
+            }
+        })
+}
+
+/// The size of the records of `batch` from `first_offset` on, once converted to `to_magic`.
+fn converted_size(batch: &RecordBatch, to_magic: i8, first_offset: i64) -> usize {
+    legacy_records(batch, to_magic, first_offset)
+        .map(|r| r.size_in_bytes())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::record::legacy_record::MAGIC_VALUE_V1;
+    use crate::common::record::record_batch::Record;
+
+    fn batch(base_offset: i64, count: usize, value_size: usize) -> RecordBatch {
+        RecordBatch::new(
+            base_offset,
+            (0..count)
+                .map(|i| Record::new(1_000 + i as i64, None, Some(&vec![7; value_size])))
+                .collect(),
+        )
+    }
+
+    fn convert(records: &LazyDownConversionRecords, max_chunk_bytes: usize) -> Vec<u8> {
+        let chunks: Vec<_> = records.chunks(max_chunk_bytes).collect();
+        assert!(chunks.iter().all(|c| c.bytes.len() <= max_chunk_bytes));
+        chunks.into_iter().flat_map(|c| c.bytes).collect()
+    }
+
+    #[test]
+    fn test_down_conversion() {
+        let mut control = batch(4, 1, 0);
+        control.set_control(true);
+        let mut log_append_time = batch(5, 2, 10);
+        log_append_time.set_log_append_time(9_000);
+        let batches = vec![batch(0, 4, 10), control, log_append_time];
+        let size: usize = batches.iter().map(|b| b.size_in_bytes()).sum();
+
+        for to_magic in [MAGIC_VALUE_V0, MAGIC_VALUE_V1] {
+            let records = LazyDownConversionRecords::new(batches.clone(), to_magic, 2);
+            assert_eq!(size, records.size_in_bytes());
+            let bytes = convert(&records, 50);
+            assert_eq!(size, bytes.len());
+
+            let converted = LegacyRecord::read_all(&bytes).unwrap();
+            let offsets: Vec<_> = converted.iter().map(|r| r.offset).collect();
+            assert_eq!(vec![2, 3, 5, 6], offsets);
+            assert!(converted.iter().all(|r| r.magic == to_magic));
+            let timestamps: Vec<_> = converted.iter().map(|r| r.timestamp).collect();
+            if to_magic == MAGIC_VALUE_V0 {
+                assert_eq!(vec![NO_TIMESTAMP; 4], timestamps);
+            } else {
+                assert_eq!(vec![1_002, 1_003, 9_000, 9_000], timestamps);
+                assert_eq!(TimestampType::LogAppendTime, converted[3].timestamp_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_conversion_is_cut_at_the_size_of_the_batches() {
+        // Many small records grow once converted.
+        let batches = vec![batch(0, 1, 1), batch(1, 50, 1)];
+        let records = LazyDownConversionRecords::new(batches.clone(), MAGIC_VALUE_V1, 0);
+        let size: usize = batches.iter().map(|b| b.size_in_bytes()).sum();
+        let bytes = convert(&records, 64);
+        assert_eq!(size, bytes.len());
+        let converted = LegacyRecord::read_all(&bytes).unwrap();
+        assert!(converted.len() > 1 && converted.len() < 51);
+        assert!(
+            converted
+                .iter()
+                .enumerate()
+                .all(|(i, r)| r.offset == i as i64)
+        );
+    }
+
+    #[test]
+    fn test_first_batch_is_always_converted() {
+        let records = LazyDownConversionRecords::new(vec![batch(0, 50, 1)], MAGIC_VALUE_V1, 0);
+        assert!(records.size_in_bytes() > batch(0, 50, 1).size_in_bytes());
+        let bytes = convert(&records, DEFAULT_MAX_CHUNK_BYTES);
+        assert_eq!(50, LegacyRecord::read_all(&bytes).unwrap().len());
+    }
+
+    #[test]
+    fn test_chunks_count_the_converted_records() {
+        let records = LazyDownConversionRecords::new(
+            vec![batch(0, 3, 100), batch(3, 3, 100)],
+            MAGIC_VALUE_V1,
+            0,
+        );
+        let counts: Vec<_> = records.chunks(300).map(|c| c.converted_records).collect();
+        assert_eq!(6, counts.iter().sum::<usize>());
+        assert_eq!(3, counts[0]);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::common::record::record_batch::{
+    LOG_OVERHEAD, NO_TIMESTAMP, RecordError, Result, TimestampType,
+};
+use crate::common::utils::crc32::crc32;
+
+/// The magic value of the v0 message format, whose messages have no timestamp.
+pub const MAGIC_VALUE_V0: i8 = 0;
+
+/// The magic value of the v1 message format, which added the timestamp.
+pub const MAGIC_VALUE_V1: i8 = 1;
+
+/// The size of a v0 message with a null key and value, including the [LOG_OVERHEAD]: crc,
+/// magic, attributes and the lengths of the key and value.
+pub const RECORD_OVERHEAD_V0: usize = LOG_OVERHEAD + 14;
+
+/// The size of a v1 message with a null key and value, including the [LOG_OVERHEAD].
+pub const RECORD_OVERHEAD_V1: usize = RECORD_OVERHEAD_V0 + 8;
+
+const CRC_OFFSET: usize = LOG_OVERHEAD;
+const MAGIC_OFFSET: usize = CRC_OFFSET + 4;
+const TIMESTAMP_TYPE_MASK: i8 = 0x08;
+
+/// An uncompressed message of the v0 or v1 format, which clients older than the v2 format
+/// read. A message set is a sequence of such messages, each carrying its own offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyRecord {
+    pub offset: i64,
+    pub magic: i8,
+    /// The type of the timestamp, always [TimestampType::CreateTime] in v0.
+    pub timestamp_type: TimestampType,
+    /// The timestamp, not stored in v0 which reads it as [NO_TIMESTAMP].
+    pub timestamp: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+}
+
+impl LegacyRecord {
+    /// The size of the message once written, including the [LOG_OVERHEAD].
+    pub fn size_in_bytes(&self) -> usize {
+        let overhead = match self.magic {
+            MAGIC_VALUE_V0 => RECORD_OVERHEAD_V0,
+            _ => RECORD_OVERHEAD_V1,
+        };
+        overhead + self.key.as_ref().map_or(0, Vec::len) + self.value.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Appends the message, offset and size included, to `buf`.
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.extend_from_slice(&self.offset.to_be_bytes());
+        buf.extend_from_slice(&((self.size_in_bytes() - LOG_OVERHEAD) as i32).to_be_bytes());
+        buf.extend_from_slice(&0u32.to_be_bytes()); // crc, set below
+        buf.push(self.magic as u8);
+        let mut attributes = 0i8;
+        if self.magic > MAGIC_VALUE_V0 && self.timestamp_type == TimestampType::LogAppendTime {
+            attributes |= TIMESTAMP_TYPE_MASK;
+        }
+        buf.push(attributes as u8);
+        if self.magic > MAGIC_VALUE_V0 {
+            buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        }
+        write_bytes(buf, self.key.as_deref());
+        write_bytes(buf, self.value.as_deref());
+        let crc = crc32(&buf[start + MAGIC_OFFSET..]);
+        buf[start + CRC_OFFSET..start + CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+    }
+
+    /// Reads every complete message of a message set. A partial message at the end, as found
+    /// at the end of fetch responses, is ignored.
+    pub fn read_all(mut buf: &[u8]) -> Result<Vec<LegacyRecord>> {
+        let mut records = vec![];
+        while buf.len() >= LOG_OVERHEAD {
+            let size = i32::from_be_bytes(buf[8..12].try_into().unwrap());
+            let size = LOG_OVERHEAD + size.max(0) as usize;
+            if buf.len() < size {
+                break;
+            }
+            records.push(Self::read(&buf[..size])?);
+            buf = &buf[size..];
+        }
+        Ok(records)
+    }
+
+    fn read(buf: &[u8]) -> Result<LegacyRecord> {
+        if buf.len() < RECORD_OVERHEAD_V0 {
+            return Err(RecordError::Corrupt(format!(
+                "message size {} is smaller than the minimum size {RECORD_OVERHEAD_V0}",
+                buf.len()
+            )));
+        }
+        let stored = u32::from_be_bytes(buf[CRC_OFFSET..MAGIC_OFFSET].try_into().unwrap());
+        let computed = crc32(&buf[MAGIC_OFFSET..]);
+        if stored != computed {
+            return Err(RecordError::InvalidCrc { stored, computed });
+        }
+        let magic = buf[MAGIC_OFFSET] as i8;
+        let attributes = buf[MAGIC_OFFSET + 1] as i8;
+        let mut body = &buf[MAGIC_OFFSET + 2..];
+        let (timestamp_type, timestamp) = match magic {
+            MAGIC_VALUE_V0 => (TimestampType::CreateTime, NO_TIMESTAMP),
+            MAGIC_VALUE_V1 => {
+                let timestamp = i64::from_be_bytes(read_slice(&mut body, 8)?.try_into().unwrap());
+                let timestamp_type = if attributes & TIMESTAMP_TYPE_MASK != 0 {
+                    TimestampType::LogAppendTime
+                } else {
+                    TimestampType::CreateTime
+                };
+                (timestamp_type, timestamp)
+            }
+            magic => return Err(RecordError::UnsupportedMagic(magic)),
+        };
+        let record = LegacyRecord {
+            offset: i64::from_be_bytes(buf[..8].try_into().unwrap()),
+            magic,
+            timestamp_type,
+            timestamp,
+            key: read_bytes(&mut body)?,
+            value: read_bytes(&mut body)?,
+        };
+        if !body.is_empty() {
+            return Err(RecordError::Corrupt(format!(
+                "{} bytes left at the end of a message",
+                body.len()
+            )));
+        }
+        Ok(record)
+    }
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            buf.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+            buf.extend_from_slice(bytes);
+        }
+        None => buf.extend_from_slice(&(-1i32).to_be_bytes()),
+    }
+}
+
+fn read_slice<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(RecordError::Corrupt(format!(
+            "field length {len} exceeds the remaining {} bytes",
+            buf.len()
+        )));
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+fn read_bytes(buf: &mut &[u8]) -> Result<Option<Vec<u8>>> {
+    let len = i32::from_be_bytes(read_slice(buf, 4)?.try_into().unwrap());
+    if len < 0 {
+        return Ok(None);
+    }
+    Ok(Some(read_slice(buf, len as usize)?.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(magic: i8, offset: i64) -> LegacyRecord {
+        LegacyRecord {
+            offset,
+            magic,
+            timestamp_type: TimestampType::CreateTime,
+            timestamp: if magic == MAGIC_VALUE_V0 {
+                NO_TIMESTAMP
+            } else {
+                1_000
+            },
+            key: Some(b"key".to_vec()),
+            value: None,
+        }
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let mut log_append_time = record(MAGIC_VALUE_V1, 8);
+        log_append_time.timestamp_type = TimestampType::LogAppendTime;
+        let records = vec![
+            record(MAGIC_VALUE_V0, 5),
+            record(MAGIC_VALUE_V1, 7),
+            log_append_time,
+        ];
+        let mut buf = vec![];
+        for record in &records {
+            let start = buf.len();
+            record.write_to(&mut buf);
+            assert_eq!(record.size_in_bytes(), buf.len() - start);
+        }
+        assert_eq!(RECORD_OVERHEAD_V0 + 3, records[0].size_in_bytes());
+        assert_eq!(records, LegacyRecord::read_all(&buf).unwrap());
+
+        // A partial message at the end is ignored.
+        assert_eq!(
+            records[..2],
+            LegacyRecord::read_all(&buf[..buf.len() - 1]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_corrupt_message() {
+        let mut buf = vec![];
+        record(MAGIC_VALUE_V1, 0).write_to(&mut buf);
+        let last = buf.len() - 1;
+        buf[last] ^= 0xff;
+        assert!(matches!(
+            LegacyRecord::read_all(&buf),
+            Err(RecordError::InvalidCrc { .. })
+        ));
+    }
+}
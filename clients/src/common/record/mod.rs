@@ -0,0 +1,4 @@
+pub mod control_record;
+pub mod lazy_down_conversion;
+pub mod legacy_record;
+pub mod record_batch;
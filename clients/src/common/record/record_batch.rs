@@ -0,0 +1,857 @@
+use crate::common::utils::byte_utils::{
+    VarintError, read_varint, read_varint64, size_of_varint, size_of_varint64, write_varint,
+    write_varint64,
+};
+use thiserror::Error;
+
+/// The only magic value this client reads and writes.
+pub const CURRENT_MAGIC_VALUE: i8 = 2;
+
+pub const NO_PRODUCER_ID: i64 = -1;
+pub const NO_PRODUCER_EPOCH: i16 = -1;
+pub const NO_SEQUENCE: i32 = -1;
+pub const NO_PARTITION_LEADER_EPOCH: i32 = -1;
+pub const NO_TIMESTAMP: i64 = -1;
+
+/// The size of the base offset and the batch length, which precede every batch.
+pub const LOG_OVERHEAD: usize = 12;
+
+/// The size of a batch header, including the [LOG_OVERHEAD].
+pub const RECORD_BATCH_OVERHEAD: usize = 61;
+
+const BATCH_LENGTH_OFFSET: usize = 8;
+const MAGIC_OFFSET: usize = 16;
+const CRC_OFFSET: usize = 17;
+const ATTRIBUTES_OFFSET: usize = 21;
+const MAX_TIMESTAMP_OFFSET: usize = 35;
+
+/// The smallest encoded record: a one-byte length, the attributes and one byte for each of
+/// the timestamp delta, the offset delta, the key length, the value length and the number of
+/// headers.
+const MIN_RECORD_SIZE: usize = 7;
+
+/// The smallest encoded header: a one-byte key length and a one-byte value length.
+const MIN_HEADER_SIZE: usize = 2;
+
+const COMPRESSION_CODEC_MASK: i16 = 0x07;
+const TIMESTAMP_TYPE_MASK: i16 = 0x08;
+const TRANSACTIONAL_FLAG_MASK: i16 = 0x10;
+const CONTROL_FLAG_MASK: i16 = 0x20;
+
+pub type Result<T> = std::result::Result<T, RecordError>;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RecordError {
+    #[error("The buffer ends before the end of the record batch")]
+    Truncated,
+    #[error("Unsupported magic value {0}")]
+    UnsupportedMagic(i8),
+    #[error("Unsupported compression codec {0}")]
+    UnsupportedCompression(i16),
+    #[error("Record batch is corrupt (stored crc = {stored}, computed crc = {computed})")]
+    InvalidCrc { stored: u32, computed: u32 },
+    #[error("Record batch is corrupt: {0}")]
+    Corrupt(String),
+}
+
+impl From<VarintError> for RecordError {
+    fn from(e: VarintError) -> Self {
+        match e {
+            VarintError::Io(_) => RecordError::Truncated,
+            e => RecordError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+/// Whether the timestamps of a batch were set by the producer or by the broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampType {
+    CreateTime,
+    LogAppendTime,
+}
+
+impl TimestampType {
+    /// The name of the type in the `message.timestamp.type` config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimestampType::CreateTime => "CreateTime",
+            TimestampType::LogAppendTime => "LogAppendTime",
+        }
+    }
+
+    pub fn for_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "CreateTime" => Some(TimestampType::CreateTime),
+            "LogAppendTime" => Some(TimestampType::LogAppendTime),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub key: String,
+    pub value: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Record {
+    pub timestamp: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    pub headers: Vec<Header>,
+}
+
+impl Record {
+    pub fn new(timestamp: i64, key: Option<&[u8]>, value: Option<&[u8]>) -> Self {
+        Self {
+            timestamp,
+            key: key.map(<[u8]>::to_vec),
+            value: value.map(<[u8]>::to_vec),
+            headers: vec![],
+        }
+    }
+}
+
+/// What the header of a batch tells about where it sits in a log and who wrote it, known without
+/// reading its records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordBatchHeader {
+    pub base_offset: i64,
+    pub last_offset: i64,
+    pub partition_leader_epoch: i32,
+    pub max_timestamp: i64,
+    pub producer_id: i64,
+    pub is_transactional: bool,
+    pub is_control: bool,
+}
+
+/// A batch of records in the v2 (magic 2) format.
+///
+/// The records of a batch have consecutive offsets starting at the base offset. When the
+/// timestamp type is [TimestampType::LogAppendTime], every record carries the max timestamp of
+/// the batch, which is the time the leader appended it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordBatch {
+    base_offset: i64,
+    partition_leader_epoch: i32,
+    timestamp_type: TimestampType,
+    max_timestamp: i64,
+    is_transactional: bool,
+    is_control: bool,
+    producer_id: i64,
+    producer_epoch: i16,
+    base_sequence: i32,
+    records: Vec<Record>,
+}
+
+impl RecordBatch {
+    /// Creates a non-transactional batch with create-time timestamps and no producer.
+    pub fn new(base_offset: i64, records: Vec<Record>) -> Self {
+        let max_timestamp = records
+            .iter()
+            .map(|r| r.timestamp)
+            .max()
+            .unwrap_or(NO_TIMESTAMP);
+        Self {
+            base_offset,
+            partition_leader_epoch: NO_PARTITION_LEADER_EPOCH,
+            timestamp_type: TimestampType::CreateTime,
+            max_timestamp,
+            is_transactional: false,
+            is_control: false,
+            producer_id: NO_PRODUCER_ID,
+            producer_epoch: NO_PRODUCER_EPOCH,
+            base_sequence: NO_SEQUENCE,
+            records,
+        }
+    }
+
+    pub fn with_producer(
+        mut self,
+        producer_id: i64,
+        producer_epoch: i16,
+        base_sequence: i32,
+    ) -> Self {
+        self.producer_id = producer_id;
+        self.producer_epoch = producer_epoch;
+        self.base_sequence = base_sequence;
+        self
+    }
+
+    pub fn base_offset(&self) -> i64 {
+        self.base_offset
+    }
+
+    pub fn set_base_offset(&mut self, base_offset: i64) {
+        self.base_offset = base_offset;
+    }
+
+    pub fn last_offset(&self) -> i64 {
+        self.base_offset + self.last_offset_delta() as i64
+    }
+
+    pub fn next_offset(&self) -> i64 {
+        self.last_offset() + 1
+    }
+
+    fn last_offset_delta(&self) -> i32 {
+        (self.records.len() as i32 - 1).max(0)
+    }
+
+    pub fn partition_leader_epoch(&self) -> i32 {
+        self.partition_leader_epoch
+    }
+
+    pub fn set_partition_leader_epoch(&mut self, epoch: i32) {
+        self.partition_leader_epoch = epoch;
+    }
+
+    pub fn timestamp_type(&self) -> TimestampType {
+        self.timestamp_type
+    }
+
+    /// Overrides the timestamps of the records with the time the batch was appended.
+    ///
+    /// See [RecordBatch::set_log_append_time_in_place] to do it on an encoded batch.
+    pub fn set_log_append_time(&mut self, timestamp: i64) {
+        self.timestamp_type = TimestampType::LogAppendTime;
+        self.max_timestamp = timestamp;
+        for record in &mut self.records {
+            record.timestamp = timestamp;
+        }
+    }
+
+    pub fn max_timestamp(&self) -> i64 {
+        self.max_timestamp
+    }
+
+    /// The offset of the first record carrying the max timestamp, which is the first offset of
+    /// the batch when all the records carry the log append time.
+    pub fn offset_of_max_timestamp(&self) -> i64 {
+        if self.timestamp_type == TimestampType::LogAppendTime {
+            return self.base_offset;
+        }
+        self.iter()
+            .find(|(_, record)| record.timestamp == self.max_timestamp)
+            .map_or(self.base_offset, |(offset, _)| offset)
+    }
+
+    pub fn header(&self) -> RecordBatchHeader {
+        RecordBatchHeader {
+            base_offset: self.base_offset,
+            last_offset: self.last_offset(),
+            partition_leader_epoch: self.partition_leader_epoch,
+            max_timestamp: self.max_timestamp,
+            producer_id: self.producer_id,
+            is_transactional: self.is_transactional,
+            is_control: self.is_control,
+        }
+    }
+
+    pub fn is_transactional(&self) -> bool {
+        self.is_transactional
+    }
+
+    pub fn set_transactional(&mut self, is_transactional: bool) {
+        self.is_transactional = is_transactional;
+    }
+
+    /// Whether the batch holds control records, e.g. transaction markers, rather than data.
+    pub fn is_control(&self) -> bool {
+        self.is_control
+    }
+
+    pub fn set_control(&mut self, is_control: bool) {
+        self.is_control = is_control;
+    }
+
+    pub fn producer_id(&self) -> i64 {
+        self.producer_id
+    }
+
+    pub fn producer_epoch(&self) -> i16 {
+        self.producer_epoch
+    }
+
+    pub fn base_sequence(&self) -> i32 {
+        self.base_sequence
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// The records, to annotate them before the batch is appended.
+    pub fn records_mut(&mut self) -> &mut [Record] {
+        &mut self.records
+    }
+
+    pub fn count(&self) -> usize {
+        self.records.len()
+    }
+
+    /// The records with their offsets.
+    pub fn iter(&self) -> impl Iterator<Item = (i64, &Record)> {
+        self.records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| (self.base_offset + i as i64, record))
+    }
+
+    fn attributes(&self) -> i16 {
+        let mut attributes = 0;
+        if self.timestamp_type == TimestampType::LogAppendTime {
+            attributes |= TIMESTAMP_TYPE_MASK;
+        }
+        if self.is_transactional {
+            attributes |= TRANSACTIONAL_FLAG_MASK;
+        }
+        if self.is_control {
+            attributes |= CONTROL_FLAG_MASK;
+        }
+        attributes
+    }
+
+    /// The size of the batch once encoded, header included, worked out from the lengths of
+    /// its fields so that sizing a batch, e.g. against a fetch or a cache budget, costs no
+    /// allocation.
+    pub fn size_in_bytes(&self) -> usize {
+        let base_timestamp = self.base_timestamp();
+        RECORD_BATCH_OVERHEAD
+            + self
+                .records
+                .iter()
+                .enumerate()
+                .map(|(offset_delta, record)| {
+                    let size = record_body_size(
+                        offset_delta as i32,
+                        record.timestamp - base_timestamp,
+                        record,
+                    );
+                    size_of_varint(size as i32) + size
+                })
+                .sum::<usize>()
+    }
+
+    fn base_timestamp(&self) -> i64 {
+        self.records.first().map_or(NO_TIMESTAMP, |r| r.timestamp)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size_in_bytes());
+        self.write_to(&mut buf);
+        buf
+    }
+
+    /// Appends the batch, header included, to `buf`.
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        let base_timestamp = self.base_timestamp();
+        buf.extend_from_slice(&self.base_offset.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes()); // batch length, set below
+        buf.extend_from_slice(&self.partition_leader_epoch.to_be_bytes());
+        buf.push(CURRENT_MAGIC_VALUE as u8);
+        buf.extend_from_slice(&0u32.to_be_bytes()); // crc, set below
+        buf.extend_from_slice(&self.attributes().to_be_bytes());
+        buf.extend_from_slice(&self.last_offset_delta().to_be_bytes());
+        buf.extend_from_slice(&base_timestamp.to_be_bytes());
+        buf.extend_from_slice(&self.max_timestamp.to_be_bytes());
+        buf.extend_from_slice(&self.producer_id.to_be_bytes());
+        buf.extend_from_slice(&self.producer_epoch.to_be_bytes());
+        buf.extend_from_slice(&self.base_sequence.to_be_bytes());
+        buf.extend_from_slice(&(self.records.len() as i32).to_be_bytes());
+        for (offset_delta, record) in self.records.iter().enumerate() {
+            write_record(
+                buf,
+                offset_delta as i32,
+                record.timestamp - base_timestamp,
+                record,
+            );
+        }
+
+        let batch_length = (buf.len() - start - LOG_OVERHEAD) as i32;
+        buf[start + BATCH_LENGTH_OFFSET..start + BATCH_LENGTH_OFFSET + 4]
+            .copy_from_slice(&batch_length.to_be_bytes());
+        let crc = crc32c::crc32c(&buf[start + ATTRIBUTES_OFFSET..]);
+        buf[start + CRC_OFFSET..start + CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+    }
+
+    /// The size of the batch starting at the beginning of `buf`, read from its header, or
+    /// `None` if `buf` is too short to contain the header.
+    pub fn size_of_next(buf: &[u8]) -> Option<usize> {
+        if buf.len() < LOG_OVERHEAD {
+            return None;
+        }
+        let batch_length = get_i32(buf, BATCH_LENGTH_OFFSET);
+        Some(LOG_OVERHEAD + batch_length.max(0) as usize)
+    }
+
+    /// The batch at the beginning of `buf`, checked to be complete and of the current magic.
+    fn next_batch(buf: &[u8]) -> Result<&[u8]> {
+        let size = Self::size_of_next(buf).ok_or(RecordError::Truncated)?;
+        if size < RECORD_BATCH_OVERHEAD {
+            return Err(RecordError::Corrupt(format!(
+                "batch size {size} is smaller than the minimum size {RECORD_BATCH_OVERHEAD}"
+            )));
+        }
+        if buf.len() < size {
+            return Err(RecordError::Truncated);
+        }
+        let buf = &buf[..size];
+        let magic = buf[MAGIC_OFFSET] as i8;
+        if magic != CURRENT_MAGIC_VALUE {
+            return Err(RecordError::UnsupportedMagic(magic));
+        }
+        Ok(buf)
+    }
+
+    /// Reads the header of the batch at the beginning of `buf`, returning it with the size of
+    /// the batch. Unlike [RecordBatch::read_from], neither the crc nor the records are checked.
+    pub fn read_header(buf: &[u8]) -> Result<(RecordBatchHeader, usize)> {
+        let buf = Self::next_batch(buf)?;
+        let base_offset = get_i64(buf, 0);
+        let attributes = get_i16(buf, ATTRIBUTES_OFFSET);
+        let header = RecordBatchHeader {
+            base_offset,
+            last_offset: base_offset + get_i32(buf, 23) as i64,
+            partition_leader_epoch: get_i32(buf, 12),
+            max_timestamp: get_i64(buf, MAX_TIMESTAMP_OFFSET),
+            producer_id: get_i64(buf, 43),
+            is_transactional: attributes & TRANSACTIONAL_FLAG_MASK != 0,
+            is_control: attributes & CONTROL_FLAG_MASK != 0,
+        };
+        Ok((header, buf.len()))
+    }
+
+    /// Reads and validates the batch at the beginning of `buf`, returning it with its size.
+    pub fn read_from(buf: &[u8]) -> Result<(RecordBatch, usize)> {
+        let buf = Self::next_batch(buf)?;
+        let size = buf.len();
+        let stored = get_i32(buf, CRC_OFFSET) as u32;
+        let computed = crc32c::crc32c(&buf[ATTRIBUTES_OFFSET..]);
+        if stored != computed {
+            return Err(RecordError::InvalidCrc { stored, computed });
+        }
+
+        let base_offset = get_i64(buf, 0);
+        let partition_leader_epoch = get_i32(buf, 12);
+        let attributes = get_i16(buf, ATTRIBUTES_OFFSET);
+        let last_offset_delta = get_i32(buf, 23);
+        let base_timestamp = get_i64(buf, 27);
+        let max_timestamp = get_i64(buf, MAX_TIMESTAMP_OFFSET);
+        let producer_id = get_i64(buf, 43);
+        let producer_epoch = get_i16(buf, 51);
+        let base_sequence = get_i32(buf, 53);
+        let count = get_i32(buf, 57);
+
+        let compression = attributes & COMPRESSION_CODEC_MASK;
+        if compression != 0 {
+            return Err(RecordError::UnsupportedCompression(compression));
+        }
+        let timestamp_type = if attributes & TIMESTAMP_TYPE_MASK != 0 {
+            TimestampType::LogAppendTime
+        } else {
+            TimestampType::CreateTime
+        };
+
+        let mut body = &buf[RECORD_BATCH_OVERHEAD..];
+        // The count comes from the wire, so only trust it as far as the body could hold it.
+        let mut records =
+            Vec::with_capacity((count.max(0) as usize).min(body.len() / MIN_RECORD_SIZE));
+        for expected_delta in 0..count {
+            let (offset_delta, mut record) = read_record(&mut body, base_timestamp)?;
+            if offset_delta != expected_delta {
+                return Err(RecordError::Corrupt(format!(
+                    "expected offset delta {expected_delta} but found {offset_delta}"
+                )));
+            }
+            if timestamp_type == TimestampType::LogAppendTime {
+                record.timestamp = max_timestamp;
+            }
+            records.push(record);
+        }
+        if !body.is_empty() {
+            return Err(RecordError::Corrupt(format!(
+                "{} bytes left after reading {count} records",
+                body.len()
+            )));
+        }
+        if count > 0 && last_offset_delta != count - 1 {
+            return Err(RecordError::Corrupt(format!(
+                "last offset delta {last_offset_delta} doesn't match the record count {count}"
+            )));
+        }
+
+        Ok((
+            RecordBatch {
+                base_offset,
+                partition_leader_epoch,
+                timestamp_type,
+                max_timestamp,
+                is_transactional: attributes & TRANSACTIONAL_FLAG_MASK != 0,
+                is_control: attributes & CONTROL_FLAG_MASK != 0,
+                producer_id,
+                producer_epoch,
+                base_sequence,
+                records,
+            },
+            size,
+        ))
+    }
+
+    /// Stamps the encoded batch at the beginning of `buf` with the log append time `timestamp`.
+    ///
+    /// Only the header changes: the timestamp type attribute, the max timestamp and the crc.
+    /// The records are left as the producer wrote them, since readers take the timestamp of
+    /// every record of such a batch from its max timestamp, so a batch appended as it was
+    /// received needs neither decoding nor encoding again.
+    pub fn set_log_append_time_in_place(buf: &mut [u8], timestamp: i64) -> Result<()> {
+        let size = Self::size_of_next(buf).ok_or(RecordError::Truncated)?;
+        if size < RECORD_BATCH_OVERHEAD {
+            return Err(RecordError::Corrupt(format!(
+                "batch size {size} is smaller than the minimum size {RECORD_BATCH_OVERHEAD}"
+            )));
+        }
+        if buf.len() < size {
+            return Err(RecordError::Truncated);
+        }
+        let buf = &mut buf[..size];
+        let magic = buf[MAGIC_OFFSET] as i8;
+        if magic != CURRENT_MAGIC_VALUE {
+            return Err(RecordError::UnsupportedMagic(magic));
+        }
+        let attributes = get_i16(buf, ATTRIBUTES_OFFSET) | TIMESTAMP_TYPE_MASK;
+        buf[ATTRIBUTES_OFFSET..ATTRIBUTES_OFFSET + 2].copy_from_slice(&attributes.to_be_bytes());
+        buf[MAX_TIMESTAMP_OFFSET..MAX_TIMESTAMP_OFFSET + 8]
+            .copy_from_slice(&timestamp.to_be_bytes());
+        let crc = crc32c::crc32c(&buf[ATTRIBUTES_OFFSET..]);
+        buf[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+        Ok(())
+    }
+
+    /// Sets the base offset and the partition leader epoch of the encoded batch at the
+    /// beginning of `buf`, as the leader does when it appends a batch as it was received.
+    ///
+    /// Neither field is covered by the crc, so the rest of the batch is left as it is.
+    pub fn assign_offsets_in_place(
+        buf: &mut [u8],
+        base_offset: i64,
+        partition_leader_epoch: i32,
+    ) -> Result<()> {
+        let size = Self::next_batch(buf)?.len();
+        let buf = &mut buf[..size];
+        buf[..8].copy_from_slice(&base_offset.to_be_bytes());
+        buf[12..16].copy_from_slice(&partition_leader_epoch.to_be_bytes());
+        Ok(())
+    }
+
+    /// Reads every complete batch of `buf`. A partial batch at the end of the buffer, as found
+    /// at the end of fetch responses, is ignored.
+    pub fn read_all(mut buf: &[u8]) -> Result<Vec<RecordBatch>> {
+        let mut batches = vec![];
+        loop {
+            match Self::read_from(buf) {
+                Ok((batch, size)) => {
+                    batches.push(batch);
+                    buf = &buf[size..];
+                }
+                Err(RecordError::Truncated) => return Ok(batches),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn get_i16(buf: &[u8], at: usize) -> i16 {
+    i16::from_be_bytes(buf[at..at + 2].try_into().unwrap())
+}
+
+fn get_i32(buf: &[u8], at: usize) -> i32 {
+    i32::from_be_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn get_i64(buf: &[u8], at: usize) -> i64 {
+    i64::from_be_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            write_varint(bytes.len() as i32, buf).expect("Writing to a Vec should not fail");
+            buf.extend_from_slice(bytes);
+        }
+        None => write_varint(-1, buf).expect("Writing to a Vec should not fail"),
+    }
+}
+
+fn bytes_size(bytes: Option<&[u8]>) -> usize {
+    bytes.map_or(size_of_varint(-1), |bytes| {
+        size_of_varint(bytes.len() as i32) + bytes.len()
+    })
+}
+
+/// The size of a record written by [write_record], without its length.
+fn record_body_size(offset_delta: i32, timestamp_delta: i64, record: &Record) -> usize {
+    1 // attributes
+        + size_of_varint64(timestamp_delta)
+        + size_of_varint(offset_delta)
+        + bytes_size(record.key.as_deref())
+        + bytes_size(record.value.as_deref())
+        + size_of_varint(record.headers.len() as i32)
+        + record
+            .headers
+            .iter()
+            .map(|header| {
+                bytes_size(Some(header.key.as_bytes())) + bytes_size(header.value.as_deref())
+            })
+            .sum::<usize>()
+}
+
+fn write_record(buf: &mut Vec<u8>, offset_delta: i32, timestamp_delta: i64, record: &Record) {
+    let mut body = vec![0u8]; // attributes, unused
+    write_varint64(timestamp_delta, &mut body).expect("Writing to a Vec should not fail");
+    write_varint(offset_delta, &mut body).expect("Writing to a Vec should not fail");
+    write_bytes(&mut body, record.key.as_deref());
+    write_bytes(&mut body, record.value.as_deref());
+    write_varint(record.headers.len() as i32, &mut body).expect("Writing to a Vec should not fail");
+    for header in &record.headers {
+        write_bytes(&mut body, Some(header.key.as_bytes()));
+        write_bytes(&mut body, header.value.as_deref());
+    }
+    write_varint(body.len() as i32, buf).expect("Writing to a Vec should not fail");
+    buf.extend_from_slice(&body);
+}
+
+fn read_bytes(buf: &mut &[u8]) -> Result<Option<Vec<u8>>> {
+    let len = read_varint(buf)?;
+    if len < 0 {
+        return Ok(None);
+    }
+    let len = len as usize;
+    if buf.len() < len {
+        return Err(RecordError::Corrupt(format!(
+            "field length {len} exceeds the remaining {} bytes",
+            buf.len()
+        )));
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(Some(bytes.to_vec()))
+}
+
+fn read_record(buf: &mut &[u8], base_timestamp: i64) -> Result<(i32, Record)> {
+    let corrupt = |e: RecordError| match e {
+        RecordError::Truncated => RecordError::Corrupt("record extends past the batch".into()),
+        e => e,
+    };
+    let len = read_varint(buf).map_err(|e| corrupt(e.into()))?;
+    if len < 0 || buf.len() < len as usize {
+        return Err(RecordError::Corrupt(format!("invalid record size {len}")));
+    }
+    let (mut body, rest) = buf.split_at(len as usize);
+    *buf = rest;
+
+    let parse = |body: &mut &[u8]| -> Result<(i32, Record)> {
+        let (_attributes, rest) = body.split_first().ok_or(RecordError::Truncated)?;
+        *body = rest;
+        let timestamp_delta = read_varint64(body)?;
+        let offset_delta = read_varint(body)?;
+        let key = read_bytes(body)?;
+        let value = read_bytes(body)?;
+        let num_headers = read_varint(body)?;
+        if num_headers < 0 {
+            return Err(RecordError::Corrupt(format!(
+                "invalid number of headers {num_headers}"
+            )));
+        }
+        let mut headers =
+            Vec::with_capacity((num_headers as usize).min(body.len() / MIN_HEADER_SIZE));
+        for _ in 0..num_headers {
+            let key =
+                read_bytes(body)?.ok_or_else(|| RecordError::Corrupt("null header key".into()))?;
+            let key = String::from_utf8(key)
+                .map_err(|_| RecordError::Corrupt("header key is not valid UTF-8".into()))?;
+            headers.push(Header {
+                key,
+                value: read_bytes(body)?,
+            });
+        }
+        let timestamp = base_timestamp.checked_add(timestamp_delta).ok_or_else(|| {
+            RecordError::Corrupt(format!(
+                "timestamp delta {timestamp_delta} overflows the base timestamp {base_timestamp}"
+            ))
+        })?;
+        Ok((
+            offset_delta,
+            Record {
+                timestamp,
+                key,
+                value,
+                headers,
+            },
+        ))
+    };
+    let result = parse(&mut body).map_err(corrupt)?;
+    if !body.is_empty() {
+        return Err(RecordError::Corrupt(format!(
+            "{} bytes left at the end of a record",
+            body.len()
+        )));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch() -> RecordBatch {
+        let mut record = Record::new(1_000, Some(b"key"), Some(b"value"));
+        record.headers.push(Header {
+            key: "h".to_string(),
+            value: None,
+        });
+        let mut batch = RecordBatch::new(
+            42,
+            vec![
+                record,
+                Record::new(1_005, None, Some(b"v2")),
+                Record::new(998, None, None),
+            ],
+        )
+        .with_producer(7, 1, 10);
+        batch.set_partition_leader_epoch(3);
+        batch
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let batch = batch();
+        let buf = batch.encode();
+        assert_eq!(Some(buf.len()), RecordBatch::size_of_next(&buf));
+
+        assert_eq!(buf.len(), batch.size_in_bytes());
+
+        let (read, size) = RecordBatch::read_from(&buf).unwrap();
+        assert_eq!(buf.len(), size);
+        assert_eq!(batch, read);
+        assert_eq!(44, read.last_offset());
+        assert_eq!(1_005, read.max_timestamp());
+        assert_eq!(43, read.offset_of_max_timestamp());
+        assert_eq!(3, read.partition_leader_epoch());
+        assert_eq!(
+            (batch.header(), buf.len()),
+            RecordBatch::read_header(&buf).unwrap()
+        );
+        assert_eq!(
+            vec![42, 43, 44],
+            read.iter().map(|(offset, _)| offset).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_log_append_time() {
+        let mut batch = batch();
+        batch.set_log_append_time(5_000);
+        let (read, _) = RecordBatch::read_from(&batch.encode()).unwrap();
+        assert_eq!(TimestampType::LogAppendTime, read.timestamp_type());
+        assert_eq!(5_000, read.max_timestamp());
+        assert!(read.records().iter().all(|r| r.timestamp == 5_000));
+        assert_eq!(42, read.offset_of_max_timestamp());
+    }
+
+    #[test]
+    fn test_log_append_time_in_place() {
+        let mut expected = batch();
+        expected.set_log_append_time(5_000);
+        let mut buf = batch().encode();
+        RecordBatch::set_log_append_time_in_place(&mut buf, 5_000).unwrap();
+        let (read, _) = RecordBatch::read_from(&buf).unwrap();
+        assert_eq!(expected, read);
+
+        let mut buf = batch().encode();
+        buf[MAGIC_OFFSET] = 1;
+        assert_eq!(
+            Err(RecordError::UnsupportedMagic(1)),
+            RecordBatch::set_log_append_time_in_place(&mut buf, 5_000)
+        );
+    }
+
+    #[test]
+    fn test_assign_offsets_in_place() {
+        let mut buf = batch().encode();
+        RecordBatch::assign_offsets_in_place(&mut buf, 100, 9).unwrap();
+        let (read, _) = RecordBatch::read_from(&buf).unwrap();
+        assert_eq!(100, read.base_offset());
+        assert_eq!(102, read.last_offset());
+        assert_eq!(9, read.partition_leader_epoch());
+        assert_eq!(batch().records(), read.records());
+    }
+
+    #[test]
+    fn test_corrupt_batches() {
+        let mut buf = batch().encode();
+        let last = buf.len() - 1;
+        buf[last] ^= 0xff;
+        assert!(matches!(
+            RecordBatch::read_from(&buf),
+            Err(RecordError::InvalidCrc { .. })
+        ));
+
+        let buf = batch().encode();
+        assert_eq!(
+            Err(RecordError::Truncated),
+            RecordBatch::read_from(&buf[..buf.len() - 1])
+        );
+
+        let mut buf = batch().encode();
+        buf[MAGIC_OFFSET] = 1;
+        assert_eq!(
+            Err(RecordError::UnsupportedMagic(1)),
+            RecordBatch::read_from(&buf)
+        );
+    }
+
+    /// Overwrites the header field at `at` of the encoded batch, keeping its crc valid.
+    fn set_header_field(buf: &mut [u8], at: usize, value: &[u8]) {
+        buf[at..at + value.len()].copy_from_slice(value);
+        let crc = crc32c::crc32c(&buf[ATTRIBUTES_OFFSET..]);
+        buf[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_be_bytes());
+    }
+
+    #[test]
+    fn test_inflated_record_count() {
+        let mut buf = batch().encode();
+        set_header_field(&mut buf, 57, &i32::MAX.to_be_bytes());
+        assert_eq!(
+            Err(RecordError::Corrupt("record extends past the batch".into())),
+            RecordBatch::read_from(&buf)
+        );
+    }
+
+    #[test]
+    fn test_timestamp_overflow() {
+        let mut buf = batch().encode();
+        set_header_field(&mut buf, 27, &i64::MAX.to_be_bytes());
+        assert!(matches!(
+            RecordBatch::read_from(&buf),
+            Err(RecordError::Corrupt(message)) if message.contains("overflows")
+        ));
+    }
+
+    #[test]
+    fn test_read_all_ignores_a_partial_batch() {
+        let mut buf = batch().encode();
+        let mut second = batch();
+        second.set_base_offset(45);
+        second.write_to(&mut buf);
+        let full = buf.len();
+        batch().write_to(&mut buf);
+        buf.truncate(full + 20);
+
+        let batches = RecordBatch::read_all(&buf).unwrap();
+        assert_eq!(2, batches.len());
+        assert_eq!(45, batches[1].base_offset());
+    }
+}
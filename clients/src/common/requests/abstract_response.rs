@@ -0,0 +1,22 @@
+//! The throttle time every response carries back to the client.
+
+/// The throttle time of a response which was not throttled.
+pub const DEFAULT_THROTTLE_TIME: i32 = 0;
+
+/// A response to a client request.
+///
+/// Brokers report how long a request was throttled for in every response that has the field.
+/// Since KIP-219 the broker sends the response right away and the client is expected to hold
+/// back further requests to the broker for the throttle time, instead of the broker delaying
+/// the response.
+pub trait AbstractResponse {
+    /// How long the request was throttled for by a quota, in milliseconds.
+    fn throttle_time_ms(&self) -> i32;
+
+    /// Sets the throttle time, for responses whose version has the field.
+    fn maybe_set_throttle_time_ms(&mut self, throttle_time_ms: i32);
+
+    /// Whether a client receiving this response at `version` must back off for the throttle
+    /// time itself. Older versions were delayed by the broker instead.
+    fn should_client_throttle(&self, version: i16) -> bool;
+}
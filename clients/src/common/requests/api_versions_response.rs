@@ -0,0 +1,152 @@
+//! The response to an ApiVersions request, which tells a client the versions of every API the
+//! broker supports.
+
+use crate::common::protocol::api_keys::ApiKeys;
+use crate::common::protocol::api_version_matrix::EnabledVersions;
+use crate::common::protocol::errors::Errors;
+use crate::common::requests::abstract_response::{AbstractResponse, DEFAULT_THROTTLE_TIME};
+use crate::common::requests::response_writer::{ResponseSink, ResponseWriter, WritableResponse};
+
+/// The versions of an API the broker supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiVersion {
+    pub api_key: i16,
+    pub min_version: i16,
+    pub max_version: i16,
+}
+
+impl From<ApiKeys> for ApiVersion {
+    fn from(api: ApiKeys) -> Self {
+        Self {
+            api_key: api.id(),
+            min_version: api.oldest_version(),
+            max_version: api.latest_version(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiVersionsResponse {
+    pub error: Errors,
+    pub api_keys: Vec<ApiVersion>,
+    pub throttle_time_ms: i32,
+}
+
+impl ApiVersionsResponse {
+    /// The response listing every API in [ApiKeys::ALL].
+    pub fn default_api_versions(error: Errors) -> Self {
+        Self {
+            error,
+            api_keys: ApiKeys::ALL.iter().copied().map(ApiVersion::from).collect(),
+            throttle_time_ms: DEFAULT_THROTTLE_TIME,
+        }
+    }
+
+    /// The response listing the APIs the broker enables, up to their latest enabled version.
+    /// An API whose oldest version is disabled is left out.
+    pub fn enabled_api_versions(error: Errors, enabled: &EnabledVersions) -> Self {
+        Self {
+            error,
+            api_keys: ApiKeys::ALL
+                .iter()
+                .filter_map(|api| {
+                    enabled
+                        .latest_enabled_version(*api)
+                        .map(|max_version| ApiVersion {
+                            max_version,
+                            ..ApiVersion::from(*api)
+                        })
+                })
+                .collect(),
+            throttle_time_ms: DEFAULT_THROTTLE_TIME,
+        }
+    }
+}
+
+/// The header of an ApiVersions response has no tagged fields at any version. A client
+/// sending a version the broker does not support is answered at version 0, which every
+/// client can parse, with [Errors::UnsupportedVersion].
+impl WritableResponse for ApiVersionsResponse {
+    fn write_into<S: ResponseSink>(&self, correlation_id: i32, version: i16, sink: &mut S) {
+        let mut writer = ResponseWriter::new(sink, ApiKeys::ApiVersions.is_flexible(version));
+        writer.i32(correlation_id);
+        writer.i16(self.error.code());
+        writer.len(Some(self.api_keys.len()), false);
+        for api in &self.api_keys {
+            writer.i16(api.api_key);
+            writer.i16(api.min_version);
+            writer.i16(api.max_version);
+            writer.tagged_fields();
+        }
+        if version >= 1 {
+            writer.i32(self.throttle_time_ms);
+        }
+        writer.tagged_fields();
+    }
+}
+
+impl AbstractResponse for ApiVersionsResponse {
+    fn throttle_time_ms(&self) -> i32 {
+        self.throttle_time_ms
+    }
+
+    fn maybe_set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+        self.throttle_time_ms = throttle_time_ms;
+    }
+
+    fn should_client_throttle(&self, version: i16) -> bool {
+        version >= 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_v0() {
+        let response = ApiVersionsResponse {
+            error: Errors::UnsupportedVersion,
+            api_keys: vec![ApiKeys::ApiVersions.into()],
+            throttle_time_ms: 0,
+        };
+        let mut buf = Vec::new();
+        response.write_to(7, 0, &mut buf);
+        assert_eq!(vec![0, 0, 0, 7, 0, 35, 0, 0, 0, 1, 0, 18, 0, 0, 0, 4], buf);
+    }
+
+    #[test]
+    fn test_enabled_api_versions() {
+        let response =
+            ApiVersionsResponse::enabled_api_versions(Errors::None, &EnabledVersions::new(false));
+        let version = |api: ApiKeys| response.api_keys.iter().find(|v| v.api_key == api.id());
+        assert_eq!(
+            Some(&ApiVersion {
+                api_key: 0,
+                min_version: 3,
+                max_version: 11,
+            }),
+            version(ApiKeys::Produce)
+        );
+        assert_eq!(
+            Some(&ApiVersion::from(ApiKeys::Fetch)),
+            version(ApiKeys::Fetch)
+        );
+        assert_eq!(None, version(ApiKeys::DescribeTopicPartitions));
+    }
+
+    #[test]
+    fn test_write_flexible() {
+        let response = ApiVersionsResponse {
+            error: Errors::None,
+            api_keys: vec![ApiKeys::ApiVersions.into()],
+            throttle_time_ms: 5,
+        };
+        let mut buf = Vec::new();
+        response.write_to(7, 3, &mut buf);
+        assert_eq!(
+            vec![0, 0, 0, 7, 0, 0, 2, 0, 18, 0, 0, 0, 4, 0, 0, 0, 0, 5, 0],
+            buf
+        );
+    }
+}
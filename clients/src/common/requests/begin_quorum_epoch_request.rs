@@ -0,0 +1,351 @@
+//! BeginQuorumEpoch: a newly elected leader tells the voters of the quorum about its epoch.
+
+use crate::common::protocol::errors::Errors;
+use crate::common::protocol::raft_api_keys::RaftApiKeys;
+use crate::common::requests::message_reader::{MessageReader, Result};
+use crate::common::requests::raft_messages::{
+    LeaderEndpoint, NodeEndpoint, check_version, node_endpoints_field, read_leader_endpoints,
+    read_node_endpoints, write_leader_endpoints, write_response_header,
+};
+use crate::common::requests::response_writer::{ResponseSink, ResponseWriter, WritableResponse};
+use crate::common::uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeginQuorumEpochPartitionRequest {
+    pub partition_index: i32,
+    /// The directory id of the voter the request is sent to, sent from version 1.
+    pub voter_directory_id: Uuid,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeginQuorumEpochTopicRequest {
+    pub topic_name: String,
+    pub partitions: Vec<BeginQuorumEpochPartitionRequest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeginQuorumEpochRequest {
+    pub cluster_id: Option<String>,
+    /// The id of the voter the request is sent to, sent from version 1.
+    pub voter_id: i32,
+    pub topics: Vec<BeginQuorumEpochTopicRequest>,
+    /// The endpoints of the leader, sent from version 1.
+    pub leader_endpoints: Vec<LeaderEndpoint>,
+}
+
+impl BeginQuorumEpochRequest {
+    /// Writes the body of the request at `version`.
+    pub fn write_into<S: ResponseSink>(&self, version: i16, sink: &mut S) {
+        let flexible = RaftApiKeys::BeginQuorumEpoch.is_flexible(version);
+        let mut writer = ResponseWriter::new(sink, flexible);
+        writer.nullable_string(self.cluster_id.as_deref());
+        if version >= 1 {
+            writer.i32(self.voter_id);
+        }
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            writer.string(&topic.topic_name);
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                writer.i32(partition.partition_index);
+                if version >= 1 {
+                    writer.bytes(&partition.voter_directory_id.to_bytes());
+                }
+                writer.i32(partition.leader_id);
+                writer.i32(partition.leader_epoch);
+                writer.tagged_fields();
+            }
+            writer.tagged_fields();
+        }
+        if version >= 1 {
+            write_leader_endpoints(&mut writer, &self.leader_endpoints);
+        }
+        writer.tagged_fields();
+    }
+
+    /// Parses the body of a request of `version`.
+    pub fn parse(version: i16, body: &[u8]) -> Result<Self> {
+        let api = RaftApiKeys::BeginQuorumEpoch;
+        check_version(api, version)?;
+        let mut reader = MessageReader::new(body, api.is_flexible(version));
+        let cluster_id = reader.nullable_string("cluster id")?;
+        let voter_id = match version >= 1 {
+            true => reader.i32("voter id")?,
+            false => -1,
+        };
+        let topics = reader.elements("topics", |reader| {
+            let topic_name = reader.string("topic name")?;
+            let partitions = reader.elements("partitions", |reader| {
+                let partition_index = reader.i32("partition index")?;
+                let voter_directory_id = match version >= 1 {
+                    true => reader.uuid("voter directory id")?,
+                    false => Uuid::ZERO,
+                };
+                let partition = BeginQuorumEpochPartitionRequest {
+                    partition_index,
+                    voter_directory_id,
+                    leader_id: reader.i32("leader id")?,
+                    leader_epoch: reader.i32("leader epoch")?,
+                };
+                reader.tagged_fields()?;
+                Ok(partition)
+            })?;
+            reader.tagged_fields()?;
+            Ok(BeginQuorumEpochTopicRequest {
+                topic_name,
+                partitions,
+            })
+        })?;
+        let leader_endpoints = match version >= 1 {
+            true => read_leader_endpoints(&mut reader)?,
+            false => vec![],
+        };
+        reader.tagged_fields()?;
+        reader.finish()?;
+        Ok(Self {
+            cluster_id,
+            voter_id,
+            topics,
+            leader_endpoints,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeginQuorumEpochPartitionResponse {
+    pub partition_index: i32,
+    pub error: Errors,
+    /// The leader the voter knows of, or -1.
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeginQuorumEpochTopicResponse {
+    pub topic_name: String,
+    pub partitions: Vec<BeginQuorumEpochPartitionResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeginQuorumEpochResponse {
+    pub error: Errors,
+    pub topics: Vec<BeginQuorumEpochTopicResponse>,
+    /// The endpoints of the leaders the response names, sent from version 1.
+    pub node_endpoints: Vec<NodeEndpoint>,
+}
+
+impl WritableResponse for BeginQuorumEpochResponse {
+    fn write_into<S: ResponseSink>(&self, correlation_id: i32, version: i16, sink: &mut S) {
+        let api = RaftApiKeys::BeginQuorumEpoch;
+        let mut writer = ResponseWriter::new(sink, api.is_flexible(version));
+        write_response_header(&mut writer, api, version, correlation_id);
+        writer.i16(self.error.code());
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            writer.string(&topic.topic_name);
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                writer.i32(partition.partition_index);
+                writer.i16(partition.error.code());
+                writer.i32(partition.leader_id);
+                writer.i32(partition.leader_epoch);
+                writer.tagged_fields();
+            }
+            writer.tagged_fields();
+        }
+        writer.tagged_fields_with(&node_endpoints_field(version, &self.node_endpoints));
+    }
+}
+
+impl BeginQuorumEpochResponse {
+    /// Parses the body of a response of `version`, after its header.
+    pub fn parse(version: i16, body: &[u8]) -> Result<Self> {
+        let api = RaftApiKeys::BeginQuorumEpoch;
+        check_version(api, version)?;
+        let mut reader = MessageReader::new(body, api.is_flexible(version));
+        let error = reader.error("error code")?;
+        let topics = reader.elements("topics", |reader| {
+            let topic_name = reader.string("topic name")?;
+            let partitions = reader.elements("partitions", |reader| {
+                let partition = BeginQuorumEpochPartitionResponse {
+                    partition_index: reader.i32("partition index")?,
+                    error: reader.error("error code")?,
+                    leader_id: reader.i32("leader id")?,
+                    leader_epoch: reader.i32("leader epoch")?,
+                };
+                reader.tagged_fields()?;
+                Ok(partition)
+            })?;
+            reader.tagged_fields()?;
+            Ok(BeginQuorumEpochTopicResponse {
+                topic_name,
+                partitions,
+            })
+        })?;
+        let node_endpoints = read_node_endpoints(version, &reader.tagged_fields()?)?;
+        reader.finish()?;
+        Ok(Self {
+            error,
+            topics,
+            node_endpoints,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> BeginQuorumEpochRequest {
+        BeginQuorumEpochRequest {
+            cluster_id: Some("c".to_string()),
+            voter_id: 2,
+            topics: vec![BeginQuorumEpochTopicRequest {
+                topic_name: "m".to_string(),
+                partitions: vec![BeginQuorumEpochPartitionRequest {
+                    partition_index: 0,
+                    voter_directory_id: Uuid::new(0, 2),
+                    leader_id: 1,
+                    leader_epoch: 5,
+                }],
+            }],
+            leader_endpoints: vec![LeaderEndpoint {
+                name: "C".to_string(),
+                host: "h".to_string(),
+                port: 9093,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_request() {
+        let mut buf = Vec::new();
+        request().write_into(0, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            // cluster id
+            0, 1, b'c',
+            // topics
+            0, 0, 0, 1, 0, 1, b'm',
+            // partitions
+            0, 0, 0, 1, 0, 0, 0, 0,
+            0, 0, 0, 1, 0, 0, 0, 5,
+        ];
+        assert_eq!(expected, buf);
+
+        let mut buf = Vec::new();
+        request().write_into(1, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            2, b'c',
+            // voter id
+            0, 0, 0, 2,
+            2, 2, b'm',
+            2, 0, 0, 0, 0,
+            // voter directory id
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2,
+            0, 0, 0, 1, 0, 0, 0, 5,
+            0, 0,
+            // leader endpoints
+            2, 2, b'C', 2, b'h', 0x23, 0x85, 0,
+            0,
+        ];
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let api = RaftApiKeys::BeginQuorumEpoch;
+        for version in api.oldest_version()..=api.latest_version() {
+            let mut expected = request();
+            if version < 1 {
+                expected.voter_id = -1;
+                expected.topics[0].partitions[0].voter_directory_id = Uuid::ZERO;
+                expected.leader_endpoints.clear();
+            }
+            let mut buf = Vec::new();
+            request().write_into(version, &mut buf);
+            assert_eq!(
+                Ok(expected),
+                BeginQuorumEpochRequest::parse(version, &buf),
+                "{version}"
+            );
+        }
+    }
+
+    fn response() -> BeginQuorumEpochResponse {
+        BeginQuorumEpochResponse {
+            error: Errors::None,
+            topics: vec![BeginQuorumEpochTopicResponse {
+                topic_name: "m".to_string(),
+                partitions: vec![BeginQuorumEpochPartitionResponse {
+                    partition_index: 0,
+                    error: Errors::FencedLeaderEpoch,
+                    leader_id: 1,
+                    leader_epoch: 6,
+                }],
+            }],
+            node_endpoints: vec![NodeEndpoint {
+                node_id: 1,
+                host: "h".to_string(),
+                port: 9093,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_response() {
+        let mut buf = Vec::new();
+        response().write_to(7, 0, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            // header, without tagged fields
+            0, 0, 0, 7,
+            0, 0,
+            0, 0, 0, 1, 0, 1, b'm',
+            0, 0, 0, 1, 0, 0, 0, 0,
+            // FENCED_LEADER_EPOCH
+            0, 74,
+            0, 0, 0, 1, 0, 0, 0, 6,
+        ];
+        assert_eq!(expected, buf);
+
+        let mut buf = Vec::new();
+        response().write_to(7, 1, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            0, 0, 0, 7, 0,
+            0, 0,
+            2, 2, b'm',
+            2, 0, 0, 0, 0, 0, 74,
+            0, 0, 0, 1, 0, 0, 0, 6,
+            0, 0,
+            // the node endpoints, tag 0 of 10 bytes
+            1, 0, 10,
+            2, 0, 0, 0, 1, 2, b'h', 0x23, 0x85, 0,
+        ];
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let api = RaftApiKeys::BeginQuorumEpoch;
+        for version in api.oldest_version()..=api.latest_version() {
+            let mut expected = response();
+            if version < 1 {
+                expected.node_endpoints.clear();
+            }
+            let mut buf = Vec::new();
+            response().write_into(7, version, &mut buf);
+            let header_size = if api.is_flexible(version) { 5 } else { 4 };
+            assert_eq!(
+                Ok(expected),
+                BeginQuorumEpochResponse::parse(version, &buf[header_size..]),
+                "{version}"
+            );
+            assert_eq!(buf.len(), response().size_in_bytes(version));
+        }
+    }
+}
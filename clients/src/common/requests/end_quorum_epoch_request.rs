@@ -0,0 +1,338 @@
+//! EndQuorumEpoch: a leader resigning, e.g. when it shuts down, tells the voters of the quorum
+//! to start an election, preferably of the candidates it names.
+
+use crate::common::protocol::errors::Errors;
+use crate::common::protocol::raft_api_keys::RaftApiKeys;
+use crate::common::requests::message_reader::{MessageReader, Result};
+use crate::common::requests::raft_messages::{
+    LeaderEndpoint, NodeEndpoint, check_version, node_endpoints_field, read_leader_endpoints,
+    read_node_endpoints, write_leader_endpoints, write_response_header,
+};
+use crate::common::requests::response_writer::{ResponseSink, ResponseWriter, WritableResponse};
+use crate::common::uuid::Uuid;
+
+/// A voter the resigning leader prefers as its successor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreferredCandidate {
+    pub candidate_id: i32,
+    /// Sent from version 1.
+    pub candidate_directory_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndQuorumEpochPartitionRequest {
+    pub partition_index: i32,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    /// In order of preference. Version 0 sends their ids only, as `PreferredSuccessors`.
+    pub preferred_candidates: Vec<PreferredCandidate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndQuorumEpochTopicRequest {
+    pub topic_name: String,
+    pub partitions: Vec<EndQuorumEpochPartitionRequest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndQuorumEpochRequest {
+    pub cluster_id: Option<String>,
+    pub topics: Vec<EndQuorumEpochTopicRequest>,
+    /// The endpoints of the leader, sent from version 1.
+    pub leader_endpoints: Vec<LeaderEndpoint>,
+}
+
+impl EndQuorumEpochRequest {
+    /// Writes the body of the request at `version`.
+    pub fn write_into<S: ResponseSink>(&self, version: i16, sink: &mut S) {
+        let flexible = RaftApiKeys::EndQuorumEpoch.is_flexible(version);
+        let mut writer = ResponseWriter::new(sink, flexible);
+        writer.nullable_string(self.cluster_id.as_deref());
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            writer.string(&topic.topic_name);
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                writer.i32(partition.partition_index);
+                writer.i32(partition.leader_id);
+                writer.i32(partition.leader_epoch);
+                let candidates = &partition.preferred_candidates;
+                writer.len(Some(candidates.len()), false);
+                for candidate in candidates {
+                    writer.i32(candidate.candidate_id);
+                    if version >= 1 {
+                        writer.bytes(&candidate.candidate_directory_id.to_bytes());
+                        writer.tagged_fields();
+                    }
+                }
+                writer.tagged_fields();
+            }
+            writer.tagged_fields();
+        }
+        if version >= 1 {
+            write_leader_endpoints(&mut writer, &self.leader_endpoints);
+        }
+        writer.tagged_fields();
+    }
+
+    /// Parses the body of a request of `version`.
+    pub fn parse(version: i16, body: &[u8]) -> Result<Self> {
+        let api = RaftApiKeys::EndQuorumEpoch;
+        check_version(api, version)?;
+        let mut reader = MessageReader::new(body, api.is_flexible(version));
+        let cluster_id = reader.nullable_string("cluster id")?;
+        let topics = reader.elements("topics", |reader| {
+            let topic_name = reader.string("topic name")?;
+            let partitions = reader.elements("partitions", |reader| {
+                let partition_index = reader.i32("partition index")?;
+                let leader_id = reader.i32("leader id")?;
+                let leader_epoch = reader.i32("leader epoch")?;
+                let preferred_candidates = reader.elements("preferred candidates", |reader| {
+                    let candidate_id = reader.i32("candidate id")?;
+                    if version < 1 {
+                        return Ok(PreferredCandidate {
+                            candidate_id,
+                            candidate_directory_id: Uuid::ZERO,
+                        });
+                    }
+                    let candidate_directory_id = reader.uuid("candidate directory id")?;
+                    reader.tagged_fields()?;
+                    Ok(PreferredCandidate {
+                        candidate_id,
+                        candidate_directory_id,
+                    })
+                })?;
+                reader.tagged_fields()?;
+                Ok(EndQuorumEpochPartitionRequest {
+                    partition_index,
+                    leader_id,
+                    leader_epoch,
+                    preferred_candidates,
+                })
+            })?;
+            reader.tagged_fields()?;
+            Ok(EndQuorumEpochTopicRequest {
+                topic_name,
+                partitions,
+            })
+        })?;
+        let leader_endpoints = match version >= 1 {
+            true => read_leader_endpoints(&mut reader)?,
+            false => vec![],
+        };
+        reader.tagged_fields()?;
+        reader.finish()?;
+        Ok(Self {
+            cluster_id,
+            topics,
+            leader_endpoints,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndQuorumEpochPartitionResponse {
+    pub partition_index: i32,
+    pub error: Errors,
+    /// The leader the voter knows of, or -1.
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndQuorumEpochTopicResponse {
+    pub topic_name: String,
+    pub partitions: Vec<EndQuorumEpochPartitionResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndQuorumEpochResponse {
+    pub error: Errors,
+    pub topics: Vec<EndQuorumEpochTopicResponse>,
+    /// The endpoints of the leaders the response names, sent from version 1.
+    pub node_endpoints: Vec<NodeEndpoint>,
+}
+
+impl WritableResponse for EndQuorumEpochResponse {
+    fn write_into<S: ResponseSink>(&self, correlation_id: i32, version: i16, sink: &mut S) {
+        let api = RaftApiKeys::EndQuorumEpoch;
+        let mut writer = ResponseWriter::new(sink, api.is_flexible(version));
+        write_response_header(&mut writer, api, version, correlation_id);
+        writer.i16(self.error.code());
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            writer.string(&topic.topic_name);
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                writer.i32(partition.partition_index);
+                writer.i16(partition.error.code());
+                writer.i32(partition.leader_id);
+                writer.i32(partition.leader_epoch);
+                writer.tagged_fields();
+            }
+            writer.tagged_fields();
+        }
+        writer.tagged_fields_with(&node_endpoints_field(version, &self.node_endpoints));
+    }
+}
+
+impl EndQuorumEpochResponse {
+    /// Parses the body of a response of `version`, after its header.
+    pub fn parse(version: i16, body: &[u8]) -> Result<Self> {
+        let api = RaftApiKeys::EndQuorumEpoch;
+        check_version(api, version)?;
+        let mut reader = MessageReader::new(body, api.is_flexible(version));
+        let error = reader.error("error code")?;
+        let topics = reader.elements("topics", |reader| {
+            let topic_name = reader.string("topic name")?;
+            let partitions = reader.elements("partitions", |reader| {
+                let partition = EndQuorumEpochPartitionResponse {
+                    partition_index: reader.i32("partition index")?,
+                    error: reader.error("error code")?,
+                    leader_id: reader.i32("leader id")?,
+                    leader_epoch: reader.i32("leader epoch")?,
+                };
+                reader.tagged_fields()?;
+                Ok(partition)
+            })?;
+            reader.tagged_fields()?;
+            Ok(EndQuorumEpochTopicResponse {
+                topic_name,
+                partitions,
+            })
+        })?;
+        let node_endpoints = read_node_endpoints(version, &reader.tagged_fields()?)?;
+        reader.finish()?;
+        Ok(Self {
+            error,
+            topics,
+            node_endpoints,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> EndQuorumEpochRequest {
+        EndQuorumEpochRequest {
+            cluster_id: Some("c".to_string()),
+            topics: vec![EndQuorumEpochTopicRequest {
+                topic_name: "m".to_string(),
+                partitions: vec![EndQuorumEpochPartitionRequest {
+                    partition_index: 0,
+                    leader_id: 1,
+                    leader_epoch: 5,
+                    preferred_candidates: vec![PreferredCandidate {
+                        candidate_id: 3,
+                        candidate_directory_id: Uuid::new(0, 3),
+                    }],
+                }],
+            }],
+            leader_endpoints: vec![LeaderEndpoint {
+                name: "C".to_string(),
+                host: "h".to_string(),
+                port: 9093,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_request() {
+        let mut buf = Vec::new();
+        request().write_into(0, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            0, 1, b'c',
+            0, 0, 0, 1, 0, 1, b'm',
+            0, 0, 0, 1, 0, 0, 0, 0,
+            0, 0, 0, 1, 0, 0, 0, 5,
+            // preferred successors
+            0, 0, 0, 1, 0, 0, 0, 3,
+        ];
+        assert_eq!(expected, buf);
+
+        let mut buf = Vec::new();
+        request().write_into(1, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            2, b'c',
+            2, 2, b'm',
+            2, 0, 0, 0, 0,
+            0, 0, 0, 1, 0, 0, 0, 5,
+            // preferred candidates
+            2, 0, 0, 0, 3,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3,
+            0,
+            0, 0,
+            // leader endpoints
+            2, 2, b'C', 2, b'h', 0x23, 0x85, 0,
+            0,
+        ];
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let api = RaftApiKeys::EndQuorumEpoch;
+        for version in api.oldest_version()..=api.latest_version() {
+            let mut expected = request();
+            if version < 1 {
+                expected.topics[0].partitions[0].preferred_candidates[0].candidate_directory_id =
+                    Uuid::ZERO;
+                expected.leader_endpoints.clear();
+            }
+            let mut buf = Vec::new();
+            request().write_into(version, &mut buf);
+            assert_eq!(
+                Ok(expected),
+                EndQuorumEpochRequest::parse(version, &buf),
+                "{version}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let response = EndQuorumEpochResponse {
+            error: Errors::None,
+            topics: vec![EndQuorumEpochTopicResponse {
+                topic_name: "m".to_string(),
+                partitions: vec![EndQuorumEpochPartitionResponse {
+                    partition_index: 0,
+                    error: Errors::None,
+                    leader_id: -1,
+                    leader_epoch: 5,
+                }],
+            }],
+            node_endpoints: vec![],
+        };
+        let mut buf = Vec::new();
+        response.write_to(7, 1, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            0, 0, 0, 7, 0,
+            0, 0,
+            2, 2, b'm',
+            2, 0, 0, 0, 0, 0, 0,
+            0xff, 0xff, 0xff, 0xff, 0, 0, 0, 5,
+            0, 0,
+            // no node endpoints
+            0,
+        ];
+        assert_eq!(expected, buf);
+
+        let api = RaftApiKeys::EndQuorumEpoch;
+        for version in api.oldest_version()..=api.latest_version() {
+            let mut buf = Vec::new();
+            response.write_into(7, version, &mut buf);
+            let header_size = if api.is_flexible(version) { 5 } else { 4 };
+            assert_eq!(
+                Ok(response.clone()),
+                EndQuorumEpochResponse::parse(version, &buf[header_size..]),
+                "{version}"
+            );
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! The response to a Fetch request: the record batches read from each partition asked for,
+//! with the offsets a consumer needs to carry on fetching.
+
+use crate::common::protocol::api_keys::ApiKeys;
+use crate::common::protocol::errors::Errors;
+use crate::common::record::record_batch::RecordBatch;
+use crate::common::requests::abstract_response::AbstractResponse;
+use crate::common::requests::response_writer::{ResponseSink, ResponseWriter, WritableResponse};
+use crate::common::uuid::Uuid;
+
+/// The preferred read replica of a partition read from its leader.
+pub const NO_PREFERRED_READ_REPLICA: i32 = -1;
+
+/// The session id of a response outside of a fetch session.
+pub const INVALID_SESSION_ID: i32 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchResponseAbortedTransaction {
+    pub producer_id: i64,
+    pub first_offset: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchResponsePartition {
+    pub partition_index: i32,
+    pub error: Errors,
+    pub high_watermark: i64,
+    pub last_stable_offset: i64,
+    pub log_start_offset: i64,
+    /// `None` for a read_uncommitted fetch.
+    pub aborted_transactions: Option<Vec<FetchResponseAbortedTransaction>>,
+    pub preferred_read_replica: i32,
+    pub records: Vec<RecordBatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchResponseTopic {
+    /// The name of the topic, sent up to version 12.
+    pub topic: String,
+    /// The id of the topic, sent from version 13 on.
+    pub topic_id: Uuid,
+    pub partitions: Vec<FetchResponsePartition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchResponse {
+    pub throttle_time_ms: i32,
+    pub error: Errors,
+    pub session_id: i32,
+    pub responses: Vec<FetchResponseTopic>,
+}
+
+/// Sized up front: the record batches making up most of a response are counted from their
+/// lengths, while writing them copies every byte, so the buffer is allocated once.
+impl WritableResponse for FetchResponse {
+    fn write_into<S: ResponseSink>(&self, correlation_id: i32, version: i16, sink: &mut S) {
+        let api = ApiKeys::Fetch;
+        let mut writer = ResponseWriter::new(sink, api.is_flexible(version));
+        writer.i32(correlation_id);
+        if api.response_header_version(version) >= 1 {
+            writer.tagged_fields();
+        }
+        writer.i32(self.throttle_time_ms);
+        if version >= 7 {
+            writer.i16(self.error.code());
+            writer.i32(self.session_id);
+        }
+        writer.len(Some(self.responses.len()), false);
+        for topic in &self.responses {
+            if version >= 13 {
+                writer.bytes(&topic.topic_id.to_bytes());
+            } else {
+                writer.string(&topic.topic);
+            }
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                write_partition(&mut writer, version, partition);
+            }
+            writer.tagged_fields();
+        }
+        writer.tagged_fields();
+    }
+
+    fn size_hint(&self, version: i16) -> Option<usize> {
+        Some(self.size_in_bytes(version))
+    }
+}
+
+fn write_partition<S: ResponseSink>(
+    writer: &mut ResponseWriter<'_, S>,
+    version: i16,
+    partition: &FetchResponsePartition,
+) {
+    writer.i32(partition.partition_index);
+    writer.i16(partition.error.code());
+    writer.i64(partition.high_watermark);
+    writer.i64(partition.last_stable_offset);
+    if version >= 5 {
+        writer.i64(partition.log_start_offset);
+    }
+    let aborted_transactions = partition.aborted_transactions.as_deref();
+    writer.len(aborted_transactions.map(<[_]>::len), false);
+    for aborted in aborted_transactions.unwrap_or_default() {
+        writer.i64(aborted.producer_id);
+        writer.i64(aborted.first_offset);
+        writer.tagged_fields();
+    }
+    if version >= 11 {
+        writer.i32(partition.preferred_read_replica);
+    }
+    writer.records(&partition.records);
+    writer.tagged_fields();
+}
+
+impl AbstractResponse for FetchResponse {
+    fn throttle_time_ms(&self) -> i32 {
+        self.throttle_time_ms
+    }
+
+    fn maybe_set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+        self.throttle_time_ms = throttle_time_ms;
+    }
+
+    fn should_client_throttle(&self, version: i16) -> bool {
+        version >= 8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::record::record_batch::Record;
+
+    fn response(records: Vec<RecordBatch>) -> FetchResponse {
+        FetchResponse {
+            throttle_time_ms: 0,
+            error: Errors::None,
+            session_id: INVALID_SESSION_ID,
+            responses: vec![FetchResponseTopic {
+                topic: "t".to_string(),
+                topic_id: Uuid::new(0, 7),
+                partitions: vec![FetchResponsePartition {
+                    partition_index: 0,
+                    error: Errors::None,
+                    high_watermark: 3,
+                    last_stable_offset: 2,
+                    log_start_offset: 0,
+                    aborted_transactions: Some(vec![FetchResponseAbortedTransaction {
+                        producer_id: 5,
+                        first_offset: 1,
+                    }]),
+                    preferred_read_replica: NO_PREFERRED_READ_REPLICA,
+                    records,
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_v4() {
+        let mut buf = Vec::new();
+        response(vec![]).write_to(7, 4, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            0, 0, 0, 7,
+            // throttle time
+            0, 0, 0, 0,
+            // topics
+            0, 0, 0, 1, 0, 1, b't',
+            0, 0, 0, 1, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 2,
+            // aborted transactions
+            0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 1,
+            // records
+            0, 0, 0, 0,
+        ];
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_records_are_written_as_encoded() {
+        let batch = RecordBatch::new(10, vec![Record::new(0, None, Some(b"v"))]);
+        let encoded = batch.encode();
+        let response = response(vec![batch.clone(), batch]);
+        for version in [4, ApiKeys::Fetch.latest_version()] {
+            let mut buf = Vec::new();
+            response.write_to(1, version, &mut buf);
+            let records = [encoded.clone(), encoded.clone()].concat();
+            // The records are followed by the tagged fields of the partition, topic and response.
+            let tagged_fields = if version >= 12 { 3 } else { 0 };
+            assert_eq!(
+                records,
+                buf[buf.len() - tagged_fields - records.len()..buf.len() - tagged_fields]
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_hint_is_exact() {
+        let batch = RecordBatch::new(0, vec![Record::new(0, None, Some(b"v"))]);
+        let response = response(vec![batch]);
+        for version in ApiKeys::Fetch.oldest_version()..=ApiKeys::Fetch.latest_version() {
+            let mut buf = Vec::new();
+            response.write_into(1, version, &mut buf);
+            assert_eq!(
+                Some(buf.len()),
+                response.size_hint(version),
+                "version {version}"
+            );
+        }
+    }
+}
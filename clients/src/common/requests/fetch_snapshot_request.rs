@@ -0,0 +1,432 @@
+//! FetchSnapshot: a replica too far behind the log start offset of the leader fetches a
+//! snapshot of the metadata log, chunk by chunk.
+
+use crate::common::protocol::errors::Errors;
+use crate::common::protocol::raft_api_keys::RaftApiKeys;
+use crate::common::requests::message_reader::{MessageError, MessageReader, Result};
+use crate::common::requests::raft_messages::{
+    NodeEndpoint, check_version, node_endpoints_field, read_node_endpoints, write_response_header,
+};
+use crate::common::requests::response_writer::{ResponseSink, ResponseWriter, WritableResponse};
+use crate::common::uuid::Uuid;
+
+/// The tag of the cluster id of a request.
+const CLUSTER_ID_TAG: u32 = 0;
+/// The tag of the directory id of a partition of a request, from version 1.
+const REPLICA_DIRECTORY_ID_TAG: u32 = 0;
+/// The tag of the current leader of a partition of a response.
+const CURRENT_LEADER_TAG: u32 = 0;
+
+/// The end offset and epoch identifying a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotId {
+    pub end_offset: i64,
+    pub epoch: i32,
+}
+
+impl SnapshotId {
+    fn write<S: ResponseSink>(&self, writer: &mut ResponseWriter<'_, S>) {
+        writer.i64(self.end_offset);
+        writer.i32(self.epoch);
+        writer.tagged_fields();
+    }
+
+    fn read(reader: &mut MessageReader<'_>) -> Result<Self> {
+        let snapshot_id = Self {
+            end_offset: reader.i64("snapshot end offset")?,
+            epoch: reader.i32("snapshot epoch")?,
+        };
+        reader.tagged_fields()?;
+        Ok(snapshot_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchSnapshotPartitionRequest {
+    pub partition: i32,
+    pub current_leader_epoch: i32,
+    pub snapshot_id: SnapshotId,
+    /// The byte position in the snapshot to fetch from.
+    pub position: i64,
+    /// Sent from version 1, as a tagged field, unless it is [Uuid::ZERO].
+    pub replica_directory_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchSnapshotTopicRequest {
+    pub name: String,
+    pub partitions: Vec<FetchSnapshotPartitionRequest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchSnapshotRequest {
+    /// Sent as a tagged field, unless it is `None`.
+    pub cluster_id: Option<String>,
+    pub replica_id: i32,
+    pub max_bytes: i32,
+    pub topics: Vec<FetchSnapshotTopicRequest>,
+}
+
+impl FetchSnapshotRequest {
+    /// Writes the body of the request at `version`.
+    pub fn write_into<S: ResponseSink>(&self, version: i16, sink: &mut S) {
+        let mut writer = ResponseWriter::new(sink, true);
+        writer.i32(self.replica_id);
+        writer.i32(self.max_bytes);
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            writer.string(&topic.name);
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                writer.i32(partition.partition);
+                writer.i32(partition.current_leader_epoch);
+                partition.snapshot_id.write(&mut writer);
+                writer.i64(partition.position);
+                let mut tagged = vec![];
+                if version >= 1 && partition.replica_directory_id != Uuid::ZERO {
+                    let value = partition.replica_directory_id.to_bytes().to_vec();
+                    tagged.push((REPLICA_DIRECTORY_ID_TAG, value));
+                }
+                writer.tagged_fields_with(&tagged);
+            }
+            writer.tagged_fields();
+        }
+        let mut tagged = vec![];
+        if let Some(cluster_id) = &self.cluster_id {
+            let mut value = Vec::new();
+            ResponseWriter::new(&mut value, true).string(cluster_id);
+            tagged.push((CLUSTER_ID_TAG, value));
+        }
+        writer.tagged_fields_with(&tagged);
+    }
+
+    /// Parses the body of a request of `version`.
+    pub fn parse(version: i16, body: &[u8]) -> Result<Self> {
+        check_version(RaftApiKeys::FetchSnapshot, version)?;
+        let mut reader = MessageReader::new(body, true);
+        let replica_id = reader.i32("replica id")?;
+        let max_bytes = reader.i32("max bytes")?;
+        let topics = reader.elements("topics", |reader| {
+            let name = reader.string("topic name")?;
+            let partitions = reader.elements("partitions", |reader| {
+                let partition = reader.i32("partition")?;
+                let current_leader_epoch = reader.i32("current leader epoch")?;
+                let snapshot_id = SnapshotId::read(reader)?;
+                let position = reader.i64("position")?;
+                let mut replica_directory_id = Uuid::ZERO;
+                for (tag, value) in reader.tagged_fields()? {
+                    if version >= 1 && tag == REPLICA_DIRECTORY_ID_TAG {
+                        replica_directory_id =
+                            MessageReader::new(value, true).uuid("replica directory id")?;
+                    }
+                }
+                Ok(FetchSnapshotPartitionRequest {
+                    partition,
+                    current_leader_epoch,
+                    snapshot_id,
+                    position,
+                    replica_directory_id,
+                })
+            })?;
+            reader.tagged_fields()?;
+            Ok(FetchSnapshotTopicRequest { name, partitions })
+        })?;
+        let mut cluster_id = None;
+        for (tag, value) in reader.tagged_fields()? {
+            if tag == CLUSTER_ID_TAG {
+                cluster_id = MessageReader::new(value, true).nullable_string("cluster id")?;
+            }
+        }
+        reader.finish()?;
+        Ok(Self {
+            cluster_id,
+            replica_id,
+            max_bytes,
+            topics,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchSnapshotPartitionResponse {
+    pub index: i32,
+    pub error: Errors,
+    pub snapshot_id: SnapshotId,
+    /// The leader the replica knows of, sent as a tagged field unless both are -1.
+    pub current_leader_id: i32,
+    pub current_leader_epoch: i32,
+    /// The total size of the snapshot.
+    pub size: i64,
+    /// The byte position of `unaligned_records` in the snapshot.
+    pub position: i64,
+    /// A chunk of the snapshot, not aligned on record batches.
+    pub unaligned_records: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchSnapshotTopicResponse {
+    pub name: String,
+    pub partitions: Vec<FetchSnapshotPartitionResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchSnapshotResponse {
+    pub throttle_time_ms: i32,
+    pub error: Errors,
+    pub topics: Vec<FetchSnapshotTopicResponse>,
+    /// The endpoints of the leaders the response names, sent from version 1.
+    pub node_endpoints: Vec<NodeEndpoint>,
+}
+
+impl WritableResponse for FetchSnapshotResponse {
+    fn write_into<S: ResponseSink>(&self, correlation_id: i32, version: i16, sink: &mut S) {
+        let api = RaftApiKeys::FetchSnapshot;
+        let mut writer = ResponseWriter::new(sink, true);
+        write_response_header(&mut writer, api, version, correlation_id);
+        writer.i32(self.throttle_time_ms);
+        writer.i16(self.error.code());
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            writer.string(&topic.name);
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                writer.i32(partition.index);
+                writer.i16(partition.error.code());
+                partition.snapshot_id.write(&mut writer);
+                writer.i64(partition.size);
+                writer.i64(partition.position);
+                writer.len(Some(partition.unaligned_records.len()), false);
+                writer.bytes(&partition.unaligned_records);
+                let mut tagged = vec![];
+                if partition.current_leader_id != -1 || partition.current_leader_epoch != -1 {
+                    let mut value = Vec::new();
+                    let mut leader = ResponseWriter::new(&mut value, true);
+                    leader.i32(partition.current_leader_id);
+                    leader.i32(partition.current_leader_epoch);
+                    leader.tagged_fields();
+                    tagged.push((CURRENT_LEADER_TAG, value));
+                }
+                writer.tagged_fields_with(&tagged);
+            }
+            writer.tagged_fields();
+        }
+        writer.tagged_fields_with(&node_endpoints_field(version, &self.node_endpoints));
+    }
+}
+
+impl FetchSnapshotResponse {
+    /// Parses the body of a response of `version`, after its header.
+    pub fn parse(version: i16, body: &[u8]) -> Result<Self> {
+        check_version(RaftApiKeys::FetchSnapshot, version)?;
+        let mut reader = MessageReader::new(body, true);
+        let throttle_time_ms = reader.i32("throttle time")?;
+        let error = reader.error("error code")?;
+        let topics = reader.elements("topics", |reader| {
+            let name = reader.string("topic name")?;
+            let partitions = reader.elements("partitions", |reader| {
+                let index = reader.i32("partition index")?;
+                let error = reader.error("error code")?;
+                let snapshot_id = SnapshotId::read(reader)?;
+                let size = reader.i64("size")?;
+                let position = reader.i64("position")?;
+                let unaligned_records = reader.bytes("unaligned records")?.to_vec();
+                let (mut current_leader_id, mut current_leader_epoch) = (-1, -1);
+                for (tag, value) in reader.tagged_fields()? {
+                    if tag == CURRENT_LEADER_TAG {
+                        let mut leader = MessageReader::new(value, true);
+                        current_leader_id = leader.i32("current leader id")?;
+                        current_leader_epoch = leader.i32("current leader epoch")?;
+                        leader.tagged_fields()?;
+                        leader.finish().map_err(|_| {
+                            MessageError::Malformed("the current leader is too long".to_string())
+                        })?;
+                    }
+                }
+                Ok(FetchSnapshotPartitionResponse {
+                    index,
+                    error,
+                    snapshot_id,
+                    current_leader_id,
+                    current_leader_epoch,
+                    size,
+                    position,
+                    unaligned_records,
+                })
+            })?;
+            reader.tagged_fields()?;
+            Ok(FetchSnapshotTopicResponse { name, partitions })
+        })?;
+        let node_endpoints = read_node_endpoints(version, &reader.tagged_fields()?)?;
+        reader.finish()?;
+        Ok(Self {
+            throttle_time_ms,
+            error,
+            topics,
+            node_endpoints,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> FetchSnapshotRequest {
+        FetchSnapshotRequest {
+            cluster_id: Some("c".to_string()),
+            replica_id: 2,
+            max_bytes: 1024,
+            topics: vec![FetchSnapshotTopicRequest {
+                name: "m".to_string(),
+                partitions: vec![FetchSnapshotPartitionRequest {
+                    partition: 0,
+                    current_leader_epoch: 5,
+                    snapshot_id: SnapshotId {
+                        end_offset: 100,
+                        epoch: 4,
+                    },
+                    position: 0,
+                    replica_directory_id: Uuid::new(0, 2),
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_request() {
+        let mut buf = Vec::new();
+        request().write_into(1, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            // replica id, max bytes
+            0, 0, 0, 2, 0, 0, 4, 0,
+            2, 2, b'm',
+            2, 0, 0, 0, 0, 0, 0, 0, 5,
+            // snapshot id
+            0, 0, 0, 0, 0, 0, 0, 100, 0, 0, 0, 4, 0,
+            // position
+            0, 0, 0, 0, 0, 0, 0, 0,
+            // the replica directory id, tag 0 of 16 bytes
+            1, 0, 16,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2,
+            0,
+            // the cluster id, tag 0 of 2 bytes
+            1, 0, 2, 2, b'c',
+        ];
+        assert_eq!(expected, buf);
+
+        let mut request = request();
+        request.cluster_id = None;
+        let mut buf = Vec::new();
+        request.write_into(0, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            0, 0, 0, 2, 0, 0, 4, 0,
+            2, 2, b'm',
+            2, 0, 0, 0, 0, 0, 0, 0, 5,
+            0, 0, 0, 0, 0, 0, 0, 100, 0, 0, 0, 4, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+            // no tagged fields
+            0, 0, 0,
+        ];
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let api = RaftApiKeys::FetchSnapshot;
+        for version in api.oldest_version()..=api.latest_version() {
+            let mut expected = request();
+            if version < 1 {
+                expected.topics[0].partitions[0].replica_directory_id = Uuid::ZERO;
+            }
+            let mut buf = Vec::new();
+            request().write_into(version, &mut buf);
+            assert_eq!(
+                Ok(expected),
+                FetchSnapshotRequest::parse(version, &buf),
+                "{version}"
+            );
+        }
+    }
+
+    fn response() -> FetchSnapshotResponse {
+        FetchSnapshotResponse {
+            throttle_time_ms: 0,
+            error: Errors::None,
+            topics: vec![FetchSnapshotTopicResponse {
+                name: "m".to_string(),
+                partitions: vec![FetchSnapshotPartitionResponse {
+                    index: 0,
+                    error: Errors::None,
+                    snapshot_id: SnapshotId {
+                        end_offset: 100,
+                        epoch: 4,
+                    },
+                    current_leader_id: 1,
+                    current_leader_epoch: 5,
+                    size: 3,
+                    position: 0,
+                    unaligned_records: vec![7, 8, 9],
+                }],
+            }],
+            node_endpoints: vec![NodeEndpoint {
+                node_id: 1,
+                host: "h".to_string(),
+                port: 9093,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_response() {
+        let mut buf = Vec::new();
+        response().write_to(7, 1, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            0, 0, 0, 7, 0,
+            // throttle time, error code
+            0, 0, 0, 0, 0, 0,
+            2, 2, b'm',
+            2, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 100, 0, 0, 0, 4, 0,
+            // size, position
+            0, 0, 0, 0, 0, 0, 0, 3,
+            0, 0, 0, 0, 0, 0, 0, 0,
+            // unaligned records
+            4, 7, 8, 9,
+            // the current leader, tag 0 of 9 bytes
+            1, 0, 9, 0, 0, 0, 1, 0, 0, 0, 5, 0,
+            0,
+            // the node endpoints, tag 0 of 10 bytes
+            1, 0, 10,
+            2, 0, 0, 0, 1, 2, b'h', 0x23, 0x85, 0,
+        ];
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let api = RaftApiKeys::FetchSnapshot;
+        for version in api.oldest_version()..=api.latest_version() {
+            for current_leader_id in [1, -1] {
+                let mut response = response();
+                let partition = &mut response.topics[0].partitions[0];
+                partition.current_leader_id = current_leader_id;
+                partition.current_leader_epoch = current_leader_id.min(5);
+                let mut expected = response.clone();
+                if version < 1 {
+                    expected.node_endpoints.clear();
+                }
+                let mut buf = Vec::new();
+                response.write_into(7, version, &mut buf);
+                assert_eq!(
+                    Ok(expected),
+                    FetchSnapshotResponse::parse(version, &buf[5..]),
+                    "{version}"
+                );
+                assert_eq!(buf.len(), response.size_in_bytes(version));
+            }
+        }
+    }
+}
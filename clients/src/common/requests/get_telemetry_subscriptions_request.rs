@@ -0,0 +1,45 @@
+//! GetTelemetrySubscriptions (KIP-714): a client asks which of its metrics the broker wants
+//! pushed, and how often.
+
+use crate::common::protocol::errors::Errors;
+use crate::common::requests::abstract_response::AbstractResponse;
+use crate::common::uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetTelemetrySubscriptionsRequest {
+    /// The id the broker assigned to the client, or [Uuid::ZERO] on the first request.
+    pub client_instance_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetTelemetrySubscriptionsResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: Errors,
+    pub client_instance_id: Uuid,
+    /// Identifies the subscription the client must push its metrics for.
+    pub subscription_id: i32,
+    /// The compression types the broker accepts for pushed metrics, by preference.
+    pub accepted_compression_types: Vec<i8>,
+    pub push_interval_ms: i32,
+    /// The largest metrics payload the broker accepts.
+    pub telemetry_max_bytes: i32,
+    /// Whether the client must push deltas rather than cumulative values.
+    pub delta_temporality: bool,
+    /// The prefixes of the metric names to push. An empty list means no metrics, a list
+    /// holding an empty string all metrics.
+    pub requested_metrics: Vec<String>,
+}
+
+impl AbstractResponse for GetTelemetrySubscriptionsResponse {
+    fn throttle_time_ms(&self) -> i32 {
+        self.throttle_time_ms
+    }
+
+    fn maybe_set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+        self.throttle_time_ms = throttle_time_ms;
+    }
+
+    fn should_client_throttle(&self, _version: i16) -> bool {
+        true
+    }
+}
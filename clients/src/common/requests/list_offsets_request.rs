@@ -0,0 +1,16 @@
+//! The special timestamps a ListOffsets request can ask for instead of a real timestamp.
+
+/// The offset of the next record to be appended, i.e. the log end offset.
+pub const LATEST_TIMESTAMP: i64 = -1;
+
+/// The first offset of the log, including the segments moved to remote storage.
+pub const EARLIEST_TIMESTAMP: i64 = -2;
+
+/// The offset of the record with the highest timestamp.
+pub const MAX_TIMESTAMP: i64 = -3;
+
+/// The first offset of the log still stored on the broker's local disk.
+pub const EARLIEST_LOCAL_TIMESTAMP: i64 = -4;
+
+/// The highest offset copied to remote storage.
+pub const LATEST_TIERED_TIMESTAMP: i64 = -5;
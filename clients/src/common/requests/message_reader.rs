@@ -0,0 +1,213 @@
+//! Reads the fields of requests and responses in the encoding of their version, the
+//! counterpart of [ResponseWriter](super::response_writer::ResponseWriter).
+
+use crate::common::protocol::errors::Errors;
+use crate::common::utils::byte_utils::read_unsigned_varint;
+use crate::common::uuid::Uuid;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MessageError {
+    #[error("Unsupported version {1} of {0}")]
+    UnsupportedVersion(&'static str, i16),
+
+    #[error("The message ends before its {0}")]
+    Truncated(&'static str),
+
+    #[error("The message is malformed: {0}")]
+    Malformed(String),
+}
+
+pub type Result<T> = std::result::Result<T, MessageError>;
+
+/// Reads fields from a buffer, with the lengths and tagged fields of the flexible versions if
+/// `flexible`.
+#[derive(Debug)]
+pub struct MessageReader<'a> {
+    buf: &'a [u8],
+    flexible: bool,
+}
+
+impl<'a> MessageReader<'a> {
+    pub fn new(buf: &'a [u8], flexible: bool) -> Self {
+        Self { buf, flexible }
+    }
+
+    pub fn is_flexible(&self) -> bool {
+        self.flexible
+    }
+
+    fn take(&mut self, len: usize, field: &'static str) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(MessageError::Truncated(field));
+        }
+        let (taken, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N]> {
+        Ok(self.take(N, field)?.try_into().expect("N bytes"))
+    }
+
+    pub fn bool(&mut self, field: &'static str) -> Result<bool> {
+        Ok(self.array::<1>(field)?[0] != 0)
+    }
+
+    pub fn i16(&mut self, field: &'static str) -> Result<i16> {
+        self.array(field).map(i16::from_be_bytes)
+    }
+
+    pub fn u16(&mut self, field: &'static str) -> Result<u16> {
+        self.array(field).map(u16::from_be_bytes)
+    }
+
+    pub fn i32(&mut self, field: &'static str) -> Result<i32> {
+        self.array(field).map(i32::from_be_bytes)
+    }
+
+    pub fn i64(&mut self, field: &'static str) -> Result<i64> {
+        self.array(field).map(i64::from_be_bytes)
+    }
+
+    pub fn uuid(&mut self, field: &'static str) -> Result<Uuid> {
+        self.array(field).map(Uuid::from_bytes)
+    }
+
+    /// An error code.
+    pub fn error(&mut self, field: &'static str) -> Result<Errors> {
+        self.i16(field).map(Errors::for_code)
+    }
+
+    fn unsigned_varint(&mut self, field: &'static str) -> Result<u32> {
+        read_unsigned_varint(&mut self.buf).map_err(|_| MessageError::Truncated(field))
+    }
+
+    /// The length of a nullable field, `None` for null. Classic fields store it as an `i16`
+    /// or an `i32`, flexible ones as an unsigned varint of the length plus one.
+    fn nullable_len(&mut self, field: &'static str, short: bool) -> Result<Option<usize>> {
+        let len = if self.flexible {
+            self.unsigned_varint(field)? as i64 - 1
+        } else if short {
+            self.i16(field)? as i64
+        } else {
+            self.i32(field)? as i64
+        };
+        match len {
+            -1 => Ok(None),
+            len if len < 0 => Err(MessageError::Malformed(format!(
+                "negative length {len} of the {field}"
+            ))),
+            len => Ok(Some(len as usize)),
+        }
+    }
+
+    pub fn nullable_string(&mut self, field: &'static str) -> Result<Option<String>> {
+        let Some(len) = self.nullable_len(field, true)? else {
+            return Ok(None);
+        };
+        std::str::from_utf8(self.take(len, field)?)
+            .map(|value| Some(value.to_string()))
+            .map_err(|_| MessageError::Malformed(format!("the {field} is not UTF-8")))
+    }
+
+    pub fn string(&mut self, field: &'static str) -> Result<String> {
+        self.nullable_string(field)?
+            .ok_or_else(|| MessageError::Malformed(format!("the {field} is null")))
+    }
+
+    pub fn bytes(&mut self, field: &'static str) -> Result<&'a [u8]> {
+        let len = self
+            .nullable_len(field, false)?
+            .ok_or_else(|| MessageError::Malformed(format!("the {field} are null")))?;
+        self.take(len, field)
+    }
+
+    pub fn array_len(&mut self, field: &'static str) -> Result<usize> {
+        let len = self
+            .nullable_len(field, false)?
+            .ok_or_else(|| MessageError::Malformed(format!("the {field} are null")))?;
+        // Every element takes a byte at least, which bounds what a corrupt length allocates.
+        if len > self.buf.len() {
+            return Err(MessageError::Truncated(field));
+        }
+        Ok(len)
+    }
+
+    pub fn i32_array(&mut self, field: &'static str) -> Result<Vec<i32>> {
+        (0..self.array_len(field)?)
+            .map(|_| self.i32(field))
+            .collect()
+    }
+
+    /// Reads an array, each element with `read`.
+    pub fn elements<T>(
+        &mut self,
+        field: &'static str,
+        mut read: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        (0..self.array_len(field)?).map(|_| read(self)).collect()
+    }
+
+    /// The tagged fields ending a structure in flexible versions, with their tags, none in
+    /// other versions. A reader ignores the tags it doesn't know.
+    pub fn tagged_fields(&mut self) -> Result<Vec<(u32, &'a [u8])>> {
+        if !self.flexible {
+            return Ok(vec![]);
+        }
+        let count = self.unsigned_varint("tagged fields")?;
+        let mut fields = Vec::new();
+        for _ in 0..count {
+            let tag = self.unsigned_varint("tagged fields")?;
+            let size = self.unsigned_varint("tagged fields")? as usize;
+            fields.push((tag, self.take(size, "tagged fields")?));
+        }
+        Ok(fields)
+    }
+
+    /// Fails if anything follows the last field.
+    pub fn finish(&self) -> Result<()> {
+        match self.buf.len() {
+            0 => Ok(()),
+            len => Err(MessageError::Malformed(format!(
+                "{len} bytes follow the last field"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::requests::response_writer::ResponseWriter;
+
+    #[test]
+    fn test_reads_what_the_writer_wrote() {
+        for flexible in [false, true] {
+            let mut buf = Vec::new();
+            let mut writer = ResponseWriter::new(&mut buf, flexible);
+            writer.i16(1);
+            writer.string("foo");
+            writer.nullable_string(None);
+            writer.i32_array(&[1, 2]);
+            writer.tagged_fields_with(&[(0, vec![7])]);
+
+            let mut reader = MessageReader::new(&buf, flexible);
+            assert_eq!(Ok(1), reader.i16("a"));
+            assert_eq!(Ok("foo".to_string()), reader.string("b"));
+            assert_eq!(Ok(None), reader.nullable_string("c"));
+            assert_eq!(Ok(vec![1, 2]), reader.i32_array("d"));
+            let tagged = reader.tagged_fields().unwrap();
+            match flexible {
+                true => assert_eq!(vec![(0, &[7][..])], tagged),
+                false => assert!(tagged.is_empty()),
+            }
+            assert_eq!(Ok(()), reader.finish());
+        }
+
+        let mut reader = MessageReader::new(&[0, 0, 0, 9, 1], false);
+        assert_eq!(Err(MessageError::Truncated("e")), reader.array_len("e"));
+        let reader = MessageReader::new(&[0], false);
+        assert!(matches!(reader.finish(), Err(MessageError::Malformed(_))));
+    }
+}
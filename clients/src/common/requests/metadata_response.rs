@@ -0,0 +1,213 @@
+//! The response to a Metadata request: the brokers of the cluster, and the partitions of the
+//! topics asked for with their leaders and replicas.
+
+use crate::common::protocol::api_keys::ApiKeys;
+use crate::common::protocol::errors::Errors;
+use crate::common::requests::abstract_response::AbstractResponse;
+use crate::common::requests::response_writer::{ResponseSink, ResponseWriter, WritableResponse};
+use crate::common::uuid::Uuid;
+
+/// The authorized operations of a response which were not asked for.
+pub const AUTHORIZED_OPERATIONS_OMITTED: i32 = i32::MIN;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataResponseBroker {
+    pub node_id: i32,
+    pub host: String,
+    pub port: i32,
+    pub rack: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataResponsePartition {
+    pub error: Errors,
+    pub partition_index: i32,
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub replica_nodes: Vec<i32>,
+    pub isr_nodes: Vec<i32>,
+    pub offline_replicas: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataResponseTopic {
+    pub error: Errors,
+    pub name: Option<String>,
+    pub topic_id: Uuid,
+    pub is_internal: bool,
+    pub partitions: Vec<MetadataResponsePartition>,
+    pub topic_authorized_operations: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataResponse {
+    pub throttle_time_ms: i32,
+    pub brokers: Vec<MetadataResponseBroker>,
+    pub cluster_id: Option<String>,
+    pub controller_id: i32,
+    pub topics: Vec<MetadataResponseTopic>,
+    pub cluster_authorized_operations: i32,
+    pub error: Errors,
+}
+
+/// Not sized up front: counting the many small fields of a large response costs about as
+/// much as writing them, more than growing its buffer.
+impl WritableResponse for MetadataResponse {
+    fn write_into<S: ResponseSink>(&self, correlation_id: i32, version: i16, sink: &mut S) {
+        let api = ApiKeys::Metadata;
+        let mut writer = ResponseWriter::new(sink, api.is_flexible(version));
+        writer.i32(correlation_id);
+        if api.response_header_version(version) >= 1 {
+            writer.tagged_fields();
+        }
+        if version >= 3 {
+            writer.i32(self.throttle_time_ms);
+        }
+        writer.len(Some(self.brokers.len()), false);
+        for broker in &self.brokers {
+            writer.i32(broker.node_id);
+            writer.string(&broker.host);
+            writer.i32(broker.port);
+            if version >= 1 {
+                writer.nullable_string(broker.rack.as_deref());
+            }
+            writer.tagged_fields();
+        }
+        if version >= 2 {
+            writer.nullable_string(self.cluster_id.as_deref());
+        }
+        if version >= 1 {
+            writer.i32(self.controller_id);
+        }
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            write_topic(&mut writer, version, topic);
+        }
+        if (8..=10).contains(&version) {
+            writer.i32(self.cluster_authorized_operations);
+        }
+        if version >= 13 {
+            writer.i16(self.error.code());
+        }
+        writer.tagged_fields();
+    }
+}
+
+fn write_topic<S: ResponseSink>(
+    writer: &mut ResponseWriter<'_, S>,
+    version: i16,
+    topic: &MetadataResponseTopic,
+) {
+    writer.i16(topic.error.code());
+    match version {
+        12.. => writer.nullable_string(topic.name.as_deref()),
+        _ => writer.string(topic.name.as_deref().unwrap_or_default()),
+    }
+    if version >= 10 {
+        writer.bytes(&topic.topic_id.to_bytes());
+    }
+    if version >= 1 {
+        writer.bool(topic.is_internal);
+    }
+    writer.len(Some(topic.partitions.len()), false);
+    for partition in &topic.partitions {
+        writer.i16(partition.error.code());
+        writer.i32(partition.partition_index);
+        writer.i32(partition.leader_id);
+        if version >= 7 {
+            writer.i32(partition.leader_epoch);
+        }
+        writer.i32_array(&partition.replica_nodes);
+        writer.i32_array(&partition.isr_nodes);
+        if version >= 5 {
+            writer.i32_array(&partition.offline_replicas);
+        }
+        writer.tagged_fields();
+    }
+    if version >= 8 {
+        writer.i32(topic.topic_authorized_operations);
+    }
+    writer.tagged_fields();
+}
+
+impl AbstractResponse for MetadataResponse {
+    fn throttle_time_ms(&self) -> i32 {
+        self.throttle_time_ms
+    }
+
+    fn maybe_set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+        self.throttle_time_ms = throttle_time_ms;
+    }
+
+    fn should_client_throttle(&self, version: i16) -> bool {
+        version >= 6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> MetadataResponse {
+        MetadataResponse {
+            throttle_time_ms: 0,
+            brokers: vec![MetadataResponseBroker {
+                node_id: 1,
+                host: "a".to_string(),
+                port: 9092,
+                rack: None,
+            }],
+            cluster_id: Some("c".to_string()),
+            controller_id: 1,
+            topics: vec![MetadataResponseTopic {
+                error: Errors::None,
+                name: Some("t".to_string()),
+                topic_id: Uuid::new(0, 7),
+                is_internal: false,
+                partitions: vec![MetadataResponsePartition {
+                    error: Errors::None,
+                    partition_index: 0,
+                    leader_id: 1,
+                    leader_epoch: 2,
+                    replica_nodes: vec![1],
+                    isr_nodes: vec![1],
+                    offline_replicas: vec![],
+                }],
+                topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+            }],
+            cluster_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+            error: Errors::None,
+        }
+    }
+
+    #[test]
+    fn test_write_v0() {
+        let mut buf = Vec::new();
+        response().write_to(7, 0, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            0, 0, 0, 7,
+            // brokers
+            0, 0, 0, 1, 0, 0, 0, 1, 0, 1, b'a', 0, 0, 0x23, 0x84,
+            // topics
+            0, 0, 0, 1, 0, 0, 0, 1, b't',
+            0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+            0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1,
+        ];
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_size_in_bytes_is_exact() {
+        let response = response();
+        for version in ApiKeys::Metadata.oldest_version()..=ApiKeys::Metadata.latest_version() {
+            let mut buf = Vec::new();
+            response.write_to(1, version, &mut buf);
+            assert_eq!(
+                buf.len(),
+                response.size_in_bytes(version),
+                "version {version}"
+            );
+        }
+    }
+}
@@ -0,0 +1,16 @@
+pub mod abstract_response;
+pub mod api_versions_response;
+pub mod begin_quorum_epoch_request;
+pub mod end_quorum_epoch_request;
+pub mod fetch_response;
+pub mod fetch_snapshot_request;
+pub mod get_telemetry_subscriptions_request;
+pub mod list_offsets_request;
+pub mod message_reader;
+pub mod metadata_response;
+pub mod produce_request;
+pub mod push_telemetry_request;
+pub mod raft_messages;
+pub mod request_header;
+pub mod response_writer;
+pub mod vote_request;
@@ -0,0 +1,454 @@
+//! Produce: a producer sends record batches for the partitions it writes to.
+//!
+//! A produce request is mostly record batches, which the broker appends as they are. Rather
+//! than deserializing the request into owned topics, partitions and records, which would copy
+//! every batch once more and hold the copies next to the frame, [ProduceRequest::parse] checks
+//! the structure of the body in one pass and the request is then read in place: the topic
+//! names and the batches are slices of the frame.
+
+use crate::common::protocol::api_keys::ApiKeys;
+use crate::common::record::record_batch::{RecordBatch, RecordError};
+use crate::common::utils::byte_utils::read_unsigned_varint;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ProduceRequestError {
+    #[error("Unsupported version {0} of the produce request")]
+    UnsupportedVersion(i16),
+
+    #[error("The produce request ends before its {0}")]
+    Truncated(&'static str),
+
+    #[error("The produce request is malformed: {0}")]
+    Malformed(String),
+}
+
+pub type Result<T> = std::result::Result<T, ProduceRequestError>;
+
+/// A produce request, read in place from the body of its frame.
+#[derive(Debug, Clone, Copy)]
+pub struct ProduceRequest<'a> {
+    version: i16,
+    transactional_id: Option<&'a str>,
+    acks: i16,
+    timeout_ms: i32,
+    topic_count: usize,
+    /// The topics, from the first one to the end of the body.
+    topics: &'a [u8],
+    records_size: usize,
+}
+
+impl<'a> ProduceRequest<'a> {
+    /// Parses the body of a produce request of `version`, checking that every field is
+    /// complete, without copying anything out of `body`.
+    pub fn parse(version: i16, body: &'a [u8]) -> Result<Self> {
+        if !ApiKeys::Produce.is_version_supported(version) {
+            return Err(ProduceRequestError::UnsupportedVersion(version));
+        }
+        let mut reader = Reader::new(body, ApiKeys::Produce.is_flexible(version));
+        let transactional_id = reader.nullable_string("transactional id")?;
+        let acks = reader.i16("acks")?;
+        let timeout_ms = reader.i32("timeout")?;
+        let topic_count = reader.array_len("topics")?;
+        let topics = reader.remaining();
+        let mut records_size = 0;
+        for _ in 0..topic_count {
+            reader.string("topic name")?;
+            for _ in 0..reader.array_len("partitions")? {
+                reader.i32("partition index")?;
+                records_size += reader.nullable_bytes("records")?.map_or(0, <[u8]>::len);
+                reader.tagged_fields()?;
+            }
+            reader.tagged_fields()?;
+        }
+        reader.tagged_fields()?;
+        if !reader.remaining().is_empty() {
+            return Err(ProduceRequestError::Malformed(format!(
+                "{} bytes follow the last topic",
+                reader.remaining().len()
+            )));
+        }
+        Ok(Self {
+            version,
+            transactional_id,
+            acks,
+            timeout_ms,
+            topic_count,
+            topics,
+            records_size,
+        })
+    }
+
+    pub fn version(&self) -> i16 {
+        self.version
+    }
+
+    pub fn transactional_id(&self) -> Option<&'a str> {
+        self.transactional_id
+    }
+
+    pub fn acks(&self) -> i16 {
+        self.acks
+    }
+
+    pub fn timeout_ms(&self) -> i32 {
+        self.timeout_ms
+    }
+
+    pub fn topic_count(&self) -> usize {
+        self.topic_count
+    }
+
+    /// The size of the records of every partition.
+    pub fn records_size(&self) -> usize {
+        self.records_size
+    }
+
+    pub fn topics(&self) -> impl Iterator<Item = TopicProduceData<'a>> + use<'a> {
+        let mut reader = Reader::new(self.topics, ApiKeys::Produce.is_flexible(self.version));
+        (0..self.topic_count).map(move |_| {
+            let name = reader.string("topic name").expect(PARSED);
+            let partition_count = reader.array_len("partitions").expect(PARSED);
+            let partitions = reader.remaining();
+            for _ in 0..partition_count {
+                reader.i32("partition index").expect(PARSED);
+                reader.nullable_bytes("records").expect(PARSED);
+                reader.tagged_fields().expect(PARSED);
+            }
+            reader.tagged_fields().expect(PARSED);
+            TopicProduceData {
+                name,
+                flexible: reader.flexible,
+                partition_count,
+                partitions,
+            }
+        })
+    }
+}
+
+const PARSED: &str = "the request was checked by ProduceRequest::parse";
+
+/// The partitions of a topic in a [ProduceRequest].
+#[derive(Debug, Clone, Copy)]
+pub struct TopicProduceData<'a> {
+    name: &'a str,
+    flexible: bool,
+    partition_count: usize,
+    /// The partitions, from the first one to the end of the body.
+    partitions: &'a [u8],
+}
+
+impl<'a> TopicProduceData<'a> {
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn partition_count(&self) -> usize {
+        self.partition_count
+    }
+
+    pub fn partitions(&self) -> impl Iterator<Item = PartitionProduceData<'a>> + use<'a> {
+        let mut reader = Reader::new(self.partitions, self.flexible);
+        (0..self.partition_count).map(move |_| {
+            let index = reader.i32("partition index").expect(PARSED);
+            let records = reader.nullable_bytes("records").expect(PARSED);
+            reader.tagged_fields().expect(PARSED);
+            PartitionProduceData { index, records }
+        })
+    }
+}
+
+/// The records produced to a partition, as a slice of the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionProduceData<'a> {
+    pub index: i32,
+    pub records: Option<&'a [u8]>,
+}
+
+impl<'a> PartitionProduceData<'a> {
+    /// The batches of the records, each checked to be complete and of the current magic but
+    /// not decoded, e.g. with [RecordBatch::read_from], until the caller gets to it.
+    pub fn batches(&self) -> impl Iterator<Item = std::result::Result<&'a [u8], RecordError>> {
+        let mut records = self.records.unwrap_or_default();
+        std::iter::from_fn(move || {
+            if records.is_empty() {
+                return None;
+            }
+            match RecordBatch::read_header(records) {
+                Ok((_, size)) => {
+                    let (batch, rest) = records.split_at(size);
+                    records = rest;
+                    Some(Ok(batch))
+                }
+                Err(e) => {
+                    // A partial or unknown batch ends the records: a producer never sends one.
+                    records = &[];
+                    Some(Err(e))
+                }
+            }
+        })
+    }
+}
+
+/// Reads the fields of a request body, in the classic or the flexible encoding.
+struct Reader<'a> {
+    buf: &'a [u8],
+    flexible: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8], flexible: bool) -> Self {
+        Self { buf, flexible }
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    fn take(&mut self, len: usize, field: &'static str) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(ProduceRequestError::Truncated(field));
+        }
+        let (taken, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(taken)
+    }
+
+    fn i16(&mut self, field: &'static str) -> Result<i16> {
+        Ok(i16::from_be_bytes(
+            self.take(2, field)?.try_into().expect("2 bytes"),
+        ))
+    }
+
+    fn i32(&mut self, field: &'static str) -> Result<i32> {
+        Ok(i32::from_be_bytes(
+            self.take(4, field)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    fn unsigned_varint(&mut self, field: &'static str) -> Result<u32> {
+        read_unsigned_varint(&mut self.buf).map_err(|_| ProduceRequestError::Truncated(field))
+    }
+
+    /// The length of a nullable field, `None` for null. Classic fields store it as an `i16`
+    /// or an `i32`, flexible ones as an unsigned varint of the length plus one.
+    fn nullable_len(&mut self, field: &'static str, short: bool) -> Result<Option<usize>> {
+        let len = if self.flexible {
+            self.unsigned_varint(field)? as i64 - 1
+        } else if short {
+            self.i16(field)? as i64
+        } else {
+            self.i32(field)? as i64
+        };
+        match len {
+            -1 => Ok(None),
+            len if len < 0 => Err(ProduceRequestError::Malformed(format!(
+                "negative length {len} of the {field}"
+            ))),
+            len => Ok(Some(len as usize)),
+        }
+    }
+
+    fn nullable_string(&mut self, field: &'static str) -> Result<Option<&'a str>> {
+        let Some(len) = self.nullable_len(field, true)? else {
+            return Ok(None);
+        };
+        std::str::from_utf8(self.take(len, field)?)
+            .map(Some)
+            .map_err(|_| ProduceRequestError::Malformed(format!("the {field} is not UTF-8")))
+    }
+
+    fn string(&mut self, field: &'static str) -> Result<&'a str> {
+        self.nullable_string(field)?
+            .ok_or_else(|| ProduceRequestError::Malformed(format!("the {field} is null")))
+    }
+
+    fn nullable_bytes(&mut self, field: &'static str) -> Result<Option<&'a [u8]>> {
+        match self.nullable_len(field, false)? {
+            Some(len) => self.take(len, field).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn array_len(&mut self, field: &'static str) -> Result<usize> {
+        self.nullable_len(field, false)?
+            .ok_or_else(|| ProduceRequestError::Malformed(format!("the {field} are null")))
+    }
+
+    /// Skips the tagged fields of a flexible structure, none of which are known.
+    fn tagged_fields(&mut self) -> Result<()> {
+        if !self.flexible {
+            return Ok(());
+        }
+        for _ in 0..self.unsigned_varint("tagged fields")? {
+            self.unsigned_varint("tagged fields")?;
+            let size = self.unsigned_varint("tagged fields")? as usize;
+            self.take(size, "tagged fields")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::record::record_batch::Record;
+    use crate::common::utils::byte_utils::write_unsigned_varint;
+
+    /// Writes the body of a produce request, as a producer would.
+    struct Writer {
+        buf: Vec<u8>,
+        flexible: bool,
+    }
+
+    impl Writer {
+        fn new(version: i16) -> Self {
+            Self {
+                buf: Vec::new(),
+                flexible: ApiKeys::Produce.is_flexible(version),
+            }
+        }
+
+        fn len(&mut self, len: Option<usize>, short: bool) {
+            if self.flexible {
+                let len = len.map_or(0, |len| len as u32 + 1);
+                write_unsigned_varint(len, &mut self.buf).unwrap();
+            } else if short {
+                let len = len.map_or(-1, |len| len as i16);
+                self.buf.extend_from_slice(&len.to_be_bytes());
+            } else {
+                let len = len.map_or(-1, |len| len as i32);
+                self.buf.extend_from_slice(&len.to_be_bytes());
+            }
+        }
+
+        fn string(&mut self, s: Option<&str>) {
+            self.len(s.map(str::len), true);
+            self.buf.extend_from_slice(s.unwrap_or_default().as_bytes());
+        }
+
+        fn bytes(&mut self, bytes: Option<&[u8]>) {
+            self.len(bytes.map(<[u8]>::len), false);
+            self.buf.extend_from_slice(bytes.unwrap_or_default());
+        }
+
+        fn tagged_fields(&mut self) {
+            if self.flexible {
+                self.buf.push(0);
+            }
+        }
+    }
+
+    /// The records of each partition, by topic.
+    type Topics<'a> = [(&'a str, Vec<(i32, Option<Vec<u8>>)>)];
+
+    fn body(version: i16, topics: &Topics<'_>) -> Vec<u8> {
+        let mut writer = Writer::new(version);
+        writer.string(Some("txn"));
+        writer.buf.extend_from_slice(&(-1i16).to_be_bytes());
+        writer.buf.extend_from_slice(&30_000i32.to_be_bytes());
+        writer.len(Some(topics.len()), false);
+        for (name, partitions) in topics {
+            writer.string(Some(name));
+            writer.len(Some(partitions.len()), false);
+            for (index, records) in partitions {
+                writer.buf.extend_from_slice(&index.to_be_bytes());
+                writer.bytes(records.as_deref());
+                writer.tagged_fields();
+            }
+            writer.tagged_fields();
+        }
+        writer.tagged_fields();
+        writer.buf
+    }
+
+    fn batches(values: &[&[u8]]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for value in values {
+            RecordBatch::new(0, vec![Record::new(1_000, None, Some(value))]).write_to(&mut buf);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_parse_in_place() {
+        for version in [3, 8, 9, 12] {
+            let foo_0 = batches(&[b"a", b"b"]);
+            let body = body(
+                version,
+                &[
+                    ("foo", vec![(0, Some(foo_0.clone())), (1, None)]),
+                    ("bar", vec![(2, Some(batches(&[b"c"])))]),
+                ],
+            );
+            let request = ProduceRequest::parse(version, &body).unwrap();
+            assert_eq!(Some("txn"), request.transactional_id());
+            assert_eq!(-1, request.acks());
+            assert_eq!(30_000, request.timeout_ms());
+            assert_eq!(2, request.topic_count());
+
+            let topics: Vec<_> = request.topics().collect();
+            assert_eq!("foo", topics[0].name());
+            assert_eq!("bar", topics[1].name());
+            let partitions: Vec<_> = topics[0].partitions().collect();
+            assert_eq!(2, partitions.len());
+            assert_eq!(1, partitions[1].index);
+            assert_eq!(None, partitions[1].records);
+            assert_eq!(0, partitions[1].batches().count());
+
+            // The records are a slice of the body, not a copy.
+            let records = partitions[0].records.unwrap();
+            assert_eq!(foo_0, records);
+            assert!(body.as_ptr_range().contains(&records.as_ptr()));
+            let decoded: Vec<_> = partitions[0]
+                .batches()
+                .map(|batch| RecordBatch::read_from(batch.unwrap()).unwrap().0)
+                .collect();
+            assert_eq!(RecordBatch::read_all(&foo_0).unwrap(), decoded);
+            assert_eq!(foo_0.len() + batches(&[b"c"]).len(), request.records_size());
+        }
+    }
+
+    #[test]
+    fn test_malformed_requests() {
+        let body = body(9, &[("foo", vec![(0, Some(batches(&[b"a"])))])]);
+        for len in 0..body.len() {
+            assert!(
+                matches!(
+                    ProduceRequest::parse(9, &body[..len]),
+                    Err(ProduceRequestError::Truncated(_))
+                ),
+                "{len}"
+            );
+        }
+        let mut trailing = body.clone();
+        trailing.push(0);
+        assert!(matches!(
+            ProduceRequest::parse(9, &trailing),
+            Err(ProduceRequestError::Malformed(_))
+        ));
+        assert_eq!(
+            ProduceRequestError::UnsupportedVersion(2),
+            ProduceRequest::parse(2, &body).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_partial_batch() {
+        let mut records = batches(&[b"a", b"b"]);
+        records.pop();
+        let body = body(3, &[("foo", vec![(0, Some(records))])]);
+        let request = ProduceRequest::parse(3, &body).unwrap();
+        let partition = request
+            .topics()
+            .next()
+            .unwrap()
+            .partitions()
+            .next()
+            .unwrap();
+        let batches: Vec<_> = partition.batches().collect();
+        assert_eq!(2, batches.len());
+        assert!(batches[0].is_ok());
+        assert_eq!(Err(RecordError::Truncated), batches[1]);
+    }
+}
@@ -0,0 +1,42 @@
+//! PushTelemetry (KIP-714): a client pushes the metrics it was subscribed to, serialized as
+//! OpenTelemetry (OTLP) protobuf.
+
+use crate::common::protocol::errors::Errors;
+use crate::common::requests::abstract_response::AbstractResponse;
+use crate::common::uuid::Uuid;
+
+/// The compression type of an uncompressed metrics payload.
+pub const COMPRESSION_TYPE_NONE: i8 = 0;
+
+/// The content type of the payload handed to telemetry receivers.
+pub const OTLP_CONTENT_TYPE: &str = "application/x-protobuf";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushTelemetryRequest {
+    pub client_instance_id: Uuid,
+    pub subscription_id: i32,
+    /// Set on the last push of a client which is closing.
+    pub terminating: bool,
+    pub compression_type: i8,
+    pub metrics: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushTelemetryResponse {
+    pub throttle_time_ms: i32,
+    pub error_code: Errors,
+}
+
+impl AbstractResponse for PushTelemetryResponse {
+    fn throttle_time_ms(&self) -> i32 {
+        self.throttle_time_ms
+    }
+
+    fn maybe_set_throttle_time_ms(&mut self, throttle_time_ms: i32) {
+        self.throttle_time_ms = throttle_time_ms;
+    }
+
+    fn should_client_throttle(&self, _version: i16) -> bool {
+        true
+    }
+}
@@ -0,0 +1,117 @@
+//! What the requests and responses of the [RaftApiKeys] share: the endpoints of KIP-853,
+//! which let the voters of a quorum find each other, and their headers.
+
+use crate::common::protocol::raft_api_keys::RaftApiKeys;
+use crate::common::requests::message_reader::{MessageError, MessageReader, Result};
+use crate::common::requests::response_writer::{ResponseSink, ResponseWriter};
+
+/// The tag of the node endpoints of the responses from version 1.
+const NODE_ENDPOINTS_TAG: u32 = 0;
+
+/// The endpoint of a node a response names, e.g. the leader it reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeEndpoint {
+    pub node_id: i32,
+    pub host: String,
+    pub port: u16,
+}
+
+/// An endpoint of the leader sending a request, by listener name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaderEndpoint {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+}
+
+/// Fails unless `api` supports `version`.
+pub(crate) fn check_version(api: RaftApiKeys, version: i16) -> Result<()> {
+    match api.is_version_supported(version) {
+        true => Ok(()),
+        false => Err(MessageError::UnsupportedVersion(api.name(), version)),
+    }
+}
+
+/// Writes the header of the response to a request of `api` at `version`.
+pub(crate) fn write_response_header<S: ResponseSink>(
+    writer: &mut ResponseWriter<'_, S>,
+    api: RaftApiKeys,
+    version: i16,
+    correlation_id: i32,
+) {
+    writer.i32(correlation_id);
+    if api.response_header_version(version) >= 1 {
+        writer.tagged_fields();
+    }
+}
+
+/// The tagged fields ending a response from version 1: its node endpoints, left out if there
+/// are none.
+pub(crate) fn node_endpoints_field(
+    version: i16,
+    endpoints: &[NodeEndpoint],
+) -> Vec<(u32, Vec<u8>)> {
+    if version < 1 || endpoints.is_empty() {
+        return vec![];
+    }
+    let mut value = Vec::new();
+    let mut writer = ResponseWriter::new(&mut value, true);
+    writer.len(Some(endpoints.len()), false);
+    for endpoint in endpoints {
+        writer.i32(endpoint.node_id);
+        writer.string(&endpoint.host);
+        writer.u16(endpoint.port);
+        writer.tagged_fields();
+    }
+    vec![(NODE_ENDPOINTS_TAG, value)]
+}
+
+/// The node endpoints among the tagged fields ending a response.
+pub(crate) fn read_node_endpoints(
+    version: i16,
+    tagged: &[(u32, &[u8])],
+) -> Result<Vec<NodeEndpoint>> {
+    let Some((_, value)) = tagged
+        .iter()
+        .find(|(tag, _)| version >= 1 && *tag == NODE_ENDPOINTS_TAG)
+    else {
+        return Ok(vec![]);
+    };
+    let mut reader = MessageReader::new(value, true);
+    let endpoints = reader.elements("node endpoints", |reader| {
+        let endpoint = NodeEndpoint {
+            node_id: reader.i32("node id")?,
+            host: reader.string("host")?,
+            port: reader.u16("port")?,
+        };
+        reader.tagged_fields()?;
+        Ok(endpoint)
+    })?;
+    reader.finish()?;
+    Ok(endpoints)
+}
+
+pub(crate) fn write_leader_endpoints<S: ResponseSink>(
+    writer: &mut ResponseWriter<'_, S>,
+    endpoints: &[LeaderEndpoint],
+) {
+    writer.len(Some(endpoints.len()), false);
+    for endpoint in endpoints {
+        writer.string(&endpoint.name);
+        writer.string(&endpoint.host);
+        writer.u16(endpoint.port);
+        writer.tagged_fields();
+    }
+}
+
+pub(crate) fn read_leader_endpoints(reader: &mut MessageReader<'_>) -> Result<Vec<LeaderEndpoint>> {
+    reader.elements("leader endpoints", |reader| {
+        let endpoint = LeaderEndpoint {
+            name: reader.string("listener name")?,
+            host: reader.string("host")?,
+            port: reader.u16("port")?,
+        };
+        reader.tagged_fields()?;
+        Ok(endpoint)
+    })
+}
@@ -0,0 +1,169 @@
+//! The header every request starts with, after the size of the request.
+
+use crate::common::protocol::api_keys::ApiKeys;
+use crate::common::utils::byte_utils::read_unsigned_varint;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RequestHeaderError {
+    #[error("The request is {0} bytes, too short for a request header")]
+    Truncated(usize),
+
+    #[error("The client id of the request header is malformed")]
+    MalformedClientId,
+
+    #[error("The tagged fields of the request header are malformed")]
+    MalformedTaggedFields,
+}
+
+/// The header of a request, which a broker can read without knowing the API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestHeader {
+    pub api_key: i16,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    pub client_id: Option<String>,
+}
+
+/// The size of the fields every header version starts with: the API key and version and the
+/// correlation id.
+pub const REQUEST_HEADER_PREFIX_SIZE: usize = 8;
+
+impl RequestHeader {
+    /// Reads the API key, version and correlation id, which every header version starts with.
+    /// Unlike [RequestHeader::parse], this does not need the API to be known, so that even a
+    /// request for an unknown API can be answered with its correlation id.
+    pub fn parse_prefix(request: &[u8]) -> Result<(i16, i16, i32), RequestHeaderError> {
+        if request.len() < REQUEST_HEADER_PREFIX_SIZE {
+            return Err(RequestHeaderError::Truncated(request.len()));
+        }
+        Ok((
+            i16::from_be_bytes([request[0], request[1]]),
+            i16::from_be_bytes([request[2], request[3]]),
+            i32::from_be_bytes([request[4], request[5], request[6], request[7]]),
+        ))
+    }
+
+    /// Parses the header of a request for `api` at the header version of its API version.
+    /// Returns the header and its size, after which the request body starts.
+    pub fn parse(
+        api: ApiKeys,
+        request: &[u8],
+    ) -> Result<(RequestHeader, usize), RequestHeaderError> {
+        let (api_key, api_version, correlation_id) = Self::parse_prefix(request)?;
+        let mut at = REQUEST_HEADER_PREFIX_SIZE;
+        let client_id = match request.get(at..at + 2) {
+            None => return Err(RequestHeaderError::MalformedClientId),
+            Some(len) => {
+                at += 2;
+                match i16::from_be_bytes([len[0], len[1]]) {
+                    -1 => None,
+                    len if len < 0 => return Err(RequestHeaderError::MalformedClientId),
+                    len => {
+                        let bytes = request
+                            .get(at..at + len as usize)
+                            .ok_or(RequestHeaderError::MalformedClientId)?;
+                        at += len as usize;
+                        Some(
+                            String::from_utf8(bytes.to_vec())
+                                .map_err(|_| RequestHeaderError::MalformedClientId)?,
+                        )
+                    }
+                }
+            }
+        };
+        if api.request_header_version(api_version) >= 2 {
+            at += skip_tagged_fields(&request[at..])?;
+        }
+        Ok((
+            RequestHeader {
+                api_key,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            at,
+        ))
+    }
+}
+
+/// Skips the tagged fields at the start of `buf`, none of which the header defines yet.
+/// Returns their size.
+fn skip_tagged_fields(buf: &[u8]) -> Result<usize, RequestHeaderError> {
+    let mut reader = buf;
+    let count =
+        read_unsigned_varint(&mut reader).map_err(|_| RequestHeaderError::MalformedTaggedFields)?;
+    for _ in 0..count {
+        read_unsigned_varint(&mut reader).map_err(|_| RequestHeaderError::MalformedTaggedFields)?;
+        let size = read_unsigned_varint(&mut reader)
+            .map_err(|_| RequestHeaderError::MalformedTaggedFields)? as usize;
+        reader = reader
+            .get(size..)
+            .ok_or(RequestHeaderError::MalformedTaggedFields)?;
+    }
+    Ok(buf.len() - reader.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(api: ApiKeys, version: i16, client_id: Option<&str>, tagged: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&api.id().to_be_bytes());
+        buf.extend_from_slice(&version.to_be_bytes());
+        buf.extend_from_slice(&42i32.to_be_bytes());
+        match client_id {
+            Some(id) => {
+                buf.extend_from_slice(&(id.len() as i16).to_be_bytes());
+                buf.extend_from_slice(id.as_bytes());
+            }
+            None => buf.extend_from_slice(&(-1i16).to_be_bytes()),
+        }
+        buf.extend_from_slice(tagged);
+        buf
+    }
+
+    #[test]
+    fn test_parse() {
+        let mut request = header(ApiKeys::Metadata, 8, Some("client"), &[]);
+        request.extend_from_slice(b"body");
+        let (parsed, size) = RequestHeader::parse(ApiKeys::Metadata, &request).unwrap();
+        assert_eq!(
+            RequestHeader {
+                api_key: 3,
+                api_version: 8,
+                correlation_id: 42,
+                client_id: Some("client".to_string()),
+            },
+            parsed
+        );
+        assert_eq!(b"body", &request[size..]);
+
+        // A flexible header ends with tagged fields: one field with tag 5 and 2 bytes.
+        let mut request = header(ApiKeys::Metadata, 12, None, &[1, 5, 2, 0xAA, 0xBB]);
+        request.extend_from_slice(b"body");
+        let (parsed, size) = RequestHeader::parse(ApiKeys::Metadata, &request).unwrap();
+        assert_eq!(None, parsed.client_id);
+        assert_eq!(b"body", &request[size..]);
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(
+            Err(RequestHeaderError::Truncated(3)),
+            RequestHeader::parse_prefix(&[0, 3, 0])
+        );
+        let mut request = header(ApiKeys::Metadata, 8, Some("client"), &[]);
+        request.truncate(12);
+        assert_eq!(
+            Err(RequestHeaderError::MalformedClientId),
+            RequestHeader::parse(ApiKeys::Metadata, &request)
+        );
+        let request = header(ApiKeys::Metadata, 12, None, &[1, 5, 9, 0xAA]);
+        assert_eq!(
+            Err(RequestHeaderError::MalformedTaggedFields),
+            RequestHeader::parse(ApiKeys::Metadata, &request)
+        );
+    }
+}
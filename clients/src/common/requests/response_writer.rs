@@ -0,0 +1,234 @@
+//! Writes the fields of responses in the encoding of their version.
+//!
+//! A response can be written twice: once into a [SizeCounter], to size its buffer, and once
+//! into the buffer itself. That only pays off when counting is much cheaper than writing,
+//! e.g. for a fetch response, whose record batches are counted from their lengths but copied
+//! byte by byte. For a response of many small fields, e.g. the metadata of thousands of
+//! topics, counting costs about as much as writing, more than growing the buffer does, so
+//! such a response is written once, ideally into a reused buffer, see
+//! [WritableResponse::size_hint].
+
+use crate::common::record::record_batch::RecordBatch;
+
+/// Where the bytes of a response go.
+pub trait ResponseSink {
+    fn put(&mut self, bytes: &[u8]);
+
+    /// Puts `batch`, encoded.
+    fn put_record_batch(&mut self, batch: &RecordBatch) {
+        self.put(&batch.encode());
+    }
+}
+
+impl ResponseSink for Vec<u8> {
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+
+    fn put_record_batch(&mut self, batch: &RecordBatch) {
+        batch.write_to(self);
+    }
+}
+
+/// A sink only counting the bytes of a response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeCounter {
+    size: usize,
+}
+
+impl SizeCounter {
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl ResponseSink for SizeCounter {
+    #[inline]
+    fn put(&mut self, bytes: &[u8]) {
+        self.size += bytes.len();
+    }
+
+    fn put_record_batch(&mut self, batch: &RecordBatch) {
+        self.size += batch.size_in_bytes();
+    }
+}
+
+/// A response written through a [ResponseWriter].
+pub trait WritableResponse {
+    /// Writes the response, its header included, at `version` into `sink`.
+    fn write_into<S: ResponseSink>(&self, correlation_id: i32, version: i16, sink: &mut S);
+
+    /// The capacity to reserve before writing the response at `version`, if sizing it costs
+    /// less than growing its buffer. `None` by default.
+    fn size_hint(&self, _version: i16) -> Option<usize> {
+        None
+    }
+
+    /// The exact size of the response written at `version`.
+    fn size_in_bytes(&self, version: i16) -> usize {
+        let mut counter = SizeCounter::default();
+        self.write_into(0, version, &mut counter);
+        counter.size()
+    }
+
+    /// Appends the response to `buf`, reserving its [size_hint](Self::size_hint) first.
+    fn write_to(&self, correlation_id: i32, version: i16, buf: &mut Vec<u8>) {
+        if let Some(size) = self.size_hint(version) {
+            buf.reserve(size);
+        }
+        self.write_into(correlation_id, version, buf);
+    }
+}
+
+/// Writes fields into a sink, with the lengths and tagged fields of the flexible versions if
+/// `flexible`.
+pub struct ResponseWriter<'a, S: ResponseSink> {
+    sink: &'a mut S,
+    flexible: bool,
+}
+
+impl<'a, S: ResponseSink> ResponseWriter<'a, S> {
+    pub fn new(sink: &'a mut S, flexible: bool) -> Self {
+        Self { sink, flexible }
+    }
+
+    pub fn is_flexible(&self) -> bool {
+        self.flexible
+    }
+
+    pub fn i8(&mut self, value: i8) {
+        self.sink.put(&value.to_be_bytes());
+    }
+
+    pub fn i16(&mut self, value: i16) {
+        self.sink.put(&value.to_be_bytes());
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.sink.put(&value.to_be_bytes());
+    }
+
+    pub fn i32(&mut self, value: i32) {
+        self.sink.put(&value.to_be_bytes());
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.sink.put(&value.to_be_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.sink.put(&[value as u8]);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.sink.put(bytes);
+    }
+
+    /// The length of an array, or of a string if `short`, `None` for a null one.
+    pub fn len(&mut self, len: Option<usize>, short: bool) {
+        if self.flexible {
+            self.unsigned_varint(len.map_or(0, |len| len as u32 + 1));
+        } else if short {
+            self.i16(len.map_or(-1, |len| len as i16));
+        } else {
+            self.i32(len.map_or(-1, |len| len as i32));
+        }
+    }
+
+    fn unsigned_varint(&mut self, mut value: u32) {
+        let mut varint = [0; 5];
+        let mut len = 0;
+        while value >= 0x80 {
+            varint[len] = (value as u8 & 0x7f) | 0x80;
+            value >>= 7;
+            len += 1;
+        }
+        varint[len] = value as u8;
+        self.sink.put(&varint[..=len]);
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.nullable_string(Some(value));
+    }
+
+    pub fn nullable_string(&mut self, value: Option<&str>) {
+        self.len(value.map(str::len), true);
+        self.sink.put(value.unwrap_or_default().as_bytes());
+    }
+
+    /// The records of a partition: the batches behind their total size.
+    pub fn records(&mut self, batches: &[RecordBatch]) {
+        let size = batches.iter().map(RecordBatch::size_in_bytes).sum();
+        self.len(Some(size), false);
+        for batch in batches {
+            self.sink.put_record_batch(batch);
+        }
+    }
+
+    pub fn i32_array(&mut self, values: &[i32]) {
+        self.len(Some(values.len()), false);
+        for value in values {
+            self.i32(*value);
+        }
+    }
+
+    /// Ends a structure with its tagged fields, none, in flexible versions.
+    pub fn tagged_fields(&mut self) {
+        self.tagged_fields_with(&[]);
+    }
+
+    /// Ends a structure with `fields` in flexible versions: each a tag and its encoded value,
+    /// in increasing order of tags.
+    pub fn tagged_fields_with(&mut self, fields: &[(u32, Vec<u8>)]) {
+        if !self.flexible {
+            return;
+        }
+        self.unsigned_varint(fields.len() as u32);
+        for (tag, value) in fields {
+            self.unsigned_varint(*tag);
+            self.unsigned_varint(value.len() as u32);
+            self.sink.put(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::utils::byte_utils::write_unsigned_varint;
+
+    fn write(flexible: bool, sink: &mut impl ResponseSink) {
+        let mut writer = ResponseWriter::new(sink, flexible);
+        writer.i16(1);
+        writer.string("foo");
+        writer.nullable_string(None);
+        writer.i32_array(&[1, 2]);
+        writer.tagged_fields();
+    }
+
+    #[test]
+    fn test_size_counter_matches_the_written_bytes() {
+        for flexible in [false, true] {
+            let mut buf = Vec::new();
+            write(flexible, &mut buf);
+            let mut counter = SizeCounter::default();
+            write(flexible, &mut counter);
+            assert_eq!(buf.len(), counter.size());
+        }
+        let mut buf = Vec::new();
+        write(true, &mut buf);
+        assert_eq!(
+            vec![0, 1, 4, b'f', b'o', b'o', 0, 3, 0, 0, 0, 1, 0, 0, 0, 2, 0],
+            buf
+        );
+
+        for len in [0, 126, 127, 16_383, 16_384, u32::MAX as usize - 1] {
+            let mut buf = Vec::new();
+            ResponseWriter::new(&mut buf, true).len(Some(len), false);
+            let mut expected = Vec::new();
+            write_unsigned_varint(len as u32 + 1, &mut expected).unwrap();
+            assert_eq!(expected, buf);
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Vote: a candidate, or a prospective candidate with a pre-vote (KIP-996), asks the voters
+//! of the quorum to elect it leader of an epoch.
+
+use crate::common::protocol::errors::Errors;
+use crate::common::protocol::raft_api_keys::RaftApiKeys;
+use crate::common::requests::message_reader::{MessageReader, Result};
+use crate::common::requests::raft_messages::{
+    NodeEndpoint, check_version, node_endpoints_field, read_node_endpoints, write_response_header,
+};
+use crate::common::requests::response_writer::{ResponseSink, ResponseWriter, WritableResponse};
+use crate::common::uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VotePartitionRequest {
+    pub partition_index: i32,
+    /// The epoch the replica asks to be elected in.
+    pub replica_epoch: i32,
+    pub replica_id: i32,
+    /// Sent from version 1.
+    pub replica_directory_id: Uuid,
+    /// The directory id of the voter the request is sent to, sent from version 1.
+    pub voter_directory_id: Uuid,
+    pub last_offset_epoch: i32,
+    pub last_offset: i64,
+    /// Whether this is a pre-vote, which doesn't bump the epoch, sent from version 2.
+    pub pre_vote: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteTopicRequest {
+    pub topic_name: String,
+    pub partitions: Vec<VotePartitionRequest>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteRequest {
+    pub cluster_id: Option<String>,
+    /// The id of the voter the request is sent to, sent from version 1.
+    pub voter_id: i32,
+    pub topics: Vec<VoteTopicRequest>,
+}
+
+impl VoteRequest {
+    /// Writes the body of the request at `version`.
+    pub fn write_into<S: ResponseSink>(&self, version: i16, sink: &mut S) {
+        let mut writer = ResponseWriter::new(sink, RaftApiKeys::Vote.is_flexible(version));
+        writer.nullable_string(self.cluster_id.as_deref());
+        if version >= 1 {
+            writer.i32(self.voter_id);
+        }
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            writer.string(&topic.topic_name);
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                writer.i32(partition.partition_index);
+                writer.i32(partition.replica_epoch);
+                writer.i32(partition.replica_id);
+                if version >= 1 {
+                    writer.bytes(&partition.replica_directory_id.to_bytes());
+                    writer.bytes(&partition.voter_directory_id.to_bytes());
+                }
+                writer.i32(partition.last_offset_epoch);
+                writer.i64(partition.last_offset);
+                if version >= 2 {
+                    writer.bool(partition.pre_vote);
+                }
+                writer.tagged_fields();
+            }
+            writer.tagged_fields();
+        }
+        writer.tagged_fields();
+    }
+
+    /// Parses the body of a request of `version`.
+    pub fn parse(version: i16, body: &[u8]) -> Result<Self> {
+        let api = RaftApiKeys::Vote;
+        check_version(api, version)?;
+        let mut reader = MessageReader::new(body, api.is_flexible(version));
+        let cluster_id = reader.nullable_string("cluster id")?;
+        let voter_id = match version >= 1 {
+            true => reader.i32("voter id")?,
+            false => -1,
+        };
+        let topics = reader.elements("topics", |reader| {
+            let topic_name = reader.string("topic name")?;
+            let partitions = reader.elements("partitions", |reader| {
+                let partition_index = reader.i32("partition index")?;
+                let replica_epoch = reader.i32("replica epoch")?;
+                let replica_id = reader.i32("replica id")?;
+                let (replica_directory_id, voter_directory_id) = match version >= 1 {
+                    true => (
+                        reader.uuid("replica directory id")?,
+                        reader.uuid("voter directory id")?,
+                    ),
+                    false => (Uuid::ZERO, Uuid::ZERO),
+                };
+                let partition = VotePartitionRequest {
+                    partition_index,
+                    replica_epoch,
+                    replica_id,
+                    replica_directory_id,
+                    voter_directory_id,
+                    last_offset_epoch: reader.i32("last offset epoch")?,
+                    last_offset: reader.i64("last offset")?,
+                    pre_vote: version >= 2 && reader.bool("pre-vote")?,
+                };
+                reader.tagged_fields()?;
+                Ok(partition)
+            })?;
+            reader.tagged_fields()?;
+            Ok(VoteTopicRequest {
+                topic_name,
+                partitions,
+            })
+        })?;
+        reader.tagged_fields()?;
+        reader.finish()?;
+        Ok(Self {
+            cluster_id,
+            voter_id,
+            topics,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VotePartitionResponse {
+    pub partition_index: i32,
+    pub error: Errors,
+    /// The leader the voter knows of, or -1.
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub vote_granted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteTopicResponse {
+    pub topic_name: String,
+    pub partitions: Vec<VotePartitionResponse>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteResponse {
+    pub error: Errors,
+    pub topics: Vec<VoteTopicResponse>,
+    /// The endpoints of the leaders the response names, sent from version 1.
+    pub node_endpoints: Vec<NodeEndpoint>,
+}
+
+impl WritableResponse for VoteResponse {
+    fn write_into<S: ResponseSink>(&self, correlation_id: i32, version: i16, sink: &mut S) {
+        let api = RaftApiKeys::Vote;
+        let mut writer = ResponseWriter::new(sink, api.is_flexible(version));
+        write_response_header(&mut writer, api, version, correlation_id);
+        writer.i16(self.error.code());
+        writer.len(Some(self.topics.len()), false);
+        for topic in &self.topics {
+            writer.string(&topic.topic_name);
+            writer.len(Some(topic.partitions.len()), false);
+            for partition in &topic.partitions {
+                writer.i32(partition.partition_index);
+                writer.i16(partition.error.code());
+                writer.i32(partition.leader_id);
+                writer.i32(partition.leader_epoch);
+                writer.bool(partition.vote_granted);
+                writer.tagged_fields();
+            }
+            writer.tagged_fields();
+        }
+        writer.tagged_fields_with(&node_endpoints_field(version, &self.node_endpoints));
+    }
+}
+
+impl VoteResponse {
+    /// Parses the body of a response of `version`, after its header.
+    pub fn parse(version: i16, body: &[u8]) -> Result<Self> {
+        let api = RaftApiKeys::Vote;
+        check_version(api, version)?;
+        let mut reader = MessageReader::new(body, api.is_flexible(version));
+        let error = reader.error("error code")?;
+        let topics = reader.elements("topics", |reader| {
+            let topic_name = reader.string("topic name")?;
+            let partitions = reader.elements("partitions", |reader| {
+                let partition = VotePartitionResponse {
+                    partition_index: reader.i32("partition index")?,
+                    error: reader.error("error code")?,
+                    leader_id: reader.i32("leader id")?,
+                    leader_epoch: reader.i32("leader epoch")?,
+                    vote_granted: reader.bool("vote granted")?,
+                };
+                reader.tagged_fields()?;
+                Ok(partition)
+            })?;
+            reader.tagged_fields()?;
+            Ok(VoteTopicResponse {
+                topic_name,
+                partitions,
+            })
+        })?;
+        let node_endpoints = read_node_endpoints(version, &reader.tagged_fields()?)?;
+        reader.finish()?;
+        Ok(Self {
+            error,
+            topics,
+            node_endpoints,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::requests::message_reader::MessageError;
+
+    fn request() -> VoteRequest {
+        VoteRequest {
+            cluster_id: Some("c".to_string()),
+            voter_id: 2,
+            topics: vec![VoteTopicRequest {
+                topic_name: "m".to_string(),
+                partitions: vec![VotePartitionRequest {
+                    partition_index: 0,
+                    replica_epoch: 5,
+                    replica_id: 1,
+                    replica_directory_id: Uuid::new(0, 1),
+                    voter_directory_id: Uuid::new(0, 2),
+                    last_offset_epoch: 4,
+                    last_offset: 100,
+                    pre_vote: true,
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_request() {
+        let mut buf = Vec::new();
+        request().write_into(0, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            // cluster id
+            2, b'c',
+            // topics
+            2, 2, b'm',
+            // partitions
+            2, 0, 0, 0, 0,
+            0, 0, 0, 5, 0, 0, 0, 1,
+            0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 100,
+            0, 0, 0,
+        ];
+        assert_eq!(expected, buf);
+
+        let mut buf = Vec::new();
+        request().write_into(2, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            2, b'c',
+            // voter id
+            0, 0, 0, 2,
+            2, 2, b'm',
+            2, 0, 0, 0, 0,
+            0, 0, 0, 5, 0, 0, 0, 1,
+            // directory ids
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2,
+            0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 100,
+            // pre-vote
+            1,
+            0, 0, 0,
+        ];
+        assert_eq!(expected, buf);
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let api = RaftApiKeys::Vote;
+        for version in api.oldest_version()..=api.latest_version() {
+            let mut expected = request();
+            if version < 1 {
+                expected.voter_id = -1;
+                let partition = &mut expected.topics[0].partitions[0];
+                partition.replica_directory_id = Uuid::ZERO;
+                partition.voter_directory_id = Uuid::ZERO;
+            }
+            if version < 2 {
+                expected.topics[0].partitions[0].pre_vote = false;
+            }
+            let mut buf = Vec::new();
+            request().write_into(version, &mut buf);
+            assert_eq!(Ok(expected), VoteRequest::parse(version, &buf), "{version}");
+        }
+        assert_eq!(
+            Err(MessageError::UnsupportedVersion("Vote", 3)),
+            VoteRequest::parse(3, &[])
+        );
+    }
+
+    fn response() -> VoteResponse {
+        VoteResponse {
+            error: Errors::None,
+            topics: vec![VoteTopicResponse {
+                topic_name: "m".to_string(),
+                partitions: vec![VotePartitionResponse {
+                    partition_index: 0,
+                    error: Errors::None,
+                    leader_id: 1,
+                    leader_epoch: 5,
+                    vote_granted: true,
+                }],
+            }],
+            node_endpoints: vec![NodeEndpoint {
+                node_id: 1,
+                host: "h".to_string(),
+                port: 9092,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_write_response() {
+        let mut buf = Vec::new();
+        response().write_to(7, 1, &mut buf);
+        #[rustfmt::skip]
+        let expected = vec![
+            // header
+            0, 0, 0, 7, 0,
+            // error code
+            0, 0,
+            2, 2, b'm',
+            2, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 1, 0, 0, 0, 5,
+            // vote granted
+            1,
+            0, 0,
+            // the node endpoints, tag 0 of 10 bytes
+            1, 0, 10,
+            2, 0, 0, 0, 1, 2, b'h', 0x23, 0x84, 0,
+        ];
+        assert_eq!(expected, buf);
+
+        let api = RaftApiKeys::Vote;
+        for version in api.oldest_version()..=api.latest_version() {
+            let mut expected = response();
+            if version < 1 {
+                expected.node_endpoints.clear();
+            }
+            let mut buf = Vec::new();
+            response().write_into(7, version, &mut buf);
+            // The header: the correlation id and its tagged fields.
+            let body = &buf[5..];
+            assert_eq!(
+                Ok(expected),
+                VoteResponse::parse(version, body),
+                "{version}"
+            );
+            assert_eq!(buf.len(), response().size_in_bytes(version));
+        }
+    }
+}
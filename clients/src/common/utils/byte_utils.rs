@@ -501,11 +501,54 @@ pub fn write_unsigned_varint64<W: io::Write>(mut value: u64, writer: &mut W) ->
     Ok(())
 }
 
+/// Encodes an i64 using zig-zag encoding and writes it as a variable-length integer.
+///
+/// This is the counterpart of [`read_varint64`].
+///
+/// # Errors
+///
+/// This function will return an `Err` if the underlying write operation to the
+/// writer fails at any point.
+pub fn write_varint64<W: io::Write>(value: i64, writer: &mut W) -> VarintResult<()> {
+    write_unsigned_varint64(((value << 1) ^ (value >> 63)) as u64, writer)
+}
+
+/// The number of bytes [`write_unsigned_varint64`] takes to write `value`.
+pub fn size_of_unsigned_varint64(value: u64) -> usize {
+    // Every byte carries 7 bits of the value, and zero still takes one byte.
+    let bits = 64 - (value | 1).leading_zeros() as usize;
+    bits.div_ceil(7)
+}
+
+/// The number of bytes [`write_varint`] takes to write `value`.
+pub fn size_of_varint(value: i32) -> usize {
+    size_of_unsigned_varint64(((value << 1) ^ (value >> 31)) as u32 as u64)
+}
+
+/// The number of bytes [`write_varint64`] takes to write `value`.
+pub fn size_of_varint64(value: i64) -> usize {
+    size_of_unsigned_varint64(((value << 1) ^ (value >> 63)) as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::{Cursor, Read};
 
+    #[test]
+    fn test_size_of_varint() {
+        for value in [0, 1, -1, 63, -64, 64, 8191, -8192, 8192, i32::MAX, i32::MIN] {
+            let mut buf = Vec::new();
+            write_varint(value, &mut buf).unwrap();
+            assert_eq!(buf.len(), size_of_varint(value), "{value}");
+        }
+        for value in [0, -1, 1 << 35, -(1 << 50), i64::MAX, i64::MIN] {
+            let mut buf = Vec::new();
+            write_varint64(value, &mut buf).unwrap();
+            assert_eq!(buf.len(), size_of_varint64(value), "{value}");
+        }
+    }
+
     #[test]
     fn test_unsigned_varint_serde() {
         assert_unsigned_varint_serde(0, &[0x0]);
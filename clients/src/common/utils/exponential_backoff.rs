@@ -0,0 +1,92 @@
+/// Computes how long to wait before retrying an operation which failed a number of times in
+/// a row: `initial_interval * multiplier^attempts`, capped to `max_interval`.
+///
+/// With a non-zero `jitter`, the interval is randomized by up to `jitter` times its value in
+/// either direction, so that clients failing at the same time don't all retry at the same
+/// time. The randomized interval never exceeds `max_interval`.
+///
+/// A `multiplier` of 1 or less, which would never reach `max_interval`, makes the backoff
+/// constant: every attempt waits `initial_interval`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExponentialBackoff {
+    initial_interval: i64,
+    multiplier: f64,
+    max_interval: i64,
+    jitter: f64,
+    /// The attempts beyond which the interval is capped anyway.
+    exp_max: f64,
+}
+
+impl ExponentialBackoff {
+    pub fn new(initial_interval: i64, multiplier: f64, max_interval: i64, jitter: f64) -> Self {
+        let initial_interval = initial_interval.clamp(0, max_interval.max(0));
+        let grows = initial_interval > 0 && multiplier > 1.0;
+        let exp_max = if grows && max_interval > initial_interval {
+            (max_interval as f64 / initial_interval as f64).ln() / multiplier.ln()
+        } else {
+            0.0
+        };
+        Self {
+            initial_interval,
+            multiplier,
+            max_interval,
+            jitter,
+            exp_max,
+        }
+    }
+
+    /// The interval to wait after `attempts` failures beyond the first, i.e. the initial
+    /// interval for 0.
+    pub fn backoff(&self, attempts: u32) -> i64 {
+        if self.exp_max == 0.0 {
+            return self.initial_interval;
+        }
+        let exp = (attempts as f64).min(self.exp_max);
+        let term = self.initial_interval as f64 * self.multiplier.powf(exp);
+        let random_factor = if self.jitter < f64::EPSILON {
+            1.0
+        } else {
+            rand::random_range(1.0 - self.jitter..1.0 + self.jitter)
+        };
+        ((term * random_factor) as i64).min(self.max_interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_up_to_the_max() {
+        let backoff = ExponentialBackoff::new(100, 2.0, 1000, 0.0);
+        let intervals: Vec<i64> = (0..6).map(|attempts| backoff.backoff(attempts)).collect();
+        assert_eq!(vec![100, 200, 400, 800, 1000, 1000], intervals);
+        assert_eq!(1000, backoff.backoff(u32::MAX));
+    }
+
+    #[test]
+    fn test_jitter() {
+        let backoff = ExponentialBackoff::new(100, 2.0, 10_000, 0.2);
+        for _ in 0..100 {
+            let interval = backoff.backoff(2);
+            assert!((320..=480).contains(&interval), "{interval}");
+        }
+        let capped = ExponentialBackoff::new(100, 2.0, 1000, 0.5);
+        assert!(capped.backoff(10) <= 1000);
+    }
+
+    #[test]
+    fn test_constant_backoff() {
+        assert_eq!(500, ExponentialBackoff::new(500, 2.0, 500, 0.0).backoff(3));
+        assert_eq!(0, ExponentialBackoff::new(0, 2.0, 1000, 0.0).backoff(3));
+        assert_eq!(
+            1000,
+            ExponentialBackoff::new(2000, 2.0, 1000, 0.0).backoff(0)
+        );
+        for multiplier in [1.0, 0.5, 0.0, -2.0, f64::NAN] {
+            let backoff = ExponentialBackoff::new(100, multiplier, 1000, 0.0);
+            assert_eq!(100, backoff.backoff(0), "{multiplier}");
+            assert_eq!(100, backoff.backoff(u32::MAX), "{multiplier}");
+        }
+    }
+}
@@ -1,3 +1,6 @@
 pub mod macros;
 pub mod utils;
 pub mod byte_utils;
+pub mod crc32;
+pub mod exponential_backoff;
+pub mod time;
@@ -0,0 +1,141 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime as StdSystemTime, UNIX_EPOCH};
+
+/// An interface abstracting the clock, so time-dependent code can be tested with [MockTime].
+pub trait Time: Send + Sync {
+    /// Returns the current wall-clock time in milliseconds.
+    fn milliseconds(&self) -> i64;
+
+    /// Returns the value of a monotonic clock in nanoseconds. Only differences between two
+    /// readings are meaningful.
+    fn nanoseconds(&self) -> i64;
+
+    /// Sleeps for the given number of milliseconds.
+    fn sleep(&self, ms: i64);
+
+    /// Returns the value of the monotonic clock in milliseconds.
+    fn hi_res_clock_ms(&self) -> i64 {
+        self.nanoseconds() / 1_000_000
+    }
+}
+
+/// A [Time] implementation backed by the system clocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemTime;
+
+/// A process-wide anchor for the monotonic clock, so `nanoseconds` fits in an `i64`.
+static MONOTONIC_ANCHOR: once_cell::sync::Lazy<Instant> = once_cell::sync::Lazy::new(Instant::now);
+
+impl Time for SystemTime {
+    fn milliseconds(&self) -> i64 {
+        StdSystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as i64)
+    }
+
+    fn nanoseconds(&self) -> i64 {
+        MONOTONIC_ANCHOR.elapsed().as_nanos() as i64
+    }
+
+    fn sleep(&self, ms: i64) {
+        if ms > 0 {
+            thread::sleep(Duration::from_millis(ms as u64));
+        }
+    }
+}
+
+/// A [Time] implementation whose clock only moves when told to.
+///
+/// `sleep` advances the clock instead of blocking, which makes timeouts and deadlines
+/// deterministic in tests.
+#[derive(Debug)]
+pub struct MockTime {
+    time_ms: AtomicI64,
+    high_res_time_ns: AtomicI64,
+    auto_tick_ms: i64,
+}
+
+impl Default for MockTime {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl MockTime {
+    /// Creates a clock which advances by `auto_tick_ms` every time it is read.
+    pub fn new(auto_tick_ms: i64) -> Self {
+        Self::with_start(
+            auto_tick_ms,
+            SystemTime.milliseconds(),
+            SystemTime.nanoseconds(),
+        )
+    }
+
+    pub fn with_start(
+        auto_tick_ms: i64,
+        current_time_ms: i64,
+        current_high_res_time_ns: i64,
+    ) -> Self {
+        Self {
+            time_ms: AtomicI64::new(current_time_ms),
+            high_res_time_ns: AtomicI64::new(current_high_res_time_ns),
+            auto_tick_ms,
+        }
+    }
+
+    fn maybe_sleep(&self, ms: i64) {
+        if ms != 0 {
+            self.sleep(ms);
+        }
+    }
+}
+
+impl Time for MockTime {
+    fn milliseconds(&self) -> i64 {
+        self.maybe_sleep(self.auto_tick_ms);
+        self.time_ms.load(Ordering::SeqCst)
+    }
+
+    fn nanoseconds(&self) -> i64 {
+        self.maybe_sleep(self.auto_tick_ms);
+        self.high_res_time_ns.load(Ordering::SeqCst)
+    }
+
+    fn sleep(&self, ms: i64) {
+        self.time_ms.fetch_add(ms, Ordering::SeqCst);
+        self.high_res_time_ns
+            .fetch_add(ms * 1_000_000, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_time_advances_only_on_sleep() {
+        let time = MockTime::with_start(0, 1_000, 5_000_000);
+        assert_eq!(1_000, time.milliseconds());
+        assert_eq!(5_000_000, time.nanoseconds());
+
+        time.sleep(10);
+        assert_eq!(1_010, time.milliseconds());
+        assert_eq!(15_000_000, time.nanoseconds());
+        assert_eq!(15, time.hi_res_clock_ms());
+    }
+
+    #[test]
+    fn test_mock_time_auto_tick() {
+        let time = MockTime::with_start(2, 0, 0);
+        assert_eq!(2, time.milliseconds());
+        assert_eq!(4, time.milliseconds());
+    }
+
+    #[test]
+    fn test_system_time_is_monotonic() {
+        let first = SystemTime.nanoseconds();
+        let second = SystemTime.nanoseconds();
+        assert!(second >= first);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+use thiserror::Error;
+
+const BASE64_URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid UUID string: {0}")]
+pub struct UuidParseError(String);
+
+/// A 128-bit universally unique identifier, used for topic ids, incarnation ids and log
+/// directory ids.
+///
+/// Its string form is the URL-safe base64 encoding of its 16 bytes without padding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Uuid {
+    most_significant_bits: i64,
+    least_significant_bits: i64,
+}
+
+impl Uuid {
+    /// The all-zero UUID, used to denote a null id.
+    pub const ZERO: Uuid = Uuid::new(0, 0);
+
+    /// A UUID for the metadata topic in KRaft mode; never returned by [Uuid::random].
+    pub const METADATA_TOPIC_ID: Uuid = Uuid::new(0, 1);
+
+    pub const fn new(most_significant_bits: i64, least_significant_bits: i64) -> Self {
+        Self {
+            most_significant_bits,
+            least_significant_bits,
+        }
+    }
+
+    /// Generates a random UUID which is neither reserved nor starts with a dash, so its
+    /// string form can't be mistaken for a command line flag.
+    pub fn random() -> Self {
+        loop {
+            let uuid = Self::new(rand::random::<i64>(), rand::random::<i64>());
+            if !uuid.is_reserved() && !uuid.to_string().starts_with('-') {
+                return uuid;
+            }
+        }
+    }
+
+    pub fn most_significant_bits(&self) -> i64 {
+        self.most_significant_bits
+    }
+
+    pub fn least_significant_bits(&self) -> i64 {
+        self.least_significant_bits
+    }
+
+    /// Whether this is one of the first 100 UUIDs, which are reserved for special meanings.
+    pub fn is_reserved(&self) -> bool {
+        self.most_significant_bits == 0 && (0..100).contains(&self.least_significant_bits)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.most_significant_bits.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.least_significant_bits.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self::new(
+            i64::from_be_bytes(bytes[..8].try_into().expect("8 bytes")),
+            i64::from_be_bytes(bytes[8..].try_into().expect("8 bytes")),
+        )
+    }
+
+    /// Parses the base64 string form produced by `to_string`.
+    pub fn from_string(s: &str) -> Result<Self, UuidParseError> {
+        let invalid = || UuidParseError(s.to_string());
+        if s.len() != 22 {
+            return Err(invalid());
+        }
+        let mut digits = s.bytes().map(|c| {
+            BASE64_URL_ALPHABET
+                .iter()
+                .position(|&a| a == c)
+                .map(|d| d as u128)
+                .ok_or_else(invalid)
+        });
+        // 22 digits carry 132 bits: the first 21 hold the top 126 bits of the UUID, the last
+        // one the remaining 2 bits followed by 4 bits of padding.
+        let mut bits: u128 = 0;
+        for digit in digits.by_ref().take(21) {
+            bits = (bits << 6) | digit?;
+        }
+        let last = digits.next().ok_or_else(invalid)??;
+        if last & 0xf != 0 {
+            return Err(invalid());
+        }
+        Ok(Self::from_bytes(((bits << 2) | (last >> 4)).to_be_bytes()))
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bits = u128::from_be_bytes(self.to_bytes());
+        let encoded: String = (0..22)
+            .map(|i| {
+                let digit = match 122 - 6 * i {
+                    shift if shift >= 0 => bits >> shift,
+                    shift => bits << -shift,
+                };
+                BASE64_URL_ALPHABET[(digit & 0x3f) as usize] as char
+            })
+            .collect();
+        f.write_str(&encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_round_trip() {
+        assert_eq!("AAAAAAAAAAAAAAAAAAAAAA", Uuid::ZERO.to_string());
+        assert_eq!(
+            "AAAAAAAAAAAAAAAAAAAAAQ",
+            Uuid::METADATA_TOPIC_ID.to_string()
+        );
+        let uuid = Uuid::new(0x1234_5678_9abc_def0, -1);
+        assert_eq!(uuid, Uuid::from_string(&uuid.to_string()).unwrap());
+        assert!(Uuid::from_string("too-short").is_err());
+    }
+
+    #[test]
+    fn test_random_is_not_reserved() {
+        for _ in 0..100 {
+            let uuid = Uuid::random();
+            assert!(!uuid.is_reserved());
+            assert!(!uuid.to_string().starts_with('-'));
+        }
+    }
+}
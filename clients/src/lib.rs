@@ -1,3 +1,4 @@
+pub mod clients;
 pub mod common;
 
 pub mod test;
@@ -0,0 +1,151 @@
+use clap::{Parser, Subcommand};
+use rafka_group_coordinator::offset_export::{ExportedOffset, GroupOffsetsExport};
+use rafka_storage::log_dir_lock::LogDirLock;
+use rafka_storage::{PartitionLog, UnifiedLog, UnifiedLogConfig};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Converts the committed offsets of a consumer group between the JSON format rafka exports
+/// and imports, and the CSV format of Apache Kafka's `kafka-consumer-groups.sh
+/// --reset-offsets --export` and `--from-file`, and computes the offsets a group is reset to
+/// from the logs of a stopped broker.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Converts the CSV offsets of a group exported from Apache Kafka to JSON.
+    FromCsv {
+        /// The group the offsets belong to.
+        #[arg(long)]
+        group: String,
+        file: PathBuf,
+    },
+    /// Converts JSON offsets to CSV, to reset a group of Apache Kafka with `--from-file`.
+    ToCsv { file: PathBuf },
+    /// Checks the JSON offsets can be imported.
+    Validate { file: PathBuf },
+    /// Prints the JSON offsets resetting a group to the first records of a topic produced at
+    /// or after a timestamp, like `--reset-offsets --to-datetime`. The broker owning the log
+    /// directory must be stopped.
+    ResetToTimestamp {
+        /// The group the offsets belong to.
+        #[arg(long)]
+        group: String,
+        #[arg(long)]
+        topic: String,
+        /// Milliseconds since the epoch.
+        #[arg(long)]
+        timestamp: i64,
+        /// The log directory holding the partitions of the topic.
+        log_dir: PathBuf,
+    },
+}
+
+fn reset_to_timestamp(
+    group: String,
+    topic: &str,
+    timestamp: i64,
+    log_dir: &Path,
+) -> Result<GroupOffsetsExport, Box<dyn Error>> {
+    let _lock = LogDirLock::acquire(log_dir)?;
+    let mut offsets = vec![];
+    for entry in fs::read_dir(log_dir)? {
+        let dir = entry?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        let Ok(topic_partition) = UnifiedLog::parse_topic_partition_name(&dir) else {
+            continue;
+        };
+        if topic_partition.topic() != topic {
+            continue;
+        }
+        let log = UnifiedLog::open(&dir, UnifiedLogConfig::default(), 0)?;
+        let offset = log.offset_for_timestamp_reset(timestamp)?;
+        offsets.push(ExportedOffset {
+            topic: topic.to_string(),
+            partition: topic_partition.partition(),
+            offset,
+            leader_epoch: log.leader_epoch_for_offset(offset),
+            metadata: String::new(),
+        });
+    }
+    offsets.sort_by_key(|o| o.partition);
+    Ok(GroupOffsetsExport::new(group, offsets))
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    match Args::parse().command {
+        Command::FromCsv { group, file } => {
+            let export = GroupOffsetsExport::from_csv(group, &fs::read_to_string(file)?)?;
+            println!("{}", export.to_json());
+        }
+        Command::ToCsv { file } => {
+            let export = GroupOffsetsExport::from_json(&fs::read_to_string(file)?)?;
+            print!("{}", export.to_csv());
+        }
+        Command::Validate { file } => {
+            let export = GroupOffsetsExport::from_json(&fs::read_to_string(file)?)?;
+            println!(
+                "{} offsets of group {} can be imported",
+                export.offsets.len(),
+                export.group_id
+            );
+        }
+        Command::ResetToTimestamp {
+            group,
+            topic,
+            timestamp,
+            log_dir,
+        } => {
+            let export = reset_to_timestamp(group, &topic, timestamp, &log_dir)?;
+            println!("{}", export.to_json());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+
+    fn write_log(log_dir: &Path, name: &str, timestamps: &[i64]) {
+        let mut log =
+            UnifiedLog::open(&log_dir.join(name), UnifiedLogConfig::default(), 0).unwrap();
+        let records = timestamps
+            .iter()
+            .map(|t| Record::new(*t, None, Some(b"value")))
+            .collect();
+        let mut batch = RecordBatch::new(0, records);
+        batch.set_partition_leader_epoch(3);
+        log.append_as_follower(batch).unwrap();
+        log.flush().unwrap();
+    }
+
+    #[test]
+    fn test_reset_to_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        write_log(dir.path(), "foo-1", &[5]);
+        write_log(dir.path(), "foo-0", &[10, 30, 20]);
+        write_log(dir.path(), "bar-0", &[40]);
+        fs::write(dir.path().join("meta.properties"), "").unwrap();
+
+        let export = reset_to_timestamp("group".to_string(), "foo", 20, dir.path()).unwrap();
+        assert_eq!("group", export.group_id);
+        let offsets = export
+            .offsets
+            .iter()
+            .map(|o| (o.topic.as_str(), o.partition, o.offset, o.leader_epoch))
+            .collect::<Vec<_>>();
+        // Every record of foo-1 is older, so the group moves to its log end offset, which no
+        // epoch has written to yet.
+        assert_eq!(vec![("foo", 0, 1, Some(3)), ("foo", 1, 1, None)], offsets);
+    }
+}
@@ -0,0 +1,109 @@
+use clap::Parser;
+use rafka_storage::UnifiedLog;
+use rafka_storage::log_verifier::{self, SegmentBatch};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Dumps the batches of the segments of partition logs, or with `--verify` audits their
+/// integrity: the crc of every batch, the offsets increasing from batch to batch and the
+/// entries of the time indexes increasing within their segments. The files are only read, so
+/// the broker may keep running.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// The directories of the partitions, or log directories holding them.
+    #[arg(required = true)]
+    dirs: Vec<PathBuf>,
+    /// Verifies the logs instead of dumping them, reporting the first corrupt offset of each
+    /// partition. Exits with a failure if any is corrupt.
+    #[arg(long)]
+    verify: bool,
+}
+
+/// The partition directories among `dirs` and in the log directories among them.
+fn partition_dirs(dirs: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut partitions = vec![];
+    for dir in dirs {
+        if UnifiedLog::parse_topic_partition_name(dir).is_ok() {
+            partitions.push(dir.clone());
+            continue;
+        }
+        let mut entries = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() && UnifiedLog::parse_topic_partition_name(&path).is_ok() {
+                entries.push(path);
+            }
+        }
+        entries.sort();
+        partitions.extend(entries);
+    }
+    Ok(partitions)
+}
+
+fn dump(dir: &Path) -> Result<bool, Box<dyn Error>> {
+    println!("Dumping {}", dir.display());
+    let mut next_offset = i64::MIN;
+    for base_offset in log_verifier::segment_base_offsets(dir)? {
+        println!("Segment {base_offset}");
+        let corruption =
+            log_verifier::scan_segment(dir, base_offset, next_offset, |read: &SegmentBatch| {
+                let batch = &read.batch;
+                println!(
+                    "baseOffset: {} lastOffset: {} count: {} partitionLeaderEpoch: {} \
+                     producerId: {} producerEpoch: {} baseSequence: {} isTransactional: {} \
+                     isControl: {} position: {} {}: {} size: {}",
+                    batch.base_offset(),
+                    batch.last_offset(),
+                    batch.records().len(),
+                    batch.partition_leader_epoch(),
+                    batch.producer_id(),
+                    batch.producer_epoch(),
+                    batch.base_sequence(),
+                    batch.is_transactional(),
+                    batch.is_control(),
+                    read.position,
+                    batch.timestamp_type().name(),
+                    batch.max_timestamp(),
+                    read.size
+                );
+                next_offset = batch.last_offset() + 1;
+            })?;
+        if let Some(corruption) = corruption {
+            println!("Stopping at the corrupt batch at {corruption}");
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let args = Args::parse();
+    let mut valid = true;
+    for dir in partition_dirs(&args.dirs)? {
+        if !args.verify {
+            valid &= dump(&dir)?;
+            continue;
+        }
+        let verification = log_verifier::verify_log(&dir)?;
+        match &verification.corruption {
+            None => println!(
+                "{}: {} segments and {} batches verified",
+                verification.topic_partition, verification.segments, verification.batches
+            ),
+            Some(corruption) => {
+                valid = false;
+                println!(
+                    "{}: corrupt from {corruption}",
+                    verification.topic_partition
+                );
+            }
+        }
+    }
+    Ok(match valid {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
@@ -0,0 +1,53 @@
+use clap::Parser;
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_group_coordinator::offset_export::GroupOffsetsExport;
+use rafka_storage::log_dir_lock::LogDirLock;
+use rafka_storage::log_report::PartitionLogReport;
+use rafka_storage::{LogDirReport, UnifiedLog, UnifiedLogConfig};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// Reports the segment counts and sizes, the produce rates and the consumer lag of the
+/// partitions of a log directory, per partition and per topic, to find the partitions and
+/// topics taking more than their share of the load. The broker owning the log directory must
+/// be stopped.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// The log directory holding the partitions.
+    log_dir: PathBuf,
+    /// The JSON offsets of a group, as exported by `rafka-consumer-offsets`, to compute the
+    /// consumer lag from. Can be repeated.
+    #[arg(long)]
+    offsets: Vec<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let _lock = LogDirLock::acquire(&args.log_dir)?;
+    let mut report = LogDirReport::default();
+    for entry in fs::read_dir(&args.log_dir)? {
+        let dir = entry?.path();
+        if !dir.is_dir() || UnifiedLog::parse_topic_partition_name(&dir).is_err() {
+            continue;
+        }
+        let log = UnifiedLog::open(&dir, UnifiedLogConfig::default(), 0)?;
+        report.add(PartitionLogReport::of(&log)?);
+    }
+    for file in args.offsets {
+        let export = GroupOffsetsExport::from_json(&fs::read_to_string(file)?)?;
+        for offset in &export.offsets {
+            let topic_partition = TopicPartition::new(&offset.topic, offset.partition);
+            if !report.add_committed_offset(&export.group_id, &topic_partition, offset.offset) {
+                eprintln!(
+                    "Ignoring the offset of group {} on {topic_partition}, which is not in {}",
+                    export.group_id,
+                    args.log_dir.display()
+                );
+            }
+        }
+    }
+    print!("{report}");
+    Ok(())
+}
@@ -3,9 +3,10 @@ mod server;
 #[cfg(test)]
 pub mod test;
 
+use crate::server::config_sources::EffectiveConfig;
 use crate::server::rafka_config::RafkaConfig;
 use crate::server::rafka_raft_server::RaftServer;
-use crate::server::{Result, Server};
+use crate::server::{Result, Server, ServerError};
 use clap::Parser;
 use easy_config_def::FromConfigDef;
 use rafka_clients::common::utils::utils::load_props;
@@ -13,7 +14,7 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::iter::Map;
 use tokio::signal;
-use tracing::{debug, info};
+use tracing::info;
 
 /// A Kafka-compatible broker implemented in Rust.
 #[derive(Parser, Debug)]
@@ -23,28 +24,36 @@ struct Args {
     #[arg(name = "server.properties")]
     server_properties_file: String,
 
-    /// Optional configuration overrides.
+    /// Overrides a config of the properties file, as `name=value`. May be repeated.
+    #[arg(long = "override", value_name = "name=value")]
+    override_opt: Vec<String>,
+
+    /// Validates the config, prints the effective config with the source of each value and
+    /// exits, without starting the node.
     #[arg(long)]
-    override_opt: Vec<String>, // Captures any --override options, though we won't use them yet.
+    validate_only: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     set_up_logging()?;
-    let server_props = get_props_from_args(Args::parse());
-    debug!("{server_props:?}");
-    let server = build_server(server_props);
-
-    //server.startup().await?;
-
-    // tokio::select! {
-    //     _ = signal::ctrl_c() => {
-    //         // The shutdown signal has been received.
-    //         info!("shutting down");
-    //     }
-    // }
-
-    //server.await_shutdown().await?;
+    let args = Args::parse();
+    let config = get_config_from_args(&args)?;
+    if args.validate_only {
+        return validate(&config);
+    }
+    let server = build_server(config.props())?;
+
+    server.startup().await?;
+
+    tokio::select! {
+        _ = signal::ctrl_c() => {
+            // The shutdown signal has been received.
+            info!("shutting down");
+            server.shutdown().await?;
+        }
+        result = server.await_shutdown() => result?,
+    }
 
     Ok(())
 }
@@ -53,14 +62,24 @@ fn set_up_logging() -> std::result::Result<(), Box<dyn Error + Send + Sync + 'st
     // See https://docs.rs/tracing for more info
     tracing_subscriber::fmt::try_init()
 }
-fn get_props_from_args(args: Args) -> HashMap<String, String> {
-    load_props(args.server_properties_file.as_str()).expect("Error loading properties file")
+fn get_config_from_args(args: &Args) -> Result<EffectiveConfig> {
+    let props = load_props(args.server_properties_file.as_str())?;
+    EffectiveConfig::new(&args.server_properties_file, props, &args.override_opt)
+}
+
+/// Runs the checks of a startup, from the parsing of the configs to the resolution of the
+/// listeners and the cross-checks of the quorum configs, and prints the effective config if
+/// they pass.
+fn validate(config: &EffectiveConfig) -> Result<()> {
+    build_server(config.props())?;
+    print!("{config}");
+    println!("The configuration is valid.");
+    Ok(())
 }
 
-fn build_server(props: HashMap<String, String>) {
-    let config = RafkaConfig::from_props(&props);
-    debug!("{config:?}");
-    //RaftServer::new()
+fn build_server(props: HashMap<String, String>) -> Result<RaftServer> {
+    let config = RafkaConfig::from_props(&props).map_err(|e| ServerError::Err(e.into()))?;
+    RaftServer::new(config, &props)
 }
 
 async fn run_broker(args: Args) -> std::result::Result<(), Box<dyn std::error::Error>> {
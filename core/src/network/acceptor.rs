@@ -1,12 +1,12 @@
+use crate::server::rafka_config::RafkaConfig;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tokio::sync::{broadcast, mpsc, Semaphore};
-use crate::server::rafka_config::RafkaConfig;
+use tokio::sync::{Semaphore, broadcast, mpsc};
 
 #[derive(Debug)]
 struct Acceptor {
     config: Arc<RafkaConfig>,
-    
+
     /// TCP listener supplied by the `SocketServer`.
     listener: TcpListener,
 
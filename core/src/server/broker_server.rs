@@ -0,0 +1,152 @@
+use crate::server::lifecycle_manager::{Component, ComponentFuture};
+use crate::server::shared_server::SharedServer;
+use crate::server::{Result, Server, ServerError};
+use rafka_clients::common::endpoint::Endpoint;
+use rafka_metadata::metadata_publisher::MetadataSubscriber;
+use rafka_metadata::{BrokerMetadataListener, LocalImageStore};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::info;
+
+/// The broker role of a node: serves the clients on the broker listeners.
+pub(crate) struct BrokerServer {
+    shared: Arc<SharedServer>,
+    /// The listeners the broker registers with the controller.
+    advertised_listeners: Vec<Endpoint>,
+    /// Where the metadata image the broker starts from is saved.
+    image_store: Option<LocalImageStore>,
+    /// The components applying the metadata, subscribed to its changes on startup.
+    metadata_subscribers: Vec<Arc<dyn MetadataSubscriber>>,
+    metadata_listener: Mutex<Option<BrokerMetadataListener>>,
+}
+
+impl fmt::Debug for BrokerServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BrokerServer")
+            .field("shared", &self.shared)
+            .field("advertised_listeners", &self.advertised_listeners)
+            .field("image_store", &self.image_store)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BrokerServer {
+    pub fn new(shared: Arc<SharedServer>, advertised_listeners: Vec<Endpoint>) -> Self {
+        Self {
+            shared,
+            advertised_listeners,
+            image_store: None,
+            metadata_subscribers: vec![],
+            metadata_listener: Mutex::new(None),
+        }
+    }
+
+    pub fn with_image_store(mut self, image_store: LocalImageStore) -> Self {
+        self.image_store = Some(image_store);
+        self
+    }
+
+    pub fn with_metadata_subscriber(mut self, subscriber: Arc<dyn MetadataSubscriber>) -> Self {
+        self.metadata_subscribers.push(subscriber);
+        self
+    }
+
+    pub fn advertised_listeners(&self) -> &[Endpoint] {
+        &self.advertised_listeners
+    }
+
+    fn metadata_listener(&self) -> MutexGuard<'_, Option<BrokerMetadataListener>> {
+        self.metadata_listener
+            .lock()
+            .expect("metadata listener lock poisoned")
+    }
+
+    /// Loads the saved metadata image and subscribes the metadata subscribers to it, so that
+    /// they start from the image before the changes replayed after it.
+    fn start_metadata_listener(&self, image_store: &LocalImageStore) -> Result<()> {
+        let listener = BrokerMetadataListener::load(image_store.clone())
+            .map_err(|e| ServerError::Err(e.into()))?;
+        let publisher = listener.publisher();
+        for subscriber in &self.metadata_subscribers {
+            publisher.subscribe(Arc::clone(subscriber));
+        }
+        *self.metadata_listener() = Some(listener);
+        Ok(())
+    }
+}
+
+impl Server for BrokerServer {
+    async fn startup(&self) -> Result<()> {
+        info!(
+            "Starting broker {} advertising {:?}",
+            self.shared.node_id(),
+            self.advertised_listeners()
+        );
+        if let Some(image_store) = &self.image_store {
+            self.start_metadata_listener(image_store)?;
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        info!("Shutting down broker {}", self.shared.node_id());
+        self.metadata_listener().take();
+        Ok(())
+    }
+
+    async fn await_shutdown(&self) -> Result<()> {
+        self.shared.await_shutdown().await
+    }
+}
+
+impl Component for BrokerServer {
+    fn startup(&self) -> ComponentFuture<'_> {
+        Box::pin(Server::startup(self))
+    }
+
+    fn shutdown(&self) -> ComponentFuture<'_> {
+        Box::pin(Server::shutdown(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_metadata::metadata_publisher::MetadataUpdate;
+    use rafka_server::raft_config::ProcessRole;
+    use std::collections::BTreeSet;
+
+    #[derive(Default)]
+    struct RecordingSubscriber {
+        snapshots: Mutex<usize>,
+    }
+
+    impl MetadataSubscriber for RecordingSubscriber {
+        fn name(&self) -> &str {
+            "RecordingSubscriber"
+        }
+
+        fn on_update(&self, update: &MetadataUpdate<'_>) {
+            if let MetadataUpdate::Snapshot(_) = update {
+                *self.snapshots.lock().unwrap() += 1;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_startup_subscribes_the_metadata_subscribers() {
+        let dir = tempfile::tempdir().unwrap();
+        let subscriber = Arc::new(RecordingSubscriber::default());
+        let shared = Arc::new(SharedServer::new(1, BTreeSet::from([ProcessRole::Broker])));
+        let broker = BrokerServer::new(shared, vec![])
+            .with_image_store(LocalImageStore::new(dir.path()))
+            .with_metadata_subscriber(subscriber.clone());
+
+        Server::startup(&broker).await.unwrap();
+        // Sent the image on subscribing.
+        assert_eq!(1, *subscriber.snapshots.lock().unwrap());
+        assert!(broker.metadata_listener().is_some());
+        Server::shutdown(&broker).await.unwrap();
+        assert!(broker.metadata_listener().is_none());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::server::startup_report::{HIDDEN, is_password};
+use crate::server::{Result, ServerError};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Where the value of a config comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ConfigSource {
+    /// The properties file the node was started with.
+    File(String),
+    /// A `--override` option, with the value of the properties file it replaces, if any.
+    Override { replaced: Option<String> },
+}
+
+/// The configs a node is started with: those of its properties file, with the `--override`
+/// options applied on top.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct EffectiveConfig {
+    configs: BTreeMap<String, (String, ConfigSource)>,
+}
+
+impl EffectiveConfig {
+    /// The configs of the properties file at `path`, holding `props`, overridden by the
+    /// `name=value` options of `overrides`.
+    pub fn new(path: &str, props: HashMap<String, String>, overrides: &[String]) -> Result<Self> {
+        let mut configs: BTreeMap<String, (String, ConfigSource)> = props
+            .into_iter()
+            .map(|(name, value)| (name, (value, ConfigSource::File(path.to_string()))))
+            .collect();
+        for option in overrides {
+            let (name, value) = option
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| {
+                    ServerError::Err(
+                        format!("Invalid override {option}, expected name=value").into(),
+                    )
+                })?;
+            let replaced = configs
+                .remove(name)
+                .and_then(|(value, source)| match source {
+                    ConfigSource::File(_) => Some(value),
+                    // The last override of a config wins, against the file.
+                    ConfigSource::Override { replaced } => replaced,
+                });
+            configs.insert(
+                name.to_string(),
+                (value.to_string(), ConfigSource::Override { replaced }),
+            );
+        }
+        Ok(Self { configs })
+    }
+
+    /// The configs and their values, to parse the node's config from.
+    pub fn props(&self) -> HashMap<String, String> {
+        self.configs
+            .iter()
+            .map(|(name, (value, _))| (name.clone(), value.clone()))
+            .collect()
+    }
+}
+
+impl fmt::Display for EffectiveConfig {
+    /// One config per line, as `name=value (source)`, with the passwords hidden.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, (value, source)) in &self.configs {
+            let shown = |value: &str| match is_password(name) {
+                true => HIDDEN.to_string(),
+                false => value.to_string(),
+            };
+            write!(f, "{name}={} ", shown(value))?;
+            match source {
+                ConfigSource::File(path) => writeln!(f, "({path})")?,
+                ConfigSource::Override { replaced: None } => writeln!(f, "(--override)")?,
+                ConfigSource::Override {
+                    replaced: Some(replaced),
+                } => writeln!(f, "(--override, was {})", shown(replaced))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_apply_on_top_of_the_file() {
+        let props = HashMap::from([
+            ("node.id".to_string(), "1".to_string()),
+            ("log.dirs".to_string(), "/data".to_string()),
+            ("ssl.key.password".to_string(), "hunter2".to_string()),
+        ]);
+        let overrides = [
+            "node.id=2".to_string(),
+            "node.id = 3".to_string(),
+            "process.roles=broker".to_string(),
+        ];
+        let config = EffectiveConfig::new("server.properties", props, &overrides).unwrap();
+
+        assert_eq!(Some(&"3".to_string()), config.props().get("node.id"));
+        assert_eq!(
+            "log.dirs=/data (server.properties)\n\
+             node.id=3 (--override, was 1)\n\
+             process.roles=broker (--override)\n\
+             ssl.key.password=[hidden] (server.properties)\n",
+            config.to_string()
+        );
+
+        assert!(
+            EffectiveConfig::new("server.properties", HashMap::new(), &["=1".to_string()]).is_err()
+        );
+        assert!(
+            EffectiveConfig::new(
+                "server.properties",
+                HashMap::new(),
+                &["node.id".to_string()]
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::server::lifecycle_manager::{Component, ComponentFuture};
+use crate::server::shared_server::SharedServer;
+use crate::server::{Result, Server};
+use rafka_clients::common::endpoint::Endpoint;
+use std::sync::Arc;
+use tracing::info;
+
+/// The controller role of a node: a voter of the metadata quorum, serving the brokers on
+/// the controller listeners.
+#[derive(Debug)]
+pub(crate) struct ControllerServer {
+    shared: Arc<SharedServer>,
+    controller_listeners: Vec<Endpoint>,
+}
+
+impl ControllerServer {
+    pub fn new(shared: Arc<SharedServer>, controller_listeners: Vec<Endpoint>) -> Self {
+        Self {
+            shared,
+            controller_listeners,
+        }
+    }
+
+    pub fn controller_listeners(&self) -> &[Endpoint] {
+        &self.controller_listeners
+    }
+}
+
+impl Server for ControllerServer {
+    async fn startup(&self) -> Result<()> {
+        info!(
+            "Starting controller {} on {:?}",
+            self.shared.node_id(),
+            self.controller_listeners()
+        );
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        info!("Shutting down controller {}", self.shared.node_id());
+        Ok(())
+    }
+
+    async fn await_shutdown(&self) -> Result<()> {
+        self.shared.await_shutdown().await
+    }
+}
+
+impl Component for ControllerServer {
+    fn startup(&self) -> ComponentFuture<'_> {
+        Box::pin(Server::startup(self))
+    }
+
+    fn shutdown(&self) -> ComponentFuture<'_> {
+        Box::pin(Server::shutdown(self))
+    }
+}
@@ -0,0 +1,362 @@
+use crate::server::{Result, ServerError};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How long a component gets to shut down before it is aborted, unless it was added with
+/// another timeout.
+pub(crate) const DEFAULT_COMPONENT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub(crate) type ComponentFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + 'a>>;
+
+/// A subsystem of a node, started and stopped by a [ComponentLifecycleManager].
+pub(crate) trait Component {
+    fn startup(&self) -> ComponentFuture<'_>;
+
+    fn shutdown(&self) -> ComponentFuture<'_>;
+
+    /// Forcibly releases what the component holds, once its shutdown timed out.
+    fn abort(&self) {}
+}
+
+struct RegisteredComponent {
+    name: &'static str,
+    component: Arc<dyn Component>,
+    depends_on: Vec<&'static str>,
+    shutdown_timeout: Duration,
+}
+
+/// Starts the components of a node after the components they depend on, e.g. the log
+/// manager before the socket server and the socket server before the coordinators, and shuts
+/// them down in the reverse order.
+///
+/// A component which fails to start stops the startup: the components started before it are
+/// shut down again. A component which doesn't shut down within its timeout is aborted, and
+/// the components after it are still shut down, so a single stalled component doesn't keep
+/// the whole node from stopping. The error then names the components which stalled.
+#[derive(Default)]
+pub(crate) struct ComponentLifecycleManager {
+    components: Vec<RegisteredComponent>,
+    /// The indexes of the started components, in the order they were started.
+    started: Mutex<Vec<usize>>,
+}
+
+impl ComponentLifecycleManager {
+    /// Adds a component which is started after the components named in `depends_on`.
+    pub fn add(
+        &mut self,
+        name: &'static str,
+        component: Arc<dyn Component>,
+        depends_on: &[&'static str],
+    ) {
+        self.add_with_shutdown_timeout(
+            name,
+            component,
+            depends_on,
+            DEFAULT_COMPONENT_SHUTDOWN_TIMEOUT,
+        );
+    }
+
+    pub fn add_with_shutdown_timeout(
+        &mut self,
+        name: &'static str,
+        component: Arc<dyn Component>,
+        depends_on: &[&'static str],
+        shutdown_timeout: Duration,
+    ) {
+        self.components.push(RegisteredComponent {
+            name,
+            component,
+            depends_on: depends_on.to_vec(),
+            shutdown_timeout,
+        });
+    }
+
+    fn started(&self) -> MutexGuard<'_, Vec<usize>> {
+        self.started
+            .lock()
+            .expect("started components lock poisoned")
+    }
+
+    /// The indexes of the components in the order to start them: every component after its
+    /// dependencies, and in the order they were added otherwise.
+    fn start_order(&self) -> Result<Vec<usize>> {
+        let indexes: HashMap<&str, usize> = self
+            .components
+            .iter()
+            .enumerate()
+            .map(|(index, c)| (c.name, index))
+            .collect();
+        let mut dependencies = Vec::with_capacity(self.components.len());
+        for c in &self.components {
+            let mut component_dependencies = vec![];
+            for dependency in &c.depends_on {
+                match indexes.get(dependency) {
+                    Some(index) => component_dependencies.push(*index),
+                    None => {
+                        return Err(ServerError::UnknownComponentDependency {
+                            component: c.name.to_string(),
+                            dependency: dependency.to_string(),
+                        });
+                    }
+                }
+            }
+            dependencies.push(component_dependencies);
+        }
+
+        let mut order = Vec::with_capacity(self.components.len());
+        let mut ordered = vec![false; self.components.len()];
+        while order.len() < self.components.len() {
+            let next = (0..self.components.len())
+                .find(|index| !ordered[*index] && dependencies[*index].iter().all(|d| ordered[*d]));
+            match next {
+                Some(index) => {
+                    ordered[index] = true;
+                    order.push(index);
+                }
+                None => {
+                    let cycle = (0..self.components.len())
+                        .filter(|index| !ordered[*index])
+                        .map(|index| self.components[index].name.to_string())
+                        .collect();
+                    return Err(ServerError::ComponentDependencyCycle(cycle));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Starts the components in dependency order.
+    pub async fn startup(&self) -> Result<()> {
+        for index in self.start_order()? {
+            let c = &self.components[index];
+            info!("Starting {}", c.name);
+            if let Err(e) = c.component.startup().await {
+                error!(
+                    "Failed to start {}, shutting down the started components: {e}",
+                    c.name
+                );
+                if let Err(shutdown_error) = self.shutdown().await {
+                    error!("Failed to shut down the started components: {shutdown_error}");
+                }
+                return Err(ServerError::ComponentStartup {
+                    component: c.name.to_string(),
+                    source: Box::new(e),
+                });
+            }
+            self.started().push(index);
+        }
+        Ok(())
+    }
+
+    /// Shuts down the started components, the last started first.
+    pub async fn shutdown(&self) -> Result<()> {
+        let mut stalled = vec![];
+        let mut first_error = None;
+        loop {
+            let Some(index) = self.started().pop() else {
+                break;
+            };
+            let c = &self.components[index];
+            info!("Shutting down {}", c.name);
+            match tokio::time::timeout(c.shutdown_timeout, c.component.shutdown()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    error!("Failed to shut down {}: {e}", c.name);
+                    first_error.get_or_insert(e);
+                }
+                Err(_) => {
+                    warn!(
+                        "{} did not shut down within {:?}, aborting it",
+                        c.name, c.shutdown_timeout
+                    );
+                    c.component.abort();
+                    stalled.push(c.name.to_string());
+                }
+            }
+        }
+        if !stalled.is_empty() {
+            return Err(ServerError::ComponentsStalled(stalled));
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    #[derive(Default)]
+    struct TestComponent {
+        name: &'static str,
+        events: Events,
+        fail_startup: bool,
+        stall_shutdown: bool,
+    }
+
+    impl TestComponent {
+        fn new(name: &'static str, events: &Events) -> Self {
+            Self {
+                name,
+                events: events.clone(),
+                ..Default::default()
+            }
+        }
+
+        fn record(&self, event: &str) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("{event} {}", self.name));
+        }
+    }
+
+    impl Component for TestComponent {
+        fn startup(&self) -> ComponentFuture<'_> {
+            Box::pin(async move {
+                if self.fail_startup {
+                    return Err(io::Error::other("no space left").into());
+                }
+                self.record("start");
+                Ok(())
+            })
+        }
+
+        fn shutdown(&self) -> ComponentFuture<'_> {
+            Box::pin(async move {
+                if self.stall_shutdown {
+                    std::future::pending::<()>().await;
+                }
+                self.record("stop");
+                Ok(())
+            })
+        }
+
+        fn abort(&self) {
+            self.record("abort");
+        }
+    }
+
+    fn events(events: &Events) -> Vec<String> {
+        events.lock().unwrap().drain(..).collect()
+    }
+
+    #[tokio::test]
+    async fn test_components_start_in_dependency_order() {
+        let log = Events::default();
+        let mut manager = ComponentLifecycleManager::default();
+        let component = |name| Arc::new(TestComponent::new(name, &log));
+        manager.add(
+            "coordinators",
+            component("coordinators"),
+            &["socket-server"],
+        );
+        manager.add(
+            "socket-server",
+            component("socket-server"),
+            &["log-manager"],
+        );
+        manager.add("log-manager", component("log-manager"), &[]);
+
+        manager.startup().await.unwrap();
+        assert_eq!(
+            vec![
+                "start log-manager",
+                "start socket-server",
+                "start coordinators"
+            ],
+            events(&log)
+        );
+        manager.shutdown().await.unwrap();
+        assert_eq!(
+            vec![
+                "stop coordinators",
+                "stop socket-server",
+                "stop log-manager"
+            ],
+            events(&log)
+        );
+        // The components are only shut down once.
+        manager.shutdown().await.unwrap();
+        assert!(events(&log).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_startup_stops_the_started_components() {
+        let log = Events::default();
+        let mut manager = ComponentLifecycleManager::default();
+        manager.add(
+            "log-manager",
+            Arc::new(TestComponent::new("log-manager", &log)),
+            &[],
+        );
+        let failing = TestComponent {
+            fail_startup: true,
+            ..TestComponent::new("socket-server", &log)
+        };
+        manager.add("socket-server", Arc::new(failing), &["log-manager"]);
+
+        let error = manager.startup().await.unwrap_err();
+        assert!(
+            matches!(&error, ServerError::ComponentStartup { component, .. } if component == "socket-server")
+        );
+        assert_eq!(vec!["start log-manager", "stop log-manager"], events(&log));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_component_is_aborted() {
+        let log = Events::default();
+        let mut manager = ComponentLifecycleManager::default();
+        manager.add(
+            "log-manager",
+            Arc::new(TestComponent::new("log-manager", &log)),
+            &[],
+        );
+        let stalling = TestComponent {
+            stall_shutdown: true,
+            ..TestComponent::new("coordinators", &log)
+        };
+        manager.add_with_shutdown_timeout(
+            "coordinators",
+            Arc::new(stalling),
+            &["log-manager"],
+            Duration::from_millis(10),
+        );
+        manager.startup().await.unwrap();
+        events(&log);
+
+        let error = manager.shutdown().await.unwrap_err();
+        assert!(
+            matches!(&error, ServerError::ComponentsStalled(stalled) if stalled == &["coordinators"])
+        );
+        // The components the stalled one depends on are still shut down.
+        assert_eq!(vec!["abort coordinators", "stop log-manager"], events(&log));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_dependencies() {
+        let log = Events::default();
+        let mut manager = ComponentLifecycleManager::default();
+        manager.add("a", Arc::new(TestComponent::new("a", &log)), &["b"]);
+        manager.add("b", Arc::new(TestComponent::new("b", &log)), &["a"]);
+        manager.add("c", Arc::new(TestComponent::new("c", &log)), &[]);
+        assert!(matches!(
+            manager.startup().await,
+            Err(ServerError::ComponentDependencyCycle(cycle)) if cycle == ["a", "b"]
+        ));
+
+        let mut manager = ComponentLifecycleManager::default();
+        manager.add("a", Arc::new(TestComponent::new("a", &log)), &["missing"]);
+        assert!(matches!(
+            manager.startup().await,
+            Err(ServerError::UnknownComponentDependency { dependency, .. }) if dependency == "missing"
+        ));
+        assert!(events(&log).is_empty());
+    }
+}
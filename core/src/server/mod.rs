@@ -1,9 +1,20 @@
+use rafka_clients::common::endpoint::EndpointError;
+use rafka_server::raft_config::RaftConfigError;
+use rafka_storage::LogError;
 use std::io;
 use thiserror::Error;
 use tokio::net::TcpListener;
 
+pub(crate) mod broker_server;
+pub(crate) mod config_sources;
+pub(crate) mod controller_server;
+pub(crate) mod lifecycle_manager;
 pub(crate) mod rafka_config;
 pub(crate) mod rafka_raft_server;
+pub(crate) mod runtimes;
+pub(crate) mod shared_server;
+pub(crate) mod shutdown_hooks;
+pub(crate) mod startup_report;
 
 #[derive(Error, Debug)]
 pub enum ServerError {
@@ -12,6 +23,37 @@ pub enum ServerError {
 
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
+
+    #[error("Invalid listener config: {0}")]
+    Endpoint(#[from] EndpointError),
+
+    #[error("Invalid KRaft config: {0}")]
+    RaftConfig(#[from] RaftConfigError),
+
+    #[error("Log error: {0}")]
+    Log(#[from] LogError),
+
+    #[error("Component {component} depends on the unknown component {dependency}")]
+    UnknownComponentDependency {
+        component: String,
+        dependency: String,
+    },
+
+    #[error("The dependencies of the components {0:?} form a cycle")]
+    ComponentDependencyCycle(Vec<String>),
+
+    #[error("Failed to start {component}: {source}")]
+    ComponentStartup {
+        component: String,
+        #[source]
+        source: Box<ServerError>,
+    },
+
+    #[error("The components {0:?} did not shut down in time and were aborted")]
+    ComponentsStalled(Vec<String>),
+
+    #[error("The shutdown hooks {0:?} failed")]
+    ShutdownHooksFailed(Vec<String>),
 }
 
 impl From<Box<dyn std::error::Error + Send + Sync + 'static>> for ServerError {
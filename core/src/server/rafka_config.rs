@@ -10,13 +10,13 @@ use rafka_storage::{CleanerConfig, LogConfig};
 #[derive(Debug, EasyConfig)]
 pub struct RafkaConfig {
     #[merge]
-    server_configs: ServerConfig,
+    pub(crate) server_configs: ServerConfig,
 
     #[merge]
-    raft_configs: RaftConfigs,
+    pub(crate) raft_configs: RaftConfigs,
 
     #[merge]
-    socket_server_config: SocketServerConfig,
+    pub(crate) socket_server_config: SocketServerConfig,
 
     #[merge]
     group_coordinator_config: GroupCoordinatorConfig,
@@ -25,7 +25,7 @@ pub struct RafkaConfig {
     cleaner_config: CleanerConfig,
 
     #[merge]
-    log_config: LogConfig,
+    pub(crate) log_config: LogConfig,
 
     #[merge]
     quota_config: QuotaConfig,
@@ -1,26 +1,378 @@
+use crate::server::broker_server::BrokerServer;
+use crate::server::controller_server::ControllerServer;
+use crate::server::lifecycle_manager::{Component, ComponentFuture, ComponentLifecycleManager};
 use crate::server::rafka_config::RafkaConfig;
-use crate::server::{Result, Server};
+use crate::server::runtimes::{RuntimeKind, RuntimeSizes, Runtimes};
+use crate::server::shared_server::SharedServer;
+use crate::server::shutdown_hooks::{ShutdownHooks, ShutdownPhase};
+use crate::server::startup_report::StartupReport;
+use crate::server::{Result, Server, ServerError};
+use rafka_clients::common::endpoint::Endpoint;
+use rafka_metadata::LocalImageStore;
+use rafka_server::dynamic_config_publisher::DynamicConfigPublisher;
+use rafka_server::raft_config::ProcessRole;
+use rafka_server::socket_server_config::split_listeners_by_plane;
+use rafka_storage::clean_shutdown_file::NO_BROKER_EPOCH;
+use rafka_storage::log_dir_lock::{LogDirLock, lock_log_dirs};
+use rafka_storage::{
+    CleanShutdownFileHandler, LogRecovery, TopicLogConfigs, UnifiedLog, UnifiedLogConfig,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::runtime::Handle;
 
+const LOG_MANAGER: &str = "log-manager";
+const CONTROLLER: &str = "controller";
+const BROKER: &str = "broker";
+
+/// A node of a KRaft cluster, assembled from the `process.roles` it is configured with: a
+/// broker, a controller, or both sharing a [SharedServer].
 pub(crate) struct RaftServer {
-    config: RafkaConfig,
+    shared: Arc<SharedServer>,
+    /// Starts the log manager, then the controller, then the broker, which registers with
+    /// the controller, and stops them in the reverse order.
+    lifecycle: ComponentLifecycleManager,
+    /// Logged on startup.
+    report: StartupReport,
+    /// Run once the components shut down, or if the node panics before they do.
+    shutdown_hooks: Arc<ShutdownHooks>,
+    /// Shut down last, once the components stopped using them.
+    runtimes: Runtimes,
+}
+
+/// The components of a node, built for its roles.
+type Components = (
+    Arc<SharedServer>,
+    Option<BrokerServer>,
+    Option<ControllerServer>,
+);
+
+/// Holds the locks on the log directories while the node runs, so that no other process
+/// uses them, and the logs loaded from them. Marks them as shut down cleanly once the node
+/// stops.
+struct LogManager {
+    log_dirs: Vec<PathBuf>,
+    /// `metadata.log.dir` if it is not one of the data log dirs, locked along with them but
+    /// holding no data log.
+    metadata_log_dir: Option<PathBuf>,
+    recovery: LogRecovery,
+    /// The runtime loading and flushing the logs, so that a slow disk only holds its threads.
+    log_io: Handle,
+    locks: Mutex<Vec<LogDirLock>>,
+    logs: Mutex<Vec<UnifiedLog>>,
+}
+
+impl LogManager {
+    fn locks(&self) -> MutexGuard<'_, Vec<LogDirLock>> {
+        self.locks.lock().expect("log dir locks lock poisoned")
+    }
+
+    fn logs(&self) -> MutexGuard<'_, Vec<UnifiedLog>> {
+        self.logs.lock().expect("logs lock poisoned")
+    }
+
+    /// Applies the new configs of `topic` to its loaded logs.
+    fn update_topic_config(&self, topic: &str, config: &UnifiedLogConfig) {
+        for log in self
+            .logs()
+            .iter_mut()
+            .filter(|log| log.topic_partition().topic() == topic)
+        {
+            log.update_config(config.clone());
+        }
+    }
+
+    /// Registers the hooks which flush the logs and release the locks of the log directories
+    /// if the node stops without shutting the log manager down. They write no clean shutdown
+    /// marker, so the logs are still recovered on the next startup.
+    fn register_shutdown_hooks(self: &Arc<Self>, hooks: &ShutdownHooks) {
+        let log_manager = Arc::clone(self);
+        hooks.register(ShutdownPhase::FlushLogs, "flush-data-logs", move || {
+            let logs = log_manager
+                .logs
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            for log in logs.iter() {
+                log.flush()?;
+            }
+            Ok(())
+        });
+        let log_manager = Arc::clone(self);
+        hooks.register(
+            ShutdownPhase::ReleaseLocks,
+            "release-log-dir-locks",
+            move || {
+                log_manager
+                    .locks
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clear();
+                Ok(())
+            },
+        );
+    }
+}
+
+impl Component for LogManager {
+    fn startup(&self) -> ComponentFuture<'_> {
+        Box::pin(async move {
+            let mut locks = lock_log_dirs(&self.log_dirs)?;
+            if let Some(metadata_log_dir) = &self.metadata_log_dir {
+                locks.push(LogDirLock::acquire(metadata_log_dir)?);
+            }
+            *self.locks() = locks;
+            let recovery = self.recovery.clone();
+            let log_dirs = self.log_dirs.clone();
+            *self.logs() = self
+                .log_io
+                .spawn_blocking(move || recovery.load_log_dirs(&log_dirs))
+                .await
+                .map_err(|e| ServerError::Err(e.into()))??;
+            Ok(())
+        })
+    }
+
+    /// Writes the clean shutdown markers while still holding the locks. An aborted node leaves
+    /// no marker, so its logs are recovered on the next startup.
+    fn shutdown(&self) -> ComponentFuture<'_> {
+        Box::pin(async move {
+            let logs = std::mem::take(&mut *self.logs());
+            self.log_io
+                .spawn_blocking(move || logs.iter().try_for_each(UnifiedLog::flush))
+                .await
+                .map_err(|e| ServerError::Err(e.into()))??;
+            let mut locks = self.locks();
+            // The node does not register with the controller yet, so it has no broker epoch.
+            for lock in locks
+                .iter()
+                .filter(|l| self.log_dirs.iter().any(|d| d == l.dir()))
+            {
+                CleanShutdownFileHandler::new(lock.dir()).write(NO_BROKER_EPOCH)?;
+            }
+            locks.clear();
+            Ok(())
+        })
+    }
+
+    fn abort(&self) {
+        self.logs().clear();
+        self.locks().clear();
+    }
 }
 
 impl RaftServer {
-    pub fn new(config: RafkaConfig) -> Self {
-        Self { config }
+    /// Builds the components for the roles of `config`, parsed from `props`, after checking
+    /// the listeners and the quorum configs are consistent with them.
+    pub fn new(config: RafkaConfig, props: &HashMap<String, String>) -> Result<Self> {
+        let raft_configs = &config.raft_configs;
+        let socket_server_config = &config.socket_server_config;
+        let roles = raft_configs.process_roles()?;
+        let controller_listener_names = raft_configs.controller_listener_names_config();
+        let protocol_map =
+            socket_server_config.listener_security_protocol_map(controller_listener_names)?;
+        let listeners = socket_server_config.listeners(&protocol_map)?;
+        let listener_names: Vec<String> =
+            listeners.iter().map(|l| l.listener_name.clone()).collect();
+        raft_configs.validate(&listener_names)?;
+        let advertised_listeners = if roles.contains(&ProcessRole::Broker) {
+            socket_server_config
+                .effective_advertised_broker_listeners(&protocol_map, controller_listener_names)?
+        } else {
+            vec![]
+        };
+
+        let node_id = *raft_configs.node_id_config() as i32;
+        let runtimes = Runtimes::new(node_id, RuntimeSizes::of(&config))?;
+        let log_dirs = config.log_config.log_dirs();
+        let report = StartupReport::new(
+            node_id,
+            roles.clone(),
+            props,
+            listeners.clone(),
+            advertised_listeners.clone(),
+            &log_dirs,
+        );
+        let (shared, broker, controller) = Self::components(
+            node_id,
+            roles,
+            listeners,
+            advertised_listeners,
+            controller_listener_names,
+        );
+
+        let metadata_log_config = raft_configs.metadata_log_config(&log_dirs)?;
+        let metadata_log_dir =
+            (!metadata_log_config.is_colocated(&log_dirs)).then(|| metadata_log_config.dir.clone());
+
+        let mut lifecycle = ComponentLifecycleManager::default();
+        let shutdown_hooks = Arc::new(ShutdownHooks::default());
+        shutdown_hooks.register(ShutdownPhase::FlushLogs, "flush-node-log", || {
+            io::stdout().flush()?;
+            Ok(())
+        });
+        let log_manager = Arc::new(LogManager {
+            log_dirs,
+            metadata_log_dir,
+            recovery: LogRecovery::new(
+                UnifiedLogConfig::default(),
+                *config.log_config.num_recovery_threads_per_data_dir_config() as usize,
+            ),
+            log_io: runtimes.handle(RuntimeKind::LogIo).clone(),
+            locks: Mutex::new(vec![]),
+            logs: Mutex::new(vec![]),
+        });
+        log_manager.register_shutdown_hooks(&shutdown_hooks);
+        lifecycle.add(LOG_MANAGER, log_manager.clone(), &[]);
+        let mut broker_dependencies = vec![LOG_MANAGER];
+        if let Some(controller) = controller {
+            lifecycle.add(CONTROLLER, Arc::new(controller), &[LOG_MANAGER]);
+            broker_dependencies.push(CONTROLLER);
+        }
+        if let Some(broker) = broker {
+            // The topic configs replicated through the metadata apply to the loaded logs.
+            let loaded_logs = Arc::clone(&log_manager);
+            let topic_configs = DynamicConfigPublisher::new(Arc::new(TopicLogConfigs::default()))
+                .with_listener(Box::new(move |topic, config| {
+                    loaded_logs.update_topic_config(topic, config)
+                }));
+            let broker = broker
+                .with_image_store(LocalImageStore::new(metadata_log_config.dir))
+                .with_metadata_subscriber(Arc::new(topic_configs));
+            lifecycle.add(BROKER, Arc::new(broker), &broker_dependencies);
+        }
+        Ok(Self {
+            shared,
+            lifecycle,
+            report,
+            shutdown_hooks,
+            runtimes,
+        })
+    }
+
+    fn components(
+        node_id: i32,
+        roles: BTreeSet<ProcessRole>,
+        listeners: Vec<Endpoint>,
+        advertised_listeners: Vec<Endpoint>,
+        controller_listener_names: &[String],
+    ) -> Components {
+        let shared = Arc::new(SharedServer::new(node_id, roles));
+        let broker = shared
+            .has_role(ProcessRole::Broker)
+            .then(|| BrokerServer::new(shared.clone(), advertised_listeners));
+        let controller = shared.has_role(ProcessRole::Controller).then(|| {
+            let planes = split_listeners_by_plane(listeners, controller_listener_names);
+            ControllerServer::new(shared.clone(), planes.control_plane)
+        });
+        (shared, broker, controller)
     }
 }
 
 impl Server for RaftServer {
+    /// Locks the log directories, so that no other process uses them while the node runs,
+    /// then starts the controller before the broker, which registers with it.
     async fn startup(&self) -> Result<()> {
-        todo!()
+        self.shared.start();
+        self.report.log();
+        self.shutdown_hooks.run_on_panic();
+        self.lifecycle.startup().await
     }
 
+    /// Stops the broker before the controller, so that it can still reach the controller
+    /// for a controlled shutdown, and unlocks the log directories last. The shutdown hooks
+    /// then run, even if a component failed to shut down, and the runtimes stop. Shutting
+    /// down a node which already shut down does nothing.
     async fn shutdown(&self) -> Result<()> {
-        todo!()
+        if self.shared.is_shutting_down() {
+            return Ok(());
+        }
+        let result = self.lifecycle.shutdown().await;
+        let hooks_result = self.shutdown_hooks.run();
+        self.runtimes.shutdown();
+        self.shared.stop();
+        result.and(hooks_result)
     }
 
     async fn await_shutdown(&self) -> Result<()> {
-        todo!()
+        self.shared.await_shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::endpoint::parse_listener_security_protocol_map;
+
+    fn listeners(listeners: &[&str]) -> Vec<Endpoint> {
+        let protocol_map =
+            parse_listener_security_protocol_map("PLAINTEXT:PLAINTEXT,CONTROLLER:PLAINTEXT")
+                .unwrap();
+        listeners
+            .iter()
+            .map(|l| Endpoint::parse(l, &protocol_map).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_components_follow_the_roles() {
+        let controller_listener_names = ["controller".to_string()];
+        let all = listeners(&["PLAINTEXT://localhost:9092", "CONTROLLER://localhost:9093"]);
+        let advertised = listeners(&["PLAINTEXT://localhost:9092"]);
+
+        let (shared, broker, controller) = RaftServer::components(
+            1,
+            BTreeSet::from([ProcessRole::Broker]),
+            advertised.clone(),
+            advertised.clone(),
+            &controller_listener_names,
+        );
+        assert_eq!(1, shared.node_id());
+        assert_eq!(advertised, broker.unwrap().advertised_listeners());
+        assert!(controller.is_none());
+
+        let (_, broker, controller) = RaftServer::components(
+            1,
+            BTreeSet::from([ProcessRole::Controller]),
+            listeners(&["CONTROLLER://localhost:9093"]),
+            vec![],
+            &controller_listener_names,
+        );
+        assert!(broker.is_none());
+        assert_eq!(1, controller.unwrap().controller_listeners().len());
+
+        let (shared, broker, controller) = RaftServer::components(
+            1,
+            BTreeSet::from([ProcessRole::Broker, ProcessRole::Controller]),
+            all,
+            advertised.clone(),
+            &controller_listener_names,
+        );
+        let (broker, controller) = (broker.unwrap(), controller.unwrap());
+        assert_eq!(advertised, broker.advertised_listeners());
+        assert_eq!(
+            listeners(&["CONTROLLER://localhost:9093"]),
+            controller.controller_listeners()
+        );
+        // The broker and the controller share the server.
+        assert_eq!(3, Arc::strong_count(&shared));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_wakes_up_the_waiters() {
+        let (shared, broker, _) = RaftServer::components(
+            1,
+            BTreeSet::from([ProcessRole::Broker]),
+            vec![],
+            vec![],
+            &[],
+        );
+        let broker = broker.unwrap();
+        let waiter = tokio::spawn(async move { broker.await_shutdown().await });
+        assert!(!shared.is_shutting_down());
+        shared.stop();
+        waiter.await.unwrap().unwrap();
+        assert!(shared.is_shutting_down());
     }
 }
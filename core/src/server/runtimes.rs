@@ -0,0 +1,184 @@
+use crate::server::rafka_config::RafkaConfig;
+use crate::server::{Result, ServerError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use tokio::runtime::{Builder, Handle, Runtime};
+use tracing::info;
+
+/// What the threads of a runtime do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RuntimeKind {
+    /// Reads the requests from the connections and writes the responses.
+    Network,
+    /// Handles the requests.
+    RequestHandler,
+    /// Runs the blocking IO of the logs, on its blocking threads.
+    LogIo,
+    /// Runs the raft client and the event queue of the controller.
+    Controller,
+}
+
+impl RuntimeKind {
+    pub const ALL: [RuntimeKind; 4] = [
+        RuntimeKind::Network,
+        RuntimeKind::RequestHandler,
+        RuntimeKind::LogIo,
+        RuntimeKind::Controller,
+    ];
+
+    /// The name of the threads of the runtime, before their index.
+    pub fn thread_name(&self) -> &'static str {
+        match self {
+            RuntimeKind::Network => "network-thread",
+            RuntimeKind::RequestHandler => "request-handler",
+            RuntimeKind::LogIo => "log-io",
+            RuntimeKind::Controller => "controller-event-thread",
+        }
+    }
+}
+
+/// The number of threads of each runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RuntimeSizes {
+    pub network: usize,
+    pub request_handler: usize,
+    pub log_io: usize,
+    pub controller: usize,
+}
+
+impl RuntimeSizes {
+    /// The sizes of `num.network.threads`, `num.io.threads`, `num.log.io.threads` and
+    /// `num.controller.threads`.
+    pub fn of(config: &RafkaConfig) -> Self {
+        let server_configs = &config.server_configs;
+        Self {
+            network: *config.socket_server_config.num_network_threads_config() as usize,
+            request_handler: *server_configs.num_io_threads_config() as usize,
+            log_io: *server_configs.num_log_io_threads_config() as usize,
+            controller: *server_configs.num_controller_threads_config() as usize,
+        }
+    }
+
+    fn size(&self, kind: RuntimeKind) -> usize {
+        match kind {
+            RuntimeKind::Network => self.network,
+            RuntimeKind::RequestHandler => self.request_handler,
+            RuntimeKind::LogIo => self.log_io,
+            RuntimeKind::Controller => self.controller,
+        }
+    }
+}
+
+/// The runtimes of a node, one per [RuntimeKind], so that the work of one kind can't starve
+/// another: a disk stalling the log IO holds the log IO threads, but the request handlers
+/// still answer the heartbeats and the controller still runs its elections.
+///
+/// The threads are named after the node and their kind, e.g. `node-1-request-handler-3`,
+/// to tell them apart in thread dumps and profiles. The log IO runs on the blocking threads
+/// of its runtime, as many as its size, behind a single worker thread.
+pub(crate) struct Runtimes {
+    runtimes: Mutex<Vec<(RuntimeKind, Runtime)>>,
+    handles: Vec<(RuntimeKind, Handle)>,
+}
+
+impl Runtimes {
+    pub fn new(node_id: i32, sizes: RuntimeSizes) -> Result<Self> {
+        let mut runtimes = vec![];
+        for kind in RuntimeKind::ALL {
+            let size = sizes.size(kind);
+            runtimes.push((kind, Self::build(node_id, kind, size)?));
+            info!(
+                "Started the {} runtime with {size} threads",
+                kind.thread_name()
+            );
+        }
+        let handles = runtimes
+            .iter()
+            .map(|(kind, runtime)| (*kind, runtime.handle().clone()))
+            .collect();
+        Ok(Self {
+            runtimes: Mutex::new(runtimes),
+            handles,
+        })
+    }
+
+    fn build(node_id: i32, kind: RuntimeKind, size: usize) -> Result<Runtime> {
+        let next_index = AtomicUsize::new(0);
+        let mut builder = Builder::new_multi_thread();
+        builder.thread_name_fn(move || {
+            let index = next_index.fetch_add(1, Ordering::Relaxed);
+            format!("node-{node_id}-{}-{index}", kind.thread_name())
+        });
+        match kind {
+            RuntimeKind::LogIo => builder.worker_threads(1).max_blocking_threads(size),
+            _ => builder.worker_threads(size),
+        };
+        builder
+            .enable_all()
+            .build()
+            .map_err(|e| ServerError::Err(e.into()))
+    }
+
+    pub fn handle(&self, kind: RuntimeKind) -> &Handle {
+        self.handles
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, handle)| handle)
+            .expect("a runtime of each kind")
+    }
+
+    /// Shuts the runtimes down without waiting for their threads, which may be blocked on a
+    /// disk: the components stopped their tasks already.
+    pub fn shutdown(&self) {
+        let runtimes =
+            std::mem::take(&mut *self.runtimes.lock().unwrap_or_else(PoisonError::into_inner));
+        for (_, runtime) in runtimes {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Drop for Runtimes {
+    /// Dropping a runtime waits for its threads, which panics within another runtime.
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[tokio::test]
+    async fn test_runtimes_have_named_threads_of_their_size() {
+        let sizes = RuntimeSizes {
+            network: 2,
+            request_handler: 3,
+            log_io: 2,
+            controller: 1,
+        };
+        let runtimes = Runtimes::new(1, sizes).unwrap();
+        for kind in RuntimeKind::ALL {
+            let name = runtimes
+                .handle(kind)
+                .spawn_blocking(|| thread::current().name().map(str::to_string))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(
+                name.starts_with(&format!("node-1-{}-", kind.thread_name())),
+                "{name}"
+            );
+        }
+        let workers = runtimes.handle(RuntimeKind::RequestHandler).metrics();
+        assert_eq!(3, workers.num_workers());
+        assert_eq!(
+            1,
+            runtimes.handle(RuntimeKind::LogIo).metrics().num_workers()
+        );
+
+        // Dropped within the runtime of the test.
+        drop(runtimes);
+    }
+}
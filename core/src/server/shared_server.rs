@@ -0,0 +1,54 @@
+use crate::server::Result;
+use rafka_server::raft_config::ProcessRole;
+use std::collections::BTreeSet;
+use tokio::sync::watch;
+use tracing::info;
+
+/// The state shared by the broker and the controller of a node: its identity and the
+/// signal the components wait on to shut down.
+///
+/// Both a combined node and a broker-only or controller-only node have one.
+#[derive(Debug)]
+pub(crate) struct SharedServer {
+    node_id: i32,
+    roles: BTreeSet<ProcessRole>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl SharedServer {
+    pub fn new(node_id: i32, roles: BTreeSet<ProcessRole>) -> Self {
+        Self {
+            node_id,
+            roles,
+            shutdown: watch::Sender::new(false),
+        }
+    }
+
+    pub fn node_id(&self) -> i32 {
+        self.node_id
+    }
+
+    pub fn has_role(&self, role: ProcessRole) -> bool {
+        self.roles.contains(&role)
+    }
+
+    pub fn start(&self) {
+        info!("Starting node {} with roles {:?}", self.node_id, self.roles);
+    }
+
+    pub fn stop(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Waits until [stop](Self::stop) is called.
+    pub async fn await_shutdown(&self) -> Result<()> {
+        let mut shutdown = self.shutdown.subscribe();
+        // The sender lives as long as self, so waiting can't fail.
+        let _ = shutdown.wait_for(|stopped| *stopped).await;
+        Ok(())
+    }
+}
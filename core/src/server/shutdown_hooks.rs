@@ -0,0 +1,131 @@
+use crate::server::{Result, ServerError};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use tracing::{error, info};
+
+/// When a shutdown hook runs: the hooks of a phase run after those of the previous phases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ShutdownPhase {
+    /// Flushes what the node wrote, e.g. its own log and its data logs.
+    FlushLogs,
+    /// Releases what other processes wait for, e.g. the locks of the log directories.
+    ReleaseLocks,
+}
+
+type Hook = Box<dyn FnOnce() -> Result<()> + Send>;
+
+/// What a node does last when it stops, however it stops: after its components shut down,
+/// or when its main thread panics and the components never get to.
+///
+/// The hooks run in a set order, phase after phase and in the order they were registered
+/// within a phase, rather than in whichever order their owners happen to be dropped. Each runs
+/// once. A hook which fails or panics does not prevent the next ones.
+#[derive(Default)]
+pub(crate) struct ShutdownHooks {
+    hooks: Mutex<Vec<(ShutdownPhase, &'static str, Hook)>>,
+}
+
+impl ShutdownHooks {
+    fn hooks(&self) -> MutexGuard<'_, Vec<(ShutdownPhase, &'static str, Hook)>> {
+        // The hooks also run on panic, possibly of a thread which held the lock.
+        self.hooks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn register(
+        &self,
+        phase: ShutdownPhase,
+        name: &'static str,
+        hook: impl FnOnce() -> Result<()> + Send + 'static,
+    ) {
+        self.hooks().push((phase, name, Box::new(hook)));
+    }
+
+    /// Runs the hooks which did not run yet, failing with the names of those which failed.
+    pub fn run(&self) -> Result<()> {
+        let mut hooks = std::mem::take(&mut *self.hooks());
+        // A stable sort keeps the registration order within a phase.
+        hooks.sort_by_key(|(phase, _, _)| *phase);
+        let mut failed = vec![];
+        for (phase, name, hook) in hooks {
+            info!("Running the shutdown hook {name} ({phase:?})");
+            match panic::catch_unwind(AssertUnwindSafe(hook)) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    error!("The shutdown hook {name} failed: {e}");
+                    failed.push(name.to_string());
+                }
+                Err(_) => {
+                    error!("The shutdown hook {name} panicked");
+                    failed.push(name.to_string());
+                }
+            }
+        }
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(ServerError::ShutdownHooksFailed(failed)),
+        }
+    }
+
+    /// Runs the hooks when the main thread panics, after the panic is reported. The panics of
+    /// the other threads are left alone: those of the tasks of the runtime are caught and
+    /// don't stop the node.
+    pub fn run_on_panic(self: &Arc<Self>) {
+        let hooks = Arc::clone(self);
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            if thread::current().name() == Some("main") {
+                let _ = hooks.run();
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_hooks_run_once_by_phase() {
+        let hooks = ShutdownHooks::default();
+        let events = Arc::new(Mutex::new(vec![]));
+        let hook = |name: &'static str, outcome: Option<&'static str>| {
+            let events = Arc::clone(&events);
+            move || {
+                events.lock().unwrap().push(name);
+                match outcome {
+                    None => Ok(()),
+                    Some("panic") => panic!("{name} panicked"),
+                    Some(e) => Err(io::Error::other(e).into()),
+                }
+            }
+        };
+        hooks.register(ShutdownPhase::ReleaseLocks, "locks", hook("locks", None));
+        hooks.register(
+            ShutdownPhase::FlushLogs,
+            "request-log",
+            hook("request-log", Some("panic")),
+        );
+        hooks.register(
+            ShutdownPhase::FlushLogs,
+            "data-logs",
+            hook("data-logs", Some("disk failure")),
+        );
+        hooks.register(ShutdownPhase::FlushLogs, "node-log", hook("node-log", None));
+
+        let error = hooks.run().unwrap_err();
+        assert!(
+            matches!(&error, ServerError::ShutdownHooksFailed(failed) if failed == &["request-log", "data-logs"])
+        );
+        assert_eq!(
+            vec!["request-log", "data-logs", "node-log", "locks"],
+            *events.lock().unwrap()
+        );
+
+        // The hooks only run once.
+        hooks.run().unwrap();
+        assert_eq!(4, events.lock().unwrap().len());
+    }
+}
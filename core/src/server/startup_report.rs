@@ -0,0 +1,280 @@
+use rafka_clients::common::endpoint::Endpoint;
+use rafka_server::raft_config::ProcessRole;
+use rafka_server_common::delegation_token_manager_configs::DELEGATION_TOKEN_SECRET_KEY_CONFIG;
+use rafka_server_common::metadata_version::MetadataVersion;
+use rafka_storage::disk_space_monitor::{DiskSpaceProbe, DiskUsage, FsDiskSpaceProbe};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// What a password config is shown as.
+pub(crate) const HIDDEN: &str = "[hidden]";
+
+/// Below this many open files, a broker with many partitions runs out of file descriptors.
+pub(crate) const MIN_RECOMMENDED_OPEN_FILES: u64 = 100_000;
+/// Below this many memory maps, a broker with many segments fails to map their indexes.
+pub(crate) const MIN_RECOMMENDED_MAX_MAP_COUNT: u64 = 262_144;
+
+/// Whether `name` is a password config, whose value is never logged: the configs of type
+/// Password, e.g. `delegation.token.secret.key`, and the passwords and JAAS configs of the
+/// listeners.
+pub(crate) fn is_password(name: &str) -> bool {
+    name == DELEGATION_TOKEN_SECRET_KEY_CONFIG
+        || name.ends_with(".password")
+        || name.ends_with("sasl.jaas.config")
+}
+
+/// The state of a log directory at startup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LogDirHealth {
+    pub dir: PathBuf,
+    /// The disk usage of the directory, or why it could not be read, e.g. it does not exist.
+    pub usage: Result<DiskUsage, String>,
+    pub read_only: bool,
+}
+
+impl LogDirHealth {
+    pub fn check(dir: &Path, probe: &dyn DiskSpaceProbe) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            usage: probe.usage(dir).map_err(|e| e.to_string()),
+            read_only: fs::metadata(dir).is_ok_and(|m| m.permissions().readonly()),
+        }
+    }
+}
+
+/// The limits the operating system puts on the broker, where they can be read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct OsLimits {
+    /// The soft limit of open files of the process.
+    pub max_open_files: Option<u64>,
+    /// `vm.max_map_count`.
+    pub max_map_count: Option<u64>,
+}
+
+impl OsLimits {
+    /// Reads the limits from `/proc`, leaving them out elsewhere than on Linux.
+    pub fn read() -> Self {
+        Self {
+            max_open_files: fs::read_to_string("/proc/self/limits")
+                .ok()
+                .and_then(|limits| parse_max_open_files(&limits)),
+            max_map_count: fs::read_to_string("/proc/sys/vm/max_map_count")
+                .ok()
+                .and_then(|count| count.trim().parse().ok()),
+        }
+    }
+
+    /// Warnings about the limits too low for a production broker.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        if let Some(max_open_files) = self.max_open_files
+            && max_open_files < MIN_RECOMMENDED_OPEN_FILES
+        {
+            warnings.push(format!(
+                "The limit of open files is {max_open_files}, below the recommended {MIN_RECOMMENDED_OPEN_FILES}"
+            ));
+        }
+        if let Some(max_map_count) = self.max_map_count
+            && max_map_count < MIN_RECOMMENDED_MAX_MAP_COUNT
+        {
+            warnings.push(format!(
+                "vm.max_map_count is {max_map_count}, below the recommended {MIN_RECOMMENDED_MAX_MAP_COUNT}"
+            ));
+        }
+        warnings
+    }
+}
+
+/// The soft limit of the `Max open files` line of `/proc/self/limits`.
+fn parse_max_open_files(limits: &str) -> Option<u64> {
+    let line = limits.lines().find(|l| l.starts_with("Max open files"))?;
+    let soft_limit = line["Max open files".len()..].split_whitespace().next()?;
+    match soft_limit {
+        "unlimited" => Some(u64::MAX),
+        limit => limit.parse().ok(),
+    }
+}
+
+/// What a node logs when it starts, so that its logs tell how it was set up: its identity,
+/// its effective config with the passwords hidden, its listeners, the state of its log
+/// directories, the metadata versions it supports and the limits of its operating system.
+#[derive(Debug, Clone)]
+pub(crate) struct StartupReport {
+    pub node_id: i32,
+    pub roles: BTreeSet<ProcessRole>,
+    pub config: BTreeMap<String, String>,
+    pub listeners: Vec<Endpoint>,
+    pub advertised_listeners: Vec<Endpoint>,
+    pub log_dirs: Vec<LogDirHealth>,
+    pub os_limits: OsLimits,
+}
+
+impl StartupReport {
+    pub fn new(
+        node_id: i32,
+        roles: BTreeSet<ProcessRole>,
+        props: &HashMap<String, String>,
+        listeners: Vec<Endpoint>,
+        advertised_listeners: Vec<Endpoint>,
+        log_dirs: &[PathBuf],
+    ) -> Self {
+        Self {
+            node_id,
+            roles,
+            config: props
+                .iter()
+                .map(|(name, value)| {
+                    let value = match is_password(name) {
+                        true => HIDDEN.to_string(),
+                        false => value.clone(),
+                    };
+                    (name.clone(), value)
+                })
+                .collect(),
+            listeners,
+            advertised_listeners,
+            log_dirs: log_dirs
+                .iter()
+                .map(|dir| LogDirHealth::check(dir, &FsDiskSpaceProbe))
+                .collect(),
+            os_limits: OsLimits::read(),
+        }
+    }
+
+    /// Logs the report, and a warning for each problem it shows.
+    pub fn log(&self) {
+        info!("Startup report:\n{self}");
+        for warning in self.warnings() {
+            warn!("{warning}");
+        }
+    }
+
+    /// The problems the report shows: unusable log directories and low OS limits.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings: Vec<String> = self
+            .log_dirs
+            .iter()
+            .filter_map(|health| match (&health.usage, health.read_only) {
+                (Err(e), _) => Some(format!(
+                    "Cannot read the log directory {}: {e}",
+                    health.dir.display()
+                )),
+                (Ok(_), true) => Some(format!(
+                    "The log directory {} is read-only",
+                    health.dir.display()
+                )),
+                (Ok(_), false) => None,
+            })
+            .collect();
+        warnings.extend(self.os_limits.warnings());
+        warnings
+    }
+}
+
+impl fmt::Display for StartupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let roles: Vec<String> = self.roles.iter().map(|r| format!("{r:?}")).collect();
+        writeln!(f, "  node.id: {}", self.node_id)?;
+        writeln!(f, "  process.roles: {}", roles.join(","))?;
+        writeln!(
+            f,
+            "  {}: {} to {}",
+            MetadataVersion::FEATURE_NAME,
+            MetadataVersion::MINIMUM_BOOTSTRAP_VERSION.version(),
+            MetadataVersion::LATEST_PRODUCTION.version()
+        )?;
+        for (name, endpoints) in [
+            ("listeners", &self.listeners),
+            ("advertised.listeners", &self.advertised_listeners),
+        ] {
+            let endpoints: Vec<String> = endpoints.iter().map(Endpoint::to_string).collect();
+            writeln!(f, "  {name}: {}", endpoints.join(","))?;
+        }
+        writeln!(f, "  log dirs:")?;
+        for health in &self.log_dirs {
+            write!(f, "    {}: ", health.dir.display())?;
+            match &health.usage {
+                Ok(usage) => write!(
+                    f,
+                    "{} of {} bytes free",
+                    usage.free_bytes, usage.total_bytes
+                )?,
+                Err(e) => write!(f, "unavailable ({e})")?,
+            }
+            writeln!(f, "{}", if health.read_only { ", read-only" } else { "" })?;
+        }
+        let limit = |limit: Option<u64>| limit.map_or("unknown".to_string(), |l| l.to_string());
+        writeln!(
+            f,
+            "  os limits: max open files {}, vm.max_map_count {}",
+            limit(self.os_limits.max_open_files),
+            limit(self.os_limits.max_map_count)
+        )?;
+        writeln!(f, "  config:")?;
+        for (name, value) in &self.config {
+            writeln!(f, "    {name} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_hides_passwords_and_flags_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let props = HashMap::from([
+            ("node.id".to_string(), "1".to_string()),
+            (
+                DELEGATION_TOKEN_SECRET_KEY_CONFIG.to_string(),
+                "hunter2".to_string(),
+            ),
+            (
+                "listener.name.internal.ssl.keystore.password".to_string(),
+                "hunter2".to_string(),
+            ),
+        ]);
+        let mut report = StartupReport::new(
+            1,
+            BTreeSet::from([ProcessRole::Broker]),
+            &props,
+            vec![],
+            vec![],
+            &[dir.path().to_path_buf(), missing.clone()],
+        );
+        report.os_limits = OsLimits {
+            max_open_files: Some(1024),
+            max_map_count: Some(MIN_RECOMMENDED_MAX_MAP_COUNT),
+        };
+
+        let text = report.to_string();
+        assert!(!text.contains("hunter2"));
+        assert!(text.contains(&format!("{DELEGATION_TOKEN_SECRET_KEY_CONFIG} = {HIDDEN}")));
+        assert!(text.contains("node.id = 1"));
+        assert!(text.contains("max open files 1024"));
+
+        let warnings = report.warnings();
+        assert_eq!(2, warnings.len());
+        assert!(warnings[0].contains(&missing.display().to_string()));
+        assert!(warnings[1].contains("open files"));
+    }
+
+    #[test]
+    fn test_parse_max_open_files() {
+        let limits = "Limit                     Soft Limit           Hard Limit           Units\n\
+                      Max cpu time              unlimited            unlimited            seconds\n\
+                      Max open files            1048576              1048576              files\n";
+        assert_eq!(Some(1_048_576), parse_max_open_files(limits));
+        assert_eq!(
+            Some(u64::MAX),
+            parse_max_open_files("Max open files unlimited unlimited files")
+        );
+        assert_eq!(None, parse_max_open_files("Max processes 10 10 processes"));
+    }
+}
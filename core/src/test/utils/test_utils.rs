@@ -17,6 +17,8 @@ use std::path::PathBuf;
 /* 0 gives a random port; you can then retrieve the assigned port from the Socket object. */
 const RANDOM_PORT: i32 = 0;
 
+const CONTROLLER_LISTENER_NAME: &str = "CONTROLLER";
+
 #[derive(Default)]
 pub struct BrokerConfigPropsBuilder {
     node_id: i32,
@@ -39,6 +41,8 @@ pub struct BrokerConfigPropsBuilder {
     num_partitions: Option<i32>,
     default_replication_factor: Option<i16>,
     enable_fetch_from_follower: Option<bool>,
+    quorum_voters: Option<String>,
+    controller: bool,
 }
 
 impl BrokerConfigPropsBuilder {
@@ -49,12 +53,125 @@ impl BrokerConfigPropsBuilder {
         }
     }
 
+    /// A builder for the configs of a node which is only a KRaft controller, listening on the
+    /// `CONTROLLER` listener and voting in `quorum_voters`.
+    pub fn controller(node_id: i32, quorum_voters: impl Into<String>) -> Self {
+        Self {
+            node_id,
+            quorum_voters: Some(quorum_voters.into()),
+            controller: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn enable_controlled_shutdown(mut self, enable_controlled_shutdown: bool) -> Self {
+        self.enable_controlled_shutdown = Some(enable_controlled_shutdown);
+        self
+    }
+
+    pub fn enable_delete_topic(mut self, enable_delete_topic: bool) -> Self {
+        self.enable_delete_topic = Some(enable_delete_topic);
+        self
+    }
+
     pub fn port(mut self, port: i32) -> Self {
         self.port = Some(port);
         self
     }
 
+    pub fn inter_broker_security_protocol(
+        mut self,
+        inter_broker_security_protocol: SecurityProtocol,
+    ) -> Self {
+        self.inter_broker_security_protocol = Some(inter_broker_security_protocol);
+        self
+    }
+
+    pub fn trust_store_file(mut self, trust_store_file: PathBuf) -> Self {
+        self.trust_store_file = Some(trust_store_file);
+        self
+    }
+
+    pub fn sasl_properties(mut self, sasl_properties: HashMap<String, String>) -> Self {
+        self.sasl_properties = Some(sasl_properties);
+        self
+    }
+
+    pub fn enable_plaintext(mut self, enable_plaintext: bool) -> Self {
+        self.enable_plaintext = Some(enable_plaintext);
+        self
+    }
+
+    pub fn enable_sasl_plaintext(mut self, enable_sasl_plaintext: bool) -> Self {
+        self.enable_sasl_plaintext = Some(enable_sasl_plaintext);
+        self
+    }
+
+    pub fn sasl_plaintext_port(mut self, sasl_plaintext_port: i32) -> Self {
+        self.sasl_plaintext_port = Some(sasl_plaintext_port);
+        self
+    }
+
+    pub fn enable_ssl(mut self, enable_ssl: bool) -> Self {
+        self.enable_ssl = Some(enable_ssl);
+        self
+    }
+
+    pub fn ssl_port(mut self, ssl_port: i32) -> Self {
+        self.ssl_port = Some(ssl_port);
+        self
+    }
+
+    pub fn enable_sasl_ssl(mut self, enable_sasl_ssl: bool) -> Self {
+        self.enable_sasl_ssl = Some(enable_sasl_ssl);
+        self
+    }
+
+    pub fn sasl_ssl_port(mut self, sasl_ssl_port: i32) -> Self {
+        self.sasl_ssl_port = Some(sasl_ssl_port);
+        self
+    }
+
+    pub fn rack(mut self, rack: impl Into<String>) -> Self {
+        self.rack = Some(rack.into());
+        self
+    }
+
+    pub fn log_dir_count(mut self, log_dir_count: i32) -> Self {
+        self.log_dir_count = Some(log_dir_count);
+        self
+    }
+
+    pub fn enable_token(mut self, enable_token: bool) -> Self {
+        self.enable_token = Some(enable_token);
+        self
+    }
+
+    pub fn num_partitions(mut self, num_partitions: i32) -> Self {
+        self.num_partitions = Some(num_partitions);
+        self
+    }
+
+    pub fn default_replication_factor(mut self, default_replication_factor: i16) -> Self {
+        self.default_replication_factor = Some(default_replication_factor);
+        self
+    }
+
+    pub fn enable_fetch_from_follower(mut self, enable_fetch_from_follower: bool) -> Self {
+        self.enable_fetch_from_follower = Some(enable_fetch_from_follower);
+        self
+    }
+
+    /// The `controller.quorum.voters` of the node, as `{id}@{host}:{port}` entries.
+    pub fn quorum_voters(mut self, quorum_voters: impl Into<String>) -> Self {
+        self.quorum_voters = Some(quorum_voters.into());
+        self
+    }
+
     pub fn build(self) -> HashMap<String, String> {
+        if self.controller {
+            return self.build_controller();
+        }
         let enable_controlled_shutdown = self.enable_controlled_shutdown.unwrap_or(true);
         let enable_delete_topic = self.enable_delete_topic.unwrap_or(true);
         let port = self.port.unwrap_or(RANDOM_PORT);
@@ -92,7 +209,7 @@ impl BrokerConfigPropsBuilder {
 
         let listeners: String = protocol_and_ports
             .iter()
-            .map(|(protocol, port)| format!("{}:localhost:{}", protocol.name(), port))
+            .map(|(protocol, port)| format!("{}://localhost:{}", protocol.name(), port))
             .collect::<Vec<_>>()
             .join(",");
 
@@ -128,7 +245,7 @@ impl BrokerConfigPropsBuilder {
         );
         props.insert(
             raft_config::CONTROLLER_LISTENER_NAMES_CONFIG.to_string(),
-            "CONTROLLER".to_string(),
+            CONTROLLER_LISTENER_NAME.to_string(),
         );
         props.insert(
             socket_server_config::LISTENER_SECURITY_PROTOCOL_MAP_CONFIG.to_string(),
@@ -138,7 +255,7 @@ impl BrokerConfigPropsBuilder {
                     .map(|p| format!("{}:{}", p.0.name(), p.0.name()))
                     .collect::<Vec<_>>()
                     .join(",");
-                format!("{},CONTROLLER:PLAINTEXT", map_str)
+                format!("{map_str},{CONTROLLER_LISTENER_NAME}:PLAINTEXT")
             },
         );
 
@@ -177,14 +294,14 @@ impl BrokerConfigPropsBuilder {
             raft_config::PROCESS_ROLES_CONFIG.to_string(),
             "broker".to_string(),
         );
-        // Note: this is just a placeholder value for controller.quorum.voters. JUnit
-        // tests use random port assignment, so the controller ports are not known ahead of
-        // time. Therefore, we ignore controller.quorum.voters and use
-        // controllerQuorumVotersFuture instead.
-        // props.insert(
-        //     QuorumConfig::QUORUM_VOTERS_CONFIG.to_string(),
-        //     "1000@localhost:0".to_string(),
-        // );
+        // Tests use random port assignment, so the controller ports are usually not known ahead
+        // of time and controller.quorum.voters is only set when the test provides it.
+        if let Some(quorum_voters) = &self.quorum_voters {
+            props.insert(
+                raft_config::QUORUM_VOTERS_CONFIG.to_string(),
+                quorum_voters.clone(),
+            );
+        }
         props.insert(
             replication_configs::REPLICA_SOCKET_TIMEOUT_MS_CONFIG.to_string(),
             "1500".to_string(),
@@ -303,4 +420,113 @@ impl BrokerConfigPropsBuilder {
 
         props
     }
+
+    fn build_controller(self) -> HashMap<String, String> {
+        let port = self.port.unwrap_or(RANDOM_PORT);
+        let mut props = HashMap::new();
+        props.insert(
+            server_configs::UNSTABLE_FEATURE_VERSIONS_ENABLE_CONFIG.to_string(),
+            "true".to_string(),
+        );
+        props.insert(
+            raft_config::SERVER_MAX_STARTUP_TIME_MS_CONFIG.to_string(),
+            "600000".to_string(),
+        );
+        props.insert(
+            raft_config::PROCESS_ROLES_CONFIG.to_string(),
+            "controller".to_string(),
+        );
+        props.insert(
+            raft_config::NODE_ID_CONFIG.to_string(),
+            self.node_id.to_string(),
+        );
+        props.insert(
+            socket_server_config::LISTENERS_CONFIG.to_string(),
+            format!("{CONTROLLER_LISTENER_NAME}://localhost:{port}"),
+        );
+        props.insert(
+            raft_config::CONTROLLER_LISTENER_NAMES_CONFIG.to_string(),
+            CONTROLLER_LISTENER_NAME.to_string(),
+        );
+        props.insert(
+            socket_server_config::LISTENER_SECURITY_PROTOCOL_MAP_CONFIG.to_string(),
+            format!("{CONTROLLER_LISTENER_NAME}:PLAINTEXT"),
+        );
+        if let Some(quorum_voters) = self.quorum_voters {
+            props.insert(raft_config::QUORUM_VOTERS_CONFIG.to_string(), quorum_voters);
+        }
+        props.insert(
+            server_log_configs::LOG_DIR_CONFIG.to_string(),
+            temp_directory_default()
+                .expect("tmp dir should be created")
+                .to_str()
+                .expect("Should be a valid path to tmp dir")
+                .to_string(),
+        );
+        props.insert(
+            replication_configs::CONTROLLER_SOCKET_TIMEOUT_MS_CONFIG.to_string(),
+            "1500".to_string(),
+        );
+        if let Some(rack) = self.rack {
+            props.insert(server_configs::BROKER_RACK_CONFIG.to_string(), rack);
+        }
+        props
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setters() {
+        let props = BrokerConfigPropsBuilder::builder(1)
+            .enable_plaintext(false)
+            .enable_sasl_ssl(true)
+            .sasl_ssl_port(9094)
+            .inter_broker_security_protocol(SecurityProtocol::SaslSsl)
+            .rack("rack-a")
+            .enable_token(true)
+            .num_partitions(3)
+            .quorum_voters("3000@localhost:9093")
+            .build();
+        assert_eq!(
+            "SASL_SSL://localhost:9094",
+            props[socket_server_config::LISTENERS_CONFIG]
+        );
+        assert_eq!(
+            "SASL_SSL",
+            props[replication_configs::INTER_BROKER_SECURITY_PROTOCOL_CONFIG]
+        );
+        assert_eq!("rack-a", props[server_configs::BROKER_RACK_CONFIG]);
+        assert!(
+            props
+                .contains_key(delegation_token_manager_configs::DELEGATION_TOKEN_SECRET_KEY_CONFIG)
+        );
+        assert_eq!("3", props[server_log_configs::NUM_PARTITIONS_CONFIG]);
+        assert_eq!(
+            "3000@localhost:9093",
+            props[raft_config::QUORUM_VOTERS_CONFIG]
+        );
+        assert_eq!("broker", props[raft_config::PROCESS_ROLES_CONFIG]);
+    }
+
+    #[test]
+    fn test_controller() {
+        let props = BrokerConfigPropsBuilder::controller(3000, "3000@localhost:9093")
+            .port(9093)
+            .build();
+        assert_eq!("controller", props[raft_config::PROCESS_ROLES_CONFIG]);
+        assert_eq!("3000", props[raft_config::NODE_ID_CONFIG]);
+        assert_eq!(
+            "CONTROLLER://localhost:9093",
+            props[socket_server_config::LISTENERS_CONFIG]
+        );
+        assert_eq!(
+            "3000@localhost:9093",
+            props[raft_config::QUORUM_VOTERS_CONFIG]
+        );
+        assert!(!props.contains_key(server_configs::BROKER_ID_CONFIG));
+        assert!(!props.contains_key(socket_server_config::ADVERTISED_LISTENERS_CONFIG));
+    }
 }
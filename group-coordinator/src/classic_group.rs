@@ -0,0 +1,275 @@
+//! The members of a classic group and the protocol they agree on.
+//!
+//! Each member joins with the protocols it supports, most preferred first: for a consumer,
+//! the names of its assignors. The group follows a protocol all its members support, the one
+//! most of them prefer, and rejects a member supporting none of the protocols of the others.
+//! The protocols of a consumer group also tell its rebalance protocol: COOPERATIVE once it
+//! selected `cooperative-sticky`, EAGER otherwise. A member mixing up the two, e.g. one
+//! upgraded to `cooperative-sticky` without keeping the previous assignor as a fallback, is
+//! rejected with an error which says so.
+
+use rafka_clients::clients::rebalance_protocol::RebalanceProtocol;
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The protocol type of the consumer groups.
+pub const CONSUMER_PROTOCOL_TYPE: &str = "consumer";
+
+/// A member of a classic group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassicGroupMember {
+    pub member_id: String,
+    pub protocol_type: String,
+    /// The names of the protocols the member supports, most preferred first.
+    pub protocols: Vec<String>,
+}
+
+impl ClassicGroupMember {
+    pub fn new(member_id: &str, protocol_type: &str, protocols: &[&str]) -> Self {
+        Self {
+            member_id: member_id.to_string(),
+            protocol_type: protocol_type.to_string(),
+            protocols: protocols.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    /// The rebalance protocol the member follows, if it is a consumer: COOPERATIVE if all its
+    /// assignors support it.
+    fn rebalance_protocol(&self) -> Option<RebalanceProtocol> {
+        (self.protocol_type == CONSUMER_PROTOCOL_TYPE)
+            .then(|| RebalanceProtocol::of_assignors(self.protocols.iter().map(String::as_str)))
+    }
+}
+
+/// A classic group: its members, its generation and the protocol it selected.
+#[derive(Debug, Clone)]
+pub struct ClassicGroup {
+    group_id: String,
+    members: BTreeMap<String, ClassicGroupMember>,
+    generation_id: i32,
+    protocol_name: Option<String>,
+}
+
+impl ClassicGroup {
+    pub fn new(group_id: &str) -> Self {
+        Self {
+            group_id: group_id.to_string(),
+            members: BTreeMap::new(),
+            generation_id: 0,
+            protocol_name: None,
+        }
+    }
+
+    pub fn group_id(&self) -> &str {
+        &self.group_id
+    }
+
+    pub fn generation_id(&self) -> i32 {
+        self.generation_id
+    }
+
+    /// The protocol selected by the last rebalance.
+    pub fn protocol_name(&self) -> Option<&str> {
+        self.protocol_name.as_deref()
+    }
+
+    /// The rebalance protocol of the group, if it is a consumer group which rebalanced.
+    pub fn rebalance_protocol(&self) -> Option<RebalanceProtocol> {
+        let protocol_name = self.protocol_name.as_deref()?;
+        let consumers = self
+            .members
+            .values()
+            .all(|m| m.protocol_type == CONSUMER_PROTOCOL_TYPE);
+        consumers.then(|| RebalanceProtocol::of_assignors([protocol_name]))
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = &ClassicGroupMember> {
+        self.members.values()
+    }
+
+    /// Adds `member` to the group, or updates its protocols if it is a member already.
+    ///
+    /// Fails with INCONSISTENT_GROUP_PROTOCOL if it has no protocols, another protocol type
+    /// than the other members, or none of the protocols all of them support.
+    pub fn join(&mut self, member: ClassicGroupMember) -> Result<(), ApiError> {
+        if member.protocols.is_empty() {
+            return Err(ApiError::new(
+                Errors::InconsistentGroupProtocol,
+                format!("Member {} joined without any protocol", member.member_id),
+            ));
+        }
+        let others: Vec<&ClassicGroupMember> = self
+            .members
+            .values()
+            .filter(|m| m.member_id != member.member_id)
+            .collect();
+        if let Some(other) = others.first()
+            && other.protocol_type != member.protocol_type
+        {
+            return Err(ApiError::new(
+                Errors::InconsistentGroupProtocol,
+                format!(
+                    "Member {} has the protocol type {}, but the group {} has {}",
+                    member.member_id, member.protocol_type, self.group_id, other.protocol_type
+                ),
+            ));
+        }
+        let candidates = Self::candidates(others.iter().copied());
+        if !others.is_empty() && !member.protocols.iter().any(|p| candidates.contains(p)) {
+            return Err(Self::inconsistent_protocols(
+                &self.group_id,
+                &member,
+                &others,
+            ));
+        }
+        self.members.insert(member.member_id.clone(), member);
+        Ok(())
+    }
+
+    pub fn leave(&mut self, member_id: &str) -> Result<(), ApiError> {
+        self.members.remove(member_id).map(|_| ()).ok_or_else(|| {
+            ApiError::new(
+                Errors::UnknownMemberId,
+                format!("Member {member_id} is not in the group {}", self.group_id),
+            )
+        })
+    }
+
+    /// Starts the next generation of the group with the protocol its members vote for: each
+    /// votes for the protocol it prefers among those all support, and the ties go to the
+    /// protocol preferred by the first member. Returns the new generation id.
+    pub fn rebalance(&mut self) -> i32 {
+        let candidates = Self::candidates(self.members.values());
+        let mut votes: Vec<(&str, usize)> = Vec::new();
+        for member in self.members.values() {
+            let Some(vote) = member.protocols.iter().find(|p| candidates.contains(*p)) else {
+                continue;
+            };
+            match votes.iter_mut().find(|(protocol, _)| protocol == vote) {
+                Some((_, count)) => *count += 1,
+                None => votes.push((vote, 1)),
+            }
+        }
+        // max_by_key returns the last maximum: reversed, the ties go to the first vote.
+        self.protocol_name = votes
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map(|(protocol, _)| protocol.to_string());
+        self.generation_id += 1;
+        self.generation_id
+    }
+
+    /// The protocols all of `members` support.
+    fn candidates<'a>(
+        members: impl IntoIterator<Item = &'a ClassicGroupMember>,
+    ) -> BTreeSet<String> {
+        let mut members = members.into_iter();
+        let Some(first) = members.next() else {
+            return BTreeSet::new();
+        };
+        let mut candidates: BTreeSet<String> = first.protocols.iter().cloned().collect();
+        for member in members {
+            candidates.retain(|p| member.protocols.contains(p));
+        }
+        candidates
+    }
+
+    /// The error for `member` supporting none of the protocols of `others`, telling the
+    /// rebalance protocols apart when a consumer mixes them up.
+    fn inconsistent_protocols(
+        group_id: &str,
+        member: &ClassicGroupMember,
+        others: &[&ClassicGroupMember],
+    ) -> ApiError {
+        let mut message = format!(
+            "Member {} supports none of the protocols of the group {group_id}: it supports [{}]",
+            member.member_id,
+            member.protocols.join(", ")
+        );
+        let group_protocols: BTreeSet<RebalanceProtocol> = others
+            .iter()
+            .filter_map(|m| m.rebalance_protocol())
+            .collect();
+        if let Some(member_protocol) = member.rebalance_protocol()
+            && !group_protocols.is_empty()
+            && !group_protocols.contains(&member_protocol)
+        {
+            let group_protocols: Vec<String> =
+                group_protocols.iter().map(|p| p.to_string()).collect();
+            message.push_str(&format!(
+                ", following the {member_protocol} rebalance protocol, while the other members \
+                 follow {}; list the assignor of the group after cooperative-sticky to upgrade \
+                 a group from EAGER to COOPERATIVE",
+                group_protocols.join(" and ")
+            ));
+        }
+        ApiError::new(Errors::InconsistentGroupProtocol, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::clients::rebalance_protocol::{
+        COOPERATIVE_STICKY_ASSIGNOR_NAME, RANGE_ASSIGNOR_NAME,
+    };
+
+    fn new_consumer(member_id: &str, assignors: &[&str]) -> ClassicGroupMember {
+        ClassicGroupMember::new(member_id, CONSUMER_PROTOCOL_TYPE, assignors)
+    }
+
+    #[test]
+    fn test_rolling_upgrade_to_cooperative() {
+        let mut group = ClassicGroup::new("group");
+        group
+            .join(new_consumer("a", &[RANGE_ASSIGNOR_NAME]))
+            .unwrap();
+        group
+            .join(new_consumer("b", &[RANGE_ASSIGNOR_NAME]))
+            .unwrap();
+        group.rebalance();
+        assert_eq!(Some(RebalanceProtocol::Eager), group.rebalance_protocol());
+
+        // A consumer upgraded without keeping range is told why it is rejected.
+        let error = group
+            .join(new_consumer("c", &[COOPERATIVE_STICKY_ASSIGNOR_NAME]))
+            .unwrap_err();
+        assert_eq!(Errors::InconsistentGroupProtocol, error.error());
+        assert!(error.message().contains("COOPERATIVE rebalance protocol"));
+
+        // Upgraded with range as a fallback, the group stays EAGER until all are.
+        let upgraded = [COOPERATIVE_STICKY_ASSIGNOR_NAME, RANGE_ASSIGNOR_NAME];
+        group.join(new_consumer("a", &upgraded)).unwrap();
+        group.rebalance();
+        assert_eq!(Some(RANGE_ASSIGNOR_NAME), group.protocol_name());
+        group.join(new_consumer("b", &upgraded)).unwrap();
+        assert_eq!(3, group.rebalance());
+        assert_eq!(
+            Some(RebalanceProtocol::Cooperative),
+            group.rebalance_protocol()
+        );
+
+        // Once range is dropped too, a consumer only supporting range is rejected.
+        group
+            .join(new_consumer("a", &[COOPERATIVE_STICKY_ASSIGNOR_NAME]))
+            .unwrap();
+        group
+            .join(new_consumer("b", &[COOPERATIVE_STICKY_ASSIGNOR_NAME]))
+            .unwrap();
+        let error = group
+            .join(new_consumer("d", &[RANGE_ASSIGNOR_NAME]))
+            .unwrap_err();
+        assert!(error.message().contains("EAGER rebalance protocol"));
+
+        assert!(
+            group
+                .join(ClassicGroupMember::new("e", "connect", &["v1"]))
+                .is_err()
+        );
+        assert_eq!(
+            Errors::UnknownMemberId,
+            group.leave("d").unwrap_err().error()
+        );
+    }
+}
@@ -15,6 +15,11 @@ const GROUP_INITIAL_REBALANCE_DELAY_MS_DOC: &str = "The amount of time the group
 before performing the first rebalance. A longer delay means potentially fewer rebalances, but increases the time until processing begins.";
 const GROUP_INITIAL_REBALANCE_DELAY_MS_DEFAULT: i32 = 3000;
 
+pub const GROUP_COORDINATOR_APPEND_LINGER_MS_CONFIG: &str = "group.coordinator.append.linger.ms";
+const GROUP_COORDINATOR_APPEND_LINGER_MS_DOC: &str = "The duration in milliseconds that the coordinator will wait for writes to accumulate before flushing them to disk. \
+Offset commits of the groups of a partition of the offsets topic arriving within this window are appended as a single batch.";
+const GROUP_COORDINATOR_APPEND_LINGER_MS_DEFAULT: i32 = 5;
+
 #[derive(Debug, EasyConfig)]
 pub struct GroupCoordinatorConfig {
     // Group coordinator configs
@@ -33,7 +38,15 @@ pub struct GroupCoordinatorConfig {
     documentation = OFFSETS_TOPIC_PARTITIONS_DOC,
     getter)]
     offsets_topic_partitions_config: u32,
-    
+
+    #[attr(name = GROUP_COORDINATOR_APPEND_LINGER_MS_CONFIG,
+    default = GROUP_COORDINATOR_APPEND_LINGER_MS_DEFAULT,
+    validator = Range::at_least(0),
+    importance = Importance::MEDIUM,
+    documentation = GROUP_COORDINATOR_APPEND_LINGER_MS_DOC,
+    getter)]
+    group_coordinator_append_linger_ms_config: i32,
+
     // Classic group configs
     #[attr(name = GROUP_INITIAL_REBALANCE_DELAY_MS_CONFIG,
     default = GROUP_INITIAL_REBALANCE_DELAY_MS_DEFAULT,
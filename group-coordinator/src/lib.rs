@@ -1 +1,5 @@
+pub mod classic_group;
 pub mod group_coordinator_config;
+pub mod offset_commit_batcher;
+pub mod offset_export;
+pub mod offset_metadata_manager;
@@ -0,0 +1,318 @@
+use crate::offset_metadata_manager::{OffsetAndMetadata, OffsetMetadataManager};
+use rafka_clients::common::protocol::errors::ApiError;
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use tracing::{debug, warn};
+
+/// The number of commits after which a batch is appended without waiting for the linger.
+pub const DEFAULT_MAX_BATCH_COMMITS: usize = 1000;
+
+/// The outcome of a commit: the offset following the batch it was appended with.
+pub type CommitResult = Result<i64, ApiError>;
+
+/// An offset committed by a group, as written to `__consumer_offsets`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetCommit {
+    pub group_id: String,
+    pub topic_partition: TopicPartition,
+    pub offset: OffsetAndMetadata,
+}
+
+/// Appends the records of the coordinator to the partitions of `__consumer_offsets`.
+pub trait PartitionWriter {
+    /// Appends `commits` to `partition` as a single batch, returning the offset following it
+    /// once it is committed.
+    fn append(&mut self, partition: i32, commits: &[OffsetCommit]) -> CommitResult;
+}
+
+/// The commits waiting for the append of a partition.
+#[derive(Debug)]
+struct PendingBatch {
+    created_ms: i64,
+    commits: Vec<OffsetCommit>,
+    completions: Vec<Sender<CommitResult>>,
+}
+
+/// Accumulates the offset commits of the groups of each `__consumer_offsets` partition and
+/// appends them together.
+///
+/// Appending each commit on its own costs an append, and with `acks=all` a replication round
+/// trip, per commit: consumers committing after every poll make the offsets partitions the
+/// busiest of the cluster. Instead, a commit joins the pending batch of its partition and is
+/// completed once the batch is appended, at most `group.coordinator.append.linger.ms` after
+/// the batch was started or once it holds `max_batch_commits` commits, whichever comes first.
+/// The committed offsets only become visible to OffsetFetch once appended, and a failed
+/// append fails every commit of the batch.
+pub struct OffsetCommitBatcher<W> {
+    offsets: OffsetMetadataManager,
+    writer: W,
+    linger_ms: i64,
+    max_batch_commits: usize,
+    batches: BTreeMap<i32, PendingBatch>,
+}
+
+impl<W: PartitionWriter> OffsetCommitBatcher<W> {
+    pub fn new(offsets: OffsetMetadataManager, writer: W, linger_ms: i64) -> Self {
+        Self {
+            offsets,
+            writer,
+            linger_ms,
+            max_batch_commits: DEFAULT_MAX_BATCH_COMMITS,
+            batches: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_max_batch_commits(mut self, max_batch_commits: usize) -> Self {
+        self.max_batch_commits = max_batch_commits.max(1);
+        self
+    }
+
+    /// The committed offsets, without the pending commits.
+    pub fn offsets(&self) -> &OffsetMetadataManager {
+        &self.offsets
+    }
+
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    /// The number of commits waiting for the append of `partition`.
+    pub fn pending_commits(&self, partition: i32) -> usize {
+        self.batches.get(&partition).map_or(0, |b| b.commits.len())
+    }
+
+    /// Adds the offsets committed by `group_id` to the pending batch of `partition`, the
+    /// `__consumer_offsets` partition of the group. The receiver gets the outcome once the
+    /// batch is appended.
+    pub fn commit(
+        &mut self,
+        partition: i32,
+        group_id: &str,
+        offsets: Vec<(TopicPartition, OffsetAndMetadata)>,
+        now_ms: i64,
+    ) -> Receiver<CommitResult> {
+        let (sender, receiver) = channel();
+        let batch = self
+            .batches
+            .entry(partition)
+            .or_insert_with(|| PendingBatch {
+                created_ms: now_ms,
+                commits: Vec::new(),
+                completions: Vec::new(),
+            });
+        batch.commits.extend(
+            offsets
+                .into_iter()
+                .map(|(topic_partition, offset)| OffsetCommit {
+                    group_id: group_id.to_string(),
+                    topic_partition,
+                    offset,
+                }),
+        );
+        batch.completions.push(sender);
+        if self.linger_ms <= 0 || batch.commits.len() >= self.max_batch_commits {
+            self.flush(partition);
+        }
+        receiver
+    }
+
+    /// Appends the batches which lingered for `group.coordinator.append.linger.ms`.
+    pub fn poll(&mut self, now_ms: i64) {
+        let expired: Vec<i32> = self
+            .batches
+            .iter()
+            .filter(|(_, batch)| now_ms - batch.created_ms >= self.linger_ms)
+            .map(|(partition, _)| *partition)
+            .collect();
+        for partition in expired {
+            self.flush(partition);
+        }
+    }
+
+    /// The time until the oldest batch is due, `None` if no commit is pending.
+    pub fn next_flush_delay_ms(&self, now_ms: i64) -> Option<i64> {
+        self.batches
+            .values()
+            .map(|batch| (batch.created_ms + self.linger_ms - now_ms).max(0))
+            .min()
+    }
+
+    /// Appends every pending batch, e.g. before the coordinator unloads its partitions.
+    pub fn flush_all(&mut self) {
+        let partitions: Vec<i32> = self.batches.keys().copied().collect();
+        for partition in partitions {
+            self.flush(partition);
+        }
+    }
+
+    fn flush(&mut self, partition: i32) {
+        let Some(batch) = self.batches.remove(&partition) else {
+            return;
+        };
+        let result = self.writer.append(partition, &batch.commits);
+        match &result {
+            Ok(_) => {
+                debug!(
+                    "Appended {} offset commits of {} requests to partition {partition}",
+                    batch.commits.len(),
+                    batch.completions.len()
+                );
+                for commit in batch.commits {
+                    self.offsets.commit_offset(
+                        &commit.group_id,
+                        commit.topic_partition,
+                        commit.offset,
+                    );
+                }
+            }
+            Err(e) => warn!(
+                "Failed to append {} offset commits to partition {partition}: {e}",
+                batch.commits.len()
+            ),
+        }
+        for completion in batch.completions {
+            // The committing request may have timed out and dropped its receiver.
+            let _ = completion.send(result.clone());
+        }
+    }
+}
+
+/// The `__consumer_offsets` partition of `group_id`, computed like the Java coordinator does
+/// from the hash code of the group id, so that every broker agrees on it.
+pub fn partition_for(group_id: &str, num_partitions: u32) -> i32 {
+    let hash = group_id
+        .encode_utf16()
+        .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32));
+    // Utils.abs maps i32::MIN to 0.
+    let hash = if hash == i32::MIN { 0 } else { hash.abs() };
+    hash % num_partitions as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::protocol::errors::Errors;
+
+    /// Records the batches appended to each partition.
+    #[derive(Default)]
+    struct RecordingWriter {
+        appends: Vec<(i32, usize)>,
+        failure: Option<Errors>,
+    }
+
+    impl PartitionWriter for RecordingWriter {
+        fn append(&mut self, partition: i32, commits: &[OffsetCommit]) -> CommitResult {
+            if let Some(error) = self.failure {
+                return Err(ApiError::new(error, "append failed"));
+            }
+            self.appends.push((partition, commits.len()));
+            Ok(self.appends.len() as i64)
+        }
+    }
+
+    fn offset(committed_offset: i64) -> OffsetAndMetadata {
+        OffsetAndMetadata {
+            committed_offset,
+            leader_epoch: None,
+            metadata: String::new(),
+            commit_timestamp_ms: 0,
+        }
+    }
+
+    fn commit(
+        batcher: &mut OffsetCommitBatcher<RecordingWriter>,
+        partition: i32,
+        group_id: &str,
+        committed_offset: i64,
+        now_ms: i64,
+    ) -> Receiver<CommitResult> {
+        batcher.commit(
+            partition,
+            group_id,
+            vec![(TopicPartition::new("foo", 0), offset(committed_offset))],
+            now_ms,
+        )
+    }
+
+    #[test]
+    fn test_concurrent_commits_are_appended_together() {
+        let mut batcher =
+            OffsetCommitBatcher::new(OffsetMetadataManager::new(), RecordingWriter::default(), 5);
+        let first = commit(&mut batcher, 0, "a", 1, 100);
+        let second = commit(&mut batcher, 0, "b", 2, 102);
+        let other = commit(&mut batcher, 1, "c", 3, 103);
+        assert_eq!(2, batcher.pending_commits(0));
+        assert_eq!(Some(3), batcher.next_flush_delay_ms(102));
+        assert!(first.try_recv().is_err());
+        // Pending commits are not visible yet.
+        assert!(
+            batcher
+                .offsets()
+                .offset("a", &TopicPartition::new("foo", 0))
+                .is_none()
+        );
+
+        batcher.poll(105);
+        assert_eq!(vec![(0, 2)], batcher.writer().appends);
+        assert_eq!(Ok(1), first.recv().unwrap());
+        assert_eq!(Ok(1), second.recv().unwrap());
+        assert!(other.try_recv().is_err());
+        assert_eq!(
+            2,
+            batcher
+                .offsets()
+                .offset("b", &TopicPartition::new("foo", 0))
+                .unwrap()
+                .committed_offset
+        );
+
+        batcher.flush_all();
+        assert_eq!(vec![(0, 2), (1, 1)], batcher.writer().appends);
+        assert_eq!(Ok(2), other.recv().unwrap());
+        assert_eq!(None, batcher.next_flush_delay_ms(200));
+    }
+
+    #[test]
+    fn test_full_batch_and_no_linger_are_appended_at_once() {
+        let mut batcher =
+            OffsetCommitBatcher::new(OffsetMetadataManager::new(), RecordingWriter::default(), 5)
+                .with_max_batch_commits(2);
+        commit(&mut batcher, 0, "a", 1, 0);
+        let full = commit(&mut batcher, 0, "b", 1, 0);
+        assert_eq!(Ok(1), full.recv().unwrap());
+
+        let mut batcher =
+            OffsetCommitBatcher::new(OffsetMetadataManager::new(), RecordingWriter::default(), 0);
+        assert_eq!(Ok(1), commit(&mut batcher, 0, "a", 1, 0).recv().unwrap());
+    }
+
+    #[test]
+    fn test_failed_append_fails_the_batch() {
+        let writer = RecordingWriter {
+            failure: Some(Errors::NotEnoughReplicas),
+            ..Default::default()
+        };
+        let mut batcher = OffsetCommitBatcher::new(OffsetMetadataManager::new(), writer, 5);
+        let first = commit(&mut batcher, 0, "a", 1, 0);
+        let second = commit(&mut batcher, 0, "b", 1, 0);
+        batcher.poll(5);
+        for receiver in [first, second] {
+            assert_eq!(
+                Errors::NotEnoughReplicas,
+                receiver.recv().unwrap().unwrap_err().error()
+            );
+        }
+        assert_eq!(0, batcher.offsets().group_ids().count());
+    }
+
+    #[test]
+    fn test_partition_for() {
+        // "group".hashCode() is 98629247 in Java.
+        assert_eq!(98629247 % 50, partition_for("group", 50));
+        assert_eq!(0, partition_for("", 50));
+        for group_id in ["a", "consumer-group-1", "🦀"] {
+            assert!((0..50).contains(&partition_for(group_id, 50)));
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// The version of the export format written by this broker.
+pub const EXPORT_VERSION: u16 = 1;
+
+#[derive(Error, Debug)]
+pub enum OffsetExportError {
+    #[error("Invalid offsets export: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+
+    #[error("Unsupported offsets export version {0}, expected at most {EXPORT_VERSION}")]
+    UnsupportedVersion(u16),
+
+    #[error("The group id of an offsets export must not be empty")]
+    EmptyGroupId,
+
+    #[error("Invalid offset {offset} for {topic}-{partition}")]
+    InvalidOffset {
+        topic: String,
+        partition: i32,
+        offset: i64,
+    },
+
+    #[error("{0}-{1} appears more than once in the offsets export")]
+    DuplicatePartition(String, i32),
+
+    #[error("Unable to parse line {0} of the CSV offsets, expected <topic>,<partition>,<offset>")]
+    InvalidCsvLine(usize),
+}
+
+/// An offset of an exported group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedOffset {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leader_epoch: Option<i32>,
+    #[serde(default)]
+    pub metadata: String,
+}
+
+/// The committed offsets of a consumer group, in the JSON format the offsets are exported
+/// to and imported from, e.g.
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "group_id": "orders",
+///   "offsets": [{ "topic": "payments", "partition": 0, "offset": 42, "metadata": "" }]
+/// }
+/// ```
+///
+/// Apache Kafka's `kafka-consumer-groups.sh --reset-offsets --export` writes the offsets of a
+/// group as `<topic>,<partition>,<offset>` CSV lines, which [from_csv](Self::from_csv) and
+/// [to_csv](Self::to_csv) convert, so groups can be migrated in both directions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupOffsetsExport {
+    pub version: u16,
+    pub group_id: String,
+    pub offsets: Vec<ExportedOffset>,
+}
+
+impl GroupOffsetsExport {
+    pub fn new(group_id: impl Into<String>, offsets: Vec<ExportedOffset>) -> Self {
+        Self {
+            version: EXPORT_VERSION,
+            group_id: group_id.into(),
+            offsets,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("an offsets export is always serializable")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, OffsetExportError> {
+        let export: Self = serde_json::from_str(json)?;
+        export.validate()?;
+        Ok(export)
+    }
+
+    /// Parses the CSV offsets of `group_id`. Empty lines are skipped.
+    pub fn from_csv(group_id: impl Into<String>, csv: &str) -> Result<Self, OffsetExportError> {
+        let mut offsets = vec![];
+        for (index, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || OffsetExportError::InvalidCsvLine(index + 1);
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [topic, partition, offset] = fields[..] else {
+                return Err(invalid());
+            };
+            offsets.push(ExportedOffset {
+                topic: topic.to_string(),
+                partition: partition.parse().map_err(|_| invalid())?,
+                offset: offset.parse().map_err(|_| invalid())?,
+                leader_epoch: None,
+                metadata: String::new(),
+            });
+        }
+        let export = Self::new(group_id, offsets);
+        export.validate()?;
+        Ok(export)
+    }
+
+    pub fn to_csv(&self) -> String {
+        self.offsets
+            .iter()
+            .map(|o| format!("{},{},{}\n", o.topic, o.partition, o.offset))
+            .collect()
+    }
+
+    /// Checks the export can be imported: a known version, a group id, and one valid offset
+    /// per partition.
+    pub fn validate(&self) -> Result<(), OffsetExportError> {
+        if self.version > EXPORT_VERSION {
+            return Err(OffsetExportError::UnsupportedVersion(self.version));
+        }
+        if self.group_id.is_empty() {
+            return Err(OffsetExportError::EmptyGroupId);
+        }
+        let mut seen = HashSet::new();
+        for o in &self.offsets {
+            if o.topic.is_empty() || o.partition < 0 || o.offset < 0 {
+                return Err(OffsetExportError::InvalidOffset {
+                    topic: o.topic.clone(),
+                    partition: o.partition,
+                    offset: o.offset,
+                });
+            }
+            if !seen.insert((o.topic.as_str(), o.partition)) {
+                return Err(OffsetExportError::DuplicatePartition(
+                    o.topic.clone(),
+                    o.partition,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let export = GroupOffsetsExport::new(
+            "orders",
+            vec![ExportedOffset {
+                topic: "payments".to_string(),
+                partition: 0,
+                offset: 42,
+                leader_epoch: Some(3),
+                metadata: "m".to_string(),
+            }],
+        );
+        assert_eq!(
+            export,
+            GroupOffsetsExport::from_json(&export.to_json()).unwrap()
+        );
+
+        let parsed = GroupOffsetsExport::from_json(
+            r#"{"version":1,"group_id":"g","offsets":[{"topic":"t","partition":1,"offset":5}]}"#,
+        )
+        .unwrap();
+        assert_eq!(None, parsed.offsets[0].leader_epoch);
+        assert_eq!("", parsed.offsets[0].metadata);
+    }
+
+    #[test]
+    fn test_invalid_exports() {
+        assert!(matches!(
+            GroupOffsetsExport::from_json("{"),
+            Err(OffsetExportError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            GroupOffsetsExport::from_json(r#"{"version":2,"group_id":"g","offsets":[]}"#),
+            Err(OffsetExportError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            GroupOffsetsExport::from_json(r#"{"version":1,"group_id":"","offsets":[]}"#),
+            Err(OffsetExportError::EmptyGroupId)
+        ));
+        assert!(matches!(
+            GroupOffsetsExport::from_csv("g", "t,0,1\nt,0,2"),
+            Err(OffsetExportError::DuplicatePartition(_, 0))
+        ));
+        assert!(matches!(
+            GroupOffsetsExport::from_csv("g", "t,0,-1"),
+            Err(OffsetExportError::InvalidOffset { offset: -1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_csv() {
+        let csv = "payments,0,42\n\npayments,1,7\n";
+        let export = GroupOffsetsExport::from_csv("orders", csv).unwrap();
+        assert_eq!(2, export.offsets.len());
+        assert_eq!(7, export.offsets[1].offset);
+        assert_eq!("payments,0,42\npayments,1,7\n", export.to_csv());
+        assert!(matches!(
+            GroupOffsetsExport::from_csv("orders", "payments,0,42\npayments,1"),
+            Err(OffsetExportError::InvalidCsvLine(2))
+        ));
+    }
+}
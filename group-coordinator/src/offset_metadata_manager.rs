@@ -0,0 +1,195 @@
+use crate::offset_export::{ExportedOffset, GroupOffsetsExport, OffsetExportError};
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::collections::BTreeMap;
+use tracing::info;
+
+/// A committed offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetAndMetadata {
+    pub committed_offset: i64,
+    /// The leader epoch of the last consumed record, if the consumer knew it.
+    pub leader_epoch: Option<i32>,
+    pub metadata: String,
+    pub commit_timestamp_ms: i64,
+}
+
+/// How an import treats the offsets the group already committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Overwrites the offsets of the imported partitions and keeps the others.
+    Merge,
+    /// Resets the group to the imported offsets, dropping those of the other partitions.
+    Replace,
+}
+
+/// Keeps the offsets committed by the consumer groups.
+#[derive(Debug, Default)]
+pub struct OffsetMetadataManager {
+    offsets: BTreeMap<String, BTreeMap<TopicPartition, OffsetAndMetadata>>,
+}
+
+impl OffsetMetadataManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn commit_offset(
+        &mut self,
+        group_id: &str,
+        topic_partition: TopicPartition,
+        offset: OffsetAndMetadata,
+    ) {
+        self.offsets
+            .entry(group_id.to_string())
+            .or_default()
+            .insert(topic_partition, offset);
+    }
+
+    pub fn offset(
+        &self,
+        group_id: &str,
+        topic_partition: &TopicPartition,
+    ) -> Option<&OffsetAndMetadata> {
+        self.offsets.get(group_id)?.get(topic_partition)
+    }
+
+    /// The offsets committed by the group, ordered by partition.
+    pub fn offsets(
+        &self,
+        group_id: &str,
+    ) -> impl Iterator<Item = (&TopicPartition, &OffsetAndMetadata)> {
+        self.offsets.get(group_id).into_iter().flatten()
+    }
+
+    pub fn group_ids(&self) -> impl Iterator<Item = &str> {
+        self.offsets.keys().map(String::as_str)
+    }
+
+    pub fn delete_group(&mut self, group_id: &str) -> bool {
+        self.offsets.remove(group_id).is_some()
+    }
+
+    /// Exports all the offsets committed by the group.
+    pub fn export_group(&self, group_id: &str) -> GroupOffsetsExport {
+        GroupOffsetsExport::new(
+            group_id,
+            self.offsets(group_id)
+                .map(|(tp, offset)| ExportedOffset {
+                    topic: tp.topic().to_string(),
+                    partition: tp.partition(),
+                    offset: offset.committed_offset,
+                    leader_epoch: offset.leader_epoch,
+                    metadata: offset.metadata.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Commits the exported offsets for the group of the export, as of `now_ms`. Returns the
+    /// number of imported offsets.
+    ///
+    /// The group must have no members, as a consumer of the group would overwrite the
+    /// imported offsets with its next commit.
+    pub fn import_group(
+        &mut self,
+        export: &GroupOffsetsExport,
+        mode: ImportMode,
+        now_ms: i64,
+    ) -> Result<usize, OffsetExportError> {
+        export.validate()?;
+        let offsets = self.offsets.entry(export.group_id.clone()).or_default();
+        if mode == ImportMode::Replace {
+            offsets.clear();
+        }
+        for exported in &export.offsets {
+            offsets.insert(
+                TopicPartition::new(exported.topic.clone(), exported.partition),
+                OffsetAndMetadata {
+                    committed_offset: exported.offset,
+                    leader_epoch: exported.leader_epoch,
+                    metadata: exported.metadata.clone(),
+                    commit_timestamp_ms: now_ms,
+                },
+            );
+        }
+        info!(
+            "Imported {} offsets of group {} ({mode:?})",
+            export.offsets.len(),
+            export.group_id
+        );
+        Ok(export.offsets.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offset(committed_offset: i64) -> OffsetAndMetadata {
+        OffsetAndMetadata {
+            committed_offset,
+            leader_epoch: Some(1),
+            metadata: "".to_string(),
+            commit_timestamp_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let mut source = OffsetMetadataManager::new();
+        source.commit_offset("group", TopicPartition::new("foo", 1), offset(7));
+        source.commit_offset("group", TopicPartition::new("foo", 0), offset(5));
+        source.commit_offset("other", TopicPartition::new("bar", 0), offset(3));
+        let export = source.export_group("group");
+        assert_eq!(
+            vec![("foo", 0, 5), ("foo", 1, 7)],
+            export
+                .offsets
+                .iter()
+                .map(|o| (o.topic.as_str(), o.partition, o.offset))
+                .collect::<Vec<_>>()
+        );
+
+        let mut target = OffsetMetadataManager::new();
+        target.commit_offset("group", TopicPartition::new("baz", 0), offset(9));
+        let export = GroupOffsetsExport::from_json(&export.to_json()).unwrap();
+        assert_eq!(
+            2,
+            target
+                .import_group(&export, ImportMode::Merge, 100)
+                .unwrap()
+        );
+        assert_eq!(3, target.offsets("group").count());
+        let imported = target
+            .offset("group", &TopicPartition::new("foo", 1))
+            .unwrap();
+        assert_eq!(7, imported.committed_offset);
+        assert_eq!(100, imported.commit_timestamp_ms);
+
+        target
+            .import_group(&export, ImportMode::Replace, 200)
+            .unwrap();
+        assert_eq!(2, target.offsets("group").count());
+        assert_eq!(None, target.offset("group", &TopicPartition::new("baz", 0)));
+    }
+
+    #[test]
+    fn test_invalid_import_changes_nothing() {
+        let mut manager = OffsetMetadataManager::new();
+        manager.commit_offset("group", TopicPartition::new("foo", 0), offset(5));
+        let mut export = manager.export_group("group");
+        export.offsets[0].offset = -2;
+        assert!(
+            manager
+                .import_group(&export, ImportMode::Replace, 0)
+                .is_err()
+        );
+        assert_eq!(
+            5,
+            manager
+                .offset("group", &TopicPartition::new("foo", 0))
+                .unwrap()
+                .committed_offset
+        );
+    }
+}
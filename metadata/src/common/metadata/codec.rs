@@ -0,0 +1,183 @@
+//! Primitive encoders and decoders shared by the metadata records.
+//!
+//! Integers are big-endian, lengths are unsigned varints, and strings and byte arrays use the
+//! compact encoding, where the length is stored plus one so that zero can denote null.
+
+use crate::common::metadata::metadata_record::{RecordError, Result};
+use rafka_clients::common::utils::byte_utils::{
+    VarintError, read_unsigned_varint, write_unsigned_varint,
+};
+use rafka_clients::common::uuid::Uuid;
+
+pub(crate) fn write_unsigned_varint_to(buf: &mut Vec<u8>, value: u32) {
+    write_unsigned_varint(value, buf).expect("writing to a Vec cannot fail");
+}
+
+pub(crate) fn write_i8(buf: &mut Vec<u8>, value: i8) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn write_i16(buf: &mut Vec<u8>, value: i16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn write_i32(buf: &mut Vec<u8>, value: i32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn write_i64(buf: &mut Vec<u8>, value: i64) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn write_f64(buf: &mut Vec<u8>, value: f64) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn write_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn write_bool(buf: &mut Vec<u8>, value: bool) {
+    buf.push(value as u8);
+}
+
+pub(crate) fn write_uuid(buf: &mut Vec<u8>, value: Uuid) {
+    buf.extend_from_slice(&value.to_bytes());
+}
+
+/// Writes the compact length of an array, whose elements the caller writes next.
+pub(crate) fn write_array_len(buf: &mut Vec<u8>, len: usize) {
+    write_unsigned_varint_to(buf, len as u32 + 1);
+}
+
+pub(crate) fn write_bytes(buf: &mut Vec<u8>, value: &[u8]) {
+    write_unsigned_varint_to(buf, value.len() as u32 + 1);
+    buf.extend_from_slice(value);
+}
+
+pub(crate) fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_bytes(buf, value.as_bytes());
+}
+
+pub(crate) fn write_nullable_string(buf: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => write_string(buf, value),
+        None => write_unsigned_varint_to(buf, 0),
+    }
+}
+
+/// A cursor over an encoded record.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.buf.len() < N {
+            return Err(RecordError::Truncated);
+        }
+        let (head, tail) = self.buf.split_at(N);
+        self.buf = tail;
+        Ok(head.try_into().expect("length checked"))
+    }
+
+    pub(crate) fn read_unsigned_varint(&mut self) -> Result<u32> {
+        read_unsigned_varint(&mut self.buf).map_err(|e| match e {
+            VarintError::Io(_) => RecordError::Truncated,
+            e => RecordError::Malformed(e.to_string()),
+        })
+    }
+
+    pub(crate) fn read_i8(&mut self) -> Result<i8> {
+        Ok(i8::from_be_bytes(self.take()?))
+    }
+
+    pub(crate) fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.take()?))
+    }
+
+    pub(crate) fn read_i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take()?))
+    }
+
+    pub(crate) fn read_i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.take()?))
+    }
+
+    pub(crate) fn read_f64(&mut self) -> Result<f64> {
+        Ok(f64::from_be_bytes(self.take()?))
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take()?))
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool> {
+        match self.take::<1>()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [b] => Err(RecordError::Malformed(format!("invalid boolean {b}"))),
+        }
+    }
+
+    pub(crate) fn read_uuid(&mut self) -> Result<Uuid> {
+        Ok(Uuid::from_bytes(self.take()?))
+    }
+
+    /// Reads an array written with [write_array_len] followed by its elements.
+    pub(crate) fn read_array<T>(
+        &mut self,
+        mut read_element: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let len = self
+            .read_unsigned_varint()?
+            .checked_sub(1)
+            .ok_or_else(|| RecordError::Malformed("unexpected null array".to_string()))?;
+        // Every element takes at least one byte, which bounds the allocation for corrupt input.
+        if len as usize > self.buf.len() {
+            return Err(RecordError::Truncated);
+        }
+        (0..len).map(|_| read_element(self)).collect()
+    }
+
+    fn read_nullable_bytes(&mut self) -> Result<Option<&'a [u8]>> {
+        let len = self.read_unsigned_varint()? as usize;
+        if len == 0 {
+            return Ok(None);
+        }
+        let len = len - 1;
+        if self.buf.len() < len {
+            return Err(RecordError::Truncated);
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(Some(head))
+    }
+
+    pub(crate) fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        self.read_nullable_bytes()?
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| RecordError::Malformed("unexpected null bytes".to_string()))
+    }
+
+    pub(crate) fn read_nullable_string(&mut self) -> Result<Option<String>> {
+        self.read_nullable_bytes()?
+            .map(|bytes| {
+                String::from_utf8(bytes.to_vec()).map_err(|e| RecordError::Malformed(e.to_string()))
+            })
+            .transpose()
+    }
+
+    pub(crate) fn read_string(&mut self) -> Result<String> {
+        self.read_nullable_string()?
+            .ok_or_else(|| RecordError::Malformed("unexpected null string".to_string()))
+    }
+}
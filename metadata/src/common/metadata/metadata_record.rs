@@ -0,0 +1,289 @@
+use crate::common::metadata::codec::{Reader, write_unsigned_varint_to};
+use crate::common::metadata::records::{
+    AbortTransactionRecord, AccessControlEntryRecord, BeginTransactionRecord,
+    BrokerRegistrationChangeRecord, ClientQuotaRecord, CompletedOperationRecord, ConfigRecord,
+    EndTransactionRecord, FeatureLevelRecord, PartitionRecord, RegisterBrokerRecord,
+    RemoveAccessControlEntryRecord, TopicRecord, UnregisterBrokerRecord, UserScramCredentialRecord,
+};
+use thiserror::Error;
+
+/// The version of the frame wrapping every serialized record.
+const FRAME_VERSION: u32 = 1;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RecordError {
+    #[error("Buffer underflow while reading a metadata record")]
+    Truncated,
+
+    #[error("Malformed metadata record: {0}")]
+    Malformed(String),
+
+    #[error("Unsupported metadata record frame version {0}")]
+    UnsupportedFrameVersion(u32),
+
+    #[error("Unknown metadata record type {0}")]
+    UnknownRecordType(u32),
+
+    #[error("Unsupported version {version} of metadata record type {api_key}")]
+    UnsupportedVersion { api_key: u32, version: u32 },
+}
+
+pub type Result<T> = std::result::Result<T, RecordError>;
+
+/// A record of the metadata log, tagged with its type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataRecord {
+    RegisterBroker(RegisterBrokerRecord),
+    UnregisterBroker(UnregisterBrokerRecord),
+    Topic(TopicRecord),
+    Partition(PartitionRecord),
+    Config(ConfigRecord),
+    UserScramCredential(UserScramCredentialRecord),
+    FeatureLevel(FeatureLevelRecord),
+    ClientQuota(ClientQuotaRecord),
+    BrokerRegistrationChange(BrokerRegistrationChangeRecord),
+    AccessControlEntry(AccessControlEntryRecord),
+    RemoveAccessControlEntry(RemoveAccessControlEntryRecord),
+    CompletedOperation(CompletedOperationRecord),
+    BeginTransaction(BeginTransactionRecord),
+    EndTransaction(EndTransactionRecord),
+    AbortTransaction(AbortTransactionRecord),
+}
+
+impl MetadataRecord {
+    /// The permanent id of the record type -- this can't change, since it is persisted in
+    /// the metadata log.
+    pub fn api_key(&self) -> u32 {
+        match self {
+            MetadataRecord::RegisterBroker(_) => 0,
+            MetadataRecord::UnregisterBroker(_) => 1,
+            MetadataRecord::Topic(_) => 2,
+            MetadataRecord::Partition(_) => 3,
+            MetadataRecord::Config(_) => 4,
+            MetadataRecord::UserScramCredential(_) => 11,
+            MetadataRecord::FeatureLevel(_) => 12,
+            MetadataRecord::ClientQuota(_) => 14,
+            MetadataRecord::BrokerRegistrationChange(_) => 17,
+            MetadataRecord::AccessControlEntry(_) => 23,
+            MetadataRecord::RemoveAccessControlEntry(_) => 24,
+            // Not a Kafka record type: the types from 1000 on are specific to rafka.
+            MetadataRecord::CompletedOperation(_) => 1000,
+            // Kafka has the transaction markers as 23 to 25, which rafka already used for the
+            // ACL records.
+            MetadataRecord::BeginTransaction(_) => 1001,
+            MetadataRecord::EndTransaction(_) => 1002,
+            MetadataRecord::AbortTransaction(_) => 1003,
+        }
+    }
+
+    /// The schema version the record is written with.
+    pub fn version(&self) -> u32 {
+        0
+    }
+
+    /// Appends the framed record to `buf`: the frame version, the record type and version,
+    /// followed by the record fields.
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        write_unsigned_varint_to(buf, FRAME_VERSION);
+        write_unsigned_varint_to(buf, self.api_key());
+        write_unsigned_varint_to(buf, self.version());
+        match self {
+            MetadataRecord::RegisterBroker(record) => record.write(buf),
+            MetadataRecord::UnregisterBroker(record) => record.write(buf),
+            MetadataRecord::Topic(record) => record.write(buf),
+            MetadataRecord::Partition(record) => record.write(buf),
+            MetadataRecord::Config(record) => record.write(buf),
+            MetadataRecord::UserScramCredential(record) => record.write(buf),
+            MetadataRecord::FeatureLevel(record) => record.write(buf),
+            MetadataRecord::ClientQuota(record) => record.write(buf),
+            MetadataRecord::BrokerRegistrationChange(record) => record.write(buf),
+            MetadataRecord::AccessControlEntry(record) => record.write(buf),
+            MetadataRecord::RemoveAccessControlEntry(record) => record.write(buf),
+            MetadataRecord::CompletedOperation(record) => record.write(buf),
+            MetadataRecord::BeginTransaction(record) => record.write(buf),
+            MetadataRecord::EndTransaction(record) => record.write(buf),
+            MetadataRecord::AbortTransaction(record) => record.write(buf),
+        }
+    }
+
+    /// Reads one framed record from the front of `buf` and advances it past the record.
+    pub fn read(buf: &mut &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(buf);
+        let frame_version = reader.read_unsigned_varint()?;
+        if frame_version != FRAME_VERSION {
+            return Err(RecordError::UnsupportedFrameVersion(frame_version));
+        }
+        let api_key = reader.read_unsigned_varint()?;
+        let version = reader.read_unsigned_varint()?;
+        if version != 0 {
+            return Err(RecordError::UnsupportedVersion { api_key, version });
+        }
+        let record = match api_key {
+            0 => MetadataRecord::RegisterBroker(RegisterBrokerRecord::read(&mut reader)?),
+            1 => MetadataRecord::UnregisterBroker(UnregisterBrokerRecord::read(&mut reader)?),
+            2 => MetadataRecord::Topic(TopicRecord::read(&mut reader)?),
+            3 => MetadataRecord::Partition(PartitionRecord::read(&mut reader)?),
+            4 => MetadataRecord::Config(ConfigRecord::read(&mut reader)?),
+            11 => {
+                MetadataRecord::UserScramCredential(UserScramCredentialRecord::read(&mut reader)?)
+            }
+            12 => MetadataRecord::FeatureLevel(FeatureLevelRecord::read(&mut reader)?),
+            14 => MetadataRecord::ClientQuota(ClientQuotaRecord::read(&mut reader)?),
+            17 => MetadataRecord::BrokerRegistrationChange(BrokerRegistrationChangeRecord::read(
+                &mut reader,
+            )?),
+            23 => MetadataRecord::AccessControlEntry(AccessControlEntryRecord::read(&mut reader)?),
+            24 => MetadataRecord::RemoveAccessControlEntry(RemoveAccessControlEntryRecord::read(
+                &mut reader,
+            )?),
+            1000 => {
+                MetadataRecord::CompletedOperation(CompletedOperationRecord::read(&mut reader)?)
+            }
+            1001 => MetadataRecord::BeginTransaction(BeginTransactionRecord::read(&mut reader)?),
+            1002 => MetadataRecord::EndTransaction(EndTransactionRecord::read(&mut reader)?),
+            1003 => MetadataRecord::AbortTransaction(AbortTransactionRecord::read(&mut reader)?),
+            _ => return Err(RecordError::UnknownRecordType(api_key)),
+        };
+        *buf = reader.remaining();
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::{BrokerEndpoint, BrokerFeature, EntityData};
+    use rafka_clients::common::uuid::Uuid;
+
+    fn records() -> Vec<MetadataRecord> {
+        vec![
+            MetadataRecord::FeatureLevel(FeatureLevelRecord {
+                name: "metadata.version".to_string(),
+                feature_level: 19,
+            }),
+            MetadataRecord::Config(ConfigRecord {
+                resource_type: 4,
+                resource_name: "".to_string(),
+                name: "log.retention.ms".to_string(),
+                value: None,
+            }),
+            MetadataRecord::UserScramCredential(UserScramCredentialRecord {
+                name: "alice".to_string(),
+                mechanism: 1,
+                salt: vec![1, 2, 3],
+                stored_key: vec![4; 32],
+                server_key: vec![5; 32],
+                iterations: 8192,
+            }),
+            MetadataRecord::RegisterBroker(RegisterBrokerRecord {
+                broker_id: 1,
+                incarnation_id: Uuid::new(42, 7),
+                broker_epoch: 100,
+                end_points: vec![BrokerEndpoint {
+                    name: "PLAINTEXT".to_string(),
+                    host: "localhost".to_string(),
+                    port: 9092,
+                    security_protocol: 0,
+                }],
+                features: vec![BrokerFeature {
+                    name: "metadata.version".to_string(),
+                    min_supported_version: 7,
+                    max_supported_version: 21,
+                }],
+                rack: None,
+                fenced: true,
+                in_controlled_shutdown: false,
+                log_dirs: vec![Uuid::new(1, 2), Uuid::new(3, 4)],
+            }),
+            MetadataRecord::BrokerRegistrationChange(BrokerRegistrationChangeRecord {
+                broker_id: 1,
+                broker_epoch: 100,
+                fenced: -1,
+                in_controlled_shutdown: 0,
+            }),
+            MetadataRecord::UnregisterBroker(UnregisterBrokerRecord {
+                broker_id: 1,
+                broker_epoch: 100,
+            }),
+            MetadataRecord::Topic(TopicRecord {
+                name: "foo".to_string(),
+                topic_id: Uuid::new(5, 6),
+            }),
+            MetadataRecord::Partition(PartitionRecord {
+                partition_id: 0,
+                topic_id: Uuid::new(5, 6),
+                replicas: vec![1, 2, 3],
+                isr: vec![1, 2],
+                leader: 1,
+                leader_epoch: 0,
+                partition_epoch: 0,
+            }),
+            MetadataRecord::ClientQuota(ClientQuotaRecord {
+                entity: vec![EntityData {
+                    entity_type: "ip".to_string(),
+                    entity_name: Some("192.168.0.1".to_string()),
+                }],
+                key: "connection_creation_rate".to_string(),
+                value: 10.0,
+                remove: false,
+            }),
+            MetadataRecord::AccessControlEntry(AccessControlEntryRecord {
+                id: Uuid::new(7, 8),
+                resource_type: 2,
+                resource_name: "foo".to_string(),
+                pattern_type: 3,
+                principal: "User:alice".to_string(),
+                host: "*".to_string(),
+                operation: 3,
+                permission_type: 3,
+            }),
+            MetadataRecord::RemoveAccessControlEntry(RemoveAccessControlEntryRecord {
+                id: Uuid::new(7, 8),
+            }),
+            MetadataRecord::CompletedOperation(CompletedOperationRecord {
+                operation_key: "create-foo-1".to_string(),
+                operation: "CreateTopics".to_string(),
+                request_hash: -42,
+                resource_name: "foo".to_string(),
+            }),
+            MetadataRecord::BeginTransaction(BeginTransactionRecord {
+                name: "CreateTopics".to_string(),
+            }),
+            MetadataRecord::EndTransaction(EndTransactionRecord {}),
+            MetadataRecord::AbortTransaction(AbortTransactionRecord {
+                reason: "The controller failed over".to_string(),
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut buf = Vec::new();
+        for record in records() {
+            record.write(&mut buf);
+        }
+        let mut remaining = buf.as_slice();
+        let mut read = Vec::new();
+        while !remaining.is_empty() {
+            read.push(MetadataRecord::read(&mut remaining).unwrap());
+        }
+        assert_eq!(records(), read);
+    }
+
+    #[test]
+    fn test_truncated_and_unknown_records() {
+        let mut buf = Vec::new();
+        records()[0].write(&mut buf);
+        let mut truncated = &buf[..buf.len() - 1];
+        assert_eq!(
+            Err(RecordError::Truncated),
+            MetadataRecord::read(&mut truncated)
+        );
+
+        let mut unknown: &[u8] = &[1, 99, 0];
+        assert_eq!(
+            Err(RecordError::UnknownRecordType(99)),
+            MetadataRecord::read(&mut unknown)
+        );
+    }
+}
@@ -0,0 +1,4 @@
+mod codec;
+pub mod metadata_record;
+pub mod records;
+pub mod transaction_buffer;
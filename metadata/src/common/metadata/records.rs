@@ -0,0 +1,506 @@
+//! The records stored in the metadata log.
+
+use crate::common::metadata::codec::{
+    Reader, write_array_len, write_bool, write_bytes, write_f64, write_i8, write_i16, write_i32,
+    write_i64, write_nullable_string, write_string, write_u16, write_uuid,
+};
+use crate::common::metadata::metadata_record::Result;
+use rafka_clients::common::endpoint::Endpoint;
+use rafka_clients::common::uuid::Uuid;
+
+/// Registers a broker, replacing any previous registration with the same id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterBrokerRecord {
+    pub broker_id: i32,
+    /// A random id generated each time the broker process starts.
+    pub incarnation_id: Uuid,
+    /// The broker epoch, which is the offset of this record in the metadata log.
+    pub broker_epoch: i64,
+    pub end_points: Vec<BrokerEndpoint>,
+    pub features: Vec<BrokerFeature>,
+    pub rack: Option<String>,
+    pub fenced: bool,
+    pub in_controlled_shutdown: bool,
+    /// The ids of the broker's online log directories.
+    pub log_dirs: Vec<Uuid>,
+}
+
+/// A listener of a registered broker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerEndpoint {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub security_protocol: i16,
+}
+
+impl From<&Endpoint> for BrokerEndpoint {
+    fn from(endpoint: &Endpoint) -> Self {
+        Self {
+            name: endpoint.listener_name.clone(),
+            host: endpoint.host.clone(),
+            port: endpoint.port,
+            security_protocol: endpoint.security_protocol.id(),
+        }
+    }
+}
+
+/// A feature range supported by a registered broker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerFeature {
+    pub name: String,
+    pub min_supported_version: i16,
+    pub max_supported_version: i16,
+}
+
+impl RegisterBrokerRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_i32(buf, self.broker_id);
+        write_uuid(buf, self.incarnation_id);
+        write_i64(buf, self.broker_epoch);
+        write_array_len(buf, self.end_points.len());
+        for end_point in &self.end_points {
+            write_string(buf, &end_point.name);
+            write_string(buf, &end_point.host);
+            write_u16(buf, end_point.port);
+            write_i16(buf, end_point.security_protocol);
+        }
+        write_array_len(buf, self.features.len());
+        for feature in &self.features {
+            write_string(buf, &feature.name);
+            write_i16(buf, feature.min_supported_version);
+            write_i16(buf, feature.max_supported_version);
+        }
+        write_nullable_string(buf, self.rack.as_deref());
+        write_bool(buf, self.fenced);
+        write_bool(buf, self.in_controlled_shutdown);
+        write_array_len(buf, self.log_dirs.len());
+        for log_dir in &self.log_dirs {
+            write_uuid(buf, *log_dir);
+        }
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            broker_id: reader.read_i32()?,
+            incarnation_id: reader.read_uuid()?,
+            broker_epoch: reader.read_i64()?,
+            end_points: reader.read_array(|reader| {
+                Ok(BrokerEndpoint {
+                    name: reader.read_string()?,
+                    host: reader.read_string()?,
+                    port: reader.read_u16()?,
+                    security_protocol: reader.read_i16()?,
+                })
+            })?,
+            features: reader.read_array(|reader| {
+                Ok(BrokerFeature {
+                    name: reader.read_string()?,
+                    min_supported_version: reader.read_i16()?,
+                    max_supported_version: reader.read_i16()?,
+                })
+            })?,
+            rack: reader.read_nullable_string()?,
+            fenced: reader.read_bool()?,
+            in_controlled_shutdown: reader.read_bool()?,
+            log_dirs: reader.read_array(Reader::read_uuid)?,
+        })
+    }
+}
+
+/// Changes the fencing or controlled shutdown state of a registered broker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerRegistrationChangeRecord {
+    pub broker_id: i32,
+    pub broker_epoch: i64,
+    /// -1 to unfence the broker, 1 to fence it, 0 to leave it unchanged.
+    pub fenced: i8,
+    /// 1 if the broker began a controlled shutdown, 0 if unchanged.
+    pub in_controlled_shutdown: i8,
+}
+
+impl BrokerRegistrationChangeRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_i32(buf, self.broker_id);
+        write_i64(buf, self.broker_epoch);
+        write_i8(buf, self.fenced);
+        write_i8(buf, self.in_controlled_shutdown);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            broker_id: reader.read_i32()?,
+            broker_epoch: reader.read_i64()?,
+            fenced: reader.read_i8()?,
+            in_controlled_shutdown: reader.read_i8()?,
+        })
+    }
+}
+
+/// Removes a decommissioned broker from the cluster metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnregisterBrokerRecord {
+    pub broker_id: i32,
+    /// The epoch of the registration being removed, so that a later registration of the same
+    /// broker id is left alone.
+    pub broker_epoch: i64,
+}
+
+impl UnregisterBrokerRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_i32(buf, self.broker_id);
+        write_i64(buf, self.broker_epoch);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            broker_id: reader.read_i32()?,
+            broker_epoch: reader.read_i64()?,
+        })
+    }
+}
+
+/// Creates a topic. Its partitions follow as [PartitionRecord]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicRecord {
+    pub name: String,
+    pub topic_id: Uuid,
+}
+
+impl TopicRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_string(buf, &self.name);
+        write_uuid(buf, self.topic_id);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            name: reader.read_string()?,
+            topic_id: reader.read_uuid()?,
+        })
+    }
+}
+
+/// Creates or replaces a partition of a topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionRecord {
+    pub partition_id: i32,
+    pub topic_id: Uuid,
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+    /// The leader, or -1 if the partition has none.
+    pub leader: i32,
+    pub leader_epoch: i32,
+    pub partition_epoch: i32,
+}
+
+impl PartitionRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_i32(buf, self.partition_id);
+        write_uuid(buf, self.topic_id);
+        write_i32_array(buf, &self.replicas);
+        write_i32_array(buf, &self.isr);
+        write_i32(buf, self.leader);
+        write_i32(buf, self.leader_epoch);
+        write_i32(buf, self.partition_epoch);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            partition_id: reader.read_i32()?,
+            topic_id: reader.read_uuid()?,
+            replicas: reader.read_array(Reader::read_i32)?,
+            isr: reader.read_array(Reader::read_i32)?,
+            leader: reader.read_i32()?,
+            leader_epoch: reader.read_i32()?,
+            partition_epoch: reader.read_i32()?,
+        })
+    }
+}
+
+fn write_i32_array(buf: &mut Vec<u8>, values: &[i32]) {
+    write_array_len(buf, values.len());
+    for value in values {
+        write_i32(buf, *value);
+    }
+}
+
+/// Sets the finalized level of a feature, such as `metadata.version`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureLevelRecord {
+    pub name: String,
+    /// The feature level, or 0 if the feature is disabled.
+    pub feature_level: i16,
+}
+
+impl FeatureLevelRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_string(buf, &self.name);
+        write_i16(buf, self.feature_level);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            name: reader.read_string()?,
+            feature_level: reader.read_i16()?,
+        })
+    }
+}
+
+/// Sets or, with a `None` value, removes a dynamic configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigRecord {
+    /// The type of the resource, e.g. 2 for topics and 4 for brokers.
+    pub resource_type: i8,
+    /// The name of the resource, or the empty string for the cluster-wide default.
+    pub resource_name: String,
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl ConfigRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_i8(buf, self.resource_type);
+        write_string(buf, &self.resource_name);
+        write_string(buf, &self.name);
+        write_nullable_string(buf, self.value.as_deref());
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            resource_type: reader.read_i8()?,
+            resource_name: reader.read_string()?,
+            name: reader.read_string()?,
+            value: reader.read_nullable_string()?,
+        })
+    }
+}
+
+/// Stores the SCRAM credential of a user for one mechanism.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserScramCredentialRecord {
+    pub name: String,
+    /// The SCRAM mechanism: 1 for SCRAM-SHA-256, 2 for SCRAM-SHA-512.
+    pub mechanism: i8,
+    pub salt: Vec<u8>,
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+    pub iterations: i32,
+}
+
+impl UserScramCredentialRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_string(buf, &self.name);
+        write_i8(buf, self.mechanism);
+        write_bytes(buf, &self.salt);
+        write_bytes(buf, &self.stored_key);
+        write_bytes(buf, &self.server_key);
+        write_i32(buf, self.iterations);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            name: reader.read_string()?,
+            mechanism: reader.read_i8()?,
+            salt: reader.read_bytes()?,
+            stored_key: reader.read_bytes()?,
+            server_key: reader.read_bytes()?,
+            iterations: reader.read_i32()?,
+        })
+    }
+}
+
+/// Sets or, with `remove`, removes a client quota of an entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientQuotaRecord {
+    pub entity: Vec<EntityData>,
+    /// The quota key, e.g. `producer_byte_rate` or `connection_creation_rate`.
+    pub key: String,
+    pub value: f64,
+    pub remove: bool,
+}
+
+// Quota values are never NaN, which makes the comparison of records an equivalence.
+impl Eq for ClientQuotaRecord {}
+
+/// A component of the entity of a client quota.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityData {
+    /// The entity type, `user`, `client-id` or `ip`.
+    pub entity_type: String,
+    /// The entity name, or `None` for the default entity of the type.
+    pub entity_name: Option<String>,
+}
+
+impl ClientQuotaRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_array_len(buf, self.entity.len());
+        for entity in &self.entity {
+            write_string(buf, &entity.entity_type);
+            write_nullable_string(buf, entity.entity_name.as_deref());
+        }
+        write_string(buf, &self.key);
+        write_f64(buf, self.value);
+        write_bool(buf, self.remove);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            entity: reader.read_array(|reader| {
+                Ok(EntityData {
+                    entity_type: reader.read_string()?,
+                    entity_name: reader.read_nullable_string()?,
+                })
+            })?,
+            key: reader.read_string()?,
+            value: reader.read_f64()?,
+            remove: reader.read_bool()?,
+        })
+    }
+}
+
+/// Adds an ACL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessControlEntryRecord {
+    /// The id of the ACL, which the record removing it refers to.
+    pub id: Uuid,
+    pub resource_type: i8,
+    pub resource_name: String,
+    pub pattern_type: i8,
+    pub principal: String,
+    pub host: String,
+    pub operation: i8,
+    pub permission_type: i8,
+}
+
+impl AccessControlEntryRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_uuid(buf, self.id);
+        write_i8(buf, self.resource_type);
+        write_string(buf, &self.resource_name);
+        write_i8(buf, self.pattern_type);
+        write_string(buf, &self.principal);
+        write_string(buf, &self.host);
+        write_i8(buf, self.operation);
+        write_i8(buf, self.permission_type);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            id: reader.read_uuid()?,
+            resource_type: reader.read_i8()?,
+            resource_name: reader.read_string()?,
+            pattern_type: reader.read_i8()?,
+            principal: reader.read_string()?,
+            host: reader.read_string()?,
+            operation: reader.read_i8()?,
+            permission_type: reader.read_i8()?,
+        })
+    }
+}
+
+/// Removes an ACL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoveAccessControlEntryRecord {
+    pub id: Uuid,
+}
+
+impl RemoveAccessControlEntryRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_uuid(buf, self.id);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            id: reader.read_uuid()?,
+        })
+    }
+}
+
+/// Records that the admin operation identified by `operation_key` was applied. It is written
+/// in the same batch as the records applying the operation, so that a retry of the operation
+/// after a controller failover is recognized instead of being applied twice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedOperationRecord {
+    /// The key the client picked for the operation, which it keeps across retries.
+    pub operation_key: String,
+    /// The name of the operation, e.g. `CreateTopics`.
+    pub operation: String,
+    /// A fingerprint of the request, to tell a retry from another request reusing the key.
+    pub request_hash: i64,
+    /// The resource the operation applied to, e.g. the name of the created topic.
+    pub resource_name: String,
+}
+
+impl CompletedOperationRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_string(buf, &self.operation_key);
+        write_string(buf, &self.operation);
+        write_i64(buf, self.request_hash);
+        write_string(buf, &self.resource_name);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            operation_key: reader.read_string()?,
+            operation: reader.read_string()?,
+            request_hash: reader.read_i64()?,
+            resource_name: reader.read_string()?,
+        })
+    }
+}
+
+/// Opens a metadata transaction: the records following it, up to the matching
+/// [EndTransactionRecord], are applied together or not at all.
+///
+/// The controller wraps the records of an operation in a transaction when they don't fit in a
+/// single batch, e.g. those of a topic with many partitions, so that no reader of the log
+/// ever applies part of the operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeginTransactionRecord {
+    /// The name of the operation, for debugging.
+    pub name: String,
+}
+
+impl BeginTransactionRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_string(buf, &self.name);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            name: reader.read_string()?,
+        })
+    }
+}
+
+/// Commits the open metadata transaction, applying its records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndTransactionRecord {}
+
+impl EndTransactionRecord {
+    pub(crate) fn write(&self, _buf: &mut Vec<u8>) {}
+
+    pub(crate) fn read(_reader: &mut Reader) -> Result<Self> {
+        Ok(Self {})
+    }
+}
+
+/// Discards the records of the open metadata transaction. Written by a new active controller
+/// which finds the transaction of its predecessor left open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbortTransactionRecord {
+    pub reason: String,
+}
+
+impl AbortTransactionRecord {
+    pub(crate) fn write(&self, buf: &mut Vec<u8>) {
+        write_string(buf, &self.reason);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(Self {
+            reason: reader.read_string()?,
+        })
+    }
+}
@@ -0,0 +1,156 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use tracing::{debug, warn};
+
+/// Holds back the records of an open metadata transaction until it ends.
+///
+/// The readers of the metadata log pass each committed record through the buffer instead of
+/// replaying it directly: records outside of a transaction are replayed right away, those
+/// following a [BeginTransaction](MetadataRecord::BeginTransaction) are kept until the
+/// matching [EndTransaction](MetadataRecord::EndTransaction) replays them all, or an
+/// [AbortTransaction](MetadataRecord::AbortTransaction) discards them. A transaction may span
+/// several batches and a snapshot boundary, so the buffer outlives both.
+#[derive(Debug, Default)]
+pub struct TransactionBuffer {
+    /// The name and the records of the open transaction, if any.
+    open: Option<(String, Vec<MetadataRecord>)>,
+}
+
+impl TransactionBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a transaction was begun and has not ended yet.
+    pub fn in_transaction(&self) -> bool {
+        self.open.is_some()
+    }
+
+    /// The name of the open transaction, if any.
+    pub fn transaction_name(&self) -> Option<&str> {
+        self.open.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    /// Passes `record` through the buffer, calling `replay` for each record which is applied
+    /// as a result: `record` itself outside of a transaction, or every record of the
+    /// transaction it ends.
+    pub fn apply(&mut self, record: &MetadataRecord, mut replay: impl FnMut(&MetadataRecord)) {
+        match record {
+            MetadataRecord::BeginTransaction(begin) => {
+                if let Some((name, records)) = self.open.take() {
+                    warn!(
+                        "Discarding the {} records of transaction {name}, which was not ended \
+                        before transaction {} began",
+                        records.len(),
+                        begin.name
+                    );
+                }
+                self.open = Some((begin.name.clone(), Vec::new()));
+            }
+            MetadataRecord::EndTransaction(_) => match self.open.take() {
+                Some((name, records)) => {
+                    debug!(
+                        "Applying the {} records of transaction {name}",
+                        records.len()
+                    );
+                    records.iter().for_each(&mut replay);
+                }
+                None => warn!("Ignoring the end of a transaction which was not begun"),
+            },
+            MetadataRecord::AbortTransaction(abort) => match self.open.take() {
+                Some((name, records)) => warn!(
+                    "Discarding the {} records of aborted transaction {name}: {}",
+                    records.len(),
+                    abort.reason
+                ),
+                None => warn!("Ignoring the abort of a transaction which was not begun"),
+            },
+            record => match &mut self.open {
+                Some((_, records)) => records.push(record.clone()),
+                None => replay(record),
+            },
+        }
+    }
+
+    /// Discards the open transaction, e.g. before loading a snapshot.
+    pub fn clear(&mut self) {
+        self.open = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::{
+        AbortTransactionRecord, BeginTransactionRecord, EndTransactionRecord, TopicRecord,
+    };
+    use rafka_clients::common::uuid::Uuid;
+
+    fn topic(name: &str) -> MetadataRecord {
+        MetadataRecord::Topic(TopicRecord {
+            name: name.to_string(),
+            topic_id: Uuid::new(1, name.len() as i64),
+        })
+    }
+
+    fn begin(name: &str) -> MetadataRecord {
+        MetadataRecord::BeginTransaction(BeginTransactionRecord {
+            name: name.to_string(),
+        })
+    }
+
+    fn apply_all(
+        buffer: &mut TransactionBuffer,
+        records: &[MetadataRecord],
+    ) -> Vec<MetadataRecord> {
+        let mut replayed = Vec::new();
+        for record in records {
+            buffer.apply(record, |r| replayed.push(r.clone()));
+        }
+        replayed
+    }
+
+    #[test]
+    fn test_transaction_is_applied_on_end() {
+        let mut buffer = TransactionBuffer::new();
+        assert_eq!(vec![topic("a")], apply_all(&mut buffer, &[topic("a")]));
+
+        let replayed = apply_all(
+            &mut buffer,
+            &[begin("CreateTopics"), topic("b"), topic("c")],
+        );
+        assert!(replayed.is_empty());
+        assert_eq!(Some("CreateTopics"), buffer.transaction_name());
+
+        let replayed = apply_all(
+            &mut buffer,
+            &[
+                MetadataRecord::EndTransaction(EndTransactionRecord {}),
+                topic("d"),
+            ],
+        );
+        assert_eq!(vec![topic("b"), topic("c"), topic("d")], replayed);
+        assert!(!buffer.in_transaction());
+    }
+
+    #[test]
+    fn test_aborted_transaction_is_discarded() {
+        let mut buffer = TransactionBuffer::new();
+        let abort = MetadataRecord::AbortTransaction(AbortTransactionRecord {
+            reason: "failover".to_string(),
+        });
+        let replayed = apply_all(&mut buffer, &[begin("a"), topic("a"), abort, topic("b")]);
+        assert_eq!(vec![topic("b")], replayed);
+
+        // A transaction begun while another one is open replaces it.
+        let end = MetadataRecord::EndTransaction(EndTransactionRecord {});
+        let replayed = apply_all(
+            &mut buffer,
+            &[begin("a"), topic("c"), begin("b"), topic("d"), end],
+        );
+        assert_eq!(vec![topic("d")], replayed);
+
+        apply_all(&mut buffer, &[begin("c"), topic("e")]);
+        buffer.clear();
+        assert!(!buffer.in_transaction());
+    }
+}
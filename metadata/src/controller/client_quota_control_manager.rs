@@ -0,0 +1,296 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::records::{ClientQuotaRecord, EntityData};
+use crate::controller::controller_result::ControllerResult;
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use rafka_clients::common::quota::client_quota_alteration::ClientQuotaAlteration;
+use rafka_clients::common::quota::client_quota_entity::{self, ClientQuotaEntity};
+use rafka_server_common::quota_config::{
+    CONSUMER_BYTE_RATE_OVERRIDE_CONFIG, IP_CONNECTION_RATE_OVERRIDE_CONFIG,
+    PRODUCER_BYTE_RATE_OVERRIDE_CONFIG, is_user_client_id_quota_key,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use tracing::info;
+
+/// Tracks the client quotas of the cluster, configured with AlterClientQuotas requests.
+#[derive(Debug, Default)]
+pub struct ClientQuotaControlManager {
+    client_quotas: HashMap<ClientQuotaEntity, BTreeMap<String, f64>>,
+}
+
+impl ClientQuotaControlManager {
+    pub fn replay(&mut self, record: &ClientQuotaRecord) {
+        let entity = ClientQuotaEntity::new(
+            record
+                .entity
+                .iter()
+                .map(|e| (e.entity_type.clone(), e.entity_name.clone()))
+                .collect(),
+        );
+        if record.remove {
+            info!(
+                "Replayed a ClientQuotaRecord removing {} of {entity}",
+                record.key
+            );
+            if let Some(quotas) = self.client_quotas.get_mut(&entity) {
+                quotas.remove(&record.key);
+                if quotas.is_empty() {
+                    self.client_quotas.remove(&entity);
+                }
+            }
+        } else {
+            info!(
+                "Replayed a ClientQuotaRecord setting {} of {entity} to {}",
+                record.key, record.value
+            );
+            self.client_quotas
+                .entry(entity)
+                .or_default()
+                .insert(record.key.clone(), record.value);
+        }
+    }
+
+    /// The quotas configured for `entity`.
+    pub fn quotas(&self, entity: &ClientQuotaEntity) -> Option<&BTreeMap<String, f64>> {
+        self.client_quotas.get(entity)
+    }
+
+    /// Returns the records applying `alterations`, with the outcome for each entity. The
+    /// alterations of an entity are applied all together or, if one of them is invalid, not
+    /// at all.
+    pub fn alter_client_quotas(
+        &self,
+        alterations: &[ClientQuotaAlteration],
+    ) -> ControllerResult<BTreeMap<ClientQuotaEntity, ApiError>> {
+        let mut records = vec![];
+        let mut results = BTreeMap::new();
+        for alteration in alterations {
+            let result = match self.alter_client_quota_entity(alteration) {
+                Ok(entity_records) => {
+                    records.extend(entity_records);
+                    ApiError::NONE
+                }
+                Err(e) => e,
+            };
+            results.insert(alteration.entity.clone(), result);
+        }
+        ControllerResult::new(records, results)
+    }
+
+    fn alter_client_quota_entity(
+        &self,
+        alteration: &ClientQuotaAlteration,
+    ) -> Result<Vec<MetadataRecord>, ApiError> {
+        let entity = &alteration.entity;
+        validate_entity(entity)?;
+        let is_ip = entity.get(client_quota_entity::IP).is_some();
+        let current = self.client_quotas.get(entity);
+        let entity_data: Vec<EntityData> = entity
+            .entries()
+            .iter()
+            .map(|(entity_type, entity_name)| EntityData {
+                entity_type: entity_type.clone(),
+                entity_name: entity_name.clone(),
+            })
+            .collect();
+
+        let mut records = vec![];
+        for op in &alteration.ops {
+            validate_key(&op.key, is_ip)?;
+            let current_value = current.and_then(|quotas| quotas.get(&op.key));
+            match op.value {
+                None if current_value.is_some() => {
+                    records.push(MetadataRecord::ClientQuota(ClientQuotaRecord {
+                        entity: entity_data.clone(),
+                        key: op.key.clone(),
+                        value: 0.0,
+                        remove: true,
+                    }));
+                }
+                None => {}
+                Some(value) => {
+                    validate_value(&op.key, value)?;
+                    if current_value != Some(&value) {
+                        records.push(MetadataRecord::ClientQuota(ClientQuotaRecord {
+                            entity: entity_data.clone(),
+                            key: op.key.clone(),
+                            value,
+                            remove: false,
+                        }));
+                    }
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+fn invalid_request(message: String) -> ApiError {
+    ApiError::new(Errors::InvalidRequest, message)
+}
+
+fn validate_entity(entity: &ClientQuotaEntity) -> Result<(), ApiError> {
+    if entity.entries().is_empty() {
+        return Err(invalid_request(
+            "Invalid empty client quota entity".to_string(),
+        ));
+    }
+    for entity_type in entity.entries().keys() {
+        if !client_quota_entity::is_valid_entity_type(entity_type) {
+            return Err(invalid_request(format!(
+                "Unhandled client quota entity type: {entity_type}"
+            )));
+        }
+    }
+    match entity.get(client_quota_entity::IP) {
+        Some(_) if entity.entries().len() > 1 => Err(invalid_request(
+            "Invalid quota entity combination, IP entity should not be combined with User or \
+            ClientId"
+                .to_string(),
+        )),
+        Some(Some(ip)) if ip.parse::<IpAddr>().is_err() => {
+            Err(invalid_request(format!("{ip} is not a valid IP address")))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_key(key: &str, is_ip: bool) -> Result<(), ApiError> {
+    let valid = if is_ip {
+        key == IP_CONNECTION_RATE_OVERRIDE_CONFIG
+    } else {
+        is_user_client_id_quota_key(key)
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid_request(format!("Invalid configuration key {key}")))
+    }
+}
+
+fn validate_value(key: &str, value: f64) -> Result<(), ApiError> {
+    if !value.is_finite() || value < 0.0 {
+        return Err(invalid_request(format!(
+            "Invalid value {value} for configuration key {key}"
+        )));
+    }
+    let integral = match key {
+        IP_CONNECTION_RATE_OVERRIDE_CONFIG => value <= i32::MAX as f64,
+        PRODUCER_BYTE_RATE_OVERRIDE_CONFIG | CONSUMER_BYTE_RATE_OVERRIDE_CONFIG => {
+            value <= i64::MAX as f64
+        }
+        _ => return Ok(()),
+    };
+    if !integral || value.fract() != 0.0 {
+        return Err(invalid_request(format!(
+            "Configuration {key} must be an integer, but was {value}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::quota::client_quota_alteration::Op;
+    use rafka_clients::common::quota::client_quota_entity::{CLIENT_ID, IP, USER};
+
+    fn apply(
+        manager: &mut ClientQuotaControlManager,
+        alterations: &[ClientQuotaAlteration],
+    ) -> BTreeMap<ClientQuotaEntity, ApiError> {
+        let (records, results) = manager.alter_client_quotas(alterations).into_parts();
+        for record in &records {
+            match record {
+                MetadataRecord::ClientQuota(record) => manager.replay(record),
+                record => panic!("unexpected record {record:?}"),
+            }
+        }
+        results
+    }
+
+    #[test]
+    fn test_ip_quotas() {
+        let mut manager = ClientQuotaControlManager::default();
+        let ip = ClientQuotaEntity::of(IP, Some("192.168.0.1"));
+        let default_ip = ClientQuotaEntity::of(IP, None);
+        let results = apply(
+            &mut manager,
+            &[
+                ClientQuotaAlteration::new(
+                    ip.clone(),
+                    vec![Op::new(IP_CONNECTION_RATE_OVERRIDE_CONFIG, Some(10.0))],
+                ),
+                ClientQuotaAlteration::new(
+                    default_ip.clone(),
+                    vec![Op::new(IP_CONNECTION_RATE_OVERRIDE_CONFIG, Some(100.0))],
+                ),
+            ],
+        );
+        assert!(results.values().all(ApiError::is_success));
+        assert_eq!(
+            Some(&10.0),
+            manager
+                .quotas(&ip)
+                .unwrap()
+                .get(IP_CONNECTION_RATE_OVERRIDE_CONFIG)
+        );
+        assert!(manager.quotas(&default_ip).is_some());
+
+        // Setting the same value again writes nothing.
+        let result = manager.alter_client_quotas(&[ClientQuotaAlteration::new(
+            ip.clone(),
+            vec![Op::new(IP_CONNECTION_RATE_OVERRIDE_CONFIG, Some(10.0))],
+        )]);
+        assert!(result.records().is_empty());
+
+        apply(
+            &mut manager,
+            &[ClientQuotaAlteration::new(
+                ip.clone(),
+                vec![Op::new(IP_CONNECTION_RATE_OVERRIDE_CONFIG, None)],
+            )],
+        );
+        assert!(manager.quotas(&ip).is_none());
+    }
+
+    #[test]
+    fn test_invalid_alterations() {
+        let manager = ClientQuotaControlManager::default();
+        let rate = |value| vec![Op::new(IP_CONNECTION_RATE_OVERRIDE_CONFIG, Some(value))];
+        let invalid = [
+            ClientQuotaAlteration::new(ClientQuotaEntity::of(IP, Some("not-an-ip")), rate(1.0)),
+            ClientQuotaAlteration::new(ClientQuotaEntity::of(IP, Some("10.0.0.1")), rate(1.5)),
+            ClientQuotaAlteration::new(ClientQuotaEntity::of(IP, Some("10.0.0.2")), rate(-1.0)),
+            ClientQuotaAlteration::new(
+                ClientQuotaEntity::of(IP, Some("10.0.0.3")),
+                vec![Op::new(PRODUCER_BYTE_RATE_OVERRIDE_CONFIG, Some(1.0))],
+            ),
+            ClientQuotaAlteration::new(
+                ClientQuotaEntity::new(BTreeMap::from([
+                    (IP.to_string(), None),
+                    (USER.to_string(), Some("alice".to_string())),
+                ])),
+                rate(1.0),
+            ),
+            ClientQuotaAlteration::new(ClientQuotaEntity::of(CLIENT_ID, Some("app")), rate(1.0)),
+            ClientQuotaAlteration::new(ClientQuotaEntity::of("group", Some("g")), rate(1.0)),
+            ClientQuotaAlteration::new(ClientQuotaEntity::new(BTreeMap::new()), rate(1.0)),
+        ];
+        let result = manager.alter_client_quotas(&invalid);
+        assert!(result.records().is_empty());
+        assert_eq!(invalid.len(), result.response().len());
+        assert!(
+            result
+                .response()
+                .values()
+                .all(|e| e.error() == Errors::InvalidRequest)
+        );
+
+        let valid = manager.alter_client_quotas(&[ClientQuotaAlteration::new(
+            ClientQuotaEntity::of(USER, Some("alice")),
+            vec![Op::new(PRODUCER_BYTE_RATE_OVERRIDE_CONFIG, Some(1024.0))],
+        )]);
+        assert_eq!(1, valid.records().len());
+    }
+}
@@ -0,0 +1,915 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::records::{
+    BrokerEndpoint, BrokerFeature, BrokerRegistrationChangeRecord, RegisterBrokerRecord,
+    UnregisterBrokerRecord,
+};
+use crate::controller::controller_result::ControllerResult;
+use crate::controller::replica_placement::UsableBroker;
+use crate::metadata::broker_registration::{BrokerRegistration, VersionRange};
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use rafka_clients::common::uuid::Uuid;
+use rafka_server_common::fault_injection::{self, FaultPoint};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::info;
+
+/// The default of `broker.session.timeout.ms`.
+pub const DEFAULT_SESSION_TIMEOUT_MS: i64 = 9000;
+
+/// A request of a broker lifecycle manager to register its broker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerRegistrationRequest {
+    pub broker_id: i32,
+    pub cluster_id: String,
+    /// A random id generated each time the broker process starts.
+    pub incarnation_id: Uuid,
+    pub listeners: Vec<BrokerEndpoint>,
+    pub features: BTreeMap<String, VersionRange>,
+    pub rack: Option<String>,
+    pub log_dirs: Vec<Uuid>,
+    /// The epoch the broker had before a clean shutdown, or -1 if it has none.
+    pub previous_broker_epoch: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrokerRegistrationReply {
+    pub broker_epoch: i64,
+}
+
+/// The state of a log directory of a broker, as reported in its heartbeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogDirStatus {
+    pub dir: Uuid,
+    /// Whether the directory failed, e.g. after an I/O error, taking its replicas offline.
+    pub offline: bool,
+    /// The bytes left for new replicas on the volume of the directory.
+    pub usable_bytes: i64,
+}
+
+impl LogDirStatus {
+    /// Whether new replicas can be placed in the directory.
+    pub fn is_usable(&self) -> bool {
+        !self.offline && self.usable_bytes > 0
+    }
+}
+
+/// A heartbeat of a broker lifecycle manager, which keeps the session of its broker alive
+/// and reports whether the broker is ready to be unfenced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerHeartbeatRequest {
+    pub broker_id: i32,
+    pub broker_epoch: i64,
+    /// The offset of the last metadata record the broker applied.
+    pub current_metadata_offset: i64,
+    /// Whether the broker wants to stay fenced, because it has not recovered its logs or
+    /// caught up with the metadata log yet.
+    pub want_fence: bool,
+    /// The state of the log directories of the broker, empty if it reports none.
+    pub log_dirs: Vec<LogDirStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrokerHeartbeatReply {
+    /// Whether the broker applied the metadata log up to its own registration.
+    pub is_caught_up: bool,
+    /// Whether the broker is fenced once the records written for the heartbeat are committed.
+    pub is_fenced: bool,
+}
+
+/// Tracks the registered brokers.
+///
+/// Registrations are replayed from the metadata log on every controller. Broker sessions are
+/// only tracked by the active controller, which gives every registered broker a fresh session
+/// when it is activated.
+pub struct ClusterControlManager {
+    cluster_id: String,
+    session_timeout_ns: i64,
+    brokers: BTreeMap<i32, BrokerRegistration>,
+    /// When each broker last registered or sent a heartbeat to the active controller.
+    last_contact_ns: HashMap<i32, i64>,
+    /// The state of the log directories each broker last reported to the active controller.
+    log_dirs: HashMap<i32, Vec<LogDirStatus>>,
+}
+
+impl ClusterControlManager {
+    pub fn new(cluster_id: impl Into<String>, session_timeout_ms: i64) -> Self {
+        Self {
+            cluster_id: cluster_id.into(),
+            session_timeout_ns: session_timeout_ms * 1_000_000,
+            brokers: BTreeMap::new(),
+            last_contact_ns: HashMap::new(),
+            log_dirs: HashMap::new(),
+        }
+    }
+
+    pub fn cluster_id(&self) -> &str {
+        &self.cluster_id
+    }
+
+    pub fn registration(&self, broker_id: i32) -> Option<&BrokerRegistration> {
+        self.brokers.get(&broker_id)
+    }
+
+    pub fn brokers(&self) -> impl Iterator<Item = &BrokerRegistration> {
+        self.brokers.values()
+    }
+
+    /// The brokers new replicas can be placed on: fenced brokers, brokers in controlled
+    /// shutdown and brokers whose log directories are all offline or full are skipped.
+    pub fn usable_brokers(&self) -> Vec<UsableBroker> {
+        self.brokers
+            .values()
+            .filter(|b| b.is_available() && self.has_usable_log_dir(b.id()))
+            .map(|b| UsableBroker {
+                id: b.id(),
+                rack: b.rack().map(str::to_string),
+            })
+            .collect()
+    }
+
+    /// Starts tracking broker sessions, as of `now_ns`, when this controller becomes active.
+    pub fn activate(&mut self, now_ns: i64) {
+        self.last_contact_ns = self.brokers.keys().map(|id| (*id, now_ns)).collect();
+    }
+
+    pub fn deactivate(&mut self) {
+        self.last_contact_ns.clear();
+        self.log_dirs.clear();
+    }
+
+    /// Whether the broker has a log directory new replicas can be placed in. A broker which
+    /// reported none since this controller became active is assumed to have one.
+    pub fn has_usable_log_dir(&self, broker_id: i32) -> bool {
+        self.log_dirs
+            .get(&broker_id)
+            .is_none_or(|dirs| dirs.iter().any(LogDirStatus::is_usable))
+    }
+
+    /// Records the state of the log directories reported by the broker.
+    fn update_log_dirs(&mut self, broker_id: i32, log_dirs: &[LogDirStatus]) {
+        if log_dirs.is_empty() {
+            return;
+        }
+        let offline: Vec<Uuid> = log_dirs
+            .iter()
+            .filter(|d| d.offline)
+            .map(|d| d.dir)
+            .collect();
+        let previous = self.log_dirs.insert(broker_id, log_dirs.to_vec());
+        let previously_offline = previous.iter().flatten().filter(|d| d.offline).count();
+        if offline.len() != previously_offline {
+            info!("Broker {broker_id} reported offline log directories {offline:?}");
+        }
+    }
+
+    /// Records contact from a registered broker, which extends its session.
+    pub fn touch(&mut self, broker_id: i32, now_ns: i64) {
+        if fault_injection::should_drop(FaultPoint::ControllerHeartbeat, broker_id.to_string()) {
+            return;
+        }
+        if self.brokers.contains_key(&broker_id) {
+            self.last_contact_ns.insert(broker_id, now_ns);
+        }
+    }
+
+    fn has_valid_session(&self, broker_id: i32, now_ns: i64) -> bool {
+        self.last_contact_ns
+            .get(&broker_id)
+            .is_some_and(|last| now_ns - last < self.session_timeout_ns)
+    }
+
+    /// Validates a registration request and returns the record registering the broker with
+    /// `broker_epoch`. The broker starts out fenced.
+    ///
+    /// A broker id may only be taken over by another incarnation once the session of the
+    /// existing one has expired, unless the request proves a restart of the same broker after
+    /// a clean shutdown by presenting its previous epoch.
+    pub fn register_broker(
+        &mut self,
+        request: &BrokerRegistrationRequest,
+        broker_epoch: i64,
+        finalized_features: &BTreeMap<String, i16>,
+        now_ns: i64,
+    ) -> Result<ControllerResult<BrokerRegistrationReply>, ApiError> {
+        let broker_id = request.broker_id;
+        if request.cluster_id != self.cluster_id {
+            return Err(ApiError::new(
+                Errors::InconsistentClusterId,
+                format!(
+                    "Expected cluster ID {}, but got cluster ID {}",
+                    self.cluster_id, request.cluster_id
+                ),
+            ));
+        }
+        if let Some(existing) = self.brokers.get(&broker_id)
+            && existing.incarnation_id() != request.incarnation_id
+            && self.has_valid_session(broker_id, now_ns)
+        {
+            if request.previous_broker_epoch == existing.epoch() {
+                info!(
+                    "Broker {broker_id} re-registered after a clean shutdown of its previous \
+                    incarnation {} with epoch {}",
+                    existing.incarnation_id(),
+                    existing.epoch()
+                );
+            } else if existing.in_controlled_shutdown() {
+                info!(
+                    "Broker {broker_id} re-registered while its previous incarnation {} was in \
+                    controlled shutdown",
+                    existing.incarnation_id()
+                );
+            } else {
+                return Err(ApiError::new(
+                    Errors::DuplicateBrokerRegistration,
+                    format!(
+                        "Another broker is registered with that broker id: incarnation {} \
+                        still has a valid session",
+                        existing.incarnation_id()
+                    ),
+                ));
+            }
+        }
+        for (feature, level) in finalized_features {
+            // A broker which does not list a feature only supports its level 0.
+            let supported = request
+                .features
+                .get(feature)
+                .map_or(*level == 0, |range| range.contains(*level));
+            if !supported {
+                return Err(ApiError::new(
+                    Errors::UnsupportedVersion,
+                    format!(
+                        "Unable to register because the broker does not support finalized \
+                        version {level} of {feature}. The broker supports {:?}",
+                        request.features.get(feature)
+                    ),
+                ));
+            }
+        }
+        let log_dirs = self.validate_log_dirs(request)?;
+        if let Some(existing) = self.brokers.get(&broker_id)
+            && existing.directories() != log_dirs.as_slice()
+        {
+            info!(
+                "Broker {broker_id} changed its log directories from {:?} to {:?}",
+                existing.directories(),
+                log_dirs
+            );
+        }
+
+        let record = RegisterBrokerRecord {
+            broker_id,
+            incarnation_id: request.incarnation_id,
+            broker_epoch,
+            end_points: request.listeners.clone(),
+            features: request
+                .features
+                .iter()
+                .map(|(name, range)| BrokerFeature {
+                    name: name.clone(),
+                    min_supported_version: range.min,
+                    max_supported_version: range.max,
+                })
+                .collect(),
+            rack: request.rack.clone(),
+            fenced: true,
+            in_controlled_shutdown: false,
+            log_dirs,
+        };
+        self.last_contact_ns.insert(broker_id, now_ns);
+        // The new incarnation reports the state of its directories in its heartbeats.
+        self.log_dirs.remove(&broker_id);
+        Ok(ControllerResult::new(
+            vec![MetadataRecord::RegisterBroker(record)],
+            BrokerRegistrationReply { broker_epoch },
+        ))
+    }
+
+    /// The registration of the broker, which must be registered with `broker_epoch`.
+    fn checked_registration(
+        &self,
+        broker_id: i32,
+        broker_epoch: i64,
+    ) -> Result<&BrokerRegistration, ApiError> {
+        let registration = self.brokers.get(&broker_id).ok_or_else(|| {
+            ApiError::new(
+                Errors::BrokerIdNotRegistered,
+                format!("Broker {broker_id} is not registered"),
+            )
+        })?;
+        if registration.epoch() != broker_epoch {
+            return Err(ApiError::new(
+                Errors::StaleBrokerEpoch,
+                format!(
+                    "Expected broker epoch {}, but got broker epoch {broker_epoch}",
+                    registration.epoch()
+                ),
+            ));
+        }
+        Ok(registration)
+    }
+
+    /// Handles a heartbeat, which extends the session of the broker.
+    ///
+    /// A fenced broker is unfenced once it no longer wants to be fenced and applied the
+    /// metadata log up to its registration, whose offset is its broker epoch. Until then the
+    /// controller places no replicas on it, so leadership only moves to brokers which
+    /// recovered their logs and know the current metadata. A broker asking to be fenced again
+    /// is fenced.
+    pub fn process_broker_heartbeat(
+        &mut self,
+        request: &BrokerHeartbeatRequest,
+        now_ns: i64,
+    ) -> Result<ControllerResult<BrokerHeartbeatReply>, ApiError> {
+        let registration = self.checked_registration(request.broker_id, request.broker_epoch)?;
+        let fenced = registration.fenced();
+        let is_caught_up = request.current_metadata_offset >= registration.epoch();
+        self.touch(request.broker_id, now_ns);
+        self.update_log_dirs(request.broker_id, &request.log_dirs);
+
+        let ready = is_caught_up && !request.want_fence;
+        let change = match (fenced, ready) {
+            (true, true) => -1,
+            (false, false) if request.want_fence => 1,
+            _ => 0,
+        };
+        let records = if change == 0 {
+            vec![]
+        } else {
+            info!(
+                "{} broker {} at metadata offset {}",
+                if change < 0 { "Unfencing" } else { "Fencing" },
+                request.broker_id,
+                request.current_metadata_offset
+            );
+            vec![MetadataRecord::BrokerRegistrationChange(
+                BrokerRegistrationChangeRecord {
+                    broker_id: request.broker_id,
+                    broker_epoch: request.broker_epoch,
+                    fenced: change,
+                    in_controlled_shutdown: 0,
+                },
+            )]
+        };
+        Ok(ControllerResult::new(
+            records,
+            BrokerHeartbeatReply {
+                is_caught_up,
+                is_fenced: (fenced && change == 0) || change > 0,
+            },
+        ))
+    }
+
+    /// Returns the record unfencing the broker, which must be registered with `broker_epoch`.
+    pub fn unfence_broker(
+        &self,
+        broker_id: i32,
+        broker_epoch: i64,
+    ) -> Result<ControllerResult<()>, ApiError> {
+        let registration = self.checked_registration(broker_id, broker_epoch)?;
+        let records = if registration.fenced() {
+            vec![MetadataRecord::BrokerRegistrationChange(
+                BrokerRegistrationChangeRecord {
+                    broker_id,
+                    broker_epoch,
+                    fenced: -1,
+                    in_controlled_shutdown: 0,
+                },
+            )]
+        } else {
+            vec![]
+        };
+        Ok(ControllerResult::new(records, ()))
+    }
+
+    /// Checks that `broker_id` is registered with `broker_epoch`, failing with
+    /// `BrokerIdNotRegistered` or `StaleBrokerEpoch` for a request sent by an incarnation of
+    /// the broker which registered again since, e.g. a zombie which lost its session.
+    pub fn validate_broker_epoch(&self, broker_id: i32, broker_epoch: i64) -> Result<(), ApiError> {
+        self.checked_registration(broker_id, broker_epoch)
+            .map(|_| ())
+    }
+
+    /// Returns the record moving the broker, which must be registered with `broker_epoch`,
+    /// into controlled shutdown. Its partitions then no longer get it as leader or new
+    /// replica, and it may re-register before its session expires.
+    pub fn begin_controlled_shutdown(
+        &self,
+        broker_id: i32,
+        broker_epoch: i64,
+    ) -> Result<ControllerResult<()>, ApiError> {
+        let registration = self.checked_registration(broker_id, broker_epoch)?;
+        let records = if registration.in_controlled_shutdown() {
+            vec![]
+        } else {
+            info!("Broker {broker_id} at epoch {broker_epoch} begins a controlled shutdown");
+            vec![MetadataRecord::BrokerRegistrationChange(
+                BrokerRegistrationChangeRecord {
+                    broker_id,
+                    broker_epoch,
+                    fenced: 0,
+                    in_controlled_shutdown: 1,
+                },
+            )]
+        };
+        Ok(ControllerResult::new(records, ()))
+    }
+
+    /// Returns the record removing the registration of `broker_id`, e.g. once the broker was
+    /// decommissioned. Fails with `BrokerIdNotRegistered` if there is none.
+    pub fn unregister_broker(&self, broker_id: i32) -> Result<ControllerResult<()>, ApiError> {
+        let registration = self.brokers.get(&broker_id).ok_or_else(|| {
+            ApiError::new(
+                Errors::BrokerIdNotRegistered,
+                format!("Broker {broker_id} is not registered"),
+            )
+        })?;
+        info!(
+            "Unregistering broker {broker_id} at epoch {}",
+            registration.epoch()
+        );
+        Ok(ControllerResult::new(
+            vec![MetadataRecord::UnregisterBroker(UnregisterBrokerRecord {
+                broker_id,
+                broker_epoch: registration.epoch(),
+            })],
+            (),
+        ))
+    }
+
+    /// Returns the sorted directory ids of the request, which must be unique, unreserved and
+    /// not registered by any other broker.
+    fn validate_log_dirs(
+        &self,
+        request: &BrokerRegistrationRequest,
+    ) -> Result<Vec<Uuid>, ApiError> {
+        let invalid = |message: String| Err(ApiError::new(Errors::InvalidRegistration, message));
+        let mut seen = HashSet::new();
+        for dir in &request.log_dirs {
+            if dir.is_reserved() {
+                return invalid(format!("Reserved directory ID {dir} in the request"));
+            }
+            if !seen.insert(*dir) {
+                return invalid(format!("Duplicate directory ID {dir} in the request"));
+            }
+            if let Some(owner) = self
+                .brokers
+                .values()
+                .find(|b| b.id() != request.broker_id && b.has_online_dir(*dir))
+            {
+                return invalid(format!(
+                    "Directory {dir} is already registered by broker {}",
+                    owner.id()
+                ));
+            }
+        }
+        let mut log_dirs = request.log_dirs.clone();
+        log_dirs.sort();
+        Ok(log_dirs)
+    }
+
+    pub fn replay_registration_change(&mut self, record: &BrokerRegistrationChangeRecord) {
+        match self.brokers.get_mut(&record.broker_id) {
+            Some(registration) if registration.epoch() == record.broker_epoch => {
+                registration.apply_change(record);
+                info!(
+                    "Replayed BrokerRegistrationChangeRecord for broker {}: fenced {}, in \
+                    controlled shutdown {}",
+                    record.broker_id,
+                    registration.fenced(),
+                    registration.in_controlled_shutdown()
+                );
+            }
+            _ => info!(
+                "Ignoring BrokerRegistrationChangeRecord for broker {} at stale epoch {}",
+                record.broker_id, record.broker_epoch
+            ),
+        }
+    }
+
+    pub fn replay_unregister(&mut self, record: &UnregisterBrokerRecord) {
+        match self.brokers.get(&record.broker_id) {
+            Some(registration) if registration.epoch() == record.broker_epoch => {
+                self.brokers.remove(&record.broker_id);
+                self.last_contact_ns.remove(&record.broker_id);
+                self.log_dirs.remove(&record.broker_id);
+                info!(
+                    "Replayed UnregisterBrokerRecord removing broker {} at epoch {}",
+                    record.broker_id, record.broker_epoch
+                );
+            }
+            _ => info!(
+                "Ignoring UnregisterBrokerRecord for broker {} at stale epoch {}",
+                record.broker_id, record.broker_epoch
+            ),
+        }
+    }
+
+    pub fn replay(&mut self, record: &RegisterBrokerRecord) {
+        let registration = BrokerRegistration::from_record(record);
+        match self.brokers.insert(record.broker_id, registration) {
+            Some(previous) if previous.incarnation_id() != record.incarnation_id => info!(
+                "Replayed RegisterBrokerRecord replacing incarnation {} of broker {} with {} \
+                at epoch {}",
+                previous.incarnation_id(),
+                record.broker_id,
+                record.incarnation_id,
+                record.broker_epoch
+            ),
+            _ => info!(
+                "Replayed RegisterBrokerRecord registering broker {} at epoch {}",
+                record.broker_id, record.broker_epoch
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_server_common::metadata_version::MetadataVersion;
+
+    const MS: i64 = 1_000_000;
+
+    fn features() -> BTreeMap<String, i16> {
+        BTreeMap::from([(
+            MetadataVersion::FEATURE_NAME.to_string(),
+            MetadataVersion::Ibp3_7Iv4.feature_level(),
+        )])
+    }
+
+    fn request(broker_id: i32, incarnation: i64, log_dirs: &[i64]) -> BrokerRegistrationRequest {
+        BrokerRegistrationRequest {
+            broker_id,
+            cluster_id: "cluster".to_string(),
+            incarnation_id: Uuid::new(1000, incarnation),
+            listeners: vec![BrokerEndpoint {
+                name: "PLAINTEXT".to_string(),
+                host: "localhost".to_string(),
+                port: 9092,
+                security_protocol: 0,
+            }],
+            features: BTreeMap::from([(
+                MetadataVersion::FEATURE_NAME.to_string(),
+                VersionRange::new(7, 21),
+            )]),
+            rack: None,
+            log_dirs: log_dirs.iter().map(|d| Uuid::new(2000, *d)).collect(),
+            previous_broker_epoch: -1,
+        }
+    }
+
+    /// Registers the broker and replays the resulting record, as the active controller does.
+    fn register(
+        manager: &mut ClusterControlManager,
+        request: &BrokerRegistrationRequest,
+        broker_epoch: i64,
+        now_ns: i64,
+    ) -> Result<BrokerRegistrationReply, ApiError> {
+        let result = manager.register_broker(request, broker_epoch, &features(), now_ns)?;
+        for record in result.records() {
+            if let MetadataRecord::RegisterBroker(record) = record {
+                manager.replay(record);
+            }
+        }
+        Ok(*result.response())
+    }
+
+    fn error(result: Result<BrokerRegistrationReply, ApiError>) -> Errors {
+        result.unwrap_err().error()
+    }
+
+    #[test]
+    fn test_register_fenced_broker() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        assert_eq!(
+            Ok(BrokerRegistrationReply { broker_epoch: 10 }),
+            register(&mut manager, &request(1, 1, &[200, 100]), 10, 0)
+        );
+        let registration = manager.registration(1).unwrap();
+        assert!(registration.fenced());
+        assert_eq!(10, registration.epoch());
+        assert_eq!(
+            &[Uuid::new(2000, 100), Uuid::new(2000, 200)],
+            registration.directories()
+        );
+    }
+
+    #[test]
+    fn test_reject_inconsistent_cluster_id_and_unsupported_features() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        let mut wrong_cluster = request(1, 1, &[100]);
+        wrong_cluster.cluster_id = "other".to_string();
+        assert_eq!(
+            Errors::InconsistentClusterId,
+            error(register(&mut manager, &wrong_cluster, 10, 0))
+        );
+
+        let mut too_old = request(1, 1, &[100]);
+        too_old.features.insert(
+            MetadataVersion::FEATURE_NAME.to_string(),
+            VersionRange::new(7, 14),
+        );
+        assert_eq!(
+            Errors::UnsupportedVersion,
+            error(register(&mut manager, &too_old, 10, 0))
+        );
+        too_old.features.clear();
+        assert_eq!(
+            Errors::UnsupportedVersion,
+            error(register(&mut manager, &too_old, 10, 0))
+        );
+        assert!(manager.registration(1).is_none());
+    }
+
+    #[test]
+    fn test_duplicate_broker_id_is_rejected_while_the_session_is_valid() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        register(&mut manager, &request(1, 1, &[100]), 10, 0).unwrap();
+
+        // Another process configured with the same node.id.
+        let duplicate = request(1, 2, &[101]);
+        assert_eq!(
+            Errors::DuplicateBrokerRegistration,
+            error(register(&mut manager, &duplicate, 20, 5000 * MS))
+        );
+        // The same incarnation may always re-register, e.g. after a lost response.
+        assert_eq!(
+            Ok(BrokerRegistrationReply { broker_epoch: 20 }),
+            register(&mut manager, &request(1, 1, &[100]), 20, 5000 * MS)
+        );
+        assert_eq!(
+            Errors::DuplicateBrokerRegistration,
+            error(register(&mut manager, &duplicate, 30, 14000 * MS - 1))
+        );
+        // Once the session of the existing incarnation expired, the id can be taken over.
+        assert_eq!(
+            Ok(BrokerRegistrationReply { broker_epoch: 30 }),
+            register(&mut manager, &duplicate, 30, 14000 * MS)
+        );
+        assert_eq!(
+            Uuid::new(1000, 2),
+            manager.registration(1).unwrap().incarnation_id()
+        );
+    }
+
+    #[test]
+    fn test_controlled_re_registration_after_restart() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        register(&mut manager, &request(1, 1, &[100]), 10, 0).unwrap();
+
+        // A restarted broker proves it is the same broker with the epoch it had before its
+        // clean shutdown, and does not have to wait for its old session to expire.
+        let mut restarted = request(1, 2, &[100]);
+        restarted.previous_broker_epoch = 9;
+        assert_eq!(
+            Errors::DuplicateBrokerRegistration,
+            error(register(&mut manager, &restarted, 20, MS))
+        );
+        restarted.previous_broker_epoch = 10;
+        assert_eq!(
+            Ok(BrokerRegistrationReply { broker_epoch: 20 }),
+            register(&mut manager, &restarted, 20, MS)
+        );
+    }
+
+    #[test]
+    fn test_directory_changes() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        register(&mut manager, &request(1, 1, &[100, 101]), 10, 0).unwrap();
+
+        // A replaced disk comes back with a new directory id.
+        register(&mut manager, &request(1, 1, &[100, 102]), 20, MS).unwrap();
+        assert!(
+            !manager
+                .registration(1)
+                .unwrap()
+                .has_online_dir(Uuid::new(2000, 101))
+        );
+        assert!(
+            manager
+                .registration(1)
+                .unwrap()
+                .has_online_dir(Uuid::new(2000, 102))
+        );
+
+        for log_dirs in [vec![102], vec![103, 103]] {
+            assert_eq!(
+                Errors::InvalidRegistration,
+                error(register(&mut manager, &request(2, 1, &log_dirs), 30, MS))
+            );
+        }
+        let mut reserved = request(2, 1, &[]);
+        reserved.log_dirs = vec![Uuid::ZERO];
+        assert_eq!(
+            Errors::InvalidRegistration,
+            error(register(&mut manager, &reserved, 30, MS))
+        );
+        // The directory released by broker 1 can be registered by another broker.
+        register(&mut manager, &request(2, 1, &[101]), 30, MS).unwrap();
+    }
+
+    #[test]
+    fn test_usable_brokers() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        for broker_id in 1..=3 {
+            register(
+                &mut manager,
+                &request(broker_id, 1, &[broker_id as i64]),
+                10 * broker_id as i64,
+                0,
+            )
+            .unwrap();
+        }
+        assert!(manager.usable_brokers().is_empty());
+
+        for broker_id in [1, 2] {
+            let result = manager
+                .unfence_broker(broker_id, 10 * broker_id as i64)
+                .unwrap();
+            for record in result.records() {
+                if let MetadataRecord::BrokerRegistrationChange(record) = record {
+                    manager.replay_registration_change(record);
+                }
+            }
+        }
+        assert_eq!(
+            Errors::StaleBrokerEpoch,
+            manager.unfence_broker(3, 10).unwrap_err().error()
+        );
+        manager.replay_registration_change(&BrokerRegistrationChangeRecord {
+            broker_id: 2,
+            broker_epoch: 20,
+            fenced: 0,
+            in_controlled_shutdown: 1,
+        });
+        let usable: Vec<_> = manager.usable_brokers().iter().map(|b| b.id).collect();
+        assert_eq!(vec![1], usable);
+    }
+
+    /// Processes the heartbeat and replays the resulting records.
+    fn heartbeat(
+        manager: &mut ClusterControlManager,
+        request: &BrokerHeartbeatRequest,
+        now_ns: i64,
+    ) -> Result<BrokerHeartbeatReply, ApiError> {
+        let result = manager.process_broker_heartbeat(request, now_ns)?;
+        for record in result.records() {
+            if let MetadataRecord::BrokerRegistrationChange(record) = record {
+                manager.replay_registration_change(record);
+            }
+        }
+        Ok(*result.response())
+    }
+
+    #[test]
+    fn test_heartbeats_unfence_ready_brokers() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        register(&mut manager, &request(1, 1, &[100]), 10, 0).unwrap();
+        let mut request = BrokerHeartbeatRequest {
+            broker_id: 1,
+            broker_epoch: 10,
+            current_metadata_offset: 12,
+            want_fence: true,
+            log_dirs: vec![],
+        };
+        // Still recovering its logs.
+        assert_eq!(
+            Ok(BrokerHeartbeatReply {
+                is_caught_up: true,
+                is_fenced: true
+            }),
+            heartbeat(&mut manager, &request, MS)
+        );
+
+        // Not caught up with its own registration yet.
+        request.want_fence = false;
+        request.current_metadata_offset = 9;
+        assert_eq!(
+            Ok(BrokerHeartbeatReply {
+                is_caught_up: false,
+                is_fenced: true
+            }),
+            heartbeat(&mut manager, &request, 2 * MS)
+        );
+        assert!(manager.usable_brokers().is_empty());
+
+        request.current_metadata_offset = 10;
+        assert_eq!(
+            Ok(BrokerHeartbeatReply {
+                is_caught_up: true,
+                is_fenced: false
+            }),
+            heartbeat(&mut manager, &request, 3 * MS)
+        );
+        assert_eq!(1, manager.usable_brokers().len());
+        assert!(
+            manager
+                .process_broker_heartbeat(&request, 4 * MS)
+                .unwrap()
+                .records()
+                .is_empty()
+        );
+
+        request.want_fence = true;
+        assert!(heartbeat(&mut manager, &request, 5 * MS).unwrap().is_fenced);
+        assert!(manager.registration(1).unwrap().fenced());
+
+        request.broker_epoch = 9;
+        assert_eq!(
+            Errors::StaleBrokerEpoch,
+            heartbeat(&mut manager, &request, 6 * MS)
+                .unwrap_err()
+                .error()
+        );
+        request.broker_id = 2;
+        assert_eq!(
+            Errors::BrokerIdNotRegistered,
+            heartbeat(&mut manager, &request, 6 * MS)
+                .unwrap_err()
+                .error()
+        );
+    }
+
+    #[test]
+    fn test_brokers_without_usable_log_dirs_get_no_replicas() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        register(&mut manager, &request(1, 1, &[100, 101]), 10, 0).unwrap();
+        let restarted = BrokerRegistrationRequest {
+            previous_broker_epoch: 10,
+            ..request(1, 2, &[100, 101])
+        };
+        let status = |dir, offline, usable_bytes| LogDirStatus {
+            dir: Uuid::new(2000, dir),
+            offline,
+            usable_bytes,
+        };
+        let mut request = BrokerHeartbeatRequest {
+            broker_id: 1,
+            broker_epoch: 10,
+            current_metadata_offset: 10,
+            want_fence: false,
+            log_dirs: vec![status(100, true, 1000), status(101, false, 1000)],
+        };
+        heartbeat(&mut manager, &request, MS).unwrap();
+        // One directory is left for new replicas.
+        assert_eq!(1, manager.usable_brokers().len());
+
+        request.log_dirs = vec![status(100, true, 1000), status(101, false, 0)];
+        heartbeat(&mut manager, &request, 2 * MS).unwrap();
+        assert!(!manager.has_usable_log_dir(1));
+        assert!(manager.usable_brokers().is_empty());
+
+        // A heartbeat reporting no directories leaves their state unchanged.
+        request.log_dirs = vec![];
+        heartbeat(&mut manager, &request, 3 * MS).unwrap();
+        assert!(manager.usable_brokers().is_empty());
+
+        // Once restarted with a replaced disk, the broker reports its directories again.
+        register(&mut manager, &restarted, 20, 4 * MS).unwrap();
+        assert!(manager.has_usable_log_dir(1));
+    }
+
+    #[test]
+    fn test_sessions_restart_when_activated() {
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        register(&mut manager, &request(1, 1, &[100]), 10, 0).unwrap();
+        manager.deactivate();
+        // A standby has no sessions, so it would not know the old incarnation is alive.
+        manager.activate(20000 * MS);
+        assert_eq!(
+            Errors::DuplicateBrokerRegistration,
+            error(register(
+                &mut manager,
+                &request(1, 2, &[100]),
+                20,
+                25000 * MS
+            ))
+        );
+        manager.touch(1, 30000 * MS);
+        assert_eq!(
+            Errors::DuplicateBrokerRegistration,
+            error(register(
+                &mut manager,
+                &request(1, 2, &[100]),
+                20,
+                38000 * MS
+            ))
+        );
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[test]
+    fn test_dropped_heartbeats_expire_the_session() {
+        use rafka_server_common::fault_injection::{Fault, inject};
+
+        let mut manager = ClusterControlManager::new("cluster", DEFAULT_SESSION_TIMEOUT_MS);
+        register(&mut manager, &request(41, 1, &[100]), 10, 0).unwrap();
+        let guard = inject(FaultPoint::ControllerHeartbeat, "41", Fault::Drop, None);
+        manager.touch(41, 8000 * MS);
+        // Without the heartbeat the session expired, so another incarnation may take over.
+        assert!(!manager.has_valid_session(41, 10000 * MS));
+        drop(guard);
+        manager.touch(41, 12000 * MS);
+        assert!(manager.has_valid_session(41, 20000 * MS));
+    }
+}
@@ -0,0 +1,417 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::records::ConfigRecord;
+use crate::controller::controller_result::ControllerResult;
+use rafka_clients::clients::admin::{self, AlterConfigOpType};
+use rafka_clients::common::config::topic_config::{
+    CLEANUP_POLICY_COMPACT, CLEANUP_POLICY_CONFIG, COMPRESSION_TYPE_CONFIG, SEGMENT_BYTES_CONFIG,
+    list_config_default,
+};
+use rafka_clients::common::internals::topic::{
+    GROUP_METADATA_TOPIC_NAME, TRANSACTION_STATE_TOPIC_NAME,
+};
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, warn};
+
+/// The resource type of the configs of a topic.
+pub const TOPIC_RESOURCE_TYPE: i8 = 2;
+
+/// The configs the coordinators rely on, which internal topics are created with and which
+/// cannot be changed: the coordinators rebuild their state by reading their topic from the
+/// start, which only works if the topic is compacted, their batches are written with the
+/// compression they chose, and segments are small enough to be cleaned regularly.
+pub fn enforced_topic_configs(topic: &str) -> &'static [(&'static str, &'static str)] {
+    match topic {
+        GROUP_METADATA_TOPIC_NAME => &[
+            (CLEANUP_POLICY_CONFIG, CLEANUP_POLICY_COMPACT),
+            (COMPRESSION_TYPE_CONFIG, "producer"),
+            (SEGMENT_BYTES_CONFIG, "104857600"),
+        ],
+        TRANSACTION_STATE_TOPIC_NAME => &[
+            (CLEANUP_POLICY_CONFIG, CLEANUP_POLICY_COMPACT),
+            (COMPRESSION_TYPE_CONFIG, "uncompressed"),
+            (SEGMENT_BYTES_CONFIG, "104857600"),
+        ],
+        _ => &[],
+    }
+}
+
+fn enforced_value(topic: &str, name: &str) -> Option<&'static str> {
+    enforced_topic_configs(topic)
+        .iter()
+        .find(|(config, _)| *config == name)
+        .map(|(_, value)| *value)
+}
+
+fn enforced_config_error(topic: &str, name: &str, value: &str) -> ApiError {
+    ApiError::new(
+        Errors::InvalidConfig,
+        format!(
+            "{name} of the internal topic {topic} must be {value}: the coordinator writing the \
+            topic relies on it, so it cannot be overridden"
+        ),
+    )
+}
+
+/// An operation of an IncrementalAlterConfigs request on a config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlterConfigOp {
+    Set {
+        name: String,
+        value: String,
+    },
+    Delete {
+        name: String,
+    },
+    /// Adds the comma-separated `values` missing from a list config.
+    Append {
+        name: String,
+        values: String,
+    },
+    /// Removes the comma-separated `values` from a list config.
+    Subtract {
+        name: String,
+        values: String,
+    },
+}
+
+impl AlterConfigOp {
+    fn name(&self) -> &str {
+        match self {
+            AlterConfigOp::Set { name, .. }
+            | AlterConfigOp::Delete { name }
+            | AlterConfigOp::Append { name, .. }
+            | AlterConfigOp::Subtract { name, .. } => name,
+        }
+    }
+
+    /// The value of the config once the operation is applied to `current`, its value
+    /// before, or `None` if it is deleted. Fails if an APPEND or SUBTRACT is applied to a
+    /// config which is not a list.
+    fn apply(&self, current: Option<&str>) -> Result<Option<String>, ApiError> {
+        let (name, values, append) = match self {
+            AlterConfigOp::Set { value, .. } => return Ok(Some(value.clone())),
+            AlterConfigOp::Delete { .. } => return Ok(None),
+            AlterConfigOp::Append { name, values } => (name, values, true),
+            AlterConfigOp::Subtract { name, values } => (name, values, false),
+        };
+        let Some(default) = list_config_default(name) else {
+            let op = if append { "append" } else { "subtract" };
+            return Err(ApiError::new(
+                Errors::InvalidConfig,
+                format!(
+                    "Config value {op} is not allowed for config key {name}, which is not a list"
+                ),
+            ));
+        };
+        let mut list: Vec<&str> = list_values(current.unwrap_or(default)).collect();
+        for value in list_values(values) {
+            match append {
+                true if !list.contains(&value) => list.push(value),
+                true => {}
+                false => list.retain(|v| *v != value),
+            }
+        }
+        Ok(Some(list.join(",")))
+    }
+}
+
+impl From<admin::AlterConfigOp> for AlterConfigOp {
+    fn from(op: admin::AlterConfigOp) -> Self {
+        let name = op.name;
+        let value = op.value.unwrap_or_default();
+        match op.op_type {
+            AlterConfigOpType::Set => AlterConfigOp::Set { name, value },
+            AlterConfigOpType::Delete => AlterConfigOp::Delete { name },
+            AlterConfigOpType::Append => AlterConfigOp::Append {
+                name,
+                values: value,
+            },
+            AlterConfigOpType::Subtract => AlterConfigOp::Subtract {
+                name,
+                values: value,
+            },
+        }
+    }
+}
+
+/// The values of a comma-separated list.
+fn list_values(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Tracks the dynamic configs of the topics.
+#[derive(Debug, Default)]
+pub struct ConfigurationControlManager {
+    topic_configs: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl ConfigurationControlManager {
+    /// Replays a config record. The configs of other resources than topics are served by
+    /// the metadata image of the brokers.
+    ///
+    /// An override of an enforced config of an internal topic, e.g. in a snapshot written
+    /// before the config was enforced, is ignored so that the topic keeps its enforced value.
+    pub fn replay(&mut self, record: &ConfigRecord) {
+        if record.resource_type != TOPIC_RESOURCE_TYPE {
+            return;
+        }
+        let topic = &record.resource_name;
+        if let Some(enforced) = enforced_value(topic, &record.name)
+            && record.value.as_deref() != Some(enforced)
+        {
+            warn!(
+                "Ignoring {} {:?} of internal topic {topic}, which is enforced to be {enforced}",
+                record.name, record.value
+            );
+            return;
+        }
+        info!(
+            "Replayed a ConfigRecord setting {} of topic {topic} to {:?}",
+            record.name, record.value
+        );
+        match &record.value {
+            Some(value) => {
+                self.topic_configs
+                    .entry(topic.clone())
+                    .or_default()
+                    .insert(record.name.clone(), value.clone());
+            }
+            None => {
+                if let Some(configs) = self.topic_configs.get_mut(topic) {
+                    configs.remove(&record.name);
+                    if configs.is_empty() {
+                        self.topic_configs.remove(topic);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The dynamic configs of `topic`.
+    pub fn topic_configs(&self, topic: &str) -> Option<&BTreeMap<String, String>> {
+        self.topic_configs.get(topic)
+    }
+
+    /// Returns the records setting the configs of a topic being created: the configs of the
+    /// request, and the enforced configs of an internal topic, which the request may only
+    /// set to their enforced value.
+    pub fn create_topic_configs(
+        &self,
+        topic: &str,
+        configs: &BTreeMap<String, String>,
+    ) -> Result<Vec<MetadataRecord>, ApiError> {
+        let mut all = configs.clone();
+        for (name, value) in enforced_topic_configs(topic) {
+            match configs.get(*name) {
+                Some(requested) if requested != value => {
+                    return Err(enforced_config_error(topic, name, value));
+                }
+                _ => all.insert(name.to_string(), value.to_string()),
+            };
+        }
+        Ok(all
+            .into_iter()
+            .map(|(name, value)| config_record(topic, name, Some(value)))
+            .collect())
+    }
+
+    /// Returns the records altering the configs of `topic`. The operations are applied in
+    /// order, an APPEND or SUBTRACT to the value left by the previous ones or, if there are
+    /// none, to the dynamic or default value of the config. They are applied all together or,
+    /// if one of them fails or changes an enforced config, not at all.
+    pub fn incremental_alter_topic_configs(
+        &self,
+        topic: &str,
+        ops: &[AlterConfigOp],
+    ) -> Result<ControllerResult<()>, ApiError> {
+        let mut configs = self.topic_configs.get(topic).cloned().unwrap_or_default();
+        let mut records = Vec::with_capacity(ops.len());
+        for op in ops {
+            let name = op.name();
+            let value = op.apply(configs.get(name).map(String::as_str))?;
+            if let Some(enforced) = enforced_value(topic, name)
+                && value.as_deref() != Some(enforced)
+            {
+                return Err(enforced_config_error(topic, name, enforced));
+            }
+            match &value {
+                Some(value) => configs.insert(name.to_string(), value.clone()),
+                None => configs.remove(name),
+            };
+            records.push(config_record(topic, name.to_string(), value));
+        }
+        Ok(ControllerResult::new(records, ()))
+    }
+}
+
+fn config_record(topic: &str, name: String, value: Option<String>) -> MetadataRecord {
+    MetadataRecord::Config(ConfigRecord {
+        resource_type: TOPIC_RESOURCE_TYPE,
+        resource_name: topic.to_string(),
+        name,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::config::topic_config::LEADER_REPLICATION_THROTTLED_REPLICAS_CONFIG;
+
+    fn replay(manager: &mut ConfigurationControlManager, records: &[MetadataRecord]) {
+        for record in records {
+            match record {
+                MetadataRecord::Config(record) => manager.replay(record),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn set(name: &str, value: &str) -> AlterConfigOp {
+        AlterConfigOp::Set {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_internal_topics_are_created_with_enforced_configs() {
+        let mut manager = ConfigurationControlManager::default();
+        let configs = BTreeMap::from([("retention.ms".to_string(), "1000".to_string())]);
+        let records = manager
+            .create_topic_configs(GROUP_METADATA_TOPIC_NAME, &configs)
+            .unwrap();
+        replay(&mut manager, &records);
+        let configs = manager.topic_configs(GROUP_METADATA_TOPIC_NAME).unwrap();
+        assert_eq!(4, configs.len());
+        assert_eq!("compact", configs[CLEANUP_POLICY_CONFIG]);
+        assert_eq!("producer", configs[COMPRESSION_TYPE_CONFIG]);
+
+        let configs = BTreeMap::from([(CLEANUP_POLICY_CONFIG.to_string(), "delete".to_string())]);
+        let error = manager
+            .create_topic_configs(TRANSACTION_STATE_TOPIC_NAME, &configs)
+            .unwrap_err();
+        assert_eq!(Errors::InvalidConfig, error.error());
+        assert_eq!(
+            1,
+            manager.create_topic_configs("foo", &configs).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_enforced_configs_cannot_be_altered() {
+        let manager = ConfigurationControlManager::default();
+        let topic = GROUP_METADATA_TOPIC_NAME;
+        let error = manager
+            .incremental_alter_topic_configs(
+                topic,
+                &[
+                    set("retention.ms", "1000"),
+                    set(CLEANUP_POLICY_CONFIG, "delete"),
+                ],
+            )
+            .unwrap_err();
+        assert_eq!(Errors::InvalidConfig, error.error());
+        assert!(error.message().contains("cannot be overridden"));
+        let delete = AlterConfigOp::Delete {
+            name: SEGMENT_BYTES_CONFIG.to_string(),
+        };
+        assert!(
+            manager
+                .incremental_alter_topic_configs(topic, &[delete])
+                .is_err()
+        );
+
+        // Setting an enforced config to its value and other configs is fine.
+        let result = manager
+            .incremental_alter_topic_configs(
+                topic,
+                &[
+                    set("retention.ms", "1000"),
+                    set(CLEANUP_POLICY_CONFIG, "compact"),
+                ],
+            )
+            .unwrap();
+        assert_eq!(2, result.records().len());
+        assert!(
+            manager
+                .incremental_alter_topic_configs("foo", &[set(CLEANUP_POLICY_CONFIG, "delete")])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_append_and_subtract_list_configs() {
+        let mut manager = ConfigurationControlManager::default();
+        let throttled = LEADER_REPLICATION_THROTTLED_REPLICAS_CONFIG;
+        let ops: Vec<AlterConfigOp> = vec![
+            admin::AlterConfigOp::append(throttled, "0:1, 0:2").into(),
+            admin::AlterConfigOp::append(throttled, "0:2,1:1").into(),
+            admin::AlterConfigOp::subtract(throttled, "0:1").into(),
+            // The default of cleanup.policy is delete.
+            admin::AlterConfigOp::append(CLEANUP_POLICY_CONFIG, CLEANUP_POLICY_COMPACT).into(),
+        ];
+        let result = manager
+            .incremental_alter_topic_configs("foo", &ops)
+            .unwrap();
+        replay(&mut manager, result.records());
+        let configs = manager.topic_configs("foo").unwrap();
+        assert_eq!("0:2,1:1", configs[throttled]);
+        assert_eq!("delete,compact", configs[CLEANUP_POLICY_CONFIG]);
+
+        let subtract = AlterConfigOp::Subtract {
+            name: throttled.to_string(),
+            values: "0:2,1:1".to_string(),
+        };
+        let result = manager
+            .incremental_alter_topic_configs("foo", &[subtract])
+            .unwrap();
+        replay(&mut manager, result.records());
+        assert_eq!("", manager.topic_configs("foo").unwrap()[throttled]);
+
+        let append = AlterConfigOp::Append {
+            name: "retention.ms".to_string(),
+            values: "1000".to_string(),
+        };
+        let error = manager
+            .incremental_alter_topic_configs("foo", &[append])
+            .unwrap_err();
+        assert_eq!(Errors::InvalidConfig, error.error());
+        // The policy of an internal topic must stay compact alone.
+        assert!(
+            manager
+                .incremental_alter_topic_configs(
+                    GROUP_METADATA_TOPIC_NAME,
+                    &[admin::AlterConfigOp::append(CLEANUP_POLICY_CONFIG, "delete").into()],
+                )
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_replay_ignores_overrides_of_enforced_configs() {
+        let mut manager = ConfigurationControlManager::default();
+        let topic = TRANSACTION_STATE_TOPIC_NAME;
+        replay(
+            &mut manager,
+            &[
+                config_record(
+                    topic,
+                    CLEANUP_POLICY_CONFIG.to_string(),
+                    Some("compact".into()),
+                ),
+                config_record(
+                    topic,
+                    CLEANUP_POLICY_CONFIG.to_string(),
+                    Some("delete".into()),
+                ),
+                config_record(topic, CLEANUP_POLICY_CONFIG.to_string(), None),
+                config_record(topic, "retention.ms".to_string(), Some("1000".into())),
+            ],
+        );
+        let configs = manager.topic_configs(topic).unwrap();
+        assert_eq!("compact", configs[CLEANUP_POLICY_CONFIG]);
+        assert_eq!("1000", configs["retention.ms"]);
+    }
+}
@@ -0,0 +1,27 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+
+/// The outcome of a controller operation: the records to append to the metadata log and the
+/// response to send once they are committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControllerResult<T> {
+    records: Vec<MetadataRecord>,
+    response: T,
+}
+
+impl<T> ControllerResult<T> {
+    pub fn new(records: Vec<MetadataRecord>, response: T) -> Self {
+        Self { records, response }
+    }
+
+    pub fn records(&self) -> &[MetadataRecord] {
+        &self.records
+    }
+
+    pub fn response(&self) -> &T {
+        &self.response
+    }
+
+    pub fn into_parts(self) -> (Vec<MetadataRecord>, T) {
+        (self.records, self.response)
+    }
+}
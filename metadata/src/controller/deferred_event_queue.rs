@@ -0,0 +1,66 @@
+use rafka_clients::common::protocol::errors::ApiError;
+use std::collections::BTreeMap;
+
+type Completion = Box<dyn FnOnce(Result<(), ApiError>) + Send>;
+
+/// Completions waiting for an offset of the metadata log to be committed, so the active
+/// controller only answers a request once the records written for it are durable.
+#[derive(Default)]
+pub(crate) struct DeferredEventQueue {
+    pending: BTreeMap<i64, Vec<Completion>>,
+}
+
+impl DeferredEventQueue {
+    /// Adds a completion to run once `offset` is committed.
+    pub(crate) fn add(&mut self, offset: i64, completion: Completion) {
+        self.pending.entry(offset).or_default().push(completion);
+    }
+
+    /// Runs the completions of every offset up to and including `offset`.
+    pub(crate) fn complete_up_to(&mut self, offset: i64) {
+        let remaining = self.pending.split_off(&(offset + 1));
+        for completion in std::mem::replace(&mut self.pending, remaining)
+            .into_values()
+            .flatten()
+        {
+            completion(Ok(()));
+        }
+    }
+
+    /// Fails every pending completion, e.g. because the controller lost leadership and its
+    /// uncommitted writes may never be committed.
+    pub(crate) fn fail_all(&mut self, error: ApiError) {
+        for completion in std::mem::take(&mut self.pending).into_values().flatten() {
+            completion(Err(error.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::protocol::errors::Errors;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_complete_up_to() {
+        let completed = Arc::new(Mutex::new(Vec::new()));
+        let mut queue = DeferredEventQueue::default();
+        for offset in [3, 1, 2] {
+            let completed = Arc::clone(&completed);
+            queue.add(
+                offset,
+                Box::new(move |result| completed.lock().unwrap().push((offset, result))),
+            );
+        }
+
+        queue.complete_up_to(2);
+        assert_eq!(vec![(1, Ok(())), (2, Ok(()))], *completed.lock().unwrap());
+
+        queue.fail_all(Errors::NotController.into());
+        assert_eq!(
+            (3, Err(Errors::NotController.into())),
+            completed.lock().unwrap()[2]
+        );
+    }
+}
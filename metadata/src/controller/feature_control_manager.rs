@@ -0,0 +1,36 @@
+use crate::common::metadata::records::FeatureLevelRecord;
+use rafka_server_common::metadata_version::MetadataVersion;
+use std::collections::BTreeMap;
+use tracing::info;
+
+/// Tracks the finalized feature levels replayed from the metadata log.
+#[derive(Debug, Default)]
+pub struct FeatureControlManager {
+    finalized_versions: BTreeMap<String, i16>,
+}
+
+impl FeatureControlManager {
+    pub fn replay(&mut self, record: &FeatureLevelRecord) {
+        info!(
+            "Replayed a FeatureLevelRecord setting feature {} to {}",
+            record.name, record.feature_level
+        );
+        if record.feature_level == 0 {
+            self.finalized_versions.remove(&record.name);
+        } else {
+            self.finalized_versions
+                .insert(record.name.clone(), record.feature_level);
+        }
+    }
+
+    /// The metadata version, or `None` if the log has not been bootstrapped yet.
+    pub fn metadata_version(&self) -> Option<MetadataVersion> {
+        self.finalized_versions
+            .get(MetadataVersion::FEATURE_NAME)
+            .and_then(|level| MetadataVersion::from_feature_level(*level))
+    }
+
+    pub fn finalized_features(&self) -> &BTreeMap<String, i16> {
+        &self.finalized_versions
+    }
+}
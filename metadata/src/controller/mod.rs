@@ -0,0 +1,11 @@
+pub mod client_quota_control_manager;
+pub mod cluster_control_manager;
+pub mod configuration_control_manager;
+pub mod controller_result;
+mod deferred_event_queue;
+pub mod feature_control_manager;
+pub mod operation_control_manager;
+pub mod quorum_controller;
+pub mod quorum_controller_metrics;
+pub mod replica_placement;
+pub mod replication_control_manager;
@@ -0,0 +1,152 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::records::CompletedOperationRecord;
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use std::collections::HashMap;
+use std::fmt;
+use tracing::info;
+
+/// The admin operations a client can make idempotent with an operation key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperationType {
+    CreateTopics,
+}
+
+impl OperationType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperationType::CreateTopics => "CreateTopics",
+        }
+    }
+}
+
+impl fmt::Display for OperationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An operation applied with an operation key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CompletedOperation {
+    operation: String,
+    request_hash: i64,
+    resource_name: String,
+}
+
+/// Tracks the admin operations applied with an operation key.
+///
+/// A client retrying an operation, e.g. because the active controller failed over before
+/// answering, can't tell whether it was applied. Applying it again could fail, as creating a
+/// topic which exists does, or apply it twice. The key the client sends along is recorded in
+/// the metadata log with the records of the operation, so every controller knows which
+/// operations were applied, and a retry is answered with the outcome of the first attempt.
+#[derive(Debug, Default)]
+pub struct OperationControlManager {
+    completed: HashMap<String, CompletedOperation>,
+}
+
+impl OperationControlManager {
+    pub fn replay(&mut self, record: &CompletedOperationRecord) {
+        info!(
+            "Replayed a CompletedOperationRecord for {} with key {}",
+            record.operation, record.operation_key
+        );
+        self.completed.insert(
+            record.operation_key.clone(),
+            CompletedOperation {
+                operation: record.operation.clone(),
+                request_hash: record.request_hash,
+                resource_name: record.resource_name.clone(),
+            },
+        );
+    }
+
+    /// The resource of the operation applied with `operation_key`, or `None` if there is
+    /// none. Fails with `InvalidRequest` if the key was used for another request.
+    pub fn completed(
+        &self,
+        operation_key: &str,
+        operation: OperationType,
+        request_hash: i64,
+    ) -> Result<Option<&str>, ApiError> {
+        let Some(completed) = self.completed.get(operation_key) else {
+            return Ok(None);
+        };
+        if completed.operation != operation.name() || completed.request_hash != request_hash {
+            return Err(ApiError::new(
+                Errors::InvalidRequest,
+                format!(
+                    "Operation key {operation_key} was already used for another {} request",
+                    completed.operation
+                ),
+            ));
+        }
+        Ok(Some(&completed.resource_name))
+    }
+
+    /// The record marking the operation as applied, to append with its records.
+    pub fn complete(
+        operation_key: &str,
+        operation: OperationType,
+        request_hash: i64,
+        resource_name: &str,
+    ) -> MetadataRecord {
+        MetadataRecord::CompletedOperation(CompletedOperationRecord {
+            operation_key: operation_key.to_string(),
+            operation: operation.name().to_string(),
+            request_hash,
+            resource_name: resource_name.to_string(),
+        })
+    }
+}
+
+/// A fingerprint of a request, from its fields in a canonical form. Unlike the hashers of
+/// the standard library it is stable across releases, as it is stored in the metadata log.
+pub fn request_hash(canonical: &str) -> i64 {
+    // 64 bits FNV-1a.
+    canonical
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        }) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completed_operations() {
+        let mut manager = OperationControlManager::default();
+        let hash = request_hash("foo");
+        assert_eq!(
+            Ok(None),
+            manager.completed("key", OperationType::CreateTopics, hash)
+        );
+
+        let MetadataRecord::CompletedOperation(record) =
+            OperationControlManager::complete("key", OperationType::CreateTopics, hash, "foo")
+        else {
+            panic!("not a CompletedOperationRecord");
+        };
+        manager.replay(&record);
+        assert_eq!(
+            Ok(Some("foo")),
+            manager.completed("key", OperationType::CreateTopics, hash)
+        );
+        assert_eq!(
+            Errors::InvalidRequest,
+            manager
+                .completed("key", OperationType::CreateTopics, request_hash("bar"))
+                .unwrap_err()
+                .error()
+        );
+    }
+
+    #[test]
+    fn test_request_hash_is_stable() {
+        assert_eq!(0xcbf2_9ce4_8422_2325u64 as i64, request_hash(""));
+        assert_eq!(0xaf63_dc4c_8601_ec8cu64 as i64, request_hash("a"));
+        assert_ne!(request_hash("foo/1"), request_hash("foo/2"));
+    }
+}
@@ -0,0 +1,1982 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::records::{
+    AbortTransactionRecord, BeginTransactionRecord, EndTransactionRecord,
+};
+use crate::common::metadata::transaction_buffer::TransactionBuffer;
+use crate::controller::client_quota_control_manager::ClientQuotaControlManager;
+use crate::controller::cluster_control_manager::{
+    BrokerHeartbeatReply, BrokerHeartbeatRequest, BrokerRegistrationReply,
+    BrokerRegistrationRequest, ClusterControlManager, DEFAULT_SESSION_TIMEOUT_MS,
+};
+use crate::controller::configuration_control_manager::{
+    AlterConfigOp, ConfigurationControlManager,
+};
+use crate::controller::controller_result::ControllerResult;
+use crate::controller::deferred_event_queue::DeferredEventQueue;
+use crate::controller::feature_control_manager::FeatureControlManager;
+use crate::controller::operation_control_manager::{OperationControlManager, OperationType};
+use crate::controller::quorum_controller_metrics::QuorumControllerMetrics;
+use crate::controller::replication_control_manager::{
+    AlterPartitionRequest, CreatableTopic, CreatableTopicResult, DEFAULT_NUM_PARTITIONS,
+    DEFAULT_REPLICATION_FACTOR, ReplicationControlManager,
+};
+use crate::metadata::bootstrap::bootstrap_metadata::BootstrapMetadata;
+use crate::metadata::broker_registration::BrokerRegistration;
+use crate::metadata::partition_registration::PartitionRegistration;
+use rafka_clients::common::metrics::{MetricConfig, Metrics};
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use rafka_clients::common::quota::client_quota_alteration::ClientQuotaAlteration;
+use rafka_clients::common::quota::client_quota_entity::ClientQuotaEntity;
+use rafka_clients::common::utils::time::{SystemTime, Time};
+use rafka_raft::raft_client::{Listener, RaftClient, RaftError};
+use rafka_raft::{Batch, BatchReader, KRaftVersion, LeaderAndEpoch, SnapshotReader};
+use rafka_server_common::event_queue::{Event, EventQueue, EventQueueError, FnEvent};
+use rafka_server_common::kafka_event_queue::KafkaEventQueue;
+use rafka_server_common::metadata_version::MetadataVersion;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// The raft client shared between the controller and the listener it registers.
+pub type SharedRaftClient = Arc<Mutex<dyn RaftClient<MetadataRecord> + Send>>;
+
+/// The number of records appended as a single batch. The records of an operation which don't
+/// fit are appended in a metadata transaction spanning several batches.
+pub const DEFAULT_MAX_RECORDS_PER_BATCH: usize = 10_000;
+
+pub struct QuorumControllerBuilder {
+    node_id: i32,
+    cluster_id: String,
+    raft_client: SharedRaftClient,
+    session_timeout_ms: i64,
+    max_records_per_batch: usize,
+    default_num_partitions: i32,
+    default_replication_factor: i16,
+    bootstrap_metadata: BootstrapMetadata,
+    time: Arc<dyn Time>,
+    metrics: Option<Metrics>,
+}
+
+impl QuorumControllerBuilder {
+    pub fn new(node_id: i32, cluster_id: impl Into<String>, raft_client: SharedRaftClient) -> Self {
+        Self {
+            node_id,
+            cluster_id: cluster_id.into(),
+            raft_client,
+            session_timeout_ms: DEFAULT_SESSION_TIMEOUT_MS,
+            max_records_per_batch: DEFAULT_MAX_RECORDS_PER_BATCH,
+            default_num_partitions: DEFAULT_NUM_PARTITIONS,
+            default_replication_factor: DEFAULT_REPLICATION_FACTOR,
+            bootstrap_metadata: BootstrapMetadata::from_version(
+                MetadataVersion::LATEST_PRODUCTION,
+                "the default bootstrap",
+            ),
+            time: Arc::new(SystemTime),
+            metrics: None,
+        }
+    }
+
+    /// Sets the records appended by the first leader of an empty metadata log.
+    pub fn set_bootstrap_metadata(mut self, bootstrap_metadata: BootstrapMetadata) -> Self {
+        self.bootstrap_metadata = bootstrap_metadata;
+        self
+    }
+
+    /// Sets how long a broker stays registered without contacting the active controller.
+    pub fn set_session_timeout_ms(mut self, session_timeout_ms: i64) -> Self {
+        self.session_timeout_ms = session_timeout_ms;
+        self
+    }
+
+    /// Sets the number of records appended as a single batch.
+    pub fn set_max_records_per_batch(mut self, max_records_per_batch: usize) -> Self {
+        self.max_records_per_batch = max_records_per_batch.max(1);
+        self
+    }
+
+    /// Sets the number of partitions and the replication factor of the topics created without
+    /// them.
+    pub fn set_topic_defaults(mut self, num_partitions: i32, replication_factor: i16) -> Self {
+        self.default_num_partitions = num_partitions;
+        self.default_replication_factor = replication_factor;
+        self
+    }
+
+    pub fn set_time(mut self, time: Arc<dyn Time>) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn set_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Creates the controller and registers it with the raft client.
+    pub fn build(self) -> QuorumController {
+        let metrics = self
+            .metrics
+            .unwrap_or_else(|| Metrics::new(MetricConfig::default(), Arc::clone(&self.time)));
+        let queue = Arc::new(KafkaEventQueue::new(
+            Arc::clone(&self.time),
+            &format!("quorum-controller-{}-", self.node_id),
+            None,
+            Some(&metrics),
+        ));
+        let state = Arc::new(Mutex::new(ControllerState {
+            node_id: self.node_id,
+            raft_client: Arc::clone(&self.raft_client),
+            bootstrap_metadata: self.bootstrap_metadata,
+            max_records_per_batch: self.max_records_per_batch,
+            time: Arc::clone(&self.time),
+            metrics: QuorumControllerMetrics::new(&metrics),
+            last_committed_offset: -1,
+            write_offset: -1,
+            cur_claim_epoch: None,
+            leader: LeaderAndEpoch::UNKNOWN,
+            leader_lost_ns: None,
+            transaction: TransactionBuffer::new(),
+            deferred: DeferredEventQueue::default(),
+            feature_control: FeatureControlManager::default(),
+            cluster_control: ClusterControlManager::new(self.cluster_id, self.session_timeout_ms),
+            replication_control: ReplicationControlManager::default()
+                .with_topic_defaults(self.default_num_partitions, self.default_replication_factor),
+            client_quota_control: ClientQuotaControlManager::default(),
+            configuration_control: ConfigurationControlManager::default(),
+            operation_control: OperationControlManager::default(),
+        }));
+        self.raft_client
+            .lock()
+            .expect("raft client lock poisoned")
+            .register(Box::new(QuorumMetaLogListener {
+                queue: Arc::clone(&queue),
+                state: Arc::clone(&state),
+                time: self.time,
+            }));
+        QuorumController {
+            node_id: self.node_id,
+            queue,
+            state,
+        }
+    }
+}
+
+/// The state of the controller. It is only modified by events running on the controller's
+/// event queue.
+///
+/// Active and standby controllers alike replay every committed record as soon as it is
+/// delivered, so a standby which is elected only has to replay the tail written since the
+/// last commit it saw before it can take over. The active controller replays its own writes
+/// right away, so each operation sees the effect of the previous ones, and answers a request
+/// once the records written for it are committed.
+///
+/// The records of an operation too large for a single batch are written in a metadata
+/// transaction. The active controller replays them once all were appended, and the standbys
+/// once they read the end of the transaction, so that no controller ever applies part of an
+/// operation, even one interrupted by a failover.
+struct ControllerState {
+    node_id: i32,
+    raft_client: SharedRaftClient,
+    bootstrap_metadata: BootstrapMetadata,
+    max_records_per_batch: usize,
+    time: Arc<dyn Time>,
+    metrics: QuorumControllerMetrics,
+    /// The offset of the last record replayed from the log, or -1 if none was.
+    last_committed_offset: i64,
+    /// The offset of the last record this controller wrote and replayed, or -1 if none was.
+    write_offset: i64,
+    /// The epoch this controller is the active controller of, if any.
+    cur_claim_epoch: Option<i32>,
+    /// The last leader this controller was notified of.
+    leader: LeaderAndEpoch,
+    /// When the cluster was last seen without an active controller.
+    leader_lost_ns: Option<i64>,
+    /// The open transaction of the log replayed by a standby.
+    transaction: TransactionBuffer,
+    /// The responses waiting for their records to be committed.
+    deferred: DeferredEventQueue,
+    feature_control: FeatureControlManager,
+    cluster_control: ClusterControlManager,
+    replication_control: ReplicationControlManager,
+    client_quota_control: ClientQuotaControlManager,
+    configuration_control: ConfigurationControlManager,
+    operation_control: OperationControlManager,
+}
+
+impl ControllerState {
+    /// The finalized features, including the `kraft.version` of the metadata quorum once it
+    /// was upgraded.
+    fn finalized_features(&self) -> BTreeMap<String, i16> {
+        let mut features = self.feature_control.finalized_features().clone();
+        let kraft_version = self
+            .raft_client
+            .lock()
+            .expect("raft client lock poisoned")
+            .kraft_version();
+        if kraft_version != KRaftVersion::Kraft0 {
+            features.insert(
+                KRaftVersion::FEATURE_NAME.to_string(),
+                kraft_version.feature_level(),
+            );
+        }
+        features
+    }
+
+    fn replay(&mut self, record: &MetadataRecord) {
+        match record {
+            MetadataRecord::RegisterBroker(record) => self.cluster_control.replay(record),
+            MetadataRecord::UnregisterBroker(record) => {
+                self.cluster_control.replay_unregister(record)
+            }
+            MetadataRecord::BrokerRegistrationChange(record) => {
+                self.cluster_control.replay_registration_change(record)
+            }
+            MetadataRecord::Topic(record) => self.replication_control.replay_topic(record),
+            MetadataRecord::Partition(record) => self.replication_control.replay_partition(record),
+            MetadataRecord::FeatureLevel(record) => self.feature_control.replay(record),
+            MetadataRecord::ClientQuota(record) => self.client_quota_control.replay(record),
+            MetadataRecord::Config(record) => self.configuration_control.replay(record),
+            // SCRAM credentials are served by the brokers' metadata image.
+            MetadataRecord::UserScramCredential(_) => {}
+            // ACLs are enforced by the authorizers of the brokers and controllers.
+            MetadataRecord::AccessControlEntry(_) | MetadataRecord::RemoveAccessControlEntry(_) => {
+            }
+            MetadataRecord::CompletedOperation(record) => self.operation_control.replay(record),
+            // Transactions are resolved by the TransactionBuffer the records go through.
+            MetadataRecord::BeginTransaction(_)
+            | MetadataRecord::EndTransaction(_)
+            | MetadataRecord::AbortTransaction(_) => {}
+        }
+    }
+
+    fn handle_commits(&mut self, batches: Vec<Batch<MetadataRecord>>) {
+        for batch in batches {
+            // The records this controller wrote were replayed when they were written.
+            if batch.last_offset() > self.write_offset {
+                let mut transaction = std::mem::take(&mut self.transaction);
+                for record in batch.records() {
+                    transaction.apply(record, |record| self.replay(record));
+                }
+                self.transaction = transaction;
+            }
+            self.last_committed_offset = batch.last_offset();
+            self.metrics
+                .set_last_applied_record(batch.last_offset(), batch.append_timestamp());
+        }
+        self.deferred.complete_up_to(self.last_committed_offset);
+    }
+
+    /// Handles a leadership change the raft client notified the listener of at `notified_ns`.
+    fn handle_leader_change(&mut self, leader: LeaderAndEpoch, notified_ns: i64) {
+        let previous = std::mem::replace(&mut self.leader, leader);
+        match leader.leader_id() {
+            None => {
+                self.leader_lost_ns.get_or_insert(notified_ns);
+            }
+            Some(leader_id) if leader_id == self.node_id => {
+                // Neither the first election of a fresh cluster nor the re-election of the
+                // active controller is a failover.
+                if previous.epoch() > 0 && self.cur_claim_epoch.is_none() {
+                    let lost_ns = self.leader_lost_ns.unwrap_or(notified_ns);
+                    let failover_time_ms = (self.time.nanoseconds() - lost_ns) as f64 / 1_000_000.0;
+                    info!(
+                        "Node {} took over as the active controller after {} ms",
+                        self.node_id, failover_time_ms
+                    );
+                    self.metrics.record_failover_time(failover_time_ms);
+                }
+                self.leader_lost_ns = None;
+                self.claim(leader.epoch());
+                return;
+            }
+            Some(_) => self.leader_lost_ns = None,
+        }
+        if let Some(epoch) = self.renounce() {
+            info!(
+                "Node {} renounced leadership of epoch {}, the new leader is {:?} in epoch {}",
+                self.node_id,
+                epoch,
+                leader.leader_id(),
+                leader.epoch()
+            );
+        }
+    }
+
+    /// Stops acting as the active controller, failing the requests waiting for their records
+    /// to be committed. Returns the epoch this controller was the active controller of.
+    fn renounce(&mut self) -> Option<i32> {
+        let epoch = self.cur_claim_epoch.take()?;
+        self.metrics.set_active(false);
+        self.cluster_control.deactivate();
+        self.deferred.fail_all(ApiError::new(
+            Errors::NotController,
+            format!("Node {} is no longer the active controller", self.node_id),
+        ));
+        Some(epoch)
+    }
+
+    fn claim(&mut self, epoch: i32) {
+        info!(
+            "Node {} became the active controller in epoch {}",
+            self.node_id, epoch
+        );
+        self.cur_claim_epoch = Some(epoch);
+        self.write_offset = self.write_offset.max(self.last_committed_offset);
+        self.metrics.set_active(true);
+        self.cluster_control.activate(self.time.nanoseconds());
+        if let Some(name) = self.transaction.transaction_name() {
+            // The previous active controller failed before it could end its transaction, so
+            // the records it appended for the operation are discarded.
+            let reason = format!(
+                "Node {} took over in epoch {epoch} while transaction {name} was open",
+                self.node_id
+            );
+            warn!("Aborting the open metadata transaction: {reason}");
+            self.transaction.clear();
+            let abort = MetadataRecord::AbortTransaction(AbortTransactionRecord { reason });
+            if let Err(e) = self.write("abort_transaction", vec![abort]) {
+                error!("Failed to abort the open metadata transaction: {e}");
+                return;
+            }
+        }
+        if self.last_committed_offset != -1 {
+            return;
+        }
+        let bootstrap = &self.bootstrap_metadata;
+        info!(
+            "The metadata log appears to be empty. Appending {} bootstrap record(s) at \
+            metadata.version {} from {}",
+            bootstrap.records().len(),
+            bootstrap.metadata_version().version(),
+            bootstrap.source()
+        );
+        if let Err(e) = self.write("bootstrap", bootstrap.records().to_vec()) {
+            error!("Failed to append the bootstrap records: {e}");
+        }
+    }
+
+    /// Appends `records`, written by the operation `name`, in the current epoch and replays
+    /// them. Returns the offset of the last record written so far.
+    ///
+    /// Records which don't fit in a single batch are appended as a transaction spanning
+    /// several batches. Should one of them fail to be appended, this controller renounces its
+    /// leadership and its successor aborts the transaction.
+    fn write(&mut self, name: &str, records: Vec<MetadataRecord>) -> Result<i64, ApiError> {
+        let Some(epoch) = self.cur_claim_epoch else {
+            return Err(ApiError::new(
+                Errors::NotController,
+                format!("Node {} is not the active controller", self.node_id),
+            ));
+        };
+        if records.is_empty() {
+            return Ok(self.write_offset);
+        }
+        let batches = self.split_into_batches(name, records);
+        let mut offset = self.write_offset;
+        for (i, batch) in batches.iter().enumerate() {
+            let result = self
+                .raft_client
+                .lock()
+                .expect("raft client lock poisoned")
+                .schedule_append(epoch, batch.clone());
+            match result {
+                Ok(last_offset) => offset = last_offset,
+                Err(e @ RaftError::AppendFailed { .. }) => {
+                    // The raft client resigned: this controller can't persist what it accepts.
+                    error!(
+                        "Node {} renounces leadership of epoch {epoch}: {e}",
+                        self.node_id
+                    );
+                    self.renounce();
+                    return Err(ApiError::new(Errors::NotController, e.to_string()));
+                }
+                Err(e) => {
+                    if i > 0 {
+                        // Writing anything else would add it to the open transaction.
+                        error!(
+                            "Node {} renounces leadership of epoch {epoch}, it failed to \
+                            complete transaction {name}: {e}",
+                            self.node_id
+                        );
+                        self.renounce();
+                    }
+                    return Err(ApiError::new(Errors::NotController, e.to_string()));
+                }
+            }
+        }
+        for record in batches.iter().flatten() {
+            self.replay(record);
+        }
+        self.write_offset = offset;
+        Ok(offset)
+    }
+
+    /// Splits `records` into the batches to append, wrapping them in a transaction if they
+    /// don't fit in one.
+    fn split_into_batches(
+        &self,
+        name: &str,
+        records: Vec<MetadataRecord>,
+    ) -> Vec<Vec<MetadataRecord>> {
+        if records.len() <= self.max_records_per_batch {
+            return vec![records];
+        }
+        let mut wrapped = Vec::with_capacity(records.len() + 2);
+        wrapped.push(MetadataRecord::BeginTransaction(BeginTransactionRecord {
+            name: name.to_string(),
+        }));
+        wrapped.extend(records);
+        wrapped.push(MetadataRecord::EndTransaction(EndTransactionRecord {}));
+        wrapped
+            .chunks(self.max_records_per_batch)
+            .map(<[MetadataRecord]>::to_vec)
+            .collect()
+    }
+
+    /// Returns the records creating `topic`, or the topic created the first time for a retry.
+    fn create_topic(
+        &mut self,
+        topic: &CreatableTopic,
+    ) -> Result<ControllerResult<CreatableTopicResult>, ApiError> {
+        let request_hash = topic.request_hash();
+        if let Some(key) = &topic.operation_key
+            && let Some(name) =
+                self.operation_control
+                    .completed(key, OperationType::CreateTopics, request_hash)?
+        {
+            info!("Topic {name} was already created with operation key {key}");
+            let result = self
+                .replication_control
+                .created_topic(name)
+                .ok_or_else(|| {
+                    ApiError::new(
+                        Errors::UnknownTopicOrPartition,
+                        format!("Topic {name} created with operation key {key} no longer exists."),
+                    )
+                })?;
+            return Ok(ControllerResult::new(vec![], result));
+        }
+        if topic.auto_create
+            && let Some(result) = self.replication_control.created_topic(&topic.name)
+        {
+            info!(
+                "Topic {} to auto-create was already created, returning it",
+                topic.name
+            );
+            return Ok(ControllerResult::new(vec![], result));
+        }
+        let config_records = self
+            .configuration_control
+            .create_topic_configs(&topic.name, &topic.configs)?;
+        let (mut records, response) = self
+            .replication_control
+            .create_topic(topic, &self.cluster_control.usable_brokers())?
+            .into_parts();
+        records.extend(config_records);
+        if let Some(key) = &topic.operation_key {
+            records.push(OperationControlManager::complete(
+                key,
+                OperationType::CreateTopics,
+                request_hash,
+                &topic.name,
+            ));
+        }
+        Ok(ControllerResult::new(records, response))
+    }
+
+    /// Returns the records altering the configs of `topic`, which must exist.
+    fn incremental_alter_topic_configs(
+        &mut self,
+        topic: &str,
+        ops: &[AlterConfigOp],
+    ) -> Result<ControllerResult<()>, ApiError> {
+        if self.replication_control.topic_id(topic).is_none() {
+            return Err(ApiError::new(
+                Errors::UnknownTopicOrPartition,
+                format!("Topic {topic} does not exist."),
+            ));
+        }
+        self.configuration_control
+            .incremental_alter_topic_configs(topic, ops)
+    }
+
+    /// Runs a controller operation and writes its records. The response is sent once they
+    /// are committed.
+    fn run_write_operation<T: Send + 'static>(
+        &mut self,
+        name: &str,
+        op: impl FnOnce(&mut ControllerState) -> Result<ControllerResult<T>, ApiError>,
+        tx: Sender<Result<T, ApiError>>,
+    ) {
+        let result = if self.cur_claim_epoch.is_some() {
+            op(self).and_then(|result| {
+                let (records, response) = result.into_parts();
+                Ok((self.write(name, records)?, response))
+            })
+        } else {
+            Err(ApiError::new(
+                Errors::NotController,
+                format!("Node {} is not the active controller", self.node_id),
+            ))
+        };
+        match result {
+            Ok((offset, response)) if offset > self.last_committed_offset => {
+                self.deferred.add(
+                    offset,
+                    Box::new(move |result| {
+                        let _ = tx.send(result.map(|()| response));
+                    }),
+                );
+            }
+            result => {
+                let _ = tx.send(result.map(|(_, response)| response));
+            }
+        }
+    }
+}
+
+/// An operation of the active controller, run on the controller's event queue.
+struct ControllerWriteEvent<T, F> {
+    name: &'static str,
+    state: Arc<Mutex<ControllerState>>,
+    op: Option<F>,
+    tx: Sender<Result<T, ApiError>>,
+}
+
+impl<T, F> Event for ControllerWriteEvent<T, F>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut ControllerState) -> Result<ControllerResult<T>, ApiError> + Send + 'static,
+{
+    fn run(&mut self) {
+        let Some(op) = self.op.take() else {
+            return;
+        };
+        let mut state = self.state.lock().expect("controller lock poisoned");
+        state.run_write_operation(self.name, op, self.tx.clone());
+    }
+
+    fn handle_exception(self: Box<Self>, error: EventQueueError) {
+        let code = match error {
+            EventQueueError::Timeout => Errors::RequestTimedOut,
+            EventQueueError::QueueFull(_) => Errors::ThrottlingQuotaExceeded,
+            EventQueueError::Cancelled | EventQueueError::Closed => Errors::NotController,
+            EventQueueError::Panicked(_) => Errors::UnknownServerError,
+        };
+        let _ = self.tx.send(Err(ApiError::new(
+            code,
+            format!("{} failed: {error}", self.name),
+        )));
+    }
+}
+
+/// The pending response to a controller operation.
+pub struct ControllerResponse<T> {
+    rx: Receiver<Result<T, ApiError>>,
+}
+
+impl<T> ControllerResponse<T> {
+    /// Blocks until the operation completes.
+    pub fn wait(self) -> Result<T, ApiError> {
+        self.rx.recv().unwrap_or_else(|_| {
+            Err(ApiError::new(
+                Errors::NotController,
+                "The controller shut down before responding",
+            ))
+        })
+    }
+
+    /// Blocks until the operation completes, for at most `timeout`, failing with
+    /// `RequestTimedOut` after that. The operation may still complete later.
+    pub fn wait_timeout(self, timeout: Duration) -> Result<T, ApiError> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(ApiError::new(
+                Errors::RequestTimedOut,
+                format!(
+                    "The controller did not respond within {} ms",
+                    timeout.as_millis()
+                ),
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(ApiError::new(
+                Errors::NotController,
+                "The controller shut down before responding",
+            )),
+        }
+    }
+}
+
+struct QuorumMetaLogListener {
+    queue: Arc<KafkaEventQueue>,
+    state: Arc<Mutex<ControllerState>>,
+    time: Arc<dyn Time>,
+}
+
+impl QuorumMetaLogListener {
+    fn append(&self, name: &'static str, f: impl FnOnce(&mut ControllerState) + Send + 'static) {
+        let state = Arc::clone(&self.state);
+        self.queue.append(FnEvent::new(name, move || {
+            f(&mut state.lock().expect("controller lock poisoned"))
+        }));
+    }
+}
+
+impl Listener<MetadataRecord> for QuorumMetaLogListener {
+    fn handle_commit(&mut self, reader: BatchReader<MetadataRecord>) {
+        let batches: Vec<_> = reader.collect();
+        self.append("handle_commit", move |state| state.handle_commits(batches));
+    }
+
+    fn handle_load_snapshot(&mut self, reader: SnapshotReader<MetadataRecord>) {
+        let last_contained_offset = reader.last_contained_log_offset();
+        let batches: Vec<_> = reader.collect();
+        self.append("handle_load_snapshot", move |state| {
+            state.handle_commits(batches);
+            state.last_committed_offset = last_contained_offset;
+            state.deferred.complete_up_to(last_contained_offset);
+        });
+    }
+
+    fn handle_leader_change(&mut self, leader: LeaderAndEpoch) {
+        let notified_ns = self.time.nanoseconds();
+        self.append("handle_leader_change", move |state| {
+            state.handle_leader_change(leader, notified_ns)
+        });
+    }
+}
+
+/// The controller of a KRaft cluster.
+///
+/// It replays the committed metadata log, and the node elected leader of the metadata quorum
+/// becomes the active controller. The first active controller of an empty log writes the
+/// bootstrap metadata.
+pub struct QuorumController {
+    node_id: i32,
+    queue: Arc<KafkaEventQueue>,
+    state: Arc<Mutex<ControllerState>>,
+}
+
+impl QuorumController {
+    pub fn node_id(&self) -> i32 {
+        self.node_id
+    }
+
+    /// Whether this controller is the active controller.
+    pub fn is_active(&self) -> bool {
+        self.state().cur_claim_epoch.is_some()
+    }
+
+    /// The offset of the last replayed record, or -1 if none was.
+    pub fn last_committed_offset(&self) -> i64 {
+        self.state().last_committed_offset
+    }
+
+    /// The metadata version, or `None` if the log has not been bootstrapped yet.
+    pub fn metadata_version(&self) -> Option<MetadataVersion> {
+        self.state().feature_control.metadata_version()
+    }
+
+    pub fn finalized_features(&self) -> BTreeMap<String, i16> {
+        self.state().finalized_features()
+    }
+
+    pub fn broker_registration(&self, broker_id: i32) -> Option<BrokerRegistration> {
+        self.state()
+            .cluster_control
+            .registration(broker_id)
+            .cloned()
+    }
+
+    /// Registers a broker, replying with its new broker epoch.
+    ///
+    /// Fails with `NotController` if this is not the active controller,
+    /// `DuplicateBrokerRegistration` if another incarnation of the broker id is still alive,
+    /// `InconsistentClusterId`, `UnsupportedVersion` or `InvalidRegistration` if the request
+    /// does not fit the cluster, and with `RequestTimedOut` or `ThrottlingQuotaExceeded` if
+    /// the controller is overloaded.
+    pub fn register_broker(
+        &self,
+        request: BrokerRegistrationRequest,
+    ) -> ControllerResponse<BrokerRegistrationReply> {
+        self.append_write_event("register_broker", None, move |state| {
+            let broker_epoch = state.write_offset + 1;
+            let now_ns = state.time.nanoseconds();
+            let finalized_features = state.finalized_features();
+            state.cluster_control.register_broker(
+                &request,
+                broker_epoch,
+                &finalized_features,
+                now_ns,
+            )
+        })
+    }
+
+    /// Handles a broker heartbeat, unfencing the broker once it reports it is ready and has
+    /// caught up with its registration.
+    ///
+    /// Fails with `NotController` if this is not the active controller, and with
+    /// `BrokerIdNotRegistered` or `StaleBrokerEpoch` if the broker has to register again.
+    pub fn process_broker_heartbeat(
+        &self,
+        request: BrokerHeartbeatRequest,
+    ) -> ControllerResponse<BrokerHeartbeatReply> {
+        self.append_write_event("process_broker_heartbeat", None, move |state| {
+            let now_ns = state.time.nanoseconds();
+            state
+                .cluster_control
+                .process_broker_heartbeat(&request, now_ns)
+        })
+    }
+
+    /// Moves a broker into controlled shutdown, so that it no longer gets leaderships or new
+    /// replicas.
+    ///
+    /// Fails with `NotController` if this is not the active controller, and with
+    /// `BrokerIdNotRegistered` or `StaleBrokerEpoch` if the request comes from an incarnation
+    /// of the broker which is no longer registered.
+    pub fn begin_controlled_shutdown(
+        &self,
+        broker_id: i32,
+        broker_epoch: i64,
+    ) -> ControllerResponse<()> {
+        self.append_write_event("begin_controlled_shutdown", None, move |state| {
+            state
+                .cluster_control
+                .begin_controlled_shutdown(broker_id, broker_epoch)
+        })
+    }
+
+    /// Changes the ISR of a partition as its leader asks, replying with the new registration
+    /// of the partition. Only registered brokers which are neither fenced nor in controlled
+    /// shutdown may join the ISR.
+    ///
+    /// Fails with `NotController` if this is not the active controller, with
+    /// `BrokerIdNotRegistered` or `StaleBrokerEpoch` if the request comes from an incarnation
+    /// of the leader which is no longer registered, and with the errors of
+    /// [ReplicationControlManager::alter_partition] otherwise.
+    pub fn alter_partition(
+        &self,
+        request: AlterPartitionRequest,
+    ) -> ControllerResponse<PartitionRegistration> {
+        self.append_write_event("alter_partition", None, move |state| {
+            let cluster_control = &state.cluster_control;
+            cluster_control.validate_broker_epoch(request.broker_id, request.broker_epoch)?;
+            state
+                .replication_control
+                .alter_partition(&request, |broker_id| {
+                    cluster_control
+                        .registration(broker_id)
+                        .is_some_and(|broker| broker.is_available())
+                })
+        })
+    }
+
+    /// Permanently removes a decommissioned broker from the cluster metadata.
+    ///
+    /// Fails with `NotController` if this is not the active controller,
+    /// `BrokerIdNotRegistered` if the broker id is unknown, and with `InvalidRequest` while
+    /// the broker still hosts replicas, which must be reassigned first.
+    pub fn unregister_broker(&self, broker_id: i32) -> ControllerResponse<()> {
+        self.append_write_event("unregister_broker", None, move |state| {
+            let partitions = state.replication_control.partitions_on_broker(broker_id);
+            if !partitions.is_empty() {
+                let listed: Vec<String> = partitions
+                    .iter()
+                    .take(10)
+                    .map(ToString::to_string)
+                    .collect();
+                return Err(ApiError::new(
+                    Errors::InvalidRequest,
+                    format!(
+                        "Broker {broker_id} still hosts replicas of {} partitions, including \
+                        {}. Reassign them before unregistering the broker",
+                        partitions.len(),
+                        listed.join(", ")
+                    ),
+                ));
+            }
+            state.cluster_control.unregister_broker(broker_id)
+        })
+    }
+
+    /// Creates a topic, placing its replicas on brokers which are neither fenced nor in
+    /// controlled shutdown. Internal topics get their enforced configs.
+    ///
+    /// A retry of a creation with the same operation key, e.g. after a controller failover, is
+    /// answered with the topic created the first time instead of failing with
+    /// `TopicAlreadyExists`.
+    pub fn create_topic(&self, topic: CreatableTopic) -> ControllerResponse<CreatableTopicResult> {
+        self.create_topic_with_deadline(topic, None)
+    }
+
+    /// Creates a topic for a request forwarded by a broker, which only waits `timeout_ms`, the
+    /// time left before its client gives up. The request fails with `RequestTimedOut` if it
+    /// did not run by then, rather than creating a topic nobody waits for.
+    pub fn create_topic_within(
+        &self,
+        topic: CreatableTopic,
+        timeout_ms: i64,
+    ) -> ControllerResponse<CreatableTopicResult> {
+        let deadline_ns = self.state().time.nanoseconds() + timeout_ms.max(0) * 1_000_000;
+        self.create_topic_with_deadline(topic, Some(deadline_ns))
+    }
+
+    fn create_topic_with_deadline(
+        &self,
+        topic: CreatableTopic,
+        deadline_ns: Option<i64>,
+    ) -> ControllerResponse<CreatableTopicResult> {
+        self.append_write_event("create_topic", deadline_ns, move |state| {
+            state.create_topic(&topic)
+        })
+    }
+
+    /// Creates several topics, replying with the outcome for each topic: a topic which can't
+    /// be created fails on its own, without failing the others. Topics listed more than once
+    /// all fail with `InvalidRequest`.
+    pub fn create_topics(
+        &self,
+        topics: Vec<CreatableTopic>,
+    ) -> ControllerResponse<BTreeMap<String, Result<CreatableTopicResult, ApiError>>> {
+        self.append_write_event("create_topics", None, move |state| {
+            let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+            for topic in &topics {
+                *counts.entry(topic.name.as_str()).or_default() += 1;
+            }
+            let mut records = vec![];
+            let mut results = BTreeMap::new();
+            for topic in &topics {
+                let result = if counts[topic.name.as_str()] > 1 {
+                    Err(ApiError::new(
+                        Errors::InvalidRequest,
+                        format!("Duplicate topic name {}.", topic.name),
+                    ))
+                } else {
+                    state.create_topic(topic).map(|result| {
+                        let (topic_records, response) = result.into_parts();
+                        records.extend(topic_records);
+                        response
+                    })
+                };
+                results.insert(topic.name.clone(), result);
+            }
+            Ok(ControllerResult::new(records, results))
+        })
+    }
+
+    /// Alters the configs of a topic, failing with `InvalidConfig` if an operation changes
+    /// an enforced config of an internal topic.
+    pub fn incremental_alter_topic_configs(
+        &self,
+        topic: String,
+        ops: Vec<AlterConfigOp>,
+    ) -> ControllerResponse<()> {
+        self.append_write_event("incremental_alter_topic_configs", None, move |state| {
+            state.incremental_alter_topic_configs(&topic, &ops)
+        })
+    }
+
+    /// Alters the configs of several topics, replying with the outcome for each topic. The
+    /// operations of a topic are applied all together or not at all, independently of the
+    /// other topics.
+    pub fn incremental_alter_configs(
+        &self,
+        alterations: BTreeMap<String, Vec<AlterConfigOp>>,
+    ) -> ControllerResponse<BTreeMap<String, ApiError>> {
+        self.append_write_event("incremental_alter_configs", None, move |state| {
+            let mut records = vec![];
+            let mut results = BTreeMap::new();
+            for (topic, ops) in &alterations {
+                let result = match state.incremental_alter_topic_configs(topic, ops) {
+                    Ok(result) => {
+                        records.extend(result.into_parts().0);
+                        ApiError::NONE
+                    }
+                    Err(e) => e,
+                };
+                results.insert(topic.clone(), result);
+            }
+            Ok(ControllerResult::new(records, results))
+        })
+    }
+
+    /// Alters the client quotas of users, client ids and IP addresses, replying with the
+    /// outcome for each entity.
+    pub fn alter_client_quotas(
+        &self,
+        alterations: Vec<ClientQuotaAlteration>,
+    ) -> ControllerResponse<BTreeMap<ClientQuotaEntity, ApiError>> {
+        self.append_write_event("alter_client_quotas", None, move |state| {
+            Ok(state.client_quota_control.alter_client_quotas(&alterations))
+        })
+    }
+
+    fn append_write_event<T: Send + 'static>(
+        &self,
+        name: &'static str,
+        deadline_ns: Option<i64>,
+        op: impl FnOnce(&mut ControllerState) -> Result<ControllerResult<T>, ApiError> + Send + 'static,
+    ) -> ControllerResponse<T> {
+        let (tx, rx) = channel();
+        let event = Box::new(ControllerWriteEvent {
+            name,
+            state: Arc::clone(&self.state),
+            op: Some(op),
+            tx,
+        });
+        match deadline_ns {
+            Some(deadline_ns) => self.queue.append_with_deadline(deadline_ns, event),
+            None => self.queue.append(event),
+        }
+        ControllerResponse { rx }
+    }
+
+    /// Stops the controller after the queued events have run. Requests still waiting for
+    /// their records to be committed fail with `NotController`.
+    pub fn close(&self) {
+        let state = Arc::clone(&self.state);
+        self.queue.append(FnEvent::new("close", move || {
+            let mut state = state.lock().expect("controller lock poisoned");
+            let error = ApiError::new(
+                Errors::NotController,
+                format!("Node {} is shutting down", state.node_id),
+            );
+            state.deferred.fail_all(error);
+        }));
+        self.queue.close();
+    }
+
+    fn state(&self) -> MutexGuard<'_, ControllerState> {
+        self.state.lock().expect("controller lock poisoned")
+    }
+
+    /// Waits until every event queued so far has run.
+    #[cfg(test)]
+    pub(crate) fn wait_for_events(&self) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.queue.append(FnEvent::new("wait_for_events", move || {
+            let _ = tx.send(());
+        }));
+        rx.recv().expect("the event queue was closed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::{ConfigRecord, TopicRecord};
+    use crate::controller::quorum_controller_metrics::{
+        ACTIVE_CONTROLLER_COUNT, CONTROLLER_FAILOVER_TIME_MS, CONTROLLER_METRICS_GROUP,
+        LAST_APPLIED_RECORD_OFFSET,
+    };
+    use crate::metadata::broker_registration::VersionRange;
+    use rafka_clients::common::internals::topic::GROUP_METADATA_TOPIC_NAME;
+    use rafka_clients::common::quota::client_quota_alteration::Op;
+    use rafka_clients::common::quota::client_quota_entity::IP;
+    use rafka_clients::common::utils::time::MockTime;
+    use rafka_clients::common::uuid::Uuid;
+    use rafka_raft::local_raft_client::{LocalRaftClient, SharedLog};
+
+    type LocalClient = Arc<Mutex<LocalRaftClient<MetadataRecord>>>;
+
+    fn new_controller(
+        node_id: i32,
+        log: &SharedLog<MetadataRecord>,
+        bootstrap: BootstrapMetadata,
+    ) -> (QuorumController, LocalClient) {
+        let client = Arc::new(Mutex::new(LocalRaftClient::new(node_id, log.clone())));
+        let controller = QuorumControllerBuilder::new(node_id, "cluster", client.clone())
+            .set_bootstrap_metadata(bootstrap)
+            .build();
+        (controller, client)
+    }
+
+    fn poll(client: &LocalClient, controller: &QuorumController) {
+        client.lock().unwrap().poll();
+        controller.wait_for_events();
+    }
+
+    fn bootstrap() -> BootstrapMetadata {
+        let mut records = BootstrapMetadata::from_version(MetadataVersion::Ibp3_7Iv4, "test")
+            .records()
+            .to_vec();
+        records.push(MetadataRecord::Config(ConfigRecord {
+            resource_type: 4,
+            resource_name: "".to_string(),
+            name: "num.io.threads".to_string(),
+            value: Some("8".to_string()),
+        }));
+        BootstrapMetadata::from_records(records, "test").unwrap()
+    }
+
+    #[test]
+    fn test_first_leader_appends_bootstrap_records() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        assert_eq!(None, controller.metadata_version());
+
+        log.elect(0);
+        poll(&client, &controller);
+        assert!(controller.is_active());
+        assert_eq!(2, log.end_offset());
+
+        poll(&client, &controller);
+        assert_eq!(1, controller.last_committed_offset());
+        assert_eq!(
+            Some(MetadataVersion::Ibp3_7Iv4),
+            controller.metadata_version()
+        );
+        controller.close();
+    }
+
+    #[test]
+    fn test_bootstrap_records_are_only_appended_to_an_empty_log() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+
+        // A new epoch on a non-empty log must not bootstrap again.
+        log.elect(0);
+        poll(&client, &controller);
+        assert!(controller.is_active());
+        assert_eq!(2, log.end_offset());
+
+        let (other, other_client) = new_controller(
+            1,
+            &log,
+            BootstrapMetadata::from_version(MetadataVersion::Ibp3_9Iv0, "other"),
+        );
+        log.elect(1);
+        poll(&client, &controller);
+        poll(&other_client, &other);
+        assert!(!controller.is_active());
+        assert!(other.is_active());
+        assert_eq!(2, log.end_offset());
+        assert_eq!(Some(MetadataVersion::Ibp3_7Iv4), other.metadata_version());
+        controller.close();
+        other.close();
+    }
+
+    #[test]
+    fn test_standby_takes_over_when_the_active_controller_is_killed() {
+        let time = Arc::new(MockTime::with_start(0, 0, 0));
+        let log = SharedLog::new();
+        let mut nodes: Vec<_> = (0..3)
+            .map(|node_id| {
+                let client = Arc::new(Mutex::new(LocalRaftClient::new(node_id, log.clone())));
+                let metrics = Metrics::new(MetricConfig::default(), time.clone());
+                let controller = QuorumControllerBuilder::new(node_id, "cluster", client.clone())
+                    .set_bootstrap_metadata(bootstrap())
+                    .set_time(time.clone())
+                    .set_metrics(metrics.clone())
+                    .build();
+                (controller, client, metrics)
+            })
+            .collect();
+        let poll_all = |nodes: &[(QuorumController, LocalClient, Metrics)]| {
+            for (controller, client, _) in nodes {
+                poll(client, controller);
+            }
+        };
+        let metric = |metrics: &Metrics, name: &str| {
+            metrics.metric_value(&metrics.metric_name(name, CONTROLLER_METRICS_GROUP, "", &[]))
+        };
+
+        log.elect(0);
+        poll_all(&nodes);
+        poll_all(&nodes);
+        // The standbys replay the log without being active.
+        for (controller, _, metrics) in &nodes[1..] {
+            assert!(!controller.is_active());
+            assert_eq!(1, controller.last_committed_offset());
+            assert_eq!(Some(1.0), metric(metrics, LAST_APPLIED_RECORD_OFFSET));
+            assert_eq!(
+                Some(MetadataVersion::Ibp3_7Iv4),
+                controller.metadata_version()
+            );
+        }
+        assert_eq!(Some(1.0), metric(&nodes[0].2, ACTIVE_CONTROLLER_COUNT));
+
+        // Kill the active controller. The quorum goes without a leader until the election
+        // timeout expires.
+        let (active, _, _) = nodes.remove(0);
+        active.close();
+        log.crash_leader();
+        poll_all(&nodes);
+        time.sleep(250);
+        log.elect(1);
+        poll_all(&nodes);
+
+        let (controller, _, metrics) = &nodes[0];
+        assert!(controller.is_active());
+        assert_eq!(Some(1.0), metric(metrics, ACTIVE_CONTROLLER_COUNT));
+        assert_eq!(Some(250.0), metric(metrics, CONTROLLER_FAILOVER_TIME_MS));
+        assert_eq!(Some(0.0), metric(&nodes[1].2, ACTIVE_CONTROLLER_COUNT));
+        // The new active controller took over the existing log instead of bootstrapping.
+        assert_eq!(2, log.end_offset());
+        for (controller, _, _) in &nodes {
+            controller.close();
+        }
+    }
+
+    fn registration_request(broker_id: i32, incarnation: i64) -> BrokerRegistrationRequest {
+        BrokerRegistrationRequest {
+            broker_id,
+            cluster_id: "cluster".to_string(),
+            incarnation_id: Uuid::new(1000, incarnation),
+            listeners: vec![],
+            features: BTreeMap::from([(
+                MetadataVersion::FEATURE_NAME.to_string(),
+                VersionRange::new(7, 21),
+            )]),
+            rack: None,
+            log_dirs: vec![Uuid::new(2000, broker_id as i64)],
+            previous_broker_epoch: -1,
+        }
+    }
+
+    #[test]
+    fn test_register_broker() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        let (standby, standby_client) = new_controller(1, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&standby_client, &standby);
+
+        let response = controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        // The reply is only sent once the registration is committed.
+        poll(&client, &controller);
+        // The broker epoch is the offset of the registration, right after the bootstrap records.
+        assert_eq!(
+            Ok(BrokerRegistrationReply { broker_epoch: 2 }),
+            response.wait()
+        );
+
+        let duplicate = controller.register_broker(registration_request(1, 2));
+        assert_eq!(
+            Errors::DuplicateBrokerRegistration,
+            duplicate.wait().unwrap_err().error()
+        );
+        assert_eq!(
+            Errors::NotController,
+            standby
+                .register_broker(registration_request(2, 1))
+                .wait()
+                .unwrap_err()
+                .error()
+        );
+
+        poll(&standby_client, &standby);
+        let registration = standby.broker_registration(1).unwrap();
+        assert!(registration.fenced());
+        assert_eq!(Uuid::new(1000, 1), registration.incarnation_id());
+        assert_eq!(3, log.end_offset());
+        controller.close();
+        standby.close();
+    }
+
+    #[test]
+    fn test_active_controller_resigns_when_it_cannot_append() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        let (standby, standby_client) = new_controller(1, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&standby_client, &standby);
+
+        let pending = controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        client.lock().unwrap().fail_appends("disk error");
+        let failed = controller.register_broker(registration_request(2, 1));
+        assert_eq!(Errors::NotController, failed.wait().unwrap_err().error());
+        // The requests waiting for their records to be committed fail as well.
+        assert_eq!(Errors::NotController, pending.wait().unwrap_err().error());
+        assert!(!controller.is_active());
+        assert!(client.lock().unwrap().is_degraded());
+        assert_eq!(3, log.end_offset());
+
+        // The degraded controller can't be elected again, but keeps replaying the log.
+        log.elect(0);
+        log.elect(1);
+        poll(&client, &controller);
+        poll(&standby_client, &standby);
+        assert!(!controller.is_active());
+        assert!(standby.is_active());
+        assert_eq!(2, controller.last_committed_offset());
+        controller.close();
+        standby.close();
+    }
+
+    #[test]
+    fn test_create_topic_only_places_replicas_on_unfenced_brokers() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        for broker_id in 1..=2 {
+            controller.register_broker(registration_request(broker_id, 1));
+        }
+        controller.wait_for_events();
+        poll(&client, &controller);
+
+        let topic = CreatableTopic {
+            name: "foo".to_string(),
+            num_partitions: 1,
+            replication_factor: 1,
+            configs: BTreeMap::new(),
+            operation_key: None,
+            assignments: BTreeMap::new(),
+            auto_create: false,
+        };
+        // Newly registered brokers are fenced until they caught up.
+        assert_eq!(
+            Errors::InvalidReplicationFactor,
+            controller
+                .create_topic(topic.clone())
+                .wait()
+                .unwrap_err()
+                .error()
+        );
+
+        let broker_epoch = controller.broker_registration(2).unwrap().epoch();
+        {
+            let mut state = controller.state();
+            let result = state
+                .cluster_control
+                .unfence_broker(2, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        }
+        let response = controller.create_topic(topic);
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let topic_id = response.wait().unwrap().topic_id;
+        let state = controller.state();
+        let partition = state.replication_control.partition(topic_id, 0).unwrap();
+        assert_eq!(vec![2], partition.replicas);
+        drop(state);
+        controller.close();
+    }
+
+    #[test]
+    fn test_create_topic_retried_after_failover_is_not_applied_twice() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        let (standby, standby_client) = new_controller(1, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        let response = controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let broker_epoch = response.wait().unwrap().broker_epoch;
+        {
+            let mut state = controller.state();
+            let result = state
+                .cluster_control
+                .unfence_broker(1, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        }
+
+        let topic = CreatableTopic {
+            name: "foo".to_string(),
+            num_partitions: 2,
+            replication_factor: 1,
+            configs: BTreeMap::new(),
+            operation_key: Some("create-foo".to_string()),
+            assignments: BTreeMap::new(),
+            auto_create: false,
+        };
+        // The active controller crashes before answering, so the client retries.
+        let response = controller.create_topic(topic.clone());
+        controller.wait_for_events();
+        controller.close();
+        log.crash_leader();
+        assert_eq!(Errors::NotController, response.wait().unwrap_err().error());
+        log.elect(1);
+        poll(&standby_client, &standby);
+        let end_offset = log.end_offset();
+        let topic_id = standby.state().replication_control.topic_id("foo").unwrap();
+
+        let retry = standby.create_topic(topic.clone()).wait().unwrap();
+        assert_eq!(
+            CreatableTopicResult {
+                name: "foo".to_string(),
+                topic_id,
+                num_partitions: 2,
+                replication_factor: 1,
+            },
+            retry
+        );
+        assert_eq!(end_offset, log.end_offset());
+
+        let other_request = CreatableTopic {
+            num_partitions: 3,
+            ..topic.clone()
+        };
+        assert_eq!(
+            Errors::InvalidRequest,
+            standby
+                .create_topic(other_request)
+                .wait()
+                .unwrap_err()
+                .error()
+        );
+        let without_key = CreatableTopic {
+            operation_key: None,
+            ..topic
+        };
+        assert_eq!(
+            Errors::TopicAlreadyExists,
+            standby
+                .create_topic(without_key)
+                .wait()
+                .unwrap_err()
+                .error()
+        );
+        controller.close();
+        standby.close();
+    }
+
+    #[test]
+    fn test_concurrent_auto_creations_return_the_existing_topic() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        {
+            let mut state = controller.state();
+            let broker_epoch = state.cluster_control.registration(1).unwrap().epoch();
+            let result = state
+                .cluster_control
+                .unfence_broker(1, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        }
+
+        // Two brokers forward the creation of the same topic for their Metadata requests.
+        let topic = CreatableTopic {
+            name: GROUP_METADATA_TOPIC_NAME.to_string(),
+            num_partitions: 2,
+            replication_factor: 1,
+            configs: BTreeMap::new(),
+            operation_key: None,
+            assignments: BTreeMap::new(),
+            auto_create: true,
+        };
+        let first = controller.create_topic(topic.clone());
+        let second = controller.create_topic(CreatableTopic {
+            num_partitions: 1,
+            ..topic.clone()
+        });
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let first = first.wait().unwrap();
+        assert_eq!(first, second.wait().unwrap());
+        assert_eq!(2, first.num_partitions);
+
+        let end_offset = log.end_offset();
+        assert_eq!(
+            first,
+            controller.create_topic(topic.clone()).wait().unwrap()
+        );
+        assert_eq!(end_offset, log.end_offset());
+        let explicit = CreatableTopic {
+            assignments: BTreeMap::new(),
+            auto_create: false,
+            ..topic
+        };
+        assert_eq!(
+            Errors::TopicAlreadyExists,
+            controller
+                .create_topic(explicit)
+                .wait()
+                .unwrap_err()
+                .error()
+        );
+        controller.close();
+    }
+
+    #[test]
+    fn test_large_operation_is_written_as_a_transaction() {
+        let log = SharedLog::new();
+        let new_node = |node_id| {
+            let client = Arc::new(Mutex::new(LocalRaftClient::new(node_id, log.clone())));
+            let controller = QuorumControllerBuilder::new(node_id, "cluster", client.clone())
+                .set_bootstrap_metadata(bootstrap())
+                .set_max_records_per_batch(4)
+                .build();
+            (controller, client)
+        };
+        let (controller, client) = new_node(0);
+        let (standby, standby_client) = new_node(1);
+        let epoch = log.elect(0).epoch();
+        poll(&client, &controller);
+        poll(&client, &controller);
+        controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        {
+            let mut state = controller.state();
+            let broker_epoch = state.cluster_control.registration(1).unwrap().epoch();
+            let result = state
+                .cluster_control
+                .unfence_broker(1, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        }
+
+        // The topic and its 6 partitions are wrapped in a transaction of 3 batches.
+        let end_offset = log.end_offset();
+        let topic = CreatableTopic {
+            name: "foo".to_string(),
+            num_partitions: 6,
+            replication_factor: 1,
+            configs: BTreeMap::new(),
+            operation_key: None,
+            assignments: BTreeMap::new(),
+            auto_create: false,
+        };
+        let response = controller.create_topic(topic.clone());
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let topic_id = response.wait().unwrap().topic_id;
+        assert_eq!(end_offset + 9, log.end_offset());
+        poll(&standby_client, &standby);
+        {
+            let state = standby.state();
+            assert_eq!(Some(topic_id), state.replication_control.topic_id("foo"));
+            assert!(state.replication_control.partition(topic_id, 5).is_some());
+            assert!(!state.transaction.in_transaction());
+        }
+
+        // The active controller fails halfway through the transaction of another topic.
+        client
+            .lock()
+            .unwrap()
+            .schedule_append(
+                epoch,
+                vec![
+                    MetadataRecord::BeginTransaction(BeginTransactionRecord {
+                        name: "create_topic".to_string(),
+                    }),
+                    MetadataRecord::Topic(TopicRecord {
+                        name: "bar".to_string(),
+                        topic_id: Uuid::new(3, 3),
+                    }),
+                ],
+            )
+            .unwrap();
+        controller.close();
+        log.crash_leader();
+        poll(&standby_client, &standby);
+        assert_eq!(None, standby.state().replication_control.topic_id("bar"));
+
+        // Its successor aborts the transaction before writing anything else.
+        log.elect(1);
+        poll(&standby_client, &standby);
+        assert!(standby.is_active());
+        assert!(!standby.state().transaction.in_transaction());
+        let response = standby.create_topic(CreatableTopic {
+            name: "bar".to_string(),
+            num_partitions: 1,
+            ..topic
+        });
+        standby.wait_for_events();
+        poll(&standby_client, &standby);
+        let bar = response.wait().unwrap();
+        assert_ne!(Uuid::new(3, 3), bar.topic_id);
+
+        let (replica, replica_client) = new_node(2);
+        poll(&replica_client, &replica);
+        assert_eq!(
+            Some(bar.topic_id),
+            replica.state().replication_control.topic_id("bar")
+        );
+        standby.close();
+        replica.close();
+    }
+
+    #[test]
+    fn test_forwarded_create_topic_is_bounded_by_the_client_timeout() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        {
+            let mut state = controller.state();
+            let broker_epoch = state.cluster_control.registration(1).unwrap().epoch();
+            let result = state
+                .cluster_control
+                .unfence_broker(1, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        }
+        let topic = CreatableTopic {
+            name: "foo".to_string(),
+            num_partitions: 1,
+            replication_factor: 1,
+            configs: BTreeMap::new(),
+            operation_key: None,
+            assignments: BTreeMap::new(),
+            auto_create: false,
+        };
+
+        // The client gave up before the controller got to the request.
+        let end_offset = log.end_offset();
+        let response = controller.create_topic_within(topic.clone(), 0);
+        assert_eq!(
+            Errors::RequestTimedOut,
+            response.wait().unwrap_err().error()
+        );
+        assert_eq!(end_offset, log.end_offset());
+
+        // The records are not committed in time.
+        let response = controller.create_topic_within(topic, 30_000);
+        controller.wait_for_events();
+        assert_eq!(
+            Errors::RequestTimedOut,
+            response
+                .wait_timeout(Duration::from_millis(10))
+                .unwrap_err()
+                .error()
+        );
+        controller.close();
+    }
+
+    #[test]
+    fn test_heartbeat_unfences_a_caught_up_broker() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        let response = controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let broker_epoch = response.wait().unwrap().broker_epoch;
+
+        let mut request = BrokerHeartbeatRequest {
+            broker_id: 1,
+            broker_epoch,
+            current_metadata_offset: broker_epoch,
+            want_fence: true,
+            log_dirs: vec![],
+        };
+        let reply = controller
+            .process_broker_heartbeat(request.clone())
+            .wait()
+            .unwrap();
+        assert!(reply.is_fenced);
+
+        request.want_fence = false;
+        let response = controller.process_broker_heartbeat(request);
+        controller.wait_for_events();
+        poll(&client, &controller);
+        assert_eq!(
+            Ok(BrokerHeartbeatReply {
+                is_caught_up: true,
+                is_fenced: false
+            }),
+            response.wait()
+        );
+        assert!(!controller.broker_registration(1).unwrap().fenced());
+        controller.close();
+    }
+
+    #[test]
+    fn test_unregister_broker_without_replicas() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        for broker_id in 1..=2 {
+            controller.register_broker(registration_request(broker_id, 1));
+        }
+        controller.wait_for_events();
+        poll(&client, &controller);
+        {
+            let mut state = controller.state();
+            let broker_epoch = state.cluster_control.registration(1).unwrap().epoch();
+            let result = state
+                .cluster_control
+                .unfence_broker(1, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        }
+        let response = controller.create_topic(CreatableTopic {
+            name: "foo".to_string(),
+            num_partitions: 2,
+            replication_factor: 1,
+            configs: BTreeMap::new(),
+            operation_key: None,
+            assignments: BTreeMap::new(),
+            auto_create: false,
+        });
+        controller.wait_for_events();
+        poll(&client, &controller);
+        response.wait().unwrap();
+
+        let error = controller.unregister_broker(1).wait().unwrap_err();
+        assert_eq!(Errors::InvalidRequest, error.error());
+        assert!(error.to_string().contains("foo-0, foo-1"));
+        assert_eq!(
+            Errors::BrokerIdNotRegistered,
+            controller.unregister_broker(3).wait().unwrap_err().error()
+        );
+
+        let response = controller.unregister_broker(2);
+        controller.wait_for_events();
+        poll(&client, &controller);
+        assert_eq!(Ok(()), response.wait());
+        assert!(controller.broker_registration(2).is_none());
+        assert!(controller.broker_registration(1).is_some());
+        assert_eq!(
+            Errors::BrokerIdNotRegistered,
+            controller.unregister_broker(2).wait().unwrap_err().error()
+        );
+        controller.close();
+    }
+
+    #[test]
+    fn test_requests_of_a_zombie_broker_are_fenced() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        let unfence = |broker_epoch| {
+            let mut state = controller.state();
+            let result = state
+                .cluster_control
+                .unfence_broker(1, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        };
+        let response = controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let old_epoch = response.wait().unwrap().broker_epoch;
+        unfence(old_epoch);
+        let response = controller.create_topic(CreatableTopic {
+            name: "foo".to_string(),
+            num_partitions: 1,
+            replication_factor: 1,
+            configs: BTreeMap::new(),
+            operation_key: None,
+            assignments: BTreeMap::new(),
+            auto_create: false,
+        });
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let topic_id = response.wait().unwrap().topic_id;
+
+        // The broker restarts cleanly and registers again, with a new epoch.
+        let response = controller.register_broker(BrokerRegistrationRequest {
+            previous_broker_epoch: old_epoch,
+            ..registration_request(1, 2)
+        });
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let new_epoch = response.wait().unwrap().broker_epoch;
+        assert!(new_epoch > old_epoch);
+        unfence(new_epoch);
+
+        let alter_partition = |broker_epoch| AlterPartitionRequest {
+            broker_id: 1,
+            broker_epoch,
+            topic_id,
+            partition_id: 0,
+            leader_epoch: 0,
+            partition_epoch: 0,
+            new_isr: vec![1],
+        };
+        // The requests of the previous incarnation are rejected.
+        assert_eq!(
+            Errors::StaleBrokerEpoch,
+            controller
+                .alter_partition(alter_partition(old_epoch))
+                .wait()
+                .unwrap_err()
+                .error()
+        );
+        assert_eq!(
+            Errors::StaleBrokerEpoch,
+            controller
+                .begin_controlled_shutdown(1, old_epoch)
+                .wait()
+                .unwrap_err()
+                .error()
+        );
+        assert!(
+            !controller
+                .broker_registration(1)
+                .unwrap()
+                .in_controlled_shutdown()
+        );
+
+        let response = controller.alter_partition(alter_partition(new_epoch));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        assert_eq!(1, response.wait().unwrap().partition_epoch);
+        let response = controller.begin_controlled_shutdown(1, new_epoch);
+        controller.wait_for_events();
+        poll(&client, &controller);
+        assert_eq!(Ok(()), response.wait());
+        assert!(
+            controller
+                .broker_registration(1)
+                .unwrap()
+                .in_controlled_shutdown()
+        );
+        controller.close();
+    }
+
+    #[test]
+    fn test_brokers_must_support_the_finalized_kraft_version() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        let epoch = log.leader_and_epoch().epoch();
+        client
+            .lock()
+            .unwrap()
+            .upgrade_kraft_version(epoch, KRaftVersion::Kraft1, false)
+            .unwrap();
+        assert_eq!(
+            Some(&1),
+            controller
+                .finalized_features()
+                .get(KRaftVersion::FEATURE_NAME)
+        );
+
+        let mut request = registration_request(1, 1);
+        assert_eq!(
+            Errors::UnsupportedVersion,
+            controller
+                .register_broker(request.clone())
+                .wait()
+                .unwrap_err()
+                .error()
+        );
+        request.features.insert(
+            KRaftVersion::FEATURE_NAME.to_string(),
+            VersionRange::new(0, 1),
+        );
+        let response = controller.register_broker(request);
+        controller.wait_for_events();
+        poll(&client, &controller);
+        assert!(response.wait().is_ok());
+        controller.close();
+    }
+
+    #[test]
+    fn test_pending_registration_fails_when_the_controller_shuts_down() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+
+        let response = controller.register_broker(registration_request(1, 1));
+        controller.close();
+        assert_eq!(Errors::NotController, response.wait().unwrap_err().error());
+    }
+
+    #[test]
+    fn test_alter_client_quotas() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+
+        let ip = ClientQuotaEntity::of(IP, Some("10.0.0.1"));
+        let host = ClientQuotaEntity::of(IP, Some("localhost"));
+        let rate = |value| vec![Op::new("connection_creation_rate", Some(value))];
+        let response = controller.alter_client_quotas(vec![
+            ClientQuotaAlteration::new(ip.clone(), rate(5.0)),
+            ClientQuotaAlteration::new(host.clone(), rate(5.0)),
+        ]);
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let results = response.wait().unwrap();
+        assert!(results[&ip].is_success());
+        assert_eq!(Errors::InvalidRequest, results[&host].error());
+        // The two bootstrap records and the quota of the valid entity.
+        assert_eq!(3, log.end_offset());
+        controller.close();
+    }
+
+    #[test]
+    fn test_batched_operations_fail_per_topic() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        {
+            let mut state = controller.state();
+            let broker_epoch = state.cluster_control.registration(1).unwrap().epoch();
+            let result = state
+                .cluster_control
+                .unfence_broker(1, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        }
+
+        let topic = |name: &str, replication_factor| CreatableTopic {
+            name: name.to_string(),
+            num_partitions: 1,
+            replication_factor,
+            configs: BTreeMap::new(),
+            operation_key: None,
+            assignments: BTreeMap::new(),
+            auto_create: false,
+        };
+        let response = controller.create_topics(vec![
+            topic("foo", 1),
+            topic("bar", 3),
+            topic("baz", 1),
+            topic("baz", 1),
+        ]);
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let results = response.wait().unwrap();
+        let topic_id = results["foo"].as_ref().unwrap().topic_id;
+        assert_eq!(
+            Errors::InvalidReplicationFactor,
+            results["bar"].as_ref().unwrap_err().error()
+        );
+        assert_eq!(
+            Errors::InvalidRequest,
+            results["baz"].as_ref().unwrap_err().error()
+        );
+        assert_eq!(
+            Some(topic_id),
+            controller.state().replication_control.topic_id("foo")
+        );
+        assert_eq!(None, controller.state().replication_control.topic_id("baz"));
+
+        let retention = vec![AlterConfigOp::Set {
+            name: "retention.ms".to_string(),
+            value: "1000".to_string(),
+        }];
+        let response = controller.incremental_alter_configs(BTreeMap::from([
+            ("foo".to_string(), retention.clone()),
+            ("bar".to_string(), retention),
+        ]));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        let results = response.wait().unwrap();
+        assert!(results["foo"].is_success());
+        assert_eq!(Errors::UnknownTopicOrPartition, results["bar"].error());
+        assert_eq!(
+            "1000",
+            controller
+                .state()
+                .configuration_control
+                .topic_configs("foo")
+                .unwrap()["retention.ms"]
+        );
+        controller.close();
+    }
+
+    #[test]
+    fn test_internal_topic_configs_are_enforced() {
+        let log = SharedLog::new();
+        let (controller, client) = new_controller(0, &log, bootstrap());
+        log.elect(0);
+        poll(&client, &controller);
+        poll(&client, &controller);
+        controller.register_broker(registration_request(1, 1));
+        controller.wait_for_events();
+        poll(&client, &controller);
+        {
+            let mut state = controller.state();
+            let broker_epoch = state.cluster_control.registration(1).unwrap().epoch();
+            let result = state
+                .cluster_control
+                .unfence_broker(1, broker_epoch)
+                .unwrap();
+            state
+                .write("unfence_broker", result.into_parts().0)
+                .unwrap();
+        }
+
+        let topic = CreatableTopic {
+            name: GROUP_METADATA_TOPIC_NAME.to_string(),
+            num_partitions: 1,
+            replication_factor: 1,
+            configs: BTreeMap::new(),
+            operation_key: None,
+            assignments: BTreeMap::new(),
+            auto_create: false,
+        };
+        let response = controller.create_topic(topic);
+        controller.wait_for_events();
+        poll(&client, &controller);
+        response.wait().unwrap();
+        assert_eq!(
+            "compact",
+            controller
+                .state()
+                .configuration_control
+                .topic_configs(GROUP_METADATA_TOPIC_NAME)
+                .unwrap()["cleanup.policy"]
+        );
+
+        let delete = AlterConfigOp::Set {
+            name: "cleanup.policy".to_string(),
+            value: "delete".to_string(),
+        };
+        let error = controller
+            .incremental_alter_topic_configs(GROUP_METADATA_TOPIC_NAME.to_string(), vec![delete])
+            .wait()
+            .unwrap_err();
+        assert_eq!(Errors::InvalidConfig, error.error());
+        let error = controller
+            .incremental_alter_topic_configs("bar".to_string(), vec![])
+            .wait()
+            .unwrap_err();
+        assert_eq!(Errors::UnknownTopicOrPartition, error.error());
+        controller.close();
+    }
+}
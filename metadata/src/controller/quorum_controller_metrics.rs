@@ -0,0 +1,109 @@
+use rafka_clients::common::metrics::stats::{Max, Value};
+use rafka_clients::common::metrics::{Metrics, Sensor};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+pub const CONTROLLER_METRICS_GROUP: &str = "controller-metrics";
+
+pub const ACTIVE_CONTROLLER_COUNT: &str = "active-controller-count";
+pub const LAST_APPLIED_RECORD_OFFSET: &str = "last-applied-record-offset";
+pub const LAST_APPLIED_RECORD_LAG_MS: &str = "last-applied-record-lag-ms";
+pub const CONTROLLER_FAILOVER_TIME_MS: &str = "controller-failover-time-ms";
+pub const CONTROLLER_FAILOVER_TIME_MS_MAX: &str = "controller-failover-time-ms-max";
+
+/// The metrics of a [QuorumController](super::quorum_controller::QuorumController).
+///
+/// Standby controllers report how far they have replayed the metadata log, so an operator
+/// can tell whether a failover would be quick, and a controller which becomes active
+/// records how long the cluster went without one.
+pub struct QuorumControllerMetrics {
+    active: Arc<AtomicBool>,
+    last_applied_record_offset: Arc<AtomicI64>,
+    last_applied_record_timestamp: Arc<AtomicI64>,
+    failover_time: Arc<Sensor>,
+}
+
+impl QuorumControllerMetrics {
+    pub fn new(metrics: &Metrics) -> Self {
+        let active = Arc::new(AtomicBool::new(false));
+        let last_applied_record_offset = Arc::new(AtomicI64::new(-1));
+        let last_applied_record_timestamp = Arc::new(AtomicI64::new(-1));
+
+        let gauge = Arc::clone(&active);
+        metrics.add_gauge(
+            metrics.metric_name(
+                ACTIVE_CONTROLLER_COUNT,
+                CONTROLLER_METRICS_GROUP,
+                "1 if this node is the active controller, 0 otherwise",
+                &[],
+            ),
+            move |_| gauge.load(Ordering::Relaxed) as i64 as f64,
+        );
+        let gauge = Arc::clone(&last_applied_record_offset);
+        metrics.add_gauge(
+            metrics.metric_name(
+                LAST_APPLIED_RECORD_OFFSET,
+                CONTROLLER_METRICS_GROUP,
+                "The offset of the last record replayed from the metadata log",
+                &[],
+            ),
+            move |_| gauge.load(Ordering::Relaxed) as f64,
+        );
+        let gauge = Arc::clone(&last_applied_record_timestamp);
+        metrics.add_gauge(
+            metrics.metric_name(
+                LAST_APPLIED_RECORD_LAG_MS,
+                CONTROLLER_METRICS_GROUP,
+                "The time since the last replayed record was appended to the metadata log",
+                &[],
+            ),
+            move |now_ms| match gauge.load(Ordering::Relaxed) {
+                -1 => 0.0,
+                timestamp => (now_ms - timestamp).max(0) as f64,
+            },
+        );
+
+        let failover_time = metrics.sensor("controller-failover-time");
+        failover_time.add(
+            metrics.metric_name(
+                CONTROLLER_FAILOVER_TIME_MS,
+                CONTROLLER_METRICS_GROUP,
+                "The time the cluster went without an active controller before this node \
+                became active the last time",
+                &[],
+            ),
+            Value::new(),
+        );
+        failover_time.add(
+            metrics.metric_name(
+                CONTROLLER_FAILOVER_TIME_MS_MAX,
+                CONTROLLER_METRICS_GROUP,
+                "The maximum failover time over the sampled window",
+                &[],
+            ),
+            Max::new(),
+        );
+
+        Self {
+            active,
+            last_applied_record_offset,
+            last_applied_record_timestamp,
+            failover_time,
+        }
+    }
+
+    pub fn set_active(&self, active: bool) {
+        self.active.store(active, Ordering::Relaxed);
+    }
+
+    pub fn set_last_applied_record(&self, offset: i64, append_timestamp: i64) {
+        self.last_applied_record_offset
+            .store(offset, Ordering::Relaxed);
+        self.last_applied_record_timestamp
+            .store(append_timestamp, Ordering::Relaxed);
+    }
+
+    pub fn record_failover_time(&self, failover_time_ms: f64) {
+        self.failover_time.record(failover_time_ms);
+    }
+}
@@ -0,0 +1,130 @@
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use std::collections::BTreeMap;
+
+/// A broker which can receive new replicas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsableBroker {
+    pub id: i32,
+    pub rack: Option<String>,
+}
+
+/// Places the replicas of new partitions on the usable brokers.
+///
+/// Brokers are interleaved by rack and the replicas of each partition are taken from
+/// consecutive positions of that list, so the replicas of a partition span as many racks as
+/// possible and consecutive partitions get different leaders.
+#[derive(Debug, Default)]
+pub struct StripedReplicaPlacer;
+
+impl StripedReplicaPlacer {
+    /// Returns the replicas of `num_partitions` partitions with `replication_factor` replicas
+    /// each, starting from position `offset` of the striped broker list.
+    pub fn place(
+        &self,
+        num_partitions: i32,
+        replication_factor: i16,
+        brokers: &[UsableBroker],
+        offset: usize,
+    ) -> Result<Vec<Vec<i32>>, ApiError> {
+        if replication_factor < 1 {
+            return Err(ApiError::new(
+                Errors::InvalidReplicationFactor,
+                format!(
+                    "Invalid replication factor {replication_factor}: the replication factor must be positive"
+                ),
+            ));
+        }
+        if brokers.is_empty() {
+            return Err(ApiError::new(
+                Errors::InvalidReplicationFactor,
+                "All brokers are currently fenced or in controlled shutdown",
+            ));
+        }
+        if replication_factor as usize > brokers.len() {
+            return Err(ApiError::new(
+                Errors::InvalidReplicationFactor,
+                format!(
+                    "The target replication factor of {replication_factor} cannot be reached \
+                    because only {} broker(s) are registered and available",
+                    brokers.len()
+                ),
+            ));
+        }
+        let striped = Self::stripe(brokers);
+        Ok((0..num_partitions as usize)
+            .map(|partition| {
+                (0..replication_factor as usize)
+                    .map(|replica| striped[(offset + partition + replica) % striped.len()])
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Orders the brokers by taking one broker of each rack in turn.
+    fn stripe(brokers: &[UsableBroker]) -> Vec<i32> {
+        let mut racks: BTreeMap<Option<&str>, Vec<i32>> = BTreeMap::new();
+        for broker in brokers {
+            racks
+                .entry(broker.rack.as_deref())
+                .or_default()
+                .push(broker.id);
+        }
+        for ids in racks.values_mut() {
+            ids.sort();
+        }
+        let rounds = racks.values().map(Vec::len).max().unwrap_or(0);
+        (0..rounds)
+            .flat_map(|round| {
+                racks
+                    .values()
+                    .filter_map(move |ids| ids.get(round).copied())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn broker(id: i32, rack: &str) -> UsableBroker {
+        UsableBroker {
+            id,
+            rack: Some(rack.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_replicas_span_racks() {
+        let brokers = [
+            broker(1, "a"),
+            broker(2, "a"),
+            broker(3, "b"),
+            broker(4, "b"),
+            broker(5, "c"),
+        ];
+        let placement = StripedReplicaPlacer.place(5, 3, &brokers, 0).unwrap();
+        assert_eq!(vec![1, 3, 5], placement[0]);
+        for replicas in &placement {
+            assert_eq!(3, replicas.iter().collect::<HashSet<_>>().len());
+        }
+        // Every broker leads one partition.
+        let leaders: HashSet<_> = placement.iter().map(|r| r[0]).collect();
+        assert_eq!(5, leaders.len());
+    }
+
+    #[test]
+    fn test_not_enough_brokers() {
+        let brokers = [broker(1, "a"), broker(2, "b")];
+        for (replication_factor, brokers) in [(3, &brokers[..]), (0, &brokers[..]), (1, &[][..])] {
+            assert_eq!(
+                Errors::InvalidReplicationFactor,
+                StripedReplicaPlacer
+                    .place(1, replication_factor, brokers, 0)
+                    .unwrap_err()
+                    .error()
+            );
+        }
+    }
+}
@@ -0,0 +1,725 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::records::{PartitionRecord, TopicRecord};
+use crate::controller::controller_result::ControllerResult;
+use crate::controller::operation_control_manager::request_hash;
+use crate::controller::replica_placement::{StripedReplicaPlacer, UsableBroker};
+use crate::metadata::partition_registration::PartitionRegistration;
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_clients::common::uuid::Uuid;
+use std::collections::{BTreeMap, HashMap};
+use tracing::info;
+
+const MAX_TOPIC_NAME_LENGTH: usize = 249;
+
+/// The `num_partitions` of a topic created with the default number of partitions, or with a
+/// manual assignment.
+pub const NO_NUM_PARTITIONS: i32 = -1;
+/// The `replication_factor` of a topic created with the default replication factor, or with
+/// a manual assignment.
+pub const NO_REPLICATION_FACTOR: i16 = -1;
+
+/// The default `num.partitions`.
+pub const DEFAULT_NUM_PARTITIONS: i32 = 1;
+/// The default `default.replication.factor`.
+pub const DEFAULT_REPLICATION_FACTOR: i16 = 1;
+
+/// A topic to create.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatableTopic {
+    pub name: String,
+    /// The number of partitions, or [NO_NUM_PARTITIONS] for the default.
+    pub num_partitions: i32,
+    /// The replication factor, or [NO_REPLICATION_FACTOR] for the default.
+    pub replication_factor: i16,
+    /// The replicas of each partition, if they are assigned manually rather than placed by
+    /// the controller, in which case the number of partitions and the replication factor are
+    /// not set.
+    pub assignments: BTreeMap<i32, Vec<i32>>,
+    /// The configs the topic is created with.
+    pub configs: BTreeMap<String, String>,
+    /// The key making the creation idempotent, kept by the client across retries, see
+    /// [OperationControlManager](crate::controller::operation_control_manager::OperationControlManager).
+    pub operation_key: Option<String>,
+    /// Whether a broker creates the topic because a client asked for it while it did not
+    /// exist, e.g. in a Metadata or FindCoordinator request. Several brokers may race to
+    /// create the same topic, so the existing topic is returned instead of failing with
+    /// `TopicAlreadyExists`.
+    pub auto_create: bool,
+}
+
+impl CreatableTopic {
+    /// A fingerprint of the topic to create, telling a retry from another request.
+    pub fn request_hash(&self) -> i64 {
+        let mut request = format!(
+            "{}/{}/{}/{:?}",
+            self.name, self.num_partitions, self.replication_factor, self.configs
+        );
+        if !self.assignments.is_empty() {
+            request.push_str(&format!("/{:?}", self.assignments));
+        }
+        request_hash(&request)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatableTopicResult {
+    pub name: String,
+    pub topic_id: Uuid,
+    pub num_partitions: i32,
+    pub replication_factor: i16,
+}
+
+/// A leader asking to change the ISR of one of its partitions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlterPartitionRequest {
+    pub broker_id: i32,
+    /// The epoch of the registration of the leader, checked by the controller so that a
+    /// zombie incarnation of the broker, which lost its session, can't change the ISR.
+    pub broker_epoch: i64,
+    pub topic_id: Uuid,
+    pub partition_id: i32,
+    pub leader_epoch: i32,
+    /// The partition epoch the leader last knew, so that concurrent changes are detected.
+    pub partition_epoch: i32,
+    pub new_isr: Vec<i32>,
+}
+
+struct TopicControlInfo {
+    name: String,
+    parts: BTreeMap<i32, PartitionRegistration>,
+}
+
+/// Tracks the topics and partitions of the cluster.
+pub struct ReplicationControlManager {
+    placer: StripedReplicaPlacer,
+    default_num_partitions: i32,
+    default_replication_factor: i16,
+    topics: HashMap<Uuid, TopicControlInfo>,
+    topics_by_name: HashMap<String, Uuid>,
+}
+
+impl Default for ReplicationControlManager {
+    fn default() -> Self {
+        Self {
+            placer: StripedReplicaPlacer,
+            default_num_partitions: DEFAULT_NUM_PARTITIONS,
+            default_replication_factor: DEFAULT_REPLICATION_FACTOR,
+            topics: HashMap::new(),
+            topics_by_name: HashMap::new(),
+        }
+    }
+}
+
+impl ReplicationControlManager {
+    /// Sets the number of partitions and the replication factor of the topics created
+    /// without them: `num.partitions` and `default.replication.factor`.
+    pub fn with_topic_defaults(mut self, num_partitions: i32, replication_factor: i16) -> Self {
+        self.default_num_partitions = num_partitions;
+        self.default_replication_factor = replication_factor;
+        self
+    }
+
+    pub fn topic_id(&self, name: &str) -> Option<Uuid> {
+        self.topics_by_name.get(name).copied()
+    }
+
+    pub fn partition(&self, topic_id: Uuid, partition_id: i32) -> Option<&PartitionRegistration> {
+        self.topics.get(&topic_id)?.parts.get(&partition_id)
+    }
+
+    /// The partitions with a replica on `broker_id`, sorted.
+    pub fn partitions_on_broker(&self, broker_id: i32) -> Vec<TopicPartition> {
+        let mut partitions: Vec<_> = self
+            .topics
+            .values()
+            .flat_map(|topic| {
+                topic
+                    .parts
+                    .iter()
+                    .filter(|(_, part)| part.replicas.contains(&broker_id))
+                    .map(|(partition_id, _)| TopicPartition::new(&topic.name, *partition_id))
+            })
+            .collect();
+        partitions.sort();
+        partitions
+    }
+
+    /// The topic `name` as a result of its creation, or `None` if it does not exist.
+    pub fn created_topic(&self, name: &str) -> Option<CreatableTopicResult> {
+        let topic_id = self.topic_id(name)?;
+        let parts = &self.topics.get(&topic_id)?.parts;
+        Some(CreatableTopicResult {
+            name: name.to_string(),
+            topic_id,
+            num_partitions: parts.len() as i32,
+            replication_factor: parts
+                .values()
+                .next()
+                .map_or(0, |part| part.replicas.len() as i16),
+        })
+    }
+
+    /// Returns the records creating a topic, with its replicas placed on `usable_brokers` or
+    /// assigned manually to some of them. Every replica starts out in the ISR and the first
+    /// one leads the partition.
+    ///
+    /// Fails with `InvalidPartitions` or `InvalidReplicationFactor` if the number of
+    /// partitions or the replication factor is neither positive nor left to the default, or
+    /// the replication factor exceeds the number of usable brokers, with
+    /// `InvalidReplicaAssignment` if a manual assignment is inconsistent, and with
+    /// `InvalidRequest` if both are set.
+    pub fn create_topic(
+        &self,
+        topic: &CreatableTopic,
+        usable_brokers: &[UsableBroker],
+    ) -> Result<ControllerResult<CreatableTopicResult>, ApiError> {
+        validate_topic_name(&topic.name)?;
+        if self.topics_by_name.contains_key(&topic.name) {
+            return Err(ApiError::new(
+                Errors::TopicAlreadyExists,
+                format!("Topic '{}' already exists.", topic.name),
+            ));
+        }
+        let placement = match topic.assignments.is_empty() {
+            true => self.place_replicas(topic, usable_brokers)?,
+            false => manual_placement(topic, usable_brokers)?,
+        };
+        let num_partitions = placement.len() as i32;
+        let replication_factor = placement[0].len() as i16;
+
+        let topic_id = Uuid::random();
+        let mut records = vec![MetadataRecord::Topic(TopicRecord {
+            name: topic.name.clone(),
+            topic_id,
+        })];
+        records.extend(
+            placement
+                .into_iter()
+                .enumerate()
+                .map(|(partition_id, replicas)| {
+                    MetadataRecord::Partition(PartitionRecord {
+                        partition_id: partition_id as i32,
+                        topic_id,
+                        isr: replicas.clone(),
+                        leader: replicas[0],
+                        replicas,
+                        leader_epoch: 0,
+                        partition_epoch: 0,
+                    })
+                }),
+        );
+        Ok(ControllerResult::new(
+            records,
+            CreatableTopicResult {
+                name: topic.name.clone(),
+                topic_id,
+                num_partitions,
+                replication_factor,
+            },
+        ))
+    }
+
+    /// Places the replicas of `topic` on `usable_brokers`, with the default number of
+    /// partitions and replication factor unless the topic sets them.
+    fn place_replicas(
+        &self,
+        topic: &CreatableTopic,
+        usable_brokers: &[UsableBroker],
+    ) -> Result<Vec<Vec<i32>>, ApiError> {
+        let num_partitions = match topic.num_partitions {
+            NO_NUM_PARTITIONS => self.default_num_partitions,
+            n if n < 1 => {
+                return Err(ApiError::new(
+                    Errors::InvalidPartitions,
+                    format!("Number of partitions was set to an invalid non-positive value {n}."),
+                ));
+            }
+            n => n,
+        };
+        let replication_factor = match topic.replication_factor {
+            NO_REPLICATION_FACTOR => self.default_replication_factor,
+            n if n < 1 => {
+                return Err(ApiError::new(
+                    Errors::InvalidReplicationFactor,
+                    format!(
+                        "Replication factor was set to an invalid non-positive value {n}: it must \
+                        be larger than 0, or -1 to use the default value."
+                    ),
+                ));
+            }
+            n => n,
+        };
+        let offset = match usable_brokers.len() {
+            0 => 0,
+            len => rand::random_range(0..len),
+        };
+        self.placer
+            .place(num_partitions, replication_factor, usable_brokers, offset)
+    }
+
+    /// Returns the record changing the ISR of a partition as its leader asks, replying with
+    /// the new registration of the partition. The broker epoch of the leader must have been
+    /// checked by the caller, `is_eligible` tells whether a broker may join the ISR.
+    ///
+    /// Fails with `UnknownTopicId` or `UnknownTopicOrPartition` if the partition does not
+    /// exist, `NotLeaderOrFollower` if the broker does not lead it, `FencedLeaderEpoch` or
+    /// `InvalidUpdateVersion` if the leader missed a change of the partition, and with
+    /// `IneligibleReplica` or `InvalidRequest` if the new ISR is not valid.
+    pub fn alter_partition(
+        &self,
+        request: &AlterPartitionRequest,
+        is_eligible: impl Fn(i32) -> bool,
+    ) -> Result<ControllerResult<PartitionRegistration>, ApiError> {
+        let topic = self.topics.get(&request.topic_id).ok_or_else(|| {
+            ApiError::new(
+                Errors::UnknownTopicId,
+                format!("Unknown topic ID {}", request.topic_id),
+            )
+        })?;
+        let partition = topic.parts.get(&request.partition_id).ok_or_else(|| {
+            ApiError::new(
+                Errors::UnknownTopicOrPartition,
+                format!("Unknown partition {}-{}", topic.name, request.partition_id),
+            )
+        })?;
+        let name = format!("{}-{}", topic.name, request.partition_id);
+        if partition.leader != request.broker_id {
+            return Err(ApiError::new(
+                Errors::NotLeaderOrFollower,
+                format!(
+                    "Broker {} is not the leader of {name}, broker {} is",
+                    request.broker_id, partition.leader
+                ),
+            ));
+        }
+        if partition.leader_epoch != request.leader_epoch {
+            return Err(ApiError::new(
+                Errors::FencedLeaderEpoch,
+                format!(
+                    "Expected leader epoch {} of {name}, but got {}",
+                    partition.leader_epoch, request.leader_epoch
+                ),
+            ));
+        }
+        if partition.partition_epoch != request.partition_epoch {
+            return Err(ApiError::new(
+                Errors::InvalidUpdateVersion,
+                format!(
+                    "Expected partition epoch {} of {name}, but got {}",
+                    partition.partition_epoch, request.partition_epoch
+                ),
+            ));
+        }
+        if !request.new_isr.contains(&request.broker_id)
+            || request
+                .new_isr
+                .iter()
+                .any(|replica| !partition.replicas.contains(replica))
+        {
+            return Err(ApiError::new(
+                Errors::InvalidRequest,
+                format!(
+                    "The ISR {:?} of {name} must hold the leader and only replicas of {:?}",
+                    request.new_isr, partition.replicas
+                ),
+            ));
+        }
+        if let Some(replica) = request
+            .new_isr
+            .iter()
+            .find(|replica| !partition.isr.contains(replica) && !is_eligible(**replica))
+        {
+            return Err(ApiError::new(
+                Errors::IneligibleReplica,
+                format!(
+                    "Replica {replica} of {name} can't join the ISR, it is fenced, in \
+                    controlled shutdown or not registered"
+                ),
+            ));
+        }
+        let record = PartitionRecord {
+            partition_id: request.partition_id,
+            topic_id: request.topic_id,
+            replicas: partition.replicas.clone(),
+            isr: request.new_isr.clone(),
+            leader: partition.leader,
+            leader_epoch: partition.leader_epoch,
+            partition_epoch: partition.partition_epoch + 1,
+        };
+        let registration = PartitionRegistration::from_record(&record);
+        Ok(ControllerResult::new(
+            vec![MetadataRecord::Partition(record)],
+            registration,
+        ))
+    }
+
+    pub fn replay_topic(&mut self, record: &TopicRecord) {
+        info!(
+            "Replayed TopicRecord for topic {} with topic ID {}",
+            record.name, record.topic_id
+        );
+        self.topics_by_name
+            .insert(record.name.clone(), record.topic_id);
+        self.topics.insert(
+            record.topic_id,
+            TopicControlInfo {
+                name: record.name.clone(),
+                parts: BTreeMap::new(),
+            },
+        );
+    }
+
+    pub fn replay_partition(&mut self, record: &PartitionRecord) {
+        let Some(topic) = self.topics.get_mut(&record.topic_id) else {
+            info!(
+                "Ignoring PartitionRecord for unknown topic ID {}",
+                record.topic_id
+            );
+            return;
+        };
+        info!(
+            "Replayed PartitionRecord for partition {}-{} with replicas {:?} and leader {}",
+            topic.name, record.partition_id, record.replicas, record.leader
+        );
+        topic.parts.insert(
+            record.partition_id,
+            PartitionRegistration::from_record(record),
+        );
+    }
+}
+
+/// The replicas of the partitions of a topic assigned manually, checked to be consistent: the
+/// partitions numbered from 0 without gaps, all with the same number of distinct replicas, on
+/// usable brokers.
+fn manual_placement(
+    topic: &CreatableTopic,
+    usable_brokers: &[UsableBroker],
+) -> Result<Vec<Vec<i32>>, ApiError> {
+    let invalid = |message: String| ApiError::new(Errors::InvalidReplicaAssignment, message);
+    if topic.num_partitions != NO_NUM_PARTITIONS
+        || topic.replication_factor != NO_REPLICATION_FACTOR
+    {
+        return Err(ApiError::new(
+            Errors::InvalidRequest,
+            "Both numPartitions or replicationFactor and replicasAssignments were set. Both \
+            cannot be used at the same time.",
+        ));
+    }
+    let mut placement: Vec<Vec<i32>> = Vec::with_capacity(topic.assignments.len());
+    for (expected, (partition_id, replicas)) in topic.assignments.iter().enumerate() {
+        if *partition_id != expected as i32 {
+            return Err(invalid(format!(
+                "Partitions should be a consecutive 0-based integer sequence, but partition \
+                {expected} is missing and partition {partition_id} is assigned."
+            )));
+        }
+        if replicas.is_empty() {
+            return Err(invalid(format!(
+                "The manual partition assignment of partition {partition_id} is empty."
+            )));
+        }
+        if let Some(first) = placement.first()
+            && first.len() != replicas.len()
+        {
+            return Err(invalid(format!(
+                "The manual partition assignment of partition {partition_id} has {} replica(s), \
+                but the previous partitions have {}.",
+                replicas.len(),
+                first.len()
+            )));
+        }
+        for (i, replica) in replicas.iter().enumerate() {
+            if replicas[..i].contains(replica) {
+                return Err(invalid(format!(
+                    "The manual partition assignment of partition {partition_id} includes \
+                    broker {replica} more than once."
+                )));
+            }
+            if !usable_brokers.iter().any(|b| b.id == *replica) {
+                return Err(invalid(format!(
+                    "The manual partition assignment of partition {partition_id} includes \
+                    broker {replica}, which is not registered or is fenced."
+                )));
+            }
+        }
+        placement.push(replicas.clone());
+    }
+    Ok(placement)
+}
+
+fn validate_topic_name(name: &str) -> Result<(), ApiError> {
+    let invalid = |reason: String| {
+        Err(ApiError::new(
+            Errors::InvalidTopicException,
+            format!("Topic name \"{name}\" is illegal, {reason}"),
+        ))
+    };
+    if name.is_empty() || name == "." || name == ".." {
+        return invalid("it can't be empty, \".\" or \"..\"".to_string());
+    }
+    if name.len() > MAX_TOPIC_NAME_LENGTH {
+        return invalid(format!(
+            "it can't be longer than {MAX_TOPIC_NAME_LENGTH} characters"
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+    {
+        return invalid(
+            "it contains a character other than ASCII alphanumerics, '.', '_' and '-'".to_string(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brokers(ids: &[i32]) -> Vec<UsableBroker> {
+        ids.iter()
+            .map(|id| UsableBroker {
+                id: *id,
+                rack: None,
+            })
+            .collect()
+    }
+
+    fn topic(name: &str, num_partitions: i32, replication_factor: i16) -> CreatableTopic {
+        CreatableTopic {
+            name: name.to_string(),
+            num_partitions,
+            replication_factor,
+            assignments: BTreeMap::new(),
+            configs: BTreeMap::new(),
+            operation_key: None,
+            auto_create: false,
+        }
+    }
+
+    fn replay(manager: &mut ReplicationControlManager, records: &[MetadataRecord]) {
+        for record in records {
+            match record {
+                MetadataRecord::Topic(record) => manager.replay_topic(record),
+                MetadataRecord::Partition(record) => manager.replay_partition(record),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_create_topic() {
+        let mut manager = ReplicationControlManager::default();
+        let result = manager
+            .create_topic(&topic("foo", 3, 2), &brokers(&[1, 2, 3]))
+            .unwrap();
+        assert_eq!(4, result.records().len());
+        replay(&mut manager, result.records());
+
+        let topic_id = result.response().topic_id;
+        assert_eq!(Some(topic_id), manager.topic_id("foo"));
+        for partition_id in 0..3 {
+            let partition = manager.partition(topic_id, partition_id).unwrap();
+            assert_eq!(2, partition.replicas.len());
+            assert_eq!(partition.replicas, partition.isr);
+            assert_eq!(partition.replicas[0], partition.leader);
+        }
+
+        assert_eq!(
+            Errors::TopicAlreadyExists,
+            manager
+                .create_topic(&topic("foo", 1, 1), &brokers(&[1]))
+                .unwrap_err()
+                .error()
+        );
+    }
+
+    #[test]
+    fn test_alter_partition() {
+        let mut manager = ReplicationControlManager::default();
+        let result = manager
+            .create_topic(&topic("foo", 1, 3), &brokers(&[1, 2, 3]))
+            .unwrap();
+        replay(&mut manager, result.records());
+        let topic_id = result.response().topic_id;
+        let partition = manager.partition(topic_id, 0).unwrap().clone();
+        let leader = partition.leader;
+        let followers: Vec<i32> = partition
+            .replicas
+            .iter()
+            .copied()
+            .filter(|r| *r != leader)
+            .collect();
+        let request = AlterPartitionRequest {
+            broker_id: leader,
+            broker_epoch: 10,
+            topic_id,
+            partition_id: 0,
+            leader_epoch: 0,
+            partition_epoch: 0,
+            new_isr: vec![leader],
+        };
+
+        let result = manager.alter_partition(&request, |_| true).unwrap();
+        replay(&mut manager, result.records());
+        let partition = manager.partition(topic_id, 0).unwrap();
+        assert_eq!(vec![leader], partition.isr);
+        assert_eq!(1, partition.partition_epoch);
+        assert_eq!(partition, result.response());
+
+        let alter = |change: &dyn Fn(&mut AlterPartitionRequest), eligible: bool| {
+            let mut request = AlterPartitionRequest {
+                partition_epoch: 1,
+                ..request.clone()
+            };
+            change(&mut request);
+            manager
+                .alter_partition(&request, |_| eligible)
+                .map(|_| ())
+                .map_err(|e| e.error())
+        };
+        assert_eq!(
+            Err(Errors::InvalidUpdateVersion),
+            alter(&|r| r.partition_epoch = 0, true)
+        );
+        assert_eq!(
+            Err(Errors::FencedLeaderEpoch),
+            alter(&|r| r.leader_epoch = 1, true)
+        );
+        assert_eq!(
+            Err(Errors::NotLeaderOrFollower),
+            alter(&|r| r.broker_id = followers[0], true)
+        );
+        assert_eq!(
+            Err(Errors::UnknownTopicOrPartition),
+            alter(&|r| r.partition_id = 1, true)
+        );
+        assert_eq!(
+            Err(Errors::InvalidRequest),
+            alter(&|r| r.new_isr = vec![leader, 4], true)
+        );
+        assert_eq!(
+            Err(Errors::InvalidRequest),
+            alter(&|r| r.new_isr = followers.clone(), true)
+        );
+        // A follower only joins the ISR while it is eligible.
+        let expand = |r: &mut AlterPartitionRequest| r.new_isr = vec![leader, followers[0]];
+        assert_eq!(Err(Errors::IneligibleReplica), alter(&expand, false));
+        assert_eq!(Ok(()), alter(&expand, true));
+    }
+
+    #[test]
+    fn test_invalid_topics() {
+        let manager = ReplicationControlManager::default();
+        for (topic, error) in [
+            (topic("", 1, 1), Errors::InvalidTopicException),
+            (topic("a/b", 1, 1), Errors::InvalidTopicException),
+            (topic("foo", 0, 1), Errors::InvalidPartitions),
+            (topic("foo", -2, 1), Errors::InvalidPartitions),
+            (topic("foo", 1, 3), Errors::InvalidReplicationFactor),
+            (topic("foo", 1, 0), Errors::InvalidReplicationFactor),
+        ] {
+            assert_eq!(
+                error,
+                manager
+                    .create_topic(&topic, &brokers(&[1, 2]))
+                    .unwrap_err()
+                    .error()
+            );
+        }
+        assert_eq!(
+            "Replication factor was set to an invalid non-positive value 0: it must be larger \
+            than 0, or -1 to use the default value.",
+            manager
+                .create_topic(&topic("foo", 1, 0), &brokers(&[1, 2]))
+                .unwrap_err()
+                .message()
+        );
+    }
+
+    #[test]
+    fn test_topic_defaults() {
+        let manager = ReplicationControlManager::default().with_topic_defaults(3, 2);
+        let topic = topic("foo", NO_NUM_PARTITIONS, NO_REPLICATION_FACTOR);
+        let result = manager.create_topic(&topic, &brokers(&[1, 2])).unwrap();
+        assert_eq!(3, result.response().num_partitions);
+        assert_eq!(2, result.response().replication_factor);
+
+        // The default replication factor can't exceed the live brokers either.
+        let error = manager.create_topic(&topic, &brokers(&[1])).unwrap_err();
+        assert_eq!(Errors::InvalidReplicationFactor, error.error());
+        assert!(error.message().contains("only 1 broker(s)"));
+    }
+
+    #[test]
+    fn test_manual_assignment() {
+        let manager = ReplicationControlManager::default();
+        let assigned = |assignments: &[(i32, &[i32])]| CreatableTopic {
+            assignments: assignments
+                .iter()
+                .map(|(partition_id, replicas)| (*partition_id, replicas.to_vec()))
+                .collect(),
+            ..topic("foo", NO_NUM_PARTITIONS, NO_REPLICATION_FACTOR)
+        };
+        let result = manager
+            .create_topic(
+                &assigned(&[(0, &[3, 1]), (1, &[1, 2])]),
+                &brokers(&[1, 2, 3]),
+            )
+            .unwrap();
+        assert_eq!(2, result.response().num_partitions);
+        assert_eq!(2, result.response().replication_factor);
+        let leaders: Vec<i32> = result
+            .records()
+            .iter()
+            .filter_map(|r| match r {
+                MetadataRecord::Partition(p) => Some(p.leader),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![3, 1], leaders);
+
+        let create = |topic: &CreatableTopic| {
+            manager
+                .create_topic(topic, &brokers(&[1, 2, 3]))
+                .map(|_| ())
+                .map_err(|e| e.error())
+        };
+        for assignments in [
+            &[(0, &[1][..]), (2, &[2][..])][..],
+            &[(0, &[][..])][..],
+            &[(0, &[1, 2][..]), (1, &[1][..])][..],
+            &[(0, &[1, 1][..])][..],
+            &[(0, &[1, 4][..])][..],
+        ] {
+            assert_eq!(
+                Err(Errors::InvalidReplicaAssignment),
+                create(&assigned(assignments)),
+                "{assignments:?}"
+            );
+        }
+        let both = CreatableTopic {
+            num_partitions: 1,
+            ..assigned(&[(0, &[1])])
+        };
+        assert_eq!(Err(Errors::InvalidRequest), create(&both));
+        assert_eq!(
+            "Both numPartitions or replicationFactor and replicasAssignments were set. Both \
+            cannot be used at the same time.",
+            manager
+                .create_topic(&both, &brokers(&[1, 2, 3]))
+                .unwrap_err()
+                .message()
+        );
+        assert_eq!(
+            "The manual partition assignment of partition 0 includes broker 4, which is not \
+            registered or is fenced.",
+            manager
+                .create_topic(&assigned(&[(0, &[1, 4])]), &brokers(&[1, 2, 3]))
+                .unwrap_err()
+                .message()
+        );
+    }
+}
@@ -0,0 +1,401 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::transaction_buffer::TransactionBuffer;
+use crate::image::local_image_store::LocalImageStore;
+use crate::image::metadata_image::MetadataImage;
+use crate::image::metadata_publisher::{MetadataPublisher, MetadataUpdate};
+use crate::image::snapshot_file::Result;
+use rafka_raft::raft_client::Listener;
+use rafka_raft::{BatchReader, OffsetAndEpoch, SnapshotReader};
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use tracing::{error, info};
+
+/// The number of committed offsets after which the image is saved again.
+pub const DEFAULT_SAVE_INTERVAL_OFFSETS: i64 = 1000;
+
+/// Builds the [MetadataImage] of a broker from the metadata log and persists it to a
+/// [LocalImageStore].
+///
+/// On startup the listener loads the latest persisted image, and is registered with
+/// [RaftClient::register_from](rafka_raft::raft_client::RaftClient::register_from) at
+/// [BrokerMetadataListener::next_offset], so that the broker only replays what was committed
+/// since it saved the image. The image is saved every `save_interval_offsets` committed
+/// offsets, whenever a snapshot is loaded, and on shutdown. A failure to save it is logged and
+/// only costs a longer replay on the next startup.
+///
+/// The records of a metadata transaction are applied to the image once it ends. The image is
+/// only ever saved at an offset outside of a transaction, so that a restarted broker replays
+/// an interrupted transaction from its beginning.
+///
+/// The records applied to the image are then published to the subscribers of the
+/// [MetadataPublisher], which apply the configs, quotas and ACLs as they change.
+pub struct BrokerMetadataListener {
+    image: Arc<RwLock<MetadataImage>>,
+    publisher: MetadataPublisher,
+    store: LocalImageStore,
+    transaction: TransactionBuffer,
+    /// The exclusive end offset and epoch of the log prefix the image was built from, which
+    /// does not end inside of a transaction.
+    snapshot_id: Option<OffsetAndEpoch>,
+    saved_snapshot_id: Option<OffsetAndEpoch>,
+    save_interval_offsets: i64,
+}
+
+impl BrokerMetadataListener {
+    /// A listener starting from the latest image of `store`, or from an empty one.
+    pub fn load(store: LocalImageStore) -> Result<Self> {
+        let (image, snapshot_id) = match store.load()? {
+            Some((image, snapshot_id)) => (image, Some(snapshot_id)),
+            None => (MetadataImage::default(), None),
+        };
+        let image = Arc::new(RwLock::new(image));
+        Ok(Self {
+            publisher: MetadataPublisher::new(Arc::clone(&image)),
+            image,
+            store,
+            transaction: TransactionBuffer::new(),
+            snapshot_id,
+            saved_snapshot_id: snapshot_id,
+            save_interval_offsets: DEFAULT_SAVE_INTERVAL_OFFSETS,
+        })
+    }
+
+    pub fn with_save_interval_offsets(mut self, save_interval_offsets: i64) -> Self {
+        self.save_interval_offsets = save_interval_offsets;
+        self
+    }
+
+    /// The image, shared with the request handlers serving it.
+    pub fn image(&self) -> Arc<RwLock<MetadataImage>> {
+        Arc::clone(&self.image)
+    }
+
+    /// The bus the changes of the image are published on, to subscribe to before the
+    /// listener is registered.
+    pub fn publisher(&self) -> MetadataPublisher {
+        self.publisher.clone()
+    }
+
+    /// The offset of the first record the image is missing.
+    pub fn next_offset(&self) -> i64 {
+        self.snapshot_id.map_or(0, |id| id.offset())
+    }
+
+    fn image_mut(&self) -> RwLockWriteGuard<'_, MetadataImage> {
+        self.image.write().expect("metadata image lock poisoned")
+    }
+
+    fn maybe_save(&mut self) {
+        let saved_offset = self.saved_snapshot_id.map_or(0, |id| id.offset());
+        if self.next_offset() - saved_offset >= self.save_interval_offsets {
+            self.save();
+        }
+    }
+
+    fn save(&mut self) {
+        let Some(snapshot_id) = self.snapshot_id else {
+            return;
+        };
+        if self.saved_snapshot_id == Some(snapshot_id) {
+            return;
+        }
+        let image = self.image.read().expect("metadata image lock poisoned");
+        match self.store.save(&image, snapshot_id) {
+            Ok(_) => self.saved_snapshot_id = Some(snapshot_id),
+            Err(e) => error!(
+                "Failed to save the metadata image at offset {}: {e}",
+                snapshot_id.offset()
+            ),
+        }
+    }
+}
+
+impl Listener<MetadataRecord> for BrokerMetadataListener {
+    fn handle_commit(&mut self, reader: BatchReader<MetadataRecord>) {
+        let mut image = self.image.write().expect("metadata image lock poisoned");
+        let mut applied = Vec::new();
+        for batch in reader {
+            for record in batch.records() {
+                self.transaction.apply(record, |record| {
+                    image.replay(record);
+                    applied.push(record.clone());
+                });
+            }
+            if !self.transaction.in_transaction() {
+                self.snapshot_id =
+                    Some(OffsetAndEpoch::new(batch.last_offset() + 1, batch.epoch()));
+            }
+        }
+        drop(image);
+        self.publisher.publish(&MetadataUpdate::Delta(&applied));
+        self.maybe_save();
+    }
+
+    fn handle_load_snapshot(&mut self, reader: SnapshotReader<MetadataRecord>) {
+        let snapshot_id = reader.snapshot_id();
+        info!("Loading the metadata snapshot {snapshot_id:?}");
+        let mut image = MetadataImage::default();
+        self.transaction.clear();
+        for batch in reader {
+            for record in batch.records() {
+                self.transaction
+                    .apply(record, |record| image.replay(record));
+            }
+        }
+        let records = image.records();
+        *self.image_mut() = image;
+        self.publisher.publish(&MetadataUpdate::Snapshot(&records));
+        // A snapshot taken in the middle of a transaction can't be saved until it ends.
+        self.snapshot_id = (!self.transaction.in_transaction()).then_some(snapshot_id);
+        self.save();
+    }
+
+    fn begin_shutdown(&mut self) {
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::{
+        AbortTransactionRecord, BeginTransactionRecord, EndTransactionRecord, TopicRecord,
+    };
+    use crate::image::metadata_publisher::MetadataSubscriber;
+    use rafka_clients::common::uuid::Uuid;
+    use rafka_raft::local_raft_client::{LocalRaftClient, SharedLog};
+    use rafka_raft::raft_client::RaftClient;
+    use std::sync::Mutex;
+
+    fn topic(name: &str) -> MetadataRecord {
+        MetadataRecord::Topic(TopicRecord {
+            name: name.to_string(),
+            topic_id: Uuid::random(),
+        })
+    }
+
+    fn image_of(records: &[MetadataRecord]) -> MetadataImage {
+        let mut image = MetadataImage::default();
+        for record in records {
+            image.replay(record);
+        }
+        image
+    }
+
+    /// Starts a broker on `shared`, returning its client and the image it serves.
+    fn start_broker(
+        shared: &SharedLog<MetadataRecord>,
+        store: &LocalImageStore,
+    ) -> (
+        LocalRaftClient<MetadataRecord>,
+        Arc<RwLock<MetadataImage>>,
+        i64,
+    ) {
+        let listener = BrokerMetadataListener::load(store.clone())
+            .unwrap()
+            .with_save_interval_offsets(2);
+        let image = listener.image();
+        let next_offset = listener.next_offset();
+        let mut client = LocalRaftClient::new(1, shared.clone());
+        client.register_from(Box::new(listener), next_offset);
+        client.poll();
+        (client, image, next_offset)
+    }
+
+    #[test]
+    fn test_restart_resumes_from_the_saved_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalImageStore::new(dir.path());
+        let shared = SharedLog::new();
+        let mut controller = LocalRaftClient::new(0, shared.clone());
+        let epoch = shared.elect(0).epoch();
+        let records = vec![topic("foo"), topic("bar"), topic("baz")];
+        controller
+            .schedule_append(epoch, records[..1].to_vec())
+            .unwrap();
+
+        let (mut broker, image, next_offset) = start_broker(&shared, &store);
+        assert_eq!(0, next_offset);
+        assert_eq!(image_of(&records[..1]), *image.read().unwrap());
+        // Not saved yet, as fewer offsets than the interval were committed.
+        assert!(store.load().unwrap().is_none());
+
+        controller
+            .schedule_append(epoch, records[1..2].to_vec())
+            .unwrap();
+        broker.poll();
+        assert_eq!(
+            Some((image_of(&records[..2]), OffsetAndEpoch::new(2, epoch))),
+            store.load().unwrap()
+        );
+
+        controller
+            .schedule_append(epoch, records[2..].to_vec())
+            .unwrap();
+        broker.poll();
+        broker.begin_shutdown();
+        assert_eq!(
+            Some((image_of(&records), OffsetAndEpoch::new(3, epoch))),
+            store.load().unwrap()
+        );
+
+        // The restarted broker serves the saved image before reading the log.
+        let listener = BrokerMetadataListener::load(store.clone()).unwrap();
+        assert_eq!(3, listener.next_offset());
+        assert_eq!(image_of(&records), *listener.image().read().unwrap());
+
+        let more = topic("qux");
+        controller
+            .schedule_append(epoch, vec![more.clone()])
+            .unwrap();
+        let (_broker, image, next_offset) = start_broker(&shared, &store);
+        assert_eq!(3, next_offset);
+        let mut expected = records.clone();
+        expected.push(more);
+        assert_eq!(image_of(&expected), *image.read().unwrap());
+    }
+
+    #[test]
+    fn test_load_snapshot_replaces_the_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalImageStore::new(dir.path());
+        let shared = SharedLog::new();
+        let mut controller = LocalRaftClient::new(0, shared.clone());
+        let epoch = shared.elect(0).epoch();
+        let records = vec![topic("foo"), topic("bar")];
+        controller.schedule_append(epoch, records.clone()).unwrap();
+        shared.snapshot(2);
+
+        let listener = BrokerMetadataListener::load(store.clone()).unwrap();
+        listener.image_mut().replay(&topic("stale"));
+        let image = listener.image();
+        let mut broker = LocalRaftClient::new(1, shared.clone());
+        broker.register(Box::new(listener));
+        broker.poll();
+        assert_eq!(image_of(&records), *image.read().unwrap());
+        assert_eq!(
+            Some((image_of(&records), OffsetAndEpoch::new(2, epoch))),
+            store.load().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_transaction_is_applied_across_a_snapshot_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalImageStore::new(dir.path());
+        let shared = SharedLog::new();
+        let mut controller = LocalRaftClient::new(0, shared.clone());
+        let epoch = shared.elect(0).epoch();
+        let begin = MetadataRecord::BeginTransaction(BeginTransactionRecord {
+            name: "create_topic".to_string(),
+        });
+        let end = MetadataRecord::EndTransaction(EndTransactionRecord {});
+        let (foo, bar) = (topic("foo"), topic("bar"));
+        controller
+            .schedule_append(epoch, vec![begin.clone(), foo.clone()])
+            .unwrap();
+        controller
+            .schedule_append(epoch, vec![bar.clone(), end])
+            .unwrap();
+        // The snapshot ends in the middle of the transaction.
+        shared.snapshot(2);
+
+        let (mut broker, image, _) = start_broker(&shared, &store);
+        assert_eq!(
+            image_of(&[foo.clone(), bar.clone()]),
+            *image.read().unwrap()
+        );
+        assert_eq!(
+            Some((
+                image_of(&[foo.clone(), bar.clone()]),
+                OffsetAndEpoch::new(4, epoch)
+            )),
+            store.load().unwrap()
+        );
+
+        // Neither the image nor the saved offset include a transaction which is still open.
+        controller
+            .schedule_append(epoch, vec![begin, topic("baz")])
+            .unwrap();
+        broker.poll();
+        broker.begin_shutdown();
+        assert_eq!(image_of(&[foo, bar]), *image.read().unwrap());
+        assert_eq!(
+            OffsetAndEpoch::new(4, epoch),
+            store.load().unwrap().unwrap().1
+        );
+    }
+
+    /// Records the updates it is notified of, and whether they were snapshots.
+    #[derive(Default)]
+    struct RecordingSubscriber {
+        updates: Mutex<Vec<(bool, Vec<MetadataRecord>)>>,
+    }
+
+    impl RecordingSubscriber {
+        fn take(&self) -> Vec<(bool, Vec<MetadataRecord>)> {
+            std::mem::take(&mut self.updates.lock().unwrap())
+        }
+    }
+
+    impl MetadataSubscriber for RecordingSubscriber {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn on_update(&self, update: &MetadataUpdate<'_>) {
+            let snapshot = matches!(update, MetadataUpdate::Snapshot(_));
+            self.updates
+                .lock()
+                .unwrap()
+                .push((snapshot, update.records().to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_applied_records_are_published() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalImageStore::new(dir.path());
+        let shared = SharedLog::new();
+        let mut controller = LocalRaftClient::new(0, shared.clone());
+        let epoch = shared.elect(0).epoch();
+        let (foo, bar) = (topic("foo"), topic("bar"));
+        controller
+            .schedule_append(epoch, vec![foo.clone()])
+            .unwrap();
+        shared.snapshot(1);
+
+        let listener = BrokerMetadataListener::load(store).unwrap();
+        let publisher = listener.publisher();
+        let subscriber = Arc::new(RecordingSubscriber::default());
+        publisher.subscribe(subscriber.clone());
+        assert_eq!(1, publisher.subscriber_count());
+        // A subscriber starts from the image, empty here.
+        assert_eq!(vec![(true, vec![])], subscriber.take());
+
+        let mut broker = LocalRaftClient::new(1, shared.clone());
+        broker.register(Box::new(listener));
+        broker.poll();
+        assert_eq!(vec![(true, vec![foo.clone()])], subscriber.take());
+
+        // Only the records applied to the image are published.
+        let begin = MetadataRecord::BeginTransaction(BeginTransactionRecord {
+            name: "create_topic".to_string(),
+        });
+        let abort = MetadataRecord::AbortTransaction(AbortTransactionRecord {
+            reason: "failed".to_string(),
+        });
+        controller
+            .schedule_append(epoch, vec![begin, topic("baz"), abort])
+            .unwrap();
+        controller
+            .schedule_append(epoch, vec![bar.clone()])
+            .unwrap();
+        broker.poll();
+        assert_eq!(vec![(false, vec![bar.clone()])], subscriber.take());
+
+        // A later subscriber starts from the current image.
+        let late = Arc::new(RecordingSubscriber::default());
+        publisher.subscribe(late.clone());
+        assert_eq!(vec![(true, image_of(&[foo, bar]).records())], late.take());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::image::metadata_image::MetadataImage;
+use crate::image::snapshot_file::{
+    Result, SNAPSHOT_SUFFIX, SnapshotCompression, read_snapshot, write_snapshot,
+};
+use rafka_raft::OffsetAndEpoch;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Persists the metadata image of a broker to its metadata log directory.
+///
+/// Each image is written as a snapshot file named after the exclusive end offset and epoch of
+/// the log prefix it was built from, so that a restarting broker loads the latest image and
+/// only reads the metadata log from that offset on, instead of replaying it from the start
+/// before it can be unfenced. Only the latest image is kept.
+#[derive(Debug, Clone)]
+pub struct LocalImageStore {
+    dir: PathBuf,
+    compression: SnapshotCompression,
+}
+
+impl LocalImageStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            compression: SnapshotCompression::default(),
+        }
+    }
+
+    pub fn with_compression(mut self, compression: SnapshotCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes `image`, built from the log up to `snapshot_id`, and deletes the older images.
+    pub fn save(&self, image: &MetadataImage, snapshot_id: OffsetAndEpoch) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let path = write_snapshot(&self.dir, snapshot_id, &image.records(), self.compression)?;
+        for (older_id, older_path) in self.images()? {
+            if older_id.offset() < snapshot_id.offset() {
+                match fs::remove_file(&older_path) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+        }
+        Ok(path)
+    }
+
+    /// The latest image and the end of the log prefix it was built from, or `None` if there
+    /// is none. A corrupt image is ignored, so that the broker replays the metadata log.
+    pub fn load(&self) -> Result<Option<(MetadataImage, OffsetAndEpoch)>> {
+        let Some((snapshot_id, path)) = self.images()?.into_iter().last() else {
+            return Ok(None);
+        };
+        let records = match read_snapshot(&path) {
+            Ok(records) => records,
+            Err(e) => {
+                warn!("Ignoring the metadata image {}: {e}", path.display());
+                return Ok(None);
+            }
+        };
+        let mut image = MetadataImage::default();
+        for record in &records {
+            image.replay(record);
+        }
+        info!(
+            "Loaded the metadata image {} with {} records",
+            path.display(),
+            records.len()
+        );
+        Ok(Some((image, snapshot_id)))
+    }
+
+    /// The images of the directory, by offset.
+    fn images(&self) -> Result<Vec<(OffsetAndEpoch, PathBuf)>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut images = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if let Some(snapshot_id) = parse_snapshot_id(&path) {
+                images.push((snapshot_id, path));
+            }
+        }
+        images.sort_by_key(|(snapshot_id, _)| (snapshot_id.offset(), snapshot_id.epoch()));
+        Ok(images)
+    }
+}
+
+/// Parses the snapshot id of a file named by
+/// [snapshot_file_name](crate::image::snapshot_file::snapshot_file_name).
+fn parse_snapshot_id(path: &Path) -> Option<OffsetAndEpoch> {
+    let name = path.file_name()?.to_str()?;
+    let (offset, epoch) = name
+        .strip_suffix(SNAPSHOT_SUFFIX)?
+        .strip_suffix('.')?
+        .split_once('-')?;
+    Some(OffsetAndEpoch::new(
+        offset.parse().ok()?,
+        epoch.parse().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::metadata_record::MetadataRecord;
+    use crate::common::metadata::records::TopicRecord;
+    use rafka_clients::common::uuid::Uuid;
+
+    fn image(topics: &[&str]) -> MetadataImage {
+        let mut image = MetadataImage::default();
+        for name in topics {
+            image.replay(&MetadataRecord::Topic(TopicRecord {
+                name: name.to_string(),
+                topic_id: Uuid::random(),
+            }));
+        }
+        image
+    }
+
+    #[test]
+    fn test_save_keeps_the_latest_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalImageStore::new(dir.path().join("images"))
+            .with_compression(SnapshotCompression::Zstd);
+        assert!(store.load().unwrap().is_none());
+
+        store
+            .save(&image(&["foo"]), OffsetAndEpoch::new(10, 1))
+            .unwrap();
+        let latest = image(&["foo", "bar"]);
+        store.save(&latest, OffsetAndEpoch::new(20, 2)).unwrap();
+        assert_eq!(1, fs::read_dir(store.dir()).unwrap().count());
+        assert_eq!(
+            Some((latest, OffsetAndEpoch::new(20, 2))),
+            store.load().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_corrupt_image_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalImageStore::new(dir.path());
+        let path = store
+            .save(&image(&["foo"]), OffsetAndEpoch::new(10, 1))
+            .unwrap();
+        fs::write(dir.path().join("other.txt"), "ignored").unwrap();
+        let mut data = fs::read(&path).unwrap();
+        *data.last_mut().unwrap() ^= 0xff;
+        fs::write(&path, data).unwrap();
+        assert!(store.load().unwrap().is_none());
+    }
+}
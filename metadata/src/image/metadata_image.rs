@@ -0,0 +1,719 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::records::{
+    AccessControlEntryRecord, ClientQuotaRecord, ConfigRecord, EntityData, TopicRecord,
+};
+use crate::metadata::broker_registration::BrokerRegistration;
+use crate::metadata::partition_registration::PartitionRegistration;
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::uuid::Uuid;
+use std::collections::{BTreeMap, HashMap};
+
+/// A broker advertised in a Metadata response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataResponseBroker {
+    pub node_id: i32,
+    pub host: String,
+    pub port: u16,
+    pub rack: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataResponsePartition {
+    pub error: Errors,
+    pub partition_index: i32,
+    /// The leader, or -1 if it is not available.
+    pub leader_id: i32,
+    pub leader_epoch: i32,
+    pub replica_nodes: Vec<i32>,
+    pub isr_nodes: Vec<i32>,
+    /// The replicas whose broker is fenced or no longer registered.
+    pub offline_replicas: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataResponseTopic {
+    pub error: Errors,
+    pub name: String,
+    pub topic_id: Uuid,
+    pub partitions: Vec<MetadataResponsePartition>,
+}
+
+/// The most partitions a DescribeTopicPartitions response describes, whatever the limit of
+/// the request.
+pub const MAX_REQUEST_PARTITION_SIZE_LIMIT: usize = 2000;
+
+/// The first partition a DescribeTopicPartitions request describes, and the partition the
+/// response tells the client to resume from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicPartitionsCursor {
+    pub topic_name: String,
+    pub partition_index: i32,
+}
+
+/// A page of a DescribeTopicPartitions response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescribeTopicPartitionsPage {
+    pub topics: Vec<MetadataResponseTopic>,
+    /// Where the next page starts, or `None` if this is the last page.
+    pub next_cursor: Option<TopicPartitionsCursor>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TopicImage {
+    id: Uuid,
+    partitions: BTreeMap<i32, PartitionRegistration>,
+}
+
+/// The view of the cluster a broker builds by replaying the metadata log, which it serves
+/// Metadata requests from.
+///
+/// Only available brokers are advertised: fenced brokers, e.g. ones which are still
+/// recovering their logs, and brokers in controlled shutdown are left out of the broker list
+/// and can't be reported as leaders. They still count as replicas and ISR members of their
+/// partitions, so clients see the full replica set and the ISR size used for `min.insync.replicas`.
+///
+/// The image also keeps the dynamic configs, client quotas and ACLs, which it does not serve
+/// itself, so that they are saved with it and the components applying them, see
+/// [MetadataPublisher](crate::image::metadata_publisher::MetadataPublisher), start from them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataImage {
+    brokers: BTreeMap<i32, BrokerRegistration>,
+    topics: BTreeMap<String, TopicImage>,
+    topic_names: HashMap<Uuid, String>,
+    /// The configs by resource type and name.
+    configs: BTreeMap<(i8, String), BTreeMap<String, String>>,
+    /// The latest record setting each quota, by entity and quota key.
+    client_quotas: BTreeMap<(Vec<EntityData>, String), ClientQuotaRecord>,
+    acls: BTreeMap<Uuid, AccessControlEntryRecord>,
+}
+
+impl MetadataImage {
+    pub fn replay(&mut self, record: &MetadataRecord) {
+        match record {
+            MetadataRecord::RegisterBroker(record) => {
+                self.brokers
+                    .insert(record.broker_id, BrokerRegistration::from_record(record));
+            }
+            MetadataRecord::UnregisterBroker(record) => {
+                if self
+                    .brokers
+                    .get(&record.broker_id)
+                    .is_some_and(|broker| broker.epoch() == record.broker_epoch)
+                {
+                    self.brokers.remove(&record.broker_id);
+                }
+            }
+            MetadataRecord::BrokerRegistrationChange(record) => {
+                if let Some(broker) = self.brokers.get_mut(&record.broker_id)
+                    && broker.epoch() == record.broker_epoch
+                {
+                    broker.apply_change(record);
+                }
+            }
+            MetadataRecord::Topic(record) => {
+                self.topic_names
+                    .insert(record.topic_id, record.name.clone());
+                self.topics.insert(
+                    record.name.clone(),
+                    TopicImage {
+                        id: record.topic_id,
+                        partitions: BTreeMap::new(),
+                    },
+                );
+            }
+            MetadataRecord::Partition(record) => {
+                if let Some(topic) = self
+                    .topic_names
+                    .get(&record.topic_id)
+                    .and_then(|name| self.topics.get_mut(name))
+                {
+                    topic.partitions.insert(
+                        record.partition_id,
+                        PartitionRegistration::from_record(record),
+                    );
+                }
+            }
+            MetadataRecord::Config(record) => {
+                let resource = (record.resource_type, record.resource_name.clone());
+                match &record.value {
+                    Some(value) => {
+                        self.configs
+                            .entry(resource)
+                            .or_default()
+                            .insert(record.name.clone(), value.clone());
+                    }
+                    None => {
+                        if let Some(configs) = self.configs.get_mut(&resource) {
+                            configs.remove(&record.name);
+                            if configs.is_empty() {
+                                self.configs.remove(&resource);
+                            }
+                        }
+                    }
+                }
+            }
+            MetadataRecord::ClientQuota(record) => {
+                let key = (record.entity.clone(), record.key.clone());
+                if record.remove {
+                    self.client_quotas.remove(&key);
+                } else {
+                    self.client_quotas.insert(key, record.clone());
+                }
+            }
+            MetadataRecord::AccessControlEntry(record) => {
+                self.acls.insert(record.id, record.clone());
+            }
+            MetadataRecord::RemoveAccessControlEntry(record) => {
+                self.acls.remove(&record.id);
+            }
+            MetadataRecord::UserScramCredential(_)
+            | MetadataRecord::FeatureLevel(_)
+            | MetadataRecord::CompletedOperation(_) => {}
+            // Transactions are resolved by the TransactionBuffer the records go through.
+            MetadataRecord::BeginTransaction(_)
+            | MetadataRecord::EndTransaction(_)
+            | MetadataRecord::AbortTransaction(_) => {}
+        }
+    }
+
+    /// The records rebuilding the image when replayed into an empty one.
+    pub fn records(&self) -> Vec<MetadataRecord> {
+        let brokers = self
+            .brokers
+            .values()
+            .map(|broker| MetadataRecord::RegisterBroker(broker.to_record()));
+        let topics = self.topics.iter().flat_map(|(name, topic)| {
+            std::iter::once(MetadataRecord::Topic(TopicRecord {
+                name: name.clone(),
+                topic_id: topic.id,
+            }))
+            .chain(topic.partitions.iter().map(|(partition_id, partition)| {
+                MetadataRecord::Partition(partition.to_record(topic.id, *partition_id))
+            }))
+        });
+        let configs = self
+            .configs
+            .iter()
+            .flat_map(|((resource_type, resource_name), configs)| {
+                configs.iter().map(|(name, value)| {
+                    MetadataRecord::Config(ConfigRecord {
+                        resource_type: *resource_type,
+                        resource_name: resource_name.clone(),
+                        name: name.clone(),
+                        value: Some(value.clone()),
+                    })
+                })
+            });
+        let client_quotas = self
+            .client_quotas
+            .values()
+            .map(|record| MetadataRecord::ClientQuota(record.clone()));
+        let acls = self
+            .acls
+            .values()
+            .map(|record| MetadataRecord::AccessControlEntry(record.clone()));
+        brokers
+            .chain(topics)
+            .chain(configs)
+            .chain(client_quotas)
+            .chain(acls)
+            .collect()
+    }
+
+    /// The configs of the resource of `resource_type` named `resource_name`, the empty name
+    /// standing for the cluster-wide default.
+    pub fn configs(
+        &self,
+        resource_type: i8,
+        resource_name: &str,
+    ) -> Option<&BTreeMap<String, String>> {
+        self.configs
+            .get(&(resource_type, resource_name.to_string()))
+    }
+
+    pub fn broker(&self, broker_id: i32) -> Option<&BrokerRegistration> {
+        self.brokers.get(&broker_id)
+    }
+
+    /// The brokers to advertise on `listener_name`.
+    pub fn alive_brokers(&self, listener_name: &str) -> Vec<MetadataResponseBroker> {
+        self.brokers
+            .values()
+            .filter(|b| b.is_available())
+            .filter_map(|b| {
+                let endpoint = b.listener(listener_name)?;
+                Some(MetadataResponseBroker {
+                    node_id: b.id(),
+                    host: endpoint.host.clone(),
+                    port: endpoint.port,
+                    rack: b.rack().map(str::to_string),
+                })
+            })
+            .collect()
+    }
+
+    /// Describes the requested topics, or all topics if `topics` is `None`.
+    pub fn topic_metadata(
+        &self,
+        listener_name: &str,
+        topics: Option<&[String]>,
+    ) -> Vec<MetadataResponseTopic> {
+        self.iter_topic_metadata(listener_name, topics).collect()
+    }
+
+    /// Describes the requested topics one at a time, so that a response for a cluster with
+    /// many partitions can be written topic by topic instead of being built as a whole first.
+    pub fn iter_topic_metadata<'a>(
+        &'a self,
+        listener_name: &'a str,
+        topics: Option<&'a [String]>,
+    ) -> impl Iterator<Item = MetadataResponseTopic> + 'a {
+        let names: Box<dyn Iterator<Item = &String>> = match topics {
+            Some(topics) => Box::new(topics.iter()),
+            None => Box::new(self.topics.keys()),
+        };
+        names.map(move |name| match self.topics.get(name) {
+            Some(topic) => self.describe_topic(listener_name, name, topic, 0, usize::MAX),
+            None => unknown_topic(Errors::UnknownTopicOrPartition, name.clone(), Uuid::ZERO),
+        })
+    }
+
+    /// Describes the topics with the requested ids. Unknown ids get `UnknownTopicId`.
+    pub fn topic_metadata_by_id(
+        &self,
+        listener_name: &str,
+        topic_ids: &[Uuid],
+    ) -> Vec<MetadataResponseTopic> {
+        topic_ids
+            .iter()
+            .map(|id| {
+                match self
+                    .topic_names
+                    .get(id)
+                    .and_then(|name| Some((name, self.topics.get(name)?)))
+                {
+                    Some((name, topic)) => {
+                        self.describe_topic(listener_name, name, topic, 0, usize::MAX)
+                    }
+                    None => unknown_topic(Errors::UnknownTopicId, String::new(), *id),
+                }
+            })
+            .collect()
+    }
+
+    /// Describes at most `partition_limit` partitions of the requested topics, or of all
+    /// topics if `topics` is `None`, in topic name and partition order from `cursor` on. The
+    /// limit is capped to [MAX_REQUEST_PARTITION_SIZE_LIMIT].
+    ///
+    /// A topic whose partitions don't all fit is split: the page holds its first partitions
+    /// and the next cursor points to the first one left out.
+    pub fn describe_topic_partitions(
+        &self,
+        listener_name: &str,
+        topics: Option<&[String]>,
+        partition_limit: usize,
+        cursor: Option<&TopicPartitionsCursor>,
+    ) -> DescribeTopicPartitionsPage {
+        let mut names: Vec<&String> = match topics {
+            Some(topics) => topics.iter().collect(),
+            None => self.topics.keys().collect(),
+        };
+        names.sort();
+        names.dedup();
+        if let Some(cursor) = cursor {
+            names.retain(|name| **name >= cursor.topic_name);
+        }
+
+        let mut remaining = partition_limit.clamp(1, MAX_REQUEST_PARTITION_SIZE_LIMIT);
+        let mut page = DescribeTopicPartitionsPage {
+            topics: vec![],
+            next_cursor: None,
+        };
+        for name in names {
+            let Some(topic) = self.topics.get(name) else {
+                page.topics.push(unknown_topic(
+                    Errors::UnknownTopicOrPartition,
+                    name.clone(),
+                    Uuid::ZERO,
+                ));
+                continue;
+            };
+            let first = cursor
+                .filter(|cursor| cursor.topic_name == *name)
+                .map_or(0, |cursor| cursor.partition_index);
+            if remaining == 0 {
+                page.next_cursor = Some(TopicPartitionsCursor {
+                    topic_name: name.clone(),
+                    partition_index: first,
+                });
+                break;
+            }
+            let described = self.describe_topic(listener_name, name, topic, first, remaining);
+            remaining -= described.partitions.len();
+            if let Some((index, _)) = topic
+                .partitions
+                .range(first..)
+                .nth(described.partitions.len())
+            {
+                page.next_cursor = Some(TopicPartitionsCursor {
+                    topic_name: name.clone(),
+                    partition_index: *index,
+                });
+                page.topics.push(described);
+                break;
+            }
+            page.topics.push(described);
+        }
+        page
+    }
+
+    fn describe_topic(
+        &self,
+        listener_name: &str,
+        name: &str,
+        topic: &TopicImage,
+        first_partition: i32,
+        max_partitions: usize,
+    ) -> MetadataResponseTopic {
+        MetadataResponseTopic {
+            error: Errors::None,
+            name: name.to_string(),
+            topic_id: topic.id,
+            partitions: topic
+                .partitions
+                .range(first_partition..)
+                .take(max_partitions)
+                .map(|(index, partition)| self.partition_metadata(listener_name, *index, partition))
+                .collect(),
+        }
+    }
+
+    fn partition_metadata(
+        &self,
+        listener_name: &str,
+        partition_index: i32,
+        partition: &PartitionRegistration,
+    ) -> MetadataResponsePartition {
+        let leader = self
+            .brokers
+            .get(&partition.leader)
+            .filter(|b| b.is_available());
+        let (error, leader_id) = match leader {
+            Some(b) if b.listener(listener_name).is_some() => (Errors::None, partition.leader),
+            Some(_) => (Errors::ListenerNotFound, PartitionRegistration::NO_LEADER),
+            None => (Errors::LeaderNotAvailable, PartitionRegistration::NO_LEADER),
+        };
+        MetadataResponsePartition {
+            error,
+            partition_index,
+            leader_id,
+            leader_epoch: partition.leader_epoch,
+            replica_nodes: partition.replicas.clone(),
+            isr_nodes: partition.isr.clone(),
+            offline_replicas: partition
+                .replicas
+                .iter()
+                .copied()
+                .filter(|id| self.brokers.get(id).is_none_or(|b| b.fenced()))
+                .collect(),
+        }
+    }
+}
+
+fn unknown_topic(error: Errors, name: String, topic_id: Uuid) -> MetadataResponseTopic {
+    MetadataResponseTopic {
+        error,
+        name,
+        topic_id,
+        partitions: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::{
+        BrokerEndpoint, BrokerRegistrationChangeRecord, PartitionRecord, RegisterBrokerRecord,
+        RemoveAccessControlEntryRecord, UnregisterBrokerRecord,
+    };
+
+    const TOPIC_ID: Uuid = Uuid::new(100, 100);
+
+    fn register(broker_id: i32, fenced: bool) -> MetadataRecord {
+        MetadataRecord::RegisterBroker(RegisterBrokerRecord {
+            broker_id,
+            incarnation_id: Uuid::new(1, broker_id as i64),
+            broker_epoch: broker_id as i64,
+            end_points: vec![BrokerEndpoint {
+                name: "PLAINTEXT".to_string(),
+                host: format!("broker-{broker_id}"),
+                port: 9092,
+                security_protocol: 0,
+            }],
+            features: vec![],
+            rack: None,
+            fenced,
+            in_controlled_shutdown: false,
+            log_dirs: vec![],
+        })
+    }
+
+    fn image() -> MetadataImage {
+        let mut image = MetadataImage::default();
+        for record in [
+            register(1, false),
+            register(2, false),
+            register(3, true),
+            MetadataRecord::Topic(TopicRecord {
+                name: "foo".to_string(),
+                topic_id: TOPIC_ID,
+            }),
+            MetadataRecord::Partition(PartitionRecord {
+                partition_id: 0,
+                topic_id: TOPIC_ID,
+                replicas: vec![1, 2, 3],
+                isr: vec![1, 2, 3],
+                leader: 1,
+                leader_epoch: 4,
+                partition_epoch: 5,
+            }),
+            MetadataRecord::Partition(PartitionRecord {
+                partition_id: 1,
+                topic_id: TOPIC_ID,
+                replicas: vec![2, 3, 1],
+                isr: vec![2, 3, 1],
+                leader: 2,
+                leader_epoch: 0,
+                partition_epoch: 0,
+            }),
+        ] {
+            image.replay(&record);
+        }
+        image
+    }
+
+    #[test]
+    fn test_fenced_brokers_are_not_advertised() {
+        let mut image = image();
+        let ids = |image: &MetadataImage| -> Vec<i32> {
+            image
+                .alive_brokers("PLAINTEXT")
+                .iter()
+                .map(|b| b.node_id)
+                .collect()
+        };
+        assert_eq!(vec![1, 2], ids(&image));
+        assert!(image.alive_brokers("SSL").is_empty());
+
+        image.replay(&MetadataRecord::BrokerRegistrationChange(
+            BrokerRegistrationChangeRecord {
+                broker_id: 2,
+                broker_epoch: 2,
+                fenced: 0,
+                in_controlled_shutdown: 1,
+            },
+        ));
+        assert_eq!(vec![1], ids(&image));
+    }
+
+    #[test]
+    fn test_unregistered_broker_is_removed() {
+        let mut image = image();
+        let unregister = |broker_epoch| {
+            MetadataRecord::UnregisterBroker(UnregisterBrokerRecord {
+                broker_id: 3,
+                broker_epoch,
+            })
+        };
+        // A record for an earlier registration leaves the current one alone.
+        image.replay(&unregister(1));
+        assert!(image.broker(3).is_some());
+        image.replay(&unregister(3));
+        assert!(image.broker(3).is_none());
+    }
+
+    #[test]
+    fn test_fenced_replicas_still_count_for_the_isr() {
+        let image = image();
+        let topics = image.topic_metadata("PLAINTEXT", None);
+        assert_eq!(1, topics.len());
+        let partition = &topics[0].partitions[0];
+        assert_eq!(Errors::None, partition.error);
+        assert_eq!(1, partition.leader_id);
+        assert_eq!(vec![1, 2, 3], partition.replica_nodes);
+        assert_eq!(vec![1, 2, 3], partition.isr_nodes);
+        assert_eq!(vec![3], partition.offline_replicas);
+    }
+
+    #[test]
+    fn test_unavailable_leader() {
+        let mut image = image();
+        image.replay(&MetadataRecord::BrokerRegistrationChange(
+            BrokerRegistrationChangeRecord {
+                broker_id: 2,
+                broker_epoch: 2,
+                fenced: 1,
+                in_controlled_shutdown: 0,
+            },
+        ));
+        let topics =
+            image.topic_metadata("PLAINTEXT", Some(&["foo".to_string(), "bar".to_string()]));
+        let partition = &topics[0].partitions[1];
+        assert_eq!(Errors::LeaderNotAvailable, partition.error);
+        assert_eq!(-1, partition.leader_id);
+        assert_eq!(vec![2, 3], partition.offline_replicas);
+        assert_eq!(Errors::UnknownTopicOrPartition, topics[1].error);
+
+        let topics = image.topic_metadata("SSL", None);
+        assert_eq!(Errors::ListenerNotFound, topics[0].partitions[0].error);
+    }
+
+    #[test]
+    fn test_topic_id_lookup() {
+        let image = image();
+        let unknown = Uuid::new(1, 1);
+        let topics = image.topic_metadata_by_id("PLAINTEXT", &[TOPIC_ID, unknown]);
+        assert_eq!("foo", topics[0].name);
+        assert_eq!(2, topics[0].partitions.len());
+        assert_eq!(Errors::UnknownTopicId, topics[1].error);
+        assert_eq!(unknown, topics[1].topic_id);
+    }
+
+    #[test]
+    fn test_describe_topic_partitions_pages() {
+        let mut image = image();
+        let bar = Uuid::new(200, 200);
+        image.replay(&MetadataRecord::Topic(TopicRecord {
+            name: "bar".to_string(),
+            topic_id: bar,
+        }));
+        for partition_id in 0..3 {
+            image.replay(&MetadataRecord::Partition(PartitionRecord {
+                partition_id,
+                topic_id: bar,
+                replicas: vec![1],
+                isr: vec![1],
+                leader: 1,
+                leader_epoch: 0,
+                partition_epoch: 0,
+            }));
+        }
+        let describe = |limit, cursor: Option<&TopicPartitionsCursor>| {
+            let page = image.describe_topic_partitions("PLAINTEXT", None, limit, cursor);
+            let described: Vec<(String, Vec<i32>)> = page
+                .topics
+                .iter()
+                .map(|t| {
+                    let partitions = t.partitions.iter().map(|p| p.partition_index).collect();
+                    (t.name.clone(), partitions)
+                })
+                .collect();
+            (described, page.next_cursor)
+        };
+
+        let (described, cursor) = describe(2, None);
+        assert_eq!(vec![("bar".to_string(), vec![0, 1])], described);
+        let cursor = cursor.unwrap();
+        assert_eq!(
+            ("bar", 2),
+            (cursor.topic_name.as_str(), cursor.partition_index)
+        );
+
+        // The page ends with the last partition of bar, so the next one starts at foo.
+        let (described, cursor) = describe(1, Some(&cursor));
+        assert_eq!(vec![("bar".to_string(), vec![2])], described);
+        let cursor = cursor.unwrap();
+        assert_eq!(
+            ("foo", 0),
+            (cursor.topic_name.as_str(), cursor.partition_index)
+        );
+
+        let (described, cursor) = describe(10, Some(&cursor));
+        assert_eq!(vec![("foo".to_string(), vec![0, 1])], described);
+        assert_eq!(None, cursor);
+
+        let page = image.describe_topic_partitions(
+            "PLAINTEXT",
+            Some(&["foo".to_string(), "baz".to_string()]),
+            10,
+            None,
+        );
+        assert_eq!(Errors::UnknownTopicOrPartition, page.topics[0].error);
+        assert_eq!(2, page.topics[1].partitions.len());
+    }
+
+    #[test]
+    fn test_configs_quotas_and_acls_are_kept() {
+        let mut image = image();
+        let config = |name: &str, value: Option<&str>| {
+            MetadataRecord::Config(ConfigRecord {
+                resource_type: 2,
+                resource_name: "foo".to_string(),
+                name: name.to_string(),
+                value: value.map(str::to_string),
+            })
+        };
+        let quota = |value: f64, remove: bool| {
+            MetadataRecord::ClientQuota(ClientQuotaRecord {
+                entity: vec![EntityData {
+                    entity_type: "user".to_string(),
+                    entity_name: Some("alice".to_string()),
+                }],
+                key: "producer_byte_rate".to_string(),
+                value,
+                remove,
+            })
+        };
+        let acl = |id: i64| AccessControlEntryRecord {
+            id: Uuid::new(0, id),
+            resource_type: 2,
+            resource_name: "foo".to_string(),
+            pattern_type: 3,
+            principal: "User:alice".to_string(),
+            host: "*".to_string(),
+            operation: 3,
+            permission_type: 3,
+        };
+        for record in [
+            config("retention.ms", Some("1000")),
+            config("segment.bytes", Some("1024")),
+            config("retention.ms", None),
+            quota(10.0, false),
+            quota(20.0, false),
+            MetadataRecord::AccessControlEntry(acl(1)),
+            MetadataRecord::AccessControlEntry(acl(2)),
+            MetadataRecord::RemoveAccessControlEntry(RemoveAccessControlEntryRecord {
+                id: Uuid::new(0, 1),
+            }),
+        ] {
+            image.replay(&record);
+        }
+        assert_eq!(
+            Some(&BTreeMap::from([(
+                "segment.bytes".to_string(),
+                "1024".to_string()
+            )])),
+            image.configs(2, "foo")
+        );
+
+        let records = image.records();
+        assert!(records.contains(&config("segment.bytes", Some("1024"))));
+        assert!(records.contains(&quota(20.0, false)));
+        assert!(records.contains(&MetadataRecord::AccessControlEntry(acl(2))));
+        let mut rebuilt = MetadataImage::default();
+        for record in &records {
+            rebuilt.replay(record);
+        }
+        assert_eq!(image, rebuilt);
+
+        image.replay(&quota(0.0, true));
+        image.replay(&config("segment.bytes", None));
+        assert_eq!(None, image.configs(2, "foo"));
+        assert!(
+            !image
+                .records()
+                .iter()
+                .any(|record| matches!(record, MetadataRecord::ClientQuota(_)))
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Notifies the components of a broker applying the metadata, e.g. the authorizer, the quota
+//! managers and the topic configs of the logs, of every change of the metadata, as the broker
+//! replays the metadata log.
+
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::image::metadata_image::MetadataImage;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+/// A change of the metadata a [MetadataSubscriber] is notified of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataUpdate<'a> {
+    /// The records committed since the previous update, transactions resolved: the records
+    /// of an aborted transaction are never published.
+    Delta(&'a [MetadataRecord]),
+    /// The whole metadata, replacing everything the subscriber applied so far, e.g. when the
+    /// broker loads a snapshot, or when the subscriber subscribes.
+    Snapshot(&'a [MetadataRecord]),
+}
+
+impl<'a> MetadataUpdate<'a> {
+    pub fn records(&self) -> &'a [MetadataRecord] {
+        match self {
+            MetadataUpdate::Delta(records) | MetadataUpdate::Snapshot(records) => records,
+        }
+    }
+}
+
+/// A component applying the metadata as it changes, rather than polling the image for it.
+pub trait MetadataSubscriber: Send + Sync {
+    /// The name of the subscriber, for logging.
+    fn name(&self) -> &str;
+
+    /// Applies `update`, ignoring the records the subscriber is not interested in. Called
+    /// from the thread replaying the metadata log, so it must not block.
+    fn on_update(&self, update: &MetadataUpdate<'_>);
+}
+
+/// The bus the changes of the metadata of a broker are published on.
+///
+/// A subscriber is first sent the current image as a [MetadataUpdate::Snapshot], then every
+/// change the [BrokerMetadataListener](crate::BrokerMetadataListener) replays. A change
+/// replayed while the subscriber subscribes may be sent both in the snapshot and in the
+/// following delta, so subscribers apply records idempotently, as the records setting or
+/// removing a config, a quota or an ACL are.
+#[derive(Clone)]
+pub struct MetadataPublisher {
+    image: Arc<RwLock<MetadataImage>>,
+    subscribers: Arc<RwLock<Vec<Arc<dyn MetadataSubscriber>>>>,
+}
+
+impl MetadataPublisher {
+    pub(crate) fn new(image: Arc<RwLock<MetadataImage>>) -> Self {
+        Self {
+            image,
+            subscribers: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    pub fn subscribe(&self, subscriber: Arc<dyn MetadataSubscriber>) {
+        let mut subscribers = self
+            .subscribers
+            .write()
+            .expect("metadata subscribers lock poisoned");
+        let records = self
+            .image
+            .read()
+            .expect("metadata image lock poisoned")
+            .records();
+        info!(
+            "Subscribed {} to the metadata, starting from {} records",
+            subscriber.name(),
+            records.len()
+        );
+        subscriber.on_update(&MetadataUpdate::Snapshot(&records));
+        subscribers.push(subscriber);
+    }
+
+    /// The number of subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers
+            .read()
+            .expect("metadata subscribers lock poisoned")
+            .len()
+    }
+
+    /// Sends `update` to every subscriber, in the order they subscribed. Empty deltas are not
+    /// sent.
+    pub(crate) fn publish(&self, update: &MetadataUpdate<'_>) {
+        if matches!(update, MetadataUpdate::Delta(records) if records.is_empty()) {
+            return;
+        }
+        for subscriber in self
+            .subscribers
+            .read()
+            .expect("metadata subscribers lock poisoned")
+            .iter()
+        {
+            subscriber.on_update(update);
+        }
+    }
+}
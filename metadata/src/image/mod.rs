@@ -0,0 +1,5 @@
+pub mod broker_metadata_listener;
+pub mod local_image_store;
+pub mod metadata_image;
+pub mod metadata_publisher;
+pub mod snapshot_file;
@@ -0,0 +1,241 @@
+use crate::common::metadata::metadata_record::{MetadataRecord, RecordError};
+use rafka_raft::OffsetAndEpoch;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::info;
+
+/// The suffix of the metadata snapshot files.
+pub const SNAPSHOT_SUFFIX: &str = "checkpoint";
+
+/// The size of the header preceding the payload: the length of the payload, its CRC32C and
+/// the compression codec.
+const HEADER_SIZE: usize = 9;
+
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Failed to read snapshot records: {0}")]
+    Record(#[from] RecordError),
+
+    #[error("Unknown snapshot compression {0}, expected none or zstd")]
+    UnknownCompression(String),
+
+    #[error("The snapshot file {} is corrupt: {reason}", .path.display())]
+    Corrupt { path: PathBuf, reason: String },
+}
+
+pub type Result<T> = std::result::Result<T, SnapshotError>;
+
+/// How the records of a snapshot file are compressed. The ids are the ones of the record
+/// batch compression codecs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SnapshotCompression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl SnapshotCompression {
+    pub fn id(&self) -> i8 {
+        match self {
+            SnapshotCompression::None => 0,
+            SnapshotCompression::Zstd => 4,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SnapshotCompression::None => "none",
+            SnapshotCompression::Zstd => "zstd",
+        }
+    }
+
+    pub fn for_id(id: i8) -> Option<Self> {
+        match id {
+            0 => Some(SnapshotCompression::None),
+            4 => Some(SnapshotCompression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn for_name(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(SnapshotCompression::None),
+            "zstd" => Ok(SnapshotCompression::Zstd),
+            _ => Err(SnapshotError::UnknownCompression(name.to_string())),
+        }
+    }
+}
+
+/// The name of the file of the snapshot `snapshot_id`, e.g.
+/// `00000000000000001000-0000000004.checkpoint`.
+pub fn snapshot_file_name(snapshot_id: OffsetAndEpoch) -> String {
+    format!(
+        "{:020}-{:010}.{SNAPSHOT_SUFFIX}",
+        snapshot_id.offset(),
+        snapshot_id.epoch()
+    )
+}
+
+/// Writes the records of the snapshot `snapshot_id` to `dir`, compressed with `compression`,
+/// returning the path of the file.
+///
+/// The file consists of the length of the payload as an `i32`, its CRC32C as a `u32`, the id
+/// of the compression codec as an `i8`, and the payload: the serialized records, compressed
+/// as a whole. The file is written under a temporary name and then renamed, so a crash never
+/// leaves a partial snapshot behind.
+pub fn write_snapshot(
+    dir: &Path,
+    snapshot_id: OffsetAndEpoch,
+    records: &[MetadataRecord],
+    compression: SnapshotCompression,
+) -> Result<PathBuf> {
+    let mut serialized = Vec::new();
+    for record in records {
+        record.write(&mut serialized);
+    }
+    let payload = match compression {
+        SnapshotCompression::None => serialized,
+        SnapshotCompression::Zstd => zstd::encode_all(serialized.as_slice(), 0)?,
+    };
+    let path = dir.join(snapshot_file_name(snapshot_id));
+    let tmp_path = path.with_extension(format!("{SNAPSHOT_SUFFIX}.tmp"));
+    {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&(payload.len() as i32).to_be_bytes())?;
+        file.write_all(&crc32c::crc32c(&payload).to_be_bytes())?;
+        file.write_all(&[compression.id() as u8])?;
+        file.write_all(&payload)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, &path)?;
+    info!(
+        "Wrote snapshot {} with {} records and {} compression",
+        path.display(),
+        records.len(),
+        compression.name()
+    );
+    Ok(path)
+}
+
+/// Reads the records of a snapshot file, whatever compression it was written with, so that
+/// changing the compression of new snapshots keeps the existing ones readable.
+pub fn read_snapshot(path: &Path) -> Result<Vec<MetadataRecord>> {
+    let data = fs::read(path)?;
+    let corrupt = |reason: &str| SnapshotError::Corrupt {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    if data.len() < HEADER_SIZE {
+        return Err(corrupt("the header is truncated"));
+    }
+    let (header, payload) = data.split_at(HEADER_SIZE);
+    let len = i32::from_be_bytes(header[..4].try_into().expect("4 bytes"));
+    let crc = u32::from_be_bytes(header[4..8].try_into().expect("4 bytes"));
+    if len < 0 || len as usize != payload.len() {
+        return Err(corrupt("the length does not match the file size"));
+    }
+    if crc32c::crc32c(payload) != crc {
+        return Err(corrupt("the checksum does not match"));
+    }
+    let serialized = match SnapshotCompression::for_id(header[8] as i8) {
+        Some(SnapshotCompression::None) => payload.to_vec(),
+        Some(SnapshotCompression::Zstd) => zstd::decode_all(payload)?,
+        None => return Err(corrupt(&format!("unknown compression codec {}", header[8]))),
+    };
+    let mut records = serialized.as_slice();
+    let mut result = Vec::new();
+    while !records.is_empty() {
+        result.push(MetadataRecord::read(&mut records)?);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::ConfigRecord;
+
+    fn records(count: usize) -> Vec<MetadataRecord> {
+        (0..count)
+            .map(|i| {
+                MetadataRecord::Config(ConfigRecord {
+                    resource_type: 2,
+                    resource_name: format!("topic-{i}"),
+                    name: "retention.ms".to_string(),
+                    value: Some("604800000".to_string()),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_write_and_read_with_every_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let records = records(1000);
+        let none = write_snapshot(
+            dir.path(),
+            OffsetAndEpoch::new(1000, 4),
+            &records,
+            SnapshotCompression::None,
+        )
+        .unwrap();
+        assert_eq!(
+            "00000000000000001000-0000000004.checkpoint",
+            none.file_name().unwrap().to_str().unwrap()
+        );
+        let zstd = write_snapshot(
+            dir.path(),
+            OffsetAndEpoch::new(2000, 4),
+            &records,
+            SnapshotCompression::Zstd,
+        )
+        .unwrap();
+        assert!(fs::metadata(&zstd).unwrap().len() * 10 < fs::metadata(&none).unwrap().len());
+
+        assert_eq!(records, read_snapshot(&none).unwrap());
+        assert_eq!(records, read_snapshot(&zstd).unwrap());
+    }
+
+    #[test]
+    fn test_corrupt_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_snapshot(
+            dir.path(),
+            OffsetAndEpoch::new(10, 1),
+            &records(10),
+            SnapshotCompression::Zstd,
+        )
+        .unwrap();
+        let mut data = fs::read(&path).unwrap();
+        data[8] = 3;
+        fs::write(&path, &data).unwrap();
+        assert!(matches!(
+            read_snapshot(&path),
+            Err(SnapshotError::Corrupt { reason, .. }) if reason == "unknown compression codec 3"
+        ));
+
+        *data.last_mut().unwrap() ^= 0xff;
+        fs::write(&path, data).unwrap();
+        assert!(matches!(
+            read_snapshot(&path),
+            Err(SnapshotError::Corrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compression_names() {
+        assert_eq!(
+            SnapshotCompression::Zstd,
+            SnapshotCompression::for_name("ZSTD").unwrap()
+        );
+        assert!(matches!(
+            SnapshotCompression::for_name("gzip"),
+            Err(SnapshotError::UnknownCompression(_))
+        ));
+    }
+}
@@ -0,0 +1,18 @@
+pub use common::metadata::{metadata_record, records, transaction_buffer};
+pub use controller::{
+    client_quota_control_manager, cluster_control_manager, configuration_control_manager,
+    controller_result, feature_control_manager, operation_control_manager, quorum_controller,
+    quorum_controller_metrics, replica_placement, replication_control_manager,
+};
+pub use image::{
+    broker_metadata_listener, broker_metadata_listener::BrokerMetadataListener, local_image_store,
+    local_image_store::LocalImageStore, metadata_image, metadata_publisher,
+    metadata_publisher::MetadataPublisher, snapshot_file,
+};
+pub use metadata::{
+    authorizer, bootstrap, broker_registration, broker_state, partition_registration,
+};
+mod common;
+mod controller;
+mod image;
+mod metadata;
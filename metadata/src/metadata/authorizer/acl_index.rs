@@ -0,0 +1,219 @@
+use crate::metadata::authorizer::standard_acl::{PatternType, ResourceType, StandardAcl, WILDCARD};
+use rafka_clients::common::uuid::Uuid;
+use std::collections::HashMap;
+
+/// A trie of the prefixes of prefixed ACLs, by byte: the ACLs applying to a resource are
+/// found under the nodes on the path of its name, in as many steps as the name is long.
+#[derive(Debug, Default)]
+struct PrefixTrie {
+    /// The ACLs whose prefix ends at this node.
+    ids: Vec<Uuid>,
+    children: HashMap<u8, PrefixTrie>,
+}
+
+impl PrefixTrie {
+    fn insert(&mut self, prefix: &[u8], id: Uuid) {
+        match prefix.split_first() {
+            None => self.ids.push(id),
+            Some((first, rest)) => self.children.entry(*first).or_default().insert(rest, id),
+        }
+    }
+
+    /// Removes an ACL, pruning the nodes left empty.
+    fn remove(&mut self, prefix: &[u8], id: Uuid) {
+        match prefix.split_first() {
+            None => self.ids.retain(|i| *i != id),
+            Some((first, rest)) => {
+                if let Some(child) = self.children.get_mut(first) {
+                    child.remove(rest, id);
+                    if child.is_empty() {
+                        self.children.remove(first);
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ids.is_empty() && self.children.is_empty()
+    }
+
+    /// Calls `f` with the ACLs of every prefix of `name`.
+    fn for_each_prefix_of(&self, name: &[u8], f: &mut impl FnMut(&Uuid) -> bool) -> bool {
+        let mut node = self;
+        for byte in name {
+            if !node.ids.iter().all(&mut *f) {
+                return false;
+            }
+            match node.children.get(byte) {
+                Some(child) => node = child,
+                None => return true,
+            }
+        }
+        node.ids.iter().all(f)
+    }
+}
+
+/// The ACLs, indexed so that the ones applying to a resource are found without scanning the
+/// others: the literal ACLs by resource type and name, the prefixed ones in a trie per
+/// resource type.
+#[derive(Debug, Default)]
+pub(crate) struct AclIndex {
+    acls: HashMap<Uuid, StandardAcl>,
+    literal: HashMap<ResourceType, HashMap<String, Vec<Uuid>>>,
+    prefixed: HashMap<ResourceType, PrefixTrie>,
+}
+
+impl AclIndex {
+    pub fn len(&self) -> usize {
+        self.acls.len()
+    }
+
+    pub fn insert(&mut self, id: Uuid, acl: StandardAcl) {
+        self.remove(id);
+        match acl.pattern_type {
+            PatternType::Literal => self
+                .literal
+                .entry(acl.resource_type)
+                .or_default()
+                .entry(acl.resource_name.clone())
+                .or_default()
+                .push(id),
+            PatternType::Prefixed => self
+                .prefixed
+                .entry(acl.resource_type)
+                .or_default()
+                .insert(acl.resource_name.as_bytes(), id),
+        }
+        self.acls.insert(id, acl);
+    }
+
+    pub fn remove(&mut self, id: Uuid) {
+        let Some(acl) = self.acls.remove(&id) else {
+            return;
+        };
+        match acl.pattern_type {
+            PatternType::Literal => {
+                if let Some(names) = self.literal.get_mut(&acl.resource_type)
+                    && let Some(ids) = names.get_mut(&acl.resource_name)
+                {
+                    ids.retain(|i| *i != id);
+                    if ids.is_empty() {
+                        names.remove(&acl.resource_name);
+                    }
+                }
+            }
+            PatternType::Prefixed => {
+                if let Some(trie) = self.prefixed.get_mut(&acl.resource_type) {
+                    trie.remove(acl.resource_name.as_bytes(), id);
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with the ACLs applying to the resource `resource_name` of type
+    /// `resource_type`, until it returns false. Returns whether `f` was called for all of
+    /// them.
+    pub fn for_each_matching(
+        &self,
+        resource_type: ResourceType,
+        resource_name: &str,
+        mut f: impl FnMut(&StandardAcl) -> bool,
+    ) -> bool {
+        let mut visit = |id: &Uuid| f(&self.acls[id]);
+        if let Some(names) = self.literal.get(&resource_type) {
+            let wildcard = (resource_name != WILDCARD)
+                .then(|| names.get(WILDCARD))
+                .flatten();
+            for ids in names.get(resource_name).into_iter().chain(wildcard) {
+                if !ids.iter().all(&mut visit) {
+                    return false;
+                }
+            }
+        }
+        match self.prefixed.get(&resource_type) {
+            Some(trie) => trie.for_each_prefix_of(resource_name.as_bytes(), &mut visit),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::authorizer::standard_acl::{AclOperation, AclPermissionType};
+
+    fn acl(resource_name: &str, pattern_type: PatternType) -> StandardAcl {
+        StandardAcl {
+            resource_type: ResourceType::Topic,
+            resource_name: resource_name.to_string(),
+            pattern_type,
+            principal: "User:alice".to_string(),
+            host: WILDCARD.to_string(),
+            operation: AclOperation::Read,
+            permission_type: AclPermissionType::Allow,
+        }
+    }
+
+    fn matching(index: &AclIndex, resource_name: &str) -> Vec<String> {
+        let mut names = vec![];
+        index.for_each_matching(ResourceType::Topic, resource_name, |acl| {
+            names.push(format!("{:?}:{}", acl.pattern_type, acl.resource_name));
+            true
+        });
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_matching_acls() {
+        let mut index = AclIndex::default();
+        index.insert(Uuid::new(0, 1), acl("orders", PatternType::Literal));
+        index.insert(Uuid::new(0, 2), acl(WILDCARD, PatternType::Literal));
+        index.insert(Uuid::new(0, 3), acl("ord", PatternType::Prefixed));
+        index.insert(Uuid::new(0, 4), acl("orders-", PatternType::Prefixed));
+        index.insert(Uuid::new(0, 5), acl("other", PatternType::Prefixed));
+        let mut group = acl("orders", PatternType::Literal);
+        group.resource_type = ResourceType::Group;
+        index.insert(Uuid::new(0, 6), group);
+
+        assert_eq!(
+            vec!["Literal:*", "Literal:orders", "Prefixed:ord"],
+            matching(&index, "orders")
+        );
+        assert_eq!(
+            vec!["Literal:*", "Prefixed:ord", "Prefixed:orders-"],
+            matching(&index, "orders-eu")
+        );
+        assert_eq!(vec!["Literal:*"], matching(&index, "or"));
+    }
+
+    #[test]
+    fn test_remove_prunes_the_index() {
+        let mut index = AclIndex::default();
+        index.insert(Uuid::new(0, 1), acl("orders", PatternType::Literal));
+        index.insert(Uuid::new(0, 2), acl("orders-", PatternType::Prefixed));
+        index.remove(Uuid::new(0, 1));
+        index.remove(Uuid::new(0, 2));
+        index.remove(Uuid::new(0, 3));
+        assert_eq!(0, index.len());
+        assert!(index.literal[&ResourceType::Topic].is_empty());
+        assert!(index.prefixed[&ResourceType::Topic].is_empty());
+        assert!(matching(&index, "orders-eu").is_empty());
+    }
+
+    #[test]
+    fn test_stops_when_asked() {
+        let mut index = AclIndex::default();
+        index.insert(Uuid::new(0, 1), acl("orders", PatternType::Literal));
+        index.insert(Uuid::new(0, 2), acl("o", PatternType::Prefixed));
+        let mut visited = 0;
+        assert!(
+            !index.for_each_matching(ResourceType::Topic, "orders", |_| {
+                visited += 1;
+                false
+            })
+        );
+        assert_eq!(1, visited);
+    }
+}
@@ -0,0 +1,16 @@
+use thiserror::Error;
+
+mod acl_index;
+pub mod standard_acl;
+pub mod standard_authorizer;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AuthorizerError {
+    #[error("The authorizer has not loaded the ACLs of the metadata log yet")]
+    NotReady,
+
+    #[error("Invalid ACL: {0}")]
+    InvalidAcl(String),
+}
+
+pub type Result<T> = std::result::Result<T, AuthorizerError>;
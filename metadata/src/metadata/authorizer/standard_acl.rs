@@ -0,0 +1,221 @@
+use crate::common::metadata::records::AccessControlEntryRecord;
+use crate::metadata::authorizer::{AuthorizerError, Result};
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::uuid::Uuid;
+
+/// The resource name matching every resource of a type, and the principal and host matching
+/// every principal and host.
+pub const WILDCARD: &str = "*";
+
+/// The principal matching every user.
+pub const WILDCARD_PRINCIPAL: &str = "User:*";
+
+macro_rules! acl_enum {
+    ($(#[$meta:meta])* $name:ident { $($(#[$variant_meta:meta])* $variant:ident = $code:expr),+ $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant),+
+        }
+
+        impl $name {
+            /// The code of the value in requests and metadata records.
+            pub fn code(&self) -> i8 {
+                match self {
+                    $($name::$variant => $code),+
+                }
+            }
+
+            pub fn from_code(code: i8) -> Result<Self> {
+                match code {
+                    $($code => Ok($name::$variant),)+
+                    _ => Err(AuthorizerError::InvalidAcl(format!(
+                        "unknown {} code {code}",
+                        stringify!($name)
+                    ))),
+                }
+            }
+        }
+    };
+}
+
+acl_enum!(
+    /// The type of the resource an ACL applies to.
+    ResourceType {
+        Topic = 2,
+        Group = 3,
+        Cluster = 4,
+        TransactionalId = 5,
+        DelegationToken = 6,
+        User = 7,
+    }
+);
+
+acl_enum!(
+    /// How the resource name of an ACL matches the names of resources.
+    PatternType {
+        /// The name is the name of the resource, or the wildcard.
+        Literal = 3,
+        /// The name is a prefix of the names of the resources.
+        Prefixed = 4,
+    }
+);
+
+acl_enum!(
+    /// An operation on a resource.
+    AclOperation {
+        All = 2,
+        Read = 3,
+        Write = 4,
+        Create = 5,
+        Delete = 6,
+        Alter = 7,
+        Describe = 8,
+        ClusterAction = 9,
+        DescribeConfigs = 10,
+        AlterConfigs = 11,
+        IdempotentWrite = 12,
+        CreateTokens = 13,
+        DescribeTokens = 14,
+    }
+);
+
+acl_enum!(
+    /// Whether an ACL allows or denies its operation.
+    AclPermissionType {
+        Deny = 2,
+        Allow = 3,
+    }
+);
+
+impl ResourceType {
+    /// The error answering a request denied access to a resource of this type.
+    pub fn authorization_error(&self) -> Errors {
+        match self {
+            ResourceType::Topic => Errors::TopicAuthorizationFailed,
+            ResourceType::Group => Errors::GroupAuthorizationFailed,
+            ResourceType::Cluster => Errors::ClusterAuthorizationFailed,
+            ResourceType::TransactionalId => Errors::TransactionalIdAuthorizationFailed,
+            ResourceType::DelegationToken => Errors::DelegationTokenAuthorizationFailed,
+            ResourceType::User => Errors::ClusterAuthorizationFailed,
+        }
+    }
+}
+
+impl AclOperation {
+    /// Whether an ACL allowing this operation also allows `operation`: every operation is
+    /// allowed by `All`, and `Describe` and `DescribeConfigs` are implied by the operations
+    /// changing what they describe.
+    pub fn allows(&self, operation: AclOperation) -> bool {
+        *self == operation
+            || *self == AclOperation::All
+            || (operation == AclOperation::Describe
+                && matches!(
+                    self,
+                    AclOperation::Read
+                        | AclOperation::Write
+                        | AclOperation::Delete
+                        | AclOperation::Alter
+                ))
+            || (operation == AclOperation::DescribeConfigs && *self == AclOperation::AlterConfigs)
+    }
+}
+
+/// An ACL as stored in the metadata log: `principal` is allowed or denied `operation` from
+/// `host` on the resources of `resource_type` matching `resource_name` and `pattern_type`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StandardAcl {
+    pub resource_type: ResourceType,
+    pub resource_name: String,
+    pub pattern_type: PatternType,
+    /// The principal, e.g. `User:alice`, or [WILDCARD_PRINCIPAL].
+    pub principal: String,
+    /// The host, or [WILDCARD].
+    pub host: String,
+    pub operation: AclOperation,
+    pub permission_type: AclPermissionType,
+}
+
+impl StandardAcl {
+    pub fn from_record(record: &AccessControlEntryRecord) -> Result<Self> {
+        Ok(Self {
+            resource_type: ResourceType::from_code(record.resource_type)?,
+            resource_name: record.resource_name.clone(),
+            pattern_type: PatternType::from_code(record.pattern_type)?,
+            principal: record.principal.clone(),
+            host: record.host.clone(),
+            operation: AclOperation::from_code(record.operation)?,
+            permission_type: AclPermissionType::from_code(record.permission_type)?,
+        })
+    }
+
+    pub fn to_record(&self, id: Uuid) -> AccessControlEntryRecord {
+        AccessControlEntryRecord {
+            id,
+            resource_type: self.resource_type.code(),
+            resource_name: self.resource_name.clone(),
+            pattern_type: self.pattern_type.code(),
+            principal: self.principal.clone(),
+            host: self.host.clone(),
+            operation: self.operation.code(),
+            permission_type: self.permission_type.code(),
+        }
+    }
+
+    /// Whether the ACL applies to the resource `resource_name` of type `resource_type`.
+    pub fn matches_resource(&self, resource_type: ResourceType, resource_name: &str) -> bool {
+        self.resource_type == resource_type
+            && match self.pattern_type {
+                PatternType::Literal => {
+                    self.resource_name == resource_name || self.resource_name == WILDCARD
+                }
+                PatternType::Prefixed => resource_name.starts_with(&self.resource_name),
+            }
+    }
+
+    /// Whether the ACL applies to `principal` connecting from `host`.
+    pub fn matches_principal_and_host(&self, principal: &str, host: &str) -> bool {
+        (self.principal == principal || self.principal == WILDCARD_PRINCIPAL)
+            && (self.host == host || self.host == WILDCARD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_round_trip() {
+        let acl = StandardAcl {
+            resource_type: ResourceType::Group,
+            resource_name: "app-".to_string(),
+            pattern_type: PatternType::Prefixed,
+            principal: "User:alice".to_string(),
+            host: WILDCARD.to_string(),
+            operation: AclOperation::Read,
+            permission_type: AclPermissionType::Allow,
+        };
+        let record = acl.to_record(Uuid::new(1, 2));
+        assert_eq!(acl, StandardAcl::from_record(&record).unwrap());
+
+        let invalid = AccessControlEntryRecord {
+            operation: 42,
+            ..record
+        };
+        assert_eq!(
+            Err(AuthorizerError::InvalidAcl(
+                "unknown AclOperation code 42".to_string()
+            )),
+            StandardAcl::from_record(&invalid)
+        );
+    }
+
+    #[test]
+    fn test_implied_operations() {
+        assert!(AclOperation::Write.allows(AclOperation::Describe));
+        assert!(AclOperation::AlterConfigs.allows(AclOperation::DescribeConfigs));
+        assert!(AclOperation::All.allows(AclOperation::ClusterAction));
+        assert!(!AclOperation::Describe.allows(AclOperation::Read));
+        assert!(!AclOperation::Read.allows(AclOperation::DescribeConfigs));
+    }
+}
@@ -0,0 +1,434 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::image::metadata_publisher::{MetadataSubscriber, MetadataUpdate};
+use crate::metadata::authorizer::acl_index::AclIndex;
+use crate::metadata::authorizer::standard_acl::{
+    AclOperation, AclPermissionType, ResourceType, StandardAcl,
+};
+use crate::metadata::authorizer::{AuthorizerError, Result};
+use rafka_clients::common::uuid::Uuid;
+use std::collections::HashSet;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::{info, warn};
+
+/// Who sends a request, and where it was received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizableRequestContext {
+    /// The authenticated principal, e.g. `User:alice`.
+    pub principal: String,
+    /// The address of the client.
+    pub host: String,
+    /// The listener the request was received on.
+    pub listener_name: String,
+}
+
+/// An operation a request performs on a resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Action {
+    pub operation: AclOperation,
+    pub resource_type: ResourceType,
+    pub resource_name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthorizationResult {
+    Allowed,
+    Denied,
+}
+
+#[derive(Debug, Default)]
+struct AclData {
+    acls: AclIndex,
+    /// Whether the ACLs of the metadata log, up to its high watermark when the node started,
+    /// were all loaded.
+    loaded: bool,
+}
+
+/// Authorizes requests with the ACLs of the metadata log.
+///
+/// Until [complete_initial_load](Self::complete_initial_load) is called, the authorizer only
+/// knows part of the ACLs, e.g. the bootstrap ACLs the storage was formatted with, and
+/// authorizing with them could allow what an ACL further in the log denies. So it fails
+/// closed: requests get [AuthorizerError::NotReady], except the requests received on the
+/// `early.start.listeners`, typically the controller listeners, which must be served for
+/// the node to catch up with the metadata log in the first place. These are authorized with
+/// the ACLs loaded so far, which is why the ACLs the controllers and brokers need to talk to
+/// each other should be seeded when the storage is formatted.
+#[derive(Debug, Default)]
+pub struct StandardAuthorizer {
+    /// The principals allowed everything, without ACLs.
+    super_users: HashSet<String>,
+    /// Whether a resource no ACL applies to is accessible to everyone.
+    allow_everyone_if_no_acl_found: bool,
+    early_start_listeners: HashSet<String>,
+    data: RwLock<AclData>,
+}
+
+impl StandardAuthorizer {
+    pub fn with_super_users(mut self, super_users: &[&str]) -> Self {
+        self.super_users = super_users.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_allow_everyone_if_no_acl_found(mut self, allow: bool) -> Self {
+        self.allow_everyone_if_no_acl_found = allow;
+        self
+    }
+
+    pub fn with_early_start_listeners(mut self, listeners: &[&str]) -> Self {
+        self.early_start_listeners = listeners.iter().map(|l| l.to_uppercase()).collect();
+        self
+    }
+
+    fn data(&self) -> RwLockReadGuard<'_, AclData> {
+        self.data.read().expect("ACL data lock poisoned")
+    }
+
+    fn data_mut(&self) -> RwLockWriteGuard<'_, AclData> {
+        self.data.write().expect("ACL data lock poisoned")
+    }
+
+    pub fn add_acl(&self, id: Uuid, acl: StandardAcl) {
+        self.data_mut().acls.insert(id, acl);
+    }
+
+    pub fn remove_acl(&self, id: Uuid) {
+        self.data_mut().acls.remove(id);
+    }
+
+    /// Applies the ACL records of the metadata log, ignoring the other records.
+    pub fn replay(&self, record: &MetadataRecord) -> Result<()> {
+        match record {
+            MetadataRecord::AccessControlEntry(record) => {
+                self.add_acl(record.id, StandardAcl::from_record(record)?)
+            }
+            MetadataRecord::RemoveAccessControlEntry(record) => self.remove_acl(record.id),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Replaces the ACLs with the ACL records of `records`, e.g. of a snapshot, skipping the
+    /// invalid ones.
+    fn load_acls(&self, records: &[MetadataRecord]) {
+        let mut acls = AclIndex::default();
+        for record in records {
+            if let MetadataRecord::AccessControlEntry(record) = record {
+                match StandardAcl::from_record(record) {
+                    Ok(acl) => acls.insert(record.id, acl),
+                    Err(e) => warn!("Skipping ACL {}: {e}", record.id),
+                }
+            }
+        }
+        self.data_mut().acls = acls;
+    }
+
+    /// Marks the ACLs as loaded, once the node replayed the metadata log up to the high
+    /// watermark it found when starting.
+    pub fn complete_initial_load(&self) {
+        let mut data = self.data_mut();
+        if !data.loaded {
+            info!("Completed the initial load of {} ACLs", data.acls.len());
+            data.loaded = true;
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.data().loaded
+    }
+
+    /// The number of ACLs.
+    pub fn acl_count(&self) -> usize {
+        self.data().acls.len()
+    }
+
+    /// Decides whether the request of `context` may perform `action`: a deny ACL applying to
+    /// the request wins over allow ACLs, and a request no ACL allows is denied.
+    ///
+    /// Super users are allowed without looking at the ACLs, and only the ACLs indexed under
+    /// the resource are looked at, up to the first deny ACL applying to the request, so the
+    /// cost doesn't grow with the number of ACLs of other resources.
+    pub fn authorize(
+        &self,
+        context: &AuthorizableRequestContext,
+        action: &Action,
+    ) -> Result<AuthorizationResult> {
+        let data = self.data();
+        if !data.loaded
+            && !self
+                .early_start_listeners
+                .contains(&context.listener_name.to_uppercase())
+        {
+            return Err(AuthorizerError::NotReady);
+        }
+        if self.super_users.contains(&context.principal) {
+            return Ok(AuthorizationResult::Allowed);
+        }
+
+        let mut found = false;
+        let mut allowed = false;
+        let denied =
+            !data
+                .acls
+                .for_each_matching(action.resource_type, &action.resource_name, |acl| {
+                    found = true;
+                    if !acl.matches_principal_and_host(&context.principal, &context.host) {
+                        return true;
+                    }
+                    match acl.permission_type {
+                        AclPermissionType::Deny => {
+                            acl.operation != action.operation && acl.operation != AclOperation::All
+                        }
+                        AclPermissionType::Allow => {
+                            allowed |= acl.operation.allows(action.operation);
+                            true
+                        }
+                    }
+                });
+        let allowed = if denied {
+            false
+        } else if found {
+            allowed
+        } else {
+            self.allow_everyone_if_no_acl_found
+        };
+        Ok(if allowed {
+            AuthorizationResult::Allowed
+        } else {
+            AuthorizationResult::Denied
+        })
+    }
+}
+
+/// Keeps the ACLs up to date with the metadata log, as the broker replays it.
+impl MetadataSubscriber for StandardAuthorizer {
+    fn name(&self) -> &str {
+        "StandardAuthorizer"
+    }
+
+    fn on_update(&self, update: &MetadataUpdate<'_>) {
+        match update {
+            MetadataUpdate::Delta(records) => {
+                for record in *records {
+                    if let Err(e) = self.replay(record) {
+                        warn!("Skipping an ACL change: {e}");
+                    }
+                }
+            }
+            MetadataUpdate::Snapshot(records) => self.load_acls(records),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::RemoveAccessControlEntryRecord;
+    use crate::metadata::authorizer::standard_acl::{PatternType, WILDCARD};
+
+    fn acl(
+        resource_name: &str,
+        pattern_type: PatternType,
+        principal: &str,
+        operation: AclOperation,
+        permission_type: AclPermissionType,
+    ) -> StandardAcl {
+        StandardAcl {
+            resource_type: ResourceType::Topic,
+            resource_name: resource_name.to_string(),
+            pattern_type,
+            principal: principal.to_string(),
+            host: WILDCARD.to_string(),
+            operation,
+            permission_type,
+        }
+    }
+
+    fn context(principal: &str, listener_name: &str) -> AuthorizableRequestContext {
+        AuthorizableRequestContext {
+            principal: principal.to_string(),
+            host: "10.0.0.1".to_string(),
+            listener_name: listener_name.to_string(),
+        }
+    }
+
+    fn read(topic: &str) -> Action {
+        Action {
+            operation: AclOperation::Read,
+            resource_type: ResourceType::Topic,
+            resource_name: topic.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_fails_closed_until_the_initial_load() {
+        let authorizer = StandardAuthorizer::default().with_early_start_listeners(&["controller"]);
+        let bootstrap = acl(
+            "foo",
+            PatternType::Literal,
+            "User:broker",
+            AclOperation::All,
+            AclPermissionType::Allow,
+        );
+        authorizer
+            .replay(&MetadataRecord::AccessControlEntry(
+                bootstrap.to_record(Uuid::new(1, 1)),
+            ))
+            .unwrap();
+
+        assert_eq!(
+            Err(AuthorizerError::NotReady),
+            authorizer.authorize(&context("User:broker", "PLAINTEXT"), &read("foo"))
+        );
+        // The early start listeners are served with the ACLs loaded so far.
+        assert_eq!(
+            Ok(AuthorizationResult::Allowed),
+            authorizer.authorize(&context("User:broker", "CONTROLLER"), &read("foo"))
+        );
+
+        authorizer.complete_initial_load();
+        assert!(authorizer.is_ready());
+        assert_eq!(
+            Ok(AuthorizationResult::Allowed),
+            authorizer.authorize(&context("User:broker", "PLAINTEXT"), &read("foo"))
+        );
+    }
+
+    #[test]
+    fn test_deny_acls_win() {
+        let authorizer = StandardAuthorizer::default().with_super_users(&["User:admin"]);
+        authorizer.add_acl(
+            Uuid::new(1, 1),
+            acl(
+                "orders-",
+                PatternType::Prefixed,
+                "User:*",
+                AclOperation::Read,
+                AclPermissionType::Allow,
+            ),
+        );
+        authorizer.add_acl(
+            Uuid::new(1, 2),
+            acl(
+                "orders-secret",
+                PatternType::Literal,
+                "User:mallory",
+                AclOperation::All,
+                AclPermissionType::Deny,
+            ),
+        );
+        authorizer.complete_initial_load();
+
+        let authorize = |principal, topic| {
+            authorizer
+                .authorize(&context(principal, "PLAINTEXT"), &read(topic))
+                .unwrap()
+        };
+        assert_eq!(
+            AuthorizationResult::Allowed,
+            authorize("User:alice", "orders-secret")
+        );
+        assert_eq!(
+            AuthorizationResult::Denied,
+            authorize("User:mallory", "orders-secret")
+        );
+        assert_eq!(
+            AuthorizationResult::Allowed,
+            authorize("User:mallory", "orders-public")
+        );
+        assert_eq!(
+            AuthorizationResult::Denied,
+            authorize("User:alice", "other")
+        );
+        assert_eq!(
+            AuthorizationResult::Allowed,
+            authorize("User:admin", "orders-secret")
+        );
+
+        authorizer.remove_acl(Uuid::new(1, 2));
+        assert_eq!(
+            AuthorizationResult::Allowed,
+            authorize("User:mallory", "orders-secret")
+        );
+    }
+
+    #[test]
+    fn test_authorize_with_many_acls() {
+        let authorizer = StandardAuthorizer::default();
+        for i in 0..100_000 {
+            let (name, pattern_type) = if i % 2 == 0 {
+                (format!("topic-{i}"), PatternType::Literal)
+            } else {
+                (format!("prefix-{i}-"), PatternType::Prefixed)
+            };
+            authorizer.add_acl(
+                Uuid::new(0, i),
+                acl(
+                    &name,
+                    pattern_type,
+                    "User:alice",
+                    AclOperation::Read,
+                    AclPermissionType::Allow,
+                ),
+            );
+        }
+        authorizer.complete_initial_load();
+        assert_eq!(100_000, authorizer.acl_count());
+
+        let authorize = |topic: &str| {
+            authorizer
+                .authorize(&context("User:alice", "PLAINTEXT"), &read(topic))
+                .unwrap()
+        };
+        assert_eq!(AuthorizationResult::Allowed, authorize("topic-42"));
+        assert_eq!(AuthorizationResult::Allowed, authorize("prefix-43-orders"));
+        assert_eq!(AuthorizationResult::Denied, authorize("topic-43"));
+        assert_eq!(AuthorizationResult::Denied, authorize("prefix-42-orders"));
+    }
+
+    #[test]
+    fn test_allow_everyone_if_no_acl_found() {
+        let authorizer = StandardAuthorizer::default().with_allow_everyone_if_no_acl_found(true);
+        authorizer.complete_initial_load();
+        assert_eq!(
+            Ok(AuthorizationResult::Allowed),
+            authorizer.authorize(&context("User:alice", "PLAINTEXT"), &read("foo"))
+        );
+    }
+
+    #[test]
+    fn test_acls_follow_metadata_updates() {
+        let authorizer = StandardAuthorizer::default();
+        authorizer.complete_initial_load();
+        let allow = |principal: &str| {
+            acl(
+                "foo",
+                PatternType::Literal,
+                principal,
+                AclOperation::Read,
+                AclPermissionType::Allow,
+            )
+        };
+        let (alice, bob) = (Uuid::new(1, 1), Uuid::new(1, 2));
+        authorizer.on_update(&MetadataUpdate::Snapshot(&[
+            MetadataRecord::AccessControlEntry(allow("User:alice").to_record(alice)),
+        ]));
+        assert_eq!(1, authorizer.acl_count());
+
+        authorizer.on_update(&MetadataUpdate::Delta(&[
+            MetadataRecord::AccessControlEntry(allow("User:bob").to_record(bob)),
+            MetadataRecord::RemoveAccessControlEntry(RemoveAccessControlEntryRecord { id: alice }),
+        ]));
+        assert_eq!(
+            Ok(AuthorizationResult::Denied),
+            authorizer.authorize(&context("User:alice", "PLAINTEXT"), &read("foo"))
+        );
+        assert_eq!(
+            Ok(AuthorizationResult::Allowed),
+            authorizer.authorize(&context("User:bob", "PLAINTEXT"), &read("foo"))
+        );
+
+        // A snapshot replaces the ACLs.
+        authorizer.on_update(&MetadataUpdate::Snapshot(&[]));
+        assert_eq!(0, authorizer.acl_count());
+    }
+}
@@ -0,0 +1,211 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::metadata::bootstrap::bootstrap_metadata::BootstrapMetadata;
+use crate::metadata::bootstrap::{BootstrapError, Result};
+use rafka_server_common::metadata_version::MetadataVersion;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// The name of the file holding the bootstrap records in the metadata log directory.
+pub const BINARY_BOOTSTRAP_FILENAME: &str = "bootstrap.checkpoint";
+
+/// The size of the header preceding the records: the length of the records and their CRC32C.
+const HEADER_SIZE: usize = 8;
+
+/// Reads and writes the bootstrap checkpoint of a metadata log directory.
+///
+/// The file consists of the length of the serialized records as an `i32`, their CRC32C as a
+/// `u32`, and the records themselves.
+pub struct BootstrapDirectory {
+    directory_path: PathBuf,
+    ibp: Option<String>,
+}
+
+impl BootstrapDirectory {
+    /// Creates a handle for `directory_path`. `ibp` is the configured
+    /// `inter.broker.protocol.version`, used when there is no bootstrap file.
+    pub fn new(directory_path: impl Into<PathBuf>, ibp: Option<&str>) -> Self {
+        Self {
+            directory_path: directory_path.into(),
+            ibp: ibp.map(str::to_string),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.directory_path.join(BINARY_BOOTSTRAP_FILENAME)
+    }
+
+    /// Reads the bootstrap metadata.
+    ///
+    /// Directories formatted before bootstrap files existed have no checkpoint, in which case
+    /// the metadata version is taken from `ibp`, or defaults to the latest production version.
+    pub fn read(&self) -> Result<BootstrapMetadata> {
+        if !self.directory_path.is_dir() {
+            return Err(BootstrapError::MissingDirectory(
+                self.directory_path.clone(),
+            ));
+        }
+        let path = self.path();
+        match fs::read(&path) {
+            Ok(data) => read_records(&path, &data),
+            Err(e) if e.kind() == ErrorKind::NotFound => self.read_from_configuration(),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_from_configuration(&self) -> Result<BootstrapMetadata> {
+        match &self.ibp {
+            None => {
+                info!(
+                    "No {} file found, defaulting to metadata.version {}",
+                    BINARY_BOOTSTRAP_FILENAME,
+                    MetadataVersion::LATEST_PRODUCTION.version()
+                );
+                Ok(BootstrapMetadata::from_version(
+                    MetadataVersion::LATEST_PRODUCTION,
+                    "the default bootstrap",
+                ))
+            }
+            Some(ibp) => {
+                let version = MetadataVersion::from_version_string(ibp)
+                    .ok_or_else(|| BootstrapError::UnknownReleaseVersion(ibp.clone()))?;
+                info!(
+                    "No {} file found, using metadata.version {} from the configured \
+                    inter.broker.protocol.version",
+                    BINARY_BOOTSTRAP_FILENAME,
+                    version.version()
+                );
+                Ok(BootstrapMetadata::from_version(
+                    version,
+                    "the configured inter.broker.protocol.version",
+                ))
+            }
+        }
+    }
+
+    /// Writes the bootstrap metadata. This is done once, when the storage is formatted.
+    ///
+    /// The file is written under a temporary name and then renamed, so a crash never leaves
+    /// a partial checkpoint behind.
+    pub fn write(&self, bootstrap: &BootstrapMetadata) -> Result<()> {
+        if !self.directory_path.is_dir() {
+            return Err(BootstrapError::MissingDirectory(
+                self.directory_path.clone(),
+            ));
+        }
+        let path = self.path();
+        if path.exists() {
+            return Err(BootstrapError::AlreadyExists(path));
+        }
+        let mut records = Vec::new();
+        for record in bootstrap.records() {
+            record.write(&mut records);
+        }
+        let tmp_path = path.with_extension("checkpoint.tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(&(records.len() as i32).to_be_bytes())?;
+            file.write_all(&crc32c::crc32c(&records).to_be_bytes())?;
+            file.write_all(&records)?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+}
+
+fn read_records(path: &Path, data: &[u8]) -> Result<BootstrapMetadata> {
+    let corrupt = |reason: &str| BootstrapError::Corrupt {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    if data.len() < HEADER_SIZE {
+        return Err(corrupt("the header is truncated"));
+    }
+    let (header, mut records) = data.split_at(HEADER_SIZE);
+    let len = i32::from_be_bytes(header[..4].try_into().expect("4 bytes"));
+    let crc = u32::from_be_bytes(header[4..].try_into().expect("4 bytes"));
+    if len < 0 || len as usize != records.len() {
+        return Err(corrupt("the length does not match the file size"));
+    }
+    if crc32c::crc32c(records) != crc {
+        return Err(corrupt("the checksum does not match"));
+    }
+    let mut result = Vec::new();
+    while !records.is_empty() {
+        result.push(MetadataRecord::read(&mut records)?);
+    }
+    BootstrapMetadata::from_records(
+        result,
+        &format!("the binary bootstrap metadata file: {}", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::ConfigRecord;
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = BootstrapDirectory::new(dir.path(), None);
+        let mut records = BootstrapMetadata::from_version(MetadataVersion::Ibp3_8Iv0, "test")
+            .records()
+            .to_vec();
+        records.push(MetadataRecord::Config(ConfigRecord {
+            resource_type: 4,
+            resource_name: "".to_string(),
+            name: "num.io.threads".to_string(),
+            value: Some("8".to_string()),
+        }));
+        let bootstrap = BootstrapMetadata::from_records(records, "test").unwrap();
+        directory.write(&bootstrap).unwrap();
+
+        let read = directory.read().unwrap();
+        assert_eq!(bootstrap.records(), read.records());
+        assert_eq!(MetadataVersion::Ibp3_8Iv0, read.metadata_version());
+        assert!(matches!(
+            directory.write(&bootstrap),
+            Err(BootstrapError::AlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn test_missing_file_falls_back_to_configuration() {
+        let dir = tempfile::tempdir().unwrap();
+        let read = BootstrapDirectory::new(dir.path(), None).read().unwrap();
+        assert_eq!(MetadataVersion::LATEST_PRODUCTION, read.metadata_version());
+
+        let read = BootstrapDirectory::new(dir.path(), Some("3.5"))
+            .read()
+            .unwrap();
+        assert_eq!(MetadataVersion::Ibp3_5Iv2, read.metadata_version());
+
+        assert!(matches!(
+            BootstrapDirectory::new(dir.path().join("missing"), None).read(),
+            Err(BootstrapError::MissingDirectory(_))
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = BootstrapDirectory::new(dir.path(), None);
+        directory
+            .write(&BootstrapMetadata::from_version(
+                MetadataVersion::Ibp3_7Iv4,
+                "test",
+            ))
+            .unwrap();
+        let path = dir.path().join(BINARY_BOOTSTRAP_FILENAME);
+        let mut data = fs::read(&path).unwrap();
+        *data.last_mut().unwrap() ^= 0xff;
+        fs::write(&path, data).unwrap();
+        assert!(matches!(
+            directory.read(),
+            Err(BootstrapError::Corrupt { .. })
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::common::metadata::metadata_record::MetadataRecord;
+use crate::common::metadata::records::FeatureLevelRecord;
+use crate::metadata::authorizer::standard_acl::StandardAcl;
+use crate::metadata::bootstrap::{BootstrapError, Result};
+use rafka_clients::common::uuid::Uuid;
+use rafka_server_common::metadata_version::MetadataVersion;
+
+/// The records a new cluster is initialized with.
+///
+/// They are produced when the storage is formatted and appended by the first controller to
+/// become leader of an empty metadata log. Besides the mandatory `metadata.version` feature
+/// level they may carry SCRAM credentials, ACLs and initial dynamic configurations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootstrapMetadata {
+    records: Vec<MetadataRecord>,
+    metadata_version: MetadataVersion,
+    source: String,
+}
+
+impl BootstrapMetadata {
+    /// Creates bootstrap metadata which only sets the metadata version.
+    pub fn from_version(metadata_version: MetadataVersion, source: &str) -> Self {
+        Self {
+            records: vec![MetadataRecord::FeatureLevel(FeatureLevelRecord {
+                name: MetadataVersion::FEATURE_NAME.to_string(),
+                feature_level: metadata_version.feature_level(),
+            })],
+            metadata_version,
+            source: source.to_string(),
+        }
+    }
+
+    /// Creates bootstrap metadata from a list of records, which must set a supported
+    /// `metadata.version`.
+    pub fn from_records(records: Vec<MetadataRecord>, source: &str) -> Result<Self> {
+        let level = records
+            .iter()
+            .rev()
+            .find_map(|record| match record {
+                MetadataRecord::FeatureLevel(r) if r.name == MetadataVersion::FEATURE_NAME => {
+                    Some(r.feature_level)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| BootstrapError::MissingMetadataVersion(source.to_string()))?;
+        let metadata_version = MetadataVersion::from_feature_level(level)
+            .ok_or(BootstrapError::UnsupportedMetadataVersion(level))?;
+        if !metadata_version.is_scram_supported()
+            && records
+                .iter()
+                .any(|r| matches!(r, MetadataRecord::UserScramCredential(_)))
+        {
+            return Err(BootstrapError::ScramNotSupported(metadata_version));
+        }
+        Ok(Self {
+            records,
+            metadata_version,
+            source: source.to_string(),
+        })
+    }
+
+    /// Adds ACLs to the bootstrap records, so that they are in force from the start of the
+    /// metadata log. This is how the ACLs the nodes need to talk to each other are seeded:
+    /// the authorizer of a starting node only applies the ACLs loaded so far.
+    pub fn with_acls(mut self, acls: &[StandardAcl]) -> Self {
+        self.records.extend(
+            acls.iter()
+                .map(|acl| MetadataRecord::AccessControlEntry(acl.to_record(Uuid::random()))),
+        );
+        self
+    }
+
+    pub fn records(&self) -> &[MetadataRecord] {
+        &self.records
+    }
+
+    pub fn metadata_version(&self) -> MetadataVersion {
+        self.metadata_version
+    }
+
+    /// A description of where the metadata came from, used in log messages.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the level the bootstrap records set for `feature`, if any.
+    pub fn feature_level(&self, feature: &str) -> Option<i16> {
+        self.records.iter().rev().find_map(|record| match record {
+            MetadataRecord::FeatureLevel(r) if r.name == feature => Some(r.feature_level),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::metadata::records::UserScramCredentialRecord;
+    use crate::metadata::authorizer::standard_acl::{
+        AclOperation, AclPermissionType, PatternType, ResourceType,
+    };
+
+    fn scram_record() -> MetadataRecord {
+        MetadataRecord::UserScramCredential(UserScramCredentialRecord {
+            name: "alice".to_string(),
+            mechanism: 1,
+            salt: vec![1],
+            stored_key: vec![2],
+            server_key: vec![3],
+            iterations: 4096,
+        })
+    }
+
+    #[test]
+    fn test_from_records() {
+        let mut records = BootstrapMetadata::from_version(MetadataVersion::Ibp3_7Iv4, "test")
+            .records()
+            .to_vec();
+        records.push(scram_record());
+        let bootstrap = BootstrapMetadata::from_records(records, "test").unwrap();
+        assert_eq!(MetadataVersion::Ibp3_7Iv4, bootstrap.metadata_version());
+        assert_eq!(
+            Some(19),
+            bootstrap.feature_level(MetadataVersion::FEATURE_NAME)
+        );
+    }
+
+    #[test]
+    fn test_with_acls() {
+        let acl = StandardAcl {
+            resource_type: ResourceType::Cluster,
+            resource_name: "kafka-cluster".to_string(),
+            pattern_type: PatternType::Literal,
+            principal: "User:broker".to_string(),
+            host: "*".to_string(),
+            operation: AclOperation::ClusterAction,
+            permission_type: AclPermissionType::Allow,
+        };
+        let bootstrap = BootstrapMetadata::from_version(MetadataVersion::Ibp3_7Iv4, "test")
+            .with_acls(std::slice::from_ref(&acl));
+        let bootstrap =
+            BootstrapMetadata::from_records(bootstrap.records().to_vec(), "test").unwrap();
+        let acls: Vec<StandardAcl> = bootstrap
+            .records()
+            .iter()
+            .filter_map(|record| match record {
+                MetadataRecord::AccessControlEntry(record) => {
+                    Some(StandardAcl::from_record(record).unwrap())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![acl], acls);
+    }
+
+    #[test]
+    fn test_from_records_requires_a_supported_metadata_version() {
+        assert!(matches!(
+            BootstrapMetadata::from_records(vec![scram_record()], "test"),
+            Err(BootstrapError::MissingMetadataVersion(source)) if source == "test"
+        ));
+
+        let old = BootstrapMetadata::from_version(MetadataVersion::Ibp3_4Iv0, "test");
+        let mut records = old.records().to_vec();
+        records.push(scram_record());
+        assert!(matches!(
+            BootstrapMetadata::from_records(records, "test"),
+            Err(BootstrapError::ScramNotSupported(
+                MetadataVersion::Ibp3_4Iv0
+            ))
+        ));
+    }
+}
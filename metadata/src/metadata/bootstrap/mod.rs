@@ -0,0 +1,40 @@
+use crate::common::metadata::metadata_record::RecordError;
+use rafka_server_common::metadata_version::MetadataVersion;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub mod bootstrap_directory;
+pub mod bootstrap_metadata;
+
+#[derive(Error, Debug)]
+pub enum BootstrapError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Failed to read bootstrap records: {0}")]
+    Record(#[from] RecordError),
+
+    #[error("No metadata.version feature level record was found in {0}")]
+    MissingMetadataVersion(String),
+
+    #[error("Unsupported metadata.version feature level {0}")]
+    UnsupportedMetadataVersion(i16),
+
+    #[error("SCRAM credentials are not supported by metadata.version {}", .0.version())]
+    ScramNotSupported(MetadataVersion),
+
+    #[error("Unknown release version {0}")]
+    UnknownReleaseVersion(String),
+
+    #[error("The bootstrap file {} is corrupt: {reason}", .path.display())]
+    Corrupt { path: PathBuf, reason: String },
+
+    #[error("The directory {} does not exist", .0.display())]
+    MissingDirectory(PathBuf),
+
+    #[error("The bootstrap file {} already exists", .0.display())]
+    AlreadyExists(PathBuf),
+}
+
+pub type Result<T> = std::result::Result<T, BootstrapError>;
@@ -0,0 +1,149 @@
+use crate::common::metadata::records::{
+    BrokerEndpoint, BrokerFeature, BrokerRegistrationChangeRecord, RegisterBrokerRecord,
+};
+use rafka_clients::common::uuid::Uuid;
+use std::collections::BTreeMap;
+
+/// The range of versions of a feature a broker supports, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min: i16,
+    pub max: i16,
+}
+
+impl VersionRange {
+    pub fn new(min: i16, max: i16) -> Self {
+        Self { min, max }
+    }
+
+    pub fn contains(&self, version: i16) -> bool {
+        (self.min..=self.max).contains(&version)
+    }
+}
+
+/// A broker registration, as replayed from the metadata log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerRegistration {
+    id: i32,
+    epoch: i64,
+    incarnation_id: Uuid,
+    listeners: Vec<BrokerEndpoint>,
+    supported_features: BTreeMap<String, VersionRange>,
+    rack: Option<String>,
+    fenced: bool,
+    in_controlled_shutdown: bool,
+    directories: Vec<Uuid>,
+}
+
+impl BrokerRegistration {
+    pub fn from_record(record: &RegisterBrokerRecord) -> Self {
+        Self {
+            id: record.broker_id,
+            epoch: record.broker_epoch,
+            incarnation_id: record.incarnation_id,
+            listeners: record.end_points.clone(),
+            supported_features: record
+                .features
+                .iter()
+                .map(|f| {
+                    (
+                        f.name.clone(),
+                        VersionRange::new(f.min_supported_version, f.max_supported_version),
+                    )
+                })
+                .collect(),
+            rack: record.rack.clone(),
+            fenced: record.fenced,
+            in_controlled_shutdown: record.in_controlled_shutdown,
+            directories: record.log_dirs.clone(),
+        }
+    }
+
+    pub fn to_record(&self) -> RegisterBrokerRecord {
+        RegisterBrokerRecord {
+            broker_id: self.id,
+            incarnation_id: self.incarnation_id,
+            broker_epoch: self.epoch,
+            end_points: self.listeners.clone(),
+            features: self
+                .supported_features
+                .iter()
+                .map(|(name, range)| BrokerFeature {
+                    name: name.clone(),
+                    min_supported_version: range.min,
+                    max_supported_version: range.max,
+                })
+                .collect(),
+            rack: self.rack.clone(),
+            fenced: self.fenced,
+            in_controlled_shutdown: self.in_controlled_shutdown,
+            log_dirs: self.directories.clone(),
+        }
+    }
+
+    /// Applies a fencing or controlled shutdown change.
+    pub fn apply_change(&mut self, record: &BrokerRegistrationChangeRecord) {
+        match record.fenced {
+            -1 => self.fenced = false,
+            1 => self.fenced = true,
+            _ => {}
+        }
+        if record.in_controlled_shutdown == 1 {
+            self.in_controlled_shutdown = true;
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn epoch(&self) -> i64 {
+        self.epoch
+    }
+
+    pub fn incarnation_id(&self) -> Uuid {
+        self.incarnation_id
+    }
+
+    pub fn listeners(&self) -> &[BrokerEndpoint] {
+        &self.listeners
+    }
+
+    /// The endpoint of the listener with the given name.
+    pub fn listener(&self, name: &str) -> Option<&BrokerEndpoint> {
+        self.listeners.iter().find(|l| l.name == name)
+    }
+
+    pub fn supported_features(&self) -> &BTreeMap<String, VersionRange> {
+        &self.supported_features
+    }
+
+    pub fn rack(&self) -> Option<&str> {
+        self.rack.as_deref()
+    }
+
+    pub fn fenced(&self) -> bool {
+        self.fenced
+    }
+
+    pub fn in_controlled_shutdown(&self) -> bool {
+        self.in_controlled_shutdown
+    }
+
+    /// Whether the broker may lead partitions, be advertised to clients and receive new
+    /// replicas. Fenced brokers and brokers in controlled shutdown are still registered and
+    /// still count as replicas of their partitions, but are not available.
+    pub fn is_available(&self) -> bool {
+        !self.fenced && !self.in_controlled_shutdown
+    }
+
+    /// The ids of the broker's online log directories, sorted.
+    pub fn directories(&self) -> &[Uuid] {
+        &self.directories
+    }
+
+    /// Whether `directory` is one of this broker's log directories.
+    pub fn has_online_dir(&self, directory: Uuid) -> bool {
+        self.directories.binary_search(&directory).is_ok()
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+/// The state of a broker process, from its start until it shuts down.
+///
+/// The values are those of the `BrokerState` metric, which operators use to track long
+/// restarts: a broker spends most of those in [BrokerState::Recovery], recovering the logs it
+/// did not shut down cleanly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BrokerState {
+    /// The broker is not running.
+    #[default]
+    NotRunning,
+    /// The broker is starting up and catching up with the metadata log.
+    Starting,
+    /// The broker is recovering its logs after an unclean shutdown.
+    Recovery,
+    /// The broker is registered and serves requests.
+    Running,
+    /// The broker is moving its leaderships away before shutting down.
+    PendingControlledShutdown,
+    /// The broker is shutting down.
+    ShuttingDown,
+}
+
+impl BrokerState {
+    pub fn value(&self) -> u8 {
+        match self {
+            BrokerState::NotRunning => 0,
+            BrokerState::Starting => 1,
+            BrokerState::Recovery => 2,
+            BrokerState::Running => 3,
+            BrokerState::PendingControlledShutdown => 6,
+            BrokerState::ShuttingDown => 7,
+        }
+    }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(BrokerState::NotRunning),
+            1 => Some(BrokerState::Starting),
+            2 => Some(BrokerState::Recovery),
+            3 => Some(BrokerState::Running),
+            6 => Some(BrokerState::PendingControlledShutdown),
+            7 => Some(BrokerState::ShuttingDown),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BrokerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrokerState::NotRunning => write!(f, "NOT_RUNNING"),
+            BrokerState::Starting => write!(f, "STARTING"),
+            BrokerState::Recovery => write!(f, "RECOVERY"),
+            BrokerState::Running => write!(f, "RUNNING"),
+            BrokerState::PendingControlledShutdown => write!(f, "PENDING_CONTROLLED_SHUTDOWN"),
+            BrokerState::ShuttingDown => write!(f, "SHUTTING_DOWN"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_round_trip() {
+        for state in [
+            BrokerState::NotRunning,
+            BrokerState::Starting,
+            BrokerState::Recovery,
+            BrokerState::Running,
+            BrokerState::PendingControlledShutdown,
+            BrokerState::ShuttingDown,
+        ] {
+            assert_eq!(Some(state), BrokerState::from_value(state.value()));
+        }
+        assert_eq!(None, BrokerState::from_value(4));
+    }
+}
@@ -0,0 +1,5 @@
+pub mod authorizer;
+pub mod bootstrap;
+pub mod broker_registration;
+pub mod broker_state;
+pub mod partition_registration;
@@ -0,0 +1,44 @@
+use crate::common::metadata::records::PartitionRecord;
+use rafka_clients::common::uuid::Uuid;
+
+/// The replicas and leadership of a partition, as replayed from the metadata log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionRegistration {
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+    /// The leader, or -1 if the partition has none.
+    pub leader: i32,
+    pub leader_epoch: i32,
+    pub partition_epoch: i32,
+}
+
+impl PartitionRegistration {
+    /// The leader value of a partition without a leader.
+    pub const NO_LEADER: i32 = -1;
+
+    pub fn from_record(record: &PartitionRecord) -> Self {
+        Self {
+            replicas: record.replicas.clone(),
+            isr: record.isr.clone(),
+            leader: record.leader,
+            leader_epoch: record.leader_epoch,
+            partition_epoch: record.partition_epoch,
+        }
+    }
+
+    pub fn to_record(&self, topic_id: Uuid, partition_id: i32) -> PartitionRecord {
+        PartitionRecord {
+            partition_id,
+            topic_id,
+            replicas: self.replicas.clone(),
+            isr: self.isr.clone(),
+            leader: self.leader,
+            leader_epoch: self.leader_epoch,
+            partition_epoch: self.partition_epoch,
+        }
+    }
+
+    pub fn has_leader(&self) -> bool {
+        self.leader != Self::NO_LEADER
+    }
+}
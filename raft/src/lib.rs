@@ -0,0 +1,13 @@
+pub use raft::{
+    batch::Batch,
+    batch_reader::BatchReader,
+    kraft_version::{KRaftVersion, SupportedVersionRange},
+    leader_and_epoch::LeaderAndEpoch,
+    local_raft_client,
+    offset_and_epoch::OffsetAndEpoch,
+    quorum_config::QuorumConfig,
+    raft_client,
+    snapshot_reader::SnapshotReader,
+    voter_set::VoterSet,
+};
+mod raft;
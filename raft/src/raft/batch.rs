@@ -0,0 +1,68 @@
+/// A batch of records committed to the replicated log.
+///
+/// All records in the batch share the epoch of the leader which appended them, and occupy
+/// consecutive offsets starting at `base_offset`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch<T> {
+    base_offset: i64,
+    epoch: i32,
+    append_timestamp: i64,
+    size_in_bytes: i32,
+    records: Vec<T>,
+}
+
+impl<T> Batch<T> {
+    /// Creates a data batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `records` is empty, a data batch always contains at least one record.
+    pub fn data(
+        base_offset: i64,
+        epoch: i32,
+        append_timestamp: i64,
+        size_in_bytes: i32,
+        records: Vec<T>,
+    ) -> Self {
+        assert!(
+            !records.is_empty(),
+            "Batch must contain at least one record"
+        );
+        Self {
+            base_offset,
+            epoch,
+            append_timestamp,
+            size_in_bytes,
+            records,
+        }
+    }
+
+    pub fn base_offset(&self) -> i64 {
+        self.base_offset
+    }
+
+    /// The offset of the last record in the batch.
+    pub fn last_offset(&self) -> i64 {
+        self.base_offset + self.records.len() as i64 - 1
+    }
+
+    pub fn epoch(&self) -> i32 {
+        self.epoch
+    }
+
+    pub fn append_timestamp(&self) -> i64 {
+        self.append_timestamp
+    }
+
+    pub fn size_in_bytes(&self) -> i32 {
+        self.size_in_bytes
+    }
+
+    pub fn records(&self) -> &[T] {
+        &self.records
+    }
+
+    pub fn into_records(self) -> Vec<T> {
+        self.records
+    }
+}
@@ -0,0 +1,41 @@
+use crate::raft::batch::Batch;
+use std::collections::VecDeque;
+
+/// An iterator over committed batches handed to a [Listener](crate::raft_client::Listener).
+///
+/// The reader covers the contiguous offset range `[base_offset, last_offset]`.
+#[derive(Debug)]
+pub struct BatchReader<T> {
+    base_offset: i64,
+    last_offset: Option<i64>,
+    batches: VecDeque<Batch<T>>,
+}
+
+impl<T> BatchReader<T> {
+    pub fn new(base_offset: i64, batches: Vec<Batch<T>>) -> Self {
+        let last_offset = batches.last().map(Batch::last_offset);
+        Self {
+            base_offset,
+            last_offset,
+            batches: batches.into(),
+        }
+    }
+
+    /// The base offset of the first batch in this reader.
+    pub fn base_offset(&self) -> i64 {
+        self.base_offset
+    }
+
+    /// The last offset covered by this reader, or `None` if the reader is empty.
+    pub fn last_offset(&self) -> Option<i64> {
+        self.last_offset
+    }
+}
+
+impl<T> Iterator for BatchReader<T> {
+    type Item = Batch<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.batches.pop_front()
+    }
+}
@@ -0,0 +1,76 @@
+/// The versions of the raft protocol features, identified by their `kraft.version` feature
+/// level.
+///
+/// The finalized version decides which features the quorum may use: a feature is only
+/// enabled once every voter has advertised support for the version introducing it, so a
+/// voter running an older release never receives a request or record it can't handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KRaftVersion {
+    /// A static voter set, configured with `controller.quorum.voters`.
+    Kraft0,
+    /// Dynamic voters: the voter set is stored in the log and changed with AddRaftVoter and
+    /// RemoveRaftVoter.
+    Kraft1,
+}
+
+impl KRaftVersion {
+    /// The name of the feature which carries the kraft version.
+    pub const FEATURE_NAME: &'static str = "kraft.version";
+
+    /// The latest version this release supports.
+    pub const LATEST_PRODUCTION: KRaftVersion = KRaftVersion::Kraft1;
+
+    /// The permanent feature level of this version.
+    pub fn feature_level(&self) -> i16 {
+        match self {
+            KRaftVersion::Kraft0 => 0,
+            KRaftVersion::Kraft1 => 1,
+        }
+    }
+
+    pub fn from_feature_level(level: i16) -> Option<Self> {
+        match level {
+            0 => Some(KRaftVersion::Kraft0),
+            1 => Some(KRaftVersion::Kraft1),
+            _ => None,
+        }
+    }
+
+    /// Whether voters can be added and removed while the quorum is running.
+    pub fn is_reconfig_supported(&self) -> bool {
+        *self >= KRaftVersion::Kraft1
+    }
+
+    /// The range of versions this release supports.
+    pub fn supported_range() -> SupportedVersionRange {
+        SupportedVersionRange::new(
+            KRaftVersion::Kraft0.feature_level(),
+            Self::LATEST_PRODUCTION.feature_level(),
+        )
+    }
+}
+
+/// A range of feature levels a node supports, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupportedVersionRange {
+    min: i16,
+    max: i16,
+}
+
+impl SupportedVersionRange {
+    pub fn new(min: i16, max: i16) -> Self {
+        Self { min, max }
+    }
+
+    pub fn min(&self) -> i16 {
+        self.min
+    }
+
+    pub fn max(&self) -> i16 {
+        self.max
+    }
+
+    pub fn contains(&self, level: i16) -> bool {
+        (self.min..=self.max).contains(&level)
+    }
+}
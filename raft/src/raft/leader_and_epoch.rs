@@ -0,0 +1,33 @@
+/// The leader of the quorum as seen by the local node, and the epoch it was elected in.
+///
+/// A `None` leader id means the leader is not known for the epoch, for example while an
+/// election is in progress or after the previous leader resigned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaderAndEpoch {
+    leader_id: Option<i32>,
+    epoch: i32,
+}
+
+impl LeaderAndEpoch {
+    pub const UNKNOWN: LeaderAndEpoch = LeaderAndEpoch {
+        leader_id: None,
+        epoch: 0,
+    };
+
+    pub fn new(leader_id: Option<i32>, epoch: i32) -> Self {
+        Self { leader_id, epoch }
+    }
+
+    pub fn leader_id(&self) -> Option<i32> {
+        self.leader_id
+    }
+
+    pub fn epoch(&self) -> i32 {
+        self.epoch
+    }
+
+    /// Returns true if `node_id` is the known leader for this epoch.
+    pub fn is_leader(&self, node_id: i32) -> bool {
+        self.leader_id == Some(node_id)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::raft::batch::Batch;
+use crate::raft::batch_reader::BatchReader;
+use crate::raft::leader_and_epoch::LeaderAndEpoch;
+use crate::raft::raft_client::Listener;
+use crate::raft::snapshot_reader::SnapshotReader;
+
+/// Tracks how far a registered [Listener] has read and which leader change it last saw.
+///
+/// Corresponds to `KafkaRaftClient.ListenerContext` in the Java code.
+pub(crate) struct ListenerContext<T> {
+    listener: Box<dyn Listener<T>>,
+    /// The next offset the listener expects to receive.
+    next_offset: i64,
+    last_fired_leader_change: LeaderAndEpoch,
+}
+
+impl<T: Clone> ListenerContext<T> {
+    pub(crate) fn new(listener: Box<dyn Listener<T>>) -> Self {
+        Self::with_next_offset(listener, 0)
+    }
+
+    /// A context for a listener which already saw every batch below `next_offset`.
+    pub(crate) fn with_next_offset(listener: Box<dyn Listener<T>>, next_offset: i64) -> Self {
+        Self {
+            listener,
+            next_offset,
+            last_fired_leader_change: LeaderAndEpoch::UNKNOWN,
+        }
+    }
+
+    pub(crate) fn next_offset(&self) -> i64 {
+        self.next_offset
+    }
+
+    /// Delivers the committed batches the listener has not seen yet, i.e. those ending
+    /// below `high_watermark`.
+    pub(crate) fn fire_commit(&mut self, log: &[Batch<T>], high_watermark: i64) {
+        let pending: Vec<Batch<T>> = log
+            .iter()
+            .filter(|b| b.base_offset() >= self.next_offset && b.last_offset() < high_watermark)
+            .cloned()
+            .collect();
+        if let Some(last) = pending.last() {
+            self.next_offset = last.last_offset() + 1;
+            let base_offset = pending[0].base_offset();
+            self.listener
+                .handle_commit(BatchReader::new(base_offset, pending));
+        }
+    }
+
+    pub(crate) fn fire_load_snapshot(&mut self, reader: SnapshotReader<T>) {
+        self.next_offset = reader.snapshot_id().offset();
+        self.listener.handle_load_snapshot(reader);
+    }
+
+    /// Fires a leader change for a leader other than the local node.
+    pub(crate) fn maybe_fire_leader_change(&mut self, leader: LeaderAndEpoch) {
+        if self.should_fire_leader_change(leader) {
+            self.fire_leader_change(leader);
+        }
+    }
+
+    /// Fires a leader change where the local node is the new leader.
+    ///
+    /// The notification is held back until the listener has caught up to
+    /// `epoch_start_offset`, which guarantees it has seen the complete committed state
+    /// before it starts writing as leader.
+    pub(crate) fn maybe_fire_local_leader_change(
+        &mut self,
+        leader: LeaderAndEpoch,
+        epoch_start_offset: i64,
+    ) {
+        if self.should_fire_leader_change(leader) && self.next_offset >= epoch_start_offset {
+            self.fire_leader_change(leader);
+        }
+    }
+
+    pub(crate) fn begin_shutdown(&mut self) {
+        self.listener.begin_shutdown();
+    }
+
+    fn should_fire_leader_change(&self, leader: LeaderAndEpoch) -> bool {
+        if leader == self.last_fired_leader_change {
+            false
+        } else if leader.epoch() > self.last_fired_leader_change.epoch() {
+            true
+        } else {
+            leader.leader_id().is_some() && self.last_fired_leader_change.leader_id().is_none()
+        }
+    }
+
+    fn fire_leader_change(&mut self, leader: LeaderAndEpoch) {
+        self.last_fired_leader_change = leader;
+        self.listener.handle_leader_change(leader);
+    }
+}
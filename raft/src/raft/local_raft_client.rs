@@ -0,0 +1,980 @@
+//! An in-process [RaftClient] backed by a log shared between several nodes.
+//!
+//! Every append is committed immediately, and leadership is decided explicitly through
+//! [SharedLog::elect], or by the election timers of the clients driven by
+//! [LocalRaftClient::tick]. This mirrors `LocalLogManager` from the Java test suite and lets
+//! controllers and metadata loaders be exercised without a network or a real quorum.
+
+use crate::raft::batch::Batch;
+use crate::raft::kraft_version::{KRaftVersion, SupportedVersionRange};
+use crate::raft::leader_and_epoch::LeaderAndEpoch;
+use crate::raft::listener_context::ListenerContext;
+use crate::raft::offset_and_epoch::OffsetAndEpoch;
+use crate::raft::quorum_config::QuorumConfig;
+use crate::raft::raft_client::{Listener, RaftClient, RaftError, Result};
+use crate::raft::snapshot_reader::SnapshotReader;
+use crate::raft::voter_set::VoterSet;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+
+struct SharedLogData<T> {
+    batches: Vec<Batch<T>>,
+    /// The latest snapshot together with the batches it replaced.
+    snapshot: Option<(OffsetAndEpoch, i64, Vec<Batch<T>>)>,
+    leader: LeaderAndEpoch,
+    /// The log end offset at the time the current leader was elected.
+    epoch_start_offset: i64,
+    end_offset: i64,
+    voters: VoterSet,
+    kraft_version: KRaftVersion,
+    /// The nodes which failed to append to their log, which can't be elected anymore.
+    degraded: BTreeSet<i32>,
+    /// The nodes cut off from the others: they neither hear from the leader nor get votes.
+    isolated: BTreeSet<i32>,
+}
+
+impl<T> SharedLogData<T> {
+    fn log_start_offset(&self) -> i64 {
+        self.snapshot.as_ref().map_or(0, |(id, _, _)| id.offset())
+    }
+
+    fn elect(&mut self, node_id: i32) -> LeaderAndEpoch {
+        self.leader = LeaderAndEpoch::new(Some(node_id), self.leader.epoch() + 1);
+        self.epoch_start_offset = self.end_offset;
+        info!(
+            "Elected node {} as leader for epoch {}",
+            node_id,
+            self.leader.epoch()
+        );
+        self.leader
+    }
+
+    fn ensure_leader(&self, node_id: i32, epoch: i32) -> Result<()> {
+        if self.leader.epoch() != epoch || !self.leader.is_leader(node_id) {
+            return Err(RaftError::NotLeader { node_id, epoch });
+        }
+        Ok(())
+    }
+
+    fn ensure_reconfig_supported(&self) -> Result<()> {
+        if !self.kraft_version.is_reconfig_supported() {
+            return Err(RaftError::ReconfigNotSupported(
+                self.kraft_version.feature_level(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The log and leadership state shared by all [LocalRaftClient]s of a simulated quorum.
+pub struct SharedLog<T> {
+    data: Arc<Mutex<SharedLogData<T>>>,
+}
+
+impl<T> Clone for SharedLog<T> {
+    fn clone(&self) -> Self {
+        Self {
+            data: Arc::clone(&self.data),
+        }
+    }
+}
+
+impl<T> Default for SharedLog<T> {
+    fn default() -> Self {
+        Self {
+            data: Arc::new(Mutex::new(SharedLogData {
+                batches: Vec::new(),
+                snapshot: None,
+                leader: LeaderAndEpoch::UNKNOWN,
+                epoch_start_offset: 0,
+                end_offset: 0,
+                voters: VoterSet::default(),
+                kraft_version: KRaftVersion::Kraft0,
+                degraded: BTreeSet::new(),
+                isolated: BTreeSet::new(),
+            })),
+        }
+    }
+}
+
+impl<T: Clone> SharedLog<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `node_id` the leader of a new epoch and returns it.
+    ///
+    /// A degraded node can't be elected: the leader and epoch are left unchanged.
+    pub fn elect(&self, node_id: i32) -> LeaderAndEpoch {
+        let mut data = self.lock();
+        if data.degraded.contains(&node_id) {
+            warn!("Node {node_id} is degraded and can't be elected");
+            return data.leader;
+        }
+        data.elect(node_id)
+    }
+
+    /// Simulates a crash of the leader: the voters time out and bump the epoch, which stays
+    /// without a leader until [SharedLog::elect] is called.
+    pub fn crash_leader(&self) -> LeaderAndEpoch {
+        let mut data = self.lock();
+        info!("The leader of epoch {} crashed", data.leader.epoch());
+        data.leader = LeaderAndEpoch::new(None, data.leader.epoch() + 1);
+        data.leader
+    }
+
+    /// Cuts `node_id` off from the other nodes, as a network partition would: its timers
+    /// run out since it no longer hears from the leader, and its elections fail for lack of
+    /// votes.
+    pub fn isolate(&self, node_id: i32) {
+        self.lock().isolated.insert(node_id);
+    }
+
+    /// Reconnects a node cut off by [SharedLog::isolate].
+    pub fn reconnect(&self, node_id: i32) {
+        self.lock().isolated.remove(&node_id);
+    }
+
+    pub fn leader_and_epoch(&self) -> LeaderAndEpoch {
+        self.lock().leader
+    }
+
+    pub fn voters(&self) -> VoterSet {
+        self.lock().voters.clone()
+    }
+
+    /// The offset that the next appended record will be assigned.
+    pub fn end_offset(&self) -> i64 {
+        self.lock().end_offset
+    }
+
+    /// Replaces every batch ending below `end_offset` with a snapshot.
+    ///
+    /// Listeners which have not read past the snapshot are sent it instead of the batches.
+    pub fn snapshot(&self, end_offset: i64) {
+        let mut data = self.lock();
+        let split = data
+            .batches
+            .iter()
+            .position(|b| b.last_offset() >= end_offset)
+            .unwrap_or(data.batches.len());
+        if split == 0 {
+            return;
+        }
+        let mut contained: Vec<Batch<T>> = data
+            .snapshot
+            .take()
+            .map(|(_, _, batches)| batches)
+            .unwrap_or_default();
+        contained.extend(data.batches.drain(..split));
+        let last = contained.last().expect("snapshot is not empty");
+        let snapshot_id = OffsetAndEpoch::new(last.last_offset() + 1, last.epoch());
+        let timestamp = last.append_timestamp();
+        data.snapshot = Some((snapshot_id, timestamp, contained));
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SharedLogData<T>> {
+        self.data.lock().expect("shared log lock poisoned")
+    }
+}
+
+/// Where a node stands in the elections of the quorum, as driven by its timers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ElectionState {
+    /// Following the leader, or waiting for one: the node stands once `deadline_ms` passes
+    /// without hearing from a leader.
+    Follower {
+        deadline_ms: i64,
+    },
+    /// Standing in an election which fails at `deadline_ms` if it did not get the votes.
+    Candidate {
+        retries: u32,
+        deadline_ms: i64,
+    },
+    /// Lost `retries` elections in a row, and stands again at `deadline_ms`.
+    BackingOff {
+        retries: u32,
+        deadline_ms: i64,
+    },
+    Leader,
+}
+
+/// A [RaftClient] for one node of a quorum simulated by a [SharedLog].
+///
+/// Listeners are driven by [LocalRaftClient::poll], which plays the role of the raft
+/// client's I/O thread, and the elections by [LocalRaftClient::tick], which plays the role
+/// of its timers.
+pub struct LocalRaftClient<T> {
+    node_id: i32,
+    shared: SharedLog<T>,
+    listeners: Vec<ListenerContext<T>>,
+    /// The error every append to the local log fails with, simulating a failed disk.
+    append_failure: Option<String>,
+    /// The offset from which commits are held back from the listeners, simulating a node
+    /// the metadata reaches late.
+    held_from_offset: Option<i64>,
+    quorum_config: QuorumConfig,
+    /// Unset until the first [LocalRaftClient::tick].
+    election_state: Option<ElectionState>,
+}
+
+impl<T: Clone> LocalRaftClient<T> {
+    /// Creates a client for a voter of the quorum which supports every `kraft.version` of
+    /// this release.
+    pub fn new(node_id: i32, shared: SharedLog<T>) -> Self {
+        shared
+            .lock()
+            .voters
+            .update_voter(node_id, KRaftVersion::supported_range());
+        Self {
+            node_id,
+            shared,
+            listeners: Vec::new(),
+            append_failure: None,
+            held_from_offset: None,
+            quorum_config: QuorumConfig::default(),
+            election_state: None,
+        }
+    }
+
+    /// Sets the timeouts of the elections run by [LocalRaftClient::tick].
+    pub fn with_quorum_config(mut self, quorum_config: QuorumConfig) -> Self {
+        self.quorum_config = quorum_config;
+        self
+    }
+
+    /// Holds back the records appended from now on from the listeners of this node until
+    /// [LocalRaftClient::release_commits], so that it keeps serving stale metadata while the
+    /// other nodes moved on, e.g. to exercise the retries of clients told `NOT_LEADER` by a
+    /// broker which does not know the new leader yet.
+    pub fn hold_commits(&mut self) {
+        self.hold_commits_from(self.shared.end_offset());
+    }
+
+    /// Holds back the batches ending at or after `offset` from the listeners of this node,
+    /// and a snapshot past it.
+    pub fn hold_commits_from(&mut self, offset: i64) {
+        self.held_from_offset = Some(offset);
+    }
+
+    /// Delivers the commits held back on the next [LocalRaftClient::poll].
+    pub fn release_commits(&mut self) {
+        self.held_from_offset = None;
+    }
+
+    /// Makes every later append to the local log fail with `reason`, as a failed disk would.
+    pub fn fail_appends(&mut self, reason: impl Into<String>) {
+        self.append_failure = Some(reason.into());
+    }
+
+    /// Whether the node failed to append to its log and was demoted to an observer.
+    pub fn is_degraded(&self) -> bool {
+        self.shared.lock().degraded.contains(&self.node_id)
+    }
+
+    /// Advertises the `kraft.version` range the local voter supports to the leader, as a
+    /// voter does after it was restarted with a different release.
+    pub fn set_supported_kraft_versions(&mut self, supported: SupportedVersionRange) {
+        let mut data = self.shared.lock();
+        if data.voters.contains(self.node_id) {
+            data.voters.update_voter(self.node_id, supported);
+        }
+    }
+
+    /// Whether the node is standing in an election which did not get the votes yet.
+    pub fn is_candidate(&self) -> bool {
+        matches!(self.election_state, Some(ElectionState::Candidate { .. }))
+    }
+
+    /// Runs the timers of the node at `now_ms`, electing it if it stands and gets the votes.
+    ///
+    /// A follower hearing from the leader resets its fetch timer to
+    /// `controller.quorum.fetch.timeout.ms`. Once it runs out, or a randomized
+    /// `controller.quorum.election.timeout.ms` after the node started without a leader, the
+    /// node stands. In process the votes come back at once, so it is elected unless it is cut
+    /// off, in which case the election fails after another randomized election timeout and the
+    /// node backs off, up to `controller.quorum.election.backoff.max.ms`, before standing
+    /// again. A degraded node never stands.
+    pub fn tick(&mut self, now_ms: i64) {
+        let config = self.quorum_config;
+        let mut data = self.shared.lock();
+        let isolated = data.isolated.contains(&self.node_id);
+        let state = match self.election_state {
+            None if data.leader.leader_id().is_none() => ElectionState::Follower {
+                deadline_ms: now_ms + config.randomized_election_timeout_ms(),
+            },
+            None => ElectionState::Follower {
+                deadline_ms: now_ms + config.fetch_timeout_ms,
+            },
+            Some(state) => state,
+        };
+        let state = match state {
+            _ if data.leader.is_leader(self.node_id) => ElectionState::Leader,
+            ElectionState::Leader => ElectionState::Follower {
+                deadline_ms: now_ms + config.fetch_timeout_ms,
+            },
+            _ if data.leader.leader_id().is_some() && !isolated => ElectionState::Follower {
+                deadline_ms: now_ms + config.fetch_timeout_ms,
+            },
+            ElectionState::Follower { deadline_ms } if now_ms >= deadline_ms => {
+                self.stand(&mut data, now_ms, 0)
+            }
+            ElectionState::BackingOff {
+                retries,
+                deadline_ms,
+            } if now_ms >= deadline_ms => self.stand(&mut data, now_ms, retries),
+            ElectionState::Candidate {
+                retries,
+                deadline_ms,
+            } if now_ms >= deadline_ms => {
+                let backoff_ms = config.election_backoff_ms(retries + 1);
+                info!(
+                    "Node {} lost the election, standing again in {backoff_ms} ms",
+                    self.node_id
+                );
+                ElectionState::BackingOff {
+                    retries: retries + 1,
+                    deadline_ms: now_ms + backoff_ms,
+                }
+            }
+            state => state,
+        };
+        self.election_state = Some(state);
+    }
+
+    /// Stands in an election at `now_ms`, after losing `retries` of them in a row.
+    fn stand(&self, data: &mut SharedLogData<T>, now_ms: i64, retries: u32) -> ElectionState {
+        if data.degraded.contains(&self.node_id) {
+            return ElectionState::Follower {
+                deadline_ms: now_ms + self.quorum_config.fetch_timeout_ms,
+            };
+        }
+        if data.isolated.contains(&self.node_id) {
+            debug!("Node {} stands but can't reach the voters", self.node_id);
+            return ElectionState::Candidate {
+                retries,
+                deadline_ms: now_ms + self.quorum_config.randomized_election_timeout_ms(),
+            };
+        }
+        data.elect(self.node_id);
+        ElectionState::Leader
+    }
+
+    /// Delivers new snapshots, commits and leader changes to every registered listener.
+    pub fn poll(&mut self) {
+        let data = self.shared.lock();
+        let log_start_offset = data.log_start_offset();
+        let high_watermark = self
+            .held_from_offset
+            .map_or(data.end_offset, |offset| offset.min(data.end_offset));
+        for context in self.listeners.iter_mut() {
+            if let Some((snapshot_id, timestamp, batches)) = &data.snapshot
+                && context.next_offset() < log_start_offset
+                && snapshot_id.offset() <= high_watermark
+            {
+                context.fire_load_snapshot(SnapshotReader::new(
+                    *snapshot_id,
+                    *timestamp,
+                    batches.clone(),
+                ));
+            }
+            context.fire_commit(&data.batches, high_watermark);
+            if data.leader.is_leader(self.node_id) {
+                context.maybe_fire_local_leader_change(data.leader, data.epoch_start_offset);
+            } else {
+                context.maybe_fire_leader_change(data.leader);
+            }
+        }
+    }
+}
+
+impl<T: Clone> RaftClient<T> for LocalRaftClient<T> {
+    fn node_id(&self) -> Option<i32> {
+        Some(self.node_id)
+    }
+
+    fn register(&mut self, listener: Box<dyn Listener<T>>) {
+        self.listeners.push(ListenerContext::new(listener));
+    }
+
+    fn register_from(&mut self, listener: Box<dyn Listener<T>>, next_offset: i64) {
+        let end_offset = self.shared.end_offset();
+        let next_offset = if next_offset > end_offset {
+            warn!(
+                "Node {} registered a listener from offset {next_offset}, beyond the log end \
+                offset {end_offset}, so it reads the log from the start",
+                self.node_id
+            );
+            0
+        } else {
+            next_offset
+        };
+        self.listeners
+            .push(ListenerContext::with_next_offset(listener, next_offset));
+    }
+
+    fn leader_and_epoch(&self) -> LeaderAndEpoch {
+        self.shared.leader_and_epoch()
+    }
+
+    fn high_watermark(&self) -> Option<i64> {
+        Some(self.shared.end_offset())
+    }
+
+    fn schedule_append(&mut self, epoch: i32, records: Vec<T>) -> Result<i64> {
+        let mut data = self.shared.lock();
+        data.ensure_leader(self.node_id, epoch)?;
+        if let Some(reason) = &self.append_failure {
+            error!(
+                "Node {} failed to append to its log in epoch {epoch}, resigning and becoming \
+                a degraded observer: {reason}",
+                self.node_id
+            );
+            data.leader = LeaderAndEpoch::new(None, epoch);
+            data.degraded.insert(self.node_id);
+            return Err(RaftError::AppendFailed {
+                node_id: self.node_id,
+                epoch,
+                reason: reason.clone(),
+            });
+        }
+        let append_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as i64);
+        // Records are never serialized by the local log, so batches carry no size.
+        let batch = Batch::data(data.end_offset, epoch, append_timestamp, 0, records);
+        data.end_offset = batch.last_offset() + 1;
+        let last_offset = batch.last_offset();
+        data.batches.push(batch);
+        Ok(last_offset)
+    }
+
+    fn kraft_version(&self) -> KRaftVersion {
+        self.shared.lock().kraft_version
+    }
+
+    fn upgrade_kraft_version(
+        &mut self,
+        epoch: i32,
+        version: KRaftVersion,
+        validate_only: bool,
+    ) -> Result<()> {
+        let mut data = self.shared.lock();
+        data.ensure_leader(self.node_id, epoch)?;
+        if version < data.kraft_version {
+            return Err(RaftError::KRaftVersionDowngrade {
+                current: data.kraft_version.feature_level(),
+                requested: version.feature_level(),
+            });
+        }
+        let voters = data.voters.voters_not_supporting(version);
+        if !voters.is_empty() {
+            return Err(RaftError::UnsupportedKRaftVersion {
+                version: version.feature_level(),
+                voters,
+            });
+        }
+        if !validate_only && version != data.kraft_version {
+            info!(
+                "Upgrading kraft.version from {} to {}",
+                data.kraft_version.feature_level(),
+                version.feature_level()
+            );
+            data.kraft_version = version;
+        }
+        Ok(())
+    }
+
+    fn add_voter(
+        &mut self,
+        epoch: i32,
+        voter_id: i32,
+        supported_kraft_versions: SupportedVersionRange,
+    ) -> Result<()> {
+        let mut data = self.shared.lock();
+        data.ensure_leader(self.node_id, epoch)?;
+        data.ensure_reconfig_supported()?;
+        if data.voters.contains(voter_id) {
+            return Err(RaftError::DuplicateVoter(voter_id));
+        }
+        let version = data.kraft_version.feature_level();
+        if !supported_kraft_versions.contains(version) {
+            return Err(RaftError::UnsupportedKRaftVersion {
+                version,
+                voters: vec![voter_id],
+            });
+        }
+        info!("Adding voter {voter_id}");
+        data.voters.update_voter(voter_id, supported_kraft_versions);
+        Ok(())
+    }
+
+    fn remove_voter(&mut self, epoch: i32, voter_id: i32) -> Result<()> {
+        let mut data = self.shared.lock();
+        data.ensure_leader(self.node_id, epoch)?;
+        data.ensure_reconfig_supported()?;
+        if !data.voters.remove_voter(voter_id) {
+            return Err(RaftError::UnknownVoter(voter_id));
+        }
+        info!("Removed voter {voter_id}");
+        Ok(())
+    }
+
+    fn resign(&mut self, epoch: i32) -> Result<()> {
+        let mut data = self.shared.lock();
+        let current = data.leader.epoch();
+        if epoch > current {
+            return Err(RaftError::InvalidEpoch {
+                requested: epoch,
+                current,
+            });
+        }
+        if epoch < current || !data.leader.is_leader(self.node_id) {
+            debug!(
+                "Ignoring resignation of node {} for epoch {} since the current leader is {:?}",
+                self.node_id, epoch, data.leader
+            );
+            return Ok(());
+        }
+        info!(
+            "Node {} resigned leadership of epoch {}",
+            self.node_id, epoch
+        );
+        data.leader = LeaderAndEpoch::new(None, epoch);
+        Ok(())
+    }
+
+    fn begin_shutdown(&mut self) {
+        for context in self.listeners.iter_mut() {
+            context.begin_shutdown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raft::batch_reader::BatchReader;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Event {
+        Commit(Vec<i32>),
+        Snapshot(OffsetAndEpoch, Vec<i32>),
+        LeaderChange(LeaderAndEpoch),
+        Shutdown,
+    }
+
+    struct RecordingListener {
+        events: Arc<Mutex<Vec<Event>>>,
+    }
+
+    impl Listener<i32> for RecordingListener {
+        fn handle_commit(&mut self, reader: BatchReader<i32>) {
+            let records = reader.flat_map(Batch::into_records).collect();
+            self.events.lock().unwrap().push(Event::Commit(records));
+        }
+
+        fn handle_load_snapshot(&mut self, reader: SnapshotReader<i32>) {
+            let snapshot_id = reader.snapshot_id();
+            let records = reader.flat_map(Batch::into_records).collect();
+            self.events
+                .lock()
+                .unwrap()
+                .push(Event::Snapshot(snapshot_id, records));
+        }
+
+        fn handle_leader_change(&mut self, leader: LeaderAndEpoch) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(Event::LeaderChange(leader));
+        }
+
+        fn begin_shutdown(&mut self) {
+            self.events.lock().unwrap().push(Event::Shutdown);
+        }
+    }
+
+    fn client_with_listener(
+        node_id: i32,
+        shared: &SharedLog<i32>,
+    ) -> (LocalRaftClient<i32>, Arc<Mutex<Vec<Event>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut client = LocalRaftClient::new(node_id, shared.clone());
+        client.register(Box::new(RecordingListener {
+            events: Arc::clone(&events),
+        }));
+        (client, events)
+    }
+
+    fn drain(events: &Arc<Mutex<Vec<Event>>>) -> Vec<Event> {
+        events.lock().unwrap().drain(..).collect()
+    }
+
+    #[test]
+    fn test_commits_and_leader_changes_are_pushed_to_listeners() {
+        let shared = SharedLog::new();
+        let (mut leader, leader_events) = client_with_listener(0, &shared);
+        let (mut follower, follower_events) = client_with_listener(1, &shared);
+
+        let epoch = shared.elect(0).epoch();
+        assert_eq!(1, leader.schedule_append(epoch, vec![1, 2]).unwrap());
+        assert_eq!(2, leader.schedule_append(epoch, vec![3]).unwrap());
+        leader.poll();
+        follower.poll();
+
+        let expected = vec![
+            Event::Commit(vec![1, 2, 3]),
+            Event::LeaderChange(LeaderAndEpoch::new(Some(0), 1)),
+        ];
+        assert_eq!(expected, drain(&leader_events));
+        assert_eq!(expected, drain(&follower_events));
+
+        // Nothing new to deliver.
+        follower.poll();
+        assert!(drain(&follower_events).is_empty());
+    }
+
+    #[test]
+    fn test_late_listener_catches_up_before_leader_change() {
+        let shared = SharedLog::new();
+        let (mut first, _) = client_with_listener(0, &shared);
+        let epoch = shared.elect(0).epoch();
+        first.schedule_append(epoch, vec![1]).unwrap();
+
+        let (mut second, second_events) = client_with_listener(1, &shared);
+        let epoch = shared.elect(1).epoch();
+        second.schedule_append(epoch, vec![2]).unwrap();
+        second.poll();
+
+        assert_eq!(
+            vec![
+                Event::Commit(vec![1, 2]),
+                Event::LeaderChange(LeaderAndEpoch::new(Some(1), 2)),
+            ],
+            drain(&second_events)
+        );
+    }
+
+    #[test]
+    fn test_held_commits_are_delivered_once_released() {
+        let shared = SharedLog::new();
+        let (mut leader, leader_events) = client_with_listener(0, &shared);
+        let (mut lagging, lagging_events) = client_with_listener(1, &shared);
+        let epoch = shared.elect(0).epoch();
+        leader.schedule_append(epoch, vec![1]).unwrap();
+        leader.poll();
+        lagging.poll();
+        drain(&leader_events);
+        drain(&lagging_events);
+
+        lagging.hold_commits();
+        leader.schedule_append(epoch, vec![2]).unwrap();
+        leader.schedule_append(epoch, vec![3]).unwrap();
+        leader.poll();
+        lagging.poll();
+        assert_eq!(vec![Event::Commit(vec![2, 3])], drain(&leader_events));
+        assert!(drain(&lagging_events).is_empty());
+
+        // A snapshot past the held offset is held back too.
+        shared.snapshot(3);
+        lagging.poll();
+        assert!(drain(&lagging_events).is_empty());
+
+        lagging.release_commits();
+        lagging.poll();
+        assert_eq!(
+            vec![Event::Snapshot(
+                OffsetAndEpoch::new(3, epoch),
+                vec![1, 2, 3]
+            )],
+            drain(&lagging_events)
+        );
+    }
+
+    #[test]
+    fn test_append_requires_leadership_of_the_epoch() {
+        let shared = SharedLog::new();
+        let (mut client, _) = client_with_listener(0, &shared);
+        assert_eq!(
+            Err(RaftError::NotLeader {
+                node_id: 0,
+                epoch: 0
+            }),
+            client.schedule_append(0, vec![1])
+        );
+
+        let epoch = shared.elect(0).epoch();
+        shared.elect(1);
+        assert_eq!(
+            Err(RaftError::NotLeader { node_id: 0, epoch }),
+            client.schedule_append(epoch, vec![1])
+        );
+    }
+
+    #[test]
+    fn test_resign() {
+        let shared = SharedLog::new();
+        let (mut client, events) = client_with_listener(0, &shared);
+        let (mut other, other_events) = client_with_listener(1, &shared);
+        let epoch = shared.elect(0).epoch();
+        client.poll();
+        drain(&events);
+
+        assert_eq!(
+            Err(RaftError::InvalidEpoch {
+                requested: epoch + 1,
+                current: epoch
+            }),
+            client.resign(epoch + 1)
+        );
+        // Not the leader, so resigning is a no-op.
+        other.resign(epoch).unwrap();
+        assert_eq!(Some(0), shared.leader_and_epoch().leader_id());
+
+        client.resign(epoch).unwrap();
+        assert_eq!(LeaderAndEpoch::new(None, epoch), client.leader_and_epoch());
+        assert!(client.schedule_append(epoch, vec![1]).is_err());
+
+        let new_leader = shared.elect(1);
+        client.poll();
+        other.poll();
+        assert_eq!(vec![Event::LeaderChange(new_leader)], drain(&events));
+        assert_eq!(vec![Event::LeaderChange(new_leader)], drain(&other_events));
+    }
+
+    #[test]
+    fn test_append_failure_resigns_and_degrades_the_leader() {
+        let shared = SharedLog::new();
+        let (mut client, events) = client_with_listener(0, &shared);
+        let (mut other, _) = client_with_listener(1, &shared);
+        let epoch = shared.elect(0).epoch();
+        client.schedule_append(epoch, vec![1]).unwrap();
+        client.poll();
+        drain(&events);
+
+        client.fail_appends("disk error");
+        assert_eq!(
+            Err(RaftError::AppendFailed {
+                node_id: 0,
+                epoch,
+                reason: "disk error".to_string()
+            }),
+            client.schedule_append(epoch, vec![2])
+        );
+        assert!(client.is_degraded());
+        assert!(!other.is_degraded());
+        assert_eq!(LeaderAndEpoch::new(None, epoch), shared.leader_and_epoch());
+        assert_eq!(1, shared.end_offset());
+        assert_eq!(
+            Err(RaftError::NotLeader { node_id: 0, epoch }),
+            client.schedule_append(epoch, vec![2])
+        );
+
+        // The degraded node can't be elected again, but keeps following the log.
+        assert_eq!(LeaderAndEpoch::new(None, epoch), shared.elect(0));
+        let new_leader = shared.elect(1);
+        other.schedule_append(new_leader.epoch(), vec![3]).unwrap();
+        client.poll();
+        assert_eq!(
+            vec![Event::Commit(vec![3]), Event::LeaderChange(new_leader)],
+            drain(&events)
+        );
+    }
+
+    #[test]
+    fn test_register_from_offset() {
+        let shared = SharedLog::new();
+        let (mut leader, _) = client_with_listener(0, &shared);
+        let epoch = shared.elect(0).epoch();
+        leader.schedule_append(epoch, vec![1, 2]).unwrap();
+        leader.schedule_append(epoch, vec![3]).unwrap();
+
+        let register_from = |next_offset: i64| {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let mut client = LocalRaftClient::new(1, shared.clone());
+            client.register_from(
+                Box::new(RecordingListener {
+                    events: Arc::clone(&events),
+                }),
+                next_offset,
+            );
+            client.poll();
+            drain(&events)
+        };
+        let leader_change = Event::LeaderChange(LeaderAndEpoch::new(Some(0), epoch));
+        assert_eq!(
+            vec![Event::Commit(vec![3]), leader_change.clone()],
+            register_from(2)
+        );
+        assert_eq!(vec![leader_change.clone()], register_from(3));
+        // The log does not hold the offset, so it is read from the start.
+        assert_eq!(
+            vec![Event::Commit(vec![1, 2, 3]), leader_change.clone()],
+            register_from(10)
+        );
+
+        shared.snapshot(2);
+        assert_eq!(
+            vec![
+                Event::Snapshot(OffsetAndEpoch::new(2, epoch), vec![1, 2]),
+                Event::Commit(vec![3]),
+                leader_change
+            ],
+            register_from(1)
+        );
+    }
+
+    #[test]
+    fn test_kraft_version_upgrade_requires_every_voter() {
+        let shared = SharedLog::<i32>::new();
+        let (mut leader, _) = client_with_listener(0, &shared);
+        let (mut old_voter, _) = client_with_listener(1, &shared);
+        old_voter.set_supported_kraft_versions(SupportedVersionRange::new(0, 0));
+        let epoch = shared.elect(0).epoch();
+
+        assert_eq!(KRaftVersion::Kraft0, leader.kraft_version());
+        assert_eq!(
+            Err(RaftError::UnsupportedKRaftVersion {
+                version: 1,
+                voters: vec![1]
+            }),
+            leader.upgrade_kraft_version(epoch, KRaftVersion::Kraft1, true)
+        );
+        // Dynamic voters are not available before the upgrade.
+        assert_eq!(
+            Err(RaftError::ReconfigNotSupported(0)),
+            leader.add_voter(epoch, 2, KRaftVersion::supported_range())
+        );
+
+        // The voter is restarted with a newer release.
+        old_voter.set_supported_kraft_versions(KRaftVersion::supported_range());
+        leader
+            .upgrade_kraft_version(epoch, KRaftVersion::Kraft1, true)
+            .unwrap();
+        assert_eq!(KRaftVersion::Kraft0, old_voter.kraft_version());
+        leader
+            .upgrade_kraft_version(epoch, KRaftVersion::Kraft1, false)
+            .unwrap();
+        assert_eq!(KRaftVersion::Kraft1, old_voter.kraft_version());
+        assert_eq!(
+            Err(RaftError::KRaftVersionDowngrade {
+                current: 1,
+                requested: 0
+            }),
+            leader.upgrade_kraft_version(epoch, KRaftVersion::Kraft0, false)
+        );
+        assert_eq!(
+            Err(RaftError::NotLeader { node_id: 1, epoch }),
+            old_voter.upgrade_kraft_version(epoch, KRaftVersion::Kraft1, false)
+        );
+    }
+
+    #[test]
+    fn test_add_and_remove_voters() {
+        let shared = SharedLog::<i32>::new();
+        let (mut leader, _) = client_with_listener(0, &shared);
+        let epoch = shared.elect(0).epoch();
+        leader
+            .upgrade_kraft_version(epoch, KRaftVersion::Kraft1, false)
+            .unwrap();
+
+        // A voter which only knows static quorums can't join a dynamic one.
+        assert_eq!(
+            Err(RaftError::UnsupportedKRaftVersion {
+                version: 1,
+                voters: vec![1]
+            }),
+            leader.add_voter(epoch, 1, SupportedVersionRange::new(0, 0))
+        );
+        leader
+            .add_voter(epoch, 1, KRaftVersion::supported_range())
+            .unwrap();
+        assert_eq!(
+            Err(RaftError::DuplicateVoter(1)),
+            leader.add_voter(epoch, 1, KRaftVersion::supported_range())
+        );
+        assert_eq!(vec![0, 1], shared.voters().voter_ids().collect::<Vec<_>>());
+
+        leader.remove_voter(epoch, 1).unwrap();
+        assert_eq!(
+            Err(RaftError::UnknownVoter(1)),
+            leader.remove_voter(epoch, 1)
+        );
+    }
+
+    #[test]
+    fn test_listener_behind_log_start_loads_snapshot() {
+        let shared = SharedLog::new();
+        let (mut leader, _) = client_with_listener(0, &shared);
+        let epoch = shared.elect(0).epoch();
+        leader.schedule_append(epoch, vec![1, 2]).unwrap();
+        leader.schedule_append(epoch, vec![3]).unwrap();
+        shared.snapshot(2);
+
+        let (mut late, late_events) = client_with_listener(1, &shared);
+        late.poll();
+        late.begin_shutdown();
+        assert_eq!(
+            vec![
+                Event::Snapshot(OffsetAndEpoch::new(2, epoch), vec![1, 2]),
+                Event::Commit(vec![3]),
+                Event::LeaderChange(LeaderAndEpoch::new(Some(0), epoch)),
+                Event::Shutdown,
+            ],
+            drain(&late_events)
+        );
+    }
+
+    #[test]
+    fn test_elections_are_driven_by_the_timers() {
+        let config = QuorumConfig {
+            election_timeout_ms: 100,
+            election_backoff_max_ms: 50,
+            fetch_timeout_ms: 300,
+            request_timeout_ms: 200,
+        };
+        let shared = SharedLog::<i32>::new();
+        let mut clients: Vec<_> = (0..3)
+            .map(|id| LocalRaftClient::new(id, shared.clone()).with_quorum_config(config))
+            .collect();
+        let tick = |clients: &mut Vec<LocalRaftClient<i32>>, now_ms| {
+            clients.iter_mut().for_each(|c| c.tick(now_ms));
+        };
+
+        // Without a leader, the voters stand after a randomized election timeout.
+        tick(&mut clients, 0);
+        tick(&mut clients, 99);
+        assert_eq!(LeaderAndEpoch::new(None, 0), shared.leader_and_epoch());
+        tick(&mut clients, 200);
+        assert_eq!(LeaderAndEpoch::new(Some(0), 1), shared.leader_and_epoch());
+
+        // After the leader crashed, the followers wait for their fetch timeout.
+        shared.crash_leader();
+        clients.remove(0);
+        tick(&mut clients, 499);
+        assert_eq!(LeaderAndEpoch::new(None, 2), shared.leader_and_epoch());
+        tick(&mut clients, 500);
+        assert_eq!(LeaderAndEpoch::new(Some(1), 3), shared.leader_and_epoch());
+
+        // A node cut off from the leader stands, loses and backs off.
+        shared.isolate(2);
+        let isolated = &mut clients[1];
+        isolated.tick(799);
+        assert!(!isolated.is_candidate());
+        isolated.tick(800);
+        assert!(isolated.is_candidate());
+        isolated.tick(1_000);
+        assert!(!isolated.is_candidate());
+        isolated.tick(1_050);
+        assert!(isolated.is_candidate());
+        assert_eq!(LeaderAndEpoch::new(Some(1), 3), shared.leader_and_epoch());
+
+        shared.reconnect(2);
+        isolated.tick(1_100);
+        assert!(!isolated.is_candidate());
+        assert_eq!(LeaderAndEpoch::new(Some(1), 3), shared.leader_and_epoch());
+    }
+}
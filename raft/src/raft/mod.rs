@@ -0,0 +1,11 @@
+pub mod batch;
+pub mod batch_reader;
+pub mod kraft_version;
+pub mod leader_and_epoch;
+mod listener_context;
+pub mod local_raft_client;
+pub mod offset_and_epoch;
+pub mod quorum_config;
+pub mod raft_client;
+pub mod snapshot_reader;
+pub mod voter_set;
@@ -0,0 +1,39 @@
+use std::cmp::Ordering;
+
+/// An offset in the replicated log together with the epoch of the leader that wrote it.
+///
+/// Instances are ordered by epoch first and offset second, which matches how Kafka compares
+/// snapshot ids and divergence points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OffsetAndEpoch {
+    offset: i64,
+    epoch: i32,
+}
+
+impl OffsetAndEpoch {
+    pub fn new(offset: i64, epoch: i32) -> Self {
+        Self { offset, epoch }
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    pub fn epoch(&self) -> i32 {
+        self.epoch
+    }
+}
+
+impl PartialOrd for OffsetAndEpoch {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OffsetAndEpoch {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then(self.offset.cmp(&other.offset))
+    }
+}
@@ -0,0 +1,62 @@
+/// The base of the exponential backoff between the elections of a candidate.
+const RETRY_BACKOFF_BASE_MS: i64 = 100;
+
+/// The timeouts driving the elections of the raft client, `controller.quorum.*`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuorumConfig {
+    /// How long a voter without a leader waits before standing, and a candidate waits for
+    /// the votes, before randomization.
+    pub election_timeout_ms: i64,
+    /// The maximum time a candidate which lost an election waits before standing again.
+    pub election_backoff_max_ms: i64,
+    /// How long a follower goes without hearing from the leader before standing.
+    pub fetch_timeout_ms: i64,
+    /// How long a request to another voter waits for its response.
+    pub request_timeout_ms: i64,
+}
+
+impl Default for QuorumConfig {
+    fn default() -> Self {
+        Self {
+            election_timeout_ms: 1_000,
+            election_backoff_max_ms: 1_000,
+            fetch_timeout_ms: 2_000,
+            request_timeout_ms: 2_000,
+        }
+    }
+}
+
+impl QuorumConfig {
+    /// A random election timeout between the configured one and twice it, so that the
+    /// voters which lost their leader at the same time don't all stand at once and split
+    /// the votes.
+    pub fn randomized_election_timeout_ms(&self) -> i64 {
+        self.election_timeout_ms + rand::random_range(0..self.election_timeout_ms.max(1))
+    }
+
+    /// The random backoff before a candidate stands again after losing `retries` elections
+    /// in a row: it doubles with every retry, up to `election_backoff_max_ms`.
+    pub fn election_backoff_ms(&self, retries: u32) -> i64 {
+        let bound = RETRY_BACKOFF_BASE_MS << retries.clamp(1, 20);
+        rand::random_range(0..bound).min(self.election_backoff_max_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_election_timeouts_are_randomized_within_bounds() {
+        let config = QuorumConfig {
+            election_timeout_ms: 50,
+            election_backoff_max_ms: 300,
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            assert!((50..100).contains(&config.randomized_election_timeout_ms()));
+            assert!((0..200).contains(&config.election_backoff_ms(1)));
+            assert!((0..=300).contains(&config.election_backoff_ms(10)));
+        }
+    }
+}
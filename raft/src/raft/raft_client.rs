@@ -0,0 +1,140 @@
+use crate::raft::batch_reader::BatchReader;
+use crate::raft::kraft_version::{KRaftVersion, SupportedVersionRange};
+use crate::raft::leader_and_epoch::LeaderAndEpoch;
+use crate::raft::snapshot_reader::SnapshotReader;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RaftError {
+    #[error("Epoch {requested} is larger than the current epoch {current}")]
+    InvalidEpoch { requested: i32, current: i32 },
+
+    #[error("Node {node_id} is not the leader for epoch {epoch}")]
+    NotLeader { node_id: i32, epoch: i32 },
+
+    #[error("kraft.version {version} is not supported by voters {voters:?}")]
+    UnsupportedKRaftVersion { version: i16, voters: Vec<i32> },
+
+    #[error("kraft.version can't be downgraded from {current} to {requested}")]
+    KRaftVersionDowngrade { current: i16, requested: i16 },
+
+    #[error("Voters can't be changed at kraft.version {0}, it must be upgraded first")]
+    ReconfigNotSupported(i16),
+
+    #[error("Node {0} is already a voter")]
+    DuplicateVoter(i32),
+
+    #[error("Node {0} is not a voter")]
+    UnknownVoter(i32),
+
+    #[error("Node {node_id} failed to append to its log in epoch {epoch}: {reason}")]
+    AppendFailed {
+        node_id: i32,
+        epoch: i32,
+        reason: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, RaftError>;
+
+/// A state machine that consumes the replicated log.
+///
+/// Listeners are registered with a [RaftClient] and are driven by it: the client pushes
+/// committed data, snapshots and leadership changes instead of the state machine polling
+/// for them. All callbacks for a given listener are invoked from the same thread.
+pub trait Listener<T>: Send {
+    /// Callback which is invoked for all records committed to the log.
+    ///
+    /// Batches are delivered in offset order and each committed offset is delivered exactly
+    /// once, unless a snapshot is loaded in between.
+    fn handle_commit(&mut self, reader: BatchReader<T>);
+
+    /// Callback which is invoked when the listener needs to load a snapshot, either at
+    /// startup or because it fell behind the log start offset.
+    ///
+    /// After the snapshot has been loaded, commits resume from the snapshot end offset.
+    fn handle_load_snapshot(&mut self, reader: SnapshotReader<T>);
+
+    /// Called on any change to leadership, including the local node becoming leader.
+    ///
+    /// When the local node becomes leader this is only invoked once the listener has
+    /// seen every record committed before the start of the new epoch, so a leader never
+    /// begins writing on top of stale state.
+    ///
+    /// A leader which cannot keep up with its own writes should call
+    /// [RaftClient::resign] with the epoch it was notified of.
+    fn handle_leader_change(&mut self, leader: LeaderAndEpoch) {
+        let _ = leader;
+    }
+
+    /// Called when the client begins shutting down. No further callbacks will follow.
+    fn begin_shutdown(&mut self) {}
+}
+
+/// The interface state machines use to read from and write to the replicated log.
+pub trait RaftClient<T> {
+    /// The id of the local node, or `None` for observers that are not part of the quorum.
+    fn node_id(&self) -> Option<i32>;
+
+    /// Registers a listener. The listener is first brought up to date from the start of
+    /// the log and then receives every subsequent commit and leader change.
+    fn register(&mut self, listener: Box<dyn Listener<T>>);
+
+    /// Registers a listener which already holds the state of the log up to `next_offset`,
+    /// e.g. loaded from a local snapshot, so that commits are delivered from there on. If
+    /// the log no longer holds `next_offset`, the listener loads the latest snapshot first.
+    fn register_from(&mut self, listener: Box<dyn Listener<T>>, next_offset: i64);
+
+    /// The current leader and epoch as known by the local node.
+    fn leader_and_epoch(&self) -> LeaderAndEpoch;
+
+    /// The current high watermark, or `None` if it is not yet known.
+    fn high_watermark(&self) -> Option<i64>;
+
+    /// Appends `records` to the log as the leader of `epoch`.
+    ///
+    /// Returns the offset of the last appended record. Fails with
+    /// [RaftError::NotLeader] if the local node is not the leader of `epoch`.
+    ///
+    /// Fails with [RaftError::AppendFailed] if the records can't be written to the local log,
+    /// e.g. because of a disk error. The leader then resigns and becomes a degraded observer:
+    /// it keeps replicating the log but can neither be elected nor write again, since it could
+    /// not persist what it accepts.
+    fn schedule_append(&mut self, epoch: i32, records: Vec<T>) -> Result<i64>;
+
+    /// The finalized `kraft.version` of the quorum.
+    fn kraft_version(&self) -> KRaftVersion;
+
+    /// Upgrades the `kraft.version` as the leader of `epoch`.
+    ///
+    /// Fails with [RaftError::UnsupportedKRaftVersion] unless every voter advertised support
+    /// for `version`. Nothing is changed if `validate_only` is set.
+    fn upgrade_kraft_version(
+        &mut self,
+        epoch: i32,
+        version: KRaftVersion,
+        validate_only: bool,
+    ) -> Result<()>;
+
+    /// Adds a voter as the leader of `epoch`. Requires a `kraft.version` which supports
+    /// reconfiguration, which the new voter must support as well.
+    fn add_voter(
+        &mut self,
+        epoch: i32,
+        voter_id: i32,
+        supported_kraft_versions: SupportedVersionRange,
+    ) -> Result<()>;
+
+    /// Removes a voter as the leader of `epoch`. Requires a `kraft.version` which supports
+    /// reconfiguration.
+    fn remove_voter(&mut self, epoch: i32, voter_id: i32) -> Result<()>;
+
+    /// Gives up leadership for `epoch` so another voter can be elected.
+    ///
+    /// This is a no-op if `epoch` is older than the current epoch or if the local node is
+    /// not the leader. It fails if `epoch` is larger than the current epoch.
+    fn resign(&mut self, epoch: i32) -> Result<()>;
+
+    /// Notifies all listeners that the client is shutting down.
+    fn begin_shutdown(&mut self);
+}
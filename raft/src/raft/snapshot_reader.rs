@@ -0,0 +1,54 @@
+use crate::raft::batch::Batch;
+use crate::raft::offset_and_epoch::OffsetAndEpoch;
+use std::collections::VecDeque;
+
+/// An iterator over the batches of a snapshot handed to a
+/// [Listener](crate::raft_client::Listener).
+///
+/// The snapshot id is the exclusive end offset and epoch of the log prefix the snapshot
+/// replaces, so after loading it a listener should resume reading at `snapshot_id.offset()`.
+#[derive(Debug)]
+pub struct SnapshotReader<T> {
+    snapshot_id: OffsetAndEpoch,
+    last_contained_log_timestamp: i64,
+    batches: VecDeque<Batch<T>>,
+}
+
+impl<T> SnapshotReader<T> {
+    pub fn new(
+        snapshot_id: OffsetAndEpoch,
+        last_contained_log_timestamp: i64,
+        batches: Vec<Batch<T>>,
+    ) -> Self {
+        Self {
+            snapshot_id,
+            last_contained_log_timestamp,
+            batches: batches.into(),
+        }
+    }
+
+    pub fn snapshot_id(&self) -> OffsetAndEpoch {
+        self.snapshot_id
+    }
+
+    /// The offset of the last record contained in the snapshot.
+    pub fn last_contained_log_offset(&self) -> i64 {
+        self.snapshot_id.offset() - 1
+    }
+
+    pub fn last_contained_log_epoch(&self) -> i32 {
+        self.snapshot_id.epoch()
+    }
+
+    pub fn last_contained_log_timestamp(&self) -> i64 {
+        self.last_contained_log_timestamp
+    }
+}
+
+impl<T> Iterator for SnapshotReader<T> {
+    type Item = Batch<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.batches.pop_front()
+    }
+}
@@ -0,0 +1,80 @@
+use crate::raft::kraft_version::{KRaftVersion, SupportedVersionRange};
+use std::collections::BTreeMap;
+
+/// The voters of the quorum, with the `kraft.version` range each of them advertised.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VoterSet {
+    voters: BTreeMap<i32, SupportedVersionRange>,
+}
+
+impl VoterSet {
+    /// Adds a voter, or updates the versions an existing voter supports, e.g. after it was
+    /// restarted with a new release.
+    pub fn update_voter(&mut self, voter_id: i32, supported: SupportedVersionRange) {
+        self.voters.insert(voter_id, supported);
+    }
+
+    pub fn remove_voter(&mut self, voter_id: i32) -> bool {
+        self.voters.remove(&voter_id).is_some()
+    }
+
+    pub fn contains(&self, voter_id: i32) -> bool {
+        self.voters.contains_key(&voter_id)
+    }
+
+    pub fn voter_ids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.voters.keys().copied()
+    }
+
+    pub fn supported_kraft_versions(&self, voter_id: i32) -> Option<SupportedVersionRange> {
+        self.voters.get(&voter_id).copied()
+    }
+
+    /// The voters which don't support `version`.
+    pub fn voters_not_supporting(&self, version: KRaftVersion) -> Vec<i32> {
+        self.voters
+            .iter()
+            .filter(|(_, range)| !range.contains(version.feature_level()))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// The highest version every voter supports.
+    pub fn max_supported_kraft_version(&self) -> KRaftVersion {
+        let max_level = self
+            .voters
+            .values()
+            .map(SupportedVersionRange::max)
+            .min()
+            .unwrap_or(KRaftVersion::LATEST_PRODUCTION.feature_level());
+        (0..=max_level)
+            .rev()
+            .find_map(KRaftVersion::from_feature_level)
+            .unwrap_or(KRaftVersion::Kraft0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_supported_kraft_version() {
+        let mut voters = VoterSet::default();
+        voters.update_voter(1, KRaftVersion::supported_range());
+        voters.update_voter(2, SupportedVersionRange::new(0, 5));
+        assert_eq!(KRaftVersion::Kraft1, voters.max_supported_kraft_version());
+        assert!(
+            voters
+                .voters_not_supporting(KRaftVersion::Kraft1)
+                .is_empty()
+        );
+
+        voters.update_voter(3, SupportedVersionRange::new(0, 0));
+        assert_eq!(KRaftVersion::Kraft0, voters.max_supported_kraft_version());
+        assert_eq!(vec![3], voters.voters_not_supporting(KRaftVersion::Kraft1));
+
+        voters.update_voter(3, KRaftVersion::supported_range());
+        assert_eq!(KRaftVersion::Kraft1, voters.max_supported_kraft_version());
+    }
+}
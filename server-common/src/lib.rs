@@ -1,5 +1,10 @@
+pub use queue::{event_queue, kafka_event_queue};
+pub use server::common::metadata_version;
+pub use server::component_registry;
 pub use server::config::{
     delegation_token_manager_configs, quota_config, server_configs, server_log_configs,
     server_topic_config_synonyms,
 };
+pub use server::fault_injection;
+mod queue;
 mod server;
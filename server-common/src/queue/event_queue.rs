@@ -0,0 +1,110 @@
+use thiserror::Error;
+use tracing::warn;
+
+/// Errors passed to [Event::handle_exception] when the queue could not run an event.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EventQueueError {
+    #[error("The event was not processed before its deadline")]
+    Timeout,
+
+    #[error("The event queue is full ({0} events are pending), try again later")]
+    QueueFull(usize),
+
+    #[error("The event was cancelled")]
+    Cancelled,
+
+    #[error("The event queue is shutting down")]
+    Closed,
+
+    #[error("The event panicked: {0}")]
+    Panicked(String),
+}
+
+impl EventQueueError {
+    /// Whether the operation which produced the event can be retried.
+    ///
+    /// A saturated queue or an expired deadline are transient conditions, so requests
+    /// rejected for those reasons should be failed with a retriable error code.
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self,
+            EventQueueError::Timeout | EventQueueError::QueueFull(_)
+        )
+    }
+}
+
+/// A unit of work executed by an [EventQueue].
+pub trait Event: Send + 'static {
+    /// Runs the event.
+    ///
+    /// The event is borrowed rather than consumed so that, if it panics, the queue can still
+    /// fail it with [EventQueueError::Panicked].
+    fn run(&mut self);
+
+    /// Handles an error raised by the queue instead of running the event, e.g. because
+    /// its deadline expired or the queue is shutting down, or because the event panicked.
+    fn handle_exception(self: Box<Self>, error: EventQueueError);
+}
+
+/// An [Event] backed by a closure. Errors raised by the queue are logged.
+pub struct FnEvent<F> {
+    name: &'static str,
+    f: Option<F>,
+}
+
+impl<F: FnOnce() + Send + 'static> FnEvent<F> {
+    pub fn new(name: &'static str, f: F) -> Box<Self> {
+        Box::new(Self { name, f: Some(f) })
+    }
+}
+
+impl<F: FnOnce() + Send + 'static> Event for FnEvent<F> {
+    fn run(&mut self) {
+        if let Some(f) = self.f.take() {
+            f()
+        }
+    }
+
+    fn handle_exception(self: Box<Self>, error: EventQueueError) {
+        warn!("Event {} failed: {}", self.name, error);
+    }
+}
+
+/// A queue of events executed one at a time by a single thread.
+pub trait EventQueue {
+    /// Adds an event to the front of the queue.
+    ///
+    /// Prepended events are not subject to the queue size limit, which makes them suitable
+    /// for high-priority work such as broker heartbeats.
+    fn prepend(&self, event: Box<dyn Event>);
+
+    /// Adds an event to the end of the queue.
+    fn append(&self, event: Box<dyn Event>);
+
+    /// Adds an event to the end of the queue. If the event has not started running by
+    /// `deadline_ns` (on the queue's monotonic clock), it is failed with
+    /// [EventQueueError::Timeout] instead.
+    fn append_with_deadline(&self, deadline_ns: i64, event: Box<dyn Event>);
+
+    /// Schedules an event to run once the clock reaches `deadline_ns`.
+    ///
+    /// A previously scheduled event with the same tag is cancelled.
+    fn schedule_deferred(&self, tag: &str, deadline_ns: i64, event: Box<dyn Event>);
+
+    /// Cancels the deferred event with the given tag, if any.
+    fn cancel_deferred(&self, tag: &str);
+
+    /// The number of pending events, including deferred ones.
+    fn size(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Re-evaluates deferred deadlines, e.g. after a mock clock was advanced.
+    fn wakeup(&self);
+
+    /// Stops accepting new events. Events already in the queue still run, deferred events
+    /// are failed with [EventQueueError::Closed].
+    fn begin_shutdown(&self, source: &str);
+}
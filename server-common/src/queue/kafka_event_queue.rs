@@ -0,0 +1,534 @@
+use crate::queue::event_queue::{Event, EventQueue, EventQueueError};
+use rafka_clients::common::metrics::stats::{Avg, CumulativeCount, Max};
+use rafka_clients::common::metrics::{Metrics, Sensor};
+use rafka_clients::common::utils::time::Time;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{error, info};
+
+const METRIC_GROUP: &str = "event-queue-metrics";
+
+struct EventContext {
+    event: Box<dyn Event>,
+    enqueue_time_ns: i64,
+    deadline_ns: Option<i64>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    queue: VecDeque<EventContext>,
+    /// Deferred events keyed by deadline and a sequence number breaking ties.
+    deferred: BTreeMap<(i64, u64), EventContext>,
+    deferred_tags: HashMap<String, (i64, u64)>,
+    next_sequence: u64,
+    shutting_down: bool,
+}
+
+impl QueueState {
+    fn remove_deferred(&mut self, tag: &str) -> Option<EventContext> {
+        let key = self.deferred_tags.remove(tag)?;
+        self.deferred.remove(&key)
+    }
+
+    fn size(&self) -> usize {
+        self.queue.len() + self.deferred.len()
+    }
+}
+
+struct QueueMetrics {
+    queue_time: Arc<Sensor>,
+    processing_time: Arc<Sensor>,
+    rejected: Arc<Sensor>,
+}
+
+struct Shared {
+    time: Arc<dyn Time>,
+    max_size: Option<usize>,
+    state: Mutex<QueueState>,
+    cond: Condvar,
+    metrics: Option<QueueMetrics>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().expect("event queue lock poisoned")
+    }
+}
+
+/// An [EventQueue] with a dedicated handler thread, like the one driving the controller.
+///
+/// When a `max_size` is configured, appended events are rejected with the retriable
+/// [EventQueueError::QueueFull] once that many events are waiting, so a saturated controller
+/// pushes back on admin requests instead of queueing them without bound. If [Metrics] are
+/// supplied, the time events spend waiting in the queue and running is recorded.
+pub struct KafkaEventQueue {
+    shared: Arc<Shared>,
+    thread_name: String,
+    handler: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl KafkaEventQueue {
+    pub fn new(
+        time: Arc<dyn Time>,
+        thread_name_prefix: &str,
+        max_size: Option<usize>,
+        metrics: Option<&Metrics>,
+    ) -> Self {
+        let thread_name = format!("{thread_name_prefix}event-handler");
+        let metrics = metrics.map(|m| Self::register_metrics(m, &thread_name));
+        let shared = Arc::new(Shared {
+            time,
+            max_size,
+            state: Mutex::new(QueueState::default()),
+            cond: Condvar::new(),
+            metrics,
+        });
+        let handler_shared = Arc::clone(&shared);
+        let handler = thread::Builder::new()
+            .name(thread_name.clone())
+            .spawn(move || handle_events(handler_shared))
+            .expect("failed to spawn the event handler thread");
+        Self {
+            shared,
+            thread_name,
+            handler: Mutex::new(Some(handler)),
+        }
+    }
+
+    fn register_metrics(metrics: &Metrics, queue: &str) -> QueueMetrics {
+        let tags = [("queue", queue)];
+        let queue_time = metrics.sensor(&format!("{queue}-queue-time"));
+        queue_time.add(
+            metrics.metric_name(
+                "event-queue-time-ms-avg",
+                METRIC_GROUP,
+                "The average time an event waits in the queue before it runs",
+                &tags,
+            ),
+            Avg::new(),
+        );
+        queue_time.add(
+            metrics.metric_name(
+                "event-queue-time-ms-max",
+                METRIC_GROUP,
+                "The maximum time an event waits in the queue before it runs",
+                &tags,
+            ),
+            Max::new(),
+        );
+        let processing_time = metrics.sensor(&format!("{queue}-processing-time"));
+        processing_time.add(
+            metrics.metric_name(
+                "event-queue-processing-time-ms-avg",
+                METRIC_GROUP,
+                "The average time it takes to run an event",
+                &tags,
+            ),
+            Avg::new(),
+        );
+        processing_time.add(
+            metrics.metric_name(
+                "event-queue-processing-time-ms-max",
+                METRIC_GROUP,
+                "The maximum time it takes to run an event",
+                &tags,
+            ),
+            Max::new(),
+        );
+        let rejected = metrics.sensor(&format!("{queue}-rejected"));
+        rejected.add(
+            metrics.metric_name(
+                "event-queue-rejected-total",
+                METRIC_GROUP,
+                "The number of events rejected because the queue was full",
+                &tags,
+            ),
+            CumulativeCount::new(),
+        );
+        QueueMetrics {
+            queue_time,
+            processing_time,
+            rejected,
+        }
+    }
+
+    /// Shuts the queue down and waits for the handler thread to finish the queued events.
+    pub fn close(&self) {
+        self.begin_shutdown("close");
+        let handler = self
+            .handler
+            .lock()
+            .expect("event queue lock poisoned")
+            .take();
+        if let Some(handler) = handler {
+            let _ = handler.join();
+        }
+    }
+
+    fn enqueue(&self, prepend: bool, deadline_ns: Option<i64>, event: Box<dyn Event>) {
+        let context = EventContext {
+            event,
+            enqueue_time_ns: self.shared.time.nanoseconds(),
+            deadline_ns,
+        };
+        let mut state = self.shared.lock();
+        if state.shutting_down {
+            drop(state);
+            context.event.handle_exception(EventQueueError::Closed);
+            return;
+        }
+        if prepend {
+            state.queue.push_front(context);
+        } else {
+            if let Some(max_size) = self.shared.max_size
+                && state.queue.len() >= max_size
+            {
+                drop(state);
+                if let Some(metrics) = &self.shared.metrics {
+                    metrics.rejected.record(1.0);
+                }
+                context
+                    .event
+                    .handle_exception(EventQueueError::QueueFull(max_size));
+                return;
+            }
+            state.queue.push_back(context);
+        }
+        self.shared.cond.notify_one();
+    }
+}
+
+impl EventQueue for KafkaEventQueue {
+    fn prepend(&self, event: Box<dyn Event>) {
+        self.enqueue(true, None, event);
+    }
+
+    fn append(&self, event: Box<dyn Event>) {
+        self.enqueue(false, None, event);
+    }
+
+    fn append_with_deadline(&self, deadline_ns: i64, event: Box<dyn Event>) {
+        self.enqueue(false, Some(deadline_ns), event);
+    }
+
+    fn schedule_deferred(&self, tag: &str, deadline_ns: i64, event: Box<dyn Event>) {
+        let context = EventContext {
+            event,
+            enqueue_time_ns: self.shared.time.nanoseconds(),
+            deadline_ns: None,
+        };
+        let mut state = self.shared.lock();
+        if state.shutting_down {
+            drop(state);
+            context.event.handle_exception(EventQueueError::Closed);
+            return;
+        }
+        let replaced = state.remove_deferred(tag);
+        let key = (deadline_ns, state.next_sequence);
+        state.next_sequence += 1;
+        state.deferred.insert(key, context);
+        state.deferred_tags.insert(tag.to_string(), key);
+        drop(state);
+        self.shared.cond.notify_one();
+        if let Some(replaced) = replaced {
+            replaced.event.handle_exception(EventQueueError::Cancelled);
+        }
+    }
+
+    fn cancel_deferred(&self, tag: &str) {
+        let removed = self.shared.lock().remove_deferred(tag);
+        if let Some(removed) = removed {
+            removed.event.handle_exception(EventQueueError::Cancelled);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.shared.lock().size()
+    }
+
+    fn wakeup(&self) {
+        self.shared.cond.notify_one();
+    }
+
+    fn begin_shutdown(&self, source: &str) {
+        let mut state = self.shared.lock();
+        if !state.shutting_down {
+            info!("{}: shutdown requested by {}", self.thread_name, source);
+            state.shutting_down = true;
+        }
+        self.shared.cond.notify_one();
+    }
+}
+
+impl Drop for KafkaEventQueue {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn handle_events(shared: Arc<Shared>) {
+    loop {
+        let mut state = shared.lock();
+        let (context, timed_out) = loop {
+            let now_ns = shared.time.nanoseconds();
+            let first_deferred = state.deferred.keys().next().copied();
+            if let Some(key) = first_deferred
+                && key.0 <= now_ns
+            {
+                let context = state.deferred.remove(&key).expect("deferred event exists");
+                state.deferred_tags.retain(|_, k| *k != key);
+                break (context, false);
+            }
+            if let Some(context) = state.queue.pop_front() {
+                let timed_out = context.deadline_ns.is_some_and(|d| d <= now_ns);
+                break (context, timed_out);
+            }
+            if state.shutting_down {
+                let deferred = std::mem::take(&mut state.deferred);
+                state.deferred_tags.clear();
+                drop(state);
+                for context in deferred.into_values() {
+                    context.event.handle_exception(EventQueueError::Closed);
+                }
+                return;
+            }
+            state = match first_deferred {
+                Some(key) => {
+                    let wait = Duration::from_nanos((key.0 - now_ns) as u64);
+                    shared
+                        .cond
+                        .wait_timeout(state, wait)
+                        .expect("event queue lock poisoned")
+                        .0
+                }
+                None => shared.cond.wait(state).expect("event queue lock poisoned"),
+            };
+        };
+        drop(state);
+
+        let start_ns = shared.time.nanoseconds();
+        if let Some(metrics) = &shared.metrics {
+            metrics
+                .queue_time
+                .record(nanos_to_millis(start_ns - context.enqueue_time_ns));
+        }
+        let mut event = context.event;
+        if timed_out {
+            event.handle_exception(EventQueueError::Timeout);
+        } else if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| event.run())) {
+            // A failing event must not take the queue down with it.
+            let message = panic_message(payload.as_ref());
+            error!("Unexpected panic in the event handler thread: {message}");
+            event.handle_exception(EventQueueError::Panicked(message));
+        }
+        if let Some(metrics) = &shared.metrics {
+            metrics
+                .processing_time
+                .record(nanos_to_millis(shared.time.nanoseconds() - start_ns));
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+fn nanos_to_millis(ns: i64) -> f64 {
+    ns as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::utils::time::{MockTime, SystemTime};
+    use std::sync::mpsc;
+
+    type Log = Arc<Mutex<Vec<Result<i32, EventQueueError>>>>;
+
+    struct RecordingEvent {
+        id: i32,
+        log: Log,
+    }
+
+    impl RecordingEvent {
+        fn boxed(id: i32, log: &Log) -> Box<Self> {
+            Box::new(Self {
+                id,
+                log: Arc::clone(log),
+            })
+        }
+    }
+
+    impl Event for RecordingEvent {
+        fn run(&mut self) {
+            self.log.lock().unwrap().push(Ok(self.id));
+        }
+
+        fn handle_exception(self: Box<Self>, error: EventQueueError) {
+            self.log.lock().unwrap().push(Err(error));
+        }
+    }
+
+    /// Blocks the handler thread until released, so tests can build up a backlog.
+    fn block(queue: &KafkaEventQueue) -> mpsc::Sender<()> {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        queue.append(crate::queue::event_queue::FnEvent::new(
+            "block",
+            move || {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+            },
+        ));
+        started_rx.recv().unwrap();
+        release_tx
+    }
+
+    fn log() -> Log {
+        Arc::new(Mutex::new(Vec::new()))
+    }
+
+    #[test]
+    fn test_prepend_runs_before_appended_events() {
+        let queue = KafkaEventQueue::new(Arc::new(SystemTime), "test-", None, None);
+        let log = log();
+        let release = block(&queue);
+        queue.append(RecordingEvent::boxed(1, &log));
+        queue.append(RecordingEvent::boxed(2, &log));
+        queue.prepend(RecordingEvent::boxed(3, &log));
+        assert_eq!(3, queue.size());
+        release.send(()).unwrap();
+        queue.close();
+        assert_eq!(vec![Ok(3), Ok(1), Ok(2)], *log.lock().unwrap());
+    }
+
+    struct PanickingEvent {
+        log: Log,
+    }
+
+    impl Event for PanickingEvent {
+        fn run(&mut self) {
+            panic!("boom");
+        }
+
+        fn handle_exception(self: Box<Self>, error: EventQueueError) {
+            self.log.lock().unwrap().push(Err(error));
+        }
+    }
+
+    #[test]
+    fn test_panicking_event_does_not_stop_the_queue() {
+        let queue = KafkaEventQueue::new(Arc::new(SystemTime), "test-", None, None);
+        let log = log();
+        queue.append(Box::new(PanickingEvent {
+            log: Arc::clone(&log),
+        }));
+        queue.append(RecordingEvent::boxed(1, &log));
+        queue.close();
+        assert_eq!(
+            vec![Err(EventQueueError::Panicked("boom".to_string())), Ok(1)],
+            *log.lock().unwrap()
+        );
+        assert!(!EventQueueError::Panicked("boom".to_string()).is_retriable());
+    }
+
+    #[test]
+    fn test_full_queue_rejects_appends_with_retriable_error() {
+        let metrics = Metrics::default();
+        let queue = KafkaEventQueue::new(Arc::new(SystemTime), "test-", Some(1), Some(&metrics));
+        let log = log();
+        let release = block(&queue);
+        queue.append(RecordingEvent::boxed(1, &log));
+        queue.append(RecordingEvent::boxed(2, &log));
+        // Prepended events bypass the limit.
+        queue.prepend(RecordingEvent::boxed(3, &log));
+        release.send(()).unwrap();
+        queue.close();
+
+        let log = log.lock().unwrap();
+        assert_eq!(vec![Err(EventQueueError::QueueFull(1)), Ok(3), Ok(1)], *log);
+        assert!(EventQueueError::QueueFull(1).is_retriable());
+        let rejected = metrics.metric_name(
+            "event-queue-rejected-total",
+            METRIC_GROUP,
+            "",
+            &[("queue", "test-event-handler")],
+        );
+        assert_eq!(Some(1.0), metrics.metric_value(&rejected));
+    }
+
+    #[test]
+    fn test_expired_deadline_fails_the_event() {
+        let time = Arc::new(MockTime::with_start(0, 0, 0));
+        let metrics = Metrics::default();
+        let queue = KafkaEventQueue::new(time.clone(), "test-", None, Some(&metrics));
+        let log = log();
+        let release = block(&queue);
+        queue.append_with_deadline(5_000_000, RecordingEvent::boxed(1, &log));
+        queue.append_with_deadline(50_000_000, RecordingEvent::boxed(2, &log));
+        time.sleep(10);
+        release.send(()).unwrap();
+        queue.close();
+        assert_eq!(
+            vec![Err(EventQueueError::Timeout), Ok(2)],
+            *log.lock().unwrap()
+        );
+
+        let max_queue_time = metrics.metric_name(
+            "event-queue-time-ms-max",
+            METRIC_GROUP,
+            "",
+            &[("queue", "test-event-handler")],
+        );
+        assert_eq!(Some(10.0), metrics.metric_value(&max_queue_time));
+    }
+
+    #[test]
+    fn test_deferred_events() {
+        let time = Arc::new(MockTime::with_start(0, 0, 0));
+        let queue = KafkaEventQueue::new(time.clone(), "test-", None, None);
+        let log = log();
+        queue.schedule_deferred("a", 10_000_000, RecordingEvent::boxed(1, &log));
+        queue.schedule_deferred("a", 20_000_000, RecordingEvent::boxed(2, &log));
+        queue.schedule_deferred("b", 30_000_000, RecordingEvent::boxed(3, &log));
+        queue.schedule_deferred("c", 40_000_000, RecordingEvent::boxed(4, &log));
+        queue.cancel_deferred("c");
+        assert_eq!(2, queue.size());
+
+        time.sleep(25);
+        queue.wakeup();
+        while log.lock().unwrap().len() < 3 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        queue.close();
+        assert_eq!(
+            vec![
+                Err(EventQueueError::Cancelled),
+                Err(EventQueueError::Cancelled),
+                Ok(2),
+                Err(EventQueueError::Closed),
+            ],
+            *log.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_events_are_rejected_after_shutdown() {
+        let queue = KafkaEventQueue::new(Arc::new(SystemTime), "test-", None, None);
+        let log = log();
+        queue.close();
+        queue.append(RecordingEvent::boxed(1, &log));
+        assert_eq!(vec![Err(EventQueueError::Closed)], *log.lock().unwrap());
+        assert!(!EventQueueError::Closed.is_retriable());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod event_queue;
+pub mod kafka_event_queue;
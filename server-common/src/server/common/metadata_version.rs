@@ -0,0 +1,116 @@
+/// The versions of the metadata log format, identified by their `metadata.version` feature level.
+///
+/// Only KRaft-capable versions are listed, starting with the minimum version a cluster can be
+/// bootstrapped with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MetadataVersion {
+    Ibp3_3Iv3,
+    Ibp3_4Iv0,
+    Ibp3_5Iv2,
+    Ibp3_6Iv2,
+    Ibp3_7Iv4,
+    Ibp3_8Iv0,
+    Ibp3_9Iv0,
+}
+
+impl MetadataVersion {
+    /// The name of the feature which carries the metadata version.
+    pub const FEATURE_NAME: &'static str = "metadata.version";
+
+    /// The lowest version a new cluster can be formatted with.
+    pub const MINIMUM_BOOTSTRAP_VERSION: MetadataVersion = MetadataVersion::Ibp3_3Iv3;
+
+    /// The latest version that is production ready, used when formatting without an explicit
+    /// release version.
+    pub const LATEST_PRODUCTION: MetadataVersion = MetadataVersion::Ibp3_9Iv0;
+
+    /// The permanent feature level of this version -- this can't change, since it is persisted
+    /// in the metadata log.
+    pub fn feature_level(&self) -> i16 {
+        match self {
+            MetadataVersion::Ibp3_3Iv3 => 7,
+            MetadataVersion::Ibp3_4Iv0 => 8,
+            MetadataVersion::Ibp3_5Iv2 => 11,
+            MetadataVersion::Ibp3_6Iv2 => 14,
+            MetadataVersion::Ibp3_7Iv4 => 19,
+            MetadataVersion::Ibp3_8Iv0 => 20,
+            MetadataVersion::Ibp3_9Iv0 => 21,
+        }
+    }
+
+    /// The release version string, e.g. `3.7-IV4`.
+    pub fn version(&self) -> &'static str {
+        match self {
+            MetadataVersion::Ibp3_3Iv3 => "3.3-IV3",
+            MetadataVersion::Ibp3_4Iv0 => "3.4-IV0",
+            MetadataVersion::Ibp3_5Iv2 => "3.5-IV2",
+            MetadataVersion::Ibp3_6Iv2 => "3.6-IV2",
+            MetadataVersion::Ibp3_7Iv4 => "3.7-IV4",
+            MetadataVersion::Ibp3_8Iv0 => "3.8-IV0",
+            MetadataVersion::Ibp3_9Iv0 => "3.9-IV0",
+        }
+    }
+
+    /// Whether SCRAM credentials can be stored in the metadata log.
+    pub fn is_scram_supported(&self) -> bool {
+        *self >= MetadataVersion::Ibp3_5Iv2
+    }
+
+    /// Returns the `MetadataVersion` with the given feature level.
+    pub fn from_feature_level(level: i16) -> Option<Self> {
+        Self::values().find(|v| v.feature_level() == level)
+    }
+
+    /// Looks up a version by its release version string. A bare release such as `3.7` maps
+    /// to the latest version of that release.
+    pub fn from_version_string(version: &str) -> Option<Self> {
+        Self::values().rev().find(|v| {
+            v.version() == version
+                || v.version()
+                    .strip_prefix(version)
+                    .is_some_and(|rest| rest.starts_with("-IV"))
+        })
+    }
+
+    pub fn values() -> impl DoubleEndedIterator<Item = Self> {
+        [
+            MetadataVersion::Ibp3_3Iv3,
+            MetadataVersion::Ibp3_4Iv0,
+            MetadataVersion::Ibp3_5Iv2,
+            MetadataVersion::Ibp3_6Iv2,
+            MetadataVersion::Ibp3_7Iv4,
+            MetadataVersion::Ibp3_8Iv0,
+            MetadataVersion::Ibp3_9Iv0,
+        ]
+        .into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(
+            Some(MetadataVersion::Ibp3_7Iv4),
+            MetadataVersion::from_feature_level(19)
+        );
+        assert_eq!(None, MetadataVersion::from_feature_level(1));
+        assert_eq!(
+            Some(MetadataVersion::Ibp3_5Iv2),
+            MetadataVersion::from_version_string("3.5")
+        );
+        assert_eq!(
+            Some(MetadataVersion::Ibp3_4Iv0),
+            MetadataVersion::from_version_string("3.4-IV0")
+        );
+        assert_eq!(None, MetadataVersion::from_version_string("2.8"));
+    }
+
+    #[test]
+    fn test_scram_support() {
+        assert!(!MetadataVersion::Ibp3_4Iv0.is_scram_supported());
+        assert!(MetadataVersion::Ibp3_5Iv2.is_scram_supported());
+    }
+}
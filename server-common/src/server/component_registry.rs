@@ -0,0 +1,180 @@
+//! Resolves the pluggable components configured by name, e.g. `replica.selector.class`.
+//!
+//! Apache Kafka configures these with Java class names. Here the Rust implementations are
+//! registered under a short key, and under the class names of the Java implementations they
+//! replace so existing configs keep working. Names are resolved once at startup, and an
+//! unknown name fails the startup with the names which would have been valid.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ComponentError {
+    #[error("Unknown {kind} {name}, expected one of {}", known.join(", "))]
+    Unknown {
+        kind: &'static str,
+        name: String,
+        known: Vec<String>,
+    },
+
+    #[error("{kind} {name} is already registered")]
+    Duplicate { kind: &'static str, name: String },
+}
+
+type Factory<T> = Arc<dyn Fn() -> Arc<T> + Send + Sync>;
+
+/// The implementations of one kind of component, keyed by name.
+pub struct ComponentRegistry<T: ?Sized> {
+    /// The kind of component, e.g. `replica selector`, which errors refer to.
+    kind: &'static str,
+    factories: BTreeMap<String, Factory<T>>,
+    /// The Java class names, mapped to the key of the implementation replacing them.
+    aliases: BTreeMap<String, String>,
+}
+
+impl<T: ?Sized> ComponentRegistry<T> {
+    pub fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            factories: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+        }
+    }
+
+    /// Registers an implementation under `key` and `aliases`, which `factory` creates.
+    pub fn register(
+        &mut self,
+        key: &str,
+        aliases: &[&str],
+        factory: impl Fn() -> Arc<T> + Send + Sync + 'static,
+    ) -> Result<(), ComponentError> {
+        for name in std::iter::once(&key).chain(aliases) {
+            if self.factories.contains_key(*name) || self.aliases.contains_key(*name) {
+                return Err(ComponentError::Duplicate {
+                    kind: self.kind,
+                    name: name.to_string(),
+                });
+            }
+        }
+        self.factories.insert(key.to_string(), Arc::new(factory));
+        for alias in aliases {
+            self.aliases.insert(alias.to_string(), key.to_string());
+        }
+        Ok(())
+    }
+
+    /// The key `name` resolves to, if it is a key or an alias.
+    pub fn key_of<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        let name = name.trim();
+        if self.factories.contains_key(name) {
+            Some(name)
+        } else {
+            self.aliases.get(name).map(String::as_str)
+        }
+    }
+
+    /// Creates the implementation registered under `name`.
+    pub fn resolve(&self, name: &str) -> Result<Arc<T>, ComponentError> {
+        let factory = self
+            .key_of(name)
+            .and_then(|key| self.factories.get(key))
+            .ok_or_else(|| ComponentError::Unknown {
+                kind: self.kind,
+                name: name.to_string(),
+                known: self.names().map(str::to_string).collect(),
+            })?;
+        Ok(factory())
+    }
+
+    /// Creates the implementation registered under `name`, or under `default` if `name` is
+    /// empty, i.e. not configured.
+    pub fn resolve_or(&self, name: &str, default: &str) -> Result<Arc<T>, ComponentError> {
+        if name.trim().is_empty() {
+            self.resolve(default)
+        } else {
+            self.resolve(name)
+        }
+    }
+
+    /// The keys and aliases, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories
+            .keys()
+            .chain(self.aliases.keys())
+            .map(String::as_str)
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for ComponentRegistry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentRegistry")
+            .field("kind", &self.kind)
+            .field("keys", &self.factories.keys().collect::<Vec<_>>())
+            .field("aliases", &self.aliases)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    trait Greeter: Send + Sync {
+        fn greet(&self) -> String;
+    }
+
+    struct English;
+
+    impl Greeter for English {
+        fn greet(&self) -> String {
+            "hello".to_string()
+        }
+    }
+
+    fn registry() -> ComponentRegistry<dyn Greeter> {
+        let mut registry = ComponentRegistry::<dyn Greeter>::new("greeter");
+        registry
+            .register("english", &["org.example.EnglishGreeter"], || {
+                Arc::new(English)
+            })
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_resolve_by_key_and_alias() {
+        let registry = registry();
+        assert_eq!("hello", registry.resolve("english").unwrap().greet());
+        assert_eq!(
+            "hello",
+            registry
+                .resolve(" org.example.EnglishGreeter ")
+                .unwrap()
+                .greet()
+        );
+        assert_eq!("hello", registry.resolve_or("", "english").unwrap().greet());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut registry = registry();
+        let error = registry.resolve("french").err().unwrap();
+        assert_eq!(
+            "Unknown greeter french, expected one of english, org.example.EnglishGreeter",
+            error.to_string()
+        );
+        assert_eq!(
+            Err(ComponentError::Duplicate {
+                kind: "greeter",
+                name: "org.example.EnglishGreeter".to_string()
+            }),
+            registry.register("other", &["org.example.EnglishGreeter"], || {
+                Arc::new(English)
+            })
+        );
+        // A failed registration registers nothing.
+        assert!(registry.resolve("other").is_err());
+    }
+}
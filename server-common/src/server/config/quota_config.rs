@@ -6,6 +6,28 @@ const NUM_QUOTA_SAMPLES_DEFAULT:u32 = 11;
 
 pub const QUOTA_WINDOW_SIZE_SECONDS_CONFIG: &str = "quota.window.size.seconds";
 
+/// The dynamic quota keys of user and client-id entities.
+pub const PRODUCER_BYTE_RATE_OVERRIDE_CONFIG: &str = "producer_byte_rate";
+pub const CONSUMER_BYTE_RATE_OVERRIDE_CONFIG: &str = "consumer_byte_rate";
+pub const REQUEST_PERCENTAGE_OVERRIDE_CONFIG: &str = "request_percentage";
+pub const CONTROLLER_MUTATION_RATE_OVERRIDE_CONFIG: &str = "controller_mutation_rate";
+
+/// The dynamic quota key of IP entities: the number of connections an IP address may create
+/// per second.
+pub const IP_CONNECTION_RATE_OVERRIDE_CONFIG: &str = "connection_creation_rate";
+pub const IP_CONNECTION_RATE_DEFAULT: i32 = i32::MAX;
+
+/// Whether `key` is a quota of user and client-id entities.
+pub fn is_user_client_id_quota_key(key: &str) -> bool {
+    matches!(
+        key,
+        PRODUCER_BYTE_RATE_OVERRIDE_CONFIG
+            | CONSUMER_BYTE_RATE_OVERRIDE_CONFIG
+            | REQUEST_PERCENTAGE_OVERRIDE_CONFIG
+            | CONTROLLER_MUTATION_RATE_OVERRIDE_CONFIG
+    )
+}
+
 #[derive(Debug, EasyConfig)]
 pub struct QuotaConfig {
     #[attr(name = NUM_QUOTA_SAMPLES_CONFIG,
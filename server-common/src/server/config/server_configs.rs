@@ -10,11 +10,48 @@ const BACKGROUND_THREADS_DEFAULT: u32 = 10;
 const BACKGROUND_THREADS_DOC: &str =
     "The number of threads to use for various background processing tasks";
 
+pub const NUM_IO_THREADS_CONFIG: &str = "num.io.threads";
+const NUM_IO_THREADS_DEFAULT: u32 = 8;
+const NUM_IO_THREADS_DOC: &str =
+    "The number of threads that the server uses for processing requests.";
+
+pub const NUM_LOG_IO_THREADS_CONFIG: &str = "num.log.io.threads";
+const NUM_LOG_IO_THREADS_DEFAULT: u32 = 4;
+const NUM_LOG_IO_THREADS_DOC: &str = "The number of threads that the server uses for the blocking IO of the logs, \
+such as loading, flushing and truncating them. They are apart from the threads handling requests and the controller, \
+so that a stalled disk can't starve the heartbeats or the raft elections.";
+
+pub const NUM_CONTROLLER_THREADS_CONFIG: &str = "num.controller.threads";
+const NUM_CONTROLLER_THREADS_DEFAULT: u32 = 1;
+const NUM_CONTROLLER_THREADS_DOC: &str = "The number of threads that the controller uses for its raft client \
+and its event queue. The events of the controller are handled one at a time whatever the number of threads.";
+
 pub const DELETE_TOPIC_ENABLE_CONFIG: &str = "delete.topic.enable";
 const DELETE_TOPIC_ENABLE_DEFAULT: bool = true;
 const DELETE_TOPIC_ENABLE_DOC: &str = "When set to true, topics can be deleted by the admin client. \
 When set to false, deletion requests will be explicitly rejected by the broker.";
 
+/** ********* Fetch Session Configuration ***********/
+pub const MAX_INCREMENTAL_FETCH_SESSION_CACHE_SLOTS_CONFIG: &str =
+    "max.incremental.fetch.session.cache.slots";
+const MAX_INCREMENTAL_FETCH_SESSION_CACHE_SLOTS_DEFAULT: i32 = 1000;
+const MAX_INCREMENTAL_FETCH_SESSION_CACHE_SLOTS_DOC: &str =
+    "The maximum number of incremental fetch sessions that we will maintain.";
+
+pub const MAX_INCREMENTAL_FETCH_SESSION_CACHE_PARTITIONS_CONFIG: &str =
+    "max.incremental.fetch.session.cache.partitions";
+const MAX_INCREMENTAL_FETCH_SESSION_CACHE_PARTITIONS_DEFAULT: i32 = 1_000_000;
+const MAX_INCREMENTAL_FETCH_SESSION_CACHE_PARTITIONS_DOC: &str = "The maximum number of partitions \
+cached by all incremental fetch sessions together. When a new session would exceed it, sessions of \
+consumers are evicted before sessions of followers, and a session which can't make room is not created.";
+
+pub const MAX_INCREMENTAL_FETCH_SESSION_CACHE_BYTES_CONFIG: &str =
+    "max.incremental.fetch.session.cache.bytes";
+const MAX_INCREMENTAL_FETCH_SESSION_CACHE_BYTES_DEFAULT: i64 = 256 * 1024 * 1024;
+const MAX_INCREMENTAL_FETCH_SESSION_CACHE_BYTES_DOC: &str = "The maximum memory, in bytes, of the \
+partitions cached by all incremental fetch sessions together, as estimated from the topic names and the \
+fetch state of the partitions. It is enforced as max.incremental.fetch.session.cache.partitions is.";
+
 /***************** rack configuration *************/
 pub const BROKER_RACK_CONFIG: &str = "broker.rack";
 const BROKER_RACK_DOC: &str = "Rack of the broker. This will be used in rack aware replication assignment for fault tolerance. Examples: <code>RACK1</code>, <code>us-east-1d</code>";
@@ -44,6 +81,55 @@ pub struct ServerConfig {
     getter)]
     background_threads_config: u32,
 
+    #[attr(name = NUM_IO_THREADS_CONFIG,
+    default = NUM_IO_THREADS_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::HIGH,
+    documentation = NUM_IO_THREADS_DOC,
+    getter)]
+    num_io_threads_config: u32,
+
+    #[attr(name = NUM_LOG_IO_THREADS_CONFIG,
+    default = NUM_LOG_IO_THREADS_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::MEDIUM,
+    documentation = NUM_LOG_IO_THREADS_DOC,
+    getter)]
+    num_log_io_threads_config: u32,
+
+    #[attr(name = NUM_CONTROLLER_THREADS_CONFIG,
+    default = NUM_CONTROLLER_THREADS_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::LOW,
+    documentation = NUM_CONTROLLER_THREADS_DOC,
+    getter)]
+    num_controller_threads_config: u32,
+
+    /** ********* Fetch Session Configuration ***********/
+    #[attr(name = MAX_INCREMENTAL_FETCH_SESSION_CACHE_SLOTS_CONFIG,
+    default = MAX_INCREMENTAL_FETCH_SESSION_CACHE_SLOTS_DEFAULT,
+    validator = Range::at_least(0),
+    importance = Importance::MEDIUM,
+    documentation = MAX_INCREMENTAL_FETCH_SESSION_CACHE_SLOTS_DOC,
+    getter)]
+    max_incremental_fetch_session_cache_slots_config: i32,
+
+    #[attr(name = MAX_INCREMENTAL_FETCH_SESSION_CACHE_PARTITIONS_CONFIG,
+    default = MAX_INCREMENTAL_FETCH_SESSION_CACHE_PARTITIONS_DEFAULT,
+    validator = Range::at_least(0),
+    importance = Importance::MEDIUM,
+    documentation = MAX_INCREMENTAL_FETCH_SESSION_CACHE_PARTITIONS_DOC,
+    getter)]
+    max_incremental_fetch_session_cache_partitions_config: i32,
+
+    #[attr(name = MAX_INCREMENTAL_FETCH_SESSION_CACHE_BYTES_CONFIG,
+    default = MAX_INCREMENTAL_FETCH_SESSION_CACHE_BYTES_DEFAULT,
+    validator = Range::at_least(0),
+    importance = Importance::MEDIUM,
+    documentation = MAX_INCREMENTAL_FETCH_SESSION_CACHE_BYTES_DOC,
+    getter)]
+    max_incremental_fetch_session_cache_bytes_config: i64,
+
     /************ Rack Configuration ******************/
     #[attr(name = BROKER_RACK_CONFIG,
     importance = Importance::MEDIUM,
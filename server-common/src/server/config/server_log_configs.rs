@@ -18,6 +18,11 @@ pub const LOG_DELETE_DELAY_MS_DOC: &str = "The amount of time to wait before del
 the filesystem. If the value is 0 and there is no file to delete, the system will wait 1 millisecond. \
 Low value will cause busy waiting";
 
+pub const NUM_RECOVERY_THREADS_PER_DATA_DIR_CONFIG: &str = "num.recovery.threads.per.data.dir";
+pub const NUM_RECOVERY_THREADS_PER_DATA_DIR_DEFAULT: u32 = 2;
+pub const NUM_RECOVERY_THREADS_PER_DATA_DIR_DOC: &str = "The number of threads per data directory to be \
+used for log recovery at startup and flushing at shutdown";
+
 pub const LOG_INITIAL_TASK_DELAY_MS_CONFIG: &str = log_prefix!("initial.task.delay.ms");
 pub const LOG_INITIAL_TASK_DELAY_MS_DEFAULT: i64 = 30 * 1000;
 pub const LOG_INITIAL_TASK_DELAY_MS_DOC: &str = "The initial task delay in millisecond when initializing \
@@ -0,0 +1,212 @@
+//! Hooks which let tests inject faults into the storage and controller code paths, to exercise
+//! their error handling deterministically: IO errors on segment operations, slow fsyncs or
+//! heartbeats lost on the way to the controller.
+//!
+//! The hooks only act with the `fault-injection` feature, which tests enable; without it
+//! [check] and [should_drop] do nothing and no fault can be injected.
+
+use std::io;
+use std::path::Path;
+
+/// An operation a fault can be injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FaultPoint {
+    SegmentOpen,
+    SegmentAppend,
+    /// The fsync of a segment.
+    SegmentFlush,
+    SegmentTruncate,
+    SegmentDelete,
+    /// A heartbeat received by the controller, targeted by broker id.
+    ControllerHeartbeat,
+}
+
+#[cfg(feature = "fault-injection")]
+pub use injection::{Fault, FaultGuard, inject};
+
+/// Applies the faults injected at `point` for `target`: fails with the injected IO error or
+/// sleeps for the injected delay before the operation goes on.
+#[cfg(feature = "fault-injection")]
+pub fn check(point: FaultPoint, target: impl AsRef<Path>) -> io::Result<()> {
+    injection::check(point, target.as_ref())
+}
+
+#[cfg(not(feature = "fault-injection"))]
+#[inline(always)]
+pub fn check(_point: FaultPoint, _target: impl AsRef<Path>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Whether the operation at `point` for `target` must be silently dropped.
+#[cfg(feature = "fault-injection")]
+pub fn should_drop(point: FaultPoint, target: impl AsRef<Path>) -> bool {
+    injection::should_drop(point, target.as_ref())
+}
+
+#[cfg(not(feature = "fault-injection"))]
+#[inline(always)]
+pub fn should_drop(_point: FaultPoint, _target: impl AsRef<Path>) -> bool {
+    false
+}
+
+#[cfg(feature = "fault-injection")]
+mod injection {
+    use super::FaultPoint;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Mutex, MutexGuard};
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Fault {
+        /// Fails the operation with an IO error of this kind.
+        Error(io::ErrorKind),
+        /// Delays the operation, e.g. to simulate a slow disk.
+        Delay(Duration),
+        /// Drops the operation without any error.
+        Drop,
+    }
+
+    struct Injection {
+        id: u64,
+        point: FaultPoint,
+        target: PathBuf,
+        fault: Fault,
+        remaining: Option<usize>,
+    }
+
+    struct Injections {
+        next_id: u64,
+        injections: Vec<Injection>,
+    }
+
+    // Tests run in parallel, they keep out of each other's way by targeting their own files
+    // or brokers.
+    static INJECTIONS: Mutex<Injections> = Mutex::new(Injections {
+        next_id: 0,
+        injections: Vec::new(),
+    });
+
+    fn injections() -> MutexGuard<'static, Injections> {
+        // A test failing while holding the lock must not fail the other tests.
+        INJECTIONS.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Removes the fault it was returned for when dropped.
+    #[must_use = "the fault is removed when the guard is dropped"]
+    #[derive(Debug)]
+    pub struct FaultGuard {
+        id: u64,
+    }
+
+    impl Drop for FaultGuard {
+        fn drop(&mut self) {
+            injections().injections.retain(|i| i.id != self.id);
+        }
+    }
+
+    /// Injects `fault` into the operations at `point` on `target` or on a path under it, e.g.
+    /// the segments of a log directory. The fault applies `times` times, or until the guard is
+    /// dropped if `None`.
+    pub fn inject(
+        point: FaultPoint,
+        target: impl AsRef<Path>,
+        fault: Fault,
+        times: Option<usize>,
+    ) -> FaultGuard {
+        let mut injections = injections();
+        let id = injections.next_id;
+        injections.next_id += 1;
+        injections.injections.push(Injection {
+            id,
+            point,
+            target: target.as_ref().to_path_buf(),
+            fault,
+            remaining: times,
+        });
+        FaultGuard { id }
+    }
+
+    /// Takes the first fault matching the operation, consuming one of its occurrences.
+    fn take(point: FaultPoint, target: &Path, matches: impl Fn(&Fault) -> bool) -> Option<Fault> {
+        let mut injections = injections();
+        let injection = injections.injections.iter_mut().find(|i| {
+            i.point == point
+                && target.starts_with(&i.target)
+                && i.remaining != Some(0)
+                && matches(&i.fault)
+        })?;
+        if let Some(remaining) = &mut injection.remaining {
+            *remaining -= 1;
+        }
+        Some(injection.fault)
+    }
+
+    pub(super) fn check(point: FaultPoint, target: &Path) -> io::Result<()> {
+        match take(point, target, |fault| *fault != Fault::Drop) {
+            Some(Fault::Error(kind)) => Err(io::Error::new(
+                kind,
+                format!("injected {point:?} fault on {}", target.display()),
+            )),
+            Some(Fault::Delay(delay)) => {
+                thread::sleep(delay);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn should_drop(point: FaultPoint, target: &Path) -> bool {
+        take(point, target, |fault| *fault == Fault::Drop).is_some()
+    }
+}
+
+#[cfg(all(test, feature = "fault-injection"))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_faults_apply_to_paths_under_the_target() {
+        let guard = inject(
+            FaultPoint::SegmentAppend,
+            "/faults/a",
+            Fault::Error(io::ErrorKind::StorageFull),
+            Some(2),
+        );
+        let error = check(FaultPoint::SegmentAppend, "/faults/a/0.log").unwrap_err();
+        assert_eq!(io::ErrorKind::StorageFull, error.kind());
+        assert!(check(FaultPoint::SegmentFlush, "/faults/a/0.log").is_ok());
+        assert!(check(FaultPoint::SegmentAppend, "/faults/ab/0.log").is_ok());
+        assert!(check(FaultPoint::SegmentAppend, "/faults/a/0.log").is_err());
+        // The fault only applied twice.
+        assert!(check(FaultPoint::SegmentAppend, "/faults/a/0.log").is_ok());
+        drop(guard);
+    }
+
+    #[test]
+    fn test_guard_removes_the_fault() {
+        let guard = inject(FaultPoint::ControllerHeartbeat, "7", Fault::Drop, None);
+        assert!(should_drop(FaultPoint::ControllerHeartbeat, "7"));
+        assert!(should_drop(FaultPoint::ControllerHeartbeat, "7"));
+        assert!(!should_drop(FaultPoint::ControllerHeartbeat, "70"));
+        // A dropped operation is not an error.
+        assert!(check(FaultPoint::ControllerHeartbeat, "7").is_ok());
+        drop(guard);
+        assert!(!should_drop(FaultPoint::ControllerHeartbeat, "7"));
+    }
+
+    #[test]
+    fn test_delay() {
+        let _guard = inject(
+            FaultPoint::SegmentFlush,
+            "/faults/delay",
+            Fault::Delay(Duration::from_millis(50)),
+            Some(1),
+        );
+        let start = Instant::now();
+        check(FaultPoint::SegmentFlush, "/faults/delay/0.log").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -1 +1,4 @@
+pub mod common;
+pub mod component_registry;
 pub mod config;
+pub mod fault_injection;
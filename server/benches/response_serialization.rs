@@ -0,0 +1,154 @@
+//! Measures the serialization of the metadata of 10k topics, and of a fetch of 1k partitions,
+//! into a new buffer grown as the response is written, into a new buffer sized up front, and
+//! into a reused pooled buffer.
+//!
+//! Growing a buffer of that size mostly costs memory rather than time: the allocator grows
+//! large buffers in place, but the buffer ends up to twice as large as the response. Sizing
+//! the metadata response takes a pass over every field, which costs more than growing the
+//! buffer, so the pool writes it without sizing it: once the pool is warm, its buffer is not
+//! allocated nor grown at all. Sizing the fetch response only adds up the lengths of its
+//! batches, so it is sized up front, pooled or not.
+//!
+//! Run with `cargo bench -p rafka-server --bench response_serialization`.
+
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+use rafka_clients::common::requests::fetch_response::{
+    FetchResponse, FetchResponsePartition, FetchResponseTopic, INVALID_SESSION_ID,
+    NO_PREFERRED_READ_REPLICA,
+};
+use rafka_clients::common::requests::metadata_response::{
+    AUTHORIZED_OPERATIONS_OMITTED, MetadataResponse, MetadataResponseBroker,
+    MetadataResponsePartition, MetadataResponseTopic,
+};
+use rafka_clients::common::requests::response_writer::WritableResponse;
+use rafka_clients::common::uuid::Uuid;
+use rafka_server::response_buffers::ResponseBufferPool;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const TOPICS: usize = 10_000;
+const PARTITIONS: i32 = 3;
+const VERSION: i16 = 12;
+const FETCH_PARTITIONS: i32 = 1_000;
+const FETCH_BATCH_BYTES: usize = 16 * 1024;
+const FETCH_VERSION: i16 = 16;
+const ITERATIONS: u32 = 50;
+
+fn metadata_response() -> MetadataResponse {
+    let brokers = (0..3)
+        .map(|node_id| MetadataResponseBroker {
+            node_id,
+            host: format!("broker-{node_id}.example.com"),
+            port: 9092,
+            rack: Some(format!("rack-{node_id}")),
+        })
+        .collect();
+    let topics = (0..TOPICS)
+        .map(|i| MetadataResponseTopic {
+            error: Errors::None,
+            name: Some(format!("topic-{i}")),
+            topic_id: Uuid::new(0, i as i64 + 1),
+            is_internal: false,
+            partitions: (0..PARTITIONS)
+                .map(|partition_index| MetadataResponsePartition {
+                    error: Errors::None,
+                    partition_index,
+                    leader_id: partition_index,
+                    leader_epoch: 1,
+                    replica_nodes: vec![0, 1, 2],
+                    isr_nodes: vec![0, 1, 2],
+                    offline_replicas: vec![],
+                })
+                .collect(),
+            topic_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        })
+        .collect();
+    MetadataResponse {
+        throttle_time_ms: 0,
+        brokers,
+        cluster_id: Some("cluster".to_string()),
+        controller_id: 0,
+        topics,
+        cluster_authorized_operations: AUTHORIZED_OPERATIONS_OMITTED,
+        error: Errors::None,
+    }
+}
+
+fn fetch_response() -> FetchResponse {
+    let batch = RecordBatch::new(0, vec![Record::new(0, None, Some(&[0; FETCH_BATCH_BYTES]))]);
+    FetchResponse {
+        throttle_time_ms: 0,
+        error: Errors::None,
+        session_id: INVALID_SESSION_ID,
+        responses: vec![FetchResponseTopic {
+            topic: "topic".to_string(),
+            topic_id: Uuid::new(0, 1),
+            partitions: (0..FETCH_PARTITIONS)
+                .map(|partition_index| FetchResponsePartition {
+                    partition_index,
+                    error: Errors::None,
+                    high_watermark: 1,
+                    last_stable_offset: 1,
+                    log_start_offset: 0,
+                    aborted_transactions: None,
+                    preferred_read_replica: NO_PREFERRED_READ_REPLICA,
+                    records: vec![batch.clone()],
+                })
+                .collect(),
+        }],
+    }
+}
+
+/// Serializes `response` at `version` the ways the pool could.
+fn bench_response(response: &impl WritableResponse, version: i16) {
+    bench("size estimation", || (response.size_in_bytes(version), 0));
+    bench("growing buffer", || {
+        let mut buf = Vec::new();
+        response.write_into(0, version, &mut buf);
+        (buf.len(), buf.capacity())
+    });
+    bench("sized buffer", || {
+        let mut buf = Vec::with_capacity(response.size_in_bytes(version));
+        response.write_into(0, version, &mut buf);
+        (buf.len(), buf.capacity())
+    });
+    let pool = ResponseBufferPool::new(4, usize::MAX);
+    bench("pooled buffer", || {
+        let buffer = pool.frame(response, 0, version);
+        (buffer.len(), buffer.capacity())
+    });
+}
+
+/// Runs `serialize`, returning the size of the response and the capacity of its buffer.
+fn bench(name: &str, mut serialize: impl FnMut() -> (usize, usize)) {
+    let mut sizes = (0, 0);
+    // Warm up, and fill the pool.
+    for _ in 0..5 {
+        sizes = black_box(serialize());
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        sizes = black_box(serialize());
+    }
+    let per_iteration = start.elapsed() / ITERATIONS;
+    println!(
+        "{name:<20} {:>8.3} ms/response, {} bytes, buffer capacity {}",
+        as_millis(per_iteration),
+        sizes.0,
+        sizes.1
+    );
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000.0
+}
+
+fn main() {
+    println!("Metadata v{VERSION} of {TOPICS} topics of {PARTITIONS} partitions");
+    bench_response(&metadata_response(), VERSION);
+    println!(
+        "Fetch v{FETCH_VERSION} of {FETCH_PARTITIONS} partitions of a {FETCH_BATCH_BYTES} byte batch"
+    );
+    bench_response(&fetch_response(), FETCH_VERSION);
+}
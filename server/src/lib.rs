@@ -1,5 +1,18 @@
-pub use network::socket_server_config;
-pub use server::{raft_config, replication_configs};
+#[cfg(feature = "rest-bridge")]
+pub use network::rest_bridge;
+pub use network::{
+    connection_quotas, memory_pool, request_channel, request_context, request_header_check,
+    request_metrics, response_buffers, response_sequencer, sasl_authenticator,
+    socket_server_config,
+};
+pub use server::{
+    broker_lifecycle_manager, broker_server_metrics, broker_topic_stats, client_metrics_manager,
+    client_quota_manager, client_quota_metadata_manager, consumer_lag_metrics,
+    delayed_operation_purgatory, dynamic_config_publisher, fetch_session, leader_end_point,
+    log_reader, metrics_naming, produce_memory_guard, raft_config, record_validator,
+    replica_fetcher, replica_selector, replication_configs, replication_quota_manager,
+    request_deadline,
+};
 
 mod network;
 mod server;
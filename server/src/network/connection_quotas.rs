@@ -0,0 +1,223 @@
+use crate::server::client_quota_manager::throttle_time_ms;
+use rafka_clients::common::metrics::stats::Rate;
+use rafka_clients::common::metrics::{MetricConfig, MetricName, Metrics, Sensor};
+use rafka_server_common::quota_config::IP_CONNECTION_RATE_DEFAULT;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use thiserror::Error;
+use tracing::{debug, info};
+
+pub const DEFAULT_NUM_QUOTA_SAMPLES: usize = 11;
+pub const DEFAULT_QUOTA_WINDOW_SIZE_SECONDS: i64 = 1;
+
+const METRICS_GROUP: &str = "connection-metrics";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionQuotaError {
+    #[error("Connection from {ip} throttled for {throttle_time_ms} ms")]
+    ConnectionThrottled {
+        ip: IpAddr,
+        start_throttle_time_ms: i64,
+        throttle_time_ms: i64,
+    },
+}
+
+/// The connection creation rate quotas of IP addresses.
+///
+/// Each IP address may create at most `connection_creation_rate` connections per second: its
+/// own quota if one is configured, otherwise the default quota of all IP addresses. The
+/// quotas are configured with AlterClientQuotas requests and reach the broker through the
+/// metadata log.
+pub struct ConnectionQuotas {
+    metrics: Metrics,
+    metric_config: MetricConfig,
+    quota_window_size_ms: i64,
+    default_connection_rate: Mutex<i32>,
+    connection_rate_per_ip: Mutex<HashMap<IpAddr, i32>>,
+}
+
+impl ConnectionQuotas {
+    pub fn new(metrics: Metrics, num_quota_samples: usize, quota_window_size_seconds: i64) -> Self {
+        Self {
+            metrics,
+            metric_config: MetricConfig::default()
+                .with_samples(num_quota_samples)
+                .with_time_window_ms(quota_window_size_seconds * 1000),
+            quota_window_size_ms: quota_window_size_seconds * 1000,
+            default_connection_rate: Mutex::new(IP_CONNECTION_RATE_DEFAULT),
+            connection_rate_per_ip: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn connection_rate_per_ip(&self) -> MutexGuard<'_, HashMap<IpAddr, i32>> {
+        self.connection_rate_per_ip
+            .lock()
+            .expect("connection rate lock poisoned")
+    }
+
+    fn default_connection_rate(&self) -> MutexGuard<'_, i32> {
+        self.default_connection_rate
+            .lock()
+            .expect("connection rate lock poisoned")
+    }
+
+    /// Sets the connection creation rate quota of `ip`, or of all IP addresses without their
+    /// own quota if `ip` is `None`. A `None` rate removes the quota.
+    pub fn update_ip_connection_rate_quota(&self, ip: Option<IpAddr>, rate: Option<i32>) {
+        match ip {
+            Some(ip) => {
+                info!("Updating the connection rate quota of {ip} to {rate:?}");
+                match rate {
+                    Some(rate) => self.connection_rate_per_ip().insert(ip, rate),
+                    None => self.connection_rate_per_ip().remove(&ip),
+                };
+            }
+            None => {
+                info!("Updating the default connection rate quota to {rate:?}");
+                *self.default_connection_rate() = rate.unwrap_or(IP_CONNECTION_RATE_DEFAULT);
+            }
+        }
+    }
+
+    /// The connections `ip` may create per second.
+    pub fn connection_rate_for_ip(&self, ip: &IpAddr) -> i32 {
+        self.connection_rate_per_ip()
+            .get(ip)
+            .copied()
+            .unwrap_or_else(|| *self.default_connection_rate())
+    }
+
+    /// Records a connection accepted from `ip` at `now_ms`. If it exceeds the quota of the IP
+    /// address, the connection is not counted and the error tells how long to wait before the
+    /// address may connect again; the acceptor closes the connection once that time passed.
+    pub fn record_ip_connection_maybe_throttle(
+        &self,
+        ip: IpAddr,
+        now_ms: i64,
+    ) -> Result<(), ConnectionQuotaError> {
+        let quota = self.connection_rate_for_ip(&ip);
+        let (sensor, metric_name) = self.ip_connection_rate_sensor(&ip);
+        sensor.record_at(1.0, now_ms);
+        if quota == IP_CONNECTION_RATE_DEFAULT {
+            return Ok(());
+        }
+        let Some(metric) = self.metrics.metric(&metric_name) else {
+            return Ok(());
+        };
+        let rate = metric.metric_value(now_ms);
+        if rate <= quota as f64 {
+            return Ok(());
+        }
+        // The rejected connection must not count against the quota.
+        sensor.record_at(-1.0, now_ms);
+        let throttle_time_ms = throttle_time_ms(
+            rate,
+            quota as f64,
+            metric.window_size_ms(now_ms).unwrap_or(0),
+        )
+        .clamp(0, self.quota_window_size_ms);
+        debug!("Connection rate {rate} of {ip} exceeds its quota {quota}");
+        Err(ConnectionQuotaError::ConnectionThrottled {
+            ip,
+            start_throttle_time_ms: now_ms,
+            throttle_time_ms,
+        })
+    }
+
+    fn ip_connection_rate_sensor(&self, ip: &IpAddr) -> (Arc<Sensor>, MetricName) {
+        let ip = ip.to_string();
+        let metric_name = self.metrics.metric_name(
+            "connection-creation-rate",
+            METRICS_GROUP,
+            "The number of connections created per second by the IP address",
+            &[("ip", &ip)],
+        );
+        let name = format!("connection-creation-rate-{ip}");
+        if let Some(sensor) = self.metrics.get_sensor(&name) {
+            return (sensor, metric_name);
+        }
+        let sensor = self
+            .metrics
+            .sensor_with_config(&name, self.metric_config.clone());
+        sensor.add(metric_name.clone(), Rate::new());
+        (sensor, metric_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::utils::time::MockTime;
+
+    fn connection_quotas() -> ConnectionQuotas {
+        let metrics = Metrics::new(
+            MetricConfig::default(),
+            Arc::new(MockTime::with_start(0, 0, 0)),
+        );
+        ConnectionQuotas::new(metrics, 2, 1)
+    }
+
+    #[test]
+    fn test_ip_connection_rate_quota() {
+        let quotas = connection_quotas();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        for _ in 0..100 {
+            assert!(quotas.record_ip_connection_maybe_throttle(ip, 0).is_ok());
+        }
+
+        quotas.update_ip_connection_rate_quota(None, Some(1_000));
+        quotas.update_ip_connection_rate_quota(Some(ip), Some(5));
+        assert_eq!(5, quotas.connection_rate_for_ip(&ip));
+        assert_eq!(1_000, quotas.connection_rate_for_ip(&other));
+
+        // A second of history: the earlier 100 connections are well beyond 5 per second.
+        match quotas.record_ip_connection_maybe_throttle(ip, 500) {
+            Err(ConnectionQuotaError::ConnectionThrottled {
+                throttle_time_ms, ..
+            }) => assert_eq!(1_000, throttle_time_ms),
+            result => panic!("unexpected {result:?}"),
+        }
+        assert!(
+            quotas
+                .record_ip_connection_maybe_throttle(other, 500)
+                .is_ok()
+        );
+
+        // Once the old samples expire, the address may connect again.
+        assert!(
+            quotas
+                .record_ip_connection_maybe_throttle(ip, 2_500)
+                .is_ok()
+        );
+
+        quotas.update_ip_connection_rate_quota(Some(ip), None);
+        assert_eq!(1_000, quotas.connection_rate_for_ip(&ip));
+        quotas.update_ip_connection_rate_quota(None, None);
+        assert_eq!(
+            IP_CONNECTION_RATE_DEFAULT,
+            quotas.connection_rate_for_ip(&other)
+        );
+    }
+
+    #[test]
+    fn test_rejected_connections_are_not_counted() {
+        let quotas = connection_quotas();
+        let ip: IpAddr = "::1".parse().unwrap();
+        quotas.update_ip_connection_rate_quota(Some(ip), Some(2));
+        // The window is padded to one second: two connections are within the quota.
+        assert!(quotas.record_ip_connection_maybe_throttle(ip, 0).is_ok());
+        assert!(quotas.record_ip_connection_maybe_throttle(ip, 0).is_ok());
+        for _ in 0..10 {
+            assert!(quotas.record_ip_connection_maybe_throttle(ip, 0).is_err());
+        }
+        // Only the two accepted connections count towards the rate, three with this one.
+        quotas.update_ip_connection_rate_quota(Some(ip), Some(3));
+        assert!(
+            quotas
+                .record_ip_connection_maybe_throttle(ip, 1_000)
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeSet;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tracing::{debug, info};
+
+/// The memory the processors of a broker read requests into, bounded by
+/// `queued.max.request.bytes`.
+///
+/// A processor is meant to allocate the buffer of a request once it read its size, and the
+/// buffer goes back to the pool when the request is dropped, after it was handled. When the
+/// pool runs out of memory, the processors stop reading from their connections, see
+/// [MemoryMutedChannels], so a broker flooded with produce requests pushes back on the
+/// producers instead of running out of memory. The socket server has no processors reading
+/// requests yet, so nothing allocates from the pool so far.
+///
+/// Like `SimpleMemoryPool` in the Java code, an allocation succeeds as long as some memory is
+/// available, even if it is smaller than the request: the pool can overshoot by up to the
+/// largest request, `socket.request.max.bytes`, but a request larger than the free memory is
+/// never starved by smaller ones.
+#[derive(Debug, Clone)]
+pub struct MemoryPool {
+    /// The size of the pool, `None` if it is unbounded.
+    size: Option<i64>,
+    available: Arc<AtomicI64>,
+}
+
+impl MemoryPool {
+    /// A pool of `size` bytes.
+    pub fn new(size: usize) -> Self {
+        Self {
+            size: Some(size as i64),
+            available: Arc::new(AtomicI64::new(size as i64)),
+        }
+    }
+
+    /// A pool which never runs out of memory, used when `queued.max.request.bytes` is not set.
+    pub fn unbounded() -> Self {
+        Self {
+            size: None,
+            available: Arc::new(AtomicI64::new(i64::MAX)),
+        }
+    }
+
+    /// A pool for `queued.max.request.bytes`, unbounded if it is not positive.
+    pub fn from_config(queued_max_request_bytes: i64) -> Self {
+        if queued_max_request_bytes > 0 {
+            Self::new(queued_max_request_bytes as usize)
+        } else {
+            Self::unbounded()
+        }
+    }
+
+    pub fn size(&self) -> Option<i64> {
+        self.size
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.size.is_some()
+    }
+
+    /// The bytes which can still be allocated, negative if the pool overshot its size.
+    pub fn available_memory(&self) -> i64 {
+        self.available.load(Ordering::Acquire)
+    }
+
+    /// The bytes of the buffers not returned yet.
+    pub fn used_memory(&self) -> i64 {
+        self.size.map_or(0, |size| size - self.available_memory())
+    }
+
+    pub fn is_out_of_memory(&self) -> bool {
+        self.available_memory() <= 0
+    }
+
+    /// Allocates a zeroed buffer of `size` bytes, or returns `None` if the pool is out of
+    /// memory.
+    pub fn try_allocate(&self, size: usize) -> Option<PooledBuffer> {
+        if self.is_bounded() {
+            let size = size as i64;
+            self.available
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |available| {
+                    (available > 0).then_some(available - size)
+                })
+                .ok()?;
+        }
+        Some(PooledBuffer {
+            buf: vec![0; size],
+            pool: self.is_bounded().then(|| Arc::clone(&self.available)),
+        })
+    }
+}
+
+/// A buffer allocated from a [MemoryPool], returned to it on drop.
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buf: Vec<u8>,
+    pool: Option<Arc<AtomicI64>>,
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            pool.fetch_add(self.buf.len() as i64, Ordering::AcqRel);
+        }
+    }
+}
+
+/// The connections a processor stopped reading from because the [MemoryPool] was out of
+/// memory.
+///
+/// A processor which cannot allocate the buffer of a request mutes the connection, leaving
+/// the request in the socket, and stops reading from any of its connections until memory is
+/// returned to the pool: the clients then block on full socket buffers rather than the broker
+/// on full memory. Connections muted for another reason, e.g. while a request is in flight or
+/// while a client is throttled, are muted and unmuted by their own logic.
+#[derive(Debug, Default)]
+pub struct MemoryMutedChannels {
+    muted: BTreeSet<String>,
+}
+
+impl MemoryMutedChannels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocates the buffer of a request of `size` bytes read from `connection_id`, muting
+    /// the connection if the pool is out of memory.
+    pub fn try_allocate(
+        &mut self,
+        pool: &MemoryPool,
+        connection_id: &str,
+        size: usize,
+    ) -> Option<PooledBuffer> {
+        let buffer = pool.try_allocate(size);
+        if buffer.is_none() && self.muted.insert(connection_id.to_string()) {
+            debug!(
+                "Muting connection {connection_id}, the memory pool is out of memory for a \
+                request of {size} bytes"
+            );
+            if self.muted.len() == 1 {
+                info!("The memory pool is out of memory, muting the connections reading requests");
+            }
+        }
+        buffer
+    }
+
+    /// Whether the processor must not read from any connection, i.e. while the pool is out
+    /// of memory.
+    pub fn should_stop_reading(&self, pool: &MemoryPool) -> bool {
+        pool.is_out_of_memory()
+    }
+
+    pub fn is_muted(&self, connection_id: &str) -> bool {
+        self.muted.contains(connection_id)
+    }
+
+    /// The connections to resume reading from, in order, once memory was returned to the
+    /// pool. Empty while the pool is still out of memory.
+    pub fn unmute_if_memory_available(&mut self, pool: &MemoryPool) -> Vec<String> {
+        if pool.is_out_of_memory() || self.muted.is_empty() {
+            return Vec::new();
+        }
+        info!(
+            "The memory pool has {} bytes available, unmuting {} connections",
+            pool.available_memory(),
+            self.muted.len()
+        );
+        std::mem::take(&mut self.muted).into_iter().collect()
+    }
+
+    /// Forgets a closed connection.
+    pub fn remove(&mut self, connection_id: &str) {
+        self.muted.remove(connection_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocations_are_returned_on_drop() {
+        let pool = MemoryPool::new(100);
+        let first = pool.try_allocate(60).unwrap();
+        assert_eq!(60, first.len());
+        assert_eq!(40, pool.available_memory());
+        // Memory is available, so a larger request is allowed to overshoot.
+        let second = pool.try_allocate(50).unwrap();
+        assert_eq!(-10, pool.available_memory());
+        assert_eq!(110, pool.used_memory());
+        assert!(pool.is_out_of_memory());
+        assert!(pool.try_allocate(1).is_none());
+
+        drop(first);
+        assert_eq!(50, pool.available_memory());
+        drop(second);
+        assert_eq!(100, pool.available_memory());
+        assert_eq!(0, pool.used_memory());
+    }
+
+    #[test]
+    fn test_unbounded_pool() {
+        let pool = MemoryPool::from_config(-1);
+        assert!(!pool.is_bounded());
+        let buffer = pool.try_allocate(1 << 20).unwrap();
+        assert!(!pool.is_out_of_memory());
+        drop(buffer);
+        assert_eq!(0, pool.used_memory());
+        assert!(MemoryPool::from_config(1024).is_bounded());
+    }
+
+    #[test]
+    fn test_channels_are_muted_until_memory_is_returned() {
+        let pool = MemoryPool::new(100);
+        let mut channels = MemoryMutedChannels::new();
+        let request = channels.try_allocate(&pool, "conn-1", 100).unwrap();
+        assert!(channels.should_stop_reading(&pool));
+
+        assert!(channels.try_allocate(&pool, "conn-2", 10).is_none());
+        assert!(channels.try_allocate(&pool, "conn-3", 10).is_none());
+        assert!(channels.try_allocate(&pool, "conn-2", 10).is_none());
+        assert!(channels.is_muted("conn-2"));
+        assert!(!channels.is_muted("conn-1"));
+        channels.remove("conn-3");
+        assert!(channels.unmute_if_memory_available(&pool).is_empty());
+
+        drop(request);
+        assert!(!channels.should_stop_reading(&pool));
+        assert_eq!(
+            vec!["conn-2".to_string()],
+            channels.unmute_if_memory_available(&pool)
+        );
+        assert!(!channels.is_muted("conn-2"));
+        assert!(channels.unmute_if_memory_available(&pool).is_empty());
+    }
+}
@@ -1 +1,12 @@
+pub mod connection_quotas;
+pub mod memory_pool;
+pub mod request_channel;
+pub mod request_context;
+pub mod request_header_check;
+pub mod request_metrics;
+pub mod response_buffers;
+pub mod response_sequencer;
+#[cfg(feature = "rest-bridge")]
+pub mod rest_bridge;
+pub mod sasl_authenticator;
 pub mod socket_server_config;
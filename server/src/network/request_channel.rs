@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// The number of requests the control-plane queue holds.
+pub const CONTROL_PLANE_QUEUED_MAX_REQUESTS: usize = 20;
+
+/// Which plane of the socket server a request was received on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestPlane {
+    /// The requests of the clients and the other brokers.
+    Data,
+    /// The requests received on the controller listeners.
+    Control,
+}
+
+impl RequestPlane {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RequestPlane::Data => "data-plane",
+            RequestPlane::Control => "control-plane",
+        }
+    }
+}
+
+/// The queue of the requests read by the processors of a plane, waiting for a request
+/// handler.
+///
+/// The queue is bounded: a processor reading a request while the queue is full waits for a
+/// handler to take one, which stops it from reading more, so a broker overloaded with
+/// requests pushes back on its clients instead of buffering without limit.
+#[derive(Debug)]
+pub struct RequestChannel<T> {
+    plane: RequestPlane,
+    capacity: usize,
+    queue: Mutex<VecDeque<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> RequestChannel<T> {
+    pub fn new(plane: RequestPlane, capacity: usize) -> Self {
+        Self {
+            plane,
+            capacity: capacity.max(1),
+            queue: Mutex::new(VecDeque::new()),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    fn queue(&self) -> MutexGuard<'_, VecDeque<T>> {
+        self.queue.lock().expect("request queue lock poisoned")
+    }
+
+    pub fn plane(&self) -> RequestPlane {
+        self.plane
+    }
+
+    /// Queues a request, waiting while the queue is full.
+    pub fn send_request(&self, request: T) {
+        let mut queue = self.queue();
+        while queue.len() >= self.capacity {
+            queue = self
+                .not_full
+                .wait(queue)
+                .expect("request queue lock poisoned");
+        }
+        queue.push_back(request);
+        self.not_empty.notify_one();
+    }
+
+    /// Queues a request unless the queue is full, in which case it is returned.
+    pub fn try_send_request(&self, request: T) -> Result<(), T> {
+        let mut queue = self.queue();
+        if queue.len() >= self.capacity {
+            return Err(request);
+        }
+        queue.push_back(request);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Takes the oldest request, waiting up to `timeout` for one.
+    pub fn receive_request(&self, timeout: Duration) -> Option<T> {
+        let (mut queue, _) = self
+            .not_empty
+            .wait_timeout_while(self.queue(), timeout, |queue| queue.is_empty())
+            .expect("request queue lock poisoned");
+        let request = queue.pop_front();
+        if request.is_some() {
+            self.not_full.notify_one();
+        }
+        request
+    }
+
+    /// The number of queued requests.
+    pub fn size(&self) -> usize {
+        self.queue().len()
+    }
+}
+
+/// The request queues of a node: the data-plane queue, holding up to `queued.max.requests`
+/// requests, and the control-plane queue of the controller listeners.
+///
+/// The control plane has its own acceptor, processors and queue, served by its own handlers,
+/// so a controller request is never stuck behind a full data-plane queue: on a combined node
+/// under heavy client load, the heartbeats and the quorum requests still get through.
+#[derive(Debug)]
+pub struct RequestChannels<T> {
+    data_plane: RequestChannel<T>,
+    control_plane: RequestChannel<T>,
+}
+
+impl<T> RequestChannels<T> {
+    pub fn new(queued_max_requests: usize) -> Self {
+        Self {
+            data_plane: RequestChannel::new(RequestPlane::Data, queued_max_requests),
+            control_plane: RequestChannel::new(
+                RequestPlane::Control,
+                CONTROL_PLANE_QUEUED_MAX_REQUESTS,
+            ),
+        }
+    }
+
+    /// The queue of the requests received on `plane`.
+    pub fn channel(&self, plane: RequestPlane) -> &RequestChannel<T> {
+        match plane {
+            RequestPlane::Data => &self.data_plane,
+            RequestPlane::Control => &self.control_plane,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_full_data_plane_does_not_block_the_control_plane() {
+        let channels = RequestChannels::new(2);
+        let data_plane = channels.channel(RequestPlane::Data);
+        data_plane.send_request("produce-1");
+        data_plane.send_request("produce-2");
+        assert_eq!(Err("produce-3"), data_plane.try_send_request("produce-3"));
+
+        let control_plane = channels.channel(RequestPlane::Control);
+        control_plane.send_request("broker-heartbeat");
+        assert_eq!(
+            Some("broker-heartbeat"),
+            control_plane.receive_request(Duration::ZERO)
+        );
+        assert_eq!(2, data_plane.size());
+        assert_eq!(
+            Some("produce-1"),
+            data_plane.receive_request(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_send_waits_for_a_handler() {
+        let channel = Arc::new(RequestChannel::new(RequestPlane::Data, 1));
+        channel.send_request(1);
+        let sender = {
+            let channel = channel.clone();
+            thread::spawn(move || channel.send_request(2))
+        };
+        assert_eq!(Some(1), channel.receive_request(Duration::from_secs(5)));
+        sender.join().unwrap();
+        assert_eq!(Some(2), channel.receive_request(Duration::from_secs(5)));
+        assert_eq!(None, channel.receive_request(Duration::from_millis(10)));
+    }
+}
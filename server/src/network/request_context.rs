@@ -0,0 +1,157 @@
+//! Who sent a request and how to tell its log lines apart.
+//!
+//! A broker handles the requests of many clients at once, so the log lines of one request are
+//! interleaved with those of the others. Handling a request within its [RequestContext::span]
+//! attaches its API, correlation id, client id, principal and connection to every line logged
+//! meanwhile, e.g. `request{api=Produce correlation_id=7 client_id="app" principal=User:alice}`,
+//! so the lines of a request, a client or a tenant can be found again.
+
+use rafka_clients::common::protocol::api_keys::ApiKeys;
+use rafka_clients::common::requests::request_header::RequestHeader;
+use tracing::{Span, info_span};
+
+/// The principal of the requests of an unauthenticated connection.
+pub const ANONYMOUS_PRINCIPAL: &str = "User:ANONYMOUS";
+
+/// A request accepted by [check_request](super::request_header_check::check_request), with the
+/// connection it was received on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestContext {
+    pub connection_id: String,
+    pub api: ApiKeys,
+    pub header: RequestHeader,
+    /// The name the connection authenticated as, if it did.
+    pub principal: Option<String>,
+}
+
+impl RequestContext {
+    pub fn new(
+        connection_id: &str,
+        api: ApiKeys,
+        header: RequestHeader,
+        principal: Option<&str>,
+    ) -> Self {
+        Self {
+            connection_id: connection_id.to_string(),
+            api,
+            header,
+            principal: principal.map(str::to_string),
+        }
+    }
+
+    pub fn correlation_id(&self) -> i32 {
+        self.header.correlation_id
+    }
+
+    /// The client id of the request, empty if it has none.
+    pub fn client_id(&self) -> &str {
+        self.header.client_id.as_deref().unwrap_or_default()
+    }
+
+    /// The principal of the request, as `User:<name>`.
+    pub fn principal(&self) -> String {
+        match &self.principal {
+            Some(name) => format!("User:{name}"),
+            None => ANONYMOUS_PRINCIPAL.to_string(),
+        }
+    }
+
+    /// The span to handle the request in.
+    pub fn span(&self) -> Span {
+        info_span!(
+            "request",
+            api = self.api.name(),
+            api_version = self.header.api_version,
+            correlation_id = self.correlation_id(),
+            client_id = self.client_id(),
+            principal = %self.principal(),
+            connection = %self.connection_id,
+        )
+    }
+
+    /// Runs `handle` within the span of the request.
+    pub fn in_span<T>(&self, handle: impl FnOnce() -> T) -> T {
+        self.span().in_scope(handle)
+    }
+
+    /// Writes the header of the response to the request, which echoes its correlation id so
+    /// that the client can match the response with the request.
+    pub fn write_response_header(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.correlation_id().to_be_bytes());
+        if self.api.response_header_version(self.header.api_version) >= 1 {
+            // No tagged fields.
+            buf.push(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing::info;
+
+    /// Collects what a subscriber writes.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn new_context(api: ApiKeys, api_version: i16, principal: Option<&str>) -> RequestContext {
+        let header = RequestHeader {
+            api_key: api.id(),
+            api_version,
+            correlation_id: 7,
+            client_id: Some("app".to_string()),
+        };
+        RequestContext::new("127.0.0.1:9092-127.0.0.1:50000-0", api, header, principal)
+    }
+
+    #[test]
+    fn test_log_lines_carry_the_request_context() {
+        let output = Output::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let context = new_context(ApiKeys::Produce, 9, Some("alice"));
+        tracing::subscriber::with_default(subscriber, || {
+            context.in_span(|| info!("Appending to foo-0"));
+        });
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("api=\"Produce\""), "{output}");
+        assert!(output.contains("correlation_id=7"), "{output}");
+        assert!(output.contains("client_id=\"app\""), "{output}");
+        assert!(output.contains("principal=User:alice"), "{output}");
+        assert!(output.contains("Appending to foo-0"), "{output}");
+
+        assert_eq!(
+            ANONYMOUS_PRINCIPAL,
+            new_context(ApiKeys::Produce, 9, None).principal()
+        );
+    }
+
+    #[test]
+    fn test_response_header_echoes_the_correlation_id() {
+        let mut buf = vec![];
+        new_context(ApiKeys::Produce, 8, None).write_response_header(&mut buf);
+        assert_eq!(7i32.to_be_bytes().to_vec(), buf);
+
+        // Flexible versions have tagged fields in their response header.
+        let mut buf = vec![];
+        new_context(ApiKeys::Produce, 9, None).write_response_header(&mut buf);
+        assert_eq!(vec![0, 0, 0, 7, 0], buf);
+    }
+}
@@ -0,0 +1,221 @@
+//! Decides what to do with a request before it is handed to the request handlers, from its
+//! header alone.
+//!
+//! A broker cannot answer a request for an API or version it does not know, as it does not
+//! know the response schema the client expects. The one exception is ApiVersions: a client
+//! sending a version newer than the broker supports is answered at version 0 with
+//! `UNSUPPORTED_VERSION` and the versions the broker does support, so it can retry at a
+//! version both know (KIP-511). For any other request, the client was expected to check the
+//! versions with ApiVersions first, so like the Java broker the connection is closed, but with
+//! the reason logged rather than dropped silently.
+//!
+//! A version the broker supports but does not enable, see [EnabledVersions], is treated like
+//! an unsupported one: it was not advertised, so no client should send it.
+
+use rafka_clients::common::protocol::api_keys::ApiKeys;
+use rafka_clients::common::protocol::api_version_matrix::EnabledVersions;
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::requests::api_versions_response::ApiVersionsResponse;
+use rafka_clients::common::requests::request_header::RequestHeader;
+use rafka_clients::common::requests::response_writer::WritableResponse;
+use tracing::info;
+
+/// The size prefix of a request and a response.
+const SIZE_PREFIX: usize = 4;
+
+/// What to do with a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderCheck {
+    /// The request is for an API and version the broker enables. The body starts at
+    /// `body_offset` in the request, after its size and header.
+    Accept {
+        api: ApiKeys,
+        header: RequestHeader,
+        body_offset: usize,
+    },
+    /// Send this response, size included, instead of handling the request.
+    Respond(Vec<u8>),
+    /// Close the connection, for the given reason.
+    Close(String),
+}
+
+/// Checks the header of `request`, a complete frame starting with its size, against the
+/// `enabled` versions.
+pub fn check_request(
+    connection_id: &str,
+    request: &[u8],
+    enabled: &EnabledVersions,
+) -> HeaderCheck {
+    let check = check(request, enabled);
+    if let HeaderCheck::Close(reason) = &check {
+        info!("Closing connection {connection_id}: {reason}");
+    }
+    check
+}
+
+fn check(request: &[u8], enabled: &EnabledVersions) -> HeaderCheck {
+    let Some(size) = request.get(..SIZE_PREFIX) else {
+        return HeaderCheck::Close("The request has no size".to_string());
+    };
+    let size = i32::from_be_bytes([size[0], size[1], size[2], size[3]]);
+    let payload = &request[SIZE_PREFIX..];
+    if size < 0 || size as usize != payload.len() {
+        return HeaderCheck::Close(format!(
+            "The request has size {size}, but {} bytes",
+            payload.len()
+        ));
+    }
+    let (api_key, api_version, correlation_id) = match RequestHeader::parse_prefix(payload) {
+        Ok(prefix) => prefix,
+        Err(e) => return HeaderCheck::Close(e.to_string()),
+    };
+    let Some(api) = ApiKeys::for_id(api_key) else {
+        return HeaderCheck::Close(format!(
+            "Unknown API key {api_key} in request with correlation id {correlation_id}"
+        ));
+    };
+    if !enabled.is_version_enabled(api, api_version) {
+        if api == ApiKeys::ApiVersions && api_version > api.latest_version() {
+            return HeaderCheck::Respond(unsupported_api_versions_response(
+                correlation_id,
+                enabled,
+            ));
+        }
+        let enabled_versions = match enabled.latest_enabled_version(api) {
+            Some(latest) => format!("versions {} to {latest}", api.oldest_version()),
+            None => "no version".to_string(),
+        };
+        return HeaderCheck::Close(format!(
+            "Unsupported version {api_version} of API {} in request with correlation id \
+            {correlation_id}, the broker enables {enabled_versions}",
+            api.name()
+        ));
+    }
+    match RequestHeader::parse(api, payload) {
+        Ok((header, header_size)) => HeaderCheck::Accept {
+            api,
+            header,
+            body_offset: SIZE_PREFIX + header_size,
+        },
+        Err(e) => HeaderCheck::Close(format!(
+            "Invalid header of {} request with correlation id {correlation_id}: {e}",
+            api.name()
+        )),
+    }
+}
+
+fn unsupported_api_versions_response(correlation_id: i32, enabled: &EnabledVersions) -> Vec<u8> {
+    let mut response = vec![0; SIZE_PREFIX];
+    ApiVersionsResponse::enabled_api_versions(Errors::UnsupportedVersion, enabled).write_to(
+        correlation_id,
+        0,
+        &mut response,
+    );
+    let size = (response.len() - SIZE_PREFIX) as i32;
+    response[..SIZE_PREFIX].copy_from_slice(&size.to_be_bytes());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A raw request frame, as a client would write it to the socket.
+    fn frame(api_key: i16, api_version: i16, correlation_id: i32, rest: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&api_key.to_be_bytes());
+        payload.extend_from_slice(&api_version.to_be_bytes());
+        payload.extend_from_slice(&correlation_id.to_be_bytes());
+        payload.extend_from_slice(rest);
+        let mut frame = (payload.len() as i32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&payload);
+        frame
+    }
+
+    /// Checks `request` against the versions a broker enables without unstable versions or
+    /// finalized features.
+    fn check_stable(request: &[u8]) -> HeaderCheck {
+        check_request("conn", request, &EnabledVersions::new(false))
+    }
+
+    fn is_close(check: &HeaderCheck) -> bool {
+        matches!(check, HeaderCheck::Close(_))
+    }
+
+    #[test]
+    fn test_accept_supported_request() {
+        // Metadata v12 has a flexible header: client id "c", then no tagged fields.
+        let request = frame(3, 12, 9, &[0, 1, b'c', 0, b'x']);
+        let HeaderCheck::Accept {
+            api,
+            header,
+            body_offset,
+        } = check_stable(&request)
+        else {
+            panic!("expected the request to be accepted");
+        };
+        assert_eq!(ApiKeys::Metadata, api);
+        assert_eq!(9, header.correlation_id);
+        assert_eq!(Some("c".to_string()), header.client_id);
+        assert_eq!(b"x", &request[body_offset..]);
+    }
+
+    #[test]
+    fn test_unsupported_api_versions_version_is_answered() {
+        let request = frame(18, 99, 7, &[0, 0x20, 0]);
+        let HeaderCheck::Respond(response) = check_stable(&request) else {
+            panic!("expected an ApiVersions response");
+        };
+        let size = i32::from_be_bytes(response[0..4].try_into().unwrap());
+        assert_eq!(response.len() - 4, size as usize);
+        assert_eq!(7, i32::from_be_bytes(response[4..8].try_into().unwrap()));
+        assert_eq!(
+            Errors::UnsupportedVersion.code(),
+            i16::from_be_bytes(response[8..10].try_into().unwrap())
+        );
+        // Only the enabled APIs and versions are listed.
+        let count = i32::from_be_bytes(response[10..14].try_into().unwrap());
+        assert_eq!(ApiKeys::ALL.len() - 1, count as usize);
+        // Version 0 has no throttle time, so the response ends with the last API.
+        assert_eq!(14 + 6 * count as usize, response.len());
+        let first = &response[14..20];
+        assert_eq!([0, 0, 0, 3, 0, 11], first);
+    }
+
+    #[test]
+    fn test_close_disabled_version() {
+        // Produce v12 is unstable. Its header is flexible: no client id, no tagged fields.
+        let request = frame(0, 12, 1, &[0xFF, 0xFF, 0]);
+        assert!(is_close(&check_stable(&request)));
+        assert!(matches!(
+            check_request("conn", &request, &EnabledVersions::new(true)),
+            HeaderCheck::Accept {
+                api: ApiKeys::Produce,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_close_unknown_or_unsupported() {
+        // An unknown API key.
+        assert!(is_close(&check_stable(&frame(999, 0, 1, &[0xFF, 0xFF]))));
+        // A Produce version older than supported, as sent by a client predating ApiVersions.
+        assert!(is_close(&check_stable(&frame(0, 0, 1, &[0xFF, 0xFF]))));
+        // A Fetch version newer than supported.
+        assert!(is_close(&check_stable(&frame(1, 99, 1, &[0xFF, 0xFF]))));
+    }
+
+    #[test]
+    fn test_close_malformed_frames() {
+        assert!(is_close(&check_stable(&[0, 0])));
+        // A size which does not match the payload.
+        let mut request = frame(3, 12, 1, &[0xFF, 0xFF, 0]);
+        request.pop();
+        assert!(is_close(&check_stable(&request)));
+        // Too short for a header.
+        assert!(is_close(&check_stable(&[0, 0, 0, 2, 0, 3])));
+        // A client id longer than the request.
+        assert!(is_close(&check_stable(&frame(3, 8, 1, &[0, 9, b'c']))));
+    }
+}
@@ -0,0 +1,275 @@
+use indexmap::IndexMap;
+use rafka_clients::common::metrics::stats::{Avg, Max, Rate};
+use rafka_clients::common::metrics::{Metrics, Sensor};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::debug;
+
+pub const REQUEST_METRICS_GROUP: &str = "request-metrics";
+
+pub const REQUEST_TAG: &str = "request";
+pub const CLIENT_ID_TAG: &str = "client-id";
+pub const PRINCIPAL_TAG: &str = "principal";
+
+pub const REQUEST_RATE: &str = "request-rate";
+pub const REQUEST_TIME_AVG: &str = "request-time-avg";
+pub const REQUEST_TIME_MAX: &str = "request-time-max";
+pub const REQUEST_BYTES_RATE: &str = "request-bytes-rate";
+
+pub const DEFAULT_MAX_TAGGED_CLIENTS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestMetricsConfig {
+    /// Whether requests are also tracked per client id and principal, on top of per API.
+    pub client_tagging_enabled: bool,
+    /// The number of client id and principal pairs tracked at most per API, beyond which the
+    /// least recently seen pair is dropped.
+    pub max_tagged_clients: usize,
+}
+
+impl Default for RequestMetricsConfig {
+    fn default() -> Self {
+        Self {
+            client_tagging_enabled: false,
+            max_tagged_clients: DEFAULT_MAX_TAGGED_CLIENTS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ClientTags {
+    api: String,
+    client_id: String,
+    principal: String,
+}
+
+/// The sensors tracking a request kind, for all clients or for a single client.
+struct RequestSensors {
+    requests: Arc<Sensor>,
+    bytes: Arc<Sensor>,
+}
+
+/// The rate, size and time of the requests a broker handles, per API.
+///
+/// Operators of a shared cluster may opt in to tracking them per client id and principal too,
+/// to attribute the load to applications. As every pair of tags makes new metrics, the number
+/// of pairs tracked per API is capped: the metrics of the pair least recently seen are removed
+/// to make room for a new one.
+pub struct RequestMetrics {
+    metrics: Metrics,
+    config: RequestMetricsConfig,
+    tagged_clients: Mutex<IndexMap<ClientTags, RequestSensors>>,
+}
+
+impl RequestMetrics {
+    pub fn new(metrics: Metrics, config: RequestMetricsConfig) -> Self {
+        Self {
+            metrics,
+            config,
+            tagged_clients: Mutex::new(IndexMap::new()),
+        }
+    }
+
+    fn tagged_clients(&self) -> MutexGuard<'_, IndexMap<ClientTags, RequestSensors>> {
+        self.tagged_clients
+            .lock()
+            .expect("request metrics lock poisoned")
+    }
+
+    /// Records a request to `api` of `size_bytes` bytes which took `time_ms` to handle.
+    pub fn record(
+        &self,
+        api: &str,
+        client_id: &str,
+        principal: &str,
+        size_bytes: usize,
+        time_ms: f64,
+    ) {
+        let sensors = self.sensors(&format!("request:{api}"), &[(REQUEST_TAG, api)]);
+        Self::record_into(&sensors, size_bytes, time_ms);
+        if !self.config.client_tagging_enabled {
+            return;
+        }
+
+        let tags = ClientTags {
+            api: api.to_string(),
+            client_id: client_id.to_string(),
+            principal: principal.to_string(),
+        };
+        let mut tagged_clients = self.tagged_clients();
+        if let Some(index) = tagged_clients.get_index_of(&tags) {
+            // Most recently seen pairs move to the back, the front is evicted first.
+            let last = tagged_clients.len() - 1;
+            tagged_clients.move_index(index, last);
+        } else {
+            let tracked = tagged_clients.keys().filter(|t| t.api == api).count();
+            if tracked >= self.config.max_tagged_clients
+                && let Some(evicted) = tagged_clients.keys().find(|t| t.api == api).cloned()
+            {
+                debug!(
+                    "Dropping the {api} request metrics of client {} and principal {}",
+                    evicted.client_id, evicted.principal
+                );
+                tagged_clients.shift_remove(&evicted);
+                let name = Self::client_sensors_name(&evicted);
+                self.metrics.remove_sensor(&format!("{name}:requests"));
+                self.metrics.remove_sensor(&format!("{name}:bytes"));
+            }
+            let sensors = self.sensors(
+                &Self::client_sensors_name(&tags),
+                &[
+                    (REQUEST_TAG, api),
+                    (CLIENT_ID_TAG, client_id),
+                    (PRINCIPAL_TAG, principal),
+                ],
+            );
+            tagged_clients.insert(tags.clone(), sensors);
+        }
+        if let Some(sensors) = tagged_clients.get(&tags) {
+            Self::record_into(sensors, size_bytes, time_ms);
+        }
+    }
+
+    /// The client id and principal pairs tracked for `api`, least recently seen first.
+    pub fn tagged_clients_of(&self, api: &str) -> Vec<(String, String)> {
+        self.tagged_clients()
+            .keys()
+            .filter(|tags| tags.api == api)
+            .map(|tags| (tags.client_id.clone(), tags.principal.clone()))
+            .collect()
+    }
+
+    fn record_into(sensors: &RequestSensors, size_bytes: usize, time_ms: f64) {
+        sensors.requests.record(time_ms);
+        sensors.bytes.record(size_bytes as f64);
+    }
+
+    fn client_sensors_name(tags: &ClientTags) -> String {
+        format!("request:{}:{}:{}", tags.api, tags.client_id, tags.principal)
+    }
+
+    fn sensors(&self, name: &str, tags: &[(&str, &str)]) -> RequestSensors {
+        RequestSensors {
+            requests: self.requests_sensor(&format!("{name}:requests"), tags),
+            bytes: self.bytes_sensor(&format!("{name}:bytes"), tags),
+        }
+    }
+
+    fn requests_sensor(&self, name: &str, tags: &[(&str, &str)]) -> Arc<Sensor> {
+        if let Some(sensor) = self.metrics.get_sensor(name) {
+            return sensor;
+        }
+        let sensor = self.metrics.sensor(name);
+        sensor.add(
+            self.metrics.metric_name(
+                REQUEST_RATE,
+                REQUEST_METRICS_GROUP,
+                "The number of requests per second",
+                tags,
+            ),
+            Rate::occurrences(),
+        );
+        sensor.add(
+            self.metrics.metric_name(
+                REQUEST_TIME_AVG,
+                REQUEST_METRICS_GROUP,
+                "The average time in ms to handle a request",
+                tags,
+            ),
+            Avg::new(),
+        );
+        sensor.add(
+            self.metrics.metric_name(
+                REQUEST_TIME_MAX,
+                REQUEST_METRICS_GROUP,
+                "The maximum time in ms to handle a request",
+                tags,
+            ),
+            Max::new(),
+        );
+        sensor
+    }
+
+    fn bytes_sensor(&self, name: &str, tags: &[(&str, &str)]) -> Arc<Sensor> {
+        if let Some(sensor) = self.metrics.get_sensor(name) {
+            return sensor;
+        }
+        let sensor = self.metrics.sensor(name);
+        sensor.add(
+            self.metrics.metric_name(
+                REQUEST_BYTES_RATE,
+                REQUEST_METRICS_GROUP,
+                "The number of request bytes received per second",
+                tags,
+            ),
+            Rate::new(),
+        );
+        sensor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric(metrics: &Metrics, name: &str, tags: &[(&str, &str)]) -> Option<f64> {
+        metrics.metric_value(&metrics.metric_name(name, REQUEST_METRICS_GROUP, "", tags))
+    }
+
+    #[test]
+    fn test_client_tagging_is_opt_in() {
+        let metrics = Metrics::default();
+        let request_metrics = RequestMetrics::new(metrics.clone(), RequestMetricsConfig::default());
+        request_metrics.record("Produce", "app", "User:alice", 100, 5.0);
+        assert_eq!(
+            Some(5.0),
+            metric(&metrics, REQUEST_TIME_MAX, &[(REQUEST_TAG, "Produce")])
+        );
+        assert!(request_metrics.tagged_clients_of("Produce").is_empty());
+        let client_tags = [
+            (REQUEST_TAG, "Produce"),
+            (CLIENT_ID_TAG, "app"),
+            (PRINCIPAL_TAG, "User:alice"),
+        ];
+        assert_eq!(None, metric(&metrics, REQUEST_TIME_MAX, &client_tags));
+    }
+
+    #[test]
+    fn test_least_recently_seen_clients_are_evicted() {
+        let metrics = Metrics::default();
+        let request_metrics = RequestMetrics::new(
+            metrics.clone(),
+            RequestMetricsConfig {
+                client_tagging_enabled: true,
+                max_tagged_clients: 2,
+            },
+        );
+        let tags = |client_id| {
+            [
+                (REQUEST_TAG, "Fetch"),
+                (CLIENT_ID_TAG, client_id),
+                (PRINCIPAL_TAG, "User:bob"),
+            ]
+        };
+        request_metrics.record("Fetch", "a", "User:bob", 10, 1.0);
+        request_metrics.record("Fetch", "b", "User:bob", 10, 2.0);
+        request_metrics.record("Fetch", "a", "User:bob", 10, 3.0);
+        // Another API does not count towards the limit of Fetch.
+        request_metrics.record("Produce", "c", "User:bob", 10, 1.0);
+        request_metrics.record("Fetch", "c", "User:bob", 10, 4.0);
+
+        assert_eq!(
+            vec![
+                ("a".to_string(), "User:bob".to_string()),
+                ("c".to_string(), "User:bob".to_string())
+            ],
+            request_metrics.tagged_clients_of("Fetch")
+        );
+        assert_eq!(Some(3.0), metric(&metrics, REQUEST_TIME_MAX, &tags("a")));
+        assert_eq!(None, metric(&metrics, REQUEST_TIME_MAX, &tags("b")));
+        assert_eq!(None, metric(&metrics, REQUEST_BYTES_RATE, &tags("b")));
+        assert_eq!(
+            Some(4.0),
+            metric(&metrics, REQUEST_TIME_MAX, &[(REQUEST_TAG, "Fetch")])
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use rafka_clients::common::requests::response_writer::WritableResponse;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The size prefix of a response frame.
+const SIZE_PREFIX: usize = 4;
+
+/// The default number of buffers a [ResponseBufferPool] keeps.
+pub const DEFAULT_MAX_POOLED_BUFFERS: usize = 64;
+
+/// The default capacity above which a buffer is not kept: 1 MiB.
+pub const DEFAULT_MAX_POOLED_CAPACITY: usize = 1024 * 1024;
+
+/// The buffers the request handlers serialize responses into, reused from one response to
+/// the next.
+///
+/// A response is serialized into a pooled buffer, which goes back to the pool once the
+/// response was sent. A handler answering the metadata of thousands of topics over and over
+/// then reuses the same allocation, grown by the first response, instead of allocating it and
+/// growing it for every response, and without sizing the response first. Only a response
+/// cheap to size, e.g. a fetch response, reserves its size up front, see
+/// [WritableResponse::size_hint].
+///
+/// The pool keeps up to `max_pooled_buffers` buffers of at most `max_pooled_capacity` bytes:
+/// the buffer of an unusually large response, e.g. a fetch of many partitions, is freed
+/// rather than pinning its memory for the small responses which follow.
+#[derive(Debug, Clone)]
+pub struct ResponseBufferPool {
+    buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+    max_pooled_buffers: usize,
+    max_pooled_capacity: usize,
+}
+
+impl Default for ResponseBufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_POOLED_BUFFERS, DEFAULT_MAX_POOLED_CAPACITY)
+    }
+}
+
+impl ResponseBufferPool {
+    pub fn new(max_pooled_buffers: usize, max_pooled_capacity: usize) -> Self {
+        Self {
+            buffers: Arc::new(Mutex::new(Vec::new())),
+            max_pooled_buffers,
+            max_pooled_capacity,
+        }
+    }
+
+    /// An empty buffer of at least `size` bytes of capacity, the largest pooled one if any.
+    pub fn acquire(&self, size: usize) -> ResponseBuffer {
+        let mut buf = self.lock().pop().unwrap_or_default();
+        buf.reserve_exact(size);
+        ResponseBuffer {
+            buf,
+            pool: self.clone(),
+        }
+    }
+
+    /// Frames `response`, written at `version`, behind its size prefix.
+    pub fn frame(
+        &self,
+        response: &impl WritableResponse,
+        correlation_id: i32,
+        version: i16,
+    ) -> ResponseBuffer {
+        let mut buffer = self.acquire(SIZE_PREFIX + response.size_hint(version).unwrap_or(0));
+        buffer.extend_from_slice(&[0; SIZE_PREFIX]);
+        response.write_into(correlation_id, version, &mut *buffer);
+        let size = (buffer.len() - SIZE_PREFIX) as i32;
+        buffer[..SIZE_PREFIX].copy_from_slice(&size.to_be_bytes());
+        buffer
+    }
+
+    /// The number of buffers waiting to be reused.
+    pub fn pooled(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn release(&self, mut buf: Vec<u8>) {
+        if buf.capacity() > self.max_pooled_capacity {
+            return;
+        }
+        buf.clear();
+        let mut buffers = self.lock();
+        if buffers.len() < self.max_pooled_buffers {
+            // Kept sorted by capacity, so that the largest is reused first.
+            let index = buffers.partition_point(|b| b.capacity() <= buf.capacity());
+            buffers.insert(index, buf);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Vec<u8>>> {
+        self.buffers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A buffer acquired from a [ResponseBufferPool], returned to it on drop.
+#[derive(Debug)]
+pub struct ResponseBuffer {
+    buf: Vec<u8>,
+    pool: ResponseBufferPool,
+}
+
+impl Deref for ResponseBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for ResponseBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl Drop for ResponseBuffer {
+    fn drop(&mut self) {
+        self.pool.release(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::protocol::errors::Errors;
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+    use rafka_clients::common::requests::api_versions_response::ApiVersionsResponse;
+    use rafka_clients::common::requests::fetch_response::{
+        FetchResponse, FetchResponsePartition, FetchResponseTopic, INVALID_SESSION_ID,
+        NO_PREFERRED_READ_REPLICA,
+    };
+    use rafka_clients::common::uuid::Uuid;
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = ResponseBufferPool::new(2, 1024);
+        let response = ApiVersionsResponse::default_api_versions(Errors::None);
+        let size = response.size_in_bytes(3);
+        let buffer = pool.frame(&response, 1, 3);
+        assert_eq!(SIZE_PREFIX + size, buffer.len());
+        assert_eq!(
+            size as i32,
+            i32::from_be_bytes(buffer[..4].try_into().unwrap())
+        );
+        let address = buffer.as_ptr();
+        drop(buffer);
+        assert_eq!(1, pool.pooled());
+
+        // The same allocation, emptied.
+        let buffer = pool.acquire(size);
+        assert!(buffer.is_empty());
+        assert_eq!(address, buffer.as_ptr());
+        assert_eq!(0, pool.pooled());
+        drop(buffer);
+
+        // Buffers too large or too many are freed.
+        drop(pool.acquire(2048));
+        assert_eq!(0, pool.pooled());
+        let buffers: Vec<_> = (0..3).map(|_| pool.acquire(16)).collect();
+        drop(buffers);
+        assert_eq!(2, pool.pooled());
+    }
+
+    #[test]
+    fn test_fetch_responses_are_sized_up_front() {
+        let pool = ResponseBufferPool::default();
+        let batch = RecordBatch::new(0, vec![Record::new(0, None, Some(&[0; 1000]))]);
+        let response = FetchResponse {
+            throttle_time_ms: 0,
+            error: Errors::None,
+            session_id: INVALID_SESSION_ID,
+            responses: vec![FetchResponseTopic {
+                topic: "foo".to_string(),
+                topic_id: Uuid::new(0, 1),
+                partitions: vec![FetchResponsePartition {
+                    partition_index: 0,
+                    error: Errors::None,
+                    high_watermark: 1,
+                    last_stable_offset: 1,
+                    log_start_offset: 0,
+                    aborted_transactions: None,
+                    preferred_read_replica: NO_PREFERRED_READ_REPLICA,
+                    records: vec![batch],
+                }],
+            }],
+        };
+        let buffer = pool.frame(&response, 1, 16);
+        assert_eq!(SIZE_PREFIX + response.size_in_bytes(16), buffer.len());
+        // Allocated once, to the size of the response.
+        assert_eq!(buffer.len(), buffer.capacity());
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ResponseSequenceError {
+    #[error("No request {0} is waiting for a response on this connection")]
+    UnknownRequest(u64),
+
+    #[error("The response of request {0} was already completed")]
+    AlreadyCompleted(u64),
+}
+
+/// Sends the responses of a connection in the order of its requests.
+///
+/// A client matches the responses to its requests by correlation id, but expects them in the
+/// order it sent the requests, as the Java broker guarantees. Requests completing right away
+/// and requests parked in a purgatory, e.g. an `acks=all` produce followed by a metadata
+/// request, complete out of order: the sequencer holds a completed response back until the
+/// responses of all the earlier requests of the connection are completed.
+///
+/// The processor of the connection numbers each request it reads with
+/// [on_request](Self::on_request) and completes it with [complete](Self::complete), which
+/// returns the responses to send now.
+#[derive(Debug)]
+pub struct ResponseSequencer<R> {
+    /// The sequence number of the next request read from the connection.
+    next_request: u64,
+    /// The sequence number of the request whose response is sent next.
+    next_response: u64,
+    /// The completed requests waiting for earlier requests, and their responses, if any.
+    completed: BTreeMap<u64, Option<R>>,
+}
+
+impl<R> Default for ResponseSequencer<R> {
+    fn default() -> Self {
+        Self {
+            next_request: 0,
+            next_response: 0,
+            completed: BTreeMap::new(),
+        }
+    }
+}
+
+impl<R> ResponseSequencer<R> {
+    /// Registers a request read from the connection, returning the sequence number to
+    /// complete it with.
+    pub fn on_request(&mut self) -> u64 {
+        let sequence = self.next_request;
+        self.next_request += 1;
+        sequence
+    }
+
+    /// Completes request `sequence` with `response`, returning the responses which can be
+    /// sent now, in request order.
+    pub fn complete(
+        &mut self,
+        sequence: u64,
+        response: R,
+    ) -> Result<Vec<R>, ResponseSequenceError> {
+        self.complete_with(sequence, Some(response))
+    }
+
+    /// Completes a request which gets no response, e.g. a produce with `acks=0`, so that it
+    /// no longer holds back the responses of the later requests.
+    pub fn complete_without_response(
+        &mut self,
+        sequence: u64,
+    ) -> Result<Vec<R>, ResponseSequenceError> {
+        self.complete_with(sequence, None)
+    }
+
+    fn complete_with(
+        &mut self,
+        sequence: u64,
+        response: Option<R>,
+    ) -> Result<Vec<R>, ResponseSequenceError> {
+        if sequence >= self.next_request {
+            return Err(ResponseSequenceError::UnknownRequest(sequence));
+        }
+        if sequence < self.next_response || self.completed.contains_key(&sequence) {
+            return Err(ResponseSequenceError::AlreadyCompleted(sequence));
+        }
+        self.completed.insert(sequence, response);
+
+        let mut ready = vec![];
+        while let Some(response) = self.completed.remove(&self.next_response) {
+            self.next_response += 1;
+            ready.extend(response);
+        }
+        Ok(ready)
+    }
+
+    /// The number of requests whose response wasn't sent yet.
+    pub fn in_flight(&self) -> usize {
+        (self.next_request - self.next_response) as usize
+    }
+
+    /// The number of completed responses held back by earlier requests.
+    pub fn held_back(&self) -> usize {
+        self.completed.values().filter(|r| r.is_some()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_responses_completed_out_of_order_are_sent_in_order() {
+        let mut sequencer = ResponseSequencer::default();
+        let produce = sequencer.on_request();
+        let metadata = sequencer.on_request();
+        let fetch = sequencer.on_request();
+        assert_eq!(3, sequencer.in_flight());
+
+        // The metadata response waits for the produce parked in the purgatory.
+        assert!(sequencer.complete(metadata, "metadata").unwrap().is_empty());
+        assert_eq!(1, sequencer.held_back());
+        assert_eq!(
+            vec!["produce", "metadata"],
+            sequencer.complete(produce, "produce").unwrap()
+        );
+        assert_eq!(vec!["fetch"], sequencer.complete(fetch, "fetch").unwrap());
+        assert_eq!(0, sequencer.in_flight());
+        assert_eq!(0, sequencer.held_back());
+    }
+
+    #[test]
+    fn test_requests_without_response() {
+        let mut sequencer = ResponseSequencer::default();
+        let produce = sequencer.on_request();
+        let metadata = sequencer.on_request();
+        assert!(sequencer.complete(metadata, "metadata").unwrap().is_empty());
+        assert_eq!(
+            vec!["metadata"],
+            sequencer.complete_without_response(produce).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_completions() {
+        let mut sequencer = ResponseSequencer::default();
+        assert_eq!(
+            Err(ResponseSequenceError::UnknownRequest(0)),
+            sequencer.complete(0, "response")
+        );
+        let first = sequencer.on_request();
+        let second = sequencer.on_request();
+        sequencer.complete(second, "second").unwrap();
+        assert_eq!(
+            Err(ResponseSequenceError::AlreadyCompleted(second)),
+            sequencer.complete(second, "second")
+        );
+        sequencer.complete(first, "first").unwrap();
+        assert_eq!(
+            Err(ResponseSequenceError::AlreadyCompleted(first)),
+            sequencer.complete(first, "first")
+        );
+    }
+}
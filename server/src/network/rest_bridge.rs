@@ -0,0 +1,498 @@
+//! An HTTP bridge which produces and consumes records with JSON requests, a subset of the
+//! Confluent REST proxy API, for applications which have no native client yet:
+//!
+//! - `GET /topics` lists the topics;
+//! - `POST /topics/{topic}/partitions/{partition}` appends
+//!   `{"records": [{"key": "k", "value": "v"}]}` and replies with the offsets of the records;
+//! - `GET /topics/{topic}/partitions/{partition}/records?offset=0&max_bytes=1048576` reads
+//!   the records from an offset on.
+//!
+//! Keys and values are UTF-8 text. Errors are replied as
+//! `{"error_code": 40401, "message": "..."}`, the error code being the HTTP status followed by
+//! two digits.
+
+use crate::record_validator::{RecordValidationError, RecordValidators};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_clients::common::utils::time::Time;
+use rafka_storage::{AppendOrigin, LogError, PartitionLog};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tracing::debug;
+
+/// The default of the `max_bytes` a consume request reads.
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum RestBridgeError {
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Topic {0} not found")]
+    UnknownTopic(String),
+
+    #[error("Partition {0} not found")]
+    UnknownPartition(TopicPartition),
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error(transparent)]
+    Validation(#[from] RecordValidationError),
+
+    #[error(transparent)]
+    Log(#[from] LogError),
+}
+
+impl RestBridgeError {
+    fn status(&self) -> StatusCode {
+        match self {
+            RestBridgeError::NotFound(_)
+            | RestBridgeError::UnknownTopic(_)
+            | RestBridgeError::UnknownPartition(_) => StatusCode::NOT_FOUND,
+            RestBridgeError::InvalidRequest(_) | RestBridgeError::Validation(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            RestBridgeError::Log(LogError::OffsetOutOfRange { .. }) => {
+                StatusCode::RANGE_NOT_SATISFIABLE
+            }
+            RestBridgeError::Log(LogError::InvalidRecord(_) | LogError::Record(_)) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            RestBridgeError::Log(LogError::RecordTooLarge { .. }) => StatusCode::PAYLOAD_TOO_LARGE,
+            RestBridgeError::Log(LogError::LogDirWriteProtected { .. }) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            RestBridgeError::Log(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The status followed by two digits telling the errors with the same status apart.
+    fn error_code(&self) -> u32 {
+        let detail = match self {
+            RestBridgeError::NotFound(_) => 0,
+            RestBridgeError::UnknownTopic(_) => 1,
+            RestBridgeError::UnknownPartition(_) | RestBridgeError::Validation(_) => 2,
+            _ => 1,
+        };
+        self.status().as_u16() as u32 * 100 + detail
+    }
+}
+
+type Result<T> = std::result::Result<T, RestBridgeError>;
+
+#[derive(Debug, Deserialize)]
+struct ProduceRequest {
+    records: Vec<ProduceRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProduceRecord {
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    value: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ConsumedRecord {
+    topic: String,
+    partition: i32,
+    offset: i64,
+    timestamp: i64,
+    key: Option<String>,
+    value: Option<String>,
+}
+
+/// The log of a partition the bridge serves, and the leader epoch it appends in.
+struct BridgedPartition {
+    log: Box<dyn PartitionLog + Send>,
+    leader_epoch: i32,
+}
+
+/// Serves the partitions it was given over HTTP.
+pub struct RestBridge {
+    time: Arc<dyn Time>,
+    partitions: Mutex<BTreeMap<TopicPartition, BridgedPartition>>,
+    validators: RecordValidators,
+}
+
+impl RestBridge {
+    pub fn new(time: Arc<dyn Time>) -> Self {
+        Self {
+            time,
+            partitions: Mutex::new(BTreeMap::new()),
+            validators: RecordValidators::new(),
+        }
+    }
+
+    /// Sets the validators the produced records must pass.
+    pub fn set_record_validators(mut self, validators: RecordValidators) -> Self {
+        self.validators = validators;
+        self
+    }
+
+    fn partitions(&self) -> MutexGuard<'_, BTreeMap<TopicPartition, BridgedPartition>> {
+        self.partitions.lock().expect("partitions lock poisoned")
+    }
+
+    /// Serves the log of a partition this broker leads in `leader_epoch`.
+    pub fn add_partition(&self, log: Box<dyn PartitionLog + Send>, leader_epoch: i32) {
+        let topic_partition = log.topic_partition().clone();
+        self.partitions()
+            .insert(topic_partition, BridgedPartition { log, leader_epoch });
+    }
+
+    pub fn remove_partition(&self, topic_partition: &TopicPartition) -> bool {
+        self.partitions().remove(topic_partition).is_some()
+    }
+
+    /// Accepts connections on `listener` until it fails.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let bridge = Arc::clone(&self);
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let bridge = Arc::clone(&bridge);
+                    async move { bridge.handle_request(request).await }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    debug!("Closed the REST connection of {peer}: {e}");
+                }
+            });
+        }
+    }
+
+    async fn handle_request(
+        &self,
+        request: Request<Incoming>,
+    ) -> std::result::Result<Response<Full<Bytes>>, Infallible> {
+        let (parts, body) = request.into_parts();
+        let (status, json) = match body.collect().await {
+            Ok(body) => self.handle(
+                &parts.method,
+                parts.uri.path(),
+                parts.uri.query(),
+                &body.to_bytes(),
+            ),
+            Err(e) => error_response(&RestBridgeError::InvalidRequest(e.to_string())),
+        };
+        Ok(Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(json.to_string())))
+            .expect("a response with a valid status and header"))
+    }
+
+    /// Routes a request to its handler, returning the status and the JSON body of the
+    /// response.
+    pub fn handle(
+        &self,
+        method: &Method,
+        path: &str,
+        query: Option<&str>,
+        body: &[u8],
+    ) -> (StatusCode, Value) {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let result = match (method, segments.as_slice()) {
+            (&Method::GET, ["topics"]) => Ok((StatusCode::OK, self.topics())),
+            (&Method::POST, ["topics", topic, "partitions", partition]) => {
+                topic_partition(topic, partition)
+                    .and_then(|tp| self.produce(&tp, body))
+                    .map(|json| (StatusCode::OK, json))
+            }
+            (&Method::GET, ["topics", topic, "partitions", partition, "records"]) => {
+                topic_partition(topic, partition)
+                    .and_then(|tp| self.consume(&tp, query))
+                    .map(|json| (StatusCode::OK, json))
+            }
+            _ => Err(RestBridgeError::NotFound(format!("{method} {path}"))),
+        };
+        result.unwrap_or_else(|e| error_response(&e))
+    }
+
+    fn topics(&self) -> Value {
+        let partitions = self.partitions();
+        let topics: BTreeSet<&str> = partitions.keys().map(|tp| tp.topic()).collect();
+        json!(topics)
+    }
+
+    fn produce(&self, topic_partition: &TopicPartition, body: &[u8]) -> Result<Value> {
+        let request: ProduceRequest = serde_json::from_slice(body)
+            .map_err(|e| RestBridgeError::InvalidRequest(e.to_string()))?;
+        if request.records.is_empty() {
+            return Err(RestBridgeError::InvalidRequest(
+                "records must not be empty".to_string(),
+            ));
+        }
+        let now_ms = self.time.milliseconds();
+        let records = request
+            .records
+            .iter()
+            .map(|r| {
+                Record::new(
+                    now_ms,
+                    r.key.as_deref().map(str::as_bytes),
+                    r.value.as_deref().map(str::as_bytes),
+                )
+            })
+            .collect();
+        let mut batch = RecordBatch::new(0, records);
+
+        let mut partitions = self.partitions();
+        let partition = lookup(&mut partitions, topic_partition)?;
+        self.validators.validate(topic_partition, &mut batch)?;
+        let info =
+            partition
+                .log
+                .append_as_leader(batch, partition.leader_epoch, AppendOrigin::Client)?;
+        let offsets: Vec<Value> = (info.first_offset..=info.last_offset)
+            .map(|offset| json!({"partition": topic_partition.partition(), "offset": offset}))
+            .collect();
+        Ok(json!({ "offsets": offsets }))
+    }
+
+    fn consume(&self, topic_partition: &TopicPartition, query: Option<&str>) -> Result<Value> {
+        let mut offset = None;
+        let mut max_bytes = DEFAULT_MAX_BYTES;
+        for (name, value) in query
+            .unwrap_or_default()
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+        {
+            let invalid = || RestBridgeError::InvalidRequest(format!("Invalid {name} {value}"));
+            match name {
+                "offset" => offset = Some(value.parse::<i64>().map_err(|_| invalid())?),
+                "max_bytes" => max_bytes = value.parse().map_err(|_| invalid())?,
+                _ => {}
+            }
+        }
+
+        let mut partitions = self.partitions();
+        let partition = lookup(&mut partitions, topic_partition)?;
+        let offset = offset.unwrap_or_else(|| partition.log.log_start_offset());
+        let mut records = vec![];
+        for batch in partition.log.read(offset, max_bytes)? {
+            for (record_offset, record) in batch.iter().filter(|(o, _)| *o >= offset) {
+                records.push(ConsumedRecord {
+                    topic: topic_partition.topic().to_string(),
+                    partition: topic_partition.partition(),
+                    offset: record_offset,
+                    timestamp: record.timestamp,
+                    key: record.key.as_deref().map(text),
+                    value: record.value.as_deref().map(text),
+                });
+            }
+        }
+        Ok(json!(records))
+    }
+}
+
+fn topic_partition(topic: &str, partition: &str) -> Result<TopicPartition> {
+    let partition = partition
+        .parse()
+        .map_err(|_| RestBridgeError::InvalidRequest(format!("Invalid partition {partition}")))?;
+    Ok(TopicPartition::new(topic, partition))
+}
+
+fn lookup<'a>(
+    partitions: &'a mut BTreeMap<TopicPartition, BridgedPartition>,
+    topic_partition: &TopicPartition,
+) -> Result<&'a mut BridgedPartition> {
+    if !partitions.contains_key(topic_partition) {
+        return Err(
+            if partitions
+                .keys()
+                .any(|tp| tp.topic() == topic_partition.topic())
+            {
+                RestBridgeError::UnknownPartition(topic_partition.clone())
+            } else {
+                RestBridgeError::UnknownTopic(topic_partition.topic().to_string())
+            },
+        );
+    }
+    Ok(partitions.get_mut(topic_partition).expect("checked above"))
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn error_response(error: &RestBridgeError) -> (StatusCode, Value) {
+    (
+        error.status(),
+        json!({"error_code": error.error_code(), "message": error.to_string()}),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::utils::time::MockTime;
+    use rafka_storage::MemoryLog;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    fn bridge() -> RestBridge {
+        let bridge = RestBridge::new(Arc::new(MockTime::with_start(0, 1000, 0)));
+        bridge.add_partition(
+            Box::new(MemoryLog::new(TopicPartition::new("foo", 0), 0)),
+            3,
+        );
+        bridge
+    }
+
+    fn produce(bridge: &RestBridge, path: &str, body: &str) -> (StatusCode, Value) {
+        bridge.handle(&Method::POST, path, None, body.as_bytes())
+    }
+
+    #[test]
+    fn test_produce_and_consume() {
+        let bridge = bridge();
+        let (status, json) = produce(
+            &bridge,
+            "/topics/foo/partitions/0",
+            r#"{"records": [{"key": "k", "value": "v1"}, {"value": "v2"}]}"#,
+        );
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            json!({"offsets": [{"partition": 0, "offset": 0}, {"partition": 0, "offset": 1}]}),
+            json
+        );
+        assert_eq!(
+            json!(["foo"]),
+            bridge.handle(&Method::GET, "/topics", None, &[]).1
+        );
+
+        let (status, json) = bridge.handle(
+            &Method::GET,
+            "/topics/foo/partitions/0/records",
+            Some("offset=1"),
+            &[],
+        );
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            json!([{
+                "topic": "foo",
+                "partition": 0,
+                "offset": 1,
+                "timestamp": 1000,
+                "key": null,
+                "value": "v2"
+            }]),
+            json
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let bridge = bridge();
+        let error_code = |(_, json): (StatusCode, Value)| json["error_code"].as_u64().unwrap();
+        assert_eq!(
+            40401,
+            error_code(produce(
+                &bridge,
+                "/topics/bar/partitions/0",
+                r#"{"records": [{"value": "v"}]}"#
+            ))
+        );
+        assert_eq!(
+            40402,
+            error_code(produce(
+                &bridge,
+                "/topics/foo/partitions/1",
+                r#"{"records": [{"value": "v"}]}"#
+            ))
+        );
+        assert_eq!(
+            42201,
+            error_code(produce(
+                &bridge,
+                "/topics/foo/partitions/0",
+                r#"{"records": []}"#
+            ))
+        );
+        assert_eq!(
+            42201,
+            error_code(produce(&bridge, "/topics/foo/partitions/x", "{}"))
+        );
+        assert_eq!(
+            41601,
+            error_code(bridge.handle(
+                &Method::GET,
+                "/topics/foo/partitions/0/records",
+                Some("offset=5"),
+                &[]
+            ))
+        );
+        assert_eq!(
+            40400,
+            error_code(bridge.handle(&Method::DELETE, "/topics", None, &[]))
+        );
+    }
+
+    #[test]
+    fn test_produced_records_are_validated() {
+        use crate::record_validator::{MaxRecordSizeValidator, TopicFilter};
+
+        let mut validators = RecordValidators::new();
+        validators.add(
+            TopicFilter::Topic("foo".to_string()),
+            Arc::new(MaxRecordSizeValidator { max_bytes: 4 }),
+        );
+        let bridge = bridge().set_record_validators(validators);
+        let (status, json) = produce(
+            &bridge,
+            "/topics/foo/partitions/0",
+            r#"{"records": [{"value": "v"}, {"value": "too large"}]}"#,
+        );
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, status);
+        assert_eq!(42202, json["error_code"]);
+        // None of the records of the batch was appended.
+        let (_, json) = bridge.handle(&Method::GET, "/topics/foo/partitions/0/records", None, &[]);
+        assert_eq!(json!([]), json);
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(Arc::new(bridge()).serve(listener));
+
+        let body = r#"{"records": [{"value": "hello"}]}"#;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(
+                format!(
+                    "POST /topics/foo/partitions/0 HTTP/1.1\r\nHost: localhost\r\n\
+                    Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with(r#"{"offsets":[{"offset":0,"partition":0}]}"#));
+        server.abort();
+    }
+}
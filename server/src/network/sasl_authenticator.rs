@@ -0,0 +1,438 @@
+//! The authentication of the connections to SASL listeners.
+//!
+//! A connection must authenticate before it may send any other request: it may first ask for
+//! the API versions, then it picks a mechanism with SaslHandshake and exchanges tokens with
+//! SaslAuthenticate until the mechanism completes. Any other request before that, and any
+//! request larger than a SASL token may be, fails the authentication, after which the
+//! connection is closed with a delay growing with the recent failures of its address, to slow
+//! down brute force attempts.
+
+use rafka_clients::common::protocol::api_keys::ApiKeys;
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::utils::exponential_backoff::ExponentialBackoff;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use thiserror::Error;
+use tracing::{debug, info};
+
+/// The largest request accepted before the authentication completes, as
+/// `sasl.server.max.receive.size`.
+pub const DEFAULT_SASL_SERVER_MAX_RECEIVE_SIZE: usize = 524288;
+
+/// The delay before closing a connection which failed to authenticate, as
+/// `connection.failed.authentication.delay.ms`.
+pub const DEFAULT_FAILED_AUTHENTICATION_DELAY_MS: i64 = 100;
+
+/// The longest delay before closing a connection which failed to authenticate, however many
+/// times its address failed.
+pub const DEFAULT_MAX_FAILED_AUTHENTICATION_DELAY_MS: i64 = 10_000;
+
+/// How long the failures of an address count towards the delay of its next failure.
+pub const DEFAULT_FAILED_AUTHENTICATION_WINDOW_MS: i64 = 60_000;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SaslAuthenticationError {
+    #[error("Unexpected {api} request in state {state:?}")]
+    IllegalSaslState { api: &'static str, state: SaslState },
+
+    #[error(
+        "The request of {size} bytes exceeds the limit of {max_size} bytes before authentication"
+    )]
+    InvalidReceive { size: usize, max_size: usize },
+
+    #[error("Unsupported SASL mechanism {mechanism}, enabled mechanisms are {}", enabled.join(", "))]
+    UnsupportedSaslMechanism {
+        mechanism: String,
+        enabled: Vec<String>,
+    },
+
+    #[error("Authentication failed: {0}")]
+    AuthenticationFailed(String),
+}
+
+impl SaslAuthenticationError {
+    /// The error code of the response to the failed request.
+    pub fn error(&self) -> Errors {
+        match self {
+            SaslAuthenticationError::IllegalSaslState { .. } => Errors::IllegalSaslState,
+            SaslAuthenticationError::InvalidReceive { .. } => Errors::InvalidRequest,
+            SaslAuthenticationError::UnsupportedSaslMechanism { .. } => {
+                Errors::UnsupportedSaslMechanism
+            }
+            SaslAuthenticationError::AuthenticationFailed(_) => Errors::SaslAuthenticationFailed,
+        }
+    }
+}
+
+/// The server side of a SASL mechanism, for one connection.
+pub trait SaslServer: Send {
+    /// Evaluates a token of the client and returns the challenge to send back.
+    fn evaluate_response(&mut self, response: &[u8]) -> Result<Vec<u8>, String>;
+
+    /// Whether the exchange completed, i.e. the client authenticated.
+    fn is_complete(&self) -> bool;
+
+    /// The authenticated principal, once complete.
+    fn authorization_id(&self) -> Option<String>;
+}
+
+type SaslServerFactory = Box<dyn Fn() -> Box<dyn SaslServer> + Send + Sync>;
+
+/// The mechanisms enabled on a listener, with the factory of their servers.
+#[derive(Default)]
+pub struct SaslMechanisms {
+    factories: BTreeMap<String, SaslServerFactory>,
+}
+
+impl SaslMechanisms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enable(
+        &mut self,
+        mechanism: &str,
+        factory: impl Fn() -> Box<dyn SaslServer> + Send + Sync + 'static,
+    ) {
+        self.factories
+            .insert(mechanism.to_string(), Box::new(factory));
+    }
+
+    pub fn enabled(&self) -> Vec<String> {
+        self.factories.keys().cloned().collect()
+    }
+}
+
+/// The state of the authentication of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaslState {
+    /// Expecting ApiVersions or SaslHandshake.
+    HandshakeOrVersionsRequest,
+    /// Expecting SaslHandshake, the API versions were sent.
+    HandshakeRequest,
+    /// Expecting SaslAuthenticate, until the mechanism completes.
+    Authenticate,
+    /// Authenticated, any request may be sent.
+    Complete,
+    /// The authentication failed, the connection is being closed.
+    Failed,
+}
+
+/// The authentication state machine of a connection to a SASL listener.
+pub struct SaslServerAuthenticator {
+    mechanisms: Arc<SaslMechanisms>,
+    max_receive_size: usize,
+    state: SaslState,
+    server: Option<Box<dyn SaslServer>>,
+    principal: Option<String>,
+}
+
+impl SaslServerAuthenticator {
+    pub fn new(mechanisms: Arc<SaslMechanisms>, max_receive_size: usize) -> Self {
+        Self {
+            mechanisms,
+            max_receive_size,
+            state: SaslState::HandshakeOrVersionsRequest,
+            server: None,
+            principal: None,
+        }
+    }
+
+    pub fn state(&self) -> SaslState {
+        self.state
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state == SaslState::Complete
+    }
+
+    /// The authenticated principal.
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+
+    /// Checks a request of `size` bytes for `api` may be received in the current state. Must
+    /// be called for every request, before it is parsed.
+    pub fn check_request(
+        &mut self,
+        api: ApiKeys,
+        size: usize,
+    ) -> Result<(), SaslAuthenticationError> {
+        if self.state == SaslState::Complete {
+            return Ok(());
+        }
+        if size > self.max_receive_size {
+            return self.fail(SaslAuthenticationError::InvalidReceive {
+                size,
+                max_size: self.max_receive_size,
+            });
+        }
+        let expected = match self.state {
+            SaslState::HandshakeOrVersionsRequest => {
+                api == ApiKeys::ApiVersions || api == ApiKeys::SaslHandshake
+            }
+            SaslState::HandshakeRequest => api == ApiKeys::SaslHandshake,
+            SaslState::Authenticate => api == ApiKeys::SaslAuthenticate,
+            SaslState::Complete | SaslState::Failed => false,
+        };
+        if !expected {
+            return self.fail(SaslAuthenticationError::IllegalSaslState {
+                api: api.name(),
+                state: self.state,
+            });
+        }
+        if api == ApiKeys::ApiVersions {
+            self.state = SaslState::HandshakeRequest;
+        }
+        Ok(())
+    }
+
+    /// Handles a SaslHandshake request selecting `mechanism`.
+    pub fn handshake(&mut self, mechanism: &str) -> Result<(), SaslAuthenticationError> {
+        self.expect_state(
+            ApiKeys::SaslHandshake,
+            &[
+                SaslState::HandshakeOrVersionsRequest,
+                SaslState::HandshakeRequest,
+            ],
+        )?;
+        match self.mechanisms.factories.get(mechanism) {
+            Some(factory) => {
+                debug!("Using SASL mechanism {mechanism}");
+                self.server = Some(factory());
+                self.state = SaslState::Authenticate;
+                Ok(())
+            }
+            None => self.fail(SaslAuthenticationError::UnsupportedSaslMechanism {
+                mechanism: mechanism.to_string(),
+                enabled: self.mechanisms.enabled(),
+            }),
+        }
+    }
+
+    /// Handles a SaslAuthenticate request and returns the token to send back.
+    pub fn authenticate(&mut self, token: &[u8]) -> Result<Vec<u8>, SaslAuthenticationError> {
+        self.expect_state(ApiKeys::SaslAuthenticate, &[SaslState::Authenticate])?;
+        let server = self.server.as_mut().expect("a mechanism was selected");
+        match server.evaluate_response(token) {
+            Ok(challenge) => {
+                if server.is_complete() {
+                    self.principal = server.authorization_id();
+                    self.server = None;
+                    self.state = SaslState::Complete;
+                }
+                Ok(challenge)
+            }
+            Err(message) => self.fail(SaslAuthenticationError::AuthenticationFailed(message)),
+        }
+    }
+
+    fn expect_state(
+        &mut self,
+        api: ApiKeys,
+        states: &[SaslState],
+    ) -> Result<(), SaslAuthenticationError> {
+        if states.contains(&self.state) {
+            Ok(())
+        } else {
+            self.fail(SaslAuthenticationError::IllegalSaslState {
+                api: api.name(),
+                state: self.state,
+            })
+        }
+    }
+
+    fn fail<T>(&mut self, error: SaslAuthenticationError) -> Result<T, SaslAuthenticationError> {
+        self.state = SaslState::Failed;
+        self.server = None;
+        Err(error)
+    }
+}
+
+/// The failed authentications of an address.
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last_failure_ms: i64,
+}
+
+/// Tracks the failed authentications of every address, to delay closing the connections
+/// which failed: the delay doubles with every failure of the address within the window, up to
+/// a maximum, so that a client guessing credentials is slowed down more the more it guesses.
+pub struct FailedAuthentications {
+    backoff: ExponentialBackoff,
+    window_ms: i64,
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl FailedAuthentications {
+    pub fn new(base_delay_ms: i64, max_delay_ms: i64, window_ms: i64) -> Self {
+        Self {
+            backoff: ExponentialBackoff::new(base_delay_ms, 2.0, max_delay_ms, 0.0),
+            window_ms,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn failures(&self) -> MutexGuard<'_, HashMap<IpAddr, Failures>> {
+        self.failures
+            .lock()
+            .expect("failed authentications lock poisoned")
+    }
+
+    /// Records a failed authentication of `ip` at `now_ms` and returns how long to wait
+    /// before closing its connection.
+    pub fn record_failure(&self, ip: IpAddr, now_ms: i64) -> i64 {
+        let mut failures = self.failures();
+        failures.retain(|_, f| now_ms - f.last_failure_ms < self.window_ms);
+        let entry = failures.entry(ip).or_insert(Failures {
+            count: 0,
+            last_failure_ms: now_ms,
+        });
+        entry.count += 1;
+        entry.last_failure_ms = now_ms;
+        let delay_ms = self.backoff.backoff(entry.count - 1);
+        info!(
+            "Failed authentication from {ip}, {} failures in a row, closing in {delay_ms} ms",
+            entry.count
+        );
+        delay_ms
+    }
+
+    /// Forgets the failures of `ip` once it authenticated.
+    pub fn record_success(&self, ip: &IpAddr) {
+        self.failures().remove(ip);
+    }
+}
+
+impl Default for FailedAuthentications {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_FAILED_AUTHENTICATION_DELAY_MS,
+            DEFAULT_MAX_FAILED_AUTHENTICATION_DELAY_MS,
+            DEFAULT_FAILED_AUTHENTICATION_WINDOW_MS,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PLAIN-like mechanism: the single token must be `user:secret`.
+    struct SecretServer {
+        user: Option<String>,
+    }
+
+    impl SaslServer for SecretServer {
+        fn evaluate_response(&mut self, response: &[u8]) -> Result<Vec<u8>, String> {
+            match std::str::from_utf8(response)
+                .ok()
+                .and_then(|r| r.split_once(':'))
+            {
+                Some((user, "secret")) => {
+                    self.user = Some(user.to_string());
+                    Ok(Vec::new())
+                }
+                _ => Err("invalid credentials".to_string()),
+            }
+        }
+
+        fn is_complete(&self) -> bool {
+            self.user.is_some()
+        }
+
+        fn authorization_id(&self) -> Option<String> {
+            self.user.clone()
+        }
+    }
+
+    fn authenticator() -> SaslServerAuthenticator {
+        let mut mechanisms = SaslMechanisms::new();
+        mechanisms.enable("PLAIN", || Box::new(SecretServer { user: None }));
+        SaslServerAuthenticator::new(Arc::new(mechanisms), 64)
+    }
+
+    #[test]
+    fn test_successful_authentication() {
+        let mut auth = authenticator();
+        auth.check_request(ApiKeys::ApiVersions, 10).unwrap();
+        assert_eq!(SaslState::HandshakeRequest, auth.state());
+        auth.check_request(ApiKeys::SaslHandshake, 10).unwrap();
+        auth.handshake("PLAIN").unwrap();
+        auth.check_request(ApiKeys::SaslAuthenticate, 10).unwrap();
+        auth.authenticate(b"alice:secret").unwrap();
+        assert!(auth.is_complete());
+        assert_eq!(Some("alice"), auth.principal());
+        // Any request may be sent once authenticated, of any size.
+        auth.check_request(ApiKeys::Produce, 1 << 20).unwrap();
+    }
+
+    #[test]
+    fn test_requests_before_authentication_fail() {
+        let mut auth = authenticator();
+        let error = auth.check_request(ApiKeys::Metadata, 10).unwrap_err();
+        assert_eq!(Errors::IllegalSaslState, error.error());
+        assert_eq!(SaslState::Failed, auth.state());
+        // A failed authentication stays failed.
+        assert!(auth.check_request(ApiKeys::SaslHandshake, 10).is_err());
+
+        // ApiVersions may only be sent once.
+        let mut auth = authenticator();
+        auth.check_request(ApiKeys::ApiVersions, 10).unwrap();
+        assert!(auth.check_request(ApiKeys::ApiVersions, 10).is_err());
+
+        // No token before the handshake.
+        let mut auth = authenticator();
+        assert!(auth.authenticate(b"alice:secret").is_err());
+        assert!(!auth.is_complete());
+
+        // No second handshake once a mechanism was selected.
+        let mut auth = authenticator();
+        auth.handshake("PLAIN").unwrap();
+        assert!(auth.check_request(ApiKeys::SaslHandshake, 10).is_err());
+    }
+
+    #[test]
+    fn test_oversized_and_invalid_tokens_fail() {
+        let mut auth = authenticator();
+        auth.handshake("PLAIN").unwrap();
+        let error = auth
+            .check_request(ApiKeys::SaslAuthenticate, 65)
+            .unwrap_err();
+        assert_eq!(
+            SaslAuthenticationError::InvalidReceive {
+                size: 65,
+                max_size: 64
+            },
+            error
+        );
+
+        let mut auth = authenticator();
+        let error = auth.handshake("GSSAPI").unwrap_err();
+        assert_eq!(Errors::UnsupportedSaslMechanism, error.error());
+
+        let mut auth = authenticator();
+        auth.handshake("PLAIN").unwrap();
+        let error = auth.authenticate(b"alice:guess").unwrap_err();
+        assert_eq!(Errors::SaslAuthenticationFailed, error.error());
+        assert_eq!(SaslState::Failed, auth.state());
+    }
+
+    #[test]
+    fn test_failure_delay_grows_per_address() {
+        let failures = FailedAuthentications::new(100, 1_000, 60_000);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let delays: Vec<_> = (0..6).map(|i| failures.record_failure(ip, i)).collect();
+        assert_eq!(vec![100, 200, 400, 800, 1_000, 1_000], delays);
+        assert_eq!(100, failures.record_failure(other, 10));
+
+        // The failures are forgotten once the address authenticates, or after the window.
+        failures.record_success(&ip);
+        assert_eq!(100, failures.record_failure(ip, 20));
+        assert_eq!(200, failures.record_failure(ip, 30));
+        assert_eq!(100, failures.record_failure(ip, 60_030));
+    }
+}
@@ -1,6 +1,11 @@
 use easy_config_def::prelude::*;
 use once_cell::sync::Lazy;
+use rafka_clients::common::endpoint::{
+    Endpoint, EndpointError, normalize_listener_name, parse_listener_security_protocol_map,
+};
 use rafka_clients::common::security_protocol::SecurityProtocol;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 
 pub const LISTENER_SECURITY_PROTOCOL_MAP_CONFIG: &str = "listener.security.protocol.map";
 const LISTENER_SECURITY_PROTOCOL_MAP_DEFAULT: Lazy<String> = Lazy::new(|| {
@@ -34,6 +39,24 @@ pub const NUM_NETWORK_THREADS_CONFIG: &str = "num.network.threads";
 const NUM_NETWORK_THREADS_DEFAULT: u32 = 3;
 const NUM_NETWORK_THREADS_DOC: &str = "The number of threads that the server uses for receiving requests from the network and sending responses to the network. Noted: each listener (except for controller listener) creates its own thread pool.";
 
+pub const QUEUED_MAX_REQUESTS_CONFIG: &str = "queued.max.requests";
+const QUEUED_MAX_REQUESTS_DEFAULT: u32 = 500;
+const QUEUED_MAX_REQUESTS_DOC: &str = "The number of queued requests allowed for the data-plane, before blocking the network threads. \
+    The requests of the controller listeners are queued separately, so that controller traffic is never starved by data-plane load.";
+
+pub const QUEUED_MAX_BYTES_CONFIG: &str = "queued.max.request.bytes";
+const QUEUED_MAX_REQUEST_BYTES_DEFAULT: i64 = -1;
+const QUEUED_MAX_REQUEST_BYTES_DOC: &str = "The number of queued bytes allowed before no more requests are read. \
+    The network threads stop reading from their connections while the requests they read hold this many bytes, \
+    and resume once the requests were handled. A value of -1 means unbounded.";
+
+pub const PRODUCE_MAX_IN_FLIGHT_BYTES_CONFIG: &str = "produce.max.in.flight.bytes";
+const PRODUCE_MAX_IN_FLIGHT_BYTES_DEFAULT: i64 = 256 * 1024 * 1024;
+const PRODUCE_MAX_IN_FLIGHT_BYTES_DOC: &str = "The number of bytes of produce requests the broker handles at once. \
+    A produce request is held from the time it is read until it is answered, so a request which would take the bytes \
+    in flight above this budget is rejected with a retriable error, and one larger than the budget is rejected with \
+    <code>RECORD_LIST_TOO_LARGE</code>.";
+
 #[derive(Debug, EasyConfig)]
 pub struct SocketServerConfig {
     #[attr(name = LISTENERS_CONFIG,
@@ -82,4 +105,279 @@ pub struct SocketServerConfig {
     documentation = NUM_NETWORK_THREADS_DOC,
     getter)]
     num_network_threads_config: u32,
+
+    #[attr(name = QUEUED_MAX_REQUESTS_CONFIG,
+    default = QUEUED_MAX_REQUESTS_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::HIGH,
+    documentation = QUEUED_MAX_REQUESTS_DOC,
+    getter)]
+    queued_max_requests_config: u32,
+
+    #[attr(name = QUEUED_MAX_BYTES_CONFIG,
+    default = QUEUED_MAX_REQUEST_BYTES_DEFAULT,
+    importance = Importance::MEDIUM,
+    documentation = QUEUED_MAX_REQUEST_BYTES_DOC,
+    getter)]
+    queued_max_bytes_config: i64,
+
+    #[attr(name = PRODUCE_MAX_IN_FLIGHT_BYTES_CONFIG,
+    default = PRODUCE_MAX_IN_FLIGHT_BYTES_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::MEDIUM,
+    documentation = PRODUCE_MAX_IN_FLIGHT_BYTES_DOC,
+    getter)]
+    produce_max_in_flight_bytes_config: i64,
+}
+
+impl SocketServerConfig {
+    /// The security protocol of each listener, keyed by normalized listener name. The
+    /// controller listeners which are not mapped explicitly default to PLAINTEXT.
+    pub fn listener_security_protocol_map(
+        &self,
+        controller_listener_names: &[String],
+    ) -> Result<HashMap<String, SecurityProtocol>, EndpointError> {
+        let mut protocol_map =
+            parse_listener_security_protocol_map(self.listener_security_protocol_map_config())?;
+        for name in controller_listener_names {
+            protocol_map
+                .entry(normalize_listener_name(name))
+                .or_insert(SecurityProtocol::Plaintext);
+        }
+        Ok(protocol_map)
+    }
+
+    /// The listeners to bind to.
+    pub fn listeners(
+        &self,
+        protocol_map: &HashMap<String, SecurityProtocol>,
+    ) -> Result<Vec<Endpoint>, EndpointError> {
+        listener_list_to_endpoints(self.listeners_config(), protocol_map)
+    }
+
+    /// The listeners the broker registers with the controller and returns in metadata
+    /// responses: the advertised listeners, or the listeners if none are advertised, without
+    /// the controller listeners.
+    pub fn effective_advertised_broker_listeners(
+        &self,
+        protocol_map: &HashMap<String, SecurityProtocol>,
+        controller_listener_names: &[String],
+    ) -> Result<Vec<Endpoint>, EndpointError> {
+        let endpoints = if self.advertised_listeners_config().is_empty() {
+            self.listeners(protocol_map)?
+        } else {
+            advertised_listener_list_to_endpoints(self.advertised_listeners_config(), protocol_map)?
+        };
+        let controller_listener_names: Vec<String> = controller_listener_names
+            .iter()
+            .map(|name| normalize_listener_name(name))
+            .collect();
+        let endpoints: Vec<Endpoint> = endpoints
+            .into_iter()
+            .filter(|e| !controller_listener_names.contains(&e.listener_name))
+            .collect();
+        if let Some(endpoint) = endpoints.iter().find(|e| e.is_wildcard()) {
+            return Err(EndpointError::UnroutableAdvertisedHost(
+                endpoint.listener_name.clone(),
+                endpoint.host.clone(),
+            ));
+        }
+        Ok(endpoints)
+    }
+}
+
+/// The listeners of a node, split by the plane serving their requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlaneListeners {
+    /// The listeners of the clients and the other brokers.
+    pub data_plane: Vec<Endpoint>,
+    /// The `controller.listener.names` listeners, which have their own acceptor, processors
+    /// and request queue, so that controller traffic isn't queued behind client requests.
+    pub control_plane: Vec<Endpoint>,
+}
+
+/// Splits `listeners` into the data-plane and the control-plane listeners.
+pub fn split_listeners_by_plane(
+    listeners: Vec<Endpoint>,
+    controller_listener_names: &[String],
+) -> PlaneListeners {
+    let controller_listener_names: Vec<String> = controller_listener_names
+        .iter()
+        .map(|name| normalize_listener_name(name))
+        .collect();
+    let (control_plane, data_plane) = listeners.into_iter().partition(|l| {
+        controller_listener_names.contains(&normalize_listener_name(&l.listener_name))
+    });
+    PlaneListeners {
+        data_plane,
+        control_plane,
+    }
+}
+
+fn parse_all(
+    listeners: &[String],
+    protocol_map: &HashMap<String, SecurityProtocol>,
+) -> Result<Vec<Endpoint>, EndpointError> {
+    let endpoints: Vec<Endpoint> = listeners
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| Endpoint::parse(l, protocol_map))
+        .collect::<Result<_, _>>()?;
+    let mut names = HashSet::new();
+    for endpoint in &endpoints {
+        if !names.insert(&endpoint.listener_name) {
+            return Err(EndpointError::DuplicateListenerName(
+                endpoint.listener_name.clone(),
+            ));
+        }
+    }
+    Ok(endpoints)
+}
+
+/// Parses the `listeners` config. Listener names must be unique, and so must ports, except
+/// that two listeners may share a port when one binds to an IPv4 address and the other to an
+/// IPv6 address.
+pub fn listener_list_to_endpoints(
+    listeners: &[String],
+    protocol_map: &HashMap<String, SecurityProtocol>,
+) -> Result<Vec<Endpoint>, EndpointError> {
+    let endpoints = parse_all(listeners, protocol_map)?;
+    let mut by_port: HashMap<u16, Vec<&Endpoint>> = HashMap::new();
+    for endpoint in &endpoints {
+        // Port 0 picks a random port, which can't collide.
+        if endpoint.port != 0 {
+            by_port.entry(endpoint.port).or_default().push(endpoint);
+        }
+    }
+    for (port, sharing) in by_port {
+        if sharing.len() < 2 {
+            continue;
+        }
+        let ips: Vec<_> = sharing.iter().filter_map(|e| e.ip_address()).collect();
+        let ipv4_and_ipv6 = sharing.len() == 2
+            && ips.len() == 2
+            && ips.iter().any(IpAddr::is_ipv4)
+            && ips.iter().any(IpAddr::is_ipv6);
+        if !ipv4_and_ipv6 {
+            let names: Vec<_> = sharing.iter().map(|e| e.listener_name.as_str()).collect();
+            return Err(EndpointError::DuplicatePort(port, names.join(", ")));
+        }
+    }
+    Ok(endpoints)
+}
+
+/// Parses the `advertised.listeners` config. Listener names must be unique, but ports may be
+/// shared, e.g. to advertise the address of a load balancer for several listeners. The
+/// wildcard address can't be advertised.
+pub fn advertised_listener_list_to_endpoints(
+    listeners: &[String],
+    protocol_map: &HashMap<String, SecurityProtocol>,
+) -> Result<Vec<Endpoint>, EndpointError> {
+    let endpoints = parse_all(listeners, protocol_map)?;
+    if let Some(endpoint) = endpoints.iter().find(|e| e.is_wildcard()) {
+        return Err(EndpointError::UnroutableAdvertisedHost(
+            endpoint.listener_name.clone(),
+            endpoint.host.clone(),
+        ));
+    }
+    Ok(endpoints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protocol_map() -> HashMap<String, SecurityProtocol> {
+        parse_listener_security_protocol_map(
+            "PLAINTEXT:PLAINTEXT,INTERNAL:SSL,EXTERNAL:SASL_SSL,CONTROLLER:PLAINTEXT",
+        )
+        .unwrap()
+    }
+
+    fn listeners(listeners: &[&str]) -> Vec<String> {
+        listeners.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_listener_list_to_endpoints() {
+        let endpoints = listener_list_to_endpoints(
+            &listeners(&[
+                "INTERNAL://127.0.0.1:9092",
+                "EXTERNAL://[::1]:9092",
+                "PLAINTEXT://:0",
+                "CONTROLLER://:0",
+            ]),
+            &protocol_map(),
+        )
+        .unwrap();
+        assert_eq!(4, endpoints.len());
+        assert_eq!(SecurityProtocol::SaslSsl, endpoints[1].security_protocol);
+
+        assert_eq!(
+            Err(EndpointError::DuplicatePort(
+                9092,
+                "INTERNAL, EXTERNAL".to_string()
+            )),
+            listener_list_to_endpoints(
+                &listeners(&["INTERNAL://127.0.0.1:9092", "EXTERNAL://127.0.0.2:9092"]),
+                &protocol_map(),
+            )
+        );
+        assert!(matches!(
+            listener_list_to_endpoints(
+                &listeners(&["INTERNAL://localhost:9092", "EXTERNAL://[::1]:9092"]),
+                &protocol_map(),
+            ),
+            Err(EndpointError::DuplicatePort(9092, _))
+        ));
+        assert_eq!(
+            Err(EndpointError::DuplicateListenerName("INTERNAL".to_string())),
+            listener_list_to_endpoints(
+                &listeners(&["INTERNAL://:9092", "internal://:9093"]),
+                &protocol_map(),
+            )
+        );
+    }
+
+    #[test]
+    fn test_split_listeners_by_plane() {
+        let endpoints = listener_list_to_endpoints(
+            &listeners(&[
+                "PLAINTEXT://:9092",
+                "CONTROLLER://:9093",
+                "INTERNAL://:9094",
+            ]),
+            &protocol_map(),
+        )
+        .unwrap();
+        let planes = split_listeners_by_plane(endpoints, &["controller".to_string()]);
+        let names = |endpoints: &[Endpoint]| {
+            endpoints
+                .iter()
+                .map(|e| e.listener_name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["PLAINTEXT", "INTERNAL"], names(&planes.data_plane));
+        assert_eq!(vec!["CONTROLLER"], names(&planes.control_plane));
+    }
+
+    #[test]
+    fn test_advertised_listeners() {
+        let endpoints = advertised_listener_list_to_endpoints(
+            &listeners(&["INTERNAL://lb:9092", "EXTERNAL://lb:9092"]),
+            &protocol_map(),
+        )
+        .unwrap();
+        assert_eq!(2, endpoints.len());
+        assert_eq!(
+            Err(EndpointError::UnroutableAdvertisedHost(
+                "EXTERNAL".to_string(),
+                "0.0.0.0".to_string()
+            )),
+            advertised_listener_list_to_endpoints(
+                &listeners(&["EXTERNAL://0.0.0.0:9092"]),
+                &protocol_map(),
+            )
+        );
+    }
 }
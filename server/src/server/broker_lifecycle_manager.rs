@@ -0,0 +1,176 @@
+use crate::broker_server_metrics::BrokerServerMetrics;
+use rafka_metadata::broker_state::BrokerState;
+use rafka_metadata::cluster_control_manager::{
+    BrokerHeartbeatReply, BrokerHeartbeatRequest, LogDirStatus,
+};
+use std::sync::Arc;
+use tracing::info;
+
+/// The default of how many records a broker may lag behind the high watermark of the
+/// metadata log and still be considered caught up.
+pub const DEFAULT_METADATA_CATCH_UP_THRESHOLD: i64 = 100;
+
+/// Drives a broker through its startup states with the heartbeats it sends the controller.
+///
+/// A registered broker is in [BrokerState::Recovery] and asks to stay fenced until it
+/// recovered its logs and applied the metadata log to within `metadata_catch_up_threshold`
+/// records of its high watermark. It then reports it is ready, and moves to
+/// [BrokerState::Running] once the controller replies it is unfenced. The controller only
+/// places replicas on unfenced brokers, so no leadership moves to a broker which would serve
+/// stale logs or metadata.
+pub struct BrokerLifecycleManager {
+    node_id: i32,
+    metadata_catch_up_threshold: i64,
+    metrics: Arc<BrokerServerMetrics>,
+    broker_epoch: Option<i64>,
+    log_recovery_complete: bool,
+    /// The offset of the last metadata record the broker applied, or -1 if none was.
+    applied_metadata_offset: i64,
+    /// The high watermark of the metadata log, or -1 if it is not known yet.
+    metadata_high_watermark: i64,
+    /// The state of the log directories, reported in every heartbeat.
+    log_dirs: Vec<LogDirStatus>,
+}
+
+impl BrokerLifecycleManager {
+    pub fn new(
+        node_id: i32,
+        metadata_catch_up_threshold: i64,
+        metrics: Arc<BrokerServerMetrics>,
+    ) -> Self {
+        metrics.set_broker_state(BrokerState::Starting);
+        Self {
+            node_id,
+            metadata_catch_up_threshold,
+            metrics,
+            broker_epoch: None,
+            log_recovery_complete: false,
+            applied_metadata_offset: -1,
+            metadata_high_watermark: -1,
+            log_dirs: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> BrokerState {
+        self.metrics.broker_state()
+    }
+
+    pub fn broker_epoch(&self) -> Option<i64> {
+        self.broker_epoch
+    }
+
+    /// Records the registration of the broker with `broker_epoch`, which starts its recovery.
+    pub fn registered(&mut self, broker_epoch: i64) {
+        self.broker_epoch = Some(broker_epoch);
+        if self.state() == BrokerState::Starting {
+            self.metrics.set_broker_state(BrokerState::Recovery);
+        }
+    }
+
+    /// Records that all the logs were recovered.
+    pub fn set_log_recovery_complete(&mut self) {
+        self.log_recovery_complete = true;
+    }
+
+    /// Records how far the broker applied the metadata log, and its high watermark.
+    pub fn set_metadata_offsets(&mut self, applied_offset: i64, high_watermark: i64) {
+        self.applied_metadata_offset = applied_offset;
+        self.metadata_high_watermark = high_watermark;
+    }
+
+    /// Records the state of the log directories, e.g. once one failed or ran out of space,
+    /// so that the controller stops placing new replicas in them.
+    pub fn set_log_dirs(&mut self, log_dirs: Vec<LogDirStatus>) {
+        self.log_dirs = log_dirs;
+    }
+
+    /// Whether the broker applied the metadata log to within the threshold of its high
+    /// watermark.
+    pub fn is_metadata_caught_up(&self) -> bool {
+        self.metadata_high_watermark >= 0
+            && self.metadata_high_watermark - (self.applied_metadata_offset + 1)
+                <= self.metadata_catch_up_threshold
+    }
+
+    /// Whether the broker can serve as a replica, so it can be unfenced.
+    pub fn is_ready_to_unfence(&self) -> bool {
+        self.log_recovery_complete && self.is_metadata_caught_up()
+    }
+
+    /// The next heartbeat to send, or `None` if the broker is not registered.
+    pub fn heartbeat_request(&self) -> Option<BrokerHeartbeatRequest> {
+        Some(BrokerHeartbeatRequest {
+            broker_id: self.node_id,
+            broker_epoch: self.broker_epoch?,
+            current_metadata_offset: self.applied_metadata_offset,
+            want_fence: !self.is_ready_to_unfence(),
+            log_dirs: self.log_dirs.clone(),
+        })
+    }
+
+    /// Handles the reply of the controller to a heartbeat.
+    pub fn handle_heartbeat_reply(&mut self, reply: &BrokerHeartbeatReply) {
+        if self.state() == BrokerState::Recovery && !reply.is_fenced {
+            info!(
+                "Broker {} was unfenced at metadata offset {}",
+                self.node_id, self.applied_metadata_offset
+            );
+            self.metrics.set_broker_state(BrokerState::Running);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::metrics::Metrics;
+    use rafka_clients::common::uuid::Uuid;
+
+    fn reply(is_fenced: bool) -> BrokerHeartbeatReply {
+        BrokerHeartbeatReply {
+            is_caught_up: true,
+            is_fenced,
+        }
+    }
+
+    #[test]
+    fn test_unfenced_only_once_recovered_and_caught_up() {
+        let metrics = Arc::new(BrokerServerMetrics::new(Metrics::default()));
+        let mut manager = BrokerLifecycleManager::new(1, 10, metrics.clone());
+        assert_eq!(BrokerState::Starting, manager.state());
+        assert_eq!(None, manager.heartbeat_request());
+
+        manager.registered(5);
+        assert_eq!(BrokerState::Recovery, metrics.broker_state());
+        let request = manager.heartbeat_request().unwrap();
+        assert_eq!(5, request.broker_epoch);
+        assert!(request.want_fence);
+
+        // Caught up, but the logs are still being recovered.
+        manager.set_metadata_offsets(89, 100);
+        assert!(manager.is_metadata_caught_up());
+        assert!(manager.heartbeat_request().unwrap().want_fence);
+
+        manager.set_log_recovery_complete();
+        manager.set_metadata_offsets(80, 100);
+        assert!(!manager.is_metadata_caught_up());
+        assert!(manager.heartbeat_request().unwrap().want_fence);
+
+        manager.set_metadata_offsets(95, 100);
+        let log_dir = LogDirStatus {
+            dir: Uuid::new(1, 1),
+            offline: true,
+            usable_bytes: 0,
+        };
+        manager.set_log_dirs(vec![log_dir]);
+        let request = manager.heartbeat_request().unwrap();
+        assert!(!request.want_fence);
+        assert_eq!(95, request.current_metadata_offset);
+        assert_eq!(vec![log_dir], request.log_dirs);
+
+        manager.handle_heartbeat_reply(&reply(true));
+        assert_eq!(BrokerState::Recovery, manager.state());
+        manager.handle_heartbeat_reply(&reply(false));
+        assert_eq!(BrokerState::Running, manager.state());
+    }
+}
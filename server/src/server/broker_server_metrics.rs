@@ -0,0 +1,213 @@
+use rafka_clients::common::metrics::{MetricName, Metrics};
+use rafka_metadata::broker_state::BrokerState;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::info;
+
+pub const BROKER_METRICS_GROUP: &str = "broker-metrics";
+pub const LOG_MANAGER_METRICS_GROUP: &str = "log-manager-metrics";
+
+pub const BROKER_STATE: &str = "broker-state";
+pub const REMAINING_LOGS_TO_RECOVER: &str = "remaining-logs-to-recover";
+pub const REMAINING_SEGMENTS_TO_RECOVER: &str = "remaining-segments-to-recover";
+
+/// The metrics reporting the state of a broker and the progress of its startup.
+///
+/// A broker restarting after an unclean shutdown stays in [BrokerState::Recovery] until it
+/// recovered all its logs, which may take long. The recovery gauges tell operators how much
+/// is left: the logs to recover in each log directory, and the segments left in the log each
+/// recovery thread is working on. They are removed once the recovery of a directory completes.
+pub struct BrokerServerMetrics {
+    metrics: Metrics,
+    broker_state: Arc<AtomicU8>,
+    recovery: Mutex<HashMap<String, DirRecovery>>,
+}
+
+/// The recovery progress of a log directory.
+struct DirRecovery {
+    remaining_logs: Arc<AtomicI64>,
+    remaining_segments: HashMap<usize, Arc<AtomicI64>>,
+    metric_names: Vec<MetricName>,
+}
+
+impl BrokerServerMetrics {
+    pub fn new(metrics: Metrics) -> Self {
+        let broker_state = Arc::new(AtomicU8::new(BrokerState::NotRunning.value()));
+        let gauge = Arc::clone(&broker_state);
+        metrics.add_gauge(
+            metrics.metric_name(
+                BROKER_STATE,
+                BROKER_METRICS_GROUP,
+                "The state of the broker: 0 NOT_RUNNING, 1 STARTING, 2 RECOVERY, 3 RUNNING, \
+                6 PENDING_CONTROLLED_SHUTDOWN, 7 SHUTTING_DOWN",
+                &[],
+            ),
+            move |_| gauge.load(Ordering::Relaxed) as f64,
+        );
+        Self {
+            metrics,
+            broker_state,
+            recovery: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn recovery(&self) -> MutexGuard<'_, HashMap<String, DirRecovery>> {
+        self.recovery.lock().expect("recovery lock poisoned")
+    }
+
+    pub fn broker_state(&self) -> BrokerState {
+        BrokerState::from_value(self.broker_state.load(Ordering::Relaxed)).unwrap_or_default()
+    }
+
+    pub fn set_broker_state(&self, state: BrokerState) {
+        let previous = self.broker_state.swap(state.value(), Ordering::Relaxed);
+        if previous != state.value() {
+            info!("Transitioning the broker state to {state}");
+        }
+    }
+
+    /// Starts tracking the recovery of the `num_logs` logs of `dir` by `num_threads` threads.
+    pub fn start_log_recovery(&self, dir: &str, num_logs: usize, num_threads: usize) {
+        self.remove_recovery_gauges(dir);
+        let remaining_logs = Arc::new(AtomicI64::new(num_logs as i64));
+        let mut metric_names = vec![];
+
+        let metric_name = self.metrics.metric_name(
+            REMAINING_LOGS_TO_RECOVER,
+            LOG_MANAGER_METRICS_GROUP,
+            "The number of logs of the directory left to recover",
+            &[("dir", dir)],
+        );
+        let gauge = Arc::clone(&remaining_logs);
+        self.metrics.add_gauge(metric_name.clone(), move |_| {
+            gauge.load(Ordering::Relaxed) as f64
+        });
+        metric_names.push(metric_name);
+
+        let mut remaining_segments = HashMap::new();
+        for thread in 0..num_threads {
+            let remaining = Arc::new(AtomicI64::new(0));
+            let thread_num = thread.to_string();
+            let metric_name = self.metrics.metric_name(
+                REMAINING_SEGMENTS_TO_RECOVER,
+                LOG_MANAGER_METRICS_GROUP,
+                "The number of segments left to recover in the log the thread is recovering",
+                &[("dir", dir), ("thread-num", &thread_num)],
+            );
+            let gauge = Arc::clone(&remaining);
+            self.metrics.add_gauge(metric_name.clone(), move |_| {
+                gauge.load(Ordering::Relaxed) as f64
+            });
+            metric_names.push(metric_name);
+            remaining_segments.insert(thread, remaining);
+        }
+
+        self.recovery().insert(
+            dir.to_string(),
+            DirRecovery {
+                remaining_logs,
+                remaining_segments,
+                metric_names,
+            },
+        );
+    }
+
+    /// Sets the number of segments left to recover in the log `thread` of `dir` recovers.
+    pub fn set_remaining_segments_to_recover(&self, dir: &str, thread: usize, segments: usize) {
+        if let Some(remaining) = self
+            .recovery()
+            .get(dir)
+            .and_then(|recovery| recovery.remaining_segments.get(&thread))
+        {
+            remaining.store(segments as i64, Ordering::Relaxed);
+        }
+    }
+
+    /// Marks a log of `dir` as recovered.
+    pub fn log_recovered(&self, dir: &str) {
+        if let Some(recovery) = self.recovery().get(dir) {
+            recovery.remaining_logs.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stops tracking the recovery of `dir`, removing its gauges.
+    pub fn complete_log_recovery(&self, dir: &str) {
+        if self.remove_recovery_gauges(dir) {
+            info!("Completed the recovery of the logs in {dir}");
+        }
+    }
+
+    fn remove_recovery_gauges(&self, dir: &str) -> bool {
+        let recovery = self.recovery().remove(dir);
+        match recovery {
+            Some(recovery) => {
+                for metric_name in &recovery.metric_names {
+                    self.metrics.remove_metric(metric_name);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(metrics: &Metrics, name: &str, group: &str, tags: &[(&str, &str)]) -> Option<f64> {
+        metrics.metric_value(&metrics.metric_name(name, group, "", tags))
+    }
+
+    #[test]
+    fn test_broker_state() {
+        let metrics = Metrics::default();
+        let broker_metrics = BrokerServerMetrics::new(metrics.clone());
+        let state = || value(&metrics, BROKER_STATE, BROKER_METRICS_GROUP, &[]);
+        assert_eq!(Some(0.0), state());
+
+        broker_metrics.set_broker_state(BrokerState::Recovery);
+        assert_eq!(BrokerState::Recovery, broker_metrics.broker_state());
+        assert_eq!(Some(2.0), state());
+        broker_metrics.set_broker_state(BrokerState::PendingControlledShutdown);
+        assert_eq!(Some(6.0), state());
+    }
+
+    #[test]
+    fn test_log_recovery_progress() {
+        let metrics = Metrics::default();
+        let broker_metrics = BrokerServerMetrics::new(metrics.clone());
+        let dir = "/data/logs";
+        let logs = || {
+            value(
+                &metrics,
+                REMAINING_LOGS_TO_RECOVER,
+                LOG_MANAGER_METRICS_GROUP,
+                &[("dir", dir)],
+            )
+        };
+        let segments = |thread: &str| {
+            value(
+                &metrics,
+                REMAINING_SEGMENTS_TO_RECOVER,
+                LOG_MANAGER_METRICS_GROUP,
+                &[("dir", dir), ("thread-num", thread)],
+            )
+        };
+
+        broker_metrics.start_log_recovery(dir, 3, 2);
+        assert_eq!(Some(3.0), logs());
+        assert_eq!(Some(0.0), segments("1"));
+
+        broker_metrics.set_remaining_segments_to_recover(dir, 1, 42);
+        broker_metrics.log_recovered(dir);
+        assert_eq!(Some(2.0), logs());
+        assert_eq!(Some(42.0), segments("1"));
+        assert_eq!(Some(0.0), segments("0"));
+
+        broker_metrics.complete_log_recovery(dir);
+        assert_eq!(None, logs());
+        assert_eq!(None, segments("0"));
+    }
+}
@@ -0,0 +1,403 @@
+use rafka_clients::common::metrics::stats::{CumulativeSum, Rate};
+use rafka_clients::common::metrics::{Metrics, Sensor};
+use rafka_clients::common::record::record_batch::RecordError;
+use rafka_storage::LogError;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::debug;
+
+pub const BROKER_TOPIC_METRICS_GROUP: &str = "broker-topic-metrics";
+
+pub const TOPIC_TAG: &str = "topic";
+pub const REASON_TAG: &str = "reason";
+
+pub const BYTES_IN_RATE: &str = "bytes-in-rate";
+pub const BYTES_IN_TOTAL: &str = "bytes-in-total";
+pub const BYTES_OUT_RATE: &str = "bytes-out-rate";
+pub const BYTES_OUT_TOTAL: &str = "bytes-out-total";
+pub const MESSAGES_IN_RATE: &str = "messages-in-rate";
+pub const MESSAGES_IN_TOTAL: &str = "messages-in-total";
+pub const FETCH_MESSAGE_CONVERSIONS_RATE: &str = "fetch-message-conversions-rate";
+pub const FETCH_MESSAGE_CONVERSIONS_TOTAL: &str = "fetch-message-conversions-total";
+pub const FAILED_PRODUCE_RECORDS_RATE: &str = "failed-produce-records-rate";
+pub const FAILED_PRODUCE_RECORDS_TOTAL: &str = "failed-produce-records-total";
+
+/// Why the leader rejected a produce batch, the `reason` tag of the failed produce metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProduceFailureReason {
+    /// The batch does not match its CRC, e.g. corrupted by the network or a buggy producer.
+    InvalidCrc,
+    /// A timestamp of the batch is too far from the time of the broker.
+    InvalidTimestamp,
+    /// The batch is larger than `max.message.bytes`.
+    RecordTooLarge,
+    /// The batch is out of sequence for its idempotent producer, or does not start at the
+    /// expected offset.
+    InvalidSequence,
+    /// The batch is malformed or not allowed in the topic.
+    InvalidRecord,
+}
+
+impl ProduceFailureReason {
+    pub const ALL: [ProduceFailureReason; 5] = [
+        ProduceFailureReason::InvalidCrc,
+        ProduceFailureReason::InvalidTimestamp,
+        ProduceFailureReason::RecordTooLarge,
+        ProduceFailureReason::InvalidSequence,
+        ProduceFailureReason::InvalidRecord,
+    ];
+
+    /// The value of the `reason` tag.
+    pub fn name(self) -> &'static str {
+        match self {
+            ProduceFailureReason::InvalidCrc => "invalid-crc",
+            ProduceFailureReason::InvalidTimestamp => "invalid-timestamp",
+            ProduceFailureReason::RecordTooLarge => "record-too-large",
+            ProduceFailureReason::InvalidSequence => "invalid-sequence",
+            ProduceFailureReason::InvalidRecord => "invalid-record",
+        }
+    }
+
+    /// The reason the log rejected a batch with `error`, or `None` if the error is not the
+    /// fault of the batch, e.g. a failing disk.
+    pub fn from_log_error(error: &LogError) -> Option<Self> {
+        match error {
+            LogError::Record(RecordError::InvalidCrc { .. }) => Some(Self::InvalidCrc),
+            LogError::Record(_) | LogError::InvalidRecord(_) => Some(Self::InvalidRecord),
+            LogError::RecordTooLarge { .. } => Some(Self::RecordTooLarge),
+            LogError::UnexpectedAppendOffset { .. } => Some(Self::InvalidSequence),
+            _ => None,
+        }
+    }
+}
+
+/// The sensors of a topic, or of all topics together.
+struct TopicSensors {
+    name: String,
+    topic: Option<String>,
+    bytes_in: Arc<Sensor>,
+    bytes_out: Arc<Sensor>,
+    messages_in: Arc<Sensor>,
+    fetch_message_conversions: Arc<Sensor>,
+}
+
+/// The bytes produced to and fetched from a broker, and the messages produced to it, per
+/// topic and for all topics together.
+///
+/// The metrics of a topic are created the first time it is produced to or fetched from and
+/// removed when the topic is deleted, so a cluster creating and deleting many short-lived
+/// topics doesn't keep growing its number of metrics.
+pub struct BrokerTopicStats {
+    metrics: Metrics,
+    all_topics: TopicSensors,
+    topics: Mutex<HashMap<String, TopicSensors>>,
+}
+
+impl BrokerTopicStats {
+    pub fn new(metrics: Metrics) -> Self {
+        let all_topics = Self::sensors(&metrics, "all-topics", &[]);
+        Self {
+            metrics,
+            all_topics,
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn topics(&self) -> MutexGuard<'_, HashMap<String, TopicSensors>> {
+        self.topics.lock().expect("topic stats lock poisoned")
+    }
+
+    /// Records a produce request appending `messages` messages of `bytes` bytes to `topic`.
+    pub fn record_produce(&self, topic: &str, bytes: usize, messages: usize) {
+        self.with_topic(topic, |sensors| {
+            sensors.bytes_in.record(bytes as f64);
+            sensors.messages_in.record(messages as f64);
+        });
+    }
+
+    /// Records a fetch request returning `bytes` bytes of `topic`.
+    pub fn record_fetch(&self, topic: &str, bytes: usize) {
+        self.with_topic(topic, |sensors| sensors.bytes_out.record(bytes as f64));
+    }
+
+    /// Records the rejection of a produce batch of `records` records to `topic` for `reason`.
+    ///
+    /// The failures of a topic are counted per reason, each from the first rejection for
+    /// that reason, so that the topics which never get a bad batch have no such metrics.
+    pub fn record_produce_failure(
+        &self,
+        topic: &str,
+        reason: ProduceFailureReason,
+        records: usize,
+    ) {
+        self.with_topic(topic, |sensors| {
+            self.failed_produce_sensor(sensors, reason)
+                .record(records as f64)
+        });
+    }
+
+    fn failed_produce_sensor(
+        &self,
+        sensors: &TopicSensors,
+        reason: ProduceFailureReason,
+    ) -> Arc<Sensor> {
+        let name = format!("{}:failed-produce:{}", sensors.name, reason.name());
+        if let Some(sensor) = self.metrics.get_sensor(&name) {
+            return sensor;
+        }
+        let mut tags = vec![(REASON_TAG, reason.name())];
+        if let Some(topic) = &sensors.topic {
+            tags.push((TOPIC_TAG, topic.as_str()));
+        }
+        let sensor = self.metrics.sensor(&name);
+        sensor.add(
+            self.metrics.metric_name(
+                FAILED_PRODUCE_RECORDS_RATE,
+                BROKER_TOPIC_METRICS_GROUP,
+                "The number of records per second in produce batches rejected for the reason",
+                &tags,
+            ),
+            Rate::new(),
+        );
+        sensor.add(
+            self.metrics.metric_name(
+                FAILED_PRODUCE_RECORDS_TOTAL,
+                BROKER_TOPIC_METRICS_GROUP,
+                "The total number of records in produce batches rejected for the reason",
+                &tags,
+            ),
+            CumulativeSum::new(),
+        );
+        sensor
+    }
+
+    /// Records `messages` messages of `topic` converted to an older message format for a
+    /// fetch request.
+    pub fn record_fetch_message_conversions(&self, topic: &str, messages: usize) {
+        self.with_topic(topic, |sensors| {
+            sensors.fetch_message_conversions.record(messages as f64)
+        });
+    }
+
+    fn with_topic(&self, topic: &str, record: impl Fn(&TopicSensors)) {
+        record(&self.all_topics);
+        let mut topics = self.topics();
+        let sensors = topics.entry(topic.to_string()).or_insert_with(|| {
+            Self::sensors(
+                &self.metrics,
+                &format!("topic:{topic}"),
+                &[(TOPIC_TAG, topic)],
+            )
+        });
+        record(sensors);
+    }
+
+    /// The topics which have metrics.
+    pub fn topics_with_metrics(&self) -> Vec<String> {
+        let mut topics: Vec<_> = self.topics().keys().cloned().collect();
+        topics.sort();
+        topics
+    }
+
+    /// Removes the metrics of a deleted topic.
+    pub fn remove_metrics(&self, topic: &str) {
+        if self.topics().remove(topic).is_some() {
+            debug!("Removing the metrics of deleted topic {topic}");
+            for sensor in [
+                "bytes-in",
+                "bytes-out",
+                "messages-in",
+                "fetch-message-conversions",
+            ] {
+                self.metrics
+                    .remove_sensor(&format!("topic:{topic}:{sensor}"));
+            }
+            for reason in ProduceFailureReason::ALL {
+                self.metrics
+                    .remove_sensor(&format!("topic:{topic}:failed-produce:{}", reason.name()));
+            }
+        }
+    }
+
+    /// Removes the metrics of the topics for which `exists` is false, after the broker
+    /// applied a metadata update deleting topics.
+    pub fn remove_metrics_of_deleted_topics(&self, exists: impl Fn(&str) -> bool) {
+        let deleted: Vec<String> = self
+            .topics()
+            .keys()
+            .filter(|topic| !exists(topic))
+            .cloned()
+            .collect();
+        for topic in deleted {
+            self.remove_metrics(&topic);
+        }
+    }
+
+    fn sensors(metrics: &Metrics, name: &str, tags: &[(&str, &str)]) -> TopicSensors {
+        let sensor = |suffix: &str, rate: (&str, &str), total: (&str, &str)| {
+            let sensor = metrics.sensor(&format!("{name}:{suffix}"));
+            sensor.add(
+                metrics.metric_name(rate.0, BROKER_TOPIC_METRICS_GROUP, rate.1, tags),
+                Rate::new(),
+            );
+            sensor.add(
+                metrics.metric_name(total.0, BROKER_TOPIC_METRICS_GROUP, total.1, tags),
+                CumulativeSum::new(),
+            );
+            sensor
+        };
+        TopicSensors {
+            name: name.to_string(),
+            topic: tags
+                .iter()
+                .find(|(tag, _)| *tag == TOPIC_TAG)
+                .map(|(_, topic)| topic.to_string()),
+            bytes_in: sensor(
+                "bytes-in",
+                (BYTES_IN_RATE, "The number of bytes produced per second"),
+                (BYTES_IN_TOTAL, "The total number of bytes produced"),
+            ),
+            bytes_out: sensor(
+                "bytes-out",
+                (BYTES_OUT_RATE, "The number of bytes fetched per second"),
+                (BYTES_OUT_TOTAL, "The total number of bytes fetched"),
+            ),
+            messages_in: sensor(
+                "messages-in",
+                (
+                    MESSAGES_IN_RATE,
+                    "The number of messages produced per second",
+                ),
+                (MESSAGES_IN_TOTAL, "The total number of messages produced"),
+            ),
+            fetch_message_conversions: sensor(
+                "fetch-message-conversions",
+                (
+                    FETCH_MESSAGE_CONVERSIONS_RATE,
+                    "The number of messages converted to an older format per second for fetches",
+                ),
+                (
+                    FETCH_MESSAGE_CONVERSIONS_TOTAL,
+                    "The total number of messages converted to an older format for fetches",
+                ),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric(metrics: &Metrics, name: &str, tags: &[(&str, &str)]) -> Option<f64> {
+        metrics.metric_value(&metrics.metric_name(name, BROKER_TOPIC_METRICS_GROUP, "", tags))
+    }
+
+    #[test]
+    fn test_per_topic_and_all_topics_metrics() {
+        let metrics = Metrics::default();
+        let stats = BrokerTopicStats::new(metrics.clone());
+        stats.record_produce("foo", 100, 2);
+        stats.record_produce("bar", 50, 1);
+        stats.record_fetch("foo", 300);
+
+        assert_eq!(
+            Some(100.0),
+            metric(&metrics, BYTES_IN_TOTAL, &[(TOPIC_TAG, "foo")])
+        );
+        assert_eq!(
+            Some(300.0),
+            metric(&metrics, BYTES_OUT_TOTAL, &[(TOPIC_TAG, "foo")])
+        );
+        assert_eq!(Some(150.0), metric(&metrics, BYTES_IN_TOTAL, &[]));
+        assert_eq!(Some(3.0), metric(&metrics, MESSAGES_IN_TOTAL, &[]));
+        assert_eq!(vec!["bar", "foo"], stats.topics_with_metrics());
+    }
+
+    #[test]
+    fn test_metrics_of_deleted_topics_are_removed() {
+        let metrics = Metrics::default();
+        let stats = BrokerTopicStats::new(metrics.clone());
+        let count = || metrics.metrics().len();
+        let before = count();
+        for topic in ["foo", "bar", "baz"] {
+            stats.record_produce(topic, 10, 1);
+        }
+        assert_eq!(before + 24, count());
+
+        stats.remove_metrics_of_deleted_topics(|topic| topic == "bar");
+        assert_eq!(vec!["bar"], stats.topics_with_metrics());
+        assert_eq!(before + 8, count());
+        assert_eq!(
+            None,
+            metric(&metrics, BYTES_IN_TOTAL, &[(TOPIC_TAG, "foo")])
+        );
+        // The totals of all topics are kept.
+        assert_eq!(Some(30.0), metric(&metrics, BYTES_IN_TOTAL, &[]));
+
+        // A topic created again with the same name starts from zero.
+        stats.record_produce("foo", 5, 1);
+        assert_eq!(
+            Some(5.0),
+            metric(&metrics, BYTES_IN_TOTAL, &[(TOPIC_TAG, "foo")])
+        );
+    }
+
+    #[test]
+    fn test_produce_failures_per_topic_and_reason() {
+        let metrics = Metrics::default();
+        let stats = BrokerTopicStats::new(metrics.clone());
+        let before = metrics.metrics().len();
+        let crc_error = LogError::Record(RecordError::InvalidCrc {
+            stored: 1,
+            computed: 2,
+        });
+        let reason = ProduceFailureReason::from_log_error(&crc_error).unwrap();
+        stats.record_produce_failure("foo", reason, 3);
+        stats.record_produce_failure("foo", ProduceFailureReason::InvalidCrc, 2);
+        stats.record_produce_failure("bar", ProduceFailureReason::RecordTooLarge, 1);
+        // Rate and total of foo and all topics for invalid-crc, then of bar and all topics
+        // for record-too-large; the produce metrics of the topics are created too.
+        assert_eq!(before + 8 + 16, metrics.metrics().len());
+
+        let crc = (REASON_TAG, "invalid-crc");
+        assert_eq!(
+            Some(5.0),
+            metric(
+                &metrics,
+                FAILED_PRODUCE_RECORDS_TOTAL,
+                &[crc, (TOPIC_TAG, "foo")]
+            )
+        );
+        assert_eq!(
+            Some(5.0),
+            metric(&metrics, FAILED_PRODUCE_RECORDS_TOTAL, &[crc])
+        );
+        assert_eq!(
+            None,
+            metric(
+                &metrics,
+                FAILED_PRODUCE_RECORDS_TOTAL,
+                &[crc, (TOPIC_TAG, "bar")]
+            )
+        );
+        assert_eq!(
+            None,
+            ProduceFailureReason::from_log_error(&LogError::Io(std::io::Error::other("disk")))
+        );
+
+        stats.remove_metrics("foo");
+        assert_eq!(
+            None,
+            metric(
+                &metrics,
+                FAILED_PRODUCE_RECORDS_TOTAL,
+                &[crc, (TOPIC_TAG, "foo")]
+            )
+        );
+        assert_eq!(
+            Some(5.0),
+            metric(&metrics, FAILED_PRODUCE_RECORDS_TOTAL, &[crc])
+        );
+    }
+}
@@ -0,0 +1,532 @@
+use rafka_clients::common::metrics::Metrics;
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::requests::abstract_response::DEFAULT_THROTTLE_TIME;
+use rafka_clients::common::requests::get_telemetry_subscriptions_request::{
+    GetTelemetrySubscriptionsRequest, GetTelemetrySubscriptionsResponse,
+};
+use rafka_clients::common::requests::push_telemetry_request::{
+    COMPRESSION_TYPE_NONE, OTLP_CONTENT_TYPE, PushTelemetryRequest, PushTelemetryResponse,
+};
+use rafka_clients::common::uuid::Uuid;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{debug, info};
+
+pub const DEFAULT_PUSH_INTERVAL_MS: i32 = 5 * 60 * 1000;
+pub const DEFAULT_TELEMETRY_MAX_BYTES: i32 = 1024 * 1024;
+
+/// The selectors a subscription may match clients on.
+pub const CLIENT_INSTANCE_ID: &str = "client_instance_id";
+pub const CLIENT_ID: &str = "client_id";
+pub const CLIENT_SOFTWARE_NAME: &str = "client_software_name";
+pub const CLIENT_SOFTWARE_VERSION: &str = "client_software_version";
+pub const CLIENT_SOURCE_ADDRESS: &str = "client_source_address";
+pub const CLIENT_SOURCE_PORT: &str = "client_source_port";
+
+pub const CLIENT_METRICS_GROUP: &str = "client-metrics";
+
+/// The client sending a telemetry request, as known from the connection and request header.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClientMetadata {
+    pub client_id: String,
+    pub client_software_name: String,
+    pub client_software_version: String,
+    pub client_source_address: String,
+    pub client_source_port: String,
+}
+
+impl ClientMetadata {
+    fn get(&self, selector: &str, client_instance_id: &Uuid) -> Option<String> {
+        match selector {
+            CLIENT_INSTANCE_ID => Some(client_instance_id.to_string()),
+            CLIENT_ID => Some(self.client_id.clone()),
+            CLIENT_SOFTWARE_NAME => Some(self.client_software_name.clone()),
+            CLIENT_SOFTWARE_VERSION => Some(self.client_software_version.clone()),
+            CLIENT_SOURCE_ADDRESS => Some(self.client_source_address.clone()),
+            CLIENT_SOURCE_PORT => Some(self.client_source_port.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// A client metrics config resource: which metrics the clients it matches must push, and
+/// how often.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientMetricsSubscription {
+    /// The prefixes of the requested metric names, an empty string requesting all metrics.
+    pub metrics: Vec<String>,
+    pub interval_ms: i32,
+    /// The values clients must have for the selectors, e.g. `client_software_name`. Values
+    /// ending with `*` match by prefix. An empty match selects all clients.
+    pub match_selectors: BTreeMap<String, String>,
+}
+
+impl ClientMetricsSubscription {
+    fn matches(&self, client: &ClientMetadata, client_instance_id: &Uuid) -> bool {
+        self.match_selectors.iter().all(|(selector, pattern)| {
+            client
+                .get(selector, client_instance_id)
+                .is_some_and(|value| match pattern.strip_suffix('*') {
+                    Some(prefix) => value.starts_with(prefix),
+                    None => value == *pattern,
+                })
+        })
+    }
+}
+
+/// The metrics a client pushed, as handed to a [ClientTelemetryReceiver].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientTelemetryPayload {
+    pub client_instance_id: Uuid,
+    pub is_terminating: bool,
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// Receives the metrics pushed by clients, e.g. to forward them to a monitoring system.
+pub trait ClientTelemetryReceiver: Send + Sync {
+    fn export_metrics(&self, client: &ClientMetadata, payload: &ClientTelemetryPayload);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientMetricsManagerConfig {
+    pub telemetry_max_bytes: i32,
+}
+
+impl Default for ClientMetricsManagerConfig {
+    fn default() -> Self {
+        Self {
+            telemetry_max_bytes: DEFAULT_TELEMETRY_MAX_BYTES,
+        }
+    }
+}
+
+/// What a client instance was subscribed to, and when it last talked to the broker.
+#[derive(Debug, Clone)]
+struct ClientInstance {
+    subscriptions_version: u64,
+    subscription_id: i32,
+    requested_metrics: Vec<String>,
+    push_interval_ms: i32,
+    last_get_ms: Option<i64>,
+    last_push_ms: Option<i64>,
+    last_error: Errors,
+    terminating: bool,
+}
+
+impl ClientInstance {
+    fn last_request_ms(&self) -> i64 {
+        self.last_get_ms
+            .into_iter()
+            .chain(self.last_push_ms)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+struct State {
+    subscriptions: BTreeMap<String, ClientMetricsSubscription>,
+    subscriptions_version: u64,
+    instances: HashMap<Uuid, ClientInstance>,
+}
+
+/// The broker side of client telemetry (KIP-714).
+///
+/// Clients ask with GetTelemetrySubscriptions which of their metrics to push: the union of
+/// the metrics of the subscriptions matching them, at the shortest of their intervals. They
+/// then push those metrics with PushTelemetry at that interval, which the broker validates,
+/// rate-limits and hands to the [ClientTelemetryReceiver]. A change of the subscriptions
+/// changes the subscription id, so clients pushing for the old one are told to ask again.
+pub struct ClientMetricsManager {
+    config: ClientMetricsManagerConfig,
+    receiver: Option<Arc<dyn ClientTelemetryReceiver>>,
+    state: Mutex<State>,
+    instance_count: Arc<AtomicI64>,
+}
+
+impl ClientMetricsManager {
+    pub fn new(
+        config: ClientMetricsManagerConfig,
+        receiver: Option<Arc<dyn ClientTelemetryReceiver>>,
+        metrics: &Metrics,
+    ) -> Self {
+        let instance_count = Arc::new(AtomicI64::new(0));
+        let gauge = Arc::clone(&instance_count);
+        metrics.add_gauge(
+            metrics.metric_name(
+                "instance-count",
+                CLIENT_METRICS_GROUP,
+                "The number of client instances the broker tracks telemetry for",
+                &[],
+            ),
+            move |_| gauge.load(Ordering::Relaxed) as f64,
+        );
+        Self {
+            config,
+            receiver,
+            state: Mutex::new(State {
+                subscriptions: BTreeMap::new(),
+                subscriptions_version: 0,
+                instances: HashMap::new(),
+            }),
+            instance_count,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("client metrics lock poisoned")
+    }
+
+    /// Sets the subscription `name`, or removes it if `subscription` is `None`.
+    pub fn update_subscription(&self, name: &str, subscription: Option<ClientMetricsSubscription>) {
+        let mut state = self.state();
+        match subscription {
+            Some(subscription) => {
+                info!("Updating the client metrics subscription {name} to {subscription:?}");
+                state.subscriptions.insert(name.to_string(), subscription);
+            }
+            None => {
+                info!("Removing the client metrics subscription {name}");
+                state.subscriptions.remove(name);
+            }
+        }
+        state.subscriptions_version += 1;
+    }
+
+    pub fn process_get_telemetry_subscriptions(
+        &self,
+        request: &GetTelemetrySubscriptionsRequest,
+        client: &ClientMetadata,
+        now_ms: i64,
+    ) -> GetTelemetrySubscriptionsResponse {
+        let mut state = self.state();
+        let client_instance_id = if request.client_instance_id == Uuid::ZERO {
+            Uuid::random()
+        } else {
+            request.client_instance_id
+        };
+        let version = state.subscriptions_version;
+        let instance = match state.instances.get(&client_instance_id) {
+            Some(instance) if instance.subscriptions_version == version => instance.clone(),
+            previous => {
+                let mut instance = Self::subscribe(&state, client, &client_instance_id);
+                if let Some(previous) = previous {
+                    instance.last_get_ms = previous.last_get_ms;
+                    instance.last_push_ms = previous.last_push_ms;
+                    instance.last_error = previous.last_error;
+                }
+                instance
+            }
+        };
+
+        // A client may ask again within its push interval only to recover from an error.
+        let error = match instance.last_get_ms {
+            Some(last_get_ms)
+                if now_ms - last_get_ms < instance.push_interval_ms as i64
+                    && instance.last_error == Errors::None =>
+            {
+                Errors::ThrottlingQuotaExceeded
+            }
+            _ => Errors::None,
+        };
+        let response = GetTelemetrySubscriptionsResponse {
+            throttle_time_ms: DEFAULT_THROTTLE_TIME,
+            error_code: error,
+            client_instance_id,
+            subscription_id: instance.subscription_id,
+            accepted_compression_types: vec![COMPRESSION_TYPE_NONE],
+            push_interval_ms: instance.push_interval_ms,
+            telemetry_max_bytes: self.config.telemetry_max_bytes,
+            delta_temporality: true,
+            requested_metrics: instance.requested_metrics.clone(),
+        };
+        if error == Errors::None {
+            let instance = ClientInstance {
+                last_get_ms: Some(now_ms),
+                last_error: Errors::None,
+                ..instance
+            };
+            state.instances.insert(client_instance_id, instance);
+            self.instance_count
+                .store(state.instances.len() as i64, Ordering::Relaxed);
+        }
+        response
+    }
+
+    pub fn process_push_telemetry(
+        &self,
+        request: &PushTelemetryRequest,
+        client: &ClientMetadata,
+        now_ms: i64,
+    ) -> PushTelemetryResponse {
+        let error = self.validate_and_export(request, client, now_ms);
+        if error != Errors::None {
+            debug!(
+                "Rejected the telemetry push of client instance {}: {error:?}",
+                request.client_instance_id
+            );
+        }
+        PushTelemetryResponse {
+            throttle_time_ms: DEFAULT_THROTTLE_TIME,
+            error_code: error,
+        }
+    }
+
+    fn validate_and_export(
+        &self,
+        request: &PushTelemetryRequest,
+        client: &ClientMetadata,
+        now_ms: i64,
+    ) -> Errors {
+        if request.client_instance_id == Uuid::ZERO {
+            return Errors::InvalidRequest;
+        }
+        let mut state = self.state();
+        let version = state.subscriptions_version;
+        let Some(instance) = state.instances.get_mut(&request.client_instance_id) else {
+            return Errors::UnknownSubscriptionId;
+        };
+        let error = if instance.terminating {
+            Errors::InvalidRequest
+        } else if instance.subscriptions_version != version
+            || instance.subscription_id != request.subscription_id
+        {
+            Errors::UnknownSubscriptionId
+        } else if request.compression_type != COMPRESSION_TYPE_NONE {
+            Errors::UnsupportedCompressionType
+        } else if !request.terminating
+            && instance
+                .last_push_ms
+                .is_some_and(|last| now_ms - last < instance.push_interval_ms as i64)
+        {
+            Errors::ThrottlingQuotaExceeded
+        } else if request.metrics.len() > self.config.telemetry_max_bytes as usize {
+            Errors::TelemetryTooLarge
+        } else {
+            Errors::None
+        };
+        instance.last_error = error;
+        if error != Errors::None {
+            return error;
+        }
+        instance.last_push_ms = Some(now_ms);
+        instance.terminating = request.terminating;
+        let requested = !instance.requested_metrics.is_empty();
+        drop(state);
+
+        if requested
+            && !request.metrics.is_empty()
+            && let Some(receiver) = &self.receiver
+        {
+            receiver.export_metrics(
+                client,
+                &ClientTelemetryPayload {
+                    client_instance_id: request.client_instance_id,
+                    is_terminating: request.terminating,
+                    content_type: OTLP_CONTENT_TYPE,
+                    data: request.metrics.clone(),
+                },
+            );
+        }
+        Errors::None
+    }
+
+    /// Forgets the client instances which neither asked for their subscription nor pushed
+    /// for three push intervals, or which terminated.
+    pub fn expire_instances(&self, now_ms: i64) {
+        let mut state = self.state();
+        state.instances.retain(|id, instance| {
+            let expired = instance.terminating
+                || now_ms - instance.last_request_ms() > 3 * instance.push_interval_ms as i64;
+            if expired {
+                debug!("Expiring the telemetry state of client instance {id}");
+            }
+            !expired
+        });
+        self.instance_count
+            .store(state.instances.len() as i64, Ordering::Relaxed);
+    }
+
+    fn subscribe(
+        state: &State,
+        client: &ClientMetadata,
+        client_instance_id: &Uuid,
+    ) -> ClientInstance {
+        let matching: Vec<&ClientMetricsSubscription> = state
+            .subscriptions
+            .values()
+            .filter(|subscription| subscription.matches(client, client_instance_id))
+            .collect();
+        let requested_metrics: BTreeSet<String> = matching
+            .iter()
+            .flat_map(|subscription| subscription.metrics.iter().cloned())
+            .collect();
+        // An empty prefix requests all metrics, which makes the others redundant.
+        let requested_metrics: Vec<String> = if requested_metrics.contains("") {
+            vec![String::new()]
+        } else {
+            requested_metrics.into_iter().collect()
+        };
+        let push_interval_ms = matching
+            .iter()
+            .map(|subscription| subscription.interval_ms)
+            .filter(|interval_ms| *interval_ms > 0)
+            .min()
+            .unwrap_or(DEFAULT_PUSH_INTERVAL_MS);
+        if matching.is_empty() {
+            debug!("No client metrics subscription matches client instance {client_instance_id}");
+        }
+
+        let mut hasher = DefaultHasher::new();
+        requested_metrics.hash(&mut hasher);
+        push_interval_ms.hash(&mut hasher);
+        state.subscriptions_version.hash(&mut hasher);
+        ClientInstance {
+            subscriptions_version: state.subscriptions_version,
+            subscription_id: hasher.finish() as i32,
+            requested_metrics,
+            push_interval_ms,
+            last_get_ms: None,
+            last_push_ms: None,
+            last_error: Errors::None,
+            terminating: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MockReceiver {
+        payloads: Mutex<Vec<ClientTelemetryPayload>>,
+    }
+
+    impl ClientTelemetryReceiver for MockReceiver {
+        fn export_metrics(&self, _client: &ClientMetadata, payload: &ClientTelemetryPayload) {
+            self.payloads.lock().unwrap().push(payload.clone());
+        }
+    }
+
+    fn client() -> ClientMetadata {
+        ClientMetadata {
+            client_id: "app".to_string(),
+            client_software_name: "apache-kafka-java".to_string(),
+            client_software_version: "3.7.0".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn manager() -> (ClientMetricsManager, Arc<MockReceiver>) {
+        let receiver = Arc::new(MockReceiver::default());
+        let manager = ClientMetricsManager::new(
+            ClientMetricsManagerConfig {
+                telemetry_max_bytes: 16,
+            },
+            Some(receiver.clone()),
+            &Metrics::default(),
+        );
+        manager.update_subscription(
+            "java",
+            Some(ClientMetricsSubscription {
+                metrics: vec!["org.apache.kafka.producer.".to_string()],
+                interval_ms: 1_000,
+                match_selectors: BTreeMap::from([(
+                    CLIENT_SOFTWARE_NAME.to_string(),
+                    "apache-kafka-*".to_string(),
+                )]),
+            }),
+        );
+        (manager, receiver)
+    }
+
+    fn push(
+        subscription: &GetTelemetrySubscriptionsResponse,
+        metrics: &[u8],
+    ) -> PushTelemetryRequest {
+        PushTelemetryRequest {
+            client_instance_id: subscription.client_instance_id,
+            subscription_id: subscription.subscription_id,
+            terminating: false,
+            compression_type: COMPRESSION_TYPE_NONE,
+            metrics: metrics.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_subscribe_and_push() {
+        let (manager, receiver) = manager();
+        let subscription = manager.process_get_telemetry_subscriptions(
+            &GetTelemetrySubscriptionsRequest {
+                client_instance_id: Uuid::ZERO,
+            },
+            &client(),
+            0,
+        );
+        assert_eq!(Errors::None, subscription.error_code);
+        assert_ne!(Uuid::ZERO, subscription.client_instance_id);
+        assert_eq!(1_000, subscription.push_interval_ms);
+        assert_eq!(
+            vec!["org.apache.kafka.producer.".to_string()],
+            subscription.requested_metrics
+        );
+
+        let response = manager.process_push_telemetry(&push(&subscription, b"otlp"), &client(), 0);
+        assert_eq!(Errors::None, response.error_code);
+        assert_eq!(b"otlp".to_vec(), receiver.payloads.lock().unwrap()[0].data);
+
+        // Pushing again within the interval is throttled, and so is a payload too large.
+        let response =
+            manager.process_push_telemetry(&push(&subscription, b"otlp"), &client(), 500);
+        assert_eq!(Errors::ThrottlingQuotaExceeded, response.error_code);
+        let response =
+            manager.process_push_telemetry(&push(&subscription, &[0; 17]), &client(), 1_000);
+        assert_eq!(Errors::TelemetryTooLarge, response.error_code);
+
+        let mut terminating = push(&subscription, b"last");
+        terminating.terminating = true;
+        let response = manager.process_push_telemetry(&terminating, &client(), 1_100);
+        assert_eq!(Errors::None, response.error_code);
+        assert!(receiver.payloads.lock().unwrap()[1].is_terminating);
+        manager.expire_instances(1_100);
+        let response =
+            manager.process_push_telemetry(&push(&subscription, b"otlp"), &client(), 5_000);
+        assert_eq!(Errors::UnknownSubscriptionId, response.error_code);
+    }
+
+    #[test]
+    fn test_subscription_change_requires_new_subscription() {
+        let (manager, receiver) = manager();
+        let request = |id| GetTelemetrySubscriptionsRequest {
+            client_instance_id: id,
+        };
+        let subscription =
+            manager.process_get_telemetry_subscriptions(&request(Uuid::ZERO), &client(), 0);
+        let id = subscription.client_instance_id;
+        let throttled = manager.process_get_telemetry_subscriptions(&request(id), &client(), 100);
+        assert_eq!(Errors::ThrottlingQuotaExceeded, throttled.error_code);
+
+        manager.update_subscription("java", None);
+        let response =
+            manager.process_push_telemetry(&push(&subscription, b"otlp"), &client(), 100);
+        assert_eq!(Errors::UnknownSubscriptionId, response.error_code);
+
+        // After an error, the client may ask again right away.
+        let resubscribed =
+            manager.process_get_telemetry_subscriptions(&request(id), &client(), 200);
+        assert_eq!(Errors::None, resubscribed.error_code);
+        assert_ne!(subscription.subscription_id, resubscribed.subscription_id);
+        assert!(resubscribed.requested_metrics.is_empty());
+        assert_eq!(DEFAULT_PUSH_INTERVAL_MS, resubscribed.push_interval_ms);
+
+        // Nothing was requested, the pushed metrics are not exported.
+        let response =
+            manager.process_push_telemetry(&push(&resubscribed, b"otlp"), &client(), 300);
+        assert_eq!(Errors::None, response.error_code);
+        assert!(receiver.payloads.lock().unwrap().is_empty());
+    }
+}
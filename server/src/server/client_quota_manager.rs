@@ -0,0 +1,421 @@
+use rafka_clients::common::metrics::stats::Rate;
+use rafka_clients::common::metrics::{MetricConfig, MetricName, Metrics, Sensor};
+use rafka_server_common::quota_config::{
+    CONSUMER_BYTE_RATE_OVERRIDE_CONFIG, CONTROLLER_MUTATION_RATE_OVERRIDE_CONFIG,
+    PRODUCER_BYTE_RATE_OVERRIDE_CONFIG, REQUEST_PERCENTAGE_OVERRIDE_CONFIG,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{debug, info};
+
+pub const DEFAULT_NUM_QUOTA_SAMPLES: usize = 11;
+pub const DEFAULT_QUOTA_WINDOW_SIZE_SECONDS: i64 = 1;
+
+/// The tag of quota metrics holding the user principal.
+pub const USER_TAG: &str = "user";
+/// The tag of quota metrics holding the client id.
+pub const CLIENT_ID_TAG: &str = "client-id";
+
+/// The kind of client traffic a quota bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientQuotaType {
+    /// Bytes fetched per second.
+    Fetch,
+    /// Bytes produced per second.
+    Produce,
+    /// Percentage of the request handler and network thread time.
+    Request,
+    /// Partitions created or deleted per second.
+    ControllerMutation,
+}
+
+impl ClientQuotaType {
+    /// The client quota config key holding quotas of this type.
+    pub fn config_key(&self) -> &'static str {
+        match self {
+            ClientQuotaType::Fetch => CONSUMER_BYTE_RATE_OVERRIDE_CONFIG,
+            ClientQuotaType::Produce => PRODUCER_BYTE_RATE_OVERRIDE_CONFIG,
+            ClientQuotaType::Request => REQUEST_PERCENTAGE_OVERRIDE_CONFIG,
+            ClientQuotaType::ControllerMutation => CONTROLLER_MUTATION_RATE_OVERRIDE_CONFIG,
+        }
+    }
+}
+
+impl fmt::Display for ClientQuotaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientQuotaType::Fetch => write!(f, "Fetch"),
+            ClientQuotaType::Produce => write!(f, "Produce"),
+            ClientQuotaType::Request => write!(f, "Request"),
+            ClientQuotaType::ControllerMutation => write!(f, "ControllerMutation"),
+        }
+    }
+}
+
+/// A user or client id a quota is configured for, either by name or as the default of all
+/// users or client ids.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConfigEntity {
+    Name(String),
+    Default,
+}
+
+impl fmt::Display for ConfigEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigEntity::Name(name) => write!(f, "{name}"),
+            ConfigEntity::Default => write!(f, "<default>"),
+        }
+    }
+}
+
+/// The entity a quota is configured for: a user, a client id, or a client id of a user.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientQuotaEntity {
+    pub user: Option<ConfigEntity>,
+    pub client_id: Option<ConfigEntity>,
+}
+
+impl ClientQuotaEntity {
+    pub fn new(user: Option<ConfigEntity>, client_id: Option<ConfigEntity>) -> Self {
+        Self { user, client_id }
+    }
+
+    /// The entities a quota of `user` and `client_id` may be configured for, from the most to
+    /// the least specific:
+    ///
+    /// 1. `/config/users/<user>/clients/<client-id>`
+    /// 2. `/config/users/<user>/clients/<default>`
+    /// 3. `/config/users/<user>`
+    /// 4. `/config/users/<default>/clients/<client-id>`
+    /// 5. `/config/users/<default>/clients/<default>`
+    /// 6. `/config/users/<default>`
+    /// 7. `/config/clients/<client-id>`
+    /// 8. `/config/clients/<default>`
+    pub fn precedence(user: &str, client_id: &str) -> [ClientQuotaEntity; 8] {
+        let user = || Some(ConfigEntity::Name(user.to_string()));
+        let client_id = || Some(ConfigEntity::Name(client_id.to_string()));
+        let default = || Some(ConfigEntity::Default);
+        [
+            Self::new(user(), client_id()),
+            Self::new(user(), default()),
+            Self::new(user(), None),
+            Self::new(default(), client_id()),
+            Self::new(default(), default()),
+            Self::new(default(), None),
+            Self::new(None, client_id()),
+            Self::new(None, default()),
+        ]
+    }
+}
+
+impl fmt::Display for ClientQuotaEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.user, &self.client_id) {
+            (Some(user), Some(client_id)) => write!(f, "user {user}, client-id {client_id}"),
+            (Some(user), None) => write!(f, "user {user}"),
+            (None, Some(client_id)) => write!(f, "client-id {client_id}"),
+            (None, None) => write!(f, "no entity"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientQuotaManagerConfig {
+    pub num_quota_samples: usize,
+    pub quota_window_size_seconds: i64,
+}
+
+impl Default for ClientQuotaManagerConfig {
+    fn default() -> Self {
+        Self {
+            num_quota_samples: DEFAULT_NUM_QUOTA_SAMPLES,
+            quota_window_size_seconds: DEFAULT_QUOTA_WINDOW_SIZE_SECONDS,
+        }
+    }
+}
+
+/// The user and client id tags the traffic of a client is tracked under. Clients sharing a
+/// quota share the tags, so that a quota configured for a user bounds all its clients together.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QuotaMetricTags {
+    pub user: String,
+    pub client_id: String,
+}
+
+/// Tracks the traffic of clients against the quota of one [ClientQuotaType] and computes how
+/// long a client exceeding its quota must be throttled for.
+///
+/// The quota of a client is the one configured for the most specific entity matching its user
+/// and client id, see [ClientQuotaEntity::precedence]. Clients without a quota are unlimited.
+pub struct ClientQuotaManager {
+    config: ClientQuotaManagerConfig,
+    quota_type: ClientQuotaType,
+    metrics: Metrics,
+    quotas: Mutex<HashMap<ClientQuotaEntity, f64>>,
+}
+
+impl ClientQuotaManager {
+    pub fn new(
+        config: ClientQuotaManagerConfig,
+        metrics: Metrics,
+        quota_type: ClientQuotaType,
+    ) -> Self {
+        Self {
+            config,
+            quota_type,
+            metrics,
+            quotas: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn quotas(&self) -> MutexGuard<'_, HashMap<ClientQuotaEntity, f64>> {
+        self.quotas.lock().expect("quotas lock poisoned")
+    }
+
+    pub fn quota_type(&self) -> ClientQuotaType {
+        self.quota_type
+    }
+
+    /// Sets the quota of `entity`, or removes it if `quota` is `None`.
+    pub fn update_quota(&self, entity: ClientQuotaEntity, quota: Option<f64>) {
+        match quota {
+            Some(quota) => {
+                info!(
+                    "Changing the {} quota of {entity} to {quota}",
+                    self.quota_type
+                );
+                self.quotas().insert(entity, quota);
+            }
+            None => {
+                info!("Removing the {} quota of {entity}", self.quota_type);
+                self.quotas().remove(&entity);
+            }
+        }
+    }
+
+    /// The entity whose quota applies to `user` and `client_id`, with the quota, or `None` if
+    /// the client is unlimited.
+    pub fn resolve_quota(&self, user: &str, client_id: &str) -> Option<(ClientQuotaEntity, f64)> {
+        let quotas = self.quotas();
+        ClientQuotaEntity::precedence(user, client_id)
+            .into_iter()
+            .find_map(|entity| quotas.get(&entity).map(|quota| (entity, *quota)))
+    }
+
+    /// The tags the traffic of `user` and `client_id` is tracked under: a tag is left empty
+    /// when the applicable quota is not specific to it.
+    pub fn quota_metric_tags(&self, user: &str, client_id: &str) -> QuotaMetricTags {
+        let (user, client_id) = match self.resolve_quota(user, client_id) {
+            Some((entity, _)) => match (entity.user, entity.client_id) {
+                (Some(_), Some(_)) => (user, client_id),
+                (Some(_), None) => (user, ""),
+                (None, _) => ("", client_id),
+            },
+            None => ("", client_id),
+        };
+        QuotaMetricTags {
+            user: user.to_string(),
+            client_id: client_id.to_string(),
+        }
+    }
+
+    /// Records `value` for the client and returns how long it must be throttled for, or 0 if
+    /// it is within its quota.
+    pub fn record_and_get_throttle_time_ms(
+        &self,
+        user: &str,
+        client_id: &str,
+        value: f64,
+        now_ms: i64,
+    ) -> i64 {
+        let tags = self.quota_metric_tags(user, client_id);
+        let (sensor, rate_metric_name) = self.sensor(&tags);
+        sensor.record_at(value, now_ms);
+        let Some((entity, quota)) = self.resolve_quota(user, client_id) else {
+            return 0;
+        };
+        let Some(metric) = self.metrics.metric(&rate_metric_name) else {
+            return 0;
+        };
+        let rate = metric.metric_value(now_ms);
+        if rate <= quota {
+            return 0;
+        }
+        let window_size_ms = metric.window_size_ms(now_ms).unwrap_or(0);
+        let throttle_time_ms = throttle_time_ms(rate, quota, window_size_ms)
+            .min(self.config.quota_window_size_seconds * 1000);
+        debug!(
+            "{} quota of {entity} violated with rate {rate}, throttling for {throttle_time_ms}ms",
+            self.quota_type
+        );
+        throttle_time_ms
+    }
+
+    fn sensor(&self, tags: &QuotaMetricTags) -> (Arc<Sensor>, MetricName) {
+        let rate_metric_name = self.metrics.metric_name(
+            "byte-rate",
+            &self.quota_type.to_string(),
+            "Tracking the rate of the quota",
+            &[(USER_TAG, &tags.user), (CLIENT_ID_TAG, &tags.client_id)],
+        );
+        let name = format!("{}-{}:{}", self.quota_type, tags.user, tags.client_id);
+        if let Some(sensor) = self.metrics.get_sensor(&name) {
+            return (sensor, rate_metric_name);
+        }
+        let sensor = self.metrics.sensor_with_config(
+            &name,
+            MetricConfig::default()
+                .with_samples(self.config.num_quota_samples)
+                .with_time_window_ms(self.config.quota_window_size_seconds * 1000),
+        );
+        sensor.add(rate_metric_name.clone(), Rate::new());
+        (sensor, rate_metric_name)
+    }
+}
+
+/// How long traffic at `rate` must pause for the rate over `window_size_ms` to drop back to
+/// `quota`.
+pub(crate) fn throttle_time_ms(rate: f64, quota: f64, window_size_ms: i64) -> i64 {
+    ((rate - quota) / quota * window_size_ms as f64).round() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::utils::time::MockTime;
+
+    fn manager() -> ClientQuotaManager {
+        let metrics = Metrics::new(
+            MetricConfig::default(),
+            Arc::new(MockTime::with_start(0, 0, 0)),
+        );
+        ClientQuotaManager::new(
+            ClientQuotaManagerConfig::default(),
+            metrics,
+            ClientQuotaType::Produce,
+        )
+    }
+
+    fn name(name: &str) -> Option<ConfigEntity> {
+        Some(ConfigEntity::Name(name.to_string()))
+    }
+
+    const DEFAULT: Option<ConfigEntity> = Some(ConfigEntity::Default);
+
+    #[test]
+    fn test_quota_precedence() {
+        let quotas = manager();
+        assert_eq!(None, quotas.resolve_quota("alice", "app"));
+
+        // Configure the entities from the least to the most specific, checking each one takes
+        // over from the previous.
+        let entities = [
+            (ClientQuotaEntity::new(None, DEFAULT), ("", "app")),
+            (ClientQuotaEntity::new(None, name("app")), ("", "app")),
+            (ClientQuotaEntity::new(DEFAULT, None), ("alice", "")),
+            (ClientQuotaEntity::new(DEFAULT, DEFAULT), ("alice", "app")),
+            (
+                ClientQuotaEntity::new(DEFAULT, name("app")),
+                ("alice", "app"),
+            ),
+            (ClientQuotaEntity::new(name("alice"), None), ("alice", "")),
+            (
+                ClientQuotaEntity::new(name("alice"), DEFAULT),
+                ("alice", "app"),
+            ),
+            (
+                ClientQuotaEntity::new(name("alice"), name("app")),
+                ("alice", "app"),
+            ),
+        ];
+        for (i, (entity, (user_tag, client_id_tag))) in entities.iter().enumerate() {
+            quotas.update_quota(entity.clone(), Some(i as f64 + 1.0));
+            assert_eq!(
+                Some((entity.clone(), i as f64 + 1.0)),
+                quotas.resolve_quota("alice", "app")
+            );
+            let tags = quotas.quota_metric_tags("alice", "app");
+            assert_eq!(
+                (*user_tag, *client_id_tag),
+                (tags.user.as_str(), tags.client_id.as_str())
+            );
+        }
+
+        // Other users and clients fall back to the defaults.
+        assert_eq!(
+            Some((ClientQuotaEntity::new(name("alice"), DEFAULT), 7.0)),
+            quotas.resolve_quota("alice", "other")
+        );
+        assert_eq!(
+            Some((ClientQuotaEntity::new(DEFAULT, name("app")), 5.0)),
+            quotas.resolve_quota("bob", "app")
+        );
+        assert_eq!(
+            Some((ClientQuotaEntity::new(DEFAULT, DEFAULT), 4.0)),
+            quotas.resolve_quota("bob", "other")
+        );
+
+        quotas.update_quota(ClientQuotaEntity::new(name("alice"), name("app")), None);
+        assert_eq!(
+            Some((ClientQuotaEntity::new(name("alice"), DEFAULT), 7.0)),
+            quotas.resolve_quota("alice", "app")
+        );
+    }
+
+    #[test]
+    fn test_client_id_quotas_without_user_quotas() {
+        let quotas = manager();
+        quotas.update_quota(ClientQuotaEntity::new(None, DEFAULT), Some(10.0));
+        quotas.update_quota(ClientQuotaEntity::new(None, name("app")), Some(20.0));
+        assert_eq!(
+            Some((ClientQuotaEntity::new(None, name("app")), 20.0)),
+            quotas.resolve_quota("alice", "app")
+        );
+        assert_eq!(
+            Some((ClientQuotaEntity::new(None, DEFAULT), 10.0)),
+            quotas.resolve_quota("alice", "other")
+        );
+    }
+
+    #[test]
+    fn test_throttle_time() {
+        let quotas = manager();
+        assert_eq!(
+            0,
+            quotas.record_and_get_throttle_time_ms("alice", "app", 1e6, 0)
+        );
+
+        quotas.update_quota(ClientQuotaEntity::new(name("alice"), None), Some(100.0));
+        // Clients of the same user share the quota. The window is padded to 10 seconds.
+        assert_eq!(
+            0,
+            quotas.record_and_get_throttle_time_ms("alice", "app", 500.0, 0)
+        );
+        assert_eq!(
+            0,
+            quotas.record_and_get_throttle_time_ms("alice", "other", 500.0, 0)
+        );
+        // 1100 bytes over 10 seconds exceed the quota by 10%, which takes 1 second to pay back.
+        assert_eq!(
+            1_000,
+            quotas.record_and_get_throttle_time_ms("alice", "app", 100.0, 0)
+        );
+        // Throttling is bounded by the quota window.
+        assert_eq!(
+            1_000,
+            quotas.record_and_get_throttle_time_ms("alice", "app", 10_000.0, 0)
+        );
+        assert_eq!(
+            0,
+            quotas.record_and_get_throttle_time_ms("bob", "app", 10_000.0, 0)
+        );
+    }
+
+    #[test]
+    fn test_throttle_time_formula() {
+        assert_eq!(500, throttle_time_ms(150.0, 100.0, 1_000));
+        assert_eq!(0, throttle_time_ms(100.0, 100.0, 1_000));
+    }
+}
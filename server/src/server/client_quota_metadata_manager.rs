@@ -0,0 +1,262 @@
+use crate::network::connection_quotas::ConnectionQuotas;
+use crate::server::client_quota_manager::{ClientQuotaEntity, ClientQuotaManager, ConfigEntity};
+use rafka_clients::common::quota::client_quota_entity::{CLIENT_ID, IP, USER};
+use rafka_metadata::metadata_publisher::{MetadataSubscriber, MetadataUpdate};
+use rafka_metadata::metadata_record::MetadataRecord;
+use rafka_metadata::records::{ClientQuotaRecord, EntityData};
+use rafka_server_common::quota_config::IP_CONNECTION_RATE_OVERRIDE_CONFIG;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Applies the client quotas replicated through the metadata log to the quota managers of the
+/// broker: the quotas of IP addresses to the [ConnectionQuotas], the quotas of users and client
+/// ids to the [ClientQuotaManager] of the quota key.
+///
+/// As a [MetadataSubscriber], the quotas are applied as they change. The quotas set so far are
+/// remembered, so that the quotas a snapshot no longer has are removed.
+pub struct ClientQuotaMetadataManager {
+    quota_managers: Vec<Arc<ClientQuotaManager>>,
+    connection_quotas: Arc<ConnectionQuotas>,
+    /// The entities and keys of the quotas set.
+    quotas: Mutex<HashSet<(Vec<EntityData>, String)>>,
+}
+
+impl ClientQuotaMetadataManager {
+    pub fn new(
+        quota_managers: Vec<Arc<ClientQuotaManager>>,
+        connection_quotas: Arc<ConnectionQuotas>,
+    ) -> Self {
+        Self {
+            quota_managers,
+            connection_quotas,
+            quotas: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn replay(&self, record: &ClientQuotaRecord) {
+        {
+            let mut quotas = self.quotas.lock().expect("quotas lock poisoned");
+            let quota = (record.entity.clone(), record.key.clone());
+            if record.remove {
+                quotas.remove(&quota);
+            } else {
+                quotas.insert(quota);
+            }
+        }
+        let value = (!record.remove).then_some(record.value);
+        let entity_name = |entity_type: &str| {
+            record
+                .entity
+                .iter()
+                .find(|entity| entity.entity_type == entity_type)
+                .map(|entity| match &entity.entity_name {
+                    Some(name) => ConfigEntity::Name(name.clone()),
+                    None => ConfigEntity::Default,
+                })
+        };
+
+        if let Some(ip) = entity_name(IP) {
+            self.replay_ip_quota(ip, &record.key, value);
+            return;
+        }
+        let entity = ClientQuotaEntity::new(entity_name(USER), entity_name(CLIENT_ID));
+        match self
+            .quota_managers
+            .iter()
+            .find(|manager| manager.quota_type().config_key() == record.key)
+        {
+            Some(manager) => manager.update_quota(entity, value),
+            None => warn!("Ignoring unknown quota {} of {entity}", record.key),
+        }
+    }
+
+    fn replay_ip_quota(&self, ip: ConfigEntity, key: &str, value: Option<f64>) {
+        if key != IP_CONNECTION_RATE_OVERRIDE_CONFIG {
+            warn!("Ignoring unknown quota {key} of IP {ip}");
+            return;
+        }
+        let ip = match ip {
+            ConfigEntity::Name(name) => match name.parse::<IpAddr>() {
+                Ok(ip) => Some(ip),
+                Err(_) => {
+                    warn!("Ignoring the connection rate quota of invalid IP address {name}");
+                    return;
+                }
+            },
+            ConfigEntity::Default => None,
+        };
+        self.connection_quotas
+            .update_ip_connection_rate_quota(ip, value.map(|value| value as i32));
+    }
+}
+
+impl MetadataSubscriber for ClientQuotaMetadataManager {
+    fn name(&self) -> &str {
+        "ClientQuotaMetadataManager"
+    }
+
+    fn on_update(&self, update: &MetadataUpdate<'_>) {
+        let records = update.records().iter().filter_map(|record| match record {
+            MetadataRecord::ClientQuota(record) => Some(record),
+            _ => None,
+        });
+        if let MetadataUpdate::Snapshot(_) = update {
+            let kept: HashSet<_> = records
+                .clone()
+                .filter(|record| !record.remove)
+                .map(|record| (record.entity.clone(), record.key.clone()))
+                .collect();
+            let removed: Vec<_> = self
+                .quotas
+                .lock()
+                .expect("quotas lock poisoned")
+                .difference(&kept)
+                .cloned()
+                .collect();
+            for (entity, key) in removed {
+                self.replay(&ClientQuotaRecord {
+                    entity,
+                    key,
+                    value: 0.0,
+                    remove: true,
+                });
+            }
+        }
+        records.for_each(|record| self.replay(record));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::connection_quotas::{
+        DEFAULT_NUM_QUOTA_SAMPLES, DEFAULT_QUOTA_WINDOW_SIZE_SECONDS,
+    };
+    use crate::server::client_quota_manager::{ClientQuotaManagerConfig, ClientQuotaType};
+    use rafka_clients::common::metrics::Metrics;
+    use rafka_metadata::records::EntityData;
+    use rafka_server_common::quota_config::{
+        IP_CONNECTION_RATE_DEFAULT, PRODUCER_BYTE_RATE_OVERRIDE_CONFIG,
+    };
+
+    fn record(entity: &[(&str, Option<&str>)], key: &str, value: Option<f64>) -> ClientQuotaRecord {
+        ClientQuotaRecord {
+            entity: entity
+                .iter()
+                .map(|(entity_type, entity_name)| EntityData {
+                    entity_type: entity_type.to_string(),
+                    entity_name: entity_name.map(str::to_string),
+                })
+                .collect(),
+            key: key.to_string(),
+            value: value.unwrap_or(0.0),
+            remove: value.is_none(),
+        }
+    }
+
+    fn managers() -> (
+        ClientQuotaMetadataManager,
+        Arc<ClientQuotaManager>,
+        Arc<ConnectionQuotas>,
+    ) {
+        let produce = Arc::new(ClientQuotaManager::new(
+            ClientQuotaManagerConfig::default(),
+            Metrics::default(),
+            ClientQuotaType::Produce,
+        ));
+        let connection_quotas = Arc::new(ConnectionQuotas::new(
+            Metrics::default(),
+            DEFAULT_NUM_QUOTA_SAMPLES,
+            DEFAULT_QUOTA_WINDOW_SIZE_SECONDS,
+        ));
+        (
+            ClientQuotaMetadataManager::new(vec![produce.clone()], connection_quotas.clone()),
+            produce,
+            connection_quotas,
+        )
+    }
+
+    #[test]
+    fn test_replay_ip_quotas() {
+        let (manager, _, connection_quotas) = managers();
+        let ip: IpAddr = "192.168.0.1".parse().unwrap();
+        let other: IpAddr = "192.168.0.2".parse().unwrap();
+        manager.replay(&record(
+            &[(IP, Some("192.168.0.1"))],
+            IP_CONNECTION_RATE_OVERRIDE_CONFIG,
+            Some(10.0),
+        ));
+        manager.replay(&record(
+            &[(IP, None)],
+            IP_CONNECTION_RATE_OVERRIDE_CONFIG,
+            Some(100.0),
+        ));
+        assert_eq!(10, connection_quotas.connection_rate_for_ip(&ip));
+        assert_eq!(100, connection_quotas.connection_rate_for_ip(&other));
+
+        manager.replay(&record(
+            &[(IP, Some("192.168.0.1"))],
+            IP_CONNECTION_RATE_OVERRIDE_CONFIG,
+            None,
+        ));
+        manager.replay(&record(
+            &[(IP, None)],
+            IP_CONNECTION_RATE_OVERRIDE_CONFIG,
+            None,
+        ));
+        assert_eq!(
+            IP_CONNECTION_RATE_DEFAULT,
+            connection_quotas.connection_rate_for_ip(&ip)
+        );
+    }
+
+    #[test]
+    fn test_replay_user_client_id_quotas() {
+        let (manager, produce, _) = managers();
+        manager.replay(&record(
+            &[(USER, Some("alice")), (CLIENT_ID, None)],
+            PRODUCER_BYTE_RATE_OVERRIDE_CONFIG,
+            Some(1024.0),
+        ));
+        let entity = ClientQuotaEntity::new(
+            Some(ConfigEntity::Name("alice".to_string())),
+            Some(ConfigEntity::Default),
+        );
+        assert_eq!(
+            Some((entity, 1024.0)),
+            produce.resolve_quota("alice", "app")
+        );
+
+        manager.replay(&record(
+            &[(USER, Some("alice")), (CLIENT_ID, None)],
+            PRODUCER_BYTE_RATE_OVERRIDE_CONFIG,
+            None,
+        ));
+        assert_eq!(None, produce.resolve_quota("alice", "app"));
+    }
+
+    #[test]
+    fn test_snapshot_removes_the_quotas_it_does_not_have() {
+        let (manager, produce, _) = managers();
+        let quota = |user: &str, value| {
+            MetadataRecord::ClientQuota(record(
+                &[(USER, Some(user))],
+                PRODUCER_BYTE_RATE_OVERRIDE_CONFIG,
+                value,
+            ))
+        };
+        manager.on_update(&MetadataUpdate::Snapshot(&[quota("alice", Some(10.0))]));
+        manager.on_update(&MetadataUpdate::Delta(&[quota("bob", Some(20.0))]));
+        assert!(produce.resolve_quota("alice", "app").is_some());
+        assert!(produce.resolve_quota("bob", "app").is_some());
+
+        manager.on_update(&MetadataUpdate::Snapshot(&[quota("bob", Some(30.0))]));
+        assert_eq!(None, produce.resolve_quota("alice", "app"));
+        assert_eq!(
+            Some(30.0),
+            produce.resolve_quota("bob", "app").map(|(_, quota)| quota)
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use rafka_clients::common::metrics::{MetricName, Metrics};
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_storage::unified_log::UnifiedLog;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+pub const CONSUMER_LAG_METRICS_GROUP: &str = "consumer-lag-metrics";
+
+pub const RECORDS_LAG: &str = "records-lag";
+pub const RECORDS_LAG_TIME_MS: &str = "records-lag-time-ms";
+
+/// How far a consumer group is behind the end of a partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsumerLag {
+    /// The number of records the group has not consumed yet.
+    pub offset_lag: i64,
+    /// How long the oldest record the group has not consumed yet has been waiting, 0 if the
+    /// group is caught up.
+    pub time_lag_ms: i64,
+}
+
+impl ConsumerLag {
+    /// The lag of a group which committed `committed_offset` on `log`, at `now_ms`.
+    ///
+    /// The time lag is estimated from the time index, see [UnifiedLog::estimate_timestamp],
+    /// so it costs no read of the log. Unlike the offset lag, it tells how late the group is
+    /// regardless of the throughput of the partition: a lag of 10,000 records is a few
+    /// milliseconds on a busy partition and days on a quiet one.
+    pub fn estimate(log: &UnifiedLog, committed_offset: i64, now_ms: i64) -> Self {
+        // Records deleted by retention are lost to the group, they don't count as lag.
+        let next_offset = committed_offset.max(log.log_start_offset());
+        let offset_lag = (log.log_end_offset() - next_offset).max(0);
+        let time_lag_ms = if offset_lag == 0 {
+            0
+        } else {
+            log.estimate_timestamp(next_offset)
+                .map_or(0, |timestamp| (now_ms - timestamp).max(0))
+        };
+        Self {
+            offset_lag,
+            time_lag_ms,
+        }
+    }
+}
+
+/// The gauges of the lag of a group on a partition.
+struct PartitionLagGauges {
+    lag: ConsumerLag,
+    offset_lag: Arc<AtomicI64>,
+    time_lag_ms: Arc<AtomicI64>,
+    metric_names: [MetricName; 2],
+}
+
+impl PartitionLagGauges {
+    fn new(metrics: &Metrics, group_id: &str, topic_partition: &TopicPartition) -> Self {
+        let partition = topic_partition.partition().to_string();
+        let tags = [
+            ("group", group_id),
+            ("topic", topic_partition.topic()),
+            ("partition", partition.as_str()),
+        ];
+        let offset_lag = Arc::new(AtomicI64::new(0));
+        let time_lag_ms = Arc::new(AtomicI64::new(0));
+        let metric_names = [
+            (
+                RECORDS_LAG,
+                "The number of records the group has not consumed yet",
+                Arc::clone(&offset_lag),
+            ),
+            (
+                RECORDS_LAG_TIME_MS,
+                "The estimated age in ms of the oldest record the group has not consumed yet",
+                Arc::clone(&time_lag_ms),
+            ),
+        ]
+        .map(|(name, description, value)| {
+            let metric_name =
+                metrics.metric_name(name, CONSUMER_LAG_METRICS_GROUP, description, &tags);
+            metrics.add_gauge(metric_name.clone(), move |_| {
+                value.load(Ordering::Relaxed) as f64
+            });
+            metric_name
+        });
+        Self {
+            lag: ConsumerLag {
+                offset_lag: 0,
+                time_lag_ms: 0,
+            },
+            offset_lag,
+            time_lag_ms,
+            metric_names,
+        }
+    }
+}
+
+/// The lag of the consumer groups on the partitions led by this broker, tagged with the
+/// group, topic and partition.
+///
+/// The owner of the committed offsets records the lag of each group periodically, and when
+/// the offsets of a group are committed. The lags are also listed per group, for tools
+/// describing the groups.
+pub struct ConsumerLagMetrics {
+    metrics: Metrics,
+    lags: BTreeMap<(String, TopicPartition), PartitionLagGauges>,
+}
+
+impl ConsumerLagMetrics {
+    pub fn new(metrics: Metrics) -> Self {
+        Self {
+            metrics,
+            lags: BTreeMap::new(),
+        }
+    }
+
+    /// Computes and records the lag of `group_id`, which committed `committed_offset` on
+    /// `log`.
+    pub fn update(
+        &mut self,
+        group_id: &str,
+        log: &UnifiedLog,
+        committed_offset: i64,
+        now_ms: i64,
+    ) -> ConsumerLag {
+        let lag = ConsumerLag::estimate(log, committed_offset, now_ms);
+        self.record(group_id, log.topic_partition(), lag);
+        lag
+    }
+
+    /// Records the lag of `group_id` on `topic_partition`, adding its gauges on first use.
+    pub fn record(&mut self, group_id: &str, topic_partition: &TopicPartition, lag: ConsumerLag) {
+        let gauges = self
+            .lags
+            .entry((group_id.to_string(), topic_partition.clone()))
+            .or_insert_with(|| PartitionLagGauges::new(&self.metrics, group_id, topic_partition));
+        gauges.lag = lag;
+        gauges.offset_lag.store(lag.offset_lag, Ordering::Relaxed);
+        gauges.time_lag_ms.store(lag.time_lag_ms, Ordering::Relaxed);
+    }
+
+    /// The last recorded lags of `group_id`, by partition.
+    pub fn group_lags(&self, group_id: &str) -> Vec<(TopicPartition, ConsumerLag)> {
+        self.lags
+            .iter()
+            .filter(|((group, _), _)| group == group_id)
+            .map(|((_, topic_partition), gauges)| (topic_partition.clone(), gauges.lag))
+            .collect()
+    }
+
+    /// Removes the gauges of `group_id` on `topic_partition`, e.g. once the group deleted
+    /// its offsets or this broker no longer leads the partition.
+    pub fn remove(&mut self, group_id: &str, topic_partition: &TopicPartition) {
+        if let Some(gauges) = self
+            .lags
+            .remove(&(group_id.to_string(), topic_partition.clone()))
+        {
+            for metric_name in &gauges.metric_names {
+                self.metrics.remove_metric(metric_name);
+            }
+        }
+    }
+
+    /// Removes the gauges of `group_id`, once the group is deleted.
+    pub fn remove_group(&mut self, group_id: &str) {
+        let partitions: Vec<TopicPartition> = self
+            .group_lags(group_id)
+            .into_iter()
+            .map(|(topic_partition, _)| topic_partition)
+            .collect();
+        for topic_partition in &partitions {
+            self.remove(group_id, topic_partition);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+    use rafka_storage::append_origin::AppendOrigin;
+    use rafka_storage::unified_log::UnifiedLogConfig;
+
+    fn log_with_timestamps(dir: &std::path::Path, timestamps: &[i64]) -> UnifiedLog {
+        let config = UnifiedLogConfig {
+            index_interval_bytes: 1,
+            ..Default::default()
+        };
+        let mut log = UnifiedLog::open(&dir.join("foo-0"), config, 0).unwrap();
+        for timestamp in timestamps {
+            let batch = RecordBatch::new(0, vec![Record::new(*timestamp, None, Some(&[0; 10]))]);
+            log.append_as_leader(batch, 0, AppendOrigin::Client)
+                .unwrap();
+        }
+        log
+    }
+
+    #[test]
+    fn test_estimate_lag() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = log_with_timestamps(dir.path(), &[1_000, 2_000, 3_000, 4_000]);
+        assert_eq!(
+            ConsumerLag {
+                offset_lag: 3,
+                time_lag_ms: 8_000,
+            },
+            ConsumerLag::estimate(&log, 1, 10_000)
+        );
+        assert_eq!(
+            ConsumerLag {
+                offset_lag: 0,
+                time_lag_ms: 0,
+            },
+            ConsumerLag::estimate(&log, 4, 10_000)
+        );
+    }
+
+    #[test]
+    fn test_lag_gauges() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = log_with_timestamps(dir.path(), &[1_000, 2_000]);
+        let metrics = Metrics::default();
+        let mut lag_metrics = ConsumerLagMetrics::new(metrics.clone());
+        let value = |name| {
+            metrics.metric_value(&metrics.metric_name(
+                name,
+                CONSUMER_LAG_METRICS_GROUP,
+                "",
+                &[("group", "g"), ("topic", "foo"), ("partition", "0")],
+            ))
+        };
+        lag_metrics.update("g", &log, 0, 5_000);
+        assert_eq!(Some(2.0), value(RECORDS_LAG));
+        assert_eq!(Some(4_000.0), value(RECORDS_LAG_TIME_MS));
+
+        let lag = lag_metrics.update("g", &log, 1, 5_000);
+        assert_eq!(Some(3_000.0), value(RECORDS_LAG_TIME_MS));
+        assert_eq!(
+            vec![(TopicPartition::new("foo", 0), lag)],
+            lag_metrics.group_lags("g")
+        );
+        assert!(lag_metrics.group_lags("other").is_empty());
+
+        lag_metrics.remove_group("g");
+        assert_eq!(None, value(RECORDS_LAG));
+        assert!(lag_metrics.group_lags("g").is_empty());
+    }
+}
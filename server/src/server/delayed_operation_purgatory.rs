@@ -0,0 +1,297 @@
+use rafka_clients::common::metrics::stats::{CumulativeCount, Rate};
+use rafka_clients::common::metrics::{Metrics, Sensor};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub const PURGATORY_METRICS_GROUP: &str = "purgatory-metrics";
+
+pub const PURGATORY_SIZE: &str = "purgatory-size";
+pub const NUM_DELAYED_OPERATIONS: &str = "num-delayed-operations";
+pub const EXPIRATION_RATE: &str = "expiration-rate";
+pub const EXPIRATION_TOTAL: &str = "expiration-total";
+
+/// The name of the purgatory of produce requests waiting for `acks=all`.
+pub const PRODUCE_PURGATORY: &str = "Produce";
+/// The name of the purgatory of fetch requests waiting for `fetch.min.bytes`.
+pub const FETCH_PURGATORY: &str = "Fetch";
+
+/// The tag of purgatory metrics holding the kind of operation, e.g. `Produce` or `Fetch`.
+pub const DELAYED_OPERATION_TAG: &str = "delayed-operation";
+
+/// An operation which can't complete right away, such as an `acks=all` produce waiting for
+/// the followers to replicate its records or a fetch waiting for `fetch.min.bytes`.
+pub trait DelayedOperation: Send + Sync {
+    /// Completes the operation if it can complete now, returning whether it did.
+    fn try_complete(&self) -> bool;
+
+    /// Completes the operation once its deadline passed.
+    fn on_expiration(&self);
+
+    /// The time at which the operation expires.
+    fn deadline_ms(&self) -> i64;
+}
+
+struct State<K, T> {
+    next_id: u64,
+    operations: HashMap<u64, Arc<T>>,
+    watchers: HashMap<K, Vec<u64>>,
+}
+
+/// Holds delayed operations until they complete or expire.
+///
+/// An operation is watched under keys, the partitions whose progress may let it complete:
+/// [DelayedOperationPurgatory::check_and_complete] retries the operations watched under a key
+/// whenever it progresses. Completed operations are dropped from the other keys lazily, so
+/// the purgatory size, the number of watched entries, can exceed the number of operations.
+/// Both are reported as metrics, with the expiration rate, tagged with the purgatory name.
+pub struct DelayedOperationPurgatory<K, T> {
+    name: String,
+    state: Mutex<State<K, T>>,
+    watched: Arc<AtomicI64>,
+    delayed: Arc<AtomicI64>,
+    expirations: Arc<Sensor>,
+}
+
+impl<K: Hash + Eq + Clone, T: DelayedOperation> DelayedOperationPurgatory<K, T> {
+    pub fn new(name: &str, metrics: &Metrics) -> Self {
+        let tags = [(DELAYED_OPERATION_TAG, name)];
+        let watched = Arc::new(AtomicI64::new(0));
+        let delayed = Arc::new(AtomicI64::new(0));
+
+        let gauge = Arc::clone(&watched);
+        metrics.add_gauge(
+            metrics.metric_name(
+                PURGATORY_SIZE,
+                PURGATORY_METRICS_GROUP,
+                "The number of operations watched in the purgatory, including completed \
+                operations not purged yet",
+                &tags,
+            ),
+            move |_| gauge.load(Ordering::Relaxed) as f64,
+        );
+        let gauge = Arc::clone(&delayed);
+        metrics.add_gauge(
+            metrics.metric_name(
+                NUM_DELAYED_OPERATIONS,
+                PURGATORY_METRICS_GROUP,
+                "The number of operations waiting in the purgatory",
+                &tags,
+            ),
+            move |_| gauge.load(Ordering::Relaxed) as f64,
+        );
+        let expirations = metrics.sensor(&format!("{name}-expirations"));
+        expirations.add(
+            metrics.metric_name(
+                EXPIRATION_RATE,
+                PURGATORY_METRICS_GROUP,
+                "The number of operations expired per second",
+                &tags,
+            ),
+            Rate::occurrences(),
+        );
+        expirations.add(
+            metrics.metric_name(
+                EXPIRATION_TOTAL,
+                PURGATORY_METRICS_GROUP,
+                "The total number of operations expired",
+                &tags,
+            ),
+            CumulativeCount::new(),
+        );
+
+        Self {
+            name: name.to_string(),
+            state: Mutex::new(State {
+                next_id: 0,
+                operations: HashMap::new(),
+                watchers: HashMap::new(),
+            }),
+            watched,
+            delayed,
+            expirations,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State<K, T>> {
+        self.state.lock().expect("purgatory lock poisoned")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Completes `operation` if it can complete now, or watches it under `keys` otherwise.
+    /// Returns whether it completed.
+    pub fn try_complete_else_watch(&self, operation: Arc<T>, keys: &[K]) -> bool {
+        if operation.try_complete() {
+            return true;
+        }
+        let mut state = self.state();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.operations.insert(id, operation);
+        for key in keys {
+            state.watchers.entry(key.clone()).or_default().push(id);
+        }
+        self.update_gauges(&state);
+        false
+    }
+
+    /// Retries the operations watched under `key`, returning how many completed.
+    pub fn check_and_complete(&self, key: &K) -> usize {
+        let mut state = self.state();
+        let Some(ids) = state.watchers.remove(key) else {
+            return 0;
+        };
+        let mut completed = 0;
+        let mut remaining = vec![];
+        for id in ids {
+            let Some(operation) = state.operations.get(&id) else {
+                continue;
+            };
+            if operation.try_complete() {
+                state.operations.remove(&id);
+                completed += 1;
+            } else {
+                remaining.push(id);
+            }
+        }
+        if !remaining.is_empty() {
+            state.watchers.insert(key.clone(), remaining);
+        }
+        self.update_gauges(&state);
+        completed
+    }
+
+    /// Expires the operations whose deadline passed at `now_ms` and purges the completed
+    /// ones from all keys. Returns how many expired.
+    pub fn expire(&self, now_ms: i64) -> usize {
+        let mut state = self.state();
+        let expired: Vec<u64> = state
+            .operations
+            .iter()
+            .filter(|(_, operation)| operation.deadline_ms() <= now_ms)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired {
+            if let Some(operation) = state.operations.remove(id) {
+                operation.on_expiration();
+                self.expirations.record_at(1.0, now_ms);
+            }
+        }
+        let State {
+            operations,
+            watchers,
+            ..
+        } = &mut *state;
+        watchers.retain(|_, ids| {
+            ids.retain(|id| operations.contains_key(id));
+            !ids.is_empty()
+        });
+        self.update_gauges(&state);
+        expired.len()
+    }
+
+    /// The number of operations watched, including completed operations not purged yet.
+    pub fn watched(&self) -> usize {
+        self.watched.load(Ordering::Relaxed) as usize
+    }
+
+    /// The number of operations waiting to complete.
+    pub fn num_delayed(&self) -> usize {
+        self.delayed.load(Ordering::Relaxed) as usize
+    }
+
+    fn update_gauges(&self, state: &State<K, T>) {
+        let watched: usize = state.watchers.values().map(Vec::len).sum();
+        self.watched.store(watched as i64, Ordering::Relaxed);
+        self.delayed
+            .store(state.operations.len() as i64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::metrics::MetricConfig;
+    use rafka_clients::common::utils::time::MockTime;
+    use std::sync::atomic::AtomicBool;
+
+    struct MockOperation {
+        deadline_ms: i64,
+        completable: AtomicBool,
+        expired: AtomicBool,
+    }
+
+    impl MockOperation {
+        fn new(deadline_ms: i64) -> Arc<Self> {
+            Arc::new(Self {
+                deadline_ms,
+                completable: AtomicBool::new(false),
+                expired: AtomicBool::new(false),
+            })
+        }
+    }
+
+    impl DelayedOperation for MockOperation {
+        fn try_complete(&self) -> bool {
+            self.completable.load(Ordering::Relaxed)
+        }
+
+        fn on_expiration(&self) {
+            self.expired.store(true, Ordering::Relaxed);
+        }
+
+        fn deadline_ms(&self) -> i64 {
+            self.deadline_ms
+        }
+    }
+
+    #[test]
+    fn test_complete_and_expire() {
+        let metrics = Metrics::new(
+            MetricConfig::default(),
+            Arc::new(MockTime::with_start(0, 0, 0)),
+        );
+        let purgatory = DelayedOperationPurgatory::new(PRODUCE_PURGATORY, &metrics);
+        let value = |name| {
+            metrics
+                .metric_value(&metrics.metric_name(
+                    name,
+                    PURGATORY_METRICS_GROUP,
+                    "",
+                    &[(DELAYED_OPERATION_TAG, PRODUCE_PURGATORY)],
+                ))
+                .unwrap()
+        };
+
+        let done = MockOperation::new(100);
+        done.completable.store(true, Ordering::Relaxed);
+        assert!(purgatory.try_complete_else_watch(done, &["foo-0"]));
+
+        let first = MockOperation::new(100);
+        let second = MockOperation::new(200);
+        assert!(!purgatory.try_complete_else_watch(first.clone(), &["foo-0", "foo-1"]));
+        assert!(!purgatory.try_complete_else_watch(second.clone(), &["foo-1"]));
+        assert_eq!(3.0, value(PURGATORY_SIZE));
+        assert_eq!(2.0, value(NUM_DELAYED_OPERATIONS));
+
+        assert_eq!(0, purgatory.check_and_complete(&"foo-1"));
+        first.completable.store(true, Ordering::Relaxed);
+        assert_eq!(1, purgatory.check_and_complete(&"foo-0"));
+        // The completed operation is still watched under foo-1 until purged.
+        assert_eq!(2, purgatory.watched());
+        assert_eq!(1, purgatory.num_delayed());
+
+        assert_eq!(0, purgatory.expire(150));
+        assert_eq!(1, purgatory.watched());
+        assert_eq!(1, purgatory.expire(200));
+        assert!(second.expired.load(Ordering::Relaxed));
+        assert!(!first.expired.load(Ordering::Relaxed));
+        assert_eq!(0.0, value(PURGATORY_SIZE));
+        assert_eq!(0.0, value(NUM_DELAYED_OPERATIONS));
+        assert_eq!(1.0, value(EXPIRATION_TOTAL));
+    }
+}
@@ -0,0 +1,184 @@
+use rafka_metadata::configuration_control_manager::TOPIC_RESOURCE_TYPE;
+use rafka_metadata::metadata_publisher::{MetadataSubscriber, MetadataUpdate};
+use rafka_metadata::metadata_record::MetadataRecord;
+use rafka_metadata::records::ConfigRecord;
+use rafka_storage::{TopicLogConfigs, UnifiedLogConfig};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Called with a topic and its log configs whenever they change, to update its open logs.
+pub type TopicConfigListener = Box<dyn Fn(&str, &UnifiedLogConfig) + Send + Sync>;
+
+/// Applies the dynamic configs of the topics replicated through the metadata log to the
+/// [TopicLogConfigs] the logs of the broker are opened with, as they change, and to the logs
+/// already open through its [TopicConfigListener]s.
+pub struct DynamicConfigPublisher {
+    topic_log_configs: Arc<TopicLogConfigs>,
+    listeners: Vec<TopicConfigListener>,
+}
+
+impl DynamicConfigPublisher {
+    pub fn new(topic_log_configs: Arc<TopicLogConfigs>) -> Self {
+        Self {
+            topic_log_configs,
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn with_listener(mut self, listener: TopicConfigListener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+
+    fn notify(&self, topics: BTreeSet<String>) {
+        for topic in topics {
+            let config = self.topic_log_configs.config(&topic);
+            for listener in &self.listeners {
+                listener(&topic, &config);
+            }
+        }
+    }
+}
+
+fn topic_configs<'a>(records: &'a [MetadataRecord]) -> impl Iterator<Item = &'a ConfigRecord> + 'a {
+    records.iter().filter_map(|record| match record {
+        MetadataRecord::Config(record)
+            if record.resource_type == TOPIC_RESOURCE_TYPE && !record.resource_name.is_empty() =>
+        {
+            Some(record)
+        }
+        _ => None,
+    })
+}
+
+impl MetadataSubscriber for DynamicConfigPublisher {
+    fn name(&self) -> &str {
+        "DynamicConfigPublisher"
+    }
+
+    fn on_update(&self, update: &MetadataUpdate<'_>) {
+        match update {
+            MetadataUpdate::Delta(records) => {
+                let mut changed = BTreeSet::new();
+                for record in topic_configs(records) {
+                    match self.topic_log_configs.set(
+                        &record.resource_name,
+                        &record.name,
+                        record.value.as_deref(),
+                    ) {
+                        Ok(()) => {
+                            changed.insert(record.resource_name.clone());
+                        }
+                        Err(e) => warn!(
+                            "Ignoring the config change of topic {}: {e}",
+                            record.resource_name
+                        ),
+                    }
+                }
+                self.notify(changed);
+            }
+            MetadataUpdate::Snapshot(records) => {
+                // The topics losing their overrides change too.
+                let mut changed: BTreeSet<String> =
+                    self.topic_log_configs.topics().into_iter().collect();
+                let mut overrides: HashMap<String, BTreeMap<String, String>> = HashMap::new();
+                for record in topic_configs(records) {
+                    if let Some(value) = &record.value {
+                        overrides
+                            .entry(record.resource_name.clone())
+                            .or_default()
+                            .insert(record.name.clone(), value.clone());
+                    }
+                }
+                changed.extend(overrides.keys().cloned());
+                self.topic_log_configs.replace_all(overrides);
+                self.notify(changed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::config::topic_config::{
+        MAX_MESSAGE_BYTES_CONFIG, SEGMENT_BYTES_CONFIG,
+    };
+    use std::sync::Mutex;
+
+    fn config(topic: &str, name: &str, value: Option<&str>) -> MetadataRecord {
+        MetadataRecord::Config(ConfigRecord {
+            resource_type: TOPIC_RESOURCE_TYPE,
+            resource_name: topic.to_string(),
+            name: name.to_string(),
+            value: value.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn test_topic_configs_follow_metadata_updates() {
+        let topic_log_configs = Arc::new(TopicLogConfigs::default());
+        let publisher = DynamicConfigPublisher::new(topic_log_configs.clone());
+        publisher.on_update(&MetadataUpdate::Snapshot(&[config(
+            "foo",
+            SEGMENT_BYTES_CONFIG,
+            Some("1024"),
+        )]));
+        assert_eq!(1024, topic_log_configs.config("foo").segment_bytes);
+
+        publisher.on_update(&MetadataUpdate::Delta(&[
+            config("foo", SEGMENT_BYTES_CONFIG, None),
+            config("bar", MAX_MESSAGE_BYTES_CONFIG, Some("100")),
+            // Invalid, and ignored.
+            config("bar", SEGMENT_BYTES_CONFIG, Some("big")),
+        ]));
+        assert_eq!(UnifiedLogConfig::default(), topic_log_configs.config("foo"));
+        let bar = topic_log_configs.config("bar");
+        assert_eq!(100, bar.max_message_bytes);
+        assert_eq!(UnifiedLogConfig::default().segment_bytes, bar.segment_bytes);
+
+        publisher.on_update(&MetadataUpdate::Snapshot(&[]));
+        assert_eq!(UnifiedLogConfig::default(), topic_log_configs.config("bar"));
+    }
+
+    #[test]
+    fn test_listeners_get_the_changed_topics() {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let listener_changes = changes.clone();
+        let publisher = DynamicConfigPublisher::new(Arc::new(TopicLogConfigs::default()))
+            .with_listener(Box::new(move |topic, config| {
+                listener_changes
+                    .lock()
+                    .unwrap()
+                    .push((topic.to_string(), config.segment_bytes));
+            }));
+        let default = UnifiedLogConfig::default().segment_bytes;
+        let changed = || std::mem::take(&mut *changes.lock().unwrap());
+
+        publisher.on_update(&MetadataUpdate::Snapshot(&[config(
+            "foo",
+            SEGMENT_BYTES_CONFIG,
+            Some("1024"),
+        )]));
+        assert_eq!(vec![("foo".to_string(), 1024)], changed());
+
+        publisher.on_update(&MetadataUpdate::Delta(&[
+            config("bar", SEGMENT_BYTES_CONFIG, Some("2048")),
+            // Invalid, and ignored.
+            config("baz", SEGMENT_BYTES_CONFIG, Some("big")),
+        ]));
+        assert_eq!(vec![("bar".to_string(), 2048)], changed());
+
+        // The topics left out of a snapshot go back to the defaults.
+        publisher.on_update(&MetadataUpdate::Snapshot(&[config(
+            "bar",
+            SEGMENT_BYTES_CONFIG,
+            Some("2048"),
+        )]));
+        assert_eq!(
+            vec![("bar".to_string(), 2048), ("foo".to_string(), default)],
+            changed()
+        );
+    }
+}
@@ -0,0 +1,737 @@
+use rafka_clients::common::metrics::stats::{Avg, CumulativeCount, Rate};
+use rafka_clients::common::metrics::{Metrics, Sensor};
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_server_common::server_configs::ServerConfig;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use tracing::{debug, info};
+
+/// The session id of fetch requests which don't use a session.
+pub const INVALID_SESSION_ID: i32 = 0;
+/// The epoch of a fetch request creating a new session.
+pub const INITIAL_EPOCH: i32 = 0;
+/// The epoch of a fetch request closing its session.
+pub const FINAL_EPOCH: i32 = -1;
+
+pub const DEFAULT_MAX_INCREMENTAL_FETCH_SESSION_CACHE_SLOTS: usize = 1000;
+pub const DEFAULT_FETCH_SESSION_EVICTION_MS: i64 = 120_000;
+
+/// The memory of a cached partition besides its topic name: the partition and its fetch state,
+/// and its share of the nodes of the map of the session.
+const CACHED_PARTITION_OVERHEAD_BYTES: usize =
+    size_of::<TopicPartition>() + size_of::<CachedPartition>() + 16;
+
+const METRICS_GROUP: &str = "FetchSessionCache";
+
+/// The epoch following `epoch`, wrapping around to 1 since 0 is reserved for new sessions.
+pub fn next_epoch(epoch: i32) -> i32 {
+    if epoch < 0 {
+        FINAL_EPOCH
+    } else if epoch == i32::MAX {
+        1
+    } else {
+        epoch + 1
+    }
+}
+
+/// The estimated memory of `partitions` in a session cache.
+pub fn cached_partitions_bytes(partitions: &BTreeMap<TopicPartition, CachedPartition>) -> usize {
+    partitions
+        .keys()
+        .map(|partition| CACHED_PARTITION_OVERHEAD_BYTES + partition.topic().len())
+        .sum()
+}
+
+/// The state the broker keeps for a partition of a fetch session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachedPartition {
+    pub fetch_offset: i64,
+    pub max_bytes: i32,
+    pub log_start_offset: i64,
+    pub high_watermark: i64,
+}
+
+/// The partitions an incremental fetch session fetches, so that a fetch request only needs to
+/// carry the partitions whose fetch state changed.
+#[derive(Debug)]
+pub struct FetchSession {
+    id: i32,
+    /// Sessions of followers are privileged and may evict client sessions.
+    privileged: bool,
+    partitions: BTreeMap<TopicPartition, CachedPartition>,
+    creation_ms: i64,
+    last_used_ms: i64,
+    epoch: i32,
+    /// The number of partitions counted by the cache, updated when the session is touched.
+    cached_size: usize,
+    /// The estimated memory of the partitions counted by the cache, updated with `cached_size`.
+    cached_bytes: usize,
+}
+
+impl FetchSession {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn privileged(&self) -> bool {
+        self.privileged
+    }
+
+    pub fn partitions(&self) -> &BTreeMap<TopicPartition, CachedPartition> {
+        &self.partitions
+    }
+
+    pub fn partitions_mut(&mut self) -> &mut BTreeMap<TopicPartition, CachedPartition> {
+        &mut self.partitions
+    }
+
+    pub fn creation_ms(&self) -> i64 {
+        self.creation_ms
+    }
+
+    pub fn last_used_ms(&self) -> i64 {
+        self.last_used_ms
+    }
+
+    /// The epoch the next fetch request of the session must carry.
+    pub fn epoch(&self) -> i32 {
+        self.epoch
+    }
+
+    pub fn size(&self) -> usize {
+        self.partitions.len()
+    }
+
+    fn last_used_key(&self) -> (i64, i32) {
+        (self.last_used_ms, self.id)
+    }
+
+    fn evictable_key(&self) -> EvictableKey {
+        EvictableKey {
+            privileged: self.privileged,
+            size: self.cached_size,
+            id: self.id,
+        }
+    }
+}
+
+/// Orders sessions from the least to the most useful: client sessions before follower
+/// sessions, then smaller sessions before larger ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct EvictableKey {
+    privileged: bool,
+    size: usize,
+    id: i32,
+}
+
+impl EvictableKey {
+    /// Whether the session is less useful than the session of `other`, whatever their ids.
+    fn less_useful_than(&self, other: &EvictableKey) -> bool {
+        (self.privileged, self.size) < (other.privileged, other.size)
+    }
+}
+
+/// What the sessions of a cache hold, against its bounds.
+#[derive(Debug, Clone, Copy, Default)]
+struct CacheUsage {
+    sessions: usize,
+    partitions: usize,
+    bytes: usize,
+}
+
+/// The fetch sessions of a broker.
+///
+/// The cache holds at most `max_entries` sessions, caching at most `max_partitions`
+/// partitions of at most `max_bytes` of estimated memory together. When a new session does not
+/// fit, it can only be created by evicting other sessions:
+///
+/// * sessions which have not been used for `eviction_ms` are always evicted first;
+/// * otherwise a session which is past its first `eviction_ms` may be evicted by a new session
+///   which is more useful, that is a follower session, or a client session with more
+///   partitions;
+/// * a follower session may also evict a client session which is still in its first
+///   `eviction_ms`, but never a newer follower session.
+///
+/// Protecting young sessions keeps clients from evicting each other in a loop, and preferring
+/// follower sessions keeps replication incremental when clients compete for the cache. Bounding
+/// the partitions as well as the sessions keeps many consumers of many partitions from growing
+/// the cache without bound.
+pub struct FetchSessionCache {
+    max_entries: usize,
+    max_partitions: usize,
+    max_bytes: usize,
+    eviction_ms: i64,
+    sessions: HashMap<i32, FetchSession>,
+    last_used: BTreeSet<(i64, i32)>,
+    /// The sessions a follower session may evict.
+    evictable_by_privileged: BTreeSet<EvictableKey>,
+    /// The sessions any session may evict.
+    evictable_by_all: BTreeSet<EvictableKey>,
+    num_sessions: Arc<AtomicI64>,
+    num_partitions: Arc<AtomicI64>,
+    num_bytes: Arc<AtomicI64>,
+    evictions_sensor: Arc<Sensor>,
+    hit_sensor: Arc<Sensor>,
+}
+
+impl FetchSessionCache {
+    pub fn new(max_entries: usize, eviction_ms: i64, metrics: &Metrics) -> Self {
+        let num_sessions = Arc::new(AtomicI64::new(0));
+        let num_partitions = Arc::new(AtomicI64::new(0));
+        let num_bytes = Arc::new(AtomicI64::new(0));
+        let gauge = |counter: &Arc<AtomicI64>| {
+            let counter = Arc::clone(counter);
+            move |_now_ms: i64| counter.load(Ordering::Relaxed) as f64
+        };
+        metrics.add_gauge(
+            metrics.metric_name(
+                "incremental-fetch-session-count",
+                METRICS_GROUP,
+                "The number of incremental fetch sessions in the cache.",
+                &[],
+            ),
+            gauge(&num_sessions),
+        );
+        metrics.add_gauge(
+            metrics.metric_name(
+                "incremental-fetch-partitions-cached",
+                METRICS_GROUP,
+                "The number of partitions cached by all incremental fetch sessions.",
+                &[],
+            ),
+            gauge(&num_partitions),
+        );
+        metrics.add_gauge(
+            metrics.metric_name(
+                "incremental-fetch-partitions-cached-bytes",
+                METRICS_GROUP,
+                "The estimated memory of the partitions cached by all incremental fetch sessions.",
+                &[],
+            ),
+            gauge(&num_bytes),
+        );
+
+        let evictions_sensor = metrics.sensor("incremental-fetch-session-evictions");
+        evictions_sensor.add(
+            metrics.metric_name(
+                "incremental-fetch-session-eviction-rate",
+                METRICS_GROUP,
+                "The number of incremental fetch sessions evicted per second.",
+                &[],
+            ),
+            Rate::occurrences(),
+        );
+        evictions_sensor.add(
+            metrics.metric_name(
+                "incremental-fetch-session-eviction-total",
+                METRICS_GROUP,
+                "The total number of incremental fetch sessions evicted.",
+                &[],
+            ),
+            CumulativeCount::new(),
+        );
+
+        let hit_sensor = metrics.sensor("incremental-fetch-session-hits");
+        hit_sensor.add(
+            metrics.metric_name(
+                "incremental-fetch-session-hit-ratio",
+                METRICS_GROUP,
+                "The fraction of incremental fetch requests which found their session.",
+                &[],
+            ),
+            Avg::new(),
+        );
+
+        Self {
+            max_entries,
+            max_partitions: usize::MAX,
+            max_bytes: usize::MAX,
+            eviction_ms,
+            sessions: HashMap::new(),
+            last_used: BTreeSet::new(),
+            evictable_by_privileged: BTreeSet::new(),
+            evictable_by_all: BTreeSet::new(),
+            num_sessions,
+            num_partitions,
+            num_bytes,
+            evictions_sensor,
+            hit_sensor,
+        }
+    }
+
+    /// A cache bounded by the `max.incremental.fetch.session.cache.*` configs.
+    pub fn from_config(config: &ServerConfig, metrics: &Metrics) -> Self {
+        Self::new(
+            *config.max_incremental_fetch_session_cache_slots_config() as usize,
+            DEFAULT_FETCH_SESSION_EVICTION_MS,
+            metrics,
+        )
+        .with_max_partitions(
+            *config.max_incremental_fetch_session_cache_partitions_config() as usize,
+        )
+        .with_max_bytes(*config.max_incremental_fetch_session_cache_bytes_config() as usize)
+    }
+
+    /// Bounds the number of partitions cached by all sessions, unbounded by default.
+    pub fn with_max_partitions(mut self, max_partitions: usize) -> Self {
+        self.max_partitions = max_partitions;
+        self
+    }
+
+    /// Bounds the estimated memory of the partitions cached by all sessions, unbounded by
+    /// default.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// The number of partitions cached by all sessions.
+    pub fn total_partitions(&self) -> usize {
+        self.num_partitions.load(Ordering::Relaxed) as usize
+    }
+
+    /// The estimated memory of the partitions cached by all sessions.
+    pub fn total_bytes(&self) -> usize {
+        self.num_bytes.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn get(&self, session_id: i32) -> Option<&FetchSession> {
+        self.sessions.get(&session_id)
+    }
+
+    /// Creates a session for `partitions` if there is room for it or sessions can be evicted
+    /// to make room. Returns the id of the new session, or [INVALID_SESSION_ID] if it could
+    /// not be cached.
+    pub fn maybe_create_session(
+        &mut self,
+        now_ms: i64,
+        privileged: bool,
+        partitions: BTreeMap<TopicPartition, CachedPartition>,
+    ) -> i32 {
+        let key = EvictableKey {
+            privileged,
+            size: partitions.len(),
+            id: 0,
+        };
+        let needed = CacheUsage {
+            sessions: 1,
+            partitions: partitions.len(),
+            bytes: cached_partitions_bytes(&partitions),
+        };
+        let Some(evictions) = self.evictions_for(key, needed, now_ms) else {
+            debug!(
+                "No fetch session created for privileged={privileged}, size={}",
+                key.size
+            );
+            return INVALID_SESSION_ID;
+        };
+        for id in evictions {
+            info!("Evicting fetch session {id} to make room for a new session");
+            self.evict(id);
+        }
+        let id = self.new_session_id();
+        let session = FetchSession {
+            id,
+            privileged,
+            partitions,
+            creation_ms: now_ms,
+            last_used_ms: now_ms,
+            epoch: next_epoch(INITIAL_EPOCH),
+            cached_size: 0,
+            cached_bytes: 0,
+        };
+        debug!(
+            "Created fetch session {id} of size {}, privileged={privileged}",
+            session.size()
+        );
+        self.last_used.insert(session.last_used_key());
+        self.sessions.insert(id, session);
+        self.touch(id, now_ms);
+        self.num_sessions
+            .store(self.sessions.len() as i64, Ordering::Relaxed);
+        id
+    }
+
+    /// Looks up the session of an incremental fetch request with `epoch`, applies `update` to
+    /// it and advances its epoch. Every lookup counts towards the hit ratio.
+    ///
+    /// If the partitions the update added don't fit in the cache, less useful sessions are
+    /// evicted as for a new session. If that is not enough, the session itself is evicted and
+    /// the update fails with `FETCH_SESSION_ID_NOT_FOUND`, so that the client falls back to
+    /// full fetch requests.
+    pub fn update_session(
+        &mut self,
+        session_id: i32,
+        epoch: i32,
+        now_ms: i64,
+        update: impl FnOnce(&mut FetchSession),
+    ) -> Result<i32, Errors> {
+        let Some(session) = self.sessions.get_mut(&session_id) else {
+            self.hit_sensor.record(0.0);
+            return Err(Errors::FetchSessionIdNotFound);
+        };
+        self.hit_sensor.record(1.0);
+        if session.epoch != epoch {
+            debug!(
+                "Fetch session {session_id} expected epoch {}, but got {epoch}",
+                session.epoch
+            );
+            return Err(Errors::InvalidFetchSessionEpoch);
+        }
+        update(session);
+        session.epoch = next_epoch(session.epoch);
+        let next = session.epoch;
+        self.touch(session_id, now_ms);
+
+        let key = self.sessions[&session_id].evictable_key();
+        match self.evictions_for(key, CacheUsage::default(), now_ms) {
+            Some(evictions) => {
+                for id in evictions {
+                    info!("Evicting fetch session {id} to make room for session {session_id}");
+                    self.evict(id);
+                }
+                Ok(next)
+            }
+            None => {
+                info!("Evicting fetch session {session_id} which outgrew the cache");
+                self.evict(session_id);
+                Err(Errors::FetchSessionIdNotFound)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, session_id: i32) -> Option<FetchSession> {
+        let session = self.sessions.remove(&session_id)?;
+        self.last_used.remove(&session.last_used_key());
+        let key = session.evictable_key();
+        self.evictable_by_privileged.remove(&key);
+        self.evictable_by_all.remove(&key);
+        self.num_partitions
+            .fetch_sub(session.cached_size as i64, Ordering::Relaxed);
+        self.num_bytes
+            .fetch_sub(session.cached_bytes as i64, Ordering::Relaxed);
+        self.num_sessions
+            .store(self.sessions.len() as i64, Ordering::Relaxed);
+        Some(session)
+    }
+
+    /// Marks the session as used at `now_ms` and recomputes where it ranks for eviction.
+    fn touch(&mut self, session_id: i32, now_ms: i64) {
+        let session = self
+            .sessions
+            .get_mut(&session_id)
+            .expect("touched session is cached");
+        self.last_used.remove(&session.last_used_key());
+        session.last_used_ms = now_ms;
+        self.last_used.insert(session.last_used_key());
+
+        let old_key = session.evictable_key();
+        self.evictable_by_privileged.remove(&old_key);
+        self.evictable_by_all.remove(&old_key);
+        self.num_partitions
+            .fetch_sub(session.cached_size as i64, Ordering::Relaxed);
+        self.num_bytes
+            .fetch_sub(session.cached_bytes as i64, Ordering::Relaxed);
+        session.cached_size = session.size();
+        session.cached_bytes = cached_partitions_bytes(&session.partitions);
+        self.num_partitions
+            .fetch_add(session.cached_size as i64, Ordering::Relaxed);
+        self.num_bytes
+            .fetch_add(session.cached_bytes as i64, Ordering::Relaxed);
+
+        let new_key = session.evictable_key();
+        let past_eviction_ms = now_ms - session.creation_ms > self.eviction_ms;
+        if !session.privileged || past_eviction_ms {
+            self.evictable_by_privileged.insert(new_key);
+        }
+        if past_eviction_ms {
+            self.evictable_by_all.insert(new_key);
+        }
+    }
+
+    fn usage(&self) -> CacheUsage {
+        CacheUsage {
+            sessions: self.sessions.len(),
+            partitions: self.total_partitions(),
+            bytes: self.total_bytes(),
+        }
+    }
+
+    fn fits(&self, usage: CacheUsage) -> bool {
+        usage.sessions <= self.max_entries
+            && usage.partitions <= self.max_partitions
+            && usage.bytes <= self.max_bytes
+    }
+
+    /// The sessions to evict so that `needed` more fits in the cache, for the session ranked
+    /// as `key`, which is never evicted itself. `None` if evicting every session it may evict
+    /// is not enough, in which case nothing should be evicted.
+    ///
+    /// Stale sessions go first, from the least recently used, then the sessions less useful
+    /// than `key`, from the least useful: client sessions before follower sessions.
+    fn evictions_for(
+        &self,
+        key: EvictableKey,
+        needed: CacheUsage,
+        now_ms: i64,
+    ) -> Option<Vec<i32>> {
+        let usage = self.usage();
+        let mut usage = CacheUsage {
+            sessions: usage.sessions + needed.sessions,
+            partitions: usage.partitions + needed.partitions,
+            bytes: usage.bytes + needed.bytes,
+        };
+        let is_stale = |last_used_ms: i64| now_ms - last_used_ms > self.eviction_ms;
+        let stale = self
+            .last_used
+            .iter()
+            .take_while(|(last_used_ms, _)| is_stale(*last_used_ms))
+            .map(|&(_, id)| id);
+        let evictable = if key.privileged {
+            &self.evictable_by_privileged
+        } else {
+            &self.evictable_by_all
+        };
+        let less_useful = evictable
+            .iter()
+            .take_while(|candidate| candidate.less_useful_than(&key))
+            .map(|candidate| candidate.id)
+            .filter(|id| !is_stale(self.sessions[id].last_used_ms));
+        let mut candidates = stale.chain(less_useful).filter(|id| *id != key.id);
+
+        let mut evictions = Vec::new();
+        while !self.fits(usage) {
+            let id = candidates.next()?;
+            let session = &self.sessions[&id];
+            usage.sessions -= 1;
+            usage.partitions -= session.cached_size;
+            usage.bytes -= session.cached_bytes;
+            evictions.push(id);
+        }
+        Some(evictions)
+    }
+
+    fn evict(&mut self, session_id: i32) {
+        self.remove(session_id);
+        self.evictions_sensor.record(1.0);
+    }
+
+    fn new_session_id(&self) -> i32 {
+        loop {
+            let id = rand::random::<i32>();
+            if id > 0 && !self.sessions.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partitions(size: i32) -> BTreeMap<TopicPartition, CachedPartition> {
+        topic_partitions("foo", size)
+    }
+
+    fn topic_partitions(topic: &str, size: i32) -> BTreeMap<TopicPartition, CachedPartition> {
+        (0..size)
+            .map(|partition| {
+                (
+                    TopicPartition::new(topic, partition),
+                    CachedPartition {
+                        fetch_offset: 0,
+                        max_bytes: 100,
+                        log_start_offset: 0,
+                        high_watermark: 0,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn metric(metrics: &Metrics, name: &str) -> f64 {
+        metrics
+            .metric_value(&metrics.metric_name(name, METRICS_GROUP, "", &[]))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_next_epoch() {
+        assert_eq!(1, next_epoch(INITIAL_EPOCH));
+        assert_eq!(1, next_epoch(i32::MAX));
+        assert_eq!(FINAL_EPOCH, next_epoch(FINAL_EPOCH));
+    }
+
+    #[test]
+    fn test_stale_sessions_are_evicted_first() {
+        let metrics = Metrics::default();
+        let mut cache = FetchSessionCache::new(2, 10, &metrics);
+        let first = cache.maybe_create_session(0, false, partitions(3));
+        let second = cache.maybe_create_session(5, false, partitions(3));
+        assert_eq!(2, cache.size());
+        // Both sessions are younger than the eviction time.
+        assert_eq!(
+            INVALID_SESSION_ID,
+            cache.maybe_create_session(8, false, partitions(5))
+        );
+        // The first session has not been used for more than the eviction time.
+        let third = cache.maybe_create_session(11, false, partitions(1));
+        assert_ne!(INVALID_SESSION_ID, third);
+        assert!(cache.get(first).is_none());
+        assert!(cache.get(second).is_some());
+        assert_eq!(4, cache.total_partitions());
+        assert_eq!(2.0, metric(&metrics, "incremental-fetch-session-count"));
+        assert_eq!(4.0, metric(&metrics, "incremental-fetch-partitions-cached"));
+        assert_eq!(
+            1.0,
+            metric(&metrics, "incremental-fetch-session-eviction-total")
+        );
+    }
+
+    #[test]
+    fn test_less_useful_sessions_are_evicted() {
+        let metrics = Metrics::default();
+        let mut cache = FetchSessionCache::new(2, 10, &metrics);
+        let small = cache.maybe_create_session(0, false, partitions(2));
+        let follower = cache.maybe_create_session(0, true, partitions(1));
+        cache.update_session(small, 1, 9, |_| {}).unwrap();
+        cache.update_session(follower, 1, 9, |_| {}).unwrap();
+
+        // A young client session may be evicted by a follower session only.
+        let client = cache.maybe_create_session(9, false, partitions(10));
+        assert_eq!(INVALID_SESSION_ID, client);
+        let new_follower = cache.maybe_create_session(9, true, partitions(1));
+        assert_ne!(INVALID_SESSION_ID, new_follower);
+        assert!(cache.get(small).is_none());
+
+        // Once past the eviction time, a follower session still outranks any client session.
+        cache.update_session(follower, 2, 15, |_| {}).unwrap();
+        cache.update_session(new_follower, 1, 15, |_| {}).unwrap();
+        assert_eq!(
+            INVALID_SESSION_ID,
+            cache.maybe_create_session(15, false, partitions(100))
+        );
+        // But a larger follower session may evict a smaller one.
+        let larger = cache.maybe_create_session(15, true, partitions(2));
+        assert_ne!(INVALID_SESSION_ID, larger);
+        assert!(cache.get(follower).is_none());
+        assert!(cache.get(new_follower).is_some());
+    }
+
+    #[test]
+    fn test_cached_partitions_are_bounded() {
+        let metrics = Metrics::default();
+        let mut cache = FetchSessionCache::new(10, 10, &metrics).with_max_partitions(10);
+        let follower = cache.maybe_create_session(0, true, partitions(4));
+        let consumer = cache.maybe_create_session(0, false, partitions(4));
+        // Too large for the cache, whatever is evicted.
+        assert_eq!(
+            INVALID_SESSION_ID,
+            cache.maybe_create_session(0, true, partitions(11))
+        );
+        // A follower session evicts the consumer session rather than the follower session.
+        let other = cache.maybe_create_session(1, true, partitions(5));
+        assert_ne!(INVALID_SESSION_ID, other);
+        assert!(cache.get(consumer).is_none());
+        assert!(cache.get(follower).is_some());
+        assert_eq!(9, cache.total_partitions());
+        // A consumer session may not evict young sessions.
+        assert_eq!(
+            INVALID_SESSION_ID,
+            cache.maybe_create_session(2, false, partitions(2))
+        );
+
+        // A consumer session which outgrows the cache is evicted itself.
+        let consumer = cache.maybe_create_session(2, false, partitions(1));
+        assert_eq!(
+            Err(Errors::FetchSessionIdNotFound),
+            cache.update_session(consumer, 1, 3, |session| {
+                session.partitions_mut().extend(topic_partitions("bar", 3))
+            })
+        );
+        assert!(cache.get(consumer).is_none());
+        assert_eq!(9, cache.total_partitions());
+        // A follower session which grows evicts consumer sessions instead.
+        let consumer = cache.maybe_create_session(3, false, partitions(1));
+        assert_eq!(
+            Ok(2),
+            cache.update_session(other, 1, 4, |session| {
+                session.partitions_mut().extend(topic_partitions("bar", 1))
+            })
+        );
+        assert!(cache.get(consumer).is_none());
+        assert_eq!(10, cache.total_partitions());
+        assert_eq!(
+            3.0,
+            metric(&metrics, "incremental-fetch-session-eviction-total")
+        );
+    }
+
+    #[test]
+    fn test_cached_bytes_are_bounded() {
+        let metrics = Metrics::default();
+        let max_bytes = cached_partitions_bytes(&partitions(3));
+        let mut cache = FetchSessionCache::new(10, 10, &metrics).with_max_bytes(max_bytes);
+        let consumer = cache.maybe_create_session(0, false, partitions(2));
+        assert_ne!(INVALID_SESSION_ID, consumer);
+        // Longer topic names take more memory.
+        assert_eq!(
+            INVALID_SESSION_ID,
+            cache.maybe_create_session(1, false, topic_partitions("foo-bar", 1))
+        );
+        assert_ne!(
+            INVALID_SESSION_ID,
+            cache.maybe_create_session(1, false, partitions(1))
+        );
+        assert_eq!(max_bytes, cache.total_bytes());
+        assert_eq!(
+            max_bytes as f64,
+            metric(&metrics, "incremental-fetch-partitions-cached-bytes")
+        );
+
+        // The stale session is evicted for a follower session.
+        cache.maybe_create_session(12, true, topic_partitions("foo-bar", 1));
+        assert!(cache.get(consumer).is_none());
+    }
+
+    #[test]
+    fn test_update_session() {
+        let metrics = Metrics::default();
+        let mut cache = FetchSessionCache::new(10, 10, &metrics);
+        let id = cache.maybe_create_session(0, false, partitions(2));
+        assert_eq!(1, cache.get(id).unwrap().epoch());
+
+        let next = cache
+            .update_session(id, 1, 1, |session| {
+                session
+                    .partitions_mut()
+                    .remove(&TopicPartition::new("foo", 0));
+            })
+            .unwrap();
+        assert_eq!(2, next);
+        assert_eq!(1, cache.total_partitions());
+        assert_eq!(
+            Err(Errors::InvalidFetchSessionEpoch),
+            cache.update_session(id, 1, 2, |_| {})
+        );
+        assert_eq!(
+            Err(Errors::FetchSessionIdNotFound),
+            cache.update_session(-5, 1, 2, |_| {})
+        );
+        assert_eq!(
+            2.0 / 3.0,
+            metric(&metrics, "incremental-fetch-session-hit-ratio")
+        );
+
+        cache.remove(id);
+        assert_eq!(0, cache.size());
+        assert_eq!(0, cache.total_partitions());
+    }
+}
@@ -0,0 +1,54 @@
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::collections::BTreeMap;
+
+/// The epoch or end offset of an OffsetsForLeaderEpoch response when the leader doesn't know
+/// the requested epoch.
+pub const UNDEFINED_EPOCH: i32 = -1;
+pub const UNDEFINED_EPOCH_OFFSET: i64 = -1;
+
+/// A partition of an OffsetsForLeaderEpoch request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochData {
+    /// The epoch the follower knows the leader by, so a stale leader can reject the request.
+    pub current_leader_epoch: i32,
+    /// The epoch to look up the end offset of.
+    pub leader_epoch: i32,
+}
+
+/// A partition of an OffsetsForLeaderEpoch response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochEndOffset {
+    pub error: Errors,
+    /// The largest epoch of the leader not above the requested one.
+    pub leader_epoch: i32,
+    /// The offset the epoch ended at on the leader.
+    pub end_offset: i64,
+}
+
+impl EpochEndOffset {
+    pub fn new(leader_epoch: i32, end_offset: i64) -> Self {
+        Self {
+            error: Errors::None,
+            leader_epoch,
+            end_offset,
+        }
+    }
+
+    pub fn error(error: Errors) -> Self {
+        Self {
+            error,
+            leader_epoch: UNDEFINED_EPOCH,
+            end_offset: UNDEFINED_EPOCH_OFFSET,
+        }
+    }
+}
+
+/// The leader a follower replicates from.
+pub trait LeaderEndPoint {
+    /// Sends an OffsetsForLeaderEpoch request for `partitions` to the leader.
+    fn fetch_epoch_end_offsets(
+        &self,
+        partitions: &BTreeMap<TopicPartition, EpochData>,
+    ) -> Result<BTreeMap<TopicPartition, EpochEndOffset>, ApiError>;
+}
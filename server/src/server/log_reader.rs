@@ -0,0 +1,430 @@
+use crate::server::broker_topic_stats::BrokerTopicStats;
+use rafka_clients::common::isolation_level::IsolationLevel;
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::record::lazy_down_conversion::{
+    DEFAULT_MAX_CHUNK_BYTES, LazyDownConversionRecords,
+};
+use rafka_clients::common::record::legacy_record::{MAGIC_VALUE_V0, MAGIC_VALUE_V1};
+use rafka_clients::common::record::record_batch::{CURRENT_MAGIC_VALUE, RecordBatch};
+use rafka_clients::common::requests::fetch_response::{
+    FetchResponseAbortedTransaction, FetchResponsePartition,
+};
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_storage::{AbortedTransaction, LogError, PartitionLog, Result};
+use std::io;
+
+/// A partition of a fetch request.
+pub struct FetchPartition<'a> {
+    pub topic_partition: TopicPartition,
+    pub log: &'a dyn PartitionLog,
+    /// The high watermark of the partition, which consumers don't read beyond.
+    pub high_watermark: i64,
+    pub fetch_offset: i64,
+    /// The `partition_max_bytes` of the partition in the request.
+    pub max_bytes: usize,
+}
+
+/// What a fetch read from a partition.
+#[derive(Debug)]
+pub struct FetchPartitionData {
+    pub topic_partition: TopicPartition,
+    pub high_watermark: i64,
+    pub log_start_offset: i64,
+    /// The offset a consumer with `isolation.level=read_committed` reads up to, see
+    /// [PartitionLog::last_stable_offset].
+    pub last_stable_offset: i64,
+    pub batches: Result<Vec<RecordBatch>>,
+    /// For `ReadCommitted`, the aborted transactions with records among the batches, whose
+    /// records the consumer skips. `None` for `ReadUncommitted`, as in the response.
+    pub aborted_transactions: Option<Vec<AbortedTransaction>>,
+}
+
+impl FetchPartitionData {
+    /// The partition as a fetch response carries it, read from the leader.
+    pub fn into_response_partition(self, preferred_read_replica: i32) -> FetchResponsePartition {
+        let (error, records) = match self.batches {
+            Ok(batches) => (Errors::None, batches),
+            Err(e) => (fetch_error(&e), vec![]),
+        };
+        FetchResponsePartition {
+            partition_index: self.topic_partition.partition(),
+            error,
+            high_watermark: self.high_watermark,
+            last_stable_offset: self.last_stable_offset,
+            log_start_offset: self.log_start_offset,
+            aborted_transactions: self.aborted_transactions.map(|aborted| {
+                aborted
+                    .into_iter()
+                    .map(|aborted| FetchResponseAbortedTransaction {
+                        producer_id: aborted.producer_id,
+                        first_offset: aborted.first_offset,
+                    })
+                    .collect()
+            }),
+            preferred_read_replica,
+            records,
+        }
+    }
+}
+
+/// The error a fetch reports for a partition whose log could not be read.
+fn fetch_error(error: &LogError) -> Errors {
+    match error {
+        LogError::OffsetOutOfRange { .. } => Errors::OffsetOutOfRange,
+        LogError::ReplicaNotAvailable(_) => Errors::NotLeaderOrFollower,
+        LogError::Record(_) => Errors::CorruptMessage,
+        LogError::Io(_) => Errors::KafkaStorageError,
+        _ => Errors::UnknownServerError,
+    }
+}
+
+/// Reads the partitions of a fetch request in order, each up to its `max_bytes` and all
+/// together up to `fetch_max_bytes`.
+///
+/// The limits are soft for the first partition with records: its first batch is returned
+/// even if it is larger than both, as a batch is never split and a consumer would otherwise
+/// be stuck on it, fetching nothing forever. That is why `max.message.bytes` can be raised
+/// above the fetch sizes of the consumers. The other partitions only return the batches
+/// which fit.
+///
+/// The batches end at the high watermark, or at the last stable offset for
+/// `ReadCommitted`, so that a read_committed consumer never gets the records of a transaction
+/// which may still be aborted. Those fetches also return the transactions aborted among the
+/// batches, as the aborted records stay in the log.
+pub fn read_from_logs(
+    partitions: &[FetchPartition<'_>],
+    fetch_max_bytes: usize,
+    isolation_level: IsolationLevel,
+) -> Vec<FetchPartitionData> {
+    let mut remaining = fetch_max_bytes;
+    let mut min_one_batch = true;
+    partitions
+        .iter()
+        .map(|partition| {
+            let last_stable_offset = partition.log.last_stable_offset(partition.high_watermark);
+            let max_offset =
+                isolation_level.max_offset(partition.high_watermark, last_stable_offset);
+            let max_bytes = partition.max_bytes.min(remaining);
+            let batches = partition
+                .log
+                .read(partition.fetch_offset, max_bytes)
+                .map(|batches| {
+                    let mut bytes = 0;
+                    let batches: Vec<RecordBatch> = batches
+                        .into_iter()
+                        .take_while(|batch| batch.last_offset() < max_offset)
+                        .take_while(|batch| {
+                            let size = batch.size_in_bytes();
+                            let fits = bytes + size <= max_bytes || (min_one_batch && bytes == 0);
+                            if fits {
+                                bytes += size;
+                            }
+                            fits
+                        })
+                        .collect();
+                    if !batches.is_empty() {
+                        min_one_batch = false;
+                    }
+                    remaining = remaining.saturating_sub(bytes);
+                    batches
+                });
+            let aborted_transactions = match (&batches, isolation_level) {
+                (Ok(batches), IsolationLevel::ReadCommitted) => {
+                    let upper_bound_offset = batches
+                        .last()
+                        .map_or(partition.fetch_offset, RecordBatch::next_offset);
+                    Some(
+                        partition
+                            .log
+                            .aborted_transactions(partition.fetch_offset, upper_bound_offset),
+                    )
+                }
+                _ => None,
+            };
+            FetchPartitionData {
+                topic_partition: partition.topic_partition.clone(),
+                high_watermark: partition.high_watermark,
+                log_start_offset: partition.log.log_start_offset(),
+                last_stable_offset,
+                batches,
+                aborted_transactions,
+            }
+        })
+        .collect()
+}
+
+/// The message format the responses to fetch requests of `version` carry: the v2 batches
+/// from version 4 on, the v1 messages in versions 2 and 3 and the v0 messages before.
+pub fn magic_for_fetch_version(version: i16) -> i8 {
+    match version {
+        ..=1 => MAGIC_VALUE_V0,
+        2..=3 => MAGIC_VALUE_V1,
+        _ => CURRENT_MAGIC_VALUE,
+    }
+}
+
+/// The records of a partition as a fetch response carries them.
+#[derive(Debug)]
+pub enum FetchRecords {
+    Batches(Vec<RecordBatch>),
+    /// The batches, converted as they are sent for a client which only reads an older format.
+    DownConverted(LazyDownConversionRecords),
+}
+
+impl FetchRecords {
+    /// The records of `batches`, read from `fetch_offset` on, for a fetch request of
+    /// `fetch_version`.
+    pub fn for_fetch_version(
+        batches: Vec<RecordBatch>,
+        fetch_offset: i64,
+        fetch_version: i16,
+    ) -> Self {
+        match magic_for_fetch_version(fetch_version) {
+            CURRENT_MAGIC_VALUE => FetchRecords::Batches(batches),
+            magic => FetchRecords::DownConverted(LazyDownConversionRecords::new(
+                batches,
+                magic,
+                fetch_offset,
+            )),
+        }
+    }
+}
+
+/// Writes the down-converted records of `topic` to `out` a chunk at a time, counting the
+/// converted messages in the fetch message conversions of `stats`.
+pub fn write_down_converted(
+    records: &LazyDownConversionRecords,
+    topic: &str,
+    stats: &BrokerTopicStats,
+    out: &mut impl io::Write,
+) -> io::Result<()> {
+    for chunk in records.chunks(DEFAULT_MAX_CHUNK_BYTES) {
+        stats.record_fetch_message_conversions(topic, chunk.converted_records);
+        out.write_all(&chunk.bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::broker_topic_stats::{
+        BROKER_TOPIC_METRICS_GROUP, FETCH_MESSAGE_CONVERSIONS_TOTAL, TOPIC_TAG,
+    };
+    use rafka_clients::common::metrics::Metrics;
+    use rafka_clients::common::record::control_record::{ControlRecordType, EndTransactionMarker};
+    use rafka_clients::common::record::legacy_record::LegacyRecord;
+    use rafka_clients::common::record::record_batch::Record;
+    use rafka_clients::common::requests::fetch_response::NO_PREFERRED_READ_REPLICA;
+    use rafka_storage::{AppendOrigin, MemoryLog};
+
+    fn log(partition: i32, batch_sizes: &[usize]) -> MemoryLog {
+        let tp = TopicPartition::new("foo", partition);
+        let mut log = MemoryLog::new(tp, 0);
+        for size in batch_sizes {
+            let batch = RecordBatch::new(0, vec![Record::new(0, None, Some(&vec![0; *size]))]);
+            log.append_as_leader(batch, 0, AppendOrigin::Client)
+                .unwrap();
+        }
+        log
+    }
+
+    fn batch_counts(data: &[FetchPartitionData]) -> Vec<usize> {
+        data.iter()
+            .map(|d| d.batches.as_ref().unwrap().len())
+            .collect()
+    }
+
+    #[test]
+    fn test_first_batch_is_returned_even_if_too_large() {
+        let large = log(0, &[5000, 100]);
+        let small = log(1, &[100, 100]);
+        let fetch = |logs: [&MemoryLog; 2]| {
+            let partitions: Vec<FetchPartition<'_>> = logs
+                .iter()
+                .map(|log| FetchPartition {
+                    topic_partition: log.topic_partition().clone(),
+                    log: *log,
+                    high_watermark: log.log_end_offset(),
+                    fetch_offset: 0,
+                    max_bytes: 1000,
+                })
+                .collect();
+            batch_counts(&read_from_logs(
+                &partitions,
+                2000,
+                IsolationLevel::ReadUncommitted,
+            ))
+        };
+
+        // The first partition returns its large batch, which exhausts the fetch.
+        assert_eq!(vec![1, 0], fetch([&large, &small]));
+        // Once a partition returned records, a too large batch is left out.
+        assert_eq!(vec![2, 0], fetch([&small, &large]));
+    }
+
+    #[test]
+    fn test_fetch_max_bytes_is_shared() {
+        let first = log(0, &[300, 300, 300]);
+        let second = log(1, &[300, 300]);
+        let partitions = [&first, &second].map(|log| FetchPartition {
+            topic_partition: log.topic_partition().clone(),
+            high_watermark: log.log_end_offset(),
+            log,
+            fetch_offset: 0,
+            max_bytes: 10_000,
+        });
+        let size = first.read(0, 0).unwrap()[0].size_in_bytes();
+        assert_eq!(
+            vec![3, 1],
+            batch_counts(&read_from_logs(
+                &partitions,
+                size * 4,
+                IsolationLevel::ReadUncommitted
+            ))
+        );
+    }
+
+    #[test]
+    fn test_isolation_level_bounds_the_batches() {
+        let mut log = log(0, &[10]);
+        let mut transactional =
+            RecordBatch::new(0, vec![Record::new(0, None, Some(b"v"))]).with_producer(1, 0, 0);
+        transactional.set_transactional(true);
+        log.append_as_leader(transactional, 0, AppendOrigin::Client)
+            .unwrap();
+        log.append_as_leader(
+            RecordBatch::new(0, vec![Record::new(0, None, Some(b"v"))]),
+            0,
+            AppendOrigin::Client,
+        )
+        .unwrap();
+        let fetch = |high_watermark, isolation_level| {
+            let partitions = [FetchPartition {
+                topic_partition: log.topic_partition().clone(),
+                log: &log,
+                high_watermark,
+                fetch_offset: 0,
+                max_bytes: 10_000,
+            }];
+            let data = read_from_logs(&partitions, 10_000, isolation_level).remove(0);
+            (
+                data.batches.unwrap().len(),
+                data.high_watermark,
+                data.last_stable_offset,
+            )
+        };
+
+        assert_eq!((3, 3, 1), fetch(3, IsolationLevel::ReadUncommitted));
+        assert_eq!((2, 2, 1), fetch(2, IsolationLevel::ReadUncommitted));
+        // The transaction starting at offset 1 is still ongoing.
+        assert_eq!((1, 3, 1), fetch(3, IsolationLevel::ReadCommitted));
+        assert_eq!((0, 0, 0), fetch(0, IsolationLevel::ReadCommitted));
+    }
+
+    #[test]
+    fn test_read_committed_fetches_return_the_aborted_transactions() {
+        let mut log = log(0, &[10]);
+        let mut transactional =
+            RecordBatch::new(0, vec![Record::new(0, None, Some(b"v"))]).with_producer(1, 0, 0);
+        transactional.set_transactional(true);
+        log.append_as_leader(transactional, 0, AppendOrigin::Client)
+            .unwrap();
+        let abort = EndTransactionMarker::new(ControlRecordType::Abort, 0);
+        log.append_as_leader(
+            RecordBatch::end_transaction_marker(1, 0, abort, 0),
+            0,
+            AppendOrigin::Coordinator,
+        )
+        .unwrap();
+        let fetch = |fetch_offset, isolation_level| {
+            let partitions = [FetchPartition {
+                topic_partition: log.topic_partition().clone(),
+                log: &log,
+                high_watermark: log.log_end_offset(),
+                fetch_offset,
+                max_bytes: 10_000,
+            }];
+            read_from_logs(&partitions, 10_000, isolation_level)
+                .remove(0)
+                .aborted_transactions
+        };
+
+        let aborted = AbortedTransaction {
+            producer_id: 1,
+            first_offset: 1,
+            last_offset: 2,
+        };
+        assert_eq!(Some(vec![aborted]), fetch(0, IsolationLevel::ReadCommitted));
+        assert_eq!(Some(vec![]), fetch(3, IsolationLevel::ReadCommitted));
+        assert_eq!(None, fetch(0, IsolationLevel::ReadUncommitted));
+    }
+
+    #[test]
+    fn test_response_partitions() {
+        let log = log(3, &[10, 10]);
+        let fetch = |fetch_offset| {
+            let partitions = [FetchPartition {
+                topic_partition: log.topic_partition().clone(),
+                log: &log,
+                high_watermark: log.log_end_offset(),
+                fetch_offset,
+                max_bytes: 10_000,
+            }];
+            read_from_logs(&partitions, 10_000, IsolationLevel::ReadCommitted)
+                .remove(0)
+                .into_response_partition(NO_PREFERRED_READ_REPLICA)
+        };
+
+        let partition = fetch(0);
+        assert_eq!(3, partition.partition_index);
+        assert_eq!(Errors::None, partition.error);
+        assert_eq!(2, partition.high_watermark);
+        assert_eq!(2, partition.last_stable_offset);
+        assert_eq!(0, partition.log_start_offset);
+        assert_eq!(Some(vec![]), partition.aborted_transactions);
+        assert_eq!(log.read(0, usize::MAX).unwrap(), partition.records);
+
+        let partition = fetch(5);
+        assert_eq!(Errors::OffsetOutOfRange, partition.error);
+        assert!(partition.records.is_empty());
+    }
+
+    #[test]
+    fn test_down_conversion_for_old_fetch_versions() {
+        let log = log(0, &[10, 10, 10]);
+        let batches = log.read(1, usize::MAX).unwrap();
+        assert!(matches!(
+            FetchRecords::for_fetch_version(batches.clone(), 1, 4),
+            FetchRecords::Batches(_)
+        ));
+
+        let metrics = Metrics::default();
+        let stats = BrokerTopicStats::new(metrics.clone());
+        for (fetch_version, magic) in [(1, MAGIC_VALUE_V0), (3, MAGIC_VALUE_V1)] {
+            let FetchRecords::DownConverted(records) =
+                FetchRecords::for_fetch_version(batches.clone(), 1, fetch_version)
+            else {
+                panic!("fetch version {fetch_version} should be down-converted");
+            };
+            let mut out = vec![];
+            write_down_converted(&records, "foo", &stats, &mut out).unwrap();
+            assert_eq!(records.size_in_bytes(), out.len());
+            let messages = LegacyRecord::read_all(&out).unwrap();
+            assert_eq!(
+                vec![(1, magic), (2, magic)],
+                messages
+                    .iter()
+                    .map(|m| (m.offset, m.magic))
+                    .collect::<Vec<_>>()
+            );
+        }
+        let total = metrics.metric_value(&metrics.metric_name(
+            FETCH_MESSAGE_CONVERSIONS_TOTAL,
+            BROKER_TOPIC_METRICS_GROUP,
+            "",
+            &[(TOPIC_TAG, "foo")],
+        ));
+        assert_eq!(Some(4.0), total);
+    }
+}
@@ -0,0 +1,392 @@
+//! Names the metrics of a broker for export, e.g. to Prometheus.
+//!
+//! The metrics are registered under their rafka group, name and tags. A [MetricNamingScheme]
+//! decides the name and labels they are exported with: [NativeNamingScheme] derives them from
+//! the rafka names, while [KafkaNamingScheme] gives the metrics which have a Kafka equivalent
+//! the MBean name of the Kafka broker, e.g. `kafka.server:type=BrokerTopicMetrics,name=BytesInPerSec`,
+//! exported as the Prometheus JMX exporter does with the rules most Kafka dashboards are built
+//! on, e.g. `kafka_server_brokertopicmetrics_bytesin_total`, so that they work unchanged
+//! against rafka.
+
+use crate::network::request_metrics::{
+    REQUEST_METRICS_GROUP, REQUEST_RATE, REQUEST_TAG, REQUEST_TIME_AVG, REQUEST_TIME_MAX,
+};
+use crate::server::broker_server_metrics::{
+    BROKER_METRICS_GROUP, BROKER_STATE, LOG_MANAGER_METRICS_GROUP, REMAINING_LOGS_TO_RECOVER,
+    REMAINING_SEGMENTS_TO_RECOVER,
+};
+use crate::server::broker_topic_stats::{
+    BROKER_TOPIC_METRICS_GROUP, BYTES_IN_RATE, BYTES_IN_TOTAL, BYTES_OUT_RATE, BYTES_OUT_TOTAL,
+    FAILED_PRODUCE_RECORDS_RATE, FAILED_PRODUCE_RECORDS_TOTAL, FETCH_MESSAGE_CONVERSIONS_RATE,
+    FETCH_MESSAGE_CONVERSIONS_TOTAL, MESSAGES_IN_RATE, MESSAGES_IN_TOTAL, ProduceFailureReason,
+    REASON_TAG, TOPIC_TAG,
+};
+use rafka_clients::common::metrics::{MetricName, Metrics};
+use rafka_storage::log_metrics::{
+    LOG_END_OFFSET, LOG_METRICS_GROUP, LOG_START_OFFSET, NUM_LOG_SEGMENTS, SIZE,
+};
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+/// The name and labels a metric is exported with.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExportedMetricName {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Decides how the metrics of the broker are named once exported.
+pub trait MetricNamingScheme: Send + Sync {
+    /// The exported name of `metric_name`, or `None` if the metric is not exported.
+    fn exported_name(&self, metric_name: &MetricName) -> Option<ExportedMetricName>;
+}
+
+/// Exports every metric as `rafka_<group>_<name>`, labelled with its tags.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeNamingScheme;
+
+impl MetricNamingScheme for NativeNamingScheme {
+    fn exported_name(&self, metric_name: &MetricName) -> Option<ExportedMetricName> {
+        Some(ExportedMetricName {
+            name: sanitize(&format!(
+                "rafka_{}_{}",
+                metric_name.group(),
+                metric_name.name()
+            )),
+            labels: metric_name
+                .tags()
+                .iter()
+                .map(|(tag, value)| (sanitize(tag), value.clone()))
+                .collect(),
+        })
+    }
+}
+
+/// The MBean a Kafka broker exposes a metric as, and the attribute holding its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KafkaMBeanName {
+    /// `kafka.server`, `kafka.network` or `kafka.log`.
+    pub domain: &'static str,
+    pub type_name: &'static str,
+    pub name: String,
+    /// The key properties after `name`, in the order of the Kafka MBean.
+    pub properties: Vec<(&'static str, String)>,
+    /// `Count`, `OneMinuteRate`, `Mean`, `Max` or `Value`.
+    pub attribute: &'static str,
+}
+
+impl KafkaMBeanName {
+    /// The JMX object name, e.g. `kafka.server:type=BrokerTopicMetrics,name=BytesInPerSec,topic=foo`.
+    pub fn object_name(&self) -> String {
+        let mut object_name = format!("{}:type={},name={}", self.domain, self.type_name, self.name);
+        for (key, value) in &self.properties {
+            let _ = write!(object_name, ",{key}={value}");
+        }
+        object_name
+    }
+
+    /// The name the Prometheus JMX exporter gives the attribute with the usual Kafka rules:
+    /// `kafka_<domain>_<type>_<name>`, lowercased, where the `Count` of a `...PerSec` meter
+    /// becomes a `_total` counter without the `PerSec`, a `Value` takes no suffix and the
+    /// other attributes are appended.
+    pub fn exported_name(&self) -> ExportedMetricName {
+        let domain = self.domain.trim_start_matches("kafka.");
+        let prefix = format!("kafka_{domain}_{}", self.type_name);
+        let name = match (self.attribute, self.name.strip_suffix("PerSec")) {
+            ("Count", Some(meter)) => format!("{prefix}_{meter}_total"),
+            ("Value", _) => format!("{prefix}_{}", self.name),
+            (attribute, _) => format!("{prefix}_{}_{attribute}", self.name),
+        };
+        ExportedMetricName {
+            name: sanitize(&name).to_lowercase(),
+            labels: self
+                .properties
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Exports the metrics which have a Kafka equivalent under the names a Kafka broker exports
+/// them with, see [KafkaMBeanName::exported_name], and leaves the others out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KafkaNamingScheme;
+
+impl KafkaNamingScheme {
+    /// The Kafka MBean of `metric_name`, if Kafka has an equivalent metric.
+    pub fn mbean_name(&self, metric_name: &MetricName) -> Option<KafkaMBeanName> {
+        let tags = metric_name.tags();
+        let tag = |key: &str| tags.get(key).cloned();
+        let mbean = |domain, type_name, name: &str, properties, attribute| {
+            Some(KafkaMBeanName {
+                domain,
+                type_name,
+                name: name.to_string(),
+                properties,
+                attribute,
+            })
+        };
+        match (metric_name.group(), metric_name.name()) {
+            (BROKER_TOPIC_METRICS_GROUP, name) => {
+                let (meter, attribute) = match name {
+                    BYTES_IN_TOTAL => ("BytesInPerSec", "Count"),
+                    BYTES_IN_RATE => ("BytesInPerSec", "OneMinuteRate"),
+                    BYTES_OUT_TOTAL => ("BytesOutPerSec", "Count"),
+                    BYTES_OUT_RATE => ("BytesOutPerSec", "OneMinuteRate"),
+                    MESSAGES_IN_TOTAL => ("MessagesInPerSec", "Count"),
+                    MESSAGES_IN_RATE => ("MessagesInPerSec", "OneMinuteRate"),
+                    FETCH_MESSAGE_CONVERSIONS_TOTAL => ("FetchMessageConversionsPerSec", "Count"),
+                    FETCH_MESSAGE_CONVERSIONS_RATE => {
+                        ("FetchMessageConversionsPerSec", "OneMinuteRate")
+                    }
+                    FAILED_PRODUCE_RECORDS_TOTAL | FAILED_PRODUCE_RECORDS_RATE => {
+                        let meter = kafka_failed_records_meter(tags.get(REASON_TAG)?)?;
+                        let attribute = match name {
+                            FAILED_PRODUCE_RECORDS_TOTAL => "Count",
+                            _ => "OneMinuteRate",
+                        };
+                        (meter, attribute)
+                    }
+                    _ => return None,
+                };
+                let properties = tag(TOPIC_TAG)
+                    .map(|topic| vec![("topic", topic)])
+                    .unwrap_or_default();
+                mbean(
+                    "kafka.server",
+                    "BrokerTopicMetrics",
+                    meter,
+                    properties,
+                    attribute,
+                )
+            }
+            // Kafka has no per-client request metrics.
+            (REQUEST_METRICS_GROUP, _) if tags.len() > 1 => None,
+            (REQUEST_METRICS_GROUP, name) => {
+                let (metric, attribute) = match name {
+                    REQUEST_RATE => ("RequestsPerSec", "OneMinuteRate"),
+                    REQUEST_TIME_AVG => ("TotalTimeMs", "Mean"),
+                    REQUEST_TIME_MAX => ("TotalTimeMs", "Max"),
+                    _ => return None,
+                };
+                mbean(
+                    "kafka.network",
+                    "RequestMetrics",
+                    metric,
+                    vec![("request", tag(REQUEST_TAG)?)],
+                    attribute,
+                )
+            }
+            (BROKER_METRICS_GROUP, BROKER_STATE) => mbean(
+                "kafka.server",
+                "KafkaServer",
+                "BrokerState",
+                vec![],
+                "Value",
+            ),
+            (LOG_MANAGER_METRICS_GROUP, REMAINING_LOGS_TO_RECOVER) => mbean(
+                "kafka.log",
+                "LogManager",
+                "remainingLogsToRecover",
+                vec![("dir", tag("dir")?)],
+                "Value",
+            ),
+            (LOG_MANAGER_METRICS_GROUP, REMAINING_SEGMENTS_TO_RECOVER) => mbean(
+                "kafka.log",
+                "LogManager",
+                "remainingSegmentsToRecover",
+                vec![("dir", tag("dir")?), ("threadNum", tag("thread-num")?)],
+                "Value",
+            ),
+            (LOG_METRICS_GROUP, name) => {
+                let gauge = match name {
+                    SIZE => "Size",
+                    NUM_LOG_SEGMENTS => "NumLogSegments",
+                    LOG_START_OFFSET => "LogStartOffset",
+                    LOG_END_OFFSET => "LogEndOffset",
+                    _ => return None,
+                };
+                mbean(
+                    "kafka.log",
+                    "Log",
+                    gauge,
+                    vec![("topic", tag("topic")?), ("partition", tag("partition")?)],
+                    "Value",
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The Kafka meter of the records rejected for `reason`, for the reasons Kafka counts.
+fn kafka_failed_records_meter(reason: &str) -> Option<&'static str> {
+    if reason == ProduceFailureReason::InvalidCrc.name() {
+        Some("InvalidMessageCrcRecordsPerSec")
+    } else if reason == ProduceFailureReason::InvalidSequence.name() {
+        Some("InvalidOffsetOrSequenceRecordsPerSec")
+    } else {
+        None
+    }
+}
+
+impl MetricNamingScheme for KafkaNamingScheme {
+    fn exported_name(&self, metric_name: &MetricName) -> Option<ExportedMetricName> {
+        self.mbean_name(metric_name)
+            .map(|mbean| mbean.exported_name())
+    }
+}
+
+/// Replaces the characters Prometheus does not allow in names with `_`.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Writes the current value of the metrics `scheme` exports in the Prometheus text format,
+/// sorted by name and labels.
+pub fn write_prometheus(
+    metrics: &Metrics,
+    scheme: &dyn MetricNamingScheme,
+    out: &mut impl fmt::Write,
+) -> fmt::Result {
+    let now_ms = metrics.time().milliseconds();
+    let mut values: Vec<(ExportedMetricName, f64)> = metrics
+        .metrics()
+        .iter()
+        .filter_map(|metric| {
+            let name = scheme.exported_name(metric.metric_name())?;
+            Some((name, metric.metric_value(now_ms)))
+        })
+        .collect();
+    values.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (name, value) in values {
+        out.write_str(&name.name)?;
+        if !name.labels.is_empty() {
+            let labels: Vec<String> = name
+                .labels
+                .iter()
+                .map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value)))
+                .collect();
+            write!(out, "{{{}}}", labels.join(","))?;
+        }
+        writeln!(out, " {value}")?;
+    }
+    Ok(())
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::request_metrics::{RequestMetrics, RequestMetricsConfig};
+    use crate::server::broker_topic_stats::BrokerTopicStats;
+
+    #[test]
+    fn test_kafka_names() {
+        let metrics = Metrics::default();
+        let scheme = KafkaNamingScheme;
+        let name = |name, group, tags: &[(&str, &str)]| {
+            scheme.mbean_name(&metrics.metric_name(name, group, "", tags))
+        };
+
+        let bytes_in = name(
+            BYTES_IN_TOTAL,
+            BROKER_TOPIC_METRICS_GROUP,
+            &[(TOPIC_TAG, "foo")],
+        )
+        .unwrap();
+        assert_eq!(
+            "kafka.server:type=BrokerTopicMetrics,name=BytesInPerSec,topic=foo",
+            bytes_in.object_name()
+        );
+        assert_eq!(
+            ExportedMetricName {
+                name: "kafka_server_brokertopicmetrics_bytesin_total".to_string(),
+                labels: BTreeMap::from([("topic".to_string(), "foo".to_string())]),
+            },
+            bytes_in.exported_name()
+        );
+
+        let request_time = name(
+            REQUEST_TIME_AVG,
+            REQUEST_METRICS_GROUP,
+            &[(REQUEST_TAG, "Produce")],
+        )
+        .unwrap();
+        assert_eq!(
+            "kafka.network:type=RequestMetrics,name=TotalTimeMs,request=Produce",
+            request_time.object_name()
+        );
+        assert_eq!(
+            "kafka_network_requestmetrics_totaltimems_mean",
+            request_time.exported_name().name
+        );
+        assert_eq!(
+            "kafka_log_logmanager_remainingsegmentstorecover",
+            name(
+                REMAINING_SEGMENTS_TO_RECOVER,
+                LOG_MANAGER_METRICS_GROUP,
+                &[("dir", "/data"), ("thread-num", "0")],
+            )
+            .unwrap()
+            .exported_name()
+            .name
+        );
+
+        // Metrics without a Kafka equivalent are not exported.
+        assert_eq!(
+            None,
+            name(
+                FAILED_PRODUCE_RECORDS_TOTAL,
+                BROKER_TOPIC_METRICS_GROUP,
+                &[(REASON_TAG, "record-too-large")],
+            )
+        );
+        assert_eq!(
+            None,
+            name(
+                REQUEST_RATE,
+                REQUEST_METRICS_GROUP,
+                &[(REQUEST_TAG, "Produce"), ("client-id", "app")],
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_prometheus() {
+        let metrics = Metrics::default();
+        let stats = BrokerTopicStats::new(metrics.clone());
+        stats.record_produce("foo", 100, 2);
+        let requests = RequestMetrics::new(metrics.clone(), RequestMetricsConfig::default());
+        requests.record("Fetch", "app", "User:alice", 10, 5.0);
+
+        let mut kafka = String::new();
+        write_prometheus(&metrics, &KafkaNamingScheme, &mut kafka).unwrap();
+        let lines: Vec<&str> = kafka.lines().collect();
+        assert!(lines.contains(&"kafka_server_brokertopicmetrics_bytesin_total 100"));
+        assert!(
+            lines.contains(&"kafka_server_brokertopicmetrics_bytesin_total{topic=\"foo\"} 100")
+        );
+        assert!(
+            lines.contains(&"kafka_network_requestmetrics_totaltimems_max{request=\"Fetch\"} 5")
+        );
+        assert!(!kafka.contains("rafka_"));
+
+        let mut native = String::new();
+        write_prometheus(&metrics, &NativeNamingScheme, &mut native).unwrap();
+        assert!(
+            native
+                .lines()
+                .any(|line| line == "rafka_broker_topic_metrics_bytes_in_total{topic=\"foo\"} 100")
+        );
+    }
+}
@@ -1,2 +1,21 @@
+pub mod broker_lifecycle_manager;
+pub mod broker_server_metrics;
+pub mod broker_topic_stats;
+pub mod client_metrics_manager;
+pub mod client_quota_manager;
+pub mod client_quota_metadata_manager;
+pub mod consumer_lag_metrics;
+pub mod delayed_operation_purgatory;
+pub mod dynamic_config_publisher;
+pub mod fetch_session;
+pub mod leader_end_point;
+pub mod log_reader;
+pub mod metrics_naming;
+pub mod produce_memory_guard;
 pub mod raft_config;
+pub mod record_validator;
+pub mod replica_fetcher;
+pub mod replica_selector;
 pub mod replication_configs;
+pub mod replication_quota_manager;
+pub mod request_deadline;
@@ -0,0 +1,159 @@
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use rafka_clients::common::requests::produce_request::ProduceRequest;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::debug;
+
+/// Bounds the memory held by the produce requests being handled.
+///
+/// A produce request is read in place from its frame, see [ProduceRequest], so the frame is
+/// held until the batches are appended, and with `acks=all` until the followers fetched them.
+/// Each request reserves the size of its body before it is parsed, and the reservation is
+/// released when its [ProduceMemoryPermit] is dropped. A request which would take the bytes
+/// in flight above `produce.max.in.flight.bytes` is rejected instead of being parsed, so a
+/// storm of large produce requests makes producers back off and retry rather than exhausting
+/// the memory of the broker.
+#[derive(Debug, Clone)]
+pub struct ProduceMemoryGuard {
+    budget: usize,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl ProduceMemoryGuard {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// The bytes reserved by the permits not dropped yet.
+    pub fn in_flight_bytes(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Reserves `bytes`, failing with `RECORD_LIST_TOO_LARGE` if they exceed the budget on
+    /// their own, or with the retriable `THROTTLING_QUOTA_EXCEEDED` if the budget is taken by
+    /// other requests.
+    pub fn try_acquire(&self, bytes: usize) -> Result<ProduceMemoryPermit, ApiError> {
+        if bytes > self.budget {
+            return Err(ApiError::new(
+                Errors::RecordListTooLarge,
+                format!(
+                    "The produce request of {bytes} bytes exceeds the in-flight budget of {} bytes",
+                    self.budget
+                ),
+            ));
+        }
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |in_flight| {
+                (in_flight + bytes <= self.budget).then_some(in_flight + bytes)
+            })
+            .map_err(|in_flight| {
+                debug!(
+                    "Rejecting a produce request of {bytes} bytes, {in_flight} of the {} bytes \
+                    budget are in flight",
+                    self.budget
+                );
+                ApiError::new(
+                    Errors::ThrottlingQuotaExceeded,
+                    format!(
+                        "The broker is handling {in_flight} bytes of produce requests, the \
+                        request of {bytes} bytes would exceed the budget of {} bytes",
+                        self.budget
+                    ),
+                )
+            })?;
+        Ok(ProduceMemoryPermit {
+            bytes,
+            in_flight: Arc::clone(&self.in_flight),
+        })
+    }
+
+    /// Reserves the size of `body` and parses it as a produce request of `version`. The
+    /// permit must be held as long as the request.
+    pub fn parse<'a>(
+        &self,
+        version: i16,
+        body: &'a [u8],
+    ) -> Result<(ProduceRequest<'a>, ProduceMemoryPermit), ApiError> {
+        let permit = self.try_acquire(body.len())?;
+        let request = ProduceRequest::parse(version, body)
+            .map_err(|e| ApiError::new(Errors::InvalidRequest, e.to_string()))?;
+        Ok((request, permit))
+    }
+}
+
+/// Bytes reserved from a [ProduceMemoryGuard], released on drop.
+#[derive(Debug)]
+pub struct ProduceMemoryPermit {
+    bytes: usize,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl ProduceMemoryPermit {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl Drop for ProduceMemoryPermit {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(self.bytes, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let guard = ProduceMemoryGuard::new(100);
+        let first = guard.try_acquire(60).unwrap();
+        assert_eq!(60, guard.in_flight_bytes());
+        assert_eq!(
+            Errors::ThrottlingQuotaExceeded,
+            guard.try_acquire(41).unwrap_err().error()
+        );
+        let second = guard.try_acquire(40).unwrap();
+        assert_eq!(100, guard.in_flight_bytes());
+
+        drop(first);
+        assert_eq!(40, guard.in_flight_bytes());
+        let _third = guard.try_acquire(60).unwrap();
+        drop(second);
+        assert_eq!(60, guard.in_flight_bytes());
+        assert_eq!(
+            Errors::RecordListTooLarge,
+            guard.try_acquire(101).unwrap_err().error()
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let guard = ProduceMemoryGuard::new(100);
+        // A v3 request without transactional id and topics.
+        let mut body = Vec::new();
+        body.extend_from_slice(&(-1i16).to_be_bytes());
+        body.extend_from_slice(&1i16.to_be_bytes());
+        body.extend_from_slice(&1_000i32.to_be_bytes());
+        body.extend_from_slice(&0i32.to_be_bytes());
+        let (request, permit) = guard.parse(3, &body).unwrap();
+        assert_eq!(1, request.acks());
+        assert_eq!(body.len(), guard.in_flight_bytes());
+        drop(permit);
+
+        let error = guard.parse(3, &body[..5]).unwrap_err();
+        assert_eq!(Errors::InvalidRequest, error.error());
+        assert_eq!(0, guard.in_flight_bytes());
+        assert_eq!(
+            Errors::RecordListTooLarge,
+            guard.parse(3, &[0; 101]).unwrap_err().error()
+        );
+    }
+}
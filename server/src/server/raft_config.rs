@@ -1,4 +1,10 @@
 use easy_config_def::prelude::*;
+use rafka_metadata::snapshot_file::SnapshotCompression;
+use rafka_raft::QuorumConfig;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+use thiserror::Error;
 
 pub const PROCESS_ROLES_CONFIG: &str = "process.roles";
 const PROCESS_ROLES_DOC: &str = "The roles that this process plays: 'broker', 'controller', \
@@ -12,11 +18,85 @@ pub const CONTROLLER_LISTENER_NAMES_CONFIG: &str = "controller.listener.names";
 const CONTROLLER_LISTENER_NAMES_DOC: &str = "A comma-separated list of the names of the listeners used by the controller. This is required \
     when communicating with the controller quorum, the broker will always use the first listener in this list.";
 
+pub const QUORUM_VOTERS_CONFIG: &str = "controller.quorum.voters";
+const QUORUM_VOTERS_DOC: &str = "Map of id/endpoint information for the set of voters in a \
+comma-separated list of <code>{id}@{host}:{port}</code> entries. For example: \
+<code>1@localhost:9092,2@localhost:9093,3@localhost:9094</code>";
+
+pub const QUORUM_ELECTION_TIMEOUT_MS_CONFIG: &str = "controller.quorum.election.timeout.ms";
+const QUORUM_ELECTION_TIMEOUT_MS_DEFAULT: i64 = 1_000;
+const QUORUM_ELECTION_TIMEOUT_MS_DOC: &str = "Maximum time in milliseconds to wait without \
+being able to fetch from the leader before triggering a new election. The actual timeout is \
+randomized between this value and twice it, so that the voters don't all stand at once.";
+
+pub const QUORUM_ELECTION_BACKOFF_MAX_MS_CONFIG: &str = "controller.quorum.election.backoff.max.ms";
+const QUORUM_ELECTION_BACKOFF_MAX_MS_DEFAULT: i64 = 1_000;
+const QUORUM_ELECTION_BACKOFF_MAX_MS_DOC: &str = "Maximum time in milliseconds before starting \
+new elections. This is used in the binary exponential backoff mechanism that helps prevent \
+gridlocked elections.";
+
+pub const QUORUM_FETCH_TIMEOUT_MS_CONFIG: &str = "controller.quorum.fetch.timeout.ms";
+const QUORUM_FETCH_TIMEOUT_MS_DEFAULT: i64 = 2_000;
+const QUORUM_FETCH_TIMEOUT_MS_DOC: &str = "Maximum time without a successful fetch from the \
+current leader before becoming a candidate and triggering an election for voters.";
+
+pub const QUORUM_REQUEST_TIMEOUT_MS_CONFIG: &str = "controller.quorum.request.timeout.ms";
+const QUORUM_REQUEST_TIMEOUT_MS_DEFAULT: i64 = 2_000;
+const QUORUM_REQUEST_TIMEOUT_MS_DOC: &str = "The configuration controls the maximum amount of \
+time the client will wait for the response of a request. If the response is not received \
+before the timeout elapses the client will resend the request if necessary or fail the \
+request if retries are exhausted.";
+
 pub const SERVER_MAX_STARTUP_TIME_MS_CONFIG: &str = "server.max.startup.time.ms";
 const SERVER_MAX_STARTUP_TIME_MS_DEFAULT: u32 = u32::MAX;
 const SERVER_MAX_STARTUP_TIME_MS_DOC: &str = "The maximum number of milliseconds we will wait \
 for the server to come up. By default there is no limit. This should be used for testing only.";
 
+pub const METADATA_SNAPSHOT_COMPRESSION_TYPE_CONFIG: &str = "metadata.snapshot.compression.type";
+const METADATA_SNAPSHOT_COMPRESSION_TYPE_DOC: &str = "The compression of the metadata snapshots \
+written by this node: none or zstd. Snapshots are readable whatever compression they were \
+written with, so this can be changed at any time. By default, snapshots are not compressed.";
+
+pub const METADATA_LOG_DIR_CONFIG: &str = "metadata.log.dir";
+const METADATA_LOG_DIR_DOC: &str = "This configuration determines where we put the metadata log. \
+If it is not set, the metadata log is placed in the first log directory from log.dirs. Keeping it \
+on a disk of its own stops the load of the data logs from delaying the metadata log.";
+
+pub const METADATA_LOG_SEGMENT_BYTES_CONFIG: &str = "metadata.log.segment.bytes";
+const METADATA_LOG_SEGMENT_BYTES_DEFAULT: i32 = 1024 * 1024 * 1024;
+const METADATA_LOG_SEGMENT_BYTES_DOC: &str = "The maximum size of a single metadata log file.";
+
+pub const METADATA_LOG_SEGMENT_MS_CONFIG: &str = "metadata.log.segment.ms";
+const METADATA_LOG_SEGMENT_MS_DEFAULT: i64 = 7 * 24 * 60 * 60 * 1000;
+const METADATA_LOG_SEGMENT_MS_DOC: &str = "The maximum time before a new metadata log file \
+is rolled out (in milliseconds).";
+
+pub const METADATA_MAX_RETENTION_BYTES_CONFIG: &str = "metadata.max.retention.bytes";
+const METADATA_MAX_RETENTION_BYTES_DEFAULT: i64 = 100 * 1024 * 1024;
+const METADATA_MAX_RETENTION_BYTES_DOC: &str = "The maximum combined size of the metadata log \
+and snapshots before deleting old snapshots and log files. Since at least one snapshot must \
+exist before any logs can be deleted, this is a soft limit. -1 means no limit.";
+
+pub const METADATA_MAX_RETENTION_MS_CONFIG: &str = "metadata.max.retention.ms";
+const METADATA_MAX_RETENTION_MS_DEFAULT: i64 = 7 * 24 * 60 * 60 * 1000;
+const METADATA_MAX_RETENTION_MS_DOC: &str = "The number of milliseconds to keep a metadata log \
+file or snapshot before deleting it. Since at least one snapshot must exist before any logs \
+can be deleted, this is a soft limit. -1 means no limit.";
+
+pub const METADATA_SNAPSHOT_MAX_NEW_RECORD_BYTES_CONFIG: &str =
+    "metadata.log.max.record.bytes.between.snapshots";
+const METADATA_SNAPSHOT_MAX_NEW_RECORD_BYTES_DEFAULT: i64 = 20 * 1024 * 1024;
+const METADATA_SNAPSHOT_MAX_NEW_RECORD_BYTES_DOC: &str = "This is the maximum number of bytes \
+in the log between the latest snapshot and the high-watermark needed before generating a new \
+snapshot.";
+
+pub const METADATA_SNAPSHOT_MAX_INTERVAL_MS_CONFIG: &str = "metadata.log.max.snapshot.interval.ms";
+const METADATA_SNAPSHOT_MAX_INTERVAL_MS_DEFAULT: i64 = 60 * 60 * 1000;
+const METADATA_SNAPSHOT_MAX_INTERVAL_MS_DOC: &str = "This is the maximum number of \
+milliseconds to wait to generate a snapshot if there are committed records in the log that \
+are not included in the latest snapshot. A value of zero disables time based snapshot \
+generation.";
+
 #[derive(Debug, EasyConfig)]
 pub struct RaftConfigs {
     #[attr(name = PROCESS_ROLES_CONFIG,
@@ -40,6 +120,45 @@ pub struct RaftConfigs {
     getter)]
     controller_listener_names_config: Vec<String>,
 
+    #[attr(name = QUORUM_VOTERS_CONFIG,
+    default = vec![],
+    importance = Importance::HIGH,
+    documentation = QUORUM_VOTERS_DOC,
+    getter)]
+    quorum_voters_config: Vec<String>,
+
+    #[attr(name = QUORUM_ELECTION_TIMEOUT_MS_CONFIG,
+    default = QUORUM_ELECTION_TIMEOUT_MS_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::HIGH,
+    documentation = QUORUM_ELECTION_TIMEOUT_MS_DOC,
+    getter)]
+    quorum_election_timeout_ms_config: i64,
+
+    #[attr(name = QUORUM_ELECTION_BACKOFF_MAX_MS_CONFIG,
+    default = QUORUM_ELECTION_BACKOFF_MAX_MS_DEFAULT,
+    validator = Range::at_least(0),
+    importance = Importance::HIGH,
+    documentation = QUORUM_ELECTION_BACKOFF_MAX_MS_DOC,
+    getter)]
+    quorum_election_backoff_max_ms_config: i64,
+
+    #[attr(name = QUORUM_FETCH_TIMEOUT_MS_CONFIG,
+    default = QUORUM_FETCH_TIMEOUT_MS_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::HIGH,
+    documentation = QUORUM_FETCH_TIMEOUT_MS_DOC,
+    getter)]
+    quorum_fetch_timeout_ms_config: i64,
+
+    #[attr(name = QUORUM_REQUEST_TIMEOUT_MS_CONFIG,
+    default = QUORUM_REQUEST_TIMEOUT_MS_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::MEDIUM,
+    documentation = QUORUM_REQUEST_TIMEOUT_MS_DOC,
+    getter)]
+    quorum_request_timeout_ms_config: i64,
+
     #[attr(name = SERVER_MAX_STARTUP_TIME_MS_CONFIG,
     default = SERVER_MAX_STARTUP_TIME_MS_DEFAULT,
     validator = Range::at_least(0),
@@ -47,4 +166,481 @@ pub struct RaftConfigs {
     documentation = SERVER_MAX_STARTUP_TIME_MS_DOC,
     getter)]
     server_max_startup_time_ms_config: u32,
+
+    #[attr(name = METADATA_SNAPSHOT_COMPRESSION_TYPE_CONFIG,
+    importance = Importance::LOW,
+    documentation = METADATA_SNAPSHOT_COMPRESSION_TYPE_DOC,
+    getter)]
+    metadata_snapshot_compression_type_config: String,
+
+    #[attr(name = METADATA_LOG_DIR_CONFIG,
+    importance = Importance::HIGH,
+    documentation = METADATA_LOG_DIR_DOC,
+    getter)]
+    metadata_log_dir_config: Option<String>,
+
+    #[attr(name = METADATA_LOG_SEGMENT_BYTES_CONFIG,
+    default = METADATA_LOG_SEGMENT_BYTES_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::HIGH,
+    documentation = METADATA_LOG_SEGMENT_BYTES_DOC,
+    getter)]
+    metadata_log_segment_bytes_config: i32,
+
+    #[attr(name = METADATA_LOG_SEGMENT_MS_CONFIG,
+    default = METADATA_LOG_SEGMENT_MS_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::LOW,
+    documentation = METADATA_LOG_SEGMENT_MS_DOC,
+    getter)]
+    metadata_log_segment_ms_config: i64,
+
+    #[attr(name = METADATA_MAX_RETENTION_BYTES_CONFIG,
+    default = METADATA_MAX_RETENTION_BYTES_DEFAULT,
+    importance = Importance::HIGH,
+    documentation = METADATA_MAX_RETENTION_BYTES_DOC,
+    getter)]
+    metadata_max_retention_bytes_config: i64,
+
+    #[attr(name = METADATA_MAX_RETENTION_MS_CONFIG,
+    default = METADATA_MAX_RETENTION_MS_DEFAULT,
+    importance = Importance::HIGH,
+    documentation = METADATA_MAX_RETENTION_MS_DOC,
+    getter)]
+    metadata_max_retention_ms_config: i64,
+
+    #[attr(name = METADATA_SNAPSHOT_MAX_NEW_RECORD_BYTES_CONFIG,
+    default = METADATA_SNAPSHOT_MAX_NEW_RECORD_BYTES_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::HIGH,
+    documentation = METADATA_SNAPSHOT_MAX_NEW_RECORD_BYTES_DOC,
+    getter)]
+    metadata_snapshot_max_new_record_bytes_config: i64,
+
+    #[attr(name = METADATA_SNAPSHOT_MAX_INTERVAL_MS_CONFIG,
+    default = METADATA_SNAPSHOT_MAX_INTERVAL_MS_DEFAULT,
+    validator = Range::at_least(0),
+    importance = Importance::HIGH,
+    documentation = METADATA_SNAPSHOT_MAX_INTERVAL_MS_DOC,
+    getter)]
+    metadata_snapshot_max_interval_ms_config: i64,
+}
+
+/// Where the metadata log is stored and how long it is kept, apart from the data logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataLogConfig {
+    /// `metadata.log.dir`, or the first of the data log dirs if it is not set.
+    pub dir: PathBuf,
+    /// `metadata.log.segment.bytes`.
+    pub segment_bytes: usize,
+    /// `metadata.log.segment.ms`.
+    pub segment_ms: i64,
+    /// `metadata.max.retention.bytes`, -1 for no limit.
+    pub retention_bytes: i64,
+    /// `metadata.max.retention.ms`, -1 for no limit.
+    pub retention_ms: i64,
+    /// `metadata.log.max.record.bytes.between.snapshots`.
+    pub max_bytes_between_snapshots: i64,
+    /// `metadata.log.max.snapshot.interval.ms`, 0 to only snapshot by size.
+    pub max_snapshot_interval_ms: i64,
+}
+
+impl MetadataLogConfig {
+    /// Whether the committed records not in the latest snapshot, `new_record_bytes` of them
+    /// with the oldest appended `new_records_age_ms` ago, call for a new snapshot.
+    pub fn should_snapshot(&self, new_record_bytes: i64, new_records_age_ms: i64) -> bool {
+        if new_record_bytes <= 0 {
+            return false;
+        }
+        new_record_bytes >= self.max_bytes_between_snapshots
+            || (self.max_snapshot_interval_ms > 0
+                && new_records_age_ms >= self.max_snapshot_interval_ms)
+    }
+
+    /// Whether the oldest snapshot, and the log up to the next one, should be deleted given
+    /// the combined `retained_bytes` of the log and snapshots and the age of the oldest
+    /// snapshot. The latest snapshot is never deleted, so the caller stops at it.
+    pub fn should_delete_oldest(&self, retained_bytes: i64, oldest_age_ms: i64) -> bool {
+        (self.retention_bytes >= 0 && retained_bytes > self.retention_bytes)
+            || (self.retention_ms >= 0 && oldest_age_ms > self.retention_ms)
+    }
+
+    /// Whether the metadata log shares a directory with the data logs in `log_dirs`.
+    pub fn is_colocated(&self, log_dirs: &[PathBuf]) -> bool {
+        log_dirs.contains(&self.dir)
+    }
+}
+
+/// A role a process plays in a KRaft cluster, as configured with `process.roles`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProcessRole {
+    Broker,
+    Controller,
+}
+
+impl ProcessRole {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessRole::Broker => "broker",
+            ProcessRole::Controller => "controller",
+        }
+    }
+
+    pub fn for_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "broker" => Some(ProcessRole::Broker),
+            "controller" => Some(ProcessRole::Controller),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ProcessRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RaftConfigError {
+    #[error("{PROCESS_ROLES_CONFIG} must hold broker, controller or both")]
+    MissingProcessRoles,
+
+    #[error("Unknown process role {0}, expected broker or controller")]
+    UnknownProcessRole(String),
+
+    #[error("Duplicate process role {0}")]
+    DuplicateProcessRole(ProcessRole),
+
+    #[error("{CONTROLLER_LISTENER_NAMES_CONFIG} must not be empty")]
+    MissingControllerListenerNames,
+
+    #[error("{QUORUM_VOTERS_CONFIG} must not be empty")]
+    MissingQuorumVoters,
+
+    #[error("Unable to parse {0} to a voter, expected <id>@<host>:<port>")]
+    InvalidVoter(String),
+
+    #[error("The node id {0} of a controller must be one of the voters in {QUORUM_VOTERS_CONFIG}")]
+    NodeIsNotAVoter(i32),
+
+    #[error("Controller listener {0} must not be a listener of a broker-only node")]
+    ControllerListenerOnBroker(String),
+
+    #[error("Controller listener {0} must be one of the listeners of a controller")]
+    MissingControllerListener(String),
+
+    #[error("Listener {0} of a controller-only node must be a controller listener")]
+    NonControllerListenerOnController(String),
+
+    #[error("Unknown {METADATA_SNAPSHOT_COMPRESSION_TYPE_CONFIG} {0}, expected none or zstd")]
+    UnknownSnapshotCompression(String),
+
+    #[error("{METADATA_LOG_DIR_CONFIG} must be set if there is no log dir")]
+    MissingMetadataLogDir,
+}
+
+impl RaftConfigs {
+    /// The parsed `process.roles`.
+    pub fn process_roles(&self) -> Result<BTreeSet<ProcessRole>, RaftConfigError> {
+        parse_process_roles(self.process_roles_config())
+    }
+
+    /// Checks that the controller listeners and quorum voters are consistent with the roles
+    /// of the node, given the names of its `listeners`.
+    pub fn validate(&self, listener_names: &[String]) -> Result<(), RaftConfigError> {
+        validate_process_roles(
+            &self.process_roles()?,
+            *self.node_id_config() as i32,
+            self.controller_listener_names_config(),
+            self.quorum_voters_config(),
+            listener_names,
+        )
+    }
+
+    /// The timeouts of the elections of the raft client.
+    pub fn quorum_config(&self) -> QuorumConfig {
+        QuorumConfig {
+            election_timeout_ms: *self.quorum_election_timeout_ms_config(),
+            election_backoff_max_ms: *self.quorum_election_backoff_max_ms_config(),
+            fetch_timeout_ms: *self.quorum_fetch_timeout_ms_config(),
+            request_timeout_ms: *self.quorum_request_timeout_ms_config(),
+        }
+    }
+
+    /// The parsed `metadata.snapshot.compression.type`, no compression if it is not set.
+    pub fn snapshot_compression(&self) -> Result<SnapshotCompression, RaftConfigError> {
+        parse_snapshot_compression(self.metadata_snapshot_compression_type_config())
+    }
+
+    /// The metadata log configs, stored in `metadata.log.dir` or in the first of the data
+    /// `log_dirs` if it is not set.
+    pub fn metadata_log_config(
+        &self,
+        log_dirs: &[PathBuf],
+    ) -> Result<MetadataLogConfig, RaftConfigError> {
+        Ok(MetadataLogConfig {
+            dir: metadata_log_dir(self.metadata_log_dir_config().as_deref(), log_dirs)?,
+            segment_bytes: *self.metadata_log_segment_bytes_config() as usize,
+            segment_ms: *self.metadata_log_segment_ms_config(),
+            retention_bytes: *self.metadata_max_retention_bytes_config(),
+            retention_ms: *self.metadata_max_retention_ms_config(),
+            max_bytes_between_snapshots: *self.metadata_snapshot_max_new_record_bytes_config(),
+            max_snapshot_interval_ms: *self.metadata_snapshot_max_interval_ms_config(),
+        })
+    }
+}
+
+/// The directory of the metadata log: `metadata.log.dir`, or the first of `log_dirs`.
+pub fn metadata_log_dir(
+    metadata_log_dir: Option<&str>,
+    log_dirs: &[PathBuf],
+) -> Result<PathBuf, RaftConfigError> {
+    match metadata_log_dir.filter(|dir| !dir.trim().is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir.trim())),
+        None => log_dirs
+            .first()
+            .cloned()
+            .ok_or(RaftConfigError::MissingMetadataLogDir),
+    }
+}
+
+pub fn parse_snapshot_compression(name: &str) -> Result<SnapshotCompression, RaftConfigError> {
+    if name.is_empty() {
+        return Ok(SnapshotCompression::None);
+    }
+    SnapshotCompression::for_name(name)
+        .map_err(|_| RaftConfigError::UnknownSnapshotCompression(name.to_string()))
+}
+
+pub fn parse_process_roles(roles: &[String]) -> Result<BTreeSet<ProcessRole>, RaftConfigError> {
+    let mut parsed = BTreeSet::new();
+    for role in roles.iter().filter(|r| !r.trim().is_empty()) {
+        let role = ProcessRole::for_name(role)
+            .ok_or_else(|| RaftConfigError::UnknownProcessRole(role.clone()))?;
+        if !parsed.insert(role) {
+            return Err(RaftConfigError::DuplicateProcessRole(role));
+        }
+    }
+    if parsed.is_empty() {
+        return Err(RaftConfigError::MissingProcessRoles);
+    }
+    Ok(parsed)
+}
+
+/// Parses the ids of the `controller.quorum.voters` entries.
+pub fn parse_voter_ids(voters: &[String]) -> Result<Vec<i32>, RaftConfigError> {
+    voters
+        .iter()
+        .filter(|v| !v.trim().is_empty())
+        .map(|voter| {
+            voter
+                .split_once('@')
+                .filter(|(_, address)| address.contains(':'))
+                .and_then(|(id, _)| id.trim().parse().ok())
+                .ok_or_else(|| RaftConfigError::InvalidVoter(voter.clone()))
+        })
+        .collect()
+}
+
+/// Checks the configs a node needs for its roles:
+/// - every node needs the controller listener names and the quorum voters, to reach the
+///   controllers;
+/// - a broker-only node must not listen on a controller listener;
+/// - a controller must be a voter and listen on its controller listeners, and a
+///   controller-only node on them only.
+pub fn validate_process_roles(
+    roles: &BTreeSet<ProcessRole>,
+    node_id: i32,
+    controller_listener_names: &[String],
+    quorum_voters: &[String],
+    listener_names: &[String],
+) -> Result<(), RaftConfigError> {
+    if controller_listener_names.is_empty() {
+        return Err(RaftConfigError::MissingControllerListenerNames);
+    }
+    let voter_ids = parse_voter_ids(quorum_voters)?;
+    if voter_ids.is_empty() {
+        return Err(RaftConfigError::MissingQuorumVoters);
+    }
+    let is_controller_listener = |name: &String| {
+        controller_listener_names
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(name))
+    };
+    if !roles.contains(&ProcessRole::Controller) {
+        if let Some(name) = listener_names.iter().find(|n| is_controller_listener(n)) {
+            return Err(RaftConfigError::ControllerListenerOnBroker(name.clone()));
+        }
+        return Ok(());
+    }
+
+    if !voter_ids.contains(&node_id) {
+        return Err(RaftConfigError::NodeIsNotAVoter(node_id));
+    }
+    if let Some(name) = controller_listener_names
+        .iter()
+        .find(|c| !listener_names.iter().any(|n| n.eq_ignore_ascii_case(c)))
+    {
+        return Err(RaftConfigError::MissingControllerListener(name.clone()));
+    }
+    if !roles.contains(&ProcessRole::Broker)
+        && let Some(name) = listener_names.iter().find(|n| !is_controller_listener(n))
+    {
+        return Err(RaftConfigError::NonControllerListenerOnController(
+            name.clone(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_snapshot_compression() {
+        assert_eq!(
+            Ok(SnapshotCompression::None),
+            parse_snapshot_compression("")
+        );
+        assert_eq!(
+            Ok(SnapshotCompression::Zstd),
+            parse_snapshot_compression("zstd")
+        );
+        assert_eq!(
+            Err(RaftConfigError::UnknownSnapshotCompression(
+                "lz4".to_string()
+            )),
+            parse_snapshot_compression("lz4")
+        );
+    }
+
+    #[test]
+    fn test_metadata_log_dir() {
+        let log_dirs = vec![PathBuf::from("/data/a"), PathBuf::from("/data/b")];
+        assert_eq!(
+            Ok(PathBuf::from("/metadata")),
+            metadata_log_dir(Some("/metadata"), &log_dirs)
+        );
+        assert_eq!(
+            Ok(PathBuf::from("/data/a")),
+            metadata_log_dir(None, &log_dirs)
+        );
+        assert_eq!(
+            Err(RaftConfigError::MissingMetadataLogDir),
+            metadata_log_dir(Some(""), &[])
+        );
+    }
+
+    #[test]
+    fn test_metadata_log_retention_and_snapshots() {
+        let config = MetadataLogConfig {
+            dir: PathBuf::from("/metadata"),
+            segment_bytes: METADATA_LOG_SEGMENT_BYTES_DEFAULT as usize,
+            segment_ms: METADATA_LOG_SEGMENT_MS_DEFAULT,
+            retention_bytes: 1_000,
+            retention_ms: -1,
+            max_bytes_between_snapshots: 100,
+            max_snapshot_interval_ms: 60_000,
+        };
+        assert!(!config.is_colocated(&[PathBuf::from("/data")]));
+        assert!(config.is_colocated(&[PathBuf::from("/metadata")]));
+
+        assert!(!config.should_snapshot(0, 120_000));
+        assert!(!config.should_snapshot(50, 1_000));
+        assert!(config.should_snapshot(100, 1_000));
+        assert!(config.should_snapshot(50, 60_000));
+        let by_size_only = MetadataLogConfig {
+            max_snapshot_interval_ms: 0,
+            ..config.clone()
+        };
+        assert!(!by_size_only.should_snapshot(50, i64::MAX));
+
+        assert!(!config.should_delete_oldest(1_000, i64::MAX));
+        assert!(config.should_delete_oldest(1_001, 0));
+        let by_age = MetadataLogConfig {
+            retention_bytes: -1,
+            retention_ms: 1_000,
+            ..config
+        };
+        assert!(!by_age.should_delete_oldest(i64::MAX, 1_000));
+        assert!(by_age.should_delete_oldest(0, 1_001));
+    }
+
+    #[test]
+    fn test_parse_process_roles() {
+        assert_eq!(
+            BTreeSet::from([ProcessRole::Broker, ProcessRole::Controller]),
+            parse_process_roles(&strings(&["controller", "broker"])).unwrap()
+        );
+        assert_eq!(
+            Err(RaftConfigError::MissingProcessRoles),
+            parse_process_roles(&[])
+        );
+        assert_eq!(
+            Err(RaftConfigError::DuplicateProcessRole(ProcessRole::Broker)),
+            parse_process_roles(&strings(&["broker", "broker"]))
+        );
+        assert_eq!(
+            Err(RaftConfigError::UnknownProcessRole("zookeeper".to_string())),
+            parse_process_roles(&strings(&["zookeeper"]))
+        );
+    }
+
+    #[test]
+    fn test_validate_process_roles() {
+        let broker = BTreeSet::from([ProcessRole::Broker]);
+        let controller = BTreeSet::from([ProcessRole::Controller]);
+        let combined = BTreeSet::from([ProcessRole::Broker, ProcessRole::Controller]);
+        let controller_names = strings(&["CONTROLLER"]);
+        let voters = strings(&["1@localhost:9093", "2@localhost:9094"]);
+        let validate = |roles, node_id, listeners: &[&str]| {
+            validate_process_roles(
+                roles,
+                node_id,
+                &controller_names,
+                &voters,
+                &strings(listeners),
+            )
+        };
+
+        assert_eq!(Ok(()), validate(&broker, 3, &["PLAINTEXT"]));
+        assert_eq!(Ok(()), validate(&controller, 1, &["CONTROLLER"]));
+        assert_eq!(Ok(()), validate(&combined, 2, &["PLAINTEXT", "CONTROLLER"]));
+        assert_eq!(
+            Err(RaftConfigError::ControllerListenerOnBroker(
+                "CONTROLLER".to_string()
+            )),
+            validate(&broker, 3, &["PLAINTEXT", "CONTROLLER"])
+        );
+        assert_eq!(
+            Err(RaftConfigError::NodeIsNotAVoter(3)),
+            validate(&controller, 3, &["CONTROLLER"])
+        );
+        assert_eq!(
+            Err(RaftConfigError::MissingControllerListener(
+                "CONTROLLER".to_string()
+            )),
+            validate(&combined, 1, &["PLAINTEXT"])
+        );
+        assert_eq!(
+            Err(RaftConfigError::NonControllerListenerOnController(
+                "PLAINTEXT".to_string()
+            )),
+            validate(&controller, 1, &["CONTROLLER", "PLAINTEXT"])
+        );
+        assert_eq!(
+            Err(RaftConfigError::MissingQuorumVoters),
+            validate_process_roles(&broker, 3, &controller_names, &[], &[])
+        );
+        assert_eq!(
+            Err(RaftConfigError::InvalidVoter("1@localhost".to_string())),
+            parse_voter_ids(&strings(&["1@localhost"]))
+        );
+    }
 }
@@ -0,0 +1,254 @@
+use rafka_clients::common::protocol::errors::{ApiError, Errors};
+use rafka_clients::common::record::record_batch::{Header, Record, RecordBatch};
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// A record rejected by a validator, which fails the append of its whole batch.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Record {record_index} was rejected by {validator}: {message}")]
+pub struct RecordValidationError {
+    pub validator: String,
+    /// The index of the rejected record in its batch.
+    pub record_index: usize,
+    pub message: String,
+}
+
+impl From<RecordValidationError> for ApiError {
+    fn from(error: RecordValidationError) -> Self {
+        ApiError::new(Errors::InvalidRecord, error.to_string())
+    }
+}
+
+/// Checks the records produced to a topic before the leader appends them, to enforce
+/// policies without forking the broker: a validator may reject a record, which fails the
+/// produce request of its batch with `INVALID_RECORD`, or annotate it, e.g. with a header.
+///
+/// Validators run on the request path, so they must be cheap.
+pub trait BrokerRecordValidator: Send + Sync {
+    /// The name errors refer to the validator by.
+    fn name(&self) -> &str;
+
+    fn validate(&self, topic_partition: &TopicPartition, record: &mut Record)
+    -> Result<(), String>;
+}
+
+/// The topics a validator applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopicFilter {
+    All,
+    Topic(String),
+    Prefix(String),
+}
+
+impl TopicFilter {
+    pub fn matches(&self, topic: &str) -> bool {
+        match self {
+            TopicFilter::All => true,
+            TopicFilter::Topic(name) => name == topic,
+            TopicFilter::Prefix(prefix) => topic.starts_with(prefix),
+        }
+    }
+}
+
+/// The validators of the broker, run in the order they were added on every batch appended
+/// to a topic they apply to.
+#[derive(Clone, Default)]
+pub struct RecordValidators {
+    validators: Vec<(TopicFilter, Arc<dyn BrokerRecordValidator>)>,
+}
+
+impl RecordValidators {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, filter: TopicFilter, validator: Arc<dyn BrokerRecordValidator>) {
+        self.validators.push((filter, validator));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    /// Validates and annotates the records of `batch`. The batch must not be appended if
+    /// any record is rejected.
+    pub fn validate(
+        &self,
+        topic_partition: &TopicPartition,
+        batch: &mut RecordBatch,
+    ) -> Result<(), RecordValidationError> {
+        let validators: Vec<_> = self
+            .validators
+            .iter()
+            .filter(|(filter, _)| filter.matches(topic_partition.topic()))
+            .map(|(_, validator)| validator)
+            .collect();
+        if validators.is_empty() {
+            return Ok(());
+        }
+        for (record_index, record) in batch.records_mut().iter_mut().enumerate() {
+            for validator in &validators {
+                validator
+                    .validate(topic_partition, record)
+                    .map_err(|message| RecordValidationError {
+                        validator: validator.name().to_string(),
+                        record_index,
+                        message,
+                    })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rejects records whose key, value and headers take more than `max_bytes`.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxRecordSizeValidator {
+    pub max_bytes: usize,
+}
+
+impl BrokerRecordValidator for MaxRecordSizeValidator {
+    fn name(&self) -> &str {
+        "MaxRecordSizeValidator"
+    }
+
+    fn validate(&self, _: &TopicPartition, record: &mut Record) -> Result<(), String> {
+        let size = record.key.as_ref().map_or(0, Vec::len)
+            + record.value.as_ref().map_or(0, Vec::len)
+            + record
+                .headers
+                .iter()
+                .map(|h| h.key.len() + h.value.as_ref().map_or(0, Vec::len))
+                .sum::<usize>();
+        if size > self.max_bytes {
+            return Err(format!(
+                "the record takes {size} bytes, more than the limit of {}",
+                self.max_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Rejects records without a key, or without one of the required headers.
+#[derive(Debug, Clone, Default)]
+pub struct RequiredFieldsValidator {
+    pub require_key: bool,
+    pub required_headers: Vec<String>,
+}
+
+impl BrokerRecordValidator for RequiredFieldsValidator {
+    fn name(&self) -> &str {
+        "RequiredFieldsValidator"
+    }
+
+    fn validate(&self, _: &TopicPartition, record: &mut Record) -> Result<(), String> {
+        if self.require_key && record.key.is_none() {
+            return Err("the record has no key".to_string());
+        }
+        match self
+            .required_headers
+            .iter()
+            .find(|name| !record.headers.iter().any(|h| &h.key == *name))
+        {
+            Some(name) => Err(format!("the record has no {name} header")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Annotates every record with a header, unless the record already has it.
+#[derive(Debug, Clone)]
+pub struct HeaderAnnotator {
+    pub key: String,
+    pub value: Vec<u8>,
+}
+
+impl BrokerRecordValidator for HeaderAnnotator {
+    fn name(&self) -> &str {
+        "HeaderAnnotator"
+    }
+
+    fn validate(&self, _: &TopicPartition, record: &mut Record) -> Result<(), String> {
+        if !record.headers.iter().any(|h| h.key == self.key) {
+            record.headers.push(Header {
+                key: self.key.clone(),
+                value: Some(self.value.clone()),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(values: &[&str]) -> RecordBatch {
+        RecordBatch::new(
+            0,
+            values
+                .iter()
+                .map(|v| Record::new(0, Some(b"key"), Some(v.as_bytes())))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_validators_apply_to_matching_topics() {
+        let mut validators = RecordValidators::new();
+        validators.add(
+            TopicFilter::Prefix("orders".to_string()),
+            Arc::new(MaxRecordSizeValidator { max_bytes: 10 }),
+        );
+        validators.add(
+            TopicFilter::All,
+            Arc::new(HeaderAnnotator {
+                key: "source".to_string(),
+                value: b"rafka".to_vec(),
+            }),
+        );
+
+        let orders = TopicPartition::new("orders-eu", 0);
+        let mut accepted = batch(&["small"]);
+        validators.validate(&orders, &mut accepted).unwrap();
+        assert_eq!("source", accepted.records()[0].headers[0].key);
+
+        let mut rejected = batch(&["small", "far too large"]);
+        let error = validators.validate(&orders, &mut rejected).unwrap_err();
+        assert_eq!("MaxRecordSizeValidator", error.validator);
+        assert_eq!(1, error.record_index);
+        assert_eq!(Errors::InvalidRecord, ApiError::from(error).error());
+
+        // The size limit only applies to the orders topics.
+        let mut other = batch(&["far too large"]);
+        validators
+            .validate(&TopicPartition::new("payments", 0), &mut other)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_required_fields() {
+        let validator = RequiredFieldsValidator {
+            require_key: true,
+            required_headers: vec!["trace-id".to_string()],
+        };
+        let tp = TopicPartition::new("foo", 0);
+        let mut record = Record::new(0, None, Some(b"value"));
+        assert_eq!(
+            Err("the record has no key".to_string()),
+            validator.validate(&tp, &mut record)
+        );
+        record.key = Some(b"key".to_vec());
+        assert_eq!(
+            Err("the record has no trace-id header".to_string()),
+            validator.validate(&tp, &mut record)
+        );
+        record.headers.push(Header {
+            key: "trace-id".to_string(),
+            value: None,
+        });
+        assert_eq!(Ok(()), validator.validate(&tp, &mut record));
+    }
+}
@@ -0,0 +1,617 @@
+use crate::server::leader_end_point::{
+    EpochData, EpochEndOffset, LeaderEndPoint, UNDEFINED_EPOCH, UNDEFINED_EPOCH_OFFSET,
+};
+use crate::server::replication_quota_manager::{ReplicaQuota, UnboundedQuota};
+use rafka_clients::common::protocol::errors::Errors;
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_clients::common::utils::exponential_backoff::ExponentialBackoff;
+use rafka_storage::{PartitionLog, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// The default of how long a follower waits before retrying a request the leader failed.
+pub const DEFAULT_REPLICA_FETCH_BACKOFF_MS: i64 = 1000;
+
+/// The default of the longest a follower waits before retrying, however many requests failed
+/// in a row.
+pub const DEFAULT_REPLICA_FETCH_BACKOFF_MAX_MS: i64 = 10_000;
+
+/// Whether a follower partition must reconcile its log with the leader before fetching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicaState {
+    Truncating,
+    Fetching,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionFetchState {
+    pub fetch_offset: i64,
+    pub current_leader_epoch: i32,
+    /// Where to truncate to if the log has no leader epochs to compare with the leader's.
+    pub high_watermark: i64,
+    pub state: ReplicaState,
+    /// How far the replica is behind the leader's high watermark, unknown until the first
+    /// fetch.
+    pub lag: Option<i64>,
+}
+
+impl PartitionFetchState {
+    /// Whether the replica had caught up with the leader as of its last fetch.
+    pub fn is_replica_in_sync(&self) -> bool {
+        self.lag.is_some_and(|lag| lag <= 0)
+    }
+}
+
+/// Replicates the partitions led by one broker.
+///
+/// A partition which becomes a follower starts out truncating: its log may hold records the
+/// previous leader wrote but never committed, which the new leader doesn't have. The follower
+/// asks the leader where its latest epoch ended, with an OffsetsForLeaderEpoch request, and
+/// truncates its log there before it fetches. When the leader's answer is for an older epoch
+/// than the follower asked for, the follower truncates to the end of that epoch in its own
+/// log and asks again with its new latest epoch, until both logs agree on the epoch.
+///
+/// Fetching replicas which are throttled and not in sync, typically the replicas a
+/// reassignment is moving here, are left out of fetches while the follower replication quota
+/// is exceeded.
+///
+/// When requests to the leader fail, e.g. while it restarts, the follower backs off
+/// exponentially between rounds instead of hammering the leader.
+pub struct ReplicaFetcher<L: LeaderEndPoint> {
+    leader: L,
+    quota: Arc<dyn ReplicaQuota>,
+    partition_states: BTreeMap<TopicPartition, PartitionFetchState>,
+    backoff: ExponentialBackoff,
+    /// The number of requests to the leader which failed in a row.
+    failed_requests: u32,
+}
+
+/// Where to truncate a log to, and whether the log is known to match the leader's up to there.
+struct OffsetTruncationState {
+    offset: i64,
+    truncation_completed: bool,
+}
+
+impl<L: LeaderEndPoint> ReplicaFetcher<L> {
+    pub fn new(leader: L) -> Self {
+        Self {
+            leader,
+            quota: Arc::new(UnboundedQuota),
+            partition_states: BTreeMap::new(),
+            backoff: ExponentialBackoff::new(
+                DEFAULT_REPLICA_FETCH_BACKOFF_MS,
+                2.0,
+                DEFAULT_REPLICA_FETCH_BACKOFF_MAX_MS,
+                0.2,
+            ),
+            failed_requests: 0,
+        }
+    }
+
+    /// Bounds the traffic of the throttled replicas by `quota`.
+    pub fn with_quota(mut self, quota: Arc<dyn ReplicaQuota>) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Replaces the default backoff between rounds after failed requests.
+    pub fn with_backoff(mut self, backoff: ExponentialBackoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Records that a fetch request to the leader failed.
+    pub fn on_fetch_failed(&mut self) {
+        self.failed_requests += 1;
+    }
+
+    /// How long to wait before the next round: nothing unless the last requests to the leader
+    /// failed.
+    pub fn backoff_ms(&self) -> i64 {
+        match self.failed_requests {
+            0 => 0,
+            failed => self.backoff.backoff(failed - 1),
+        }
+    }
+
+    /// Starts following the leader of `topic_partition`, known by `current_leader_epoch`.
+    pub fn add_partition(
+        &mut self,
+        topic_partition: TopicPartition,
+        current_leader_epoch: i32,
+        log: &impl PartitionLog,
+        high_watermark: i64,
+    ) {
+        self.partition_states.insert(
+            topic_partition,
+            PartitionFetchState {
+                fetch_offset: log.log_end_offset(),
+                current_leader_epoch,
+                high_watermark,
+                state: ReplicaState::Truncating,
+                lag: None,
+            },
+        );
+    }
+
+    pub fn remove_partition(&mut self, topic_partition: &TopicPartition) {
+        self.partition_states.remove(topic_partition);
+    }
+
+    pub fn partition_state(
+        &self,
+        topic_partition: &TopicPartition,
+    ) -> Option<&PartitionFetchState> {
+        self.partition_states.get(topic_partition)
+    }
+
+    /// The partitions which are done truncating and can be fetched.
+    pub fn fetchable_partitions(
+        &self,
+    ) -> impl Iterator<Item = (&TopicPartition, &PartitionFetchState)> {
+        self.partition_states
+            .iter()
+            .filter(|(_, state)| state.state == ReplicaState::Fetching)
+    }
+
+    /// The fetchable partitions to include in the next fetch request, leaving out the
+    /// throttled replicas which are not in sync while the quota is exceeded.
+    pub fn partitions_to_fetch(
+        &self,
+    ) -> impl Iterator<Item = (&TopicPartition, &PartitionFetchState)> {
+        self.fetchable_partitions()
+            .filter(|(topic_partition, state)| {
+                !self.should_follower_throttle(topic_partition, state)
+            })
+    }
+
+    fn should_follower_throttle(
+        &self,
+        topic_partition: &TopicPartition,
+        state: &PartitionFetchState,
+    ) -> bool {
+        !state.is_replica_in_sync()
+            && self.quota.is_throttled(topic_partition)
+            && self.quota.is_quota_exceeded()
+    }
+
+    /// Records that `bytes` of records were fetched and appended for `topic_partition`, whose
+    /// log now ends at `log_end_offset`, while the leader's high watermark is
+    /// `leader_high_watermark`. The bytes of throttled replicas count against the quota.
+    pub fn on_partition_fetched(
+        &mut self,
+        topic_partition: &TopicPartition,
+        bytes: usize,
+        log_end_offset: i64,
+        leader_high_watermark: i64,
+    ) {
+        self.failed_requests = 0;
+        let Some(state) = self.partition_states.get_mut(topic_partition) else {
+            return;
+        };
+        state.fetch_offset = log_end_offset;
+        state.high_watermark = leader_high_watermark.min(log_end_offset);
+        state.lag = Some((leader_high_watermark - log_end_offset).max(0));
+        if self.quota.is_throttled(topic_partition) {
+            self.quota.record(bytes as i64);
+        }
+    }
+
+    /// Runs one round of the truncation protocol for the partitions which are truncating.
+    /// Partitions whose truncation completes move to [ReplicaState::Fetching]; the others are
+    /// retried in the next round.
+    pub fn maybe_truncate<G: PartitionLog>(
+        &mut self,
+        logs: &mut HashMap<TopicPartition, G>,
+    ) -> Result<()> {
+        let mut with_epochs = BTreeMap::new();
+        let mut without_epochs = vec![];
+        for (topic_partition, state) in &self.partition_states {
+            if state.state != ReplicaState::Truncating {
+                continue;
+            }
+            let Some(log) = logs.get(topic_partition) else {
+                warn!("Skipping truncation of {topic_partition}, which has no local log");
+                continue;
+            };
+            match log.latest_epoch() {
+                Some(leader_epoch) => {
+                    with_epochs.insert(
+                        topic_partition.clone(),
+                        EpochData {
+                            current_leader_epoch: state.current_leader_epoch,
+                            leader_epoch,
+                        },
+                    );
+                }
+                None => without_epochs.push(topic_partition.clone()),
+            }
+        }
+
+        for topic_partition in without_epochs {
+            let high_watermark = self.partition_states[&topic_partition].high_watermark;
+            let log = logs.get_mut(&topic_partition).expect("checked above");
+            self.truncate(&topic_partition, log, high_watermark, true)?;
+        }
+        if with_epochs.is_empty() {
+            return Ok(());
+        }
+
+        let end_offsets = match self.leader.fetch_epoch_end_offsets(&with_epochs) {
+            Ok(end_offsets) => end_offsets,
+            Err(e) => {
+                self.failed_requests += 1;
+                warn!(
+                    "Error sending OffsetsForLeaderEpoch request, will retry in {} ms: {e}",
+                    self.backoff_ms()
+                );
+                return Ok(());
+            }
+        };
+        self.failed_requests = 0;
+        for (topic_partition, end_offset) in end_offsets {
+            if !with_epochs.contains_key(&topic_partition) {
+                continue;
+            }
+            match end_offset.error {
+                Errors::None => {}
+                Errors::FencedLeaderEpoch => {
+                    info!(
+                        "Removing {topic_partition} from the fetcher, its leader epoch {} is fenced",
+                        with_epochs[&topic_partition].current_leader_epoch
+                    );
+                    self.partition_states.remove(&topic_partition);
+                    continue;
+                }
+                error => {
+                    warn!(
+                        "Error fetching the epoch end offset of {topic_partition}, will retry: {}",
+                        error.message()
+                    );
+                    continue;
+                }
+            }
+            let log = logs.get_mut(&topic_partition).expect("checked above");
+            let state = self.offset_truncation_state(&topic_partition, log, &end_offset);
+            self.truncate(
+                &topic_partition,
+                log,
+                state.offset,
+                state.truncation_completed,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn offset_truncation_state(
+        &self,
+        topic_partition: &TopicPartition,
+        log: &impl PartitionLog,
+        leader: &EpochEndOffset,
+    ) -> OffsetTruncationState {
+        let log_end_offset = log.log_end_offset();
+        if leader.end_offset == UNDEFINED_EPOCH_OFFSET {
+            // The leader knows nothing about the epoch; the high watermark is the only offset
+            // known to be replicated.
+            return OffsetTruncationState {
+                offset: self.partition_states[topic_partition].high_watermark,
+                truncation_completed: true,
+            };
+        }
+        if leader.leader_epoch == UNDEFINED_EPOCH {
+            return OffsetTruncationState {
+                offset: leader.end_offset.min(log_end_offset),
+                truncation_completed: true,
+            };
+        }
+        match log.end_offset_for_epoch(leader.leader_epoch) {
+            // The follower has no epoch at or below the leader's: everything it has after the
+            // leader's end offset diverged.
+            None => OffsetTruncationState {
+                offset: leader.end_offset.min(log_end_offset),
+                truncation_completed: true,
+            },
+            // The follower doesn't have the leader's epoch: truncate to the end of the epoch it
+            // has before it and ask the leader again.
+            Some((epoch, end_offset)) if epoch != leader.leader_epoch => OffsetTruncationState {
+                offset: end_offset.min(log_end_offset),
+                truncation_completed: false,
+            },
+            Some((_, end_offset)) => OffsetTruncationState {
+                offset: leader.end_offset.min(end_offset),
+                truncation_completed: true,
+            },
+        }
+    }
+
+    fn truncate(
+        &mut self,
+        topic_partition: &TopicPartition,
+        log: &mut impl PartitionLog,
+        offset: i64,
+        truncation_completed: bool,
+    ) -> Result<()> {
+        log.truncate_to(offset)?;
+        let state = self
+            .partition_states
+            .get_mut(topic_partition)
+            .expect("partition is fetched");
+        state.fetch_offset = log.log_end_offset();
+        if truncation_completed {
+            info!(
+                "Truncation of {topic_partition} completed, fetching from offset {}",
+                state.fetch_offset
+            );
+            state.state = ReplicaState::Fetching;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::protocol::errors::ApiError;
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+    use rafka_storage::{MemoryLog, UnifiedLog, UnifiedLogConfig};
+    use std::cell::RefCell;
+
+    /// A leader whose log holds the given epochs as (epoch, start offset), ending at
+    /// `log_end_offset`.
+    struct MockLeader {
+        epochs: Vec<(i32, i64)>,
+        log_end_offset: i64,
+        requests: RefCell<Vec<i32>>,
+    }
+
+    impl LeaderEndPoint for MockLeader {
+        fn fetch_epoch_end_offsets(
+            &self,
+            partitions: &BTreeMap<TopicPartition, EpochData>,
+        ) -> std::result::Result<BTreeMap<TopicPartition, EpochEndOffset>, ApiError> {
+            Ok(partitions
+                .iter()
+                .map(|(tp, data)| {
+                    self.requests.borrow_mut().push(data.leader_epoch);
+                    let floor = self
+                        .epochs
+                        .iter()
+                        .rev()
+                        .find(|(e, _)| *e <= data.leader_epoch);
+                    let higher = self.epochs.iter().find(|(e, _)| *e > data.leader_epoch);
+                    let end_offset = match (floor, higher) {
+                        (None, _) => EpochEndOffset::new(UNDEFINED_EPOCH, UNDEFINED_EPOCH_OFFSET),
+                        (Some((epoch, _)), None) => {
+                            EpochEndOffset::new(*epoch, self.log_end_offset)
+                        }
+                        (Some((epoch, _)), Some((_, start))) => EpochEndOffset::new(*epoch, *start),
+                    };
+                    (tp.clone(), end_offset)
+                })
+                .collect())
+        }
+    }
+
+    fn append(log: &mut impl PartitionLog, base_offset: i64, epoch: i32, count: usize) {
+        let mut batch = RecordBatch::new(
+            base_offset,
+            (0..count)
+                .map(|_| Record::new(0, None, Some(b"v")))
+                .collect(),
+        );
+        batch.set_partition_leader_epoch(epoch);
+        log.append_as_follower(batch).unwrap();
+    }
+
+    #[test]
+    fn test_truncates_the_divergent_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        let tp = TopicPartition::new("foo", 0);
+        let mut log =
+            UnifiedLog::open(&dir.path().join("foo-0"), UnifiedLogConfig::default(), 0).unwrap();
+        // The follower wrote offsets 0-4 in epoch 1 and 5-9 in epoch 3, while the leader wrote
+        // offsets 0-2 in epoch 1, 3-7 in epoch 2 and has been writing since offset 8 in epoch 4.
+        append(&mut log, 0, 1, 3);
+        append(&mut log, 3, 1, 2);
+        append(&mut log, 5, 3, 5);
+        let leader = MockLeader {
+            epochs: vec![(1, 0), (2, 3), (4, 8)],
+            log_end_offset: 12,
+            requests: RefCell::new(vec![]),
+        };
+        let mut fetcher = ReplicaFetcher::new(leader);
+        fetcher.add_partition(tp.clone(), 4, &log, 0);
+        let mut logs = HashMap::from([(tp.clone(), log)]);
+
+        // The leader answers epoch 2 for epoch 3. The follower doesn't have epoch 2, so it
+        // truncates to the end of its epoch 1, at offset 5, and asks again.
+        fetcher.maybe_truncate(&mut logs).unwrap();
+        assert_eq!(
+            ReplicaState::Truncating,
+            fetcher.partition_state(&tp).unwrap().state
+        );
+        assert_eq!(5, logs[&tp].log_end_offset());
+
+        // Epoch 1 ended at offset 3 on the leader.
+        fetcher.maybe_truncate(&mut logs).unwrap();
+        let state = fetcher.partition_state(&tp).unwrap();
+        assert_eq!(ReplicaState::Fetching, state.state);
+        assert_eq!(3, state.fetch_offset);
+        assert_eq!(vec![3, 1], *fetcher.leader.requests.borrow());
+        assert_eq!(1, fetcher.fetchable_partitions().count());
+    }
+
+    #[test]
+    fn test_truncates_to_the_leader_end_offset_of_a_shared_epoch() {
+        let dir = tempfile::tempdir().unwrap();
+        let tp = TopicPartition::new("foo", 0);
+        let mut log =
+            UnifiedLog::open(&dir.path().join("foo-0"), UnifiedLogConfig::default(), 0).unwrap();
+        append(&mut log, 0, 1, 3);
+        append(&mut log, 3, 1, 3);
+        let leader = MockLeader {
+            epochs: vec![(1, 0), (2, 4)],
+            log_end_offset: 10,
+            requests: RefCell::new(vec![]),
+        };
+        let mut fetcher = ReplicaFetcher::new(leader);
+        fetcher.add_partition(tp.clone(), 2, &log, 0);
+        let mut logs = HashMap::from([(tp.clone(), log)]);
+
+        fetcher.maybe_truncate(&mut logs).unwrap();
+        let state = fetcher.partition_state(&tp).unwrap();
+        assert_eq!(ReplicaState::Fetching, state.state);
+        // Offset 4 is in the middle of the second batch, which is removed as a whole.
+        assert_eq!(3, state.fetch_offset);
+        assert_eq!(3, logs[&tp].log_end_offset());
+    }
+
+    #[test]
+    fn test_truncates_an_in_memory_log() {
+        let tp = TopicPartition::new("foo", 0);
+        let mut log = MemoryLog::new(tp.clone(), 0);
+        append(&mut log, 0, 1, 3);
+        append(&mut log, 3, 3, 3);
+        let leader = MockLeader {
+            epochs: vec![(1, 0), (2, 2)],
+            log_end_offset: 10,
+            requests: RefCell::new(vec![]),
+        };
+        let mut fetcher = ReplicaFetcher::new(leader);
+        fetcher.add_partition(tp.clone(), 2, &log, 0);
+        let mut logs = HashMap::from([(tp.clone(), log)]);
+
+        // The leader answers epoch 2 for epoch 3, so the follower truncates to the end of its
+        // epoch 1 and asks again, then to where epoch 1 ended on the leader.
+        fetcher.maybe_truncate(&mut logs).unwrap();
+        fetcher.maybe_truncate(&mut logs).unwrap();
+        assert_eq!(vec![3, 1], *fetcher.leader.requests.borrow());
+        let state = fetcher.partition_state(&tp).unwrap();
+        assert_eq!(ReplicaState::Fetching, state.state);
+        // Offset 2 is in the middle of the first batch, which is removed as a whole.
+        assert_eq!(0, state.fetch_offset);
+        assert_eq!(0, logs[&tp].log_end_offset());
+    }
+
+    struct FencedLeader;
+
+    impl LeaderEndPoint for FencedLeader {
+        fn fetch_epoch_end_offsets(
+            &self,
+            partitions: &BTreeMap<TopicPartition, EpochData>,
+        ) -> std::result::Result<BTreeMap<TopicPartition, EpochEndOffset>, ApiError> {
+            Ok(partitions
+                .keys()
+                .map(|tp| (tp.clone(), EpochEndOffset::error(Errors::FencedLeaderEpoch)))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_fenced_partitions_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let tp = TopicPartition::new("foo", 0);
+        let mut log =
+            UnifiedLog::open(&dir.path().join("foo-0"), UnifiedLogConfig::default(), 0).unwrap();
+        append(&mut log, 0, 1, 3);
+        let mut fetcher = ReplicaFetcher::new(FencedLeader);
+        fetcher.add_partition(tp.clone(), 1, &log, 0);
+        let mut logs = HashMap::from([(tp.clone(), log)]);
+        fetcher.maybe_truncate(&mut logs).unwrap();
+        assert!(fetcher.partition_state(&tp).is_none());
+        assert_eq!(3, logs[&tp].log_end_offset());
+    }
+
+    struct UnavailableLeader;
+
+    impl LeaderEndPoint for UnavailableLeader {
+        fn fetch_epoch_end_offsets(
+            &self,
+            _partitions: &BTreeMap<TopicPartition, EpochData>,
+        ) -> std::result::Result<BTreeMap<TopicPartition, EpochEndOffset>, ApiError> {
+            Err(ApiError::from(Errors::NotLeaderOrFollower))
+        }
+    }
+
+    #[test]
+    fn test_backs_off_while_the_leader_fails() {
+        let tp = TopicPartition::new("foo", 0);
+        let mut log = MemoryLog::new(tp.clone(), 0);
+        append(&mut log, 0, 1, 3);
+        let mut fetcher = ReplicaFetcher::new(UnavailableLeader)
+            .with_backoff(ExponentialBackoff::new(100, 2.0, 300, 0.0));
+        fetcher.add_partition(tp.clone(), 1, &log, 0);
+        let mut logs = HashMap::from([(tp.clone(), log)]);
+        assert_eq!(0, fetcher.backoff_ms());
+
+        let mut backoffs = vec![];
+        for _ in 0..3 {
+            fetcher.maybe_truncate(&mut logs).unwrap();
+            backoffs.push(fetcher.backoff_ms());
+        }
+        assert_eq!(vec![100, 200, 300], backoffs);
+        fetcher.on_fetch_failed();
+        assert_eq!(300, fetcher.backoff_ms());
+
+        fetcher.on_partition_fetched(&tp, 0, 3, 3);
+        assert_eq!(0, fetcher.backoff_ms());
+    }
+
+    #[test]
+    fn test_only_out_of_sync_throttled_replicas_are_held_back() {
+        use crate::server::replication_quota_manager::{
+            ReplicationQuotaManager, ReplicationQuotaManagerConfig, ReplicationQuotaType,
+        };
+        use rafka_clients::common::metrics::{MetricConfig, Metrics};
+        use rafka_clients::common::utils::time::MockTime;
+
+        let dir = tempfile::tempdir().unwrap();
+        let quota = Arc::new(ReplicationQuotaManager::new(
+            ReplicationQuotaManagerConfig {
+                quota_bytes_per_second: 10,
+                ..Default::default()
+            },
+            Metrics::new(
+                MetricConfig::default(),
+                Arc::new(MockTime::with_start(0, 0, 0)),
+            ),
+            ReplicationQuotaType::FollowerReplication,
+        ));
+        quota.mark_throttled("foo");
+        let leader = MockLeader {
+            epochs: vec![],
+            log_end_offset: 0,
+            requests: RefCell::new(vec![]),
+        };
+        let mut fetcher = ReplicaFetcher::new(leader).with_quota(quota);
+        let mut logs = HashMap::new();
+        for topic_partition in [TopicPartition::new("foo", 0), TopicPartition::new("foo", 1)] {
+            let log = UnifiedLog::open(
+                &dir.path().join(topic_partition.to_string()),
+                UnifiedLogConfig::default(),
+                0,
+            )
+            .unwrap();
+            fetcher.add_partition(topic_partition.clone(), 0, &log, 0);
+            logs.insert(topic_partition, log);
+        }
+        fetcher.maybe_truncate(&mut logs).unwrap();
+        assert_eq!(2, fetcher.partitions_to_fetch().count());
+
+        // Partition 0 caught up while partition 1 is far behind; the fetched bytes of both
+        // exceed the quota.
+        let in_sync = TopicPartition::new("foo", 0);
+        let catching_up = TopicPartition::new("foo", 1);
+        fetcher.on_partition_fetched(&in_sync, 1_000, 100, 100);
+        fetcher.on_partition_fetched(&catching_up, 1_000, 100, 5_000);
+        assert!(
+            fetcher
+                .partition_state(&in_sync)
+                .unwrap()
+                .is_replica_in_sync()
+        );
+        let to_fetch: Vec<_> = fetcher.partitions_to_fetch().map(|(tp, _)| tp).collect();
+        assert_eq!(vec![&in_sync], to_fetch);
+    }
+}
@@ -0,0 +1,190 @@
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_server_common::component_registry::ComponentRegistry;
+use std::sync::Arc;
+
+pub const LEADER_SELECTOR: &str = "leader";
+pub const RACK_AWARE_REPLICA_SELECTOR: &str = "rack-aware";
+
+/// A replica a consumer may fetch from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaView {
+    pub broker_id: i32,
+    pub rack: Option<String>,
+    pub log_end_offset: i64,
+    /// How long ago the replica was last caught up with the leader.
+    pub time_since_last_caught_up_ms: i64,
+}
+
+/// The replicas of a partition, the leader included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionView {
+    pub leader: ReplicaView,
+    pub replicas: Vec<ReplicaView>,
+}
+
+/// The consumer a replica is selected for.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClientMetadata {
+    /// The `client.rack` of the consumer, empty if it has none.
+    pub rack_id: String,
+    pub client_id: String,
+}
+
+/// Selects the preferred replica a consumer should fetch from, configured with
+/// `replica.selector.class`.
+pub trait ReplicaSelector: Send + Sync {
+    fn select(
+        &self,
+        topic_partition: &TopicPartition,
+        client: &ClientMetadata,
+        partition: &PartitionView,
+    ) -> Option<ReplicaView>;
+}
+
+/// Always selects the leader.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeaderSelector;
+
+impl ReplicaSelector for LeaderSelector {
+    fn select(
+        &self,
+        _: &TopicPartition,
+        _: &ClientMetadata,
+        p: &PartitionView,
+    ) -> Option<ReplicaView> {
+        Some(p.leader.clone())
+    }
+}
+
+/// Selects a replica in the rack of the consumer: the leader if it is there, otherwise the
+/// most caught up replica. Consumers without a rack, or without a replica in their rack,
+/// fetch from the leader.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RackAwareReplicaSelector;
+
+impl ReplicaSelector for RackAwareReplicaSelector {
+    fn select(
+        &self,
+        _: &TopicPartition,
+        client: &ClientMetadata,
+        partition: &PartitionView,
+    ) -> Option<ReplicaView> {
+        if client.rack_id.is_empty() {
+            return Some(partition.leader.clone());
+        }
+        let in_rack = |r: &&ReplicaView| r.rack.as_deref() == Some(client.rack_id.as_str());
+        if in_rack(&&partition.leader) {
+            return Some(partition.leader.clone());
+        }
+        partition
+            .replicas
+            .iter()
+            .filter(in_rack)
+            .max_by(|a, b| {
+                a.log_end_offset.cmp(&b.log_end_offset).then(
+                    b.time_since_last_caught_up_ms
+                        .cmp(&a.time_since_last_caught_up_ms),
+                )
+            })
+            .or(Some(&partition.leader))
+            .cloned()
+    }
+}
+
+/// The replica selectors `replica.selector.class` accepts, under their keys and the class
+/// names of the Apache Kafka implementations.
+pub fn replica_selectors() -> ComponentRegistry<dyn ReplicaSelector> {
+    let mut registry = ComponentRegistry::<dyn ReplicaSelector>::new("replica selector");
+    registry
+        .register(
+            LEADER_SELECTOR,
+            &["org.apache.kafka.common.replica.LeaderSelector"],
+            || Arc::new(LeaderSelector),
+        )
+        .expect("unique names");
+    registry
+        .register(
+            RACK_AWARE_REPLICA_SELECTOR,
+            &["org.apache.kafka.common.replica.RackAwareReplicaSelector"],
+            || Arc::new(RackAwareReplicaSelector),
+        )
+        .expect("unique names");
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replica(broker_id: i32, rack: &str, log_end_offset: i64, lag_ms: i64) -> ReplicaView {
+        ReplicaView {
+            broker_id,
+            rack: Some(rack.to_string()),
+            log_end_offset,
+            time_since_last_caught_up_ms: lag_ms,
+        }
+    }
+
+    fn client(rack_id: &str) -> ClientMetadata {
+        ClientMetadata {
+            rack_id: rack_id.to_string(),
+            client_id: "consumer".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rack_aware_selection() {
+        let leader = replica(1, "a", 100, 0);
+        let partition = PartitionView {
+            leader: leader.clone(),
+            replicas: vec![
+                leader.clone(),
+                replica(2, "b", 90, 0),
+                replica(3, "b", 95, 50),
+                replica(4, "b", 95, 10),
+            ],
+        };
+        let tp = TopicPartition::new("foo", 0);
+        let selector = RackAwareReplicaSelector;
+        assert_eq!(
+            Some(4),
+            selector
+                .select(&tp, &client("b"), &partition)
+                .map(|r| r.broker_id)
+        );
+        assert_eq!(
+            Some(1),
+            selector
+                .select(&tp, &client("a"), &partition)
+                .map(|r| r.broker_id)
+        );
+        assert_eq!(
+            Some(1),
+            selector
+                .select(&tp, &client("c"), &partition)
+                .map(|r| r.broker_id)
+        );
+        assert_eq!(
+            Some(1),
+            selector
+                .select(&tp, &client(""), &partition)
+                .map(|r| r.broker_id)
+        );
+    }
+
+    #[test]
+    fn test_java_class_names_resolve() {
+        let registry = replica_selectors();
+        let partition = PartitionView {
+            leader: replica(1, "a", 100, 0),
+            replicas: vec![replica(1, "a", 100, 0), replica(2, "b", 100, 0)],
+        };
+        let selector = registry
+            .resolve("org.apache.kafka.common.replica.RackAwareReplicaSelector")
+            .unwrap();
+        let selected = selector.select(&TopicPartition::new("foo", 0), &client("b"), &partition);
+        assert_eq!(Some(2), selected.map(|r| r.broker_id));
+        assert!(registry.resolve_or("", LEADER_SELECTOR).is_ok());
+        assert!(registry.resolve("org.example.Selector").is_err());
+    }
+}
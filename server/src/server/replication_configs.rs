@@ -1,4 +1,7 @@
+use crate::replica_selector::{LEADER_SELECTOR, ReplicaSelector, replica_selectors};
 use easy_config_def::prelude::*;
+use rafka_server_common::component_registry::ComponentError;
+use std::sync::Arc;
 
 pub const CONTROLLER_SOCKET_TIMEOUT_MS_CONFIG: &str = "controller.socket.timeout.ms";
 const CONTROLLER_SOCKET_TIMEOUT_MS_DEFAULT: i32 = 30000;
@@ -19,7 +22,7 @@ pub const INTER_BROKER_SECURITY_PROTOCOL_CONFIG: &str = "security.inter.broker.p
 pub const INTER_BROKER_LISTENER_NAME_CONFIG: &str = "inter.broker.listener.name";
 
 pub const REPLICA_SELECTOR_CLASS_CONFIG: &str = "replica.selector.class";
-const REPLICA_SELECTOR_CLASS_DOC: &str = "The fully qualified class name that implements ReplicaSelector. This is used by the broker to find the preferred read replica. By default, we use an implementation that returns the leader.";
+const REPLICA_SELECTOR_CLASS_DOC: &str = "The ReplicaSelector used by the broker to find the preferred read replica: leader, rack-aware, or the fully qualified class name of the Apache Kafka implementation they replace. By default, we use an implementation that returns the leader.";
 
 #[derive(Debug, EasyConfig)]
 pub struct ReplicationConfigs {
@@ -59,3 +62,10 @@ pub struct ReplicationConfigs {
     getter)]
     replica_selector_class_config: String,
 }
+
+impl ReplicationConfigs {
+    /// Resolves `replica.selector.class`, the leader selector if it is not set.
+    pub fn replica_selector(&self) -> Result<Arc<dyn ReplicaSelector>, ComponentError> {
+        replica_selectors().resolve_or(self.replica_selector_class_config(), LEADER_SELECTOR)
+    }
+}
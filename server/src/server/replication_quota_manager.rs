@@ -0,0 +1,337 @@
+use rafka_clients::common::metrics::stats::Rate;
+use rafka_clients::common::metrics::{MetricConfig, MetricName, Metrics, Sensor};
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::info;
+
+pub const LEADER_REPLICATION_THROTTLED_REPLICAS_CONFIG: &str =
+    "leader.replication.throttled.replicas";
+pub const FOLLOWER_REPLICATION_THROTTLED_REPLICAS_CONFIG: &str =
+    "follower.replication.throttled.replicas";
+
+/// The wildcard value of the throttled replicas configs, throttling every replica of a topic.
+pub const ALL_REPLICAS_WILDCARD: &str = "*";
+
+pub const DEFAULT_NUM_QUOTA_SAMPLES: usize = 11;
+pub const DEFAULT_QUOTA_WINDOW_SIZE_SECONDS: i64 = 1;
+
+/// Whether a quota bounds the replication traffic a leader serves or the traffic a follower
+/// fetches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReplicationQuotaType {
+    LeaderReplication,
+    FollowerReplication,
+}
+
+impl fmt::Display for ReplicationQuotaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicationQuotaType::LeaderReplication => write!(f, "LeaderReplication"),
+            ReplicationQuotaType::FollowerReplication => write!(f, "FollowerReplication"),
+        }
+    }
+}
+
+/// A quota on replication traffic, applying to throttled partitions only.
+pub trait ReplicaQuota: Send + Sync {
+    /// Records `value` bytes of throttled replication traffic.
+    fn record(&self, value: i64);
+
+    fn is_throttled(&self, topic_partition: &TopicPartition) -> bool;
+
+    fn is_quota_exceeded(&self) -> bool;
+}
+
+/// A quota which never throttles, used where replication is not bounded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnboundedQuota;
+
+impl ReplicaQuota for UnboundedQuota {
+    fn record(&self, _value: i64) {}
+
+    fn is_throttled(&self, _topic_partition: &TopicPartition) -> bool {
+        false
+    }
+
+    fn is_quota_exceeded(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationQuotaManagerConfig {
+    /// The bound on the throttled replication traffic, in bytes per second.
+    pub quota_bytes_per_second: i64,
+    pub num_quota_samples: usize,
+    pub quota_window_size_seconds: i64,
+}
+
+impl Default for ReplicationQuotaManagerConfig {
+    fn default() -> Self {
+        Self {
+            quota_bytes_per_second: i64::MAX,
+            num_quota_samples: DEFAULT_NUM_QUOTA_SAMPLES,
+            quota_window_size_seconds: DEFAULT_QUOTA_WINDOW_SIZE_SECONDS,
+        }
+    }
+}
+
+/// The replicas of a topic which are throttled on this broker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThrottledReplicas {
+    All,
+    Partitions(BTreeSet<i32>),
+}
+
+impl ThrottledReplicas {
+    fn contains(&self, partition: i32) -> bool {
+        match self {
+            ThrottledReplicas::All => true,
+            ThrottledReplicas::Partitions(partitions) => partitions.contains(&partition),
+        }
+    }
+}
+
+/// Parses a `leader.replication.throttled.replicas` or
+/// `follower.replication.throttled.replicas` topic config, a comma separated list of
+/// `partition:broker` pairs or the `*` wildcard, into the partitions throttled on `broker_id`.
+///
+/// Returns `None` if the value is malformed.
+pub fn parse_throttled_replicas(value: &str, broker_id: i32) -> Option<ThrottledReplicas> {
+    let value = value.trim();
+    if value == ALL_REPLICAS_WILDCARD {
+        return Some(ThrottledReplicas::All);
+    }
+    let mut partitions = BTreeSet::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (partition, broker) = entry.split_once(':')?;
+        let partition: i32 = partition.trim().parse().ok()?;
+        let broker: i32 = broker.trim().parse().ok()?;
+        if broker == broker_id {
+            partitions.insert(partition);
+        }
+    }
+    Some(ThrottledReplicas::Partitions(partitions))
+}
+
+/// Bounds the replication traffic of throttled replicas.
+///
+/// Only the replicas marked throttled are subject to the quota, which are the replicas being
+/// moved by a reassignment or catching up with the leader. In-sync replicas are never held
+/// back, so a reassignment can't starve the replication which keeps the ISR up to date.
+pub struct ReplicationQuotaManager {
+    config: Mutex<ReplicationQuotaManagerConfig>,
+    replication_type: ReplicationQuotaType,
+    metrics: Metrics,
+    sensor: Arc<Sensor>,
+    rate_metric_name: MetricName,
+    throttled_partitions: Mutex<HashMap<String, ThrottledReplicas>>,
+}
+
+impl ReplicationQuotaManager {
+    pub fn new(
+        config: ReplicationQuotaManagerConfig,
+        metrics: Metrics,
+        replication_type: ReplicationQuotaType,
+    ) -> Self {
+        let group = replication_type.to_string();
+        let rate_metric_name = metrics.metric_name(
+            "byte-rate",
+            &group,
+            "Tracking byte-rate for throttled replication",
+            &[],
+        );
+        let sensor = metrics.sensor_with_config(&group, metric_config(&config));
+        sensor.add(rate_metric_name.clone(), Rate::new());
+        Self {
+            config: Mutex::new(config),
+            replication_type,
+            metrics,
+            sensor,
+            rate_metric_name,
+            throttled_partitions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn throttled_partitions(&self) -> MutexGuard<'_, HashMap<String, ThrottledReplicas>> {
+        self.throttled_partitions
+            .lock()
+            .expect("throttled partitions lock poisoned")
+    }
+
+    pub fn replication_type(&self) -> ReplicationQuotaType {
+        self.replication_type
+    }
+
+    /// The bound on the throttled replication traffic, in bytes per second.
+    pub fn upper_bound(&self) -> i64 {
+        self.config
+            .lock()
+            .expect("quota config lock poisoned")
+            .quota_bytes_per_second
+    }
+
+    pub fn update_quota(&self, quota_bytes_per_second: i64) {
+        info!(
+            "Updating the {} quota to {quota_bytes_per_second} bytes per second",
+            self.replication_type
+        );
+        self.config
+            .lock()
+            .expect("quota config lock poisoned")
+            .quota_bytes_per_second = quota_bytes_per_second;
+    }
+
+    /// The measured rate of the throttled replication traffic, in bytes per second.
+    pub fn rate(&self) -> f64 {
+        self.metrics
+            .metric_value(&self.rate_metric_name)
+            .unwrap_or(0.0)
+    }
+
+    /// Throttles every replica of `topic`.
+    pub fn mark_throttled(&self, topic: &str) {
+        self.throttled_partitions()
+            .insert(topic.to_string(), ThrottledReplicas::All);
+    }
+
+    /// Throttles the given partitions of `topic`, replacing those throttled before.
+    pub fn mark_partitions_throttled(
+        &self,
+        topic: &str,
+        partitions: impl IntoIterator<Item = i32>,
+    ) {
+        self.throttled_partitions().insert(
+            topic.to_string(),
+            ThrottledReplicas::Partitions(partitions.into_iter().collect()),
+        );
+    }
+
+    /// Applies the parsed throttled replicas config of `topic`. An empty list removes the
+    /// throttle.
+    pub fn update_throttled_replicas(&self, topic: &str, replicas: ThrottledReplicas) {
+        match replicas {
+            ThrottledReplicas::Partitions(partitions) if partitions.is_empty() => {
+                self.remove_throttle(topic)
+            }
+            replicas => {
+                self.throttled_partitions()
+                    .insert(topic.to_string(), replicas);
+            }
+        }
+    }
+
+    pub fn remove_throttle(&self, topic: &str) {
+        self.throttled_partitions().remove(topic);
+    }
+}
+
+impl ReplicaQuota for ReplicationQuotaManager {
+    fn record(&self, value: i64) {
+        self.sensor.record(value as f64);
+    }
+
+    fn is_throttled(&self, topic_partition: &TopicPartition) -> bool {
+        self.throttled_partitions()
+            .get(topic_partition.topic())
+            .is_some_and(|replicas| replicas.contains(topic_partition.partition()))
+    }
+
+    fn is_quota_exceeded(&self) -> bool {
+        self.rate() > self.upper_bound() as f64
+    }
+}
+
+fn metric_config(config: &ReplicationQuotaManagerConfig) -> MetricConfig {
+    MetricConfig::default()
+        .with_samples(config.num_quota_samples)
+        .with_time_window_ms(config.quota_window_size_seconds * 1000)
+}
+
+/// Whether a leader should leave a partition out of a fetch response to a follower: only
+/// throttled replicas outside the ISR are held back, and only while the quota is exceeded.
+pub fn should_leader_throttle(
+    quota: &dyn ReplicaQuota,
+    topic_partition: &TopicPartition,
+    replica_in_sync: bool,
+) -> bool {
+    !replica_in_sync && quota.is_throttled(topic_partition) && quota.is_quota_exceeded()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::utils::time::{MockTime, Time};
+
+    fn manager(quota_bytes_per_second: i64) -> (ReplicationQuotaManager, Arc<MockTime>) {
+        let time = Arc::new(MockTime::with_start(0, 0, 0));
+        let config = ReplicationQuotaManagerConfig {
+            quota_bytes_per_second,
+            ..Default::default()
+        };
+        let metrics = Metrics::new(MetricConfig::default(), time.clone());
+        (
+            ReplicationQuotaManager::new(config, metrics, ReplicationQuotaType::LeaderReplication),
+            time,
+        )
+    }
+
+    #[test]
+    fn test_throttled_partitions() {
+        let (quota, _) = manager(100);
+        let tp = |topic: &str, partition| TopicPartition::new(topic, partition);
+        quota.mark_partitions_throttled("foo", [1, 2]);
+        quota.mark_throttled("bar");
+        assert!(!quota.is_throttled(&tp("foo", 0)));
+        assert!(quota.is_throttled(&tp("foo", 1)));
+        assert!(quota.is_throttled(&tp("bar", 42)));
+        assert!(!quota.is_throttled(&tp("baz", 0)));
+
+        quota.update_throttled_replicas("foo", parse_throttled_replicas("0:1,3:2", 1).unwrap());
+        assert!(quota.is_throttled(&tp("foo", 0)));
+        assert!(!quota.is_throttled(&tp("foo", 1)));
+        quota.update_throttled_replicas("bar", parse_throttled_replicas("", 1).unwrap());
+        assert!(!quota.is_throttled(&tp("bar", 42)));
+    }
+
+    #[test]
+    fn test_parse_throttled_replicas() {
+        assert_eq!(
+            Some(ThrottledReplicas::All),
+            parse_throttled_replicas(" * ", 1)
+        );
+        assert_eq!(
+            Some(ThrottledReplicas::Partitions(BTreeSet::from([0, 2]))),
+            parse_throttled_replicas("0:1, 1:2, 2:1", 1)
+        );
+        assert_eq!(None, parse_throttled_replicas("0-1", 1));
+    }
+
+    #[test]
+    fn test_quota_exceeded_only_for_out_of_sync_throttled_replicas() {
+        let (quota, time) = manager(100);
+        let tp = TopicPartition::new("foo", 0);
+        quota.mark_throttled("foo");
+        assert!(!quota.is_quota_exceeded());
+
+        // 11 samples of one second: the window is padded to 10 seconds up front.
+        quota.record(900);
+        time.sleep(1_000);
+        assert!(!quota.is_quota_exceeded());
+        quota.record(200);
+        assert!(quota.is_quota_exceeded());
+
+        assert!(should_leader_throttle(&quota, &tp, false));
+        assert!(!should_leader_throttle(&quota, &tp, true));
+        assert!(!should_leader_throttle(
+            &quota,
+            &TopicPartition::new("bar", 0),
+            false
+        ));
+
+        quota.update_quota(1_000);
+        assert!(!quota.is_quota_exceeded());
+    }
+}
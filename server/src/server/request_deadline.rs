@@ -0,0 +1,118 @@
+use rafka_clients::common::requests::produce_request::ProduceRequest;
+
+/// The timeout of a request which carries no hint of its own, the default `request.timeout.ms`
+/// of the Java clients.
+pub const DEFAULT_CLIENT_REQUEST_TIMEOUT_MS: i64 = 30_000;
+
+/// The time after which the client no longer waits for the response of a request.
+///
+/// A client gives up on a request once its timeout passed, and retries it or fails. The
+/// broker working on the request past that point wastes resources on a response nobody reads,
+/// and with a retry in flight, does the work twice. The deadline of a request is taken from
+/// the timeout the client sent with it, e.g. the `timeout_ms` of a produce request, or from
+/// the default client timeout otherwise, and bounds every step of the request which waits:
+/// a produce waiting in purgatory for `acks=all`, a fetch reading from remote storage, or a
+/// request forwarded to the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestDeadline {
+    received_ms: i64,
+    deadline_ms: i64,
+}
+
+impl RequestDeadline {
+    /// The deadline of a request received at `received_ms` from a client which waits
+    /// `client_timeout_ms` for it, or the default client timeout if it sent no hint.
+    pub fn new(received_ms: i64, client_timeout_ms: Option<i64>) -> Self {
+        let timeout_ms = client_timeout_ms
+            .unwrap_or(DEFAULT_CLIENT_REQUEST_TIMEOUT_MS)
+            .max(0);
+        Self {
+            received_ms,
+            deadline_ms: received_ms.saturating_add(timeout_ms),
+        }
+    }
+
+    /// The deadline of a produce request, after its `timeout_ms`.
+    pub fn for_produce(received_ms: i64, request: &ProduceRequest<'_>) -> Self {
+        Self::new(received_ms, Some(request.timeout_ms() as i64))
+    }
+
+    pub fn received_ms(&self) -> i64 {
+        self.received_ms
+    }
+
+    pub fn deadline_ms(&self) -> i64 {
+        self.deadline_ms
+    }
+
+    /// The time the client still waits for the response, 0 once it gave up.
+    pub fn remaining_ms(&self, now_ms: i64) -> i64 {
+        (self.deadline_ms - now_ms).max(0)
+    }
+
+    /// Whether the client gave up on the request, so the broker should drop it rather than
+    /// start working on it.
+    pub fn is_expired(&self, now_ms: i64) -> bool {
+        now_ms >= self.deadline_ms
+    }
+
+    /// The deadline of a step starting at `now_ms` which may wait up to `timeout_ms`, e.g.
+    /// `remote.fetch.max.wait.ms`, cut short at the deadline of the request.
+    pub fn step_deadline_ms(&self, now_ms: i64, timeout_ms: i64) -> i64 {
+        now_ms
+            .saturating_add(timeout_ms.max(0))
+            .min(self.deadline_ms)
+    }
+
+    /// The timeout of a step starting at `now_ms` which may wait up to `timeout_ms`, e.g.
+    /// forwarding the request to the controller, cut short at the deadline of the request.
+    pub fn step_timeout_ms(&self, now_ms: i64, timeout_ms: i64) -> i64 {
+        (self.step_deadline_ms(now_ms, timeout_ms) - now_ms).max(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_from_the_client_timeout() {
+        let deadline = RequestDeadline::new(1_000, Some(500));
+        assert_eq!(1_500, deadline.deadline_ms());
+        assert_eq!(200, deadline.remaining_ms(1_300));
+        assert!(!deadline.is_expired(1_499));
+        assert!(deadline.is_expired(1_500));
+        assert_eq!(0, deadline.remaining_ms(2_000));
+
+        assert_eq!(
+            1_000 + DEFAULT_CLIENT_REQUEST_TIMEOUT_MS,
+            RequestDeadline::new(1_000, None).deadline_ms()
+        );
+        assert!(RequestDeadline::new(1_000, Some(-1)).is_expired(1_000));
+    }
+
+    #[test]
+    fn test_steps_end_with_the_request() {
+        let deadline = RequestDeadline::new(0, Some(1_000));
+        // A remote fetch may wait for 500 ms, unless the client gives up before.
+        assert_eq!(600, deadline.step_deadline_ms(100, 500));
+        assert_eq!(1_000, deadline.step_deadline_ms(700, 500));
+        // A request forwarded to the controller only gets the time the client still waits.
+        assert_eq!(300, deadline.step_timeout_ms(700, 30_000));
+        assert_eq!(0, deadline.step_timeout_ms(1_200, 30_000));
+    }
+
+    #[test]
+    fn test_produce_deadline() {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(-1i16).to_be_bytes());
+        body.extend_from_slice(&(-1i16).to_be_bytes());
+        body.extend_from_slice(&2_000i32.to_be_bytes());
+        body.extend_from_slice(&0i32.to_be_bytes());
+        let request = ProduceRequest::parse(3, &body).unwrap();
+        assert_eq!(
+            2_100,
+            RequestDeadline::for_produce(100, &request).deadline_ms()
+        );
+    }
+}
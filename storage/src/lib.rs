@@ -1,4 +1,20 @@
+pub use storage::internals::epoch::{
+    leader_epoch_file_cache, leader_epoch_file_cache::LeaderEpochFileCache,
+};
 pub use storage::internals::log::{
-    cleaner_config, cleaner_config::CleanerConfig, log_config::LogConfig,
+    LogError, Result, append_origin, append_origin::AppendOrigin, clean_shutdown_file,
+    clean_shutdown_file::CleanShutdownFileHandler, cleaner_config, cleaner_config::CleanerConfig,
+    disk_space_monitor, disk_space_monitor::DiskSpaceMonitor, log_cleaner_metrics,
+    log_config::LogConfig, log_dir_lock, log_metrics, log_recovery, log_recovery::LogRecovery,
+    log_report, log_report::LogDirReport, log_segment, log_segment::LogSegment,
+    log_segment::TimestampAndOffset, log_verifier, memory_log, memory_log::MemoryLog,
+    ongoing_transactions, ongoing_transactions::AbortedTransaction,
+    ongoing_transactions::OngoingTransactions, partition_log, partition_log::MemoryLogFactory,
+    partition_log::PartitionLog, partition_log::PartitionLogFactory,
+    partition_log::UnifiedLogFactory, remote_log_reader, remote_log_reader::RemoteLogReader,
+    replica_log_dir_mover, replica_log_dir_mover::ReplicaLogDirMover, tail_batch_cache,
+    tail_batch_cache::TailBatchCache, tail_batch_cache::TailCacheMemory, time_index,
+    time_index::TimeIndex, topic_log_configs, topic_log_configs::TopicLogConfigs, unified_log,
+    unified_log::UnifiedLog, unified_log::UnifiedLogConfig,
 };
 mod storage;
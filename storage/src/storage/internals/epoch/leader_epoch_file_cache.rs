@@ -0,0 +1,266 @@
+use crate::storage::internals::log::{LogError, Result};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+pub const LEADER_EPOCH_CHECKPOINT_FILENAME: &str = "leader-epoch-checkpoint";
+
+const CURRENT_VERSION: i32 = 0;
+
+/// The first offset a leader epoch wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochEntry {
+    pub epoch: i32,
+    pub start_offset: i64,
+}
+
+impl EpochEntry {
+    pub fn new(epoch: i32, start_offset: i64) -> Self {
+        Self {
+            epoch,
+            start_offset,
+        }
+    }
+}
+
+/// The leader epochs of a log and the offset each of them started at, checkpointed to the
+/// `leader-epoch-checkpoint` file of the log directory.
+///
+/// A follower compares its epochs with the leader's to find where its log diverged: the end
+/// offset of an epoch is the start offset of the next one, or the log end offset for the latest
+/// epoch.
+#[derive(Debug)]
+pub struct LeaderEpochFileCache {
+    /// `None` for the cache of an in-memory log, which is never checkpointed.
+    path: Option<PathBuf>,
+    entries: Vec<EpochEntry>,
+}
+
+impl LeaderEpochFileCache {
+    /// Loads the checkpoint in `dir`, if there is one.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join(LEADER_EPOCH_CHECKPOINT_FILENAME);
+        let entries = if path.exists() {
+            read_checkpoint(&path)?
+        } else {
+            vec![]
+        };
+        Ok(Self {
+            path: Some(path),
+            entries,
+        })
+    }
+
+    /// A cache which is not checkpointed, for a log which lives in memory.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            entries: vec![],
+        }
+    }
+
+    pub fn entries(&self) -> &[EpochEntry] {
+        &self.entries
+    }
+
+    pub fn latest_entry(&self) -> Option<EpochEntry> {
+        self.entries.last().copied()
+    }
+
+    pub fn latest_epoch(&self) -> Option<i32> {
+        self.latest_entry().map(|e| e.epoch)
+    }
+
+    /// The epoch of the leader which wrote `offset`.
+    pub fn epoch_for_offset(&self, offset: i64) -> Option<i32> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.start_offset <= offset)
+            .map(|e| e.epoch)
+    }
+
+    /// Records that `epoch` started at `start_offset`. Entries with a larger epoch or start
+    /// offset are left over from a divergent log and are removed.
+    pub fn assign(&mut self, epoch: i32, start_offset: i64) -> Result<()> {
+        if epoch < 0 || self.latest_epoch() == Some(epoch) {
+            return Ok(());
+        }
+        self.entries
+            .retain(|e| e.epoch < epoch && e.start_offset < start_offset);
+        self.entries.push(EpochEntry::new(epoch, start_offset));
+        if let Some(path) = &self.path {
+            info!(
+                "Updated the leader epochs in {} with epoch {epoch} starting at offset {start_offset}",
+                path.display()
+            );
+        }
+        self.flush()
+    }
+
+    /// Returns the largest epoch not above `requested_epoch` together with its end offset, or
+    /// `None` if `requested_epoch` is above every known epoch.
+    ///
+    /// If `requested_epoch` is below every known epoch, it is returned with the start offset
+    /// of the first epoch.
+    pub fn end_offset_for(&self, requested_epoch: i32, log_end_offset: i64) -> Option<(i32, i64)> {
+        if requested_epoch < 0 {
+            return None;
+        }
+        if self.latest_epoch() == Some(requested_epoch) {
+            return Some((requested_epoch, log_end_offset));
+        }
+        let higher = self.entries.iter().find(|e| e.epoch > requested_epoch)?;
+        let floor_epoch = self
+            .entries
+            .iter()
+            .rev()
+            .find(|e| e.epoch <= requested_epoch)
+            .map_or(requested_epoch, |e| e.epoch);
+        Some((floor_epoch, higher.start_offset))
+    }
+
+    /// Removes the epochs starting at or after `end_offset`, after the log was truncated to it.
+    pub fn truncate_from_end(&mut self, end_offset: i64) -> Result<()> {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.start_offset < end_offset);
+        if self.entries.len() != before {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Removes the epochs which ended before `start_offset`, after the log start offset moved
+    /// to it. The epoch holding `start_offset` now starts there.
+    pub fn truncate_from_start(&mut self, start_offset: i64) -> Result<()> {
+        let Some(first_kept) = self
+            .entries
+            .iter()
+            .rposition(|e| e.start_offset <= start_offset)
+        else {
+            return Ok(());
+        };
+        if first_kept == 0 && self.entries[0].start_offset == start_offset {
+            return Ok(());
+        }
+        self.entries.drain(..first_kept);
+        self.entries[0].start_offset = start_offset;
+        self.flush()
+    }
+
+    /// Writes the checkpoint to a temporary file first, so a crash leaves either the old or the
+    /// new checkpoint behind.
+    fn flush(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            writeln!(file, "{CURRENT_VERSION}")?;
+            writeln!(file, "{}", self.entries.len())?;
+            for entry in &self.entries {
+                writeln!(file, "{} {}", entry.epoch, entry.start_offset)?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+fn read_checkpoint(path: &Path) -> Result<Vec<EpochEntry>> {
+    let corrupt = |reason: String| LogError::CorruptIndex {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let content = fs::read_to_string(path)?;
+    let mut lines = content.lines();
+    let version = lines.next().and_then(|l| l.trim().parse::<i32>().ok());
+    if version != Some(CURRENT_VERSION) {
+        return Err(corrupt(format!("unrecognized version {version:?}")));
+    }
+    let count = lines
+        .next()
+        .and_then(|l| l.trim().parse::<usize>().ok())
+        .ok_or_else(|| corrupt("missing entry count".to_string()))?;
+    let entries = lines
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            match (
+                parts.next().and_then(|p| p.parse().ok()),
+                parts.next().and_then(|p| p.parse().ok()),
+                parts.next(),
+            ) {
+                (Some(epoch), Some(start_offset), None) => Ok(EpochEntry::new(epoch, start_offset)),
+                _ => Err(corrupt(format!("malformed line '{line}'"))),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if entries.len() != count {
+        return Err(corrupt(format!(
+            "expected {count} entries but found {}",
+            entries.len()
+        )));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_end_offset_for() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = LeaderEpochFileCache::open(dir.path()).unwrap();
+        assert_eq!(None, cache.end_offset_for(0, 0));
+        cache.assign(1, 0).unwrap();
+        cache.assign(1, 5).unwrap();
+        cache.assign(3, 10).unwrap();
+        cache.assign(5, 20).unwrap();
+        assert_eq!(
+            vec![
+                EpochEntry::new(1, 0),
+                EpochEntry::new(3, 10),
+                EpochEntry::new(5, 20)
+            ],
+            cache.entries()
+        );
+
+        assert_eq!(Some((5, 25)), cache.end_offset_for(5, 25));
+        assert_eq!(Some((3, 20)), cache.end_offset_for(3, 25));
+        assert_eq!(Some((3, 20)), cache.end_offset_for(4, 25));
+        assert_eq!(Some((0, 0)), cache.end_offset_for(0, 25));
+        assert_eq!(None, cache.end_offset_for(6, 25));
+        assert_eq!(Some(3), cache.epoch_for_offset(19));
+    }
+
+    #[test]
+    fn test_truncation_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = LeaderEpochFileCache::open(dir.path()).unwrap();
+        for (epoch, offset) in [(1, 0), (2, 10), (3, 20)] {
+            cache.assign(epoch, offset).unwrap();
+        }
+        cache.truncate_from_end(20).unwrap();
+        assert_eq!(Some(2), cache.latest_epoch());
+        cache.truncate_from_start(15).unwrap();
+        assert_eq!(vec![EpochEntry::new(2, 15)], cache.entries());
+
+        // A divergent entry is replaced by the new epoch.
+        cache.assign(4, 18).unwrap();
+        cache.assign(5, 17).unwrap();
+        assert_eq!(
+            vec![EpochEntry::new(2, 15), EpochEntry::new(5, 17)],
+            cache.entries()
+        );
+
+        let cache = LeaderEpochFileCache::open(dir.path()).unwrap();
+        assert_eq!(
+            vec![EpochEntry::new(2, 15), EpochEntry::new(5, 17)],
+            cache.entries()
+        );
+    }
+}
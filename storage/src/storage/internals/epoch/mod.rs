@@ -0,0 +1 @@
+pub mod leader_epoch_file_cache;
@@ -0,0 +1,11 @@
+/// Who is appending to a log, which decides how the appended batches are validated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppendOrigin {
+    /// A follower copying the batches of the leader, which were validated when the leader
+    /// appended them.
+    Replication,
+    /// A group or transaction coordinator writing its own records or transaction markers.
+    Coordinator,
+    /// A producer.
+    Client,
+}
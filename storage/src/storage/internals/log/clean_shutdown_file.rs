@@ -0,0 +1,239 @@
+use crate::storage::internals::log::Result;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// The marker a broker leaves in each of its log directories once it shut down cleanly.
+pub const CLEAN_SHUTDOWN_FILE_NAME: &str = ".kafka_cleanshutdown";
+
+/// The broker epoch of a marker written by a broker which had none, or which could not be read.
+pub const NO_BROKER_EPOCH: i64 = -1;
+
+const CURRENT_VERSION: i32 = 0;
+
+/// Reads and writes the clean shutdown marker of a log directory.
+///
+/// The marker is written once every log of the directory is flushed and closed, and consumed
+/// on startup: a log directory holding it had all its segments closed cleanly, so they are
+/// loaded without being recovered, see [UnifiedLog::load](super::unified_log::UnifiedLog::load).
+/// A broker crashing before writing the marker, or after consuming it, recovers its logs on
+/// the next startup.
+///
+/// The marker holds the epoch of the broker which wrote it, which the broker sends to the
+/// controller when it registers again so that the controller knows it shut down cleanly.
+#[derive(Debug, Clone)]
+pub struct CleanShutdownFileHandler {
+    path: PathBuf,
+}
+
+impl CleanShutdownFileHandler {
+    pub fn new(log_dir: &Path) -> Self {
+        Self {
+            path: log_dir.join(CLEAN_SHUTDOWN_FILE_NAME),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /// Writes the marker with `broker_epoch` to a temporary file first, so that a crash leaves
+    /// either no marker or a complete one behind.
+    pub fn write(&self, broker_epoch: i64) -> Result<()> {
+        let tmp_path = self.tmp_path();
+        {
+            let mut file = File::create(&tmp_path)?;
+            write!(
+                file,
+                "{{\"version\":{CURRENT_VERSION},\"brokerEpoch\":{broker_epoch}}}"
+            )?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        if let Some(dir) = self.path.parent() {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// The broker epoch of the marker, or `None` if there is none.
+    ///
+    /// A marker which cannot be parsed still means that the directory was shut down cleanly,
+    /// as the marker is only renamed into place once complete; its epoch is [NO_BROKER_EPOCH].
+    pub fn read(&self) -> Result<Option<i64>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(parse_broker_epoch(&content).unwrap_or_else(|| {
+            warn!(
+                "Failed to parse the clean shutdown marker {}: '{content}'",
+                self.path.display()
+            );
+            NO_BROKER_EPOCH
+        })))
+    }
+
+    /// Reads and deletes the marker, along with a temporary one left by a crash in the middle
+    /// of [CleanShutdownFileHandler::write], so that a crash of the starting broker leads to a
+    /// recovery on the next startup.
+    pub fn consume(&self) -> Result<Option<i64>> {
+        let broker_epoch = self.read()?;
+        for path in [self.path.clone(), self.tmp_path()] {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(broker_epoch)
+    }
+
+    fn tmp_path(&self) -> PathBuf {
+        self.path.with_extension("tmp")
+    }
+}
+
+fn parse_broker_epoch(content: &str) -> Option<i64> {
+    let fields = content.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut version = None;
+    let mut broker_epoch = None;
+    for field in fields.split(',') {
+        let (name, value) = field.split_once(':')?;
+        let value = value.trim();
+        match name.trim().trim_matches('"') {
+            "version" => version = value.parse::<i32>().ok(),
+            "brokerEpoch" => broker_epoch = value.parse::<i64>().ok(),
+            _ => {}
+        }
+    }
+    if version != Some(CURRENT_VERSION) {
+        return None;
+    }
+    broker_epoch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::append_origin::AppendOrigin;
+    use crate::storage::internals::log::unified_log::{UnifiedLog, UnifiedLogConfig};
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+    use std::fs::OpenOptions;
+
+    fn append(log: &mut UnifiedLog, values: &[&[u8]]) {
+        let records = values
+            .iter()
+            .map(|v| Record::new(1_000, None, Some(v)))
+            .collect();
+        log.append_as_leader(RecordBatch::new(0, records), 0, AppendOrigin::Client)
+            .unwrap();
+    }
+
+    fn segment_file(dir: &Path) -> PathBuf {
+        dir.join("00000000000000000000.log")
+    }
+
+    /// Appends two batches to a log, then damages the last one as a crash would.
+    fn damaged_log(log_dir: &Path, damage: impl FnOnce(&Path)) -> PathBuf {
+        let dir = log_dir.join("foo-0");
+        let mut log = UnifiedLog::open(&dir, UnifiedLogConfig::default(), 0).unwrap();
+        append(&mut log, &[b"a", b"b"]);
+        append(&mut log, &[b"c"]);
+        drop(log);
+        damage(&segment_file(&dir));
+        dir
+    }
+
+    fn tear_last_byte(path: &Path) {
+        let len = fs::metadata(path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_len(len - 1).unwrap();
+    }
+
+    fn flip_last_byte(path: &Path) {
+        let mut data = fs::read(path).unwrap();
+        *data.last_mut().unwrap() ^= 0xff;
+        fs::write(path, data).unwrap();
+    }
+
+    fn load(log_dir: &Path, dir: &Path) -> UnifiedLog {
+        let handler = CleanShutdownFileHandler::new(log_dir);
+        let had_clean_shutdown = handler.consume().unwrap().is_some();
+        assert!(!handler.exists());
+        UnifiedLog::load(dir, UnifiedLogConfig::default(), 0, had_clean_shutdown).unwrap()
+    }
+
+    #[test]
+    fn test_write_read_and_consume() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = CleanShutdownFileHandler::new(dir.path());
+        assert_eq!(None, handler.read().unwrap());
+        assert_eq!(None, handler.consume().unwrap());
+
+        handler.write(42).unwrap();
+        assert!(handler.exists());
+        assert_eq!(Some(42), handler.read().unwrap());
+        assert_eq!(Some(42), handler.consume().unwrap());
+        assert!(!handler.exists());
+        assert_eq!(None, handler.read().unwrap());
+
+        handler.write(NO_BROKER_EPOCH).unwrap();
+        assert_eq!(Some(NO_BROKER_EPOCH), handler.read().unwrap());
+    }
+
+    #[test]
+    fn test_unparseable_marker_still_means_clean_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let handler = CleanShutdownFileHandler::new(dir.path());
+        fs::write(handler.path(), "garbage").unwrap();
+        assert_eq!(Some(NO_BROKER_EPOCH), handler.read().unwrap());
+        fs::write(handler.path(), r#"{"version":1,"brokerEpoch":3}"#).unwrap();
+        assert_eq!(Some(NO_BROKER_EPOCH), handler.read().unwrap());
+        fs::write(handler.path(), r#"{ "brokerEpoch": 3, "version": 0 }"#).unwrap();
+        assert_eq!(Some(3), handler.read().unwrap());
+    }
+
+    #[test]
+    fn test_clean_shutdown_skips_recovery() {
+        let log_dir = tempfile::tempdir().unwrap();
+        // A flipped byte in the records is only found by checking the crc, which a log shut
+        // down cleanly does not do.
+        let dir = damaged_log(log_dir.path(), flip_last_byte);
+        CleanShutdownFileHandler::new(log_dir.path())
+            .write(7)
+            .unwrap();
+        assert_eq!(3, load(log_dir.path(), &dir).log_end_offset());
+
+        // The marker was consumed, so the next startup recovers the log.
+        assert_eq!(2, load(log_dir.path(), &dir).log_end_offset());
+    }
+
+    #[test]
+    fn test_crash_during_shutdown_recovers() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let dir = damaged_log(log_dir.path(), tear_last_byte);
+        // The broker crashed in the middle of writing the marker.
+        let handler = CleanShutdownFileHandler::new(log_dir.path());
+        fs::write(handler.tmp_path(), r#"{"version":0,"brok"#).unwrap();
+        let log = load(log_dir.path(), &dir);
+        assert_eq!(2, log.log_end_offset());
+        assert!(!handler.tmp_path().exists());
+        assert_eq!(
+            log.segment_sizes().iter().sum::<u64>(),
+            fs::metadata(segment_file(&dir)).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_crash_before_shutdown_recovers_corrupt_batch() {
+        let log_dir = tempfile::tempdir().unwrap();
+        let dir = damaged_log(log_dir.path(), flip_last_byte);
+        assert_eq!(2, load(log_dir.path(), &dir).log_end_offset());
+    }
+}
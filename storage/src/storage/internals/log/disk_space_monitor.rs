@@ -0,0 +1,424 @@
+//! Watches the free space of the log directories.
+//!
+//! Running out of space in the middle of an append leaves the broker with a partially written
+//! segment at best. The monitor checks the free space of every log directory periodically:
+//! below the warning threshold it logs a warning, and below the hard limit it may put the
+//! directory in write protection, where produce requests to its logs fail with a retriable
+//! error until space is freed, e.g. by retention or by moving partitions away. Replication
+//! keeps appending, as a follower which falls behind would only have to catch up later.
+
+use crate::storage::internals::log::{LogError, Result};
+use rafka_clients::common::metrics::{MetricName, Metrics};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+pub const DISK_SPACE_METRICS_GROUP: &str = "log-dir-metrics";
+
+pub const FREE_BYTES: &str = "free-bytes";
+pub const TOTAL_BYTES: &str = "total-bytes";
+pub const WRITE_PROTECTED: &str = "write-protected";
+
+/// The size and free space of the file system holding a directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+}
+
+/// Reads the disk usage of a directory, from the file system unless testing.
+pub trait DiskSpaceProbe: Send + Sync {
+    fn usage(&self, dir: &Path) -> io::Result<DiskUsage>;
+}
+
+/// Reads the disk usage from the file system, the space available to the broker's user.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsDiskSpaceProbe;
+
+impl DiskSpaceProbe for FsDiskSpaceProbe {
+    fn usage(&self, dir: &Path) -> io::Result<DiskUsage> {
+        Ok(DiskUsage {
+            total_bytes: fs2::total_space(dir)?,
+            free_bytes: fs2::available_space(dir)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpaceConfig {
+    /// Below this many free bytes, every check logs a warning.
+    pub warning_free_bytes: u64,
+    /// Below this many free bytes, the directory is write protected if enabled.
+    pub hard_limit_free_bytes: u64,
+    /// Whether produce requests fail once a directory is below the hard limit.
+    pub write_protection_enable: bool,
+    pub check_interval_ms: u64,
+}
+
+impl Default for DiskSpaceConfig {
+    fn default() -> Self {
+        Self {
+            warning_free_bytes: 10 * 1024 * 1024 * 1024,
+            hard_limit_free_bytes: 1024 * 1024 * 1024,
+            write_protection_enable: true,
+            check_interval_ms: 10_000,
+        }
+    }
+}
+
+/// The last known disk usage of a log directory, shared with the logs stored in it.
+#[derive(Debug)]
+pub struct LogDirSpace {
+    dir: PathBuf,
+    hard_limit_free_bytes: u64,
+    total_bytes: AtomicU64,
+    free_bytes: AtomicU64,
+    write_protected: AtomicBool,
+}
+
+impl LogDirSpace {
+    pub(crate) fn new(dir: PathBuf, hard_limit_free_bytes: u64) -> Self {
+        Self {
+            dir,
+            hard_limit_free_bytes,
+            total_bytes: AtomicU64::new(0),
+            free_bytes: AtomicU64::new(u64::MAX),
+            write_protected: AtomicBool::new(false),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.free_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protected.load(Ordering::Relaxed)
+    }
+
+    /// Fails if produce requests to the logs in the directory must be rejected.
+    pub fn check_writable(&self) -> Result<()> {
+        if self.is_write_protected() {
+            return Err(self.write_protected_error());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_protected_error(&self) -> LogError {
+        LogError::LogDirWriteProtected {
+            dir: self.dir.clone(),
+            free_bytes: self.free_bytes(),
+            hard_limit_free_bytes: self.hard_limit_free_bytes,
+        }
+    }
+
+    /// Write protects the directory after an append ran out of space, until the next check
+    /// finds enough free space.
+    pub fn mark_out_of_space(&self) {
+        self.free_bytes.store(0, Ordering::Relaxed);
+        if !self.write_protected.swap(true, Ordering::Relaxed) {
+            error!(
+                "Log directory {} ran out of space, rejecting produce requests",
+                self.dir.display()
+            );
+        }
+    }
+}
+
+/// Checks the disk usage of the log directories and maintains their write protection.
+pub struct DiskSpaceMonitor {
+    config: DiskSpaceConfig,
+    probe: Box<dyn DiskSpaceProbe>,
+    log_dirs: Vec<Arc<LogDirSpace>>,
+    metrics: Metrics,
+    metric_names: Vec<MetricName>,
+}
+
+impl DiskSpaceMonitor {
+    pub fn new(
+        config: DiskSpaceConfig,
+        probe: Box<dyn DiskSpaceProbe>,
+        log_dirs: &[PathBuf],
+        metrics: Metrics,
+    ) -> Self {
+        let mut monitor = Self {
+            config,
+            probe,
+            log_dirs: log_dirs
+                .iter()
+                .map(|dir| Arc::new(LogDirSpace::new(dir.clone(), config.hard_limit_free_bytes)))
+                .collect(),
+            metrics,
+            metric_names: vec![],
+        };
+        for log_dir in monitor.log_dirs.clone() {
+            monitor.add_gauges(&log_dir);
+        }
+        monitor
+    }
+
+    fn add_gauges(&mut self, log_dir: &Arc<LogDirSpace>) {
+        let dir = log_dir.dir.display().to_string();
+        for (name, description) in [
+            (
+                FREE_BYTES,
+                "The free bytes of the file system of the log directory",
+            ),
+            (
+                TOTAL_BYTES,
+                "The size in bytes of the file system of the log directory",
+            ),
+            (
+                WRITE_PROTECTED,
+                "1 if produce requests to the log directory are rejected",
+            ),
+        ] {
+            let metric_name = self.metrics.metric_name(
+                name,
+                DISK_SPACE_METRICS_GROUP,
+                description,
+                &[("dir", &dir)],
+            );
+            let log_dir = Arc::clone(log_dir);
+            self.metrics
+                .add_gauge(metric_name.clone(), move |_| match name {
+                    FREE_BYTES => log_dir.free_bytes() as f64,
+                    TOTAL_BYTES => log_dir.total_bytes.load(Ordering::Relaxed) as f64,
+                    _ => log_dir.is_write_protected() as u8 as f64,
+                });
+            self.metric_names.push(metric_name);
+        }
+    }
+
+    /// The disk usage of `dir`, to be shared with the logs stored in it.
+    pub fn log_dir(&self, dir: &Path) -> Option<Arc<LogDirSpace>> {
+        self.log_dirs.iter().find(|d| d.dir == dir).cloned()
+    }
+
+    /// Reads the disk usage of every log directory and updates their write protection.
+    pub fn check(&self) {
+        for log_dir in &self.log_dirs {
+            let usage = match self.probe.usage(&log_dir.dir) {
+                Ok(usage) => usage,
+                Err(e) => {
+                    warn!(
+                        "Failed to read the disk usage of log directory {}: {e}",
+                        log_dir.dir.display()
+                    );
+                    continue;
+                }
+            };
+            log_dir
+                .total_bytes
+                .store(usage.total_bytes, Ordering::Relaxed);
+            log_dir
+                .free_bytes
+                .store(usage.free_bytes, Ordering::Relaxed);
+            let below_hard_limit = usage.free_bytes < self.config.hard_limit_free_bytes;
+            if below_hard_limit || usage.free_bytes < self.config.warning_free_bytes {
+                warn!(
+                    "Log directory {} has {} free bytes of {}",
+                    log_dir.dir.display(),
+                    usage.free_bytes,
+                    usage.total_bytes
+                );
+            }
+            let protect = below_hard_limit && self.config.write_protection_enable;
+            if log_dir.write_protected.swap(protect, Ordering::Relaxed) != protect {
+                if protect {
+                    error!(
+                        "Log directory {} is below {} free bytes, rejecting produce requests",
+                        log_dir.dir.display(),
+                        self.config.hard_limit_free_bytes
+                    );
+                } else {
+                    info!(
+                        "Log directory {} has enough free space again, accepting produce requests",
+                        log_dir.dir.display()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Checks the disk usage now and then every `check_interval_ms`, on a thread of its own,
+    /// until the returned task is stopped.
+    pub fn start(self: Arc<Self>) -> DiskSpaceMonitorTask {
+        let (stop, stopped) = mpsc::channel();
+        let interval = Duration::from_millis(self.config.check_interval_ms);
+        let handle = thread::Builder::new()
+            .name("disk-space-monitor".to_string())
+            .spawn(move || {
+                loop {
+                    self.check();
+                    match stopped.recv_timeout(interval) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        _ => break,
+                    }
+                }
+            })
+            .expect("failed to spawn the disk space monitor thread");
+        DiskSpaceMonitorTask {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Removes the gauges.
+    pub fn close(&self) {
+        for metric_name in &self.metric_names {
+            self.metrics.remove_metric(metric_name);
+        }
+    }
+}
+
+/// The thread of a started [DiskSpaceMonitor], which is stopped when the task is dropped.
+pub struct DiskSpaceMonitorTask {
+    stop: Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DiskSpaceMonitorTask {
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for DiskSpaceMonitorTask {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Reports the free bytes set by the test, out of 1000.
+    #[derive(Clone, Default)]
+    struct MockProbe {
+        free_bytes: Arc<Mutex<u64>>,
+    }
+
+    impl DiskSpaceProbe for MockProbe {
+        fn usage(&self, _: &Path) -> io::Result<DiskUsage> {
+            Ok(DiskUsage {
+                total_bytes: 1000,
+                free_bytes: *self.free_bytes.lock().unwrap(),
+            })
+        }
+    }
+
+    fn config(write_protection_enable: bool) -> DiskSpaceConfig {
+        DiskSpaceConfig {
+            warning_free_bytes: 200,
+            hard_limit_free_bytes: 100,
+            write_protection_enable,
+            check_interval_ms: 10,
+        }
+    }
+
+    #[test]
+    fn test_write_protection() {
+        let probe = MockProbe::default();
+        let dir = PathBuf::from("/data/log-dir");
+        let metrics = Metrics::default();
+        let monitor = DiskSpaceMonitor::new(
+            config(true),
+            Box::new(probe.clone()),
+            std::slice::from_ref(&dir),
+            metrics.clone(),
+        );
+        let log_dir = monitor.log_dir(&dir).unwrap();
+        let gauge = |name| {
+            metrics.metric_value(&metrics.metric_name(
+                name,
+                DISK_SPACE_METRICS_GROUP,
+                "",
+                &[("dir", "/data/log-dir")],
+            ))
+        };
+
+        *probe.free_bytes.lock().unwrap() = 150;
+        monitor.check();
+        assert!(log_dir.check_writable().is_ok());
+        assert_eq!(Some(150.0), gauge(FREE_BYTES));
+
+        *probe.free_bytes.lock().unwrap() = 50;
+        monitor.check();
+        assert!(matches!(
+            log_dir.check_writable(),
+            Err(LogError::LogDirWriteProtected { free_bytes: 50, .. })
+        ));
+        assert_eq!(Some(1.0), gauge(WRITE_PROTECTED));
+
+        *probe.free_bytes.lock().unwrap() = 500;
+        monitor.check();
+        assert!(log_dir.check_writable().is_ok());
+        assert_eq!(Some(0.0), gauge(WRITE_PROTECTED));
+
+        monitor.close();
+        assert_eq!(None, gauge(FREE_BYTES));
+    }
+
+    #[test]
+    fn test_write_protection_disabled() {
+        let probe = MockProbe::default();
+        let dir = PathBuf::from("/data/log-dir");
+        let monitor = DiskSpaceMonitor::new(
+            config(false),
+            Box::new(probe.clone()),
+            std::slice::from_ref(&dir),
+            Metrics::default(),
+        );
+        monitor.check();
+        assert!(monitor.log_dir(&dir).unwrap().check_writable().is_ok());
+        assert!(monitor.log_dir(Path::new("/other")).is_none());
+    }
+
+    #[test]
+    fn test_background_checks() {
+        let probe = MockProbe::default();
+        let dir = PathBuf::from("/data/log-dir");
+        let monitor = Arc::new(DiskSpaceMonitor::new(
+            config(true),
+            Box::new(probe.clone()),
+            std::slice::from_ref(&dir),
+            Metrics::default(),
+        ));
+        let log_dir = monitor.log_dir(&dir).unwrap();
+        let task = Arc::clone(&monitor).start();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !log_dir.is_write_protected() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "never write protected"
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+        task.stop();
+    }
+
+    #[test]
+    fn test_fs_probe() {
+        let dir = tempfile::tempdir().unwrap();
+        let usage = FsDiskSpaceProbe.usage(dir.path()).unwrap();
+        assert!(usage.total_bytes >= usage.free_bytes);
+    }
+}
@@ -0,0 +1,208 @@
+use rafka_clients::common::metrics::Metrics;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub const LOG_CLEANER_METRICS_GROUP: &str = "log-cleaner-metrics";
+
+pub const MAX_BUFFER_UTILIZATION_PERCENT: &str = "max-buffer-utilization-percent";
+pub const MAX_CLEAN_TIME_SECS: &str = "max-clean-time-secs";
+pub const CLEANER_RECOPY_PERCENT: &str = "cleaner-recopy-percent";
+pub const TIME_SINCE_LAST_RUN_MS: &str = "time-since-last-run-ms";
+pub const MAX_DIRTY_PERCENT: &str = "max-dirty-percent";
+pub const CLEANABLE_BYTES: &str = "cleanable-bytes";
+pub const UNCLEANABLE_PARTITIONS_COUNT: &str = "uncleanable-partitions-count";
+
+/// The statistics of a cleaning run of a cleaner thread.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CleanerStats {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// The share of the dedupe buffer the offset map filled, from 0 to 1.
+    pub buffer_utilization: f64,
+}
+
+impl CleanerStats {
+    pub fn elapsed_secs(&self) -> f64 {
+        (self.end_ms - self.start_ms) as f64 / 1000.0
+    }
+}
+
+/// The state of the logs awaiting cleaning, computed when the cleaner picks the next log.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CleanerBacklog {
+    /// The highest ratio of dirty bytes to total bytes over the compacted logs, from 0 to 1.
+    pub max_dirty_ratio: f64,
+    /// The dirty bytes of the logs eligible for cleaning.
+    pub cleanable_bytes: u64,
+    /// The partitions the cleaner gave up on after failing to clean them.
+    pub uncleanable_partitions: usize,
+}
+
+struct State {
+    start_ms: i64,
+    last_runs: HashMap<usize, CleanerStats>,
+    backlog: CleanerBacklog,
+}
+
+/// Measures a gauge from the state of the cleaner and the current time.
+type Gauge = fn(&State, i64) -> f64;
+
+/// The metrics of the log cleaner.
+///
+/// The run metrics are computed over the last run of every cleaner thread, the backlog ones
+/// over the state of the logs when the cleaner last picked a log to clean.
+pub struct LogCleanerMetrics {
+    state: Arc<Mutex<State>>,
+}
+
+impl LogCleanerMetrics {
+    pub fn new(metrics: &Metrics, start_ms: i64) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            start_ms,
+            last_runs: HashMap::new(),
+            backlog: CleanerBacklog::default(),
+        }));
+        let gauges: [(&str, &str, Gauge); 7] = [
+            (
+                MAX_BUFFER_UTILIZATION_PERCENT,
+                "The highest dedupe buffer utilization of the last run of the cleaner threads",
+                |state, _| max(state, |stats| stats.buffer_utilization * 100.0),
+            ),
+            (
+                MAX_CLEAN_TIME_SECS,
+                "The longest duration of the last run of the cleaner threads",
+                |state, _| max(state, CleanerStats::elapsed_secs),
+            ),
+            (
+                CLEANER_RECOPY_PERCENT,
+                "The share of the bytes read by the last runs which were written back",
+                |state, _| {
+                    let (read, written) = state.last_runs.values().fold((0, 0), |acc, stats| {
+                        (acc.0 + stats.bytes_read, acc.1 + stats.bytes_written)
+                    });
+                    if read == 0 {
+                        0.0
+                    } else {
+                        100.0 * written as f64 / read as f64
+                    }
+                },
+            ),
+            (
+                TIME_SINCE_LAST_RUN_MS,
+                "The time since the last run of a cleaner thread completed",
+                |state, now_ms| {
+                    let last_run_ms = state
+                        .last_runs
+                        .values()
+                        .map(|stats| stats.end_ms)
+                        .max()
+                        .unwrap_or(state.start_ms);
+                    (now_ms - last_run_ms).max(0) as f64
+                },
+            ),
+            (
+                MAX_DIRTY_PERCENT,
+                "The highest share of dirty bytes of the compacted logs",
+                |state, _| state.backlog.max_dirty_ratio * 100.0,
+            ),
+            (
+                CLEANABLE_BYTES,
+                "The dirty bytes of the logs eligible for cleaning",
+                |state, _| state.backlog.cleanable_bytes as f64,
+            ),
+            (
+                UNCLEANABLE_PARTITIONS_COUNT,
+                "The number of partitions the cleaner failed to clean",
+                |state, _| state.backlog.uncleanable_partitions as f64,
+            ),
+        ];
+        for (name, description, gauge) in gauges {
+            let metric_name =
+                metrics.metric_name(name, LOG_CLEANER_METRICS_GROUP, description, &[]);
+            let state = Arc::clone(&state);
+            metrics.add_gauge(metric_name, move |now_ms| {
+                gauge(
+                    &state.lock().expect("cleaner metrics lock poisoned"),
+                    now_ms,
+                )
+            });
+        }
+        Self { state }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("cleaner metrics lock poisoned")
+    }
+
+    /// Records the completed run of cleaner thread `thread`.
+    pub fn record_run(&self, thread: usize, stats: CleanerStats) {
+        self.state().last_runs.insert(thread, stats);
+    }
+
+    pub fn update_backlog(&self, backlog: CleanerBacklog) {
+        self.state().backlog = backlog;
+    }
+}
+
+fn max(state: &State, f: impl Fn(&CleanerStats) -> f64) -> f64 {
+    state.last_runs.values().map(f).fold(0.0, f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::metrics::MetricConfig;
+    use rafka_clients::common::utils::time::{MockTime, Time};
+
+    #[test]
+    fn test_cleaner_metrics() {
+        let time = Arc::new(MockTime::with_start(0, 0, 0));
+        let metrics = Metrics::new(MetricConfig::default(), time.clone());
+        let cleaner_metrics = LogCleanerMetrics::new(&metrics, 0);
+        let value = |name| {
+            metrics
+                .metric_value(&metrics.metric_name(name, LOG_CLEANER_METRICS_GROUP, "", &[]))
+                .unwrap()
+        };
+        time.sleep(500);
+        assert_eq!(0.0, value(MAX_BUFFER_UTILIZATION_PERCENT));
+        assert_eq!(500.0, value(TIME_SINCE_LAST_RUN_MS));
+
+        cleaner_metrics.record_run(
+            0,
+            CleanerStats {
+                start_ms: 0,
+                end_ms: 2_000,
+                bytes_read: 1_000,
+                bytes_written: 200,
+                buffer_utilization: 0.25,
+            },
+        );
+        cleaner_metrics.record_run(
+            1,
+            CleanerStats {
+                start_ms: 0,
+                end_ms: 400,
+                bytes_read: 1_000,
+                bytes_written: 600,
+                buffer_utilization: 0.5,
+            },
+        );
+        time.sleep(2_500);
+        assert_eq!(50.0, value(MAX_BUFFER_UTILIZATION_PERCENT));
+        assert_eq!(2.0, value(MAX_CLEAN_TIME_SECS));
+        assert_eq!(40.0, value(CLEANER_RECOPY_PERCENT));
+        assert_eq!(1_000.0, value(TIME_SINCE_LAST_RUN_MS));
+
+        cleaner_metrics.update_backlog(CleanerBacklog {
+            max_dirty_ratio: 0.75,
+            cleanable_bytes: 4096,
+            uncleanable_partitions: 1,
+        });
+        assert_eq!(75.0, value(MAX_DIRTY_PERCENT));
+        assert_eq!(4096.0, value(CLEANABLE_BYTES));
+        assert_eq!(1.0, value(UNCLEANABLE_PARTITIONS_COUNT));
+    }
+}
@@ -1,5 +1,6 @@
 use easy_config_def::prelude::*;
 use rafka_server_common::server_log_configs;
+use std::path::PathBuf;
 
 #[derive(Debug, EasyConfig)]
 pub struct LogConfig {
@@ -43,4 +44,24 @@ pub struct LogConfig {
     documentation = server_log_configs::LOG_INITIAL_TASK_DELAY_MS_DOC,
     getter)]
     log_initial_task_delay_ms_config: i64,
+
+    #[attr(name = server_log_configs::NUM_RECOVERY_THREADS_PER_DATA_DIR_CONFIG,
+    default = server_log_configs::NUM_RECOVERY_THREADS_PER_DATA_DIR_DEFAULT,
+    validator = Range::at_least(1),
+    importance = Importance::HIGH,
+    documentation = server_log_configs::NUM_RECOVERY_THREADS_PER_DATA_DIR_DOC,
+    getter)]
+    num_recovery_threads_per_data_dir_config: u32,
+}
+
+impl LogConfig {
+    /// The log directories: `log.dirs`, or `log.dir` if it is not set.
+    pub fn log_dirs(&self) -> Vec<PathBuf> {
+        self.log_dirs_config()
+            .as_ref()
+            .unwrap_or(self.log_dir_config())
+            .iter()
+            .map(PathBuf::from)
+            .collect()
+    }
 }
@@ -0,0 +1,118 @@
+use crate::storage::internals::log::{LogError, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// The lock file a broker holds in each of its log directories while it runs.
+pub const LOCK_FILE_NAME: &str = ".lock";
+
+/// The exclusive lock on a log directory, released when dropped or when the process exits.
+///
+/// The lock file holds the PID of the process holding the lock, so that a broker failing to
+/// start because another process uses its log directories can tell which one.
+#[derive(Debug)]
+pub struct LogDirLock {
+    dir: PathBuf,
+    file: File,
+}
+
+impl LogDirLock {
+    /// Locks `dir`, creating it if it does not exist.
+    pub fn acquire(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if file.try_lock().is_err() {
+            return Err(LogError::LogDirLocked {
+                dir: dir.to_path_buf(),
+                pid: read_pid(&mut file),
+            });
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for LogDirLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+/// Locks all the log directories, or none if any is locked by another process.
+pub fn lock_log_dirs(dirs: &[PathBuf]) -> Result<Vec<LogDirLock>> {
+    let locks = dirs
+        .iter()
+        .map(|dir| LogDirLock::acquire(dir))
+        .collect::<Result<Vec<_>>>()?;
+    info!("Locked log directories {dirs:?}");
+    Ok(locks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        let lock = LogDirLock::acquire(&b).unwrap();
+        assert_eq!(
+            format!("{}\n", std::process::id()),
+            fs::read_to_string(b.join(LOCK_FILE_NAME)).unwrap()
+        );
+
+        let error = lock_log_dirs(&[a.clone(), b.clone()]).unwrap_err();
+        assert!(matches!(
+            &error,
+            LogError::LogDirLocked { dir, pid: Some(pid) } if *dir == b && *pid == std::process::id()
+        ));
+        assert!(
+            error
+                .to_string()
+                .contains(&format!("PID {}", std::process::id()))
+        );
+        // The directories locked before the failure were released.
+        drop(LogDirLock::acquire(&a).unwrap());
+
+        drop(lock);
+        let locks = lock_log_dirs(&[a, b]).unwrap();
+        assert_eq!(2, locks.len());
+    }
+
+    #[test]
+    fn test_stale_lock_file_is_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+        // A broker which crashed leaves its lock file, but not the lock.
+        fs::write(dir.path().join(LOCK_FILE_NAME), "12345\n").unwrap();
+        let _lock = LogDirLock::acquire(dir.path()).unwrap();
+        assert_eq!(
+            format!("{}\n", std::process::id()),
+            fs::read_to_string(dir.path().join(LOCK_FILE_NAME)).unwrap()
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::storage::internals::log::unified_log::UnifiedLog;
+use rafka_clients::common::metrics::{MetricName, Metrics};
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+pub const LOG_METRICS_GROUP: &str = "log-metrics";
+
+pub const SIZE: &str = "size";
+pub const NUM_LOG_SEGMENTS: &str = "num-log-segments";
+pub const LOG_START_OFFSET: &str = "log-start-offset";
+pub const LOG_END_OFFSET: &str = "log-end-offset";
+
+/// The gauges of the log of a partition, tagged with its topic and partition.
+///
+/// A [UnifiedLog] is owned by a single writer, so the gauges read a snapshot which the owner
+/// refreshes with [LogMetrics::update] after appending to, truncating or deleting from the log.
+pub struct LogMetrics {
+    metrics: Metrics,
+    size: Arc<AtomicI64>,
+    num_segments: Arc<AtomicI64>,
+    log_start_offset: Arc<AtomicI64>,
+    log_end_offset: Arc<AtomicI64>,
+    metric_names: Vec<MetricName>,
+}
+
+impl LogMetrics {
+    pub fn new(metrics: Metrics, log: &UnifiedLog) -> Self {
+        let topic_partition = log.topic_partition();
+        let mut log_metrics = Self {
+            metrics,
+            size: Arc::new(AtomicI64::new(0)),
+            num_segments: Arc::new(AtomicI64::new(0)),
+            log_start_offset: Arc::new(AtomicI64::new(0)),
+            log_end_offset: Arc::new(AtomicI64::new(0)),
+            metric_names: vec![],
+        };
+        for (name, description, value) in [
+            (
+                SIZE,
+                "The size in bytes of the log on the local disk",
+                Arc::clone(&log_metrics.size),
+            ),
+            (
+                NUM_LOG_SEGMENTS,
+                "The number of segments of the log on the local disk",
+                Arc::clone(&log_metrics.num_segments),
+            ),
+            (
+                LOG_START_OFFSET,
+                "The first offset of the log",
+                Arc::clone(&log_metrics.log_start_offset),
+            ),
+            (
+                LOG_END_OFFSET,
+                "The offset the next record appended to the log gets",
+                Arc::clone(&log_metrics.log_end_offset),
+            ),
+        ] {
+            log_metrics.add_gauge(topic_partition, name, description, value);
+        }
+        log_metrics.update(log);
+        log_metrics
+    }
+
+    fn add_gauge(
+        &mut self,
+        topic_partition: &TopicPartition,
+        name: &str,
+        description: &str,
+        value: Arc<AtomicI64>,
+    ) {
+        let partition = topic_partition.partition().to_string();
+        let metric_name = self.metrics.metric_name(
+            name,
+            LOG_METRICS_GROUP,
+            description,
+            &[
+                ("topic", topic_partition.topic()),
+                ("partition", &partition),
+            ],
+        );
+        self.metrics.add_gauge(metric_name.clone(), move |_| {
+            value.load(Ordering::Relaxed) as f64
+        });
+        self.metric_names.push(metric_name);
+    }
+
+    /// Refreshes the gauges from the current state of `log`.
+    pub fn update(&self, log: &UnifiedLog) {
+        self.size.store(log.size() as i64, Ordering::Relaxed);
+        self.num_segments
+            .store(log.num_segments() as i64, Ordering::Relaxed);
+        self.log_start_offset
+            .store(log.log_start_offset(), Ordering::Relaxed);
+        self.log_end_offset
+            .store(log.log_end_offset(), Ordering::Relaxed);
+    }
+
+    /// Removes the gauges, once the log is deleted or moved away from this broker.
+    pub fn close(self) {
+        for metric_name in &self.metric_names {
+            self.metrics.remove_metric(metric_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::append_origin::AppendOrigin;
+    use crate::storage::internals::log::unified_log::UnifiedLogConfig;
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+
+    #[test]
+    fn test_log_gauges() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = UnifiedLogConfig {
+            segment_bytes: 300,
+            ..Default::default()
+        };
+        let mut log = UnifiedLog::open(&dir.path().join("foo-3"), config, 0).unwrap();
+        let metrics = Metrics::default();
+        let log_metrics = LogMetrics::new(metrics.clone(), &log);
+        let value = |name| {
+            metrics.metric_value(&metrics.metric_name(
+                name,
+                LOG_METRICS_GROUP,
+                "",
+                &[("topic", "foo"), ("partition", "3")],
+            ))
+        };
+        assert_eq!(Some(0.0), value(SIZE));
+        assert_eq!(Some(1.0), value(NUM_LOG_SEGMENTS));
+        assert_eq!(Some(0.0), value(LOG_END_OFFSET));
+
+        for _ in 0..4 {
+            let batch = RecordBatch::new(0, vec![Record::new(0, None, Some(&[0; 100]))]);
+            log.append_as_leader(batch, 0, AppendOrigin::Client)
+                .unwrap();
+        }
+        log_metrics.update(&log);
+        assert_eq!(Some(log.size() as f64), value(SIZE));
+        assert!(value(NUM_LOG_SEGMENTS).unwrap() > 1.0);
+        assert_eq!(Some(0.0), value(LOG_START_OFFSET));
+        assert_eq!(Some(4.0), value(LOG_END_OFFSET));
+
+        log_metrics.close();
+        assert_eq!(None, value(SIZE));
+    }
+}
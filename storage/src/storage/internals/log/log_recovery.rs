@@ -0,0 +1,372 @@
+use crate::storage::internals::log::Result;
+use crate::storage::internals::log::clean_shutdown_file::CleanShutdownFileHandler;
+use crate::storage::internals::log::log_segment::LOG_FILE_SUFFIX;
+use crate::storage::internals::log::unified_log::{UnifiedLog, UnifiedLogConfig};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// How often the progress of the recovery is logged by default.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Loads the logs of the log directories of a broker on startup.
+///
+/// The logs of a directory shut down uncleanly are recovered, reading and validating every
+/// batch, which takes long for large directories and competes for the disk. The directories
+/// are loaded at once, each by at most `num.recovery.threads.per.data.dir` threads, which
+/// bounds the IO on each disk. While they load, the directories, logs and segments left, and
+/// the estimated time to load them, are logged every progress interval.
+#[derive(Debug, Clone)]
+pub struct LogRecovery {
+    config: UnifiedLogConfig,
+    num_threads_per_dir: usize,
+    progress_interval: Duration,
+}
+
+/// A log directory to load, and the segment count of each of its logs.
+#[derive(Debug)]
+struct DirToLoad {
+    dir: PathBuf,
+    had_clean_shutdown: bool,
+    logs: Vec<(PathBuf, usize)>,
+}
+
+impl LogRecovery {
+    pub fn new(config: UnifiedLogConfig, num_threads_per_dir: usize) -> Self {
+        Self {
+            config,
+            num_threads_per_dir: num_threads_per_dir.max(1),
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+        }
+    }
+
+    pub fn with_progress_interval(mut self, progress_interval: Duration) -> Self {
+        self.progress_interval = progress_interval;
+        self
+    }
+
+    /// Loads the logs of `dirs`, consuming their clean shutdown markers: the logs of a
+    /// directory holding one are not recovered.
+    pub fn load_log_dirs(&self, dirs: &[PathBuf]) -> Result<Vec<UnifiedLog>> {
+        let dirs = dirs
+            .iter()
+            .map(|dir| DirToLoad::scan(dir))
+            .collect::<Result<Vec<_>>>()?;
+        let progress = RecoveryProgress::new(&dirs);
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let loaded = thread::scope(|scope| {
+            // Dropped on return, or on a panic, to stop the progress logging.
+            let _done = done_tx;
+            let progress = &progress;
+            scope.spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) =
+                    done_rx.recv_timeout(self.progress_interval)
+                {
+                    progress.log();
+                }
+            });
+            dirs.iter()
+                .map(|dir| scope.spawn(move || self.load_dir(dir, progress)))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().expect("log recovery thread panicked"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        let mut logs: Vec<_> = loaded.into_iter().flatten().collect();
+        logs.sort_by(|a, b| a.dir().cmp(b.dir()));
+        info!(
+            "Loaded {} logs in {} log directories in {} ms",
+            logs.len(),
+            dirs.len(),
+            progress.start.elapsed().as_millis()
+        );
+        Ok(logs)
+    }
+
+    fn load_dir(&self, dir: &DirToLoad, progress: &RecoveryProgress) -> Result<Vec<UnifiedLog>> {
+        let queue = Mutex::new(dir.logs.iter());
+        let num_threads = self.num_threads_per_dir.min(dir.logs.len()).max(1);
+        let loaded = thread::scope(|scope| {
+            (0..num_threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut logs = vec![];
+                        loop {
+                            let next = queue.lock().expect("log queue lock poisoned").next();
+                            let Some((path, num_segments)) = next else {
+                                return Ok(logs);
+                            };
+                            logs.push(UnifiedLog::load(
+                                path,
+                                self.config.clone(),
+                                0,
+                                dir.had_clean_shutdown,
+                            )?);
+                            progress.log_loaded(*num_segments);
+                        }
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().expect("log recovery thread panicked"))
+                .collect::<Result<Vec<Vec<_>>>>()
+        })?;
+        progress.dir_loaded();
+        info!(
+            "Loaded the {} logs of log directory {}",
+            dir.logs.len(),
+            dir.dir.display()
+        );
+        Ok(loaded.into_iter().flatten().collect())
+    }
+}
+
+impl DirToLoad {
+    fn scan(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let had_clean_shutdown = match CleanShutdownFileHandler::new(dir).consume()? {
+            Some(broker_epoch) => {
+                info!(
+                    "Log directory {} was shut down cleanly with broker epoch {broker_epoch}",
+                    dir.display()
+                );
+                true
+            }
+            None => {
+                info!(
+                    "Log directory {} was not shut down cleanly, its logs will be recovered",
+                    dir.display()
+                );
+                false
+            }
+        };
+        let mut logs = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            UnifiedLog::parse_topic_partition_name(&path)?;
+            let num_segments = fs::read_dir(&path)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    entry.path().extension().and_then(|e| e.to_str()) == Some(LOG_FILE_SUFFIX)
+                })
+                .count();
+            logs.push((path, num_segments));
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            had_clean_shutdown,
+            logs,
+        })
+    }
+}
+
+/// What is left to load of the log directories.
+#[derive(Debug)]
+struct RecoveryProgress {
+    start: Instant,
+    total_segments: usize,
+    remaining_dirs: AtomicUsize,
+    remaining_logs: AtomicUsize,
+    remaining_segments: AtomicUsize,
+}
+
+impl RecoveryProgress {
+    fn new(dirs: &[DirToLoad]) -> Self {
+        let logs = dirs.iter().flat_map(|dir| &dir.logs);
+        let total_segments = logs.clone().map(|(_, segments)| segments).sum();
+        Self {
+            start: Instant::now(),
+            total_segments,
+            remaining_dirs: AtomicUsize::new(dirs.len()),
+            remaining_logs: AtomicUsize::new(logs.count()),
+            remaining_segments: AtomicUsize::new(total_segments),
+        }
+    }
+
+    fn log_loaded(&self, num_segments: usize) {
+        self.remaining_logs.fetch_sub(1, Ordering::Relaxed);
+        self.remaining_segments
+            .fetch_sub(num_segments, Ordering::Relaxed);
+    }
+
+    fn dir_loaded(&self) {
+        self.remaining_dirs.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn status(&self) -> RecoveryStatus {
+        let remaining_segments = self.remaining_segments.load(Ordering::Relaxed);
+        RecoveryStatus {
+            remaining_dirs: self.remaining_dirs.load(Ordering::Relaxed),
+            remaining_logs: self.remaining_logs.load(Ordering::Relaxed),
+            remaining_segments,
+            eta: estimate_remaining(
+                self.start.elapsed(),
+                self.total_segments - remaining_segments,
+                remaining_segments,
+            ),
+        }
+    }
+
+    fn log(&self) {
+        let status = self.status();
+        info!(
+            remaining_dirs = status.remaining_dirs,
+            remaining_logs = status.remaining_logs,
+            remaining_segments = status.remaining_segments,
+            eta_ms = status.eta.map(|eta| eta.as_millis() as u64),
+            "Loading logs: {status}"
+        );
+    }
+}
+
+/// A snapshot of the progress of the recovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RecoveryStatus {
+    remaining_dirs: usize,
+    remaining_logs: usize,
+    remaining_segments: usize,
+    /// The time left to load the remaining segments at the pace so far, unknown until a
+    /// segment is loaded.
+    eta: Option<Duration>,
+}
+
+impl fmt::Display for RecoveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} log directories, {} logs and {} segments left, ",
+            self.remaining_dirs, self.remaining_logs, self.remaining_segments
+        )?;
+        match self.eta {
+            Some(eta) => write!(f, "done in about {} s", eta.as_secs()),
+            None => write!(f, "unknown time left"),
+        }
+    }
+}
+
+/// The time to load `remaining` segments, given that `loaded` took `elapsed`.
+fn estimate_remaining(elapsed: Duration, loaded: usize, remaining: usize) -> Option<Duration> {
+    if loaded == 0 {
+        return None;
+    }
+    Some(elapsed.mul_f64(remaining as f64 / loaded as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::LogError;
+    use crate::storage::internals::log::append_origin::AppendOrigin;
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+
+    fn create_log(dir: &Path, num_batches: usize) {
+        let config = UnifiedLogConfig {
+            segment_bytes: 1,
+            ..UnifiedLogConfig::default()
+        };
+        let mut log = UnifiedLog::open(dir, config, 0).unwrap();
+        for _ in 0..num_batches {
+            let batch = RecordBatch::new(0, vec![Record::new(1_000, None, Some(b"value"))]);
+            log.append_as_leader(batch, 0, AppendOrigin::Client)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_load_log_dirs() {
+        let root = tempfile::tempdir().unwrap();
+        let dirs = vec![root.path().join("a"), root.path().join("b")];
+        for partition in 0..3 {
+            create_log(&dirs[0].join(format!("foo-{partition}")), partition + 1);
+        }
+        create_log(&dirs[1].join("bar-0"), 2);
+        CleanShutdownFileHandler::new(&dirs[1]).write(3).unwrap();
+
+        let recovery = LogRecovery::new(UnifiedLogConfig::default(), 2)
+            .with_progress_interval(Duration::from_millis(1));
+        let logs = recovery.load_log_dirs(&dirs).unwrap();
+        let end_offsets: Vec<_> = logs
+            .iter()
+            .map(|log| (log.topic_partition().to_string(), log.log_end_offset()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("foo-0".to_string(), 1),
+                ("foo-1".to_string(), 2),
+                ("foo-2".to_string(), 3),
+                ("bar-0".to_string(), 2),
+            ],
+            end_offsets
+        );
+        assert!(!CleanShutdownFileHandler::new(&dirs[1]).exists());
+    }
+
+    #[test]
+    fn test_load_fails_on_invalid_directory() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("not_a_partition")).unwrap();
+        let recovery = LogRecovery::new(UnifiedLogConfig::default(), 1);
+        assert!(matches!(
+            recovery.load_log_dirs(&[root.path().to_path_buf()]),
+            Err(LogError::InvalidDirectory(_))
+        ));
+    }
+
+    #[test]
+    fn test_progress() {
+        let root = tempfile::tempdir().unwrap();
+        create_log(&root.path().join("foo-0"), 3);
+        create_log(&root.path().join("foo-1"), 1);
+        let dirs = vec![DirToLoad::scan(root.path()).unwrap()];
+        let progress = RecoveryProgress::new(&dirs);
+        let status = progress.status();
+        assert_eq!(
+            (1, 2, 4, None),
+            (
+                status.remaining_dirs,
+                status.remaining_logs,
+                status.remaining_segments,
+                status.eta
+            )
+        );
+        assert_eq!(
+            "1 log directories, 2 logs and 4 segments left, unknown time left",
+            status.to_string()
+        );
+
+        progress.log_loaded(1);
+        let status = progress.status();
+        assert_eq!((1, 3), (status.remaining_logs, status.remaining_segments));
+        assert!(status.eta.is_some());
+        progress.log_loaded(3);
+        progress.dir_loaded();
+        let status = progress.status();
+        assert_eq!(
+            (0, 0, 0),
+            (
+                status.remaining_dirs,
+                status.remaining_logs,
+                status.remaining_segments
+            )
+        );
+        assert_eq!(Some(Duration::ZERO), status.eta);
+    }
+
+    #[test]
+    fn test_estimate_remaining() {
+        assert_eq!(None, estimate_remaining(Duration::from_secs(5), 0, 10));
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            estimate_remaining(Duration::from_secs(10), 4, 12)
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use crate::storage::internals::log::Result;
+use crate::storage::internals::log::unified_log::UnifiedLog;
+use rafka_clients::common::record::record_batch::NO_TIMESTAMP;
+use rafka_clients::common::requests::list_offsets_request::MAX_TIMESTAMP;
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The spread of the sizes of the segments of a log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeDistribution {
+    pub count: usize,
+    pub total: u64,
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+}
+
+impl SizeDistribution {
+    pub fn of(sizes: &[u64]) -> Self {
+        let mut sorted = sizes.to_vec();
+        sorted.sort_unstable();
+        Self {
+            count: sorted.len(),
+            total: sorted.iter().sum(),
+            min: sorted.first().copied().unwrap_or(0),
+            median: sorted.get(sorted.len() / 2).copied().unwrap_or(0),
+            max: sorted.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// How far the consumer groups which committed an offset on a partition are behind its end.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ConsumerLag {
+    pub groups: usize,
+    pub total: i64,
+    pub max: i64,
+    /// The group furthest behind.
+    pub max_group: Option<String>,
+}
+
+/// The size, growth and consumption of the log of a partition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionLogReport {
+    pub topic_partition: TopicPartition,
+    pub segment_sizes: SizeDistribution,
+    pub log_start_offset: i64,
+    pub log_end_offset: i64,
+    /// The records appended per second between the first and the last record of the local
+    /// log, if they have different timestamps.
+    pub produce_rate: Option<f64>,
+    pub consumer_lag: ConsumerLag,
+}
+
+impl PartitionLogReport {
+    pub fn of(log: &UnifiedLog) -> Result<Self> {
+        let local_log_start_offset = log.local_log_start_offset();
+        let first_timestamp = log
+            .read(local_log_start_offset, 1)?
+            .first()
+            .and_then(|batch| batch.records().first())
+            .map_or(NO_TIMESTAMP, |record| record.timestamp);
+        let last_timestamp = log
+            .fetch_offset_by_timestamp(MAX_TIMESTAMP, None)?
+            .map_or(NO_TIMESTAMP, |max| max.timestamp);
+        let records = log.log_end_offset() - local_log_start_offset;
+        let produce_rate = (first_timestamp != NO_TIMESTAMP && last_timestamp > first_timestamp)
+            .then(|| records as f64 * 1000.0 / (last_timestamp - first_timestamp) as f64);
+        Ok(Self {
+            topic_partition: log.topic_partition().clone(),
+            segment_sizes: SizeDistribution::of(&log.segment_sizes()),
+            log_start_offset: log.log_start_offset(),
+            log_end_offset: log.log_end_offset(),
+            produce_rate,
+            consumer_lag: ConsumerLag::default(),
+        })
+    }
+
+    /// The size in bytes of the local log.
+    pub fn size(&self) -> u64 {
+        self.segment_sizes.total
+    }
+
+    fn add_committed_offset(&mut self, group: &str, offset: i64) {
+        let lag = (self.log_end_offset - offset).max(0);
+        let consumer_lag = &mut self.consumer_lag;
+        consumer_lag.groups += 1;
+        consumer_lag.total += lag;
+        if consumer_lag.max_group.is_none() || lag > consumer_lag.max {
+            consumer_lag.max = lag;
+            consumer_lag.max_group = Some(group.to_string());
+        }
+    }
+}
+
+/// The partitions of a topic together, and how unevenly the load is spread over them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicReport {
+    pub topic: String,
+    pub partitions: usize,
+    pub size: u64,
+    pub produce_rate: f64,
+    /// The size of the largest partition divided by the average size, 1 when the partitions
+    /// are even.
+    pub size_skew: f64,
+    /// The produce rate of the busiest partition divided by the average rate.
+    pub produce_rate_skew: f64,
+    pub consumer_lag: i64,
+}
+
+/// The logs of a log directory, to find the partitions and topics receiving or keeping more
+/// than their share of the load, and the groups falling behind.
+#[derive(Debug, Default)]
+pub struct LogDirReport {
+    partitions: BTreeMap<TopicPartition, PartitionLogReport>,
+}
+
+impl LogDirReport {
+    pub fn add(&mut self, report: PartitionLogReport) {
+        self.partitions
+            .insert(report.topic_partition.clone(), report);
+    }
+
+    /// Counts the offset `group` committed on a partition in its consumer lag. Returns false
+    /// if the partition is not in the report.
+    pub fn add_committed_offset(
+        &mut self,
+        group: &str,
+        topic_partition: &TopicPartition,
+        offset: i64,
+    ) -> bool {
+        match self.partitions.get_mut(topic_partition) {
+            Some(report) => {
+                report.add_committed_offset(group, offset);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn partitions(&self) -> impl Iterator<Item = &PartitionLogReport> {
+        self.partitions.values()
+    }
+
+    /// The `n` partitions with the highest produce rate, the busiest first.
+    pub fn hot_partitions(&self, n: usize) -> Vec<&PartitionLogReport> {
+        let mut partitions: Vec<(f64, &PartitionLogReport)> = self
+            .partitions()
+            .filter_map(|p| Some((p.produce_rate?, p)))
+            .collect();
+        partitions.sort_by(|a, b| b.0.total_cmp(&a.0));
+        partitions.into_iter().take(n).map(|(_, p)| p).collect()
+    }
+
+    pub fn topics(&self) -> Vec<TopicReport> {
+        let mut by_topic: BTreeMap<&str, Vec<&PartitionLogReport>> = BTreeMap::new();
+        for report in self.partitions() {
+            by_topic
+                .entry(report.topic_partition.topic())
+                .or_default()
+                .push(report);
+        }
+        by_topic
+            .into_iter()
+            .map(|(topic, partitions)| {
+                let skew = |values: Vec<f64>| {
+                    let max = values.iter().copied().fold(0.0, f64::max);
+                    let mean = values.iter().sum::<f64>() / values.len() as f64;
+                    if mean > 0.0 { max / mean } else { 1.0 }
+                };
+                TopicReport {
+                    topic: topic.to_string(),
+                    partitions: partitions.len(),
+                    size: partitions.iter().map(|p| p.size()).sum(),
+                    produce_rate: partitions.iter().filter_map(|p| p.produce_rate).sum(),
+                    size_skew: skew(partitions.iter().map(|p| p.size() as f64).collect()),
+                    produce_rate_skew: skew(
+                        partitions
+                            .iter()
+                            .map(|p| p.produce_rate.unwrap_or(0.0))
+                            .collect(),
+                    ),
+                    consumer_lag: partitions.iter().map(|p| p.consumer_lag.total).sum(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The report as tables: the partitions, the topics, then the hot partitions.
+impl fmt::Display for LogDirReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rate = |rate: Option<f64>| rate.map_or("-".to_string(), |r| format!("{r:.1}"));
+        writeln!(
+            f,
+            "{:<30} {:>8} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12} {:>10} {:>12} {:>12}",
+            "PARTITION",
+            "SEGMENTS",
+            "SIZE",
+            "MIN SEGMENT",
+            "MED SEGMENT",
+            "MAX SEGMENT",
+            "LOG START",
+            "LOG END",
+            "RECORDS/S",
+            "TOTAL LAG",
+            "MAX LAG"
+        )?;
+        for p in self.partitions() {
+            let sizes = &p.segment_sizes;
+            writeln!(
+                f,
+                "{:<30} {:>8} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12} {:>10} {:>12} {:>12}",
+                p.topic_partition.to_string(),
+                sizes.count,
+                sizes.total,
+                sizes.min,
+                sizes.median,
+                sizes.max,
+                p.log_start_offset,
+                p.log_end_offset,
+                rate(p.produce_rate),
+                p.consumer_lag.total,
+                match &p.consumer_lag.max_group {
+                    Some(group) => format!("{} ({group})", p.consumer_lag.max),
+                    None => "-".to_string(),
+                }
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<30} {:>10} {:>12} {:>10} {:>10} {:>10} {:>12}",
+            "TOPIC", "PARTITIONS", "SIZE", "RECORDS/S", "SIZE SKEW", "RATE SKEW", "TOTAL LAG"
+        )?;
+        for t in self.topics() {
+            writeln!(
+                f,
+                "{:<30} {:>10} {:>12} {:>10.1} {:>10.2} {:>10.2} {:>12}",
+                t.topic,
+                t.partitions,
+                t.size,
+                t.produce_rate,
+                t.size_skew,
+                t.produce_rate_skew,
+                t.consumer_lag
+            )?;
+        }
+        let hot = self.hot_partitions(10);
+        if !hot.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "HOT PARTITIONS")?;
+            for p in hot {
+                writeln!(
+                    f,
+                    "{:<30} {:>10}",
+                    p.topic_partition.to_string(),
+                    rate(p.produce_rate)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::append_origin::AppendOrigin;
+    use crate::storage::internals::log::unified_log::UnifiedLogConfig;
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+
+    fn log(dir: &std::path::Path, name: &str, timestamps: &[i64]) -> UnifiedLog {
+        let config = UnifiedLogConfig {
+            segment_bytes: 200,
+            ..Default::default()
+        };
+        let mut log = UnifiedLog::open(&dir.join(name), config, 0).unwrap();
+        for timestamp in timestamps {
+            let batch = RecordBatch::new(0, vec![Record::new(*timestamp, None, Some(&[0; 50]))]);
+            log.append_as_leader(batch, 0, AppendOrigin::Client)
+                .unwrap();
+        }
+        log
+    }
+
+    #[test]
+    fn test_size_distribution() {
+        assert_eq!(
+            SizeDistribution {
+                count: 4,
+                total: 100,
+                min: 10,
+                median: 30,
+                max: 40
+            },
+            SizeDistribution::of(&[40, 10, 30, 20])
+        );
+        assert_eq!(SizeDistribution::default(), SizeDistribution::of(&[]));
+    }
+
+    #[test]
+    fn test_log_dir_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut report = LogDirReport::default();
+        // foo-0 gets 11 records in a second, foo-1 2 records in 10 seconds.
+        let busy: Vec<i64> = (0..=10).map(|i| 1_000 + i * 100).collect();
+        for log in [
+            log(dir.path(), "foo-0", &busy),
+            log(dir.path(), "foo-1", &[1_000, 11_000]),
+            log(dir.path(), "bar-0", &[]),
+        ] {
+            report.add(PartitionLogReport::of(&log).unwrap());
+        }
+        let foo0 = TopicPartition::new("foo", 0);
+        assert!(report.add_committed_offset("a", &foo0, 4));
+        assert!(report.add_committed_offset("b", &foo0, 10));
+        assert!(!report.add_committed_offset("a", &TopicPartition::new("baz", 0), 0));
+
+        let partitions: Vec<_> = report.partitions().collect();
+        let foo0_report = partitions[1];
+        assert_eq!(11, foo0_report.log_end_offset);
+        assert!(foo0_report.segment_sizes.count > 1);
+        assert_eq!(Some(11.0), foo0_report.produce_rate);
+        assert_eq!(
+            ConsumerLag {
+                groups: 2,
+                total: 8,
+                max: 7,
+                max_group: Some("a".to_string())
+            },
+            foo0_report.consumer_lag
+        );
+        assert_eq!(None, partitions[0].produce_rate);
+
+        let hot: Vec<_> = report
+            .hot_partitions(5)
+            .iter()
+            .map(|p| p.topic_partition.to_string())
+            .collect();
+        assert_eq!(vec!["foo-0", "foo-1"], hot);
+
+        let topics = report.topics();
+        assert_eq!(
+            vec!["bar", "foo"],
+            topics.iter().map(|t| t.topic.as_str()).collect::<Vec<_>>()
+        );
+        let foo = &topics[1];
+        assert!((foo.produce_rate - 11.2).abs() < 1e-9);
+        assert!((foo.produce_rate_skew - 11.0 / 5.6).abs() < 1e-9);
+        assert!(foo.size_skew > 1.0);
+        assert_eq!(8, foo.consumer_lag);
+        assert_eq!(1.0, topics[0].size_skew);
+
+        let text = report.to_string();
+        assert!(text.contains("HOT PARTITIONS"));
+        assert!(text.contains("7 (a)"));
+    }
+}
@@ -0,0 +1,493 @@
+use crate::storage::internals::log::time_index::{
+    TIME_INDEX_FILE_SUFFIX, TimeIndex, TimestampOffset,
+};
+use crate::storage::internals::log::{Result, filename_prefix_from_offset};
+use rafka_clients::common::record::record_batch::{
+    NO_PARTITION_LEADER_EPOCH, NO_TIMESTAMP, RecordBatch, RecordBatchHeader, RecordError,
+};
+use rafka_server_common::fault_injection::{self, FaultPoint};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+pub const LOG_FILE_SUFFIX: &str = "log";
+
+/// The result of a lookup by timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampAndOffset {
+    pub timestamp: i64,
+    pub offset: i64,
+    pub leader_epoch: Option<i32>,
+}
+
+impl TimestampAndOffset {
+    pub fn new(timestamp: i64, offset: i64, leader_epoch: Option<i32>) -> Self {
+        Self {
+            timestamp,
+            offset,
+            leader_epoch,
+        }
+    }
+}
+
+/// Where a batch is stored in the segment file and what the lookups need to know about it
+/// without reading it.
+#[derive(Debug, Clone, Copy)]
+struct BatchPosition {
+    base_offset: i64,
+    last_offset: i64,
+    position: u64,
+    size: usize,
+    max_timestamp: i64,
+    partition_leader_epoch: i32,
+    producer_id: i64,
+    is_transactional: bool,
+    is_control: bool,
+}
+
+/// A segment of a log: a `.log` file holding consecutive record batches, named after the
+/// offset of its first record, and its time index.
+///
+/// The position of every batch is kept in memory, so reads seek straight to the batch holding
+/// an offset.
+#[derive(Debug)]
+pub struct LogSegment {
+    base_offset: i64,
+    log_path: PathBuf,
+    log: File,
+    size: u64,
+    batches: Vec<BatchPosition>,
+    time_index: TimeIndex,
+    index_interval_bytes: usize,
+    bytes_since_last_index_entry: usize,
+    max_timestamp_so_far: TimestampOffset,
+}
+
+impl LogSegment {
+    /// Opens the segment starting at `base_offset` in `dir`, creating it if it doesn't exist.
+    ///
+    /// The batches are validated on open: the file is truncated at the first incomplete or
+    /// corrupt batch, e.g. one left behind by a crash in the middle of an append.
+    pub fn open(dir: &Path, base_offset: i64, index_interval_bytes: usize) -> Result<Self> {
+        Self::load(dir, base_offset, index_interval_bytes, true)
+    }
+
+    /// Opens the segment like [LogSegment::open], validating its batches only if `recover`.
+    ///
+    /// A segment of a log shut down cleanly holds only complete batches which were validated
+    /// when appended, so only their headers are read to locate them, which is much faster
+    /// than checking the crc of every batch of a large log.
+    pub fn load(
+        dir: &Path,
+        base_offset: i64,
+        index_interval_bytes: usize,
+        recover: bool,
+    ) -> Result<Self> {
+        let prefix = filename_prefix_from_offset(base_offset);
+        let log_path = dir.join(format!("{prefix}.{LOG_FILE_SUFFIX}"));
+        fault_injection::check(FaultPoint::SegmentOpen, &log_path)?;
+        let mut log = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&log_path)?;
+        let time_index = TimeIndex::open(
+            &dir.join(format!("{prefix}.{TIME_INDEX_FILE_SUFFIX}")),
+            base_offset,
+        )?;
+
+        let mut data = Vec::new();
+        log.read_to_end(&mut data)?;
+        let mut batches = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let next = if recover {
+                RecordBatch::read_from(&data[position..])
+                    .map(|(batch, size)| (batch.header(), size))
+            } else {
+                RecordBatch::read_header(&data[position..])
+            };
+            match next {
+                Ok((header, size)) => {
+                    batches.push(BatchPosition::new(header, position as u64, size));
+                    position += size;
+                }
+                Err(e) => {
+                    if e != RecordError::Truncated {
+                        warn!(
+                            "Found an invalid batch at position {position} of {}: {e}",
+                            log_path.display()
+                        );
+                    }
+                    warn!(
+                        "Truncating {} to {position} bytes, discarding {} bytes",
+                        log_path.display(),
+                        data.len() - position
+                    );
+                    log.set_len(position as u64)?;
+                    break;
+                }
+            }
+        }
+
+        let mut segment = Self {
+            base_offset,
+            log_path,
+            log,
+            size: position as u64,
+            batches,
+            time_index,
+            index_interval_bytes,
+            bytes_since_last_index_entry: 0,
+            max_timestamp_so_far: TimestampOffset::new(NO_TIMESTAMP, base_offset),
+        };
+        let next_offset = segment.next_offset();
+        segment.time_index.truncate_to(next_offset)?;
+        segment.load_max_timestamp_so_far()?;
+        Ok(segment)
+    }
+
+    fn load_max_timestamp_so_far(&mut self) -> Result<()> {
+        self.max_timestamp_so_far = TimestampOffset::new(NO_TIMESTAMP, self.base_offset);
+        if let Some(batch) = self
+            .batches
+            .iter()
+            .filter(|b| b.max_timestamp > NO_TIMESTAMP)
+            .max_by_key(|b| (b.max_timestamp, -b.base_offset))
+            .copied()
+        {
+            let batch = self.read_batch(&batch)?;
+            self.max_timestamp_so_far =
+                TimestampOffset::new(batch.max_timestamp(), batch.offset_of_max_timestamp());
+        }
+        Ok(())
+    }
+
+    pub fn base_offset(&self) -> i64 {
+        self.base_offset
+    }
+
+    /// The headers of the batches of the segment, in offset order, known without reading them.
+    pub fn batch_headers(&self) -> impl Iterator<Item = RecordBatchHeader> + '_ {
+        self.batches.iter().map(BatchPosition::header)
+    }
+
+    /// The size of the `.log` file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    /// The offset following the last batch of the segment.
+    pub fn next_offset(&self) -> i64 {
+        self.batches
+            .last()
+            .map_or(self.base_offset, |b| b.last_offset + 1)
+    }
+
+    /// The largest timestamp of the segment and the offset of the first record carrying it.
+    pub fn max_timestamp_so_far(&self) -> TimestampOffset {
+        self.max_timestamp_so_far
+    }
+
+    pub fn time_index(&self) -> &TimeIndex {
+        &self.time_index
+    }
+
+    /// Appends a batch, whose offsets must follow the last batch of the segment, as `data`, the
+    /// batch once encoded.
+    pub fn append(&mut self, batch: &RecordBatch, data: &[u8]) -> Result<()> {
+        fault_injection::check(FaultPoint::SegmentAppend, &self.log_path)?;
+        self.log.write_all(data)?;
+        self.batches
+            .push(BatchPosition::new(batch.header(), self.size, data.len()));
+        self.size += data.len() as u64;
+
+        if batch.max_timestamp() > self.max_timestamp_so_far.timestamp {
+            self.max_timestamp_so_far =
+                TimestampOffset::new(batch.max_timestamp(), batch.offset_of_max_timestamp());
+        }
+        self.bytes_since_last_index_entry += data.len();
+        if self.bytes_since_last_index_entry > self.index_interval_bytes {
+            self.time_index.maybe_append(
+                self.max_timestamp_so_far.timestamp,
+                self.max_timestamp_so_far.offset,
+            )?;
+            self.bytes_since_last_index_entry = 0;
+        }
+        Ok(())
+    }
+
+    /// Reads the batches holding offsets from `start_offset` on, up to `max_bytes`. The first
+    /// batch is always returned, even if it is larger than `max_bytes`, so a consumer can make
+    /// progress.
+    pub fn read(&self, start_offset: i64, max_bytes: usize) -> Result<Vec<RecordBatch>> {
+        let first = self
+            .batches
+            .partition_point(|b| b.last_offset < start_offset);
+        let mut batches = Vec::new();
+        let mut bytes = 0;
+        for position in &self.batches[first..] {
+            if !batches.is_empty() && bytes + position.size > max_bytes {
+                break;
+            }
+            batches.push(self.read_batch(position)?);
+            bytes += position.size;
+        }
+        Ok(batches)
+    }
+
+    /// Finds the first record at or after `starting_offset` whose timestamp is at least
+    /// `timestamp`.
+    ///
+    /// The time index gives the offset to start scanning from; the batches whose max timestamp
+    /// is smaller than `timestamp` are skipped without being read.
+    pub fn find_offset_by_timestamp(
+        &self,
+        timestamp: i64,
+        starting_offset: i64,
+    ) -> Result<Option<TimestampAndOffset>> {
+        let start = self
+            .time_index
+            .lookup(timestamp)
+            .offset
+            .max(starting_offset);
+        let first = self.batches.partition_point(|b| b.last_offset < start);
+        for position in &self.batches[first..] {
+            if position.max_timestamp < timestamp {
+                continue;
+            }
+            let batch = self.read_batch(position)?;
+            if let Some((offset, record)) = batch
+                .iter()
+                .find(|(offset, r)| *offset >= starting_offset && r.timestamp >= timestamp)
+            {
+                return Ok(Some(TimestampAndOffset::new(
+                    record.timestamp,
+                    offset,
+                    position.leader_epoch(),
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The leader epoch of the batch holding `offset`.
+    pub fn leader_epoch_for_offset(&self, offset: i64) -> Option<i32> {
+        let position = self.batches.partition_point(|b| b.last_offset < offset);
+        self.batches
+            .get(position)
+            .filter(|b| b.base_offset <= offset)
+            .and_then(BatchPosition::leader_epoch)
+    }
+
+    fn read_batch(&self, position: &BatchPosition) -> Result<RecordBatch> {
+        let mut data = vec![0; position.size];
+        let mut log = &self.log;
+        log.seek(SeekFrom::Start(position.position))?;
+        log.read_exact(&mut data)?;
+        Ok(RecordBatch::read_from(&data)?.0)
+    }
+
+    /// Removes the batches holding `offset` and the offsets above it. The batch holding
+    /// `offset` is removed as a whole, so the segment may end before `offset`. Returns the
+    /// number of bytes removed.
+    pub fn truncate_to(&mut self, offset: i64) -> Result<u64> {
+        fault_injection::check(FaultPoint::SegmentTruncate, &self.log_path)?;
+        let first_removed = self.batches.partition_point(|b| b.last_offset < offset);
+        let Some(position) = self.batches.get(first_removed).map(|b| b.position) else {
+            return Ok(0);
+        };
+        self.batches.truncate(first_removed);
+        self.log.set_len(position)?;
+        let removed = self.size - position;
+        self.size = position;
+        self.time_index.truncate_to(offset)?;
+        self.bytes_since_last_index_entry = 0;
+        self.load_max_timestamp_so_far()?;
+        Ok(removed)
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        fault_injection::check(FaultPoint::SegmentFlush, &self.log_path)?;
+        self.log.sync_all()?;
+        self.time_index.flush()
+    }
+
+    /// Deletes the files of the segment.
+    pub fn delete(self) -> Result<()> {
+        fault_injection::check(FaultPoint::SegmentDelete, &self.log_path)?;
+        drop(self.log);
+        fs::remove_file(&self.log_path)?;
+        self.time_index.delete()
+    }
+}
+
+impl BatchPosition {
+    fn new(header: RecordBatchHeader, position: u64, size: usize) -> Self {
+        Self {
+            base_offset: header.base_offset,
+            last_offset: header.last_offset,
+            position,
+            size,
+            max_timestamp: header.max_timestamp,
+            partition_leader_epoch: header.partition_leader_epoch,
+            producer_id: header.producer_id,
+            is_transactional: header.is_transactional,
+            is_control: header.is_control,
+        }
+    }
+
+    fn header(&self) -> RecordBatchHeader {
+        RecordBatchHeader {
+            base_offset: self.base_offset,
+            last_offset: self.last_offset,
+            partition_leader_epoch: self.partition_leader_epoch,
+            max_timestamp: self.max_timestamp,
+            producer_id: self.producer_id,
+            is_transactional: self.is_transactional,
+            is_control: self.is_control,
+        }
+    }
+
+    fn leader_epoch(&self) -> Option<i32> {
+        (self.partition_leader_epoch != NO_PARTITION_LEADER_EPOCH)
+            .then_some(self.partition_leader_epoch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::record::record_batch::Record;
+
+    fn batch(base_offset: i64, timestamps: &[i64]) -> RecordBatch {
+        let mut batch = RecordBatch::new(
+            base_offset,
+            timestamps
+                .iter()
+                .map(|ts| Record::new(*ts, None, Some(b"value")))
+                .collect(),
+        );
+        batch.set_partition_leader_epoch(5);
+        batch
+    }
+
+    fn append(segment: &mut LogSegment, batch: &RecordBatch) -> Result<()> {
+        segment.append(batch, &batch.encode())
+    }
+
+    #[test]
+    fn test_append_and_find_offset_by_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut segment = LogSegment::open(dir.path(), 10, 0).unwrap();
+        append(&mut segment, &batch(10, &[100, 300, 200])).unwrap();
+        append(&mut segment, &batch(13, &[250, 400])).unwrap();
+        assert_eq!(15, segment.next_offset());
+        assert_eq!(
+            TimestampOffset::new(400, 14),
+            segment.max_timestamp_so_far()
+        );
+        assert_eq!(2, segment.time_index().entries());
+
+        let find = |ts, start| {
+            segment
+                .find_offset_by_timestamp(ts, start)
+                .unwrap()
+                .map(|r| (r.timestamp, r.offset))
+        };
+        assert_eq!(Some((100, 10)), find(50, 10));
+        assert_eq!(Some((300, 11)), find(201, 10));
+        assert_eq!(Some((250, 13)), find(201, 12));
+        assert_eq!(Some((400, 14)), find(301, 10));
+        assert_eq!(None, find(401, 10));
+        assert_eq!(Some(5), segment.leader_epoch_for_offset(13));
+        assert_eq!(None, segment.leader_epoch_for_offset(15));
+    }
+
+    #[test]
+    fn test_truncate_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut segment = LogSegment::open(dir.path(), 0, 0).unwrap();
+        append(&mut segment, &batch(0, &[100, 200])).unwrap();
+        let size = segment.size();
+        append(&mut segment, &batch(2, &[300, 400])).unwrap();
+        append(&mut segment, &batch(4, &[500])).unwrap();
+
+        assert_eq!(0, segment.truncate_to(5).unwrap());
+        // Offset 3 is in the middle of the second batch, which is removed as a whole.
+        segment.truncate_to(3).unwrap();
+        assert_eq!(2, segment.next_offset());
+        assert_eq!(size, segment.size());
+        assert_eq!(TimestampOffset::new(200, 1), segment.max_timestamp_so_far());
+        assert_eq!(
+            TimestampOffset::new(200, 1),
+            segment.time_index().last_entry()
+        );
+        append(&mut segment, &batch(2, &[250])).unwrap();
+        drop(segment);
+        assert_eq!(3, LogSegment::open(dir.path(), 0, 0).unwrap().next_offset());
+    }
+
+    #[test]
+    fn test_open_truncates_a_partial_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut segment = LogSegment::open(dir.path(), 0, 4096).unwrap();
+        append(&mut segment, &batch(0, &[100, 200])).unwrap();
+        let size = segment.size();
+        let partial = batch(2, &[300]).encode();
+        segment
+            .log
+            .write_all(&partial[..partial.len() / 2])
+            .unwrap();
+        drop(segment);
+
+        let segment = LogSegment::open(dir.path(), 0, 4096).unwrap();
+        assert_eq!(size, segment.size());
+        assert_eq!(2, segment.next_offset());
+        assert_eq!(TimestampOffset::new(200, 1), segment.max_timestamp_so_far());
+        let batches = segment.read(1, 1).unwrap();
+        assert_eq!(1, batches.len());
+        assert_eq!(0, batches[0].base_offset());
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[test]
+    fn test_injected_io_errors() {
+        use rafka_server_common::fault_injection::{Fault, inject};
+        use std::io;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut segment = LogSegment::open(dir.path(), 0, 0).unwrap();
+        append(&mut segment, &batch(0, &[100])).unwrap();
+        let size = segment.size();
+        let _guard = inject(
+            FaultPoint::SegmentAppend,
+            dir.path(),
+            Fault::Error(io::ErrorKind::StorageFull),
+            Some(1),
+        );
+        assert!(matches!(
+            append(&mut segment, &batch(1, &[200])),
+            Err(crate::storage::internals::log::LogError::Io(e))
+                if e.kind() == io::ErrorKind::StorageFull
+        ));
+        // The failed append left the segment as it was, the next one goes through.
+        assert_eq!(size, segment.size());
+        assert_eq!(1, segment.next_offset());
+        append(&mut segment, &batch(1, &[200])).unwrap();
+
+        let _guard = inject(
+            FaultPoint::SegmentFlush,
+            dir.path(),
+            Fault::Error(io::ErrorKind::Other),
+            None,
+        );
+        assert!(segment.flush().is_err());
+        assert!(segment.flush().is_err());
+    }
+}
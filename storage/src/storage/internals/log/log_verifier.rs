@@ -0,0 +1,294 @@
+//! Reads the segments of a log as they are on disk, without opening the log, to dump their
+//! batches or to audit their integrity.
+//!
+//! Opening a log recovers it: a corrupt batch and everything after it is truncated away, and
+//! after a clean shutdown the crcs are not even checked. Verifying a log instead reads every
+//! batch, checks its crc and that the offsets only increase, checks that the entries of the
+//! time indexes only increase and stay within their segments, and leaves the files untouched.
+
+use crate::storage::internals::log::log_segment::LOG_FILE_SUFFIX;
+use crate::storage::internals::log::time_index::TIME_INDEX_FILE_SUFFIX;
+use crate::storage::internals::log::unified_log::UnifiedLog;
+use crate::storage::internals::log::{Result, filename_prefix_from_offset};
+use rafka_clients::common::record::record_batch::RecordBatch;
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The size of an entry of a time index: the timestamp and the offset relative to the base
+/// offset of the segment.
+const TIME_INDEX_ENTRY_SIZE: usize = 12;
+
+/// The first problem found in the files of a log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    pub file: PathBuf,
+    /// The first offset which can't be trusted: the offset the corrupt batch should start at,
+    /// or the offset of the corrupt index entry.
+    pub offset: i64,
+    /// The position of the corrupt batch or entry in the file.
+    pub position: u64,
+    pub reason: String,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "offset {} at position {} of {}: {}",
+            self.offset,
+            self.position,
+            self.file.display(),
+            self.reason
+        )
+    }
+}
+
+/// A batch read from a segment file, with where it is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentBatch {
+    pub position: u64,
+    pub size: usize,
+    pub batch: RecordBatch,
+}
+
+/// What the verification of the log of a partition found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogVerification {
+    pub topic_partition: TopicPartition,
+    pub segments: usize,
+    pub batches: usize,
+    /// The first corruption, in offset order. The files after it are not verified.
+    pub corruption: Option<Corruption>,
+}
+
+/// The base offsets of the segments in `dir`, in order.
+pub fn segment_base_offsets(dir: &Path) -> Result<Vec<i64>> {
+    let mut base_offsets = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(LOG_FILE_SUFFIX) {
+            continue;
+        }
+        if let Some(base_offset) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<i64>().ok())
+        {
+            base_offsets.push(base_offset);
+        }
+    }
+    base_offsets.sort_unstable();
+    Ok(base_offsets)
+}
+
+/// Reads the batches of the segment of `dir` starting at `base_offset`, passing them to
+/// `visit` in order, and stops at the first batch which is corrupt, incomplete or does not
+/// start above the previous one, or starts below `next_offset`.
+pub fn scan_segment(
+    dir: &Path,
+    base_offset: i64,
+    next_offset: i64,
+    mut visit: impl FnMut(&SegmentBatch),
+) -> Result<Option<Corruption>> {
+    let file = dir.join(format!(
+        "{}.{LOG_FILE_SUFFIX}",
+        filename_prefix_from_offset(base_offset)
+    ));
+    let data = fs::read(&file)?;
+    let mut expected = next_offset.max(base_offset);
+    let mut position = 0;
+    while position < data.len() {
+        let corruption = |reason: String| Corruption {
+            file: file.clone(),
+            offset: expected,
+            position: position as u64,
+            reason,
+        };
+        let (batch, size) = match RecordBatch::read_from(&data[position..]) {
+            Ok(read) => read,
+            Err(e) => return Ok(Some(corruption(e.to_string()))),
+        };
+        if batch.base_offset() < expected {
+            return Ok(Some(corruption(format!(
+                "the batch starts at offset {}, below offset {expected}",
+                batch.base_offset()
+            ))));
+        }
+        expected = batch.last_offset() + 1;
+        visit(&SegmentBatch {
+            position: position as u64,
+            size,
+            batch,
+        });
+        position += size;
+    }
+    Ok(None)
+}
+
+/// Checks the time index of the segment of `dir` starting at `base_offset` and ending before
+/// `next_offset`: its entries must be complete, only increase and point into the segment. A
+/// missing index is valid, it is rebuilt when the log is opened.
+pub fn verify_time_index(
+    dir: &Path,
+    base_offset: i64,
+    next_offset: i64,
+) -> Result<Option<Corruption>> {
+    let file = dir.join(format!(
+        "{}.{TIME_INDEX_FILE_SUFFIX}",
+        filename_prefix_from_offset(base_offset)
+    ));
+    let data = match fs::read(&file) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut last: Option<(i64, i64)> = None;
+    for (i, entry) in data.chunks(TIME_INDEX_ENTRY_SIZE).enumerate() {
+        let position = (i * TIME_INDEX_ENTRY_SIZE) as u64;
+        let corruption = |offset: i64, reason: String| Corruption {
+            file: file.clone(),
+            offset,
+            position,
+            reason,
+        };
+        if entry.len() < TIME_INDEX_ENTRY_SIZE {
+            let offset = last.map_or(base_offset, |(_, offset)| offset);
+            return Ok(Some(corruption(
+                offset,
+                format!("incomplete entry of {} bytes", entry.len()),
+            )));
+        }
+        let timestamp = i64::from_be_bytes(entry[..8].try_into().expect("8 bytes"));
+        let relative_offset = i32::from_be_bytes(entry[8..].try_into().expect("4 bytes"));
+        let offset = base_offset + relative_offset as i64;
+        if !(base_offset..next_offset.max(base_offset + 1)).contains(&offset) {
+            return Ok(Some(corruption(
+                offset,
+                format!("the entry points outside of the segment, [{base_offset}, {next_offset})"),
+            )));
+        }
+        if let Some((last_timestamp, last_offset)) = last
+            && (timestamp < last_timestamp || offset < last_offset)
+        {
+            return Ok(Some(corruption(
+                offset,
+                format!(
+                    "the entry (timestamp {timestamp}, offset {offset}) is smaller than the \
+                    previous one (timestamp {last_timestamp}, offset {last_offset})"
+                ),
+            )));
+        }
+        last = Some((timestamp, offset));
+    }
+    Ok(None)
+}
+
+/// Verifies every segment of the log in `dir` and its time index, in offset order, up to the
+/// first corruption.
+pub fn verify_log(dir: &Path) -> Result<LogVerification> {
+    let mut verification = LogVerification {
+        topic_partition: UnifiedLog::parse_topic_partition_name(dir)?,
+        segments: 0,
+        batches: 0,
+        corruption: None,
+    };
+    let mut next_offset = i64::MIN;
+    for base_offset in segment_base_offsets(dir)? {
+        verification.segments += 1;
+        let mut segment_next_offset = base_offset;
+        let corruption = scan_segment(dir, base_offset, next_offset, |read| {
+            verification.batches += 1;
+            segment_next_offset = read.batch.last_offset() + 1;
+        })?;
+        next_offset = segment_next_offset;
+        let corruption = match corruption {
+            Some(corruption) => Some(corruption),
+            None => verify_time_index(dir, base_offset, segment_next_offset)?,
+        };
+        if corruption.is_some() {
+            verification.corruption = corruption;
+            break;
+        }
+    }
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::append_origin::AppendOrigin;
+    use crate::storage::internals::log::unified_log::UnifiedLogConfig;
+    use rafka_clients::common::record::record_batch::Record;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    /// A log of three segments of two batches of two records each.
+    fn new_log(dir: &Path) -> PathBuf {
+        let dir = dir.join("foo-0");
+        let config = UnifiedLogConfig {
+            segment_bytes: 200,
+            index_interval_bytes: 1,
+            ..Default::default()
+        };
+        let mut log = UnifiedLog::open(&dir, config, 0).unwrap();
+        for i in 0..6 {
+            let records = vec![
+                Record::new(i * 10, None, Some(b"a")),
+                Record::new(i * 10 + 5, None, Some(b"b")),
+            ];
+            log.append_as_leader(RecordBatch::new(0, records), 1, AppendOrigin::Client)
+                .unwrap();
+        }
+        assert_eq!(3, log.num_segments());
+        log.flush().unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_verify_reports_the_first_corrupt_offset() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = new_log(tmp.path());
+        let verification = verify_log(&dir).unwrap();
+        assert_eq!(TopicPartition::new("foo", 0), verification.topic_partition);
+        assert_eq!(3, verification.segments);
+        assert_eq!(6, verification.batches);
+        assert_eq!(None, verification.corruption);
+
+        // Flip a byte of the records of the second batch of the second segment.
+        let base_offsets = segment_base_offsets(&dir).unwrap();
+        assert_eq!(vec![0, 4, 8], base_offsets);
+        let segment = dir.join(format!("{}.log", filename_prefix_from_offset(4)));
+        let mut data = fs::read(&segment).unwrap();
+        *data.last_mut().unwrap() ^= 0xff;
+        fs::write(&segment, &data).unwrap();
+
+        let corruption = verify_log(&dir).unwrap().corruption.unwrap();
+        assert_eq!(6, corruption.offset);
+        assert_eq!(segment, corruption.file);
+        assert!(corruption.reason.contains("crc"), "{}", corruption.reason);
+        // The files are left as they were.
+        assert_eq!(data, fs::read(&segment).unwrap());
+    }
+
+    #[test]
+    fn test_verify_time_index() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = new_log(tmp.path());
+        let index = dir.join(format!(
+            "{}.{TIME_INDEX_FILE_SUFFIX}",
+            filename_prefix_from_offset(0)
+        ));
+        // An entry going back in time.
+        let mut file = OpenOptions::new().append(true).open(&index).unwrap();
+        file.write_all(&0i64.to_be_bytes()).unwrap();
+        file.write_all(&3i32.to_be_bytes()).unwrap();
+        drop(file);
+
+        let corruption = verify_log(&dir).unwrap().corruption.unwrap();
+        assert_eq!(index, corruption.file);
+        assert_eq!(3, corruption.offset);
+        assert!(corruption.reason.contains("smaller than the previous one"));
+    }
+}
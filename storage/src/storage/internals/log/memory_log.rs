@@ -0,0 +1,483 @@
+use crate::storage::internals::epoch::leader_epoch_file_cache::LeaderEpochFileCache;
+use crate::storage::internals::log::append_origin::AppendOrigin;
+use crate::storage::internals::log::log_segment::TimestampAndOffset;
+use crate::storage::internals::log::ongoing_transactions::{
+    AbortedTransaction, AbortedTransactions, OngoingTransactions,
+};
+use crate::storage::internals::log::partition_log::PartitionLog;
+use crate::storage::internals::log::unified_log::{
+    DEFAULT_MAX_MESSAGE_BYTES, LogAppendInfo, stamp_log_append_time, validate_batch,
+    validate_batch_size,
+};
+use crate::storage::internals::log::{LogError, Result};
+use rafka_clients::common::record::record_batch::{
+    NO_PARTITION_LEADER_EPOCH, NO_TIMESTAMP, RecordBatch, TimestampType,
+};
+use rafka_clients::common::requests::list_offsets_request::{
+    EARLIEST_LOCAL_TIMESTAMP, EARLIEST_TIMESTAMP, LATEST_TIERED_TIMESTAMP, LATEST_TIMESTAMP,
+    MAX_TIMESTAMP,
+};
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_clients::common::utils::time::{SystemTime, Time};
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug)]
+struct StoredBatch {
+    batch: RecordBatch,
+    size: usize,
+}
+
+/// A log kept in memory, which behaves like a [UnifiedLog](super::unified_log::UnifiedLog)
+/// with a single segment that never needs an fsync.
+///
+/// It lets the tests of the replication, purgatory and coordinator code run against a real
+/// log without temporary directories.
+pub struct MemoryLog {
+    topic_partition: TopicPartition,
+    batches: Vec<StoredBatch>,
+    leader_epoch_cache: LeaderEpochFileCache,
+    ongoing_transactions: OngoingTransactions,
+    aborted_transactions: AbortedTransactions,
+    log_start_offset: i64,
+    max_message_bytes: usize,
+    message_timestamp_type: TimestampType,
+    time: Arc<dyn Time>,
+}
+
+impl fmt::Debug for MemoryLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryLog")
+            .field("topic_partition", &self.topic_partition)
+            .field("batches", &self.batches)
+            .field("leader_epoch_cache", &self.leader_epoch_cache)
+            .field("ongoing_transactions", &self.ongoing_transactions)
+            .field("aborted_transactions", &self.aborted_transactions)
+            .field("log_start_offset", &self.log_start_offset)
+            .field("max_message_bytes", &self.max_message_bytes)
+            .field("message_timestamp_type", &self.message_timestamp_type)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MemoryLog {
+    pub fn new(topic_partition: TopicPartition, log_start_offset: i64) -> Self {
+        Self {
+            topic_partition,
+            batches: vec![],
+            leader_epoch_cache: LeaderEpochFileCache::in_memory(),
+            ongoing_transactions: OngoingTransactions::default(),
+            aborted_transactions: AbortedTransactions::default(),
+            log_start_offset,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            message_timestamp_type: TimestampType::CreateTime,
+            time: Arc::new(SystemTime),
+        }
+    }
+
+    pub fn with_max_message_bytes(mut self, max_message_bytes: usize) -> Self {
+        self.max_message_bytes = max_message_bytes;
+        self
+    }
+
+    /// Stamps the batches appended as leader with the time of `time` if `timestamp_type` is
+    /// [TimestampType::LogAppendTime].
+    pub fn with_message_timestamp_type(
+        mut self,
+        timestamp_type: TimestampType,
+        time: Arc<dyn Time>,
+    ) -> Self {
+        self.message_timestamp_type = timestamp_type;
+        self.time = time;
+        self
+    }
+
+    /// The size in bytes the batches would take on disk.
+    pub fn size(&self) -> u64 {
+        self.batches.iter().map(|b| b.size as u64).sum()
+    }
+
+    fn append(&mut self, batch: RecordBatch) -> Result<LogAppendInfo> {
+        self.leader_epoch_cache
+            .assign(batch.partition_leader_epoch(), batch.base_offset())?;
+        if let Some(first_offset) = self.ongoing_transactions.update(&batch.header()) {
+            self.aborted_transactions.complete(&batch, first_offset);
+        }
+        let info = LogAppendInfo {
+            first_offset: batch.base_offset(),
+            last_offset: batch.last_offset(),
+            max_timestamp: batch.max_timestamp(),
+            offset_of_max_timestamp: batch.offset_of_max_timestamp(),
+            log_append_time: NO_TIMESTAMP,
+        };
+        let size = batch.size_in_bytes();
+        self.batches.push(StoredBatch { batch, size });
+        Ok(info)
+    }
+
+    fn batch_index(&self, offset: i64) -> usize {
+        self.batches
+            .partition_point(|b| b.batch.last_offset() < offset)
+    }
+}
+
+impl PartitionLog for MemoryLog {
+    fn topic_partition(&self) -> &TopicPartition {
+        &self.topic_partition
+    }
+
+    fn log_start_offset(&self) -> i64 {
+        self.log_start_offset
+    }
+
+    fn log_end_offset(&self) -> i64 {
+        self.batches
+            .last()
+            .map_or(self.log_start_offset, |b| b.batch.last_offset() + 1)
+    }
+
+    fn append_as_leader(
+        &mut self,
+        mut batch: RecordBatch,
+        leader_epoch: i32,
+        origin: AppendOrigin,
+    ) -> Result<LogAppendInfo> {
+        validate_batch(&self.topic_partition, &batch, origin)?;
+        validate_batch_size(
+            &self.topic_partition,
+            batch.size_in_bytes(),
+            self.max_message_bytes,
+        )?;
+        batch.set_base_offset(self.log_end_offset());
+        batch.set_partition_leader_epoch(leader_epoch);
+        let log_append_time =
+            stamp_log_append_time(&mut batch, self.message_timestamp_type, self.time.as_ref());
+        Ok(LogAppendInfo {
+            log_append_time,
+            ..self.append(batch)?
+        })
+    }
+
+    fn append_as_follower(&mut self, batch: RecordBatch) -> Result<LogAppendInfo> {
+        validate_batch(&self.topic_partition, &batch, AppendOrigin::Replication)?;
+        let log_end_offset = self.log_end_offset();
+        if batch.base_offset() != log_end_offset {
+            return Err(LogError::UnexpectedAppendOffset {
+                expected: log_end_offset,
+                actual: batch.base_offset(),
+            });
+        }
+        self.append(batch)
+    }
+
+    fn read(&self, start_offset: i64, max_bytes: usize) -> Result<Vec<RecordBatch>> {
+        let log_end_offset = self.log_end_offset();
+        if start_offset < self.log_start_offset || start_offset > log_end_offset {
+            return Err(LogError::OffsetOutOfRange {
+                offset: start_offset,
+                log_start_offset: self.log_start_offset,
+                log_end_offset,
+            });
+        }
+        let mut batches = Vec::new();
+        let mut bytes = 0;
+        for stored in &self.batches[self.batch_index(start_offset)..] {
+            if !batches.is_empty() && bytes + stored.size > max_bytes {
+                break;
+            }
+            batches.push(stored.batch.clone());
+            bytes += stored.size;
+        }
+        Ok(batches)
+    }
+
+    fn truncate_to(&mut self, target_offset: i64) -> Result<bool> {
+        if target_offset >= self.log_end_offset() {
+            return Ok(false);
+        }
+        if target_offset <= self.log_start_offset {
+            self.batches.clear();
+            self.log_start_offset = target_offset;
+        } else {
+            let first_removed = self.batch_index(target_offset);
+            self.batches.truncate(first_removed);
+        }
+        self.leader_epoch_cache
+            .truncate_from_end(self.log_end_offset())?;
+        // The truncation may have removed the marker ending a transaction as well as its start.
+        self.ongoing_transactions =
+            OngoingTransactions::of(self.batches.iter().map(|b| b.batch.header()));
+        self.aborted_transactions.truncate_to(target_offset);
+        Ok(true)
+    }
+
+    fn latest_epoch(&self) -> Option<i32> {
+        self.leader_epoch_cache.latest_epoch()
+    }
+
+    fn first_unstable_offset(&self) -> Option<i64> {
+        self.ongoing_transactions.first_unstable_offset()
+    }
+
+    fn aborted_transactions(
+        &self,
+        start_offset: i64,
+        upper_bound_offset: i64,
+    ) -> Vec<AbortedTransaction> {
+        self.aborted_transactions
+            .overlapping(start_offset, upper_bound_offset)
+    }
+
+    fn end_offset_for_epoch(&self, leader_epoch: i32) -> Option<(i32, i64)> {
+        self.leader_epoch_cache
+            .end_offset_for(leader_epoch, self.log_end_offset())
+    }
+
+    fn leader_epoch_for_offset(&self, offset: i64) -> Option<i32> {
+        self.batches
+            .get(self.batch_index(offset))
+            .map(|b| b.batch.partition_leader_epoch())
+            .filter(|epoch| *epoch != NO_PARTITION_LEADER_EPOCH)
+    }
+
+    fn fetch_offset_by_timestamp(
+        &self,
+        target_timestamp: i64,
+    ) -> Result<Option<TimestampAndOffset>> {
+        let at = |timestamp, offset| {
+            TimestampAndOffset::new(timestamp, offset, self.leader_epoch_for_offset(offset))
+        };
+        let records = || {
+            self.batches.iter().flat_map(|b| {
+                let epoch = b.batch.partition_leader_epoch();
+                b.batch
+                    .iter()
+                    .map(move |(offset, r)| (offset, r.timestamp, epoch))
+            })
+        };
+        let found = match target_timestamp {
+            EARLIEST_TIMESTAMP | EARLIEST_LOCAL_TIMESTAMP => {
+                Some(at(NO_TIMESTAMP, self.log_start_offset))
+            }
+            LATEST_TIMESTAMP => {
+                let log_end_offset = self.log_end_offset();
+                Some(TimestampAndOffset::new(
+                    NO_TIMESTAMP,
+                    log_end_offset,
+                    self.leader_epoch_for_offset(log_end_offset - 1),
+                ))
+            }
+            // Nothing of a memory log is in remote storage.
+            LATEST_TIERED_TIMESTAMP => Some(TimestampAndOffset::new(NO_TIMESTAMP, -1, None)),
+            MAX_TIMESTAMP => records()
+                .filter(|(_, timestamp, _)| *timestamp != NO_TIMESTAMP)
+                .reduce(|max, other| if other.1 > max.1 { other } else { max })
+                .map(|(offset, timestamp, _)| at(timestamp, offset)),
+            timestamp if timestamp >= 0 => records()
+                .find(|(_, t, _)| *t >= timestamp)
+                .map(|(offset, timestamp, _)| at(timestamp, offset)),
+            _ => None,
+        };
+        Ok(found)
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::unified_log::{UnifiedLog, UnifiedLogConfig};
+    use rafka_clients::common::record::record_batch::Record;
+    use rafka_clients::common::utils::time::MockTime;
+
+    fn batch(base_offset: i64, epoch: i32, count: usize) -> RecordBatch {
+        let mut batch = RecordBatch::new(
+            base_offset,
+            (0..count)
+                .map(|i| Record::new(i as i64, None, Some(b"value")))
+                .collect(),
+        );
+        batch.set_partition_leader_epoch(epoch);
+        batch
+    }
+
+    /// Runs the same operations on `log` and returns what they observed.
+    fn exercise(log: &mut impl PartitionLog) -> Vec<String> {
+        let mut observed = vec![];
+        log.append_as_follower(batch(0, 1, 3)).unwrap();
+        log.append_as_follower(batch(3, 1, 2)).unwrap();
+        log.append_as_follower(batch(5, 3, 4)).unwrap();
+        log.append_as_leader(batch(0, 0, 1), 4, AppendOrigin::Client)
+            .unwrap();
+        observed.push(format!(
+            "{:?}",
+            log.append_as_follower(batch(3, 4, 1)).err()
+        ));
+        observed.push(format!(
+            "{} {:?} {:?} {:?}",
+            log.log_end_offset(),
+            log.latest_epoch(),
+            log.end_offset_for_epoch(2),
+            log.leader_epoch_for_offset(6)
+        ));
+        let read = log.read(4, 1).unwrap();
+        observed.push(format!(
+            "{:?}",
+            read.iter().map(|b| b.base_offset()).collect::<Vec<_>>()
+        ));
+        observed.push(format!("{:?}", log.read(11, 1).err()));
+
+        // Offset 6 is in the middle of the epoch 3 batch, which is removed as a whole.
+        observed.push(format!("{:?}", log.truncate_to(6).unwrap()));
+        observed.push(format!(
+            "{} {:?} {:?}",
+            log.log_end_offset(),
+            log.latest_epoch(),
+            log.end_offset_for_epoch(3)
+        ));
+        observed.push(format!("{:?}", log.truncate_to(0).unwrap()));
+        observed.push(format!("{} {:?}", log.log_end_offset(), log.latest_epoch()));
+        observed
+    }
+
+    #[test]
+    fn test_behaves_like_a_unified_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut unified_log =
+            UnifiedLog::open(&dir.path().join("foo-0"), UnifiedLogConfig::default(), 0).unwrap();
+        let mut memory_log = MemoryLog::new(TopicPartition::new("foo", 0), 0);
+        let observed = exercise(&mut memory_log);
+        assert_eq!(exercise(&mut unified_log), observed);
+        assert_eq!("10 Some(4) Some((1, 5)) Some(3)", observed[1]);
+        assert_eq!("[3]", observed[2]);
+        assert_eq!("5 Some(1) None", observed[5]);
+        assert_eq!("0 None", observed[7]);
+    }
+
+    #[test]
+    fn test_fetch_offset_by_timestamp() {
+        let timestamped = |base_offset, epoch, timestamps: &[i64]| {
+            let records = timestamps
+                .iter()
+                .map(|t| Record::new(*t, None, Some(b"value")))
+                .collect();
+            let mut batch = RecordBatch::new(base_offset, records);
+            batch.set_partition_leader_epoch(epoch);
+            batch
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let mut unified_log =
+            UnifiedLog::open(&dir.path().join("foo-0"), UnifiedLogConfig::default(), 0).unwrap();
+        let mut memory_log = MemoryLog::new(TopicPartition::new("foo", 0), 0);
+        let logs: [&mut dyn PartitionLog; 2] = [&mut unified_log, &mut memory_log];
+        for log in logs {
+            log.append_as_follower(timestamped(0, 1, &[10, 30, 20]))
+                .unwrap();
+            log.append_as_follower(timestamped(3, 3, &[40, 50]))
+                .unwrap();
+            log.append_as_leader(timestamped(0, 0, &[45]), 4, AppendOrigin::Client)
+                .unwrap();
+
+            let found = |timestamp| {
+                log.fetch_offset_by_timestamp(timestamp)
+                    .unwrap()
+                    .map(|found| (found.timestamp, found.offset, found.leader_epoch))
+            };
+            assert_eq!(Some((10, 0, Some(1))), found(0));
+            assert_eq!(Some((30, 1, Some(1))), found(25));
+            // The first record at or above the timestamp, not the one closest to it.
+            assert_eq!(Some((50, 4, Some(3))), found(41));
+            assert_eq!(None, found(51));
+            assert_eq!(Some((50, 4, Some(3))), found(MAX_TIMESTAMP));
+            assert_eq!(Some((NO_TIMESTAMP, 0, Some(1))), found(EARLIEST_TIMESTAMP));
+            assert_eq!(Some((NO_TIMESTAMP, 6, Some(4))), found(LATEST_TIMESTAMP));
+
+            assert_eq!(4, log.offset_for_timestamp_reset(45).unwrap());
+            assert_eq!(6, log.offset_for_timestamp_reset(51).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_leader_rejects_batches_above_max_message_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let max_message_bytes = batch(0, 0, 2).size_in_bytes();
+        let config = UnifiedLogConfig {
+            max_message_bytes,
+            ..Default::default()
+        };
+        let mut unified_log = UnifiedLog::open(&dir.path().join("foo-0"), config, 0).unwrap();
+        let mut memory_log = MemoryLog::new(TopicPartition::new("foo", 0), 0)
+            .with_max_message_bytes(max_message_bytes);
+        let logs: [&mut dyn PartitionLog; 2] = [&mut unified_log, &mut memory_log];
+        for log in logs {
+            log.append_as_leader(batch(0, 0, 2), 1, AppendOrigin::Client)
+                .unwrap();
+            let error = log
+                .append_as_leader(batch(0, 0, 3), 1, AppendOrigin::Coordinator)
+                .unwrap_err();
+            assert!(
+                matches!(error, LogError::RecordTooLarge { size, .. } if size > max_message_bytes)
+            );
+            // Followers append what the leader accepted.
+            log.append_as_follower(batch(2, 1, 3)).unwrap();
+            assert_eq!(5, log.log_end_offset());
+        }
+    }
+
+    #[test]
+    fn test_log_append_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let time = Arc::new(MockTime::with_start(0, 1_000, 0));
+        let config = UnifiedLogConfig {
+            message_timestamp_type: TimestampType::LogAppendTime,
+            ..Default::default()
+        };
+        let mut unified_log = UnifiedLog::open(&dir.path().join("foo-0"), config, 0).unwrap();
+        unified_log.set_time(time.clone());
+        let mut memory_log = MemoryLog::new(TopicPartition::new("foo", 0), 0)
+            .with_message_timestamp_type(TimestampType::LogAppendTime, time.clone());
+        let logs: [&mut dyn PartitionLog; 2] = [&mut unified_log, &mut memory_log];
+        for (i, log) in logs.into_iter().enumerate() {
+            let appended_at = 1_000 + 200 * i as i64;
+            let info = log
+                .append_as_leader(batch(500, 0, 2), 1, AppendOrigin::Client)
+                .unwrap();
+            assert_eq!(appended_at, info.log_append_time);
+            assert_eq!(appended_at, info.max_timestamp);
+            assert_eq!(0, info.offset_of_max_timestamp);
+            time.sleep(100);
+            log.append_as_leader(batch(0, 0, 2), 1, AppendOrigin::Client)
+                .unwrap();
+            time.sleep(100);
+
+            // The producer's timestamps are gone, the time index only knows the append times.
+            let read = log.read(0, usize::MAX).unwrap();
+            assert!(
+                read[1]
+                    .records()
+                    .iter()
+                    .all(|r| r.timestamp == appended_at + 100)
+            );
+            let max = log
+                .fetch_offset_by_timestamp(MAX_TIMESTAMP)
+                .unwrap()
+                .unwrap();
+            assert_eq!((appended_at + 100, 2), (max.timestamp, max.offset));
+            let found = log
+                .fetch_offset_by_timestamp(appended_at + 1)
+                .unwrap()
+                .unwrap();
+            assert_eq!(2, found.offset);
+
+            // A follower keeps the timestamps the leader stamped.
+            let mut replicated = read[1].clone();
+            replicated.set_base_offset(4);
+            let info = log.append_as_follower(replicated).unwrap();
+            assert_eq!(NO_TIMESTAMP, info.log_append_time);
+            assert_eq!(appended_at + 100, info.max_timestamp);
+        }
+    }
+}
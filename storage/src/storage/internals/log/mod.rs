@@ -1,2 +1,104 @@
+use log_dir_lock::LOCK_FILE_NAME;
+use rafka_clients::common::record::record_batch::RecordError;
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub mod append_origin;
+pub mod clean_shutdown_file;
 pub mod cleaner_config;
+pub mod disk_space_monitor;
+pub mod log_cleaner_metrics;
 pub mod log_config;
+pub mod log_dir_lock;
+pub mod log_metrics;
+pub mod log_recovery;
+pub mod log_report;
+pub mod log_segment;
+pub mod log_verifier;
+pub mod memory_log;
+pub mod ongoing_transactions;
+pub mod partition_log;
+pub mod remote_log_reader;
+pub mod replica_log_dir_mover;
+pub mod tail_batch_cache;
+pub mod time_index;
+pub mod topic_log_configs;
+pub mod unified_log;
+
+#[derive(Error, Debug)]
+pub enum LogError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid record batch: {0}")]
+    Record(#[from] RecordError),
+
+    #[error(
+        "Offset {offset} is out of range, the log has offsets from {log_start_offset} to {log_end_offset}"
+    )]
+    OffsetOutOfRange {
+        offset: i64,
+        log_start_offset: i64,
+        log_end_offset: i64,
+    },
+
+    #[error("Expected the appended batch to start at offset {expected} but it starts at {actual}")]
+    UnexpectedAppendOffset { expected: i64, actual: i64 },
+
+    #[error("Invalid record: {0}")]
+    InvalidRecord(String),
+
+    #[error("Invalid topic: {0}")]
+    InvalidTopic(String),
+
+    #[error(
+        "The batch appended to {topic_partition} takes {size} bytes, more than max.message.bytes {max_message_bytes}"
+    )]
+    RecordTooLarge {
+        topic_partition: TopicPartition,
+        size: usize,
+        max_message_bytes: usize,
+    },
+
+    #[error("Found directory {}, which is not in the form of topic-partition", .0.display())]
+    InvalidDirectory(PathBuf),
+
+    #[error(
+        "Failed to acquire lock on file {LOCK_FILE_NAME} in {}. A broker in another process{} is using this directory",
+        .dir.display(),
+        .pid.map(|pid| format!(" with PID {pid}")).unwrap_or_default()
+    )]
+    LogDirLocked { dir: PathBuf, pid: Option<u32> },
+
+    #[error(
+        "The log directory {} has {free_bytes} free bytes, below the limit of {hard_limit_free_bytes}",
+        .dir.display()
+    )]
+    LogDirWriteProtected {
+        dir: PathBuf,
+        free_bytes: u64,
+        hard_limit_free_bytes: u64,
+    },
+
+    #[error("The index file {} is corrupt: {reason}", .path.display())]
+    CorruptIndex { path: PathBuf, reason: String },
+
+    #[error("{} is not one of the log directories of the broker", .0.display())]
+    LogDirNotFound(PathBuf),
+
+    #[error("The broker hosts no replica of {0}")]
+    ReplicaNotAvailable(TopicPartition),
+
+    #[error("Invalid value {value} for topic config {name}")]
+    InvalidConfig { name: String, value: String },
+}
+
+pub type Result<T> = std::result::Result<T, LogError>;
+
+/// The name of the files of the segment starting at `offset`, without extension: the offset
+/// padded with zeros to 20 digits so the files sort by offset.
+pub fn filename_prefix_from_offset(offset: i64) -> String {
+    format!("{offset:020}")
+}
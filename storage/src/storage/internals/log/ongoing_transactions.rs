@@ -0,0 +1,216 @@
+use rafka_clients::common::record::control_record::{ControlRecordType, EndTransactionMarker};
+use rafka_clients::common::record::record_batch::{NO_PRODUCER_ID, RecordBatch, RecordBatchHeader};
+use std::collections::BTreeMap;
+
+/// The transactions written to a log which are neither committed nor aborted yet, i.e. whose
+/// producers wrote transactional batches but no transaction marker after them.
+///
+/// The first offset of the oldest of them is the first unstable offset of the log: a consumer
+/// with `isolation.level=read_committed` reads up to the last stable offset, the smaller of
+/// it and the high watermark, since the transactions from there on may still be aborted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OngoingTransactions {
+    /// The first offset of the ongoing transaction of each producer.
+    first_offsets: BTreeMap<i64, i64>,
+}
+
+impl OngoingTransactions {
+    /// The ongoing transactions of a log holding the batches of `headers`, in offset order.
+    pub fn of(headers: impl IntoIterator<Item = RecordBatchHeader>) -> Self {
+        let mut transactions = Self::default();
+        for header in headers {
+            transactions.update(&header);
+        }
+        transactions
+    }
+
+    /// Tracks the batch of `header`, just appended: a transactional batch starts the
+    /// transaction of its producer unless it is ongoing already, and a transaction marker
+    /// ends it.
+    ///
+    /// Returns the first offset of the transaction a marker ends, the offset of the marker
+    /// itself if its producer had no ongoing transaction.
+    pub fn update(&mut self, header: &RecordBatchHeader) -> Option<i64> {
+        if !header.is_transactional || header.producer_id == NO_PRODUCER_ID {
+            return None;
+        }
+        if header.is_control {
+            Some(
+                self.first_offsets
+                    .remove(&header.producer_id)
+                    .unwrap_or(header.base_offset),
+            )
+        } else {
+            self.first_offsets
+                .entry(header.producer_id)
+                .or_insert(header.base_offset);
+            None
+        }
+    }
+
+    /// The first offset of the oldest ongoing transaction.
+    pub fn first_unstable_offset(&self) -> Option<i64> {
+        self.first_offsets.values().min().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.first_offsets.is_empty()
+    }
+}
+
+/// A transaction which was aborted: the records of `producer_id` from `first_offset` up to
+/// the abort marker at `last_offset` are to be skipped by read_committed consumers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbortedTransaction {
+    pub producer_id: i64,
+    pub first_offset: i64,
+    pub last_offset: i64,
+}
+
+/// The aborted transactions of a log, in the order of their abort markers.
+///
+/// A fetch of a read_committed consumer returns the ones overlapping the records it fetched,
+/// so that the consumer can drop their records, which the log keeps like any other.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AbortedTransactions {
+    aborted: Vec<AbortedTransaction>,
+}
+
+impl AbortedTransactions {
+    /// Tracks `marker`, a transaction marker just appended which ends a transaction starting
+    /// at `first_offset`, see [OngoingTransactions::update], if it aborts the transaction.
+    pub fn complete(&mut self, marker: &RecordBatch, first_offset: i64) {
+        let aborted = marker.records().first().is_some_and(|record| {
+            EndTransactionMarker::from_record(record)
+                .is_ok_and(|marker| marker.control_type == ControlRecordType::Abort)
+        });
+        if aborted {
+            self.aborted.push(AbortedTransaction {
+                producer_id: marker.producer_id(),
+                first_offset,
+                last_offset: marker.base_offset(),
+            });
+        }
+    }
+
+    /// The transactions with records from `start_offset` up to `upper_bound_offset`.
+    pub fn overlapping(
+        &self,
+        start_offset: i64,
+        upper_bound_offset: i64,
+    ) -> Vec<AbortedTransaction> {
+        let first = self
+            .aborted
+            .partition_point(|aborted| aborted.last_offset < start_offset);
+        self.aborted[first..]
+            .iter()
+            .filter(|aborted| aborted.first_offset < upper_bound_offset)
+            .copied()
+            .collect()
+    }
+
+    /// Forgets the transactions aborted at `offset` and above, e.g. after a truncation removed
+    /// their markers.
+    pub fn truncate_to(&mut self, offset: i64) {
+        self.aborted.retain(|aborted| aborted.last_offset < offset);
+    }
+
+    /// Forgets the transactions aborted below `offset`, e.g. once the log start offset moved
+    /// past their markers: none of their records are left.
+    pub fn evict_below(&mut self, offset: i64) {
+        self.aborted.retain(|aborted| aborted.last_offset >= offset);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aborted.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::record::record_batch::Record;
+
+    fn transactional(producer_id: i64, base_offset: i64) -> RecordBatch {
+        let mut batch = RecordBatch::new(base_offset, vec![Record::new(0, None, Some(b"v"))])
+            .with_producer(producer_id, 0, 0);
+        batch.set_transactional(true);
+        batch
+    }
+
+    fn marker(producer_id: i64, base_offset: i64) -> RecordBatch {
+        end_marker(ControlRecordType::Commit, producer_id, base_offset)
+    }
+
+    fn end_marker(
+        control_type: ControlRecordType,
+        producer_id: i64,
+        base_offset: i64,
+    ) -> RecordBatch {
+        let marker = EndTransactionMarker::new(control_type, 0);
+        let mut batch = RecordBatch::end_transaction_marker(producer_id, 0, marker, 0);
+        batch.set_base_offset(base_offset);
+        batch
+    }
+
+    #[test]
+    fn test_first_unstable_offset() {
+        let mut transactions = OngoingTransactions::of(
+            [
+                RecordBatch::new(0, vec![Record::new(0, None, Some(b"v"))]),
+                transactional(1, 1),
+                transactional(2, 2),
+                transactional(1, 3),
+            ]
+            .iter()
+            .map(RecordBatch::header),
+        );
+        assert_eq!(Some(1), transactions.first_unstable_offset());
+
+        assert_eq!(Some(1), transactions.update(&marker(1, 4).header()));
+        assert_eq!(Some(2), transactions.first_unstable_offset());
+        transactions.update(&marker(2, 5).header());
+        assert!(transactions.is_empty());
+        assert_eq!(None, transactions.first_unstable_offset());
+        // A marker without a transaction ends an empty one.
+        assert_eq!(Some(6), transactions.update(&marker(3, 6).header()));
+    }
+
+    #[test]
+    fn test_aborted_transactions() {
+        let mut ongoing = OngoingTransactions::default();
+        let mut aborted = AbortedTransactions::default();
+        let batches = [
+            transactional(1, 0),
+            transactional(2, 1),
+            end_marker(ControlRecordType::Abort, 1, 2),
+            transactional(1, 3),
+            end_marker(ControlRecordType::Commit, 2, 4),
+            end_marker(ControlRecordType::Abort, 1, 5),
+        ];
+        for batch in &batches {
+            if let Some(first_offset) = ongoing.update(&batch.header()) {
+                aborted.complete(batch, first_offset);
+            }
+        }
+        let first = AbortedTransaction {
+            producer_id: 1,
+            first_offset: 0,
+            last_offset: 2,
+        };
+        let second = AbortedTransaction {
+            producer_id: 1,
+            first_offset: 3,
+            last_offset: 5,
+        };
+        assert_eq!(vec![first, second], aborted.overlapping(0, 6));
+        assert_eq!(vec![first], aborted.overlapping(0, 3));
+        assert_eq!(vec![second], aborted.overlapping(3, 4));
+        assert_eq!(Vec::<AbortedTransaction>::new(), aborted.overlapping(6, 10));
+
+        aborted.truncate_to(5);
+        assert_eq!(vec![first], aborted.overlapping(0, 6));
+        aborted.evict_below(3);
+        assert!(aborted.is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+use crate::storage::internals::log::Result;
+use crate::storage::internals::log::append_origin::AppendOrigin;
+use crate::storage::internals::log::log_segment::TimestampAndOffset;
+use crate::storage::internals::log::memory_log::MemoryLog;
+use crate::storage::internals::log::ongoing_transactions::AbortedTransaction;
+use crate::storage::internals::log::topic_log_configs::TopicLogConfigs;
+use crate::storage::internals::log::unified_log::{LogAppendInfo, UnifiedLog, UnifiedLogConfig};
+use rafka_clients::common::record::record_batch::RecordBatch;
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The operations of the log of a partition which replication relies on.
+///
+/// [UnifiedLog] stores the log on disk; [MemoryLog](super::memory_log::MemoryLog) keeps it in
+/// memory, so the components built on top of a log can be tested without temporary
+/// directories and fsyncs.
+pub trait PartitionLog {
+    fn topic_partition(&self) -> &TopicPartition;
+
+    fn log_start_offset(&self) -> i64;
+
+    /// The offset the next appended record gets.
+    fn log_end_offset(&self) -> i64;
+
+    /// Appends a batch on the leader, assigning it the next offsets and stamping it with the
+    /// leader epoch.
+    fn append_as_leader(
+        &mut self,
+        batch: RecordBatch,
+        leader_epoch: i32,
+        origin: AppendOrigin,
+    ) -> Result<LogAppendInfo>;
+
+    /// Appends a batch fetched from the leader, which keeps the offsets and the leader epoch
+    /// the leader assigned.
+    fn append_as_follower(&mut self, batch: RecordBatch) -> Result<LogAppendInfo>;
+
+    /// Reads the batches from `start_offset` on, up to `max_bytes`.
+    fn read(&self, start_offset: i64, max_bytes: usize) -> Result<Vec<RecordBatch>>;
+
+    /// Removes the records at `target_offset` and above, the batch holding `target_offset` as a
+    /// whole. Returns whether anything was removed.
+    fn truncate_to(&mut self, target_offset: i64) -> Result<bool>;
+
+    /// The latest leader epoch which wrote to the log.
+    fn latest_epoch(&self) -> Option<i32>;
+
+    /// The largest epoch not above `leader_epoch` and the offset it ended at.
+    fn end_offset_for_epoch(&self, leader_epoch: i32) -> Option<(i32, i64)>;
+
+    /// The leader epoch of the batch holding `offset`.
+    fn leader_epoch_for_offset(&self, offset: i64) -> Option<i32>;
+
+    /// The first offset of the oldest transaction of the log which is neither committed nor
+    /// aborted yet.
+    fn first_unstable_offset(&self) -> Option<i64>;
+
+    /// The transactions aborted with records from `start_offset` up to `upper_bound_offset`,
+    /// which a consumer with `isolation.level=read_committed` needs to skip the aborted
+    /// records among them.
+    fn aborted_transactions(
+        &self,
+        start_offset: i64,
+        upper_bound_offset: i64,
+    ) -> Vec<AbortedTransaction>;
+
+    /// The offset a consumer with `isolation.level=read_committed` reads up to: the first
+    /// unstable offset, or `high_watermark` if it is smaller or there is none.
+    fn last_stable_offset(&self, high_watermark: i64) -> i64 {
+        self.first_unstable_offset()
+            .map_or(high_watermark, |offset| offset.min(high_watermark))
+    }
+
+    /// Looks up the offset a ListOffsets request asks for with `target_timestamp`, see
+    /// [UnifiedLog::fetch_offset_by_timestamp].
+    fn fetch_offset_by_timestamp(
+        &self,
+        target_timestamp: i64,
+    ) -> Result<Option<TimestampAndOffset>>;
+
+    /// The offset `kafka-consumer-groups.sh --reset-offsets --to-datetime` moves a group to:
+    /// the first record with a timestamp at or above `timestamp`, or the log end offset if
+    /// every record is older.
+    fn offset_for_timestamp_reset(&self, timestamp: i64) -> Result<i64> {
+        Ok(self
+            .fetch_offset_by_timestamp(timestamp)?
+            .map_or_else(|| self.log_end_offset(), |found| found.offset))
+    }
+
+    fn flush(&self) -> Result<()>;
+}
+
+impl PartitionLog for UnifiedLog {
+    fn topic_partition(&self) -> &TopicPartition {
+        UnifiedLog::topic_partition(self)
+    }
+
+    fn log_start_offset(&self) -> i64 {
+        UnifiedLog::log_start_offset(self)
+    }
+
+    fn log_end_offset(&self) -> i64 {
+        UnifiedLog::log_end_offset(self)
+    }
+
+    fn append_as_leader(
+        &mut self,
+        batch: RecordBatch,
+        leader_epoch: i32,
+        origin: AppendOrigin,
+    ) -> Result<LogAppendInfo> {
+        UnifiedLog::append_as_leader(self, batch, leader_epoch, origin)
+    }
+
+    fn append_as_follower(&mut self, batch: RecordBatch) -> Result<LogAppendInfo> {
+        UnifiedLog::append_as_follower(self, batch)
+    }
+
+    fn read(&self, start_offset: i64, max_bytes: usize) -> Result<Vec<RecordBatch>> {
+        UnifiedLog::read(self, start_offset, max_bytes)
+    }
+
+    fn truncate_to(&mut self, target_offset: i64) -> Result<bool> {
+        UnifiedLog::truncate_to(self, target_offset)
+    }
+
+    fn latest_epoch(&self) -> Option<i32> {
+        UnifiedLog::latest_epoch(self)
+    }
+
+    fn end_offset_for_epoch(&self, leader_epoch: i32) -> Option<(i32, i64)> {
+        UnifiedLog::end_offset_for_epoch(self, leader_epoch)
+    }
+
+    fn leader_epoch_for_offset(&self, offset: i64) -> Option<i32> {
+        UnifiedLog::leader_epoch_for_offset(self, offset)
+    }
+
+    fn first_unstable_offset(&self) -> Option<i64> {
+        UnifiedLog::first_unstable_offset(self)
+    }
+
+    fn aborted_transactions(
+        &self,
+        start_offset: i64,
+        upper_bound_offset: i64,
+    ) -> Vec<AbortedTransaction> {
+        UnifiedLog::aborted_transactions(self, start_offset, upper_bound_offset)
+    }
+
+    fn fetch_offset_by_timestamp(
+        &self,
+        target_timestamp: i64,
+    ) -> Result<Option<TimestampAndOffset>> {
+        UnifiedLog::fetch_offset_by_timestamp(self, target_timestamp, None)
+    }
+
+    fn flush(&self) -> Result<()> {
+        UnifiedLog::flush(self)
+    }
+}
+
+/// Creates the logs of the partitions a broker hosts, the boundary between the replication
+/// layer and the storage backend.
+///
+/// The replication layer only sees [PartitionLog]s, so an experimental backend, e.g. one
+/// storing the segments natively in object storage, plugs in by implementing both traits
+/// and handing its factory to the broker in place of [UnifiedLogFactory].
+pub trait PartitionLogFactory {
+    /// Opens the log of `topic_partition`, creating it empty from `log_start_offset` if it
+    /// does not exist yet.
+    fn open(
+        &self,
+        topic_partition: &TopicPartition,
+        log_start_offset: i64,
+    ) -> Result<Box<dyn PartitionLog + Send>>;
+}
+
+/// Opens each partition as a [UnifiedLog] in its `topic-partition` directory of a log dir.
+#[derive(Debug, Clone)]
+pub struct UnifiedLogFactory {
+    log_dir: PathBuf,
+    config: UnifiedLogConfig,
+    topic_configs: Option<Arc<TopicLogConfigs>>,
+}
+
+impl UnifiedLogFactory {
+    pub fn new(log_dir: impl Into<PathBuf>, config: UnifiedLogConfig) -> Self {
+        Self {
+            log_dir: log_dir.into(),
+            config,
+            topic_configs: None,
+        }
+    }
+
+    /// Opens the logs with the current configs of their topic rather than with `config`.
+    pub fn with_topic_configs(mut self, topic_configs: Arc<TopicLogConfigs>) -> Self {
+        self.topic_configs = Some(topic_configs);
+        self
+    }
+
+    /// The directory the log of `topic_partition` is stored in.
+    pub fn partition_dir(&self, topic_partition: &TopicPartition) -> PathBuf {
+        self.log_dir.join(format!(
+            "{}-{}",
+            topic_partition.topic(),
+            topic_partition.partition()
+        ))
+    }
+}
+
+impl PartitionLogFactory for UnifiedLogFactory {
+    fn open(
+        &self,
+        topic_partition: &TopicPartition,
+        log_start_offset: i64,
+    ) -> Result<Box<dyn PartitionLog + Send>> {
+        let config = match &self.topic_configs {
+            Some(topic_configs) => topic_configs.config(topic_partition.topic()),
+            None => self.config.clone(),
+        };
+        let log = UnifiedLog::open(
+            &self.partition_dir(topic_partition),
+            config,
+            log_start_offset,
+        )?;
+        Ok(Box::new(log))
+    }
+}
+
+/// Creates each partition as an empty [MemoryLog], for tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryLogFactory;
+
+impl PartitionLogFactory for MemoryLogFactory {
+    fn open(
+        &self,
+        topic_partition: &TopicPartition,
+        log_start_offset: i64,
+    ) -> Result<Box<dyn PartitionLog + Send>> {
+        Ok(Box::new(MemoryLog::new(
+            topic_partition.clone(),
+            log_start_offset,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::LogError;
+    use rafka_clients::common::config::topic_config::MAX_MESSAGE_BYTES_CONFIG;
+    use rafka_clients::common::record::record_batch::Record;
+
+    fn append_and_reopen(factory: &dyn PartitionLogFactory) -> Box<dyn PartitionLog + Send> {
+        let topic_partition = TopicPartition::new("foo", 1);
+        let mut log = factory.open(&topic_partition, 5).unwrap();
+        assert_eq!(&topic_partition, log.topic_partition());
+        assert_eq!(5, log.log_end_offset());
+
+        let batch = RecordBatch::new(0, vec![Record::new(0, None, Some(b"a"))]);
+        log.append_as_leader(batch, 3, AppendOrigin::Client)
+            .unwrap();
+        log.flush().unwrap();
+        assert_eq!(6, log.log_end_offset());
+        assert_eq!(Some(3), log.latest_epoch());
+        assert_eq!(1, log.read(5, usize::MAX).unwrap().len());
+        drop(log);
+        factory.open(&topic_partition, 5).unwrap()
+    }
+
+    #[test]
+    fn test_unified_log_factory() {
+        let dir = tempfile::tempdir().unwrap();
+        let factory = UnifiedLogFactory::new(dir.path(), UnifiedLogConfig::default());
+        let reopened = append_and_reopen(&factory);
+        assert!(dir.path().join("foo-1").is_dir());
+        // The log is recovered from its directory.
+        assert_eq!(6, reopened.log_end_offset());
+        assert_eq!(Some(3), reopened.latest_epoch());
+    }
+
+    #[test]
+    fn test_unified_log_factory_uses_the_topic_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let topic_configs = Arc::new(TopicLogConfigs::default());
+        let factory = UnifiedLogFactory::new(dir.path(), UnifiedLogConfig::default())
+            .with_topic_configs(topic_configs.clone());
+        topic_configs
+            .set("foo", MAX_MESSAGE_BYTES_CONFIG, Some("10"))
+            .unwrap();
+        let batch = RecordBatch::new(0, vec![Record::new(0, None, Some(b"a"))]);
+        let mut foo = factory.open(&TopicPartition::new("foo", 0), 0).unwrap();
+        assert!(matches!(
+            foo.append_as_leader(batch.clone(), 0, AppendOrigin::Client),
+            Err(LogError::RecordTooLarge { .. })
+        ));
+        let mut bar = factory.open(&TopicPartition::new("bar", 0), 0).unwrap();
+        bar.append_as_leader(batch, 0, AppendOrigin::Client)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_memory_log_factory() {
+        let reopened = append_and_reopen(&MemoryLogFactory);
+        // Nothing outlives a memory log.
+        assert_eq!(5, reopened.log_end_offset());
+    }
+}
@@ -0,0 +1,20 @@
+use crate::storage::internals::log::Result;
+use crate::storage::internals::log::log_segment::TimestampAndOffset;
+
+/// Looks up the segments a partition copied to remote storage, from their metadata and, when
+/// needed, their indexes fetched from the remote store.
+pub trait RemoteLogReader {
+    /// The last offset copied to remote storage, or `None` if nothing was copied yet.
+    fn highest_offset_in_remote_storage(&self) -> Option<i64>;
+
+    /// The leader epoch of the remote segment holding `offset`.
+    fn leader_epoch_for_offset(&self, offset: i64) -> Option<i32>;
+
+    /// Finds the first record at or after `starting_offset` in remote storage whose timestamp
+    /// is at least `timestamp`.
+    fn find_offset_by_timestamp(
+        &self,
+        timestamp: i64,
+        starting_offset: i64,
+    ) -> Result<Option<TimestampAndOffset>>;
+}
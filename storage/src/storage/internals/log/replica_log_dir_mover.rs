@@ -0,0 +1,348 @@
+//! Moves replicas between the log directories of a broker, as asked by AlterReplicaLogDirs,
+//! so that an operator of a JBOD broker can rebalance its disks without moving data to
+//! another broker.
+//!
+//! A move creates a future replica in the destination directory, named
+//! `<topic>-<partition>-future`, which copies the current replica in the background while
+//! it keeps serving requests. Once the future replica caught up, it takes the place of the
+//! current one and the current one is deleted. A future directory left behind by a broker
+//! which stopped in the middle of a move holds an incomplete copy, which is deleted on
+//! startup by [delete_future_dirs].
+
+use crate::storage::internals::log::unified_log::UnifiedLog;
+use crate::storage::internals::log::{LogError, Result};
+use rafka_clients::common::topic_partition::TopicPartition;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// The suffix of the directory of a future replica.
+pub const FUTURE_DIR_SUFFIX: &str = "-future";
+
+/// The default of how many bytes a move copies per partition in each round, so that a large
+/// replica does not hold up the moves of the others.
+pub const DEFAULT_COPY_MAX_BYTES: usize = 1024 * 1024;
+
+/// The directory, in `log_dir`, of the future replica of `topic_partition`.
+pub fn future_dir(log_dir: &Path, topic_partition: &TopicPartition) -> PathBuf {
+    log_dir.join(format!("{topic_partition}{FUTURE_DIR_SUFFIX}"))
+}
+
+/// Deletes the future replicas of moves interrupted by a shutdown from `log_dir`, returning
+/// their directories.
+pub fn delete_future_dirs(log_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut deleted = Vec::new();
+    for entry in fs::read_dir(log_dir)? {
+        let path = entry?.path();
+        let is_future = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(FUTURE_DIR_SUFFIX));
+        if path.is_dir() && is_future {
+            info!(
+                "Deleting the future replica {} of an interrupted move",
+                path.display()
+            );
+            fs::remove_dir_all(&path)?;
+            deleted.push(path);
+        }
+    }
+    Ok(deleted)
+}
+
+/// A replica being copied to another log directory.
+struct FutureReplica {
+    log_dir: PathBuf,
+    log: UnifiedLog,
+}
+
+/// Tracks the moves of replicas between the log directories of a broker and carries them out.
+pub struct ReplicaLogDirMover {
+    log_dirs: Vec<PathBuf>,
+    copy_max_bytes: usize,
+    futures: BTreeMap<TopicPartition, FutureReplica>,
+}
+
+impl ReplicaLogDirMover {
+    /// A mover between `log_dirs`, the log directories of the broker.
+    pub fn new(log_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            log_dirs,
+            copy_max_bytes: DEFAULT_COPY_MAX_BYTES,
+            futures: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_copy_max_bytes(mut self, copy_max_bytes: usize) -> Self {
+        self.copy_max_bytes = copy_max_bytes;
+        self
+    }
+
+    /// The log directory the replica of `topic_partition` is being moved to, if any.
+    pub fn future_log_dir(&self, topic_partition: &TopicPartition) -> Option<&Path> {
+        self.futures
+            .get(topic_partition)
+            .map(|future| future.log_dir.as_path())
+    }
+
+    /// Starts moving the replicas of `logs` to the log directories of `assignments`,
+    /// replying with the outcome for each partition: the broker must host a replica of the
+    /// partition and the destination must be one of its log directories.
+    ///
+    /// Assigning the directory a replica is in cancels its move, and assigning another
+    /// directory restarts it there.
+    pub fn alter_replica_log_dirs(
+        &mut self,
+        logs: &BTreeMap<TopicPartition, UnifiedLog>,
+        assignments: &BTreeMap<TopicPartition, PathBuf>,
+    ) -> BTreeMap<TopicPartition, Result<()>> {
+        assignments
+            .iter()
+            .map(|(topic_partition, log_dir)| {
+                let result =
+                    self.alter_replica_log_dir(logs.get(topic_partition), topic_partition, log_dir);
+                (topic_partition.clone(), result)
+            })
+            .collect()
+    }
+
+    fn alter_replica_log_dir(
+        &mut self,
+        log: Option<&UnifiedLog>,
+        topic_partition: &TopicPartition,
+        log_dir: &Path,
+    ) -> Result<()> {
+        if !self.log_dirs.iter().any(|dir| dir == log_dir) {
+            return Err(LogError::LogDirNotFound(log_dir.to_path_buf()));
+        }
+        let log = log.ok_or_else(|| LogError::ReplicaNotAvailable(topic_partition.clone()))?;
+        if log.dir().parent() == Some(log_dir) {
+            return self.cancel(topic_partition);
+        }
+        if self.future_log_dir(topic_partition) == Some(log_dir) {
+            return Ok(());
+        }
+        self.cancel(topic_partition)?;
+        let dir = future_dir(log_dir, topic_partition);
+        info!(
+            "Moving the replica of {topic_partition} from {} to {}",
+            log.dir().display(),
+            dir.display()
+        );
+        let future = UnifiedLog::load_as(
+            &dir,
+            topic_partition.clone(),
+            log.config().clone(),
+            log.local_log_start_offset(),
+            false,
+        )?;
+        self.futures.insert(
+            topic_partition.clone(),
+            FutureReplica {
+                log_dir: log_dir.to_path_buf(),
+                log: future,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stops moving the replica of `topic_partition`, deleting its future replica.
+    pub fn cancel(&mut self, topic_partition: &TopicPartition) -> Result<()> {
+        if let Some(future) = self.futures.remove(topic_partition) {
+            let dir = future.log.dir().to_path_buf();
+            drop(future);
+            info!("Cancelled the move of the replica of {topic_partition}");
+            fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a round of the moves: copies up to `copy.max.bytes` of each replica being moved
+    /// to its future replica, and swaps the future replicas which caught up into `logs`.
+    ///
+    /// Replies with the outcome of each move, `true` if the replica moved. A move which
+    /// failed stays in progress and is retried in the next round; the move of a replica which
+    /// is no longer hosted is cancelled.
+    pub fn copy_and_swap(
+        &mut self,
+        logs: &mut BTreeMap<TopicPartition, UnifiedLog>,
+    ) -> BTreeMap<TopicPartition, Result<bool>> {
+        let partitions: Vec<TopicPartition> = self.futures.keys().cloned().collect();
+        let mut results = BTreeMap::new();
+        for topic_partition in partitions {
+            let Some(log) = logs.get(&topic_partition) else {
+                let _ = self.cancel(&topic_partition);
+                continue;
+            };
+            let result = self.copy(&topic_partition, log).and_then(|caught_up| {
+                if caught_up {
+                    self.swap(&topic_partition, logs)?;
+                }
+                Ok(caught_up)
+            });
+            results.insert(topic_partition, result);
+        }
+        results
+    }
+
+    /// Copies the next records of `log` to its future replica, returning whether the future
+    /// replica caught up.
+    fn copy(&mut self, topic_partition: &TopicPartition, log: &UnifiedLog) -> Result<bool> {
+        let future = &mut self
+            .futures
+            .get_mut(topic_partition)
+            .expect("the replica is being moved")
+            .log;
+        // The current replica truncated its log, e.g. after a leader change.
+        future.truncate_to(log.log_end_offset())?;
+        let mut copied = 0;
+        while copied < self.copy_max_bytes && future.log_end_offset() < log.log_end_offset() {
+            let batches = log.read(future.log_end_offset(), self.copy_max_bytes - copied)?;
+            if batches.is_empty() {
+                break;
+            }
+            for batch in batches {
+                copied += batch.size_in_bytes();
+                future.append_as_follower(batch)?;
+            }
+        }
+        Ok(future.log_end_offset() == log.log_end_offset())
+    }
+
+    /// Replaces the replica of `topic_partition` in `logs` with its future replica, which
+    /// caught up, and deletes the former.
+    fn swap(
+        &mut self,
+        topic_partition: &TopicPartition,
+        logs: &mut BTreeMap<TopicPartition, UnifiedLog>,
+    ) -> Result<()> {
+        let future = &self.futures[topic_partition];
+        let log = &logs[topic_partition];
+        future.log.flush()?;
+        log.flush()?;
+        let future_dir = future.log.dir().to_path_buf();
+        let source_dir = log.dir().to_path_buf();
+        let dir = future.log_dir.join(topic_partition.to_string());
+        let config = log.config().clone();
+        let log_start_offset = log.log_start_offset();
+
+        // Close both logs before their directories are renamed.
+        self.futures.remove(topic_partition);
+        logs.remove(topic_partition);
+        if let Err(e) = fs::rename(&future_dir, &dir) {
+            logs.insert(
+                topic_partition.clone(),
+                UnifiedLog::load(&source_dir, config, log_start_offset, true)?,
+            );
+            let _ = fs::remove_dir_all(&future_dir);
+            return Err(e.into());
+        }
+        logs.insert(
+            topic_partition.clone(),
+            UnifiedLog::load(&dir, config, log_start_offset, true)?,
+        );
+        info!(
+            "Moved the replica of {topic_partition} from {} to {}",
+            source_dir.display(),
+            dir.display()
+        );
+        if let Err(e) = fs::remove_dir_all(&source_dir) {
+            warn!(
+                "Failed to delete {} after moving its replica: {e}",
+                source_dir.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::append_origin::AppendOrigin;
+    use crate::storage::internals::log::unified_log::UnifiedLogConfig;
+    use rafka_clients::common::record::record_batch::{Record, RecordBatch};
+
+    fn append(log: &mut UnifiedLog, count: usize) {
+        for _ in 0..count {
+            let batch = RecordBatch::new(0, vec![Record::new(0, None, Some(&[0; 100]))]);
+            log.append_as_leader(batch, 3, AppendOrigin::Client)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_move_replica_to_another_log_dir() {
+        let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let (source, destination) = (dirs[0].path(), dirs[1].path());
+        let foo = TopicPartition::new("foo", 0);
+        let mut log =
+            UnifiedLog::open(&source.join("foo-0"), UnifiedLogConfig::default(), 0).unwrap();
+        append(&mut log, 5);
+        let mut logs = BTreeMap::from([(foo.clone(), log)]);
+        let mut mover = ReplicaLogDirMover::new(vec![source.into(), destination.into()])
+            .with_copy_max_bytes(300);
+
+        let results = mover.alter_replica_log_dirs(
+            &logs,
+            &BTreeMap::from([(foo.clone(), destination.to_path_buf())]),
+        );
+        assert!(results[&foo].is_ok());
+        assert_eq!(Some(destination), mover.future_log_dir(&foo));
+        assert!(future_dir(destination, &foo).is_dir());
+
+        // The replica keeps taking records while it is copied.
+        assert!(!mover.copy_and_swap(&mut logs)[&foo].as_ref().unwrap());
+        append(logs.get_mut(&foo).unwrap(), 1);
+        while !*mover.copy_and_swap(&mut logs)[&foo].as_ref().unwrap() {}
+
+        let log = &logs[&foo];
+        assert_eq!(destination.join("foo-0"), log.dir());
+        assert_eq!(6, log.log_end_offset());
+        assert_eq!(Some(3), log.latest_epoch());
+        assert!(!source.join("foo-0").exists());
+        assert!(!future_dir(destination, &foo).exists());
+        assert_eq!(None, mover.future_log_dir(&foo));
+    }
+
+    #[test]
+    fn test_invalid_and_cancelled_moves() {
+        let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let (source, destination) = (dirs[0].path(), dirs[1].path());
+        let foo = TopicPartition::new("foo", 0);
+        let bar = TopicPartition::new("bar", 0);
+        let log = UnifiedLog::open(&source.join("foo-0"), UnifiedLogConfig::default(), 0).unwrap();
+        let logs = BTreeMap::from([(foo.clone(), log)]);
+        let mut mover = ReplicaLogDirMover::new(vec![source.into(), destination.into()]);
+
+        let results = mover.alter_replica_log_dirs(
+            &logs,
+            &BTreeMap::from([
+                (foo.clone(), PathBuf::from("/unknown")),
+                (bar.clone(), destination.to_path_buf()),
+            ]),
+        );
+        assert!(matches!(results[&foo], Err(LogError::LogDirNotFound(_))));
+        assert!(matches!(
+            results[&bar],
+            Err(LogError::ReplicaNotAvailable(_))
+        ));
+        assert_eq!(None, mover.future_log_dir(&foo));
+
+        // Assigning the current directory cancels the move.
+        let assign = |dir: &Path| BTreeMap::from([(foo.clone(), dir.to_path_buf())]);
+        assert!(mover.alter_replica_log_dirs(&logs, &assign(destination))[&foo].is_ok());
+        assert!(mover.alter_replica_log_dirs(&logs, &assign(source))[&foo].is_ok());
+        assert_eq!(None, mover.future_log_dir(&foo));
+        assert!(!future_dir(destination, &foo).exists());
+
+        // A move interrupted by a shutdown is dropped on startup.
+        mover.alter_replica_log_dirs(&logs, &assign(destination));
+        drop(mover);
+        assert_eq!(
+            vec![future_dir(destination, &foo)],
+            delete_future_dirs(destination).unwrap()
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Keeps the most recently appended batches of a log in memory.
+//!
+//! The consumers which keep up with a partition all fetch its tail, so a topic read by many
+//! consumer groups reads the same few batches from its active segment again and again. The
+//! cache serves those fetches from memory. It holds a contiguous run of batches ending at the
+//! log end offset, within `tail.cache.bytes` for the log and, if the logs share one, within a
+//! [TailCacheMemory] for the whole broker: the oldest batches are evicted to make room, and a
+//! batch which does not fit at all empties the cache, which the fetches then bypass until it
+//! fills again.
+
+use rafka_clients::common::record::record_batch::RecordBatch;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The memory the tail caches of the logs of a broker share.
+#[derive(Debug)]
+pub struct TailCacheMemory {
+    max_bytes: usize,
+    used_bytes: AtomicUsize,
+}
+
+impl TailCacheMemory {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            used_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Takes `bytes` of the memory, returning false if not that many are left.
+    fn try_reserve(&self, bytes: usize) -> bool {
+        self.used_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes)
+                    .filter(|used| *used <= self.max_bytes)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+/// The most recently appended batches of a log.
+#[derive(Debug)]
+pub struct TailBatchCache {
+    max_bytes: usize,
+    bytes: usize,
+    /// The batches in offset order, with their encoded size.
+    batches: VecDeque<(RecordBatch, usize)>,
+    memory: Option<Arc<TailCacheMemory>>,
+}
+
+impl TailBatchCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            bytes: 0,
+            batches: VecDeque::new(),
+            memory: None,
+        }
+    }
+
+    /// Bounds the cache by `memory` too, shared with the caches of other logs.
+    pub fn with_memory(mut self, memory: Arc<TailCacheMemory>) -> Self {
+        self.clear();
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Changes the size of the cache, evicting the oldest batches beyond it.
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+        while self.bytes > self.max_bytes {
+            self.evict_oldest();
+        }
+    }
+
+    /// The size of the cached batches.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    /// Caches `batch`, of `size` bytes once encoded, just appended to the log, evicting the
+    /// oldest batches to make room for it.
+    pub fn append(&mut self, batch: &RecordBatch, size: usize) {
+        if size > self.max_bytes {
+            self.clear();
+            return;
+        }
+        while self.bytes + size > self.max_bytes {
+            self.evict_oldest();
+        }
+        if let Some(memory) = self.memory.clone() {
+            while !memory.try_reserve(size) {
+                if self.batches.is_empty() {
+                    // The other logs hold the memory: the batch can't be cached.
+                    return;
+                }
+                self.evict_oldest();
+            }
+        }
+        self.bytes += size;
+        self.batches.push_back((batch.clone(), size));
+    }
+
+    /// Reads the batches holding offsets from `start_offset` on, up to `max_bytes` but at
+    /// least one, as [LogSegment::read](super::log_segment::LogSegment::read) does, or `None`
+    /// if `start_offset` is before the cached batches.
+    pub fn read(&self, start_offset: i64, max_bytes: usize) -> Option<Vec<RecordBatch>> {
+        let (first, _) = self.batches.front()?;
+        if start_offset < first.base_offset() {
+            return None;
+        }
+        let mut batches = Vec::new();
+        let mut bytes = 0;
+        for (batch, size) in self
+            .batches
+            .iter()
+            .skip_while(|(batch, _)| batch.last_offset() < start_offset)
+        {
+            if !batches.is_empty() && bytes + size > max_bytes {
+                break;
+            }
+            batches.push(batch.clone());
+            bytes += size;
+        }
+        Some(batches)
+    }
+
+    /// Drops the batches holding offsets at `offset` and above, which the log truncated.
+    pub fn truncate_to(&mut self, offset: i64) {
+        while self
+            .batches
+            .back()
+            .is_some_and(|(batch, _)| batch.last_offset() >= offset)
+        {
+            let (_, size) = self.batches.pop_back().expect("a batch was just seen");
+            self.forget(size);
+        }
+    }
+
+    /// Drops the batches entirely below `offset`, which the log deleted.
+    pub fn evict_below(&mut self, offset: i64) {
+        while self
+            .batches
+            .front()
+            .is_some_and(|(batch, _)| batch.last_offset() < offset)
+        {
+            self.evict_oldest();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        while !self.batches.is_empty() {
+            self.evict_oldest();
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some((_, size)) = self.batches.pop_front() {
+            self.forget(size);
+        }
+    }
+
+    fn forget(&mut self, size: usize) {
+        self.bytes -= size;
+        if let Some(memory) = &self.memory {
+            memory.release(size);
+        }
+    }
+}
+
+impl Drop for TailBatchCache {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rafka_clients::common::record::record_batch::Record;
+
+    /// A batch of a single record at `offset`, and its size.
+    fn new_batch(offset: i64) -> (RecordBatch, usize) {
+        let mut batch = RecordBatch::new(0, vec![Record::new(0, None, Some(&[0; 100]))]);
+        batch.set_base_offset(offset);
+        let size = batch.size_in_bytes();
+        (batch, size)
+    }
+
+    fn offsets(batches: Option<Vec<RecordBatch>>) -> Option<Vec<i64>> {
+        batches.map(|batches| batches.iter().map(RecordBatch::base_offset).collect())
+    }
+
+    #[test]
+    fn test_cache_keeps_the_tail_within_its_bounds() {
+        let size = new_batch(0).1;
+        let mut cache = TailBatchCache::new(size * 3);
+        for offset in 0..5 {
+            let (batch, size) = new_batch(offset);
+            cache.append(&batch, size);
+        }
+        assert_eq!(3, cache.len());
+        assert_eq!(None, offsets(cache.read(1, usize::MAX)));
+        assert_eq!(Some(vec![2, 3, 4]), offsets(cache.read(2, usize::MAX)));
+        // At least one batch is returned, however small max_bytes is.
+        assert_eq!(Some(vec![3]), offsets(cache.read(3, 1)));
+        assert_eq!(Some(vec![]), offsets(cache.read(5, usize::MAX)));
+
+        cache.truncate_to(4);
+        assert_eq!(Some(vec![2, 3]), offsets(cache.read(2, usize::MAX)));
+        cache.evict_below(3);
+        assert_eq!(None, offsets(cache.read(2, usize::MAX)));
+
+        // Shrinking the cache evicts the oldest batches.
+        let (batch, _) = new_batch(4);
+        cache.append(&batch, size);
+        cache.set_max_bytes(size);
+        assert_eq!(Some(vec![4]), offsets(cache.read(4, usize::MAX)));
+        assert_eq!(None, offsets(cache.read(3, usize::MAX)));
+
+        // A batch larger than the cache empties it, so that it stays contiguous.
+        let (batch, _) = new_batch(5);
+        cache.append(&batch, size * 4);
+        assert!(cache.is_empty());
+        assert_eq!(0, cache.bytes());
+    }
+
+    #[test]
+    fn test_caches_share_their_memory() {
+        let size = new_batch(0).1;
+        let memory = Arc::new(TailCacheMemory::new(size * 3));
+        let mut first = TailBatchCache::new(size * 10).with_memory(Arc::clone(&memory));
+        let mut second = TailBatchCache::new(size * 10).with_memory(Arc::clone(&memory));
+        for offset in 0..3 {
+            let (batch, size) = new_batch(offset);
+            first.append(&batch, size);
+        }
+        assert_eq!(size * 3, memory.used_bytes());
+
+        // The first cache holds all the memory.
+        let (batch, _) = new_batch(0);
+        second.append(&batch, size);
+        assert!(second.is_empty());
+
+        // Its own batches are evicted to make room.
+        let (batch, _) = new_batch(3);
+        first.append(&batch, size);
+        assert_eq!(Some(vec![1, 2, 3]), offsets(first.read(1, usize::MAX)));
+        assert_eq!(size * 3, memory.used_bytes());
+
+        drop(first);
+        assert_eq!(0, memory.used_bytes());
+        second.append(&new_batch(0).0, size);
+        assert_eq!(1, second.len());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::storage::internals::log::{LogError, Result};
+use rafka_clients::common::record::record_batch::NO_TIMESTAMP;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The size of an entry: the timestamp and the offset relative to the base offset.
+const ENTRY_SIZE: usize = 12;
+
+pub const TIME_INDEX_FILE_SUFFIX: &str = "timeindex";
+
+/// A timestamp and the offset of the record carrying it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampOffset {
+    pub timestamp: i64,
+    pub offset: i64,
+}
+
+impl TimestampOffset {
+    pub fn new(timestamp: i64, offset: i64) -> Self {
+        Self { timestamp, offset }
+    }
+}
+
+/// Maps timestamps to offsets of a segment.
+///
+/// Each entry holds the max timestamp seen so far in the segment and the offset of the record
+/// carrying it, so the timestamps of the entries increase monotonically. An entry is added
+/// every `index.interval.bytes` of appended data, when the max timestamp grew. The entries are
+/// kept in memory and persisted in a `.timeindex` file next to the segment.
+#[derive(Debug)]
+pub struct TimeIndex {
+    path: PathBuf,
+    file: File,
+    base_offset: i64,
+    entries: Vec<TimestampOffset>,
+}
+
+impl TimeIndex {
+    /// Opens the index at `path`, creating it if it doesn't exist. A partially written
+    /// entry at the end of the file is discarded.
+    pub fn open(path: &Path, base_offset: i64) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let valid = data.len() - data.len() % ENTRY_SIZE;
+        if valid != data.len() {
+            file.set_len(valid as u64)?;
+        }
+
+        let mut entries: Vec<TimestampOffset> = Vec::with_capacity(valid / ENTRY_SIZE);
+        for entry in data[..valid].chunks_exact(ENTRY_SIZE) {
+            let timestamp = i64::from_be_bytes(entry[..8].try_into().expect("8 bytes"));
+            let relative_offset = i32::from_be_bytes(entry[8..].try_into().expect("4 bytes"));
+            let entry = TimestampOffset::new(timestamp, base_offset + relative_offset as i64);
+            if let Some(last) = entries.last()
+                && (entry.timestamp < last.timestamp || entry.offset < last.offset)
+            {
+                return Err(LogError::CorruptIndex {
+                    path: path.to_path_buf(),
+                    reason: format!("entry {entry:?} is smaller than the previous entry {last:?}"),
+                });
+            }
+            entries.push(entry);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            base_offset,
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The last entry, or the base offset with no timestamp if the index is empty.
+    pub fn last_entry(&self) -> TimestampOffset {
+        self.entries
+            .last()
+            .copied()
+            .unwrap_or(TimestampOffset::new(NO_TIMESTAMP, self.base_offset))
+    }
+
+    /// Appends an entry if `timestamp` is larger than the timestamp of the last entry.
+    pub fn maybe_append(&mut self, timestamp: i64, offset: i64) -> Result<()> {
+        let last = self.last_entry();
+        if timestamp <= last.timestamp {
+            return Ok(());
+        }
+        let mut entry = [0u8; ENTRY_SIZE];
+        entry[..8].copy_from_slice(&timestamp.to_be_bytes());
+        entry[8..].copy_from_slice(&((offset - self.base_offset) as i32).to_be_bytes());
+        self.file.write_all(&entry)?;
+        self.entries.push(TimestampOffset::new(timestamp, offset));
+        Ok(())
+    }
+
+    /// The last entry whose timestamp is at most `timestamp`, or the base offset with no
+    /// timestamp if every entry is larger.
+    pub fn lookup(&self, timestamp: i64) -> TimestampOffset {
+        let pos = self.entries.partition_point(|e| e.timestamp <= timestamp);
+        match pos {
+            0 => TimestampOffset::new(NO_TIMESTAMP, self.base_offset),
+            pos => self.entries[pos - 1],
+        }
+    }
+
+    /// The last entry pointing at `offset` or below and the first entry pointing above it, if
+    /// any.
+    pub fn entries_around(
+        &self,
+        offset: i64,
+    ) -> (Option<TimestampOffset>, Option<TimestampOffset>) {
+        let pos = self.entries.partition_point(|e| e.offset <= offset);
+        let before = pos.checked_sub(1).map(|pos| self.entries[pos]);
+        (before, self.entries.get(pos).copied())
+    }
+
+    /// Removes the entries pointing at `offset` or above.
+    pub fn truncate_to(&mut self, offset: i64) -> Result<()> {
+        let keep = self.entries.partition_point(|e| e.offset < offset);
+        if keep < self.entries.len() {
+            self.entries.truncate(keep);
+            self.file.set_len((keep * ENTRY_SIZE) as u64)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    pub fn delete(self) -> Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_lookup_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("00000000000000000100.timeindex");
+        let mut index = TimeIndex::open(&path, 100).unwrap();
+        assert_eq!(TimestampOffset::new(NO_TIMESTAMP, 100), index.lookup(1_000));
+
+        index.maybe_append(1_000, 105).unwrap();
+        index.maybe_append(900, 107).unwrap();
+        index.maybe_append(2_000, 110).unwrap();
+        assert_eq!(2, index.entries());
+        assert_eq!(TimestampOffset::new(NO_TIMESTAMP, 100), index.lookup(999));
+        assert_eq!(TimestampOffset::new(1_000, 105), index.lookup(1_500));
+        assert_eq!(TimestampOffset::new(2_000, 110), index.lookup(5_000));
+        assert_eq!(
+            (None, Some(TimestampOffset::new(1_000, 105))),
+            index.entries_around(104)
+        );
+        assert_eq!(
+            (
+                Some(TimestampOffset::new(1_000, 105)),
+                Some(TimestampOffset::new(2_000, 110))
+            ),
+            index.entries_around(107)
+        );
+        assert_eq!(
+            (Some(TimestampOffset::new(2_000, 110)), None),
+            index.entries_around(110)
+        );
+
+        drop(index);
+        let mut index = TimeIndex::open(&path, 100).unwrap();
+        assert_eq!(TimestampOffset::new(2_000, 110), index.last_entry());
+        index.truncate_to(110).unwrap();
+        assert_eq!(TimestampOffset::new(1_000, 105), index.last_entry());
+        drop(index);
+        assert_eq!(1, TimeIndex::open(&path, 100).unwrap().entries());
+    }
+}
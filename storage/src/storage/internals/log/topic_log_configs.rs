@@ -0,0 +1,177 @@
+use crate::storage::internals::log::Result;
+use crate::storage::internals::log::unified_log::UnifiedLogConfig;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::{info, warn};
+
+/// The [UnifiedLogConfig] of every topic: the log configs of the broker, overridden by the
+/// dynamic configs of the topic.
+///
+/// The overrides are updated as the configs of the topics change in the metadata log, and
+/// read by the [UnifiedLogFactory](super::partition_log::UnifiedLogFactory) whenever it
+/// opens a log, so that a log is opened with the current configs of its topic. The logs already
+/// open are updated by whoever holds them, with
+/// [UnifiedLog::update_config](super::unified_log::UnifiedLog::update_config). An invalid
+/// override is rejected, and the topic keeps its previous value.
+#[derive(Debug, Default)]
+pub struct TopicLogConfigs {
+    defaults: UnifiedLogConfig,
+    overrides: RwLock<HashMap<String, BTreeMap<String, String>>>,
+}
+
+impl TopicLogConfigs {
+    pub fn new(defaults: UnifiedLogConfig) -> Self {
+        Self {
+            defaults,
+            overrides: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn overrides(&self) -> RwLockReadGuard<'_, HashMap<String, BTreeMap<String, String>>> {
+        self.overrides.read().expect("topic configs lock poisoned")
+    }
+
+    fn overrides_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, BTreeMap<String, String>>> {
+        self.overrides.write().expect("topic configs lock poisoned")
+    }
+
+    /// The log configs of the broker.
+    pub fn defaults(&self) -> &UnifiedLogConfig {
+        &self.defaults
+    }
+
+    /// The configs of the logs of `topic`.
+    pub fn config(&self, topic: &str) -> UnifiedLogConfig {
+        let config = self.defaults.clone();
+        match self.overrides().get(topic) {
+            Some(overrides) => apply(config, overrides)
+                .expect("the overrides of a topic are validated when they are set"),
+            None => config,
+        }
+    }
+
+    /// Sets the override `name` of `topic` to `value`, or removes it if `value` is `None`.
+    pub fn set(&self, topic: &str, name: &str, value: Option<&str>) -> Result<()> {
+        let mut overrides = self.overrides_mut();
+        match value {
+            Some(value) => {
+                self.defaults.clone().with_override(name, value)?;
+                info!("Set the log config {name} of topic {topic} to {value}");
+                overrides
+                    .entry(topic.to_string())
+                    .or_default()
+                    .insert(name.to_string(), value.to_string());
+            }
+            None => {
+                if let Some(topic_overrides) = overrides.get_mut(topic) {
+                    topic_overrides.remove(name);
+                    if topic_overrides.is_empty() {
+                        overrides.remove(topic);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces every override with `overrides`, by topic, skipping the invalid ones.
+    pub fn replace_all(&self, overrides: HashMap<String, BTreeMap<String, String>>) {
+        let overrides = overrides
+            .into_iter()
+            .map(|(topic, mut configs)| {
+                configs.retain(|name, value| {
+                    match self.defaults.clone().with_override(name, value) {
+                        Ok(_) => true,
+                        Err(e) => {
+                            warn!("Skipping the log config of topic {topic}: {e}");
+                            false
+                        }
+                    }
+                });
+                (topic, configs)
+            })
+            .filter(|(_, configs)| !configs.is_empty())
+            .collect();
+        *self.overrides_mut() = overrides;
+    }
+
+    /// The overrides of `topic`.
+    pub fn overrides_of(&self, topic: &str) -> Option<BTreeMap<String, String>> {
+        self.overrides().get(topic).cloned()
+    }
+
+    /// The topics with overrides.
+    pub fn topics(&self) -> Vec<String> {
+        self.overrides().keys().cloned().collect()
+    }
+}
+
+fn apply(
+    mut config: UnifiedLogConfig,
+    overrides: &BTreeMap<String, String>,
+) -> Result<UnifiedLogConfig> {
+    for (name, value) in overrides {
+        config = config.with_override(name, value)?;
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::LogError;
+    use rafka_clients::common::config::topic_config::{
+        CLEANUP_POLICY_CONFIG, MAX_MESSAGE_BYTES_CONFIG, MESSAGE_TIMESTAMP_TYPE_CONFIG,
+        RETENTION_MS_CONFIG, SEGMENT_BYTES_CONFIG,
+    };
+    use rafka_clients::common::record::record_batch::TimestampType;
+
+    #[test]
+    fn test_topic_overrides() {
+        let configs = TopicLogConfigs::default();
+        configs
+            .set("foo", SEGMENT_BYTES_CONFIG, Some("1024"))
+            .unwrap();
+        configs
+            .set("foo", MESSAGE_TIMESTAMP_TYPE_CONFIG, Some("LogAppendTime"))
+            .unwrap();
+        configs.set("foo", RETENTION_MS_CONFIG, Some("10")).unwrap();
+        // Not a log config.
+        configs
+            .set("foo", CLEANUP_POLICY_CONFIG, Some("compact"))
+            .unwrap();
+        let foo = configs.config("foo");
+        assert_eq!(1024, foo.segment_bytes);
+        assert_eq!(TimestampType::LogAppendTime, foo.message_timestamp_type);
+        assert_eq!(10, foo.retention_ms);
+        assert_eq!(vec!["foo".to_string()], configs.topics());
+        assert_eq!(UnifiedLogConfig::default(), configs.config("bar"));
+
+        // An invalid value leaves the topic alone.
+        assert!(matches!(
+            configs.set("foo", SEGMENT_BYTES_CONFIG, Some("big")),
+            Err(LogError::InvalidConfig { .. })
+        ));
+        assert_eq!(1024, configs.config("foo").segment_bytes);
+
+        configs.set("foo", SEGMENT_BYTES_CONFIG, None).unwrap();
+        assert_eq!(
+            UnifiedLogConfig::default().segment_bytes,
+            configs.config("foo").segment_bytes
+        );
+
+        configs.replace_all(HashMap::from([(
+            "bar".to_string(),
+            BTreeMap::from([
+                (MAX_MESSAGE_BYTES_CONFIG.to_string(), "100".to_string()),
+                (SEGMENT_BYTES_CONFIG.to_string(), "-1".to_string()),
+            ]),
+        )]));
+        assert_eq!(None, configs.overrides_of("foo"));
+        assert_eq!(100, configs.config("bar").max_message_bytes);
+        assert_eq!(
+            UnifiedLogConfig::default().segment_bytes,
+            configs.config("bar").segment_bytes
+        );
+    }
+}
@@ -0,0 +1,1597 @@
+use crate::storage::internals::epoch::leader_epoch_file_cache::LeaderEpochFileCache;
+use crate::storage::internals::log::append_origin::AppendOrigin;
+use crate::storage::internals::log::disk_space_monitor::LogDirSpace;
+use crate::storage::internals::log::log_segment::{
+    LOG_FILE_SUFFIX, LogSegment, TimestampAndOffset,
+};
+use crate::storage::internals::log::ongoing_transactions::{
+    AbortedTransaction, AbortedTransactions, OngoingTransactions,
+};
+use crate::storage::internals::log::remote_log_reader::RemoteLogReader;
+use crate::storage::internals::log::tail_batch_cache::{TailBatchCache, TailCacheMemory};
+use crate::storage::internals::log::{LogError, Result};
+use rafka_clients::common::config::topic_config;
+use rafka_clients::common::internals::topic;
+use rafka_clients::common::record::control_record::EndTransactionMarker;
+use rafka_clients::common::record::record_batch::{
+    NO_PRODUCER_ID, NO_TIMESTAMP, RecordBatch, RecordError, TimestampType,
+};
+use rafka_clients::common::requests::list_offsets_request::{
+    EARLIEST_LOCAL_TIMESTAMP, EARLIEST_TIMESTAMP, LATEST_TIERED_TIMESTAMP, LATEST_TIMESTAMP,
+    MAX_TIMESTAMP,
+};
+use rafka_clients::common::topic_partition::TopicPartition;
+use rafka_clients::common::utils::time::{SystemTime, Time};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::info;
+
+/// The topic configs a log is created with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnifiedLogConfig {
+    /// `segment.bytes`: the size at which a new segment is rolled.
+    pub segment_bytes: usize,
+    /// `index.interval.bytes`: how many bytes are appended between two time index entries.
+    pub index_interval_bytes: usize,
+    /// `remote.storage.enable`: whether the segments are copied to remote storage.
+    pub remote_storage_enable: bool,
+    /// `max.message.bytes`: the largest batch, once encoded, the leader accepts.
+    pub max_message_bytes: usize,
+    /// `message.timestamp.type`: whether the records keep the timestamps of the producers or
+    /// are stamped with the time the leader appends them.
+    pub message_timestamp_type: TimestampType,
+    /// `tail.cache.bytes`: how many bytes of the most recently appended batches are kept in
+    /// memory to serve the fetches at the tail of the log, see [TailBatchCache]. 0 disables
+    /// the cache.
+    pub tail_cache_bytes: usize,
+    /// `retention.ms`: how long a segment is kept after its last record, -1 for ever.
+    pub retention_ms: i64,
+    /// `retention.bytes`: the size the log is kept under by deleting its oldest segments, -1
+    /// for no limit.
+    pub retention_bytes: i64,
+    /// `local.retention.ms`: [retention_ms](Self::retention_ms) for the local segments when
+    /// the segments are copied to remote storage, -2 for the same as `retention.ms`.
+    pub local_retention_ms: i64,
+    /// `local.retention.bytes`: [retention_bytes](Self::retention_bytes) for the local
+    /// segments when the segments are copied to remote storage, -2 for the same as
+    /// `retention.bytes`.
+    pub local_retention_bytes: i64,
+}
+
+/// The topic config of [UnifiedLogConfig::tail_cache_bytes].
+pub const TAIL_CACHE_BYTES_CONFIG: &str = "tail.cache.bytes";
+
+/// The default `max.message.bytes`: 1 MiB of records plus the overhead of the batch.
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 1024 * 1024 + 12;
+
+/// The default `retention.ms`: 7 days.
+pub const DEFAULT_RETENTION_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+/// The `local.retention.ms` and `local.retention.bytes` deferring to `retention.ms` and
+/// `retention.bytes`.
+pub const LOCAL_RETENTION_AS_RETENTION: i64 = -2;
+
+impl Default for UnifiedLogConfig {
+    fn default() -> Self {
+        Self {
+            segment_bytes: 1024 * 1024 * 1024,
+            index_interval_bytes: 4096,
+            remote_storage_enable: false,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            message_timestamp_type: TimestampType::CreateTime,
+            tail_cache_bytes: 0,
+            retention_ms: DEFAULT_RETENTION_MS,
+            retention_bytes: -1,
+            local_retention_ms: LOCAL_RETENTION_AS_RETENTION,
+            local_retention_bytes: LOCAL_RETENTION_AS_RETENTION,
+        }
+    }
+}
+
+impl UnifiedLogConfig {
+    /// The config with the topic config `name` set to `value`. The topic configs which are
+    /// not log configs, e.g. `cleanup.policy`, are ignored.
+    pub fn with_override(mut self, name: &str, value: &str) -> Result<Self> {
+        let invalid = || LogError::InvalidConfig {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        let bytes = || value.trim().parse::<usize>().map_err(|_| invalid());
+        let at_least = |min: i64| match value.trim().parse::<i64>() {
+            Ok(value) if value >= min => Ok(value),
+            _ => Err(invalid()),
+        };
+        match name {
+            topic_config::SEGMENT_BYTES_CONFIG => self.segment_bytes = bytes()?,
+            topic_config::INDEX_INTERVAL_BYTES_CONFIG => self.index_interval_bytes = bytes()?,
+            topic_config::REMOTE_LOG_STORAGE_ENABLE_CONFIG => {
+                self.remote_storage_enable = value.trim().parse().map_err(|_| invalid())?
+            }
+            topic_config::MAX_MESSAGE_BYTES_CONFIG => self.max_message_bytes = bytes()?,
+            topic_config::MESSAGE_TIMESTAMP_TYPE_CONFIG => {
+                self.message_timestamp_type = TimestampType::for_name(value).ok_or_else(invalid)?
+            }
+            TAIL_CACHE_BYTES_CONFIG => self.tail_cache_bytes = bytes()?,
+            topic_config::RETENTION_MS_CONFIG => self.retention_ms = at_least(-1)?,
+            topic_config::RETENTION_BYTES_CONFIG => self.retention_bytes = at_least(-1)?,
+            topic_config::LOCAL_LOG_RETENTION_MS_CONFIG => {
+                self.local_retention_ms = at_least(LOCAL_RETENTION_AS_RETENTION)?
+            }
+            topic_config::LOCAL_LOG_RETENTION_BYTES_CONFIG => {
+                self.local_retention_bytes = at_least(LOCAL_RETENTION_AS_RETENTION)?
+            }
+            _ => {}
+        }
+        Ok(self)
+    }
+
+    /// The `retention.ms` and `retention.bytes` the local segments are deleted after: the
+    /// local ones if the segments are copied to remote storage.
+    pub fn local_retention(&self) -> (i64, i64) {
+        if !self.remote_storage_enable {
+            return (self.retention_ms, self.retention_bytes);
+        }
+        let local = |local, retention| match local {
+            LOCAL_RETENTION_AS_RETENTION => retention,
+            local => local,
+        };
+        (
+            local(self.local_retention_ms, self.retention_ms),
+            local(self.local_retention_bytes, self.retention_bytes),
+        )
+    }
+}
+
+/// What a successful append wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogAppendInfo {
+    pub first_offset: i64,
+    pub last_offset: i64,
+    pub max_timestamp: i64,
+    pub offset_of_max_timestamp: i64,
+    /// The time the leader stamped the records with, or [NO_TIMESTAMP] if they keep the
+    /// timestamps of the producer.
+    pub log_append_time: i64,
+}
+
+/// The log of a partition: a sequence of segments in a directory named `<topic>-<partition>`.
+///
+/// With tiered storage, segments are copied to remote storage and then deleted locally, so the
+/// log starts before its first local segment: offsets from the log start offset up to the
+/// local log start offset are only available remotely.
+pub struct UnifiedLog {
+    dir: PathBuf,
+    topic_partition: TopicPartition,
+    config: UnifiedLogConfig,
+    segments: BTreeMap<i64, LogSegment>,
+    leader_epoch_cache: LeaderEpochFileCache,
+    ongoing_transactions: OngoingTransactions,
+    aborted_transactions: AbortedTransactions,
+    log_start_offset: i64,
+    local_log_start_offset: i64,
+    /// The disk usage of the log directory holding the log, if it is monitored.
+    log_dir_space: Option<Arc<LogDirSpace>>,
+    tail_cache: Option<TailBatchCache>,
+    time: Arc<dyn Time>,
+}
+
+impl fmt::Debug for UnifiedLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnifiedLog")
+            .field("dir", &self.dir)
+            .field("topic_partition", &self.topic_partition)
+            .field("config", &self.config)
+            .field("segments", &self.segments)
+            .field("leader_epoch_cache", &self.leader_epoch_cache)
+            .field("ongoing_transactions", &self.ongoing_transactions)
+            .field("aborted_transactions", &self.aborted_transactions)
+            .field("log_start_offset", &self.log_start_offset)
+            .field("local_log_start_offset", &self.local_log_start_offset)
+            .field("log_dir_space", &self.log_dir_space)
+            .field("tail_cache", &self.tail_cache)
+            .finish_non_exhaustive()
+    }
+}
+
+impl UnifiedLog {
+    /// Opens the log stored in `dir`, creating the directory and a first segment starting at
+    /// `log_start_offset` if it holds no segment.
+    ///
+    /// The segments are recovered: every batch is validated, see [LogSegment::open].
+    pub fn open(dir: &Path, config: UnifiedLogConfig, log_start_offset: i64) -> Result<Self> {
+        Self::load(dir, config, log_start_offset, false)
+    }
+
+    /// Opens the log like [UnifiedLog::open], skipping the recovery of the segments if the
+    /// log directory `had_clean_shutdown`, see
+    /// [CleanShutdownFileHandler](super::clean_shutdown_file::CleanShutdownFileHandler).
+    pub fn load(
+        dir: &Path,
+        config: UnifiedLogConfig,
+        log_start_offset: i64,
+        had_clean_shutdown: bool,
+    ) -> Result<Self> {
+        let topic_partition = Self::parse_topic_partition_name(dir)?;
+        Self::load_as(
+            dir,
+            topic_partition,
+            config,
+            log_start_offset,
+            had_clean_shutdown,
+        )
+    }
+
+    /// Loads the log of `topic_partition` from `dir`, whose name need not be in the form of
+    /// topic-partition, e.g. the directory of a future replica.
+    pub(crate) fn load_as(
+        dir: &Path,
+        topic_partition: TopicPartition,
+        config: UnifiedLogConfig,
+        log_start_offset: i64,
+        had_clean_shutdown: bool,
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut segments = BTreeMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(LOG_FILE_SUFFIX) {
+                continue;
+            }
+            let Some(base_offset) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<i64>().ok())
+            else {
+                continue;
+            };
+            segments.insert(
+                base_offset,
+                LogSegment::load(
+                    dir,
+                    base_offset,
+                    config.index_interval_bytes,
+                    !had_clean_shutdown,
+                )?,
+            );
+        }
+        if segments.is_empty() {
+            segments.insert(
+                log_start_offset,
+                LogSegment::open(dir, log_start_offset, config.index_interval_bytes)?,
+            );
+        }
+        let local_log_start_offset = *segments.keys().next().expect("at least one segment");
+        info!(
+            "Loaded log {} with {} segments, log end offset {}",
+            dir.display(),
+            segments.len(),
+            segments
+                .values()
+                .last()
+                .expect("at least one segment")
+                .next_offset()
+        );
+        let mut leader_epoch_cache = LeaderEpochFileCache::open(dir)?;
+        let log_end_offset = segments
+            .values()
+            .next_back()
+            .expect("at least one segment")
+            .next_offset();
+        leader_epoch_cache.truncate_from_end(log_end_offset)?;
+        let (ongoing_transactions, aborted_transactions) = Self::scan_transactions(&segments)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            topic_partition,
+            segments,
+            leader_epoch_cache,
+            ongoing_transactions,
+            aborted_transactions,
+            log_start_offset: log_start_offset.min(local_log_start_offset),
+            local_log_start_offset,
+            log_dir_space: None,
+            tail_cache: (config.tail_cache_bytes > 0)
+                .then(|| TailBatchCache::new(config.tail_cache_bytes)),
+            config,
+            time: Arc::new(SystemTime),
+        })
+    }
+
+    /// The transactions left ongoing by the batches of `segments`, found from the headers of
+    /// the batches the segments keep in memory, without reading the batches.
+    fn scan_ongoing_transactions(segments: &BTreeMap<i64, LogSegment>) -> OngoingTransactions {
+        OngoingTransactions::of(segments.values().flat_map(LogSegment::batch_headers))
+    }
+
+    /// The ongoing and the aborted transactions of the batches of `segments`. Only the
+    /// transaction markers are read, to tell the aborts from the commits.
+    fn scan_transactions(
+        segments: &BTreeMap<i64, LogSegment>,
+    ) -> Result<(OngoingTransactions, AbortedTransactions)> {
+        let mut ongoing = OngoingTransactions::default();
+        let mut aborted = AbortedTransactions::default();
+        for segment in segments.values() {
+            for header in segment.batch_headers() {
+                if let Some(first_offset) = ongoing.update(&header)
+                    && let Some(marker) = segment.read(header.base_offset, 0)?.first()
+                {
+                    aborted.complete(marker, first_offset);
+                }
+            }
+        }
+        Ok((ongoing, aborted))
+    }
+
+    /// Parses the topic and partition from the name of a log directory.
+    pub fn parse_topic_partition_name(dir: &Path) -> Result<TopicPartition> {
+        let invalid = || LogError::InvalidDirectory(dir.to_path_buf());
+        let name = dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(invalid)?;
+        let (topic, partition) = name.rsplit_once('-').ok_or_else(invalid)?;
+        match partition.parse::<i32>() {
+            Ok(partition) if !topic.is_empty() && partition >= 0 => {
+                Ok(TopicPartition::new(topic, partition))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn topic_partition(&self) -> &TopicPartition {
+        &self.topic_partition
+    }
+
+    pub fn config(&self) -> &UnifiedLogConfig {
+        &self.config
+    }
+
+    /// Applies the new configs of the topic to the open log. The segment configs apply from
+    /// the next segment rolled; the tail cache is resized right away.
+    pub fn update_config(&mut self, config: UnifiedLogConfig) {
+        if config == self.config {
+            return;
+        }
+        match (&mut self.tail_cache, config.tail_cache_bytes) {
+            (Some(_), 0) => self.tail_cache = None,
+            (Some(tail_cache), max_bytes) => tail_cache.set_max_bytes(max_bytes),
+            (None, 0) => {}
+            (None, max_bytes) => self.tail_cache = Some(TailBatchCache::new(max_bytes)),
+        }
+        info!("Updated the config of {}", self.topic_partition);
+        self.config = config;
+    }
+
+    /// Rejects the appends of producers while the log directory is write protected, see
+    /// [DiskSpaceMonitor](super::disk_space_monitor::DiskSpaceMonitor).
+    pub fn set_log_dir_space(&mut self, log_dir_space: Arc<LogDirSpace>) {
+        self.log_dir_space = Some(log_dir_space);
+    }
+
+    /// Sets the clock the log append time is read from.
+    /// Bounds the tail cache of the log by `memory`, shared with the other logs of the
+    /// broker. The cache is emptied.
+    pub fn set_tail_cache_memory(&mut self, memory: Arc<TailCacheMemory>) {
+        self.tail_cache = self
+            .tail_cache
+            .take()
+            .map(|cache| cache.with_memory(memory));
+    }
+
+    pub fn set_time(&mut self, time: Arc<dyn Time>) {
+        self.time = time;
+    }
+
+    pub fn log_start_offset(&self) -> i64 {
+        self.log_start_offset
+    }
+
+    /// The first offset stored on the local disk.
+    pub fn local_log_start_offset(&self) -> i64 {
+        self.local_log_start_offset
+    }
+
+    /// The offset the next appended record gets.
+    pub fn log_end_offset(&self) -> i64 {
+        self.active_segment().next_offset()
+    }
+
+    pub fn num_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// The size in bytes of the local segments.
+    pub fn size(&self) -> u64 {
+        self.segments.values().map(LogSegment::size).sum()
+    }
+
+    /// The sizes in bytes of the local segments, from the oldest to the active one.
+    pub fn segment_sizes(&self) -> Vec<u64> {
+        self.segments.values().map(LogSegment::size).collect()
+    }
+
+    fn active_segment(&self) -> &LogSegment {
+        self.segments
+            .values()
+            .next_back()
+            .expect("a log has at least one segment")
+    }
+
+    /// Appends a batch on the leader, assigning it the next offsets and stamping it with the
+    /// leader epoch.
+    ///
+    /// Producers may neither write control records nor write to the internal topics. The
+    /// coordinators, which own the internal topics, append through the same path with
+    /// [AppendOrigin::Coordinator]: their control batches must hold a single valid transaction
+    /// marker, and their records in the internal topics must be keyed, as those topics are
+    /// compacted. A batch larger than `max.message.bytes` is rejected, whatever its origin.
+    ///
+    /// With `message.timestamp.type=LogAppendTime`, the batch is stamped with the current
+    /// time, which becomes its max timestamp in the time index.
+    pub fn append_as_leader(
+        &mut self,
+        batch: RecordBatch,
+        leader_epoch: i32,
+        origin: AppendOrigin,
+    ) -> Result<LogAppendInfo> {
+        validate_batch(&self.topic_partition, &batch, origin)?;
+        let data = batch.encode();
+        self.append_encoded_as_leader(batch, data, leader_epoch, origin)
+    }
+
+    /// Appends a batch on the leader like [UnifiedLog::append_as_leader], from `records`, the
+    /// batch as the producer encoded it, e.g. as read in place from a produce request by
+    /// [PartitionProduceData::batches].
+    ///
+    /// The batch is decoded to be validated but never encoded again: the offsets, the leader
+    /// epoch and the log append time are written into a copy of `records`, which is appended.
+    ///
+    /// [PartitionProduceData::batches]: rafka_clients::common::requests::produce_request::PartitionProduceData::batches
+    pub fn append_records_as_leader(
+        &mut self,
+        records: &[u8],
+        leader_epoch: i32,
+        origin: AppendOrigin,
+    ) -> Result<LogAppendInfo> {
+        // Checked before the batch is decoded, which would cost as much as the batch is large.
+        validate_batch_size(
+            &self.topic_partition,
+            records.len(),
+            self.config.max_message_bytes,
+        )?;
+        let (batch, size) = RecordBatch::read_from(records)?;
+        if size != records.len() {
+            return Err(RecordError::Corrupt(format!(
+                "{} bytes follow the batch",
+                records.len() - size
+            ))
+            .into());
+        }
+        validate_batch(&self.topic_partition, &batch, origin)?;
+        self.append_encoded_as_leader(batch, records.to_vec(), leader_epoch, origin)
+    }
+
+    fn append_encoded_as_leader(
+        &mut self,
+        mut batch: RecordBatch,
+        mut data: Vec<u8>,
+        leader_epoch: i32,
+        origin: AppendOrigin,
+    ) -> Result<LogAppendInfo> {
+        validate_batch_size(
+            &self.topic_partition,
+            data.len(),
+            self.config.max_message_bytes,
+        )?;
+        if origin == AppendOrigin::Client
+            && let Some(log_dir_space) = &self.log_dir_space
+        {
+            log_dir_space.check_writable()?;
+        }
+        let base_offset = self.log_end_offset();
+        batch.set_base_offset(base_offset);
+        batch.set_partition_leader_epoch(leader_epoch);
+        RecordBatch::assign_offsets_in_place(&mut data, base_offset, leader_epoch)?;
+        let log_append_time = stamp_log_append_time(
+            &mut batch,
+            self.config.message_timestamp_type,
+            self.time.as_ref(),
+        );
+        if log_append_time != NO_TIMESTAMP {
+            RecordBatch::set_log_append_time_in_place(&mut data, log_append_time)?;
+        }
+        Ok(LogAppendInfo {
+            log_append_time,
+            ..self.append(&batch, &data)?
+        })
+    }
+
+    /// Appends a batch fetched from the leader, which keeps the offsets and the leader epoch
+    /// the leader assigned.
+    pub fn append_as_follower(&mut self, batch: RecordBatch) -> Result<LogAppendInfo> {
+        validate_batch(&self.topic_partition, &batch, AppendOrigin::Replication)?;
+        let log_end_offset = self.log_end_offset();
+        if batch.base_offset() != log_end_offset {
+            return Err(LogError::UnexpectedAppendOffset {
+                expected: log_end_offset,
+                actual: batch.base_offset(),
+            });
+        }
+        self.append(&batch, &batch.encode())
+    }
+
+    /// Appends `batch`, encoded as `data`.
+    fn append(&mut self, batch: &RecordBatch, data: &[u8]) -> Result<LogAppendInfo> {
+        let size = data.len();
+        let active = self.active_segment();
+        if !active.is_empty() && active.size() + size as u64 > self.config.segment_bytes as u64 {
+            self.roll()?;
+        }
+        let appended = self
+            .segments
+            .values_mut()
+            .next_back()
+            .expect("a log has at least one segment")
+            .append(batch, data);
+        match (appended, &self.log_dir_space) {
+            (Err(LogError::Io(e)), Some(log_dir_space))
+                if e.kind() == io::ErrorKind::StorageFull =>
+            {
+                // The failed append left the segment as it was: stop accepting produce
+                // requests rather than failing every one of them on the full disk.
+                log_dir_space.mark_out_of_space();
+                return Err(log_dir_space.write_protected_error());
+            }
+            (appended, _) => appended?,
+        }
+        self.leader_epoch_cache
+            .assign(batch.partition_leader_epoch(), batch.base_offset())?;
+        if let Some(first_offset) = self.ongoing_transactions.update(&batch.header()) {
+            self.aborted_transactions.complete(batch, first_offset);
+        }
+        if let Some(tail_cache) = &mut self.tail_cache {
+            tail_cache.append(batch, size);
+        }
+        Ok(LogAppendInfo {
+            first_offset: batch.base_offset(),
+            last_offset: batch.last_offset(),
+            max_timestamp: batch.max_timestamp(),
+            offset_of_max_timestamp: batch.offset_of_max_timestamp(),
+            log_append_time: NO_TIMESTAMP,
+        })
+    }
+
+    fn roll(&mut self) -> Result<()> {
+        let base_offset = self.log_end_offset();
+        self.active_segment().flush()?;
+        info!(
+            "Rolled new log segment at offset {base_offset} in {}",
+            self.dir.display()
+        );
+        self.segments.insert(
+            base_offset,
+            LogSegment::open(&self.dir, base_offset, self.config.index_interval_bytes)?,
+        );
+        Ok(())
+    }
+
+    /// Reads the local batches from `start_offset` on, up to `max_bytes`, from the tail cache
+    /// if it holds `start_offset`.
+    pub fn read(&self, start_offset: i64, max_bytes: usize) -> Result<Vec<RecordBatch>> {
+        let log_end_offset = self.log_end_offset();
+        if start_offset < self.local_log_start_offset || start_offset > log_end_offset {
+            return Err(LogError::OffsetOutOfRange {
+                offset: start_offset,
+                log_start_offset: self.local_log_start_offset,
+                log_end_offset,
+            });
+        }
+        if let Some(mut batches) = self
+            .tail_cache
+            .as_ref()
+            .and_then(|tail_cache| tail_cache.read(start_offset, max_bytes))
+        {
+            // Like a read from the segments, stop at the end of the segment of the first batch.
+            if let Some(first) = batches.first()
+                && let Some(next_segment) = self.segments.range(first.base_offset() + 1..).next()
+            {
+                let next_segment = *next_segment.0;
+                batches.retain(|batch| batch.base_offset() < next_segment);
+            }
+            return Ok(batches);
+        }
+        let first_segment = self
+            .segments
+            .range(..=start_offset)
+            .next_back()
+            .map_or(start_offset, |(base_offset, _)| *base_offset);
+        for segment in self.segments.range(first_segment..).map(|(_, s)| s) {
+            let batches = segment.read(start_offset, max_bytes)?;
+            if !batches.is_empty() {
+                return Ok(batches);
+            }
+        }
+        Ok(vec![])
+    }
+
+    /// Deletes the local segments whose records are all below `offset`, e.g. once they were
+    /// copied to remote storage. The active segment is never deleted.
+    pub fn delete_local_segments_below(&mut self, offset: i64) -> Result<()> {
+        let active_base_offset = self.active_segment().base_offset();
+        let deletable: Vec<i64> = self
+            .segments
+            .values()
+            .filter(|s| s.base_offset() != active_base_offset && s.next_offset() <= offset)
+            .map(LogSegment::base_offset)
+            .collect();
+        for base_offset in deletable {
+            let segment = self.segments.remove(&base_offset).expect("segment exists");
+            info!(
+                "Deleting local segment {base_offset} of {}",
+                self.dir.display()
+            );
+            segment.delete()?;
+        }
+        self.local_log_start_offset = *self.segments.keys().next().expect("at least one segment");
+        if let Some(tail_cache) = &mut self.tail_cache {
+            tail_cache.evict_below(self.local_log_start_offset);
+        }
+        if !self.config.remote_storage_enable {
+            self.log_start_offset = self.log_start_offset.max(self.local_log_start_offset);
+            self.leader_epoch_cache
+                .truncate_from_start(self.log_start_offset)?;
+            // The transactions left with no record are neither ongoing nor aborted anymore.
+            self.ongoing_transactions = Self::scan_ongoing_transactions(&self.segments);
+            self.aborted_transactions.evict_below(self.log_start_offset);
+        }
+        Ok(())
+    }
+
+    /// Deletes the oldest local segments breaching the retention of the log, see
+    /// [UnifiedLogConfig::local_retention]: those whose records are all older than
+    /// `retention.ms`, then those beyond `retention.bytes`. A segment without timestamps is
+    /// only deleted by size. Returns the number of segments deleted.
+    pub fn delete_retention_breached_segments(&mut self) -> Result<usize> {
+        let (retention_ms, retention_bytes) = self.config.local_retention();
+        let now = self.time.milliseconds();
+        let active_base_offset = self.active_segment().base_offset();
+        let mut excess_bytes = match retention_bytes {
+            ..0 => i64::MIN,
+            retention_bytes => self.size() as i64 - retention_bytes,
+        };
+        let mut delete_below = None;
+        for segment in self
+            .segments
+            .values()
+            .take_while(|s| s.base_offset() != active_base_offset)
+        {
+            let max_timestamp = segment.max_timestamp_so_far().timestamp;
+            let expired = retention_ms >= 0
+                && max_timestamp != NO_TIMESTAMP
+                && now - max_timestamp > retention_ms;
+            let oversized = excess_bytes >= segment.size() as i64;
+            if !expired && !oversized {
+                break;
+            }
+            excess_bytes = excess_bytes.saturating_sub(segment.size() as i64);
+            delete_below = Some(segment.next_offset());
+        }
+        let Some(offset) = delete_below else {
+            return Ok(0);
+        };
+        let num_segments = self.segments.len();
+        self.delete_local_segments_below(offset)?;
+        Ok(num_segments - self.segments.len())
+    }
+
+    /// Removes the records at `target_offset` and above, e.g. the records a follower wrote
+    /// after its log diverged from the leader's. The batch holding `target_offset` is removed as
+    /// a whole. Returns whether anything was removed.
+    pub fn truncate_to(&mut self, target_offset: i64) -> Result<bool> {
+        if target_offset >= self.log_end_offset() {
+            return Ok(false);
+        }
+        info!(
+            "Truncating {} to offset {target_offset}, log end offset {}",
+            self.topic_partition,
+            self.log_end_offset()
+        );
+        if let Some(tail_cache) = &mut self.tail_cache {
+            tail_cache.truncate_to(target_offset);
+        }
+        if target_offset <= self.local_log_start_offset {
+            // Nothing is left: start over with an empty segment at the target offset.
+            for (_, segment) in std::mem::take(&mut self.segments) {
+                segment.delete()?;
+            }
+            self.segments.insert(
+                target_offset,
+                LogSegment::open(&self.dir, target_offset, self.config.index_interval_bytes)?,
+            );
+            self.local_log_start_offset = target_offset;
+            self.log_start_offset = self.log_start_offset.min(target_offset);
+        } else {
+            let removed: Vec<i64> = self
+                .segments
+                .range(target_offset..)
+                .map(|(base_offset, _)| *base_offset)
+                .collect();
+            for base_offset in removed {
+                self.segments
+                    .remove(&base_offset)
+                    .expect("segment exists")
+                    .delete()?;
+            }
+            self.segments
+                .values_mut()
+                .next_back()
+                .expect("a log has at least one segment")
+                .truncate_to(target_offset)?;
+        }
+        self.leader_epoch_cache
+            .truncate_from_end(self.log_end_offset())?;
+        // The truncation may have removed the marker ending a transaction as well as its start.
+        self.ongoing_transactions = Self::scan_ongoing_transactions(&self.segments);
+        self.aborted_transactions.truncate_to(target_offset);
+        self.aborted_transactions.evict_below(self.log_start_offset);
+        Ok(true)
+    }
+
+    /// The latest leader epoch which wrote to the log.
+    pub fn latest_epoch(&self) -> Option<i32> {
+        self.leader_epoch_cache.latest_epoch()
+    }
+
+    /// Answers an OffsetsForLeaderEpoch request: the largest epoch not above `leader_epoch` and
+    /// the offset it ended at, or `None` if the log has no such epoch.
+    pub fn end_offset_for_epoch(&self, leader_epoch: i32) -> Option<(i32, i64)> {
+        self.leader_epoch_cache
+            .end_offset_for(leader_epoch, self.log_end_offset())
+    }
+
+    /// The first offset of the oldest transaction of the local log which is neither committed
+    /// nor aborted yet.
+    pub fn first_unstable_offset(&self) -> Option<i64> {
+        self.ongoing_transactions.first_unstable_offset()
+    }
+
+    /// The transactions aborted with records from `start_offset` up to `upper_bound_offset`,
+    /// which a read_committed consumer of those records needs to skip the aborted ones.
+    pub fn aborted_transactions(
+        &self,
+        start_offset: i64,
+        upper_bound_offset: i64,
+    ) -> Vec<AbortedTransaction> {
+        self.aborted_transactions
+            .overlapping(start_offset, upper_bound_offset)
+    }
+
+    /// The leader epoch of the local batch holding `offset`.
+    pub fn leader_epoch_for_offset(&self, offset: i64) -> Option<i32> {
+        self.segments
+            .range(..=offset)
+            .next_back()
+            .and_then(|(_, segment)| segment.leader_epoch_for_offset(offset))
+    }
+
+    /// Looks up the offset a ListOffsets request asks for with `target_timestamp`: either a
+    /// real timestamp, for which the first record with a timestamp at or above it is returned,
+    /// or one of the special timestamps of `list_offsets_request`.
+    ///
+    /// When remote storage is enabled, `remote` serves the part of the log which was moved to
+    /// remote storage. `None` is returned when no offset matches, which a ListOffsets response
+    /// reports as offset -1.
+    pub fn fetch_offset_by_timestamp(
+        &self,
+        target_timestamp: i64,
+        remote: Option<&dyn RemoteLogReader>,
+    ) -> Result<Option<TimestampAndOffset>> {
+        let remote = remote.filter(|_| self.config.remote_storage_enable);
+        let epoch_for_offset = |offset: i64| {
+            self.leader_epoch_for_offset(offset)
+                .or_else(|| remote.and_then(|r| r.leader_epoch_for_offset(offset)))
+        };
+        match target_timestamp {
+            EARLIEST_TIMESTAMP => Ok(Some(TimestampAndOffset::new(
+                NO_TIMESTAMP,
+                self.log_start_offset,
+                epoch_for_offset(self.log_start_offset),
+            ))),
+            EARLIEST_LOCAL_TIMESTAMP => Ok(Some(TimestampAndOffset::new(
+                NO_TIMESTAMP,
+                self.local_log_start_offset,
+                epoch_for_offset(self.local_log_start_offset),
+            ))),
+            LATEST_TIMESTAMP => {
+                let log_end_offset = self.log_end_offset();
+                Ok(Some(TimestampAndOffset::new(
+                    NO_TIMESTAMP,
+                    log_end_offset,
+                    epoch_for_offset(log_end_offset - 1),
+                )))
+            }
+            LATEST_TIERED_TIMESTAMP => {
+                let highest = remote.and_then(|r| r.highest_offset_in_remote_storage());
+                Ok(Some(TimestampAndOffset::new(
+                    NO_TIMESTAMP,
+                    highest.unwrap_or(-1),
+                    highest.and_then(epoch_for_offset),
+                )))
+            }
+            MAX_TIMESTAMP => Ok(self
+                .segments
+                .values()
+                .map(LogSegment::max_timestamp_so_far)
+                .filter(|max| max.timestamp != NO_TIMESTAMP)
+                .reduce(|max, other| {
+                    if other.timestamp > max.timestamp {
+                        other
+                    } else {
+                        max
+                    }
+                })
+                .map(|max| {
+                    TimestampAndOffset::new(
+                        max.timestamp,
+                        max.offset,
+                        self.leader_epoch_for_offset(max.offset),
+                    )
+                })),
+            timestamp if timestamp >= 0 => {
+                if let Some(remote) = remote
+                    && let Some(found) =
+                        remote.find_offset_by_timestamp(timestamp, self.log_start_offset)?
+                {
+                    return Ok(Some(found));
+                }
+                let starting_offset = self.log_start_offset.max(self.local_log_start_offset);
+                for segment in self.segments.values() {
+                    if segment.max_timestamp_so_far().timestamp < timestamp {
+                        continue;
+                    }
+                    if let Some(found) =
+                        segment.find_offset_by_timestamp(timestamp, starting_offset)?
+                    {
+                        return Ok(Some(found));
+                    }
+                }
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Estimates the timestamp of the record at `offset` from the time indexes of the local
+    /// segments, without reading the log.
+    ///
+    /// The estimate is interpolated between the index entries around the offset, as if the
+    /// records between them were appended at a steady rate, so it follows the throughput of
+    /// the partition. `None` is returned if no record of the log carries a timestamp.
+    pub fn estimate_timestamp(&self, offset: i64) -> Option<i64> {
+        let mut before = None;
+        let mut after = None;
+        for segment in self.segments.range(..=offset).rev().map(|(_, s)| s) {
+            let (entry_before, entry_after) = segment.time_index().entries_around(offset);
+            after = after.or(entry_after);
+            if entry_before.is_some() {
+                before = entry_before;
+                break;
+            }
+        }
+        if after.is_none() {
+            after = self
+                .segments
+                .range(offset + 1..)
+                .find_map(|(_, s)| s.time_index().entries_around(offset).1);
+        }
+        match (before, after) {
+            (Some(before), Some(after)) if after.offset > before.offset => {
+                let elapsed = (after.timestamp - before.timestamp) as i128
+                    * (offset - before.offset) as i128
+                    / (after.offset - before.offset) as i128;
+                Some(before.timestamp + elapsed as i64)
+            }
+            (Some(entry), _) | (None, Some(entry)) => Some(entry.timestamp),
+            (None, None) => None,
+        }
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.active_segment().flush()
+    }
+}
+
+/// Checks that `origin` may append `batch` to the log of `topic_partition`, whatever the log
+/// implementation.
+pub(crate) fn validate_batch(
+    topic_partition: &TopicPartition,
+    batch: &RecordBatch,
+    origin: AppendOrigin,
+) -> Result<()> {
+    let topic = topic_partition.topic();
+    if batch.count() == 0 {
+        return Err(LogError::InvalidRecord(format!(
+            "Cannot append an empty batch to {topic_partition}"
+        )));
+    }
+    match origin {
+        AppendOrigin::Client => {
+            if topic::is_internal(topic) {
+                return Err(LogError::InvalidTopic(format!(
+                    "Cannot append to internal topic {topic}"
+                )));
+            }
+            if batch.is_control() {
+                return Err(LogError::InvalidRecord(
+                    "Clients are not allowed to write control records".to_string(),
+                ));
+            }
+        }
+        AppendOrigin::Coordinator if batch.is_control() => {
+            if !batch.is_transactional() || batch.producer_id() == NO_PRODUCER_ID {
+                return Err(LogError::InvalidRecord(
+                    "Control batches must be transactional and have a producer id".to_string(),
+                ));
+            }
+            if batch.count() != 1 {
+                return Err(LogError::InvalidRecord(format!(
+                    "Control batches must hold exactly one record, found {}",
+                    batch.count()
+                )));
+            }
+            EndTransactionMarker::from_record(&batch.records()[0])?;
+        }
+        AppendOrigin::Coordinator => {
+            if topic::is_internal(topic) && batch.records().iter().any(|r| r.key.is_none()) {
+                return Err(LogError::InvalidRecord(format!(
+                    "Records written to internal topic {topic} must have a key"
+                )));
+            }
+        }
+        AppendOrigin::Replication => {}
+    }
+    Ok(())
+}
+
+/// Stamps `batch` with the current time if the records of the log carry their log append
+/// time, returning that time, or [NO_TIMESTAMP] if they keep the timestamps of the producer.
+pub(crate) fn stamp_log_append_time(
+    batch: &mut RecordBatch,
+    timestamp_type: TimestampType,
+    time: &dyn Time,
+) -> i64 {
+    match timestamp_type {
+        TimestampType::CreateTime => NO_TIMESTAMP,
+        TimestampType::LogAppendTime => {
+            let now = time.milliseconds();
+            batch.set_log_append_time(now);
+            now
+        }
+    }
+}
+
+/// Rejects a batch of `size` bytes once encoded if it is larger than `max_message_bytes`: the
+/// consumers, whose fetches are sized for `max.message.bytes`, could fail to read it.
+///
+/// The check is on the leader only: a follower must accept whatever the leader appended,
+/// even if its own `max.message.bytes` is lower, or it could never catch up.
+pub(crate) fn validate_batch_size(
+    topic_partition: &TopicPartition,
+    size: usize,
+    max_message_bytes: usize,
+) -> Result<()> {
+    if size > max_message_bytes {
+        return Err(LogError::RecordTooLarge {
+            topic_partition: topic_partition.clone(),
+            size,
+            max_message_bytes,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::internals::log::partition_log::PartitionLog;
+    use rafka_clients::common::record::control_record::ControlRecordType;
+    use rafka_clients::common::record::record_batch::Record;
+    use rafka_clients::common::utils::time::MockTime;
+
+    fn records(timestamps: &[i64]) -> RecordBatch {
+        RecordBatch::new(
+            0,
+            timestamps
+                .iter()
+                .map(|ts| Record::new(*ts, None, Some(&[0; 100])))
+                .collect(),
+        )
+    }
+
+    fn config(remote_storage_enable: bool) -> UnifiedLogConfig {
+        UnifiedLogConfig {
+            segment_bytes: 300,
+            index_interval_bytes: 1,
+            remote_storage_enable,
+            ..Default::default()
+        }
+    }
+
+    /// A remote store holding offsets 0 to 3 with timestamps 10, 20, 30 and 40 in epoch 1.
+    struct FakeRemote;
+
+    impl RemoteLogReader for FakeRemote {
+        fn highest_offset_in_remote_storage(&self) -> Option<i64> {
+            Some(3)
+        }
+
+        fn leader_epoch_for_offset(&self, offset: i64) -> Option<i32> {
+            (0..=3).contains(&offset).then_some(1)
+        }
+
+        fn find_offset_by_timestamp(
+            &self,
+            timestamp: i64,
+            starting_offset: i64,
+        ) -> Result<Option<TimestampAndOffset>> {
+            Ok((starting_offset..=3)
+                .map(|offset| (offset, (offset + 1) * 10))
+                .find(|(_, ts)| *ts >= timestamp)
+                .map(|(offset, ts)| TimestampAndOffset::new(ts, offset, Some(1))))
+        }
+    }
+
+    fn offset(log: &UnifiedLog, timestamp: i64, remote: Option<&dyn RemoteLogReader>) -> i64 {
+        log.fetch_offset_by_timestamp(timestamp, remote)
+            .unwrap()
+            .map_or(-1, |r| r.offset)
+    }
+
+    #[test]
+    fn test_append_roll_and_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        for i in 0..3 {
+            let info = log
+                .append_as_leader(records(&[i, i + 1]), 2, AppendOrigin::Client)
+                .unwrap();
+            assert_eq!(i * 2, info.first_offset);
+        }
+        assert_eq!(6, log.log_end_offset());
+        assert_eq!(3, log.num_segments());
+        let batches = log.read(3, 1).unwrap();
+        assert_eq!(2, batches[0].base_offset());
+        assert_eq!(2, batches[0].partition_leader_epoch());
+        assert!(matches!(
+            log.read(7, 100),
+            Err(LogError::OffsetOutOfRange { .. })
+        ));
+        drop(log);
+
+        let log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        assert_eq!(6, log.log_end_offset());
+        assert_eq!(Some(2), log.leader_epoch_for_offset(5));
+    }
+
+    #[test]
+    fn test_fetch_offset_by_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        log.append_as_leader(records(&[100, 300]), 1, AppendOrigin::Client)
+            .unwrap();
+        log.append_as_leader(records(&[200, 500]), 2, AppendOrigin::Client)
+            .unwrap();
+        log.append_as_leader(records(&[500, 400]), 3, AppendOrigin::Client)
+            .unwrap();
+
+        assert_eq!(0, offset(&log, EARLIEST_TIMESTAMP, None));
+        assert_eq!(0, offset(&log, EARLIEST_LOCAL_TIMESTAMP, None));
+        assert_eq!(6, offset(&log, LATEST_TIMESTAMP, None));
+        assert_eq!(-1, offset(&log, LATEST_TIERED_TIMESTAMP, None));
+
+        let max = log
+            .fetch_offset_by_timestamp(MAX_TIMESTAMP, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(TimestampAndOffset::new(500, 3, Some(2)), max);
+
+        assert_eq!(0, offset(&log, 0, None));
+        assert_eq!(1, offset(&log, 250, None));
+        assert_eq!(3, offset(&log, 301, None));
+        assert_eq!(-1, offset(&log, 501, None));
+    }
+
+    #[test]
+    fn test_estimate_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        assert_eq!(None, log.estimate_timestamp(0));
+        // Every batch is indexed, by its max timestamp and the offset of its first record.
+        for timestamps in [[100, 200], [300, 400], [1_300, 1_400]] {
+            log.append_as_leader(records(&timestamps), 1, AppendOrigin::Client)
+                .unwrap();
+        }
+        assert_eq!(Some(200), log.estimate_timestamp(0));
+        assert_eq!(Some(400), log.estimate_timestamp(3));
+        // Between two entries, the records are assumed to be appended at a steady rate.
+        assert_eq!(Some(900), log.estimate_timestamp(4));
+        assert_eq!(Some(1_400), log.estimate_timestamp(5));
+        assert_eq!(Some(1_400), log.estimate_timestamp(100));
+    }
+
+    #[test]
+    fn test_fetch_offset_by_timestamp_with_remote_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(true), 0).unwrap();
+        for ts in [10, 30, 50, 70] {
+            log.append_as_leader(records(&[ts, ts + 10]), 1, AppendOrigin::Client)
+                .unwrap();
+        }
+        // The segments holding offsets 0 to 3 were copied to remote storage.
+        log.delete_local_segments_below(4).unwrap();
+        assert_eq!(0, log.log_start_offset());
+        assert_eq!(4, log.local_log_start_offset());
+
+        let remote: Option<&dyn RemoteLogReader> = Some(&FakeRemote);
+        assert_eq!(0, offset(&log, EARLIEST_TIMESTAMP, remote));
+        assert_eq!(4, offset(&log, EARLIEST_LOCAL_TIMESTAMP, remote));
+        assert_eq!(3, offset(&log, LATEST_TIERED_TIMESTAMP, remote));
+        assert_eq!(
+            Some(1),
+            log.fetch_offset_by_timestamp(EARLIEST_TIMESTAMP, remote)
+                .unwrap()
+                .unwrap()
+                .leader_epoch
+        );
+
+        // Timestamps are looked up in remote storage first, then in the local log.
+        assert_eq!(1, offset(&log, 15, remote));
+        assert_eq!(4, offset(&log, 45, remote));
+        assert_eq!(7, offset(&log, 80, remote));
+        // The local log only knows its own max timestamp.
+        assert_eq!(7, offset(&log, MAX_TIMESTAMP, remote));
+        assert_eq!(
+            4,
+            offset(&log, 15, None),
+            "without remote storage the lookup starts at the local log"
+        );
+    }
+
+    #[test]
+    fn test_append_origin_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = |producer_id| {
+            RecordBatch::end_transaction_marker(
+                producer_id,
+                0,
+                EndTransactionMarker::new(ControlRecordType::Commit, 3),
+                1_000,
+            )
+        };
+
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        assert!(matches!(
+            log.append_as_leader(marker(1), 0, AppendOrigin::Client),
+            Err(LogError::InvalidRecord(_))
+        ));
+        assert!(matches!(
+            log.append_as_leader(RecordBatch::new(0, vec![]), 0, AppendOrigin::Client),
+            Err(LogError::InvalidRecord(_))
+        ));
+        log.append_as_leader(marker(1), 0, AppendOrigin::Coordinator)
+            .unwrap();
+        let mut invalid = marker(1);
+        invalid.set_transactional(false);
+        assert!(matches!(
+            log.append_as_leader(invalid, 0, AppendOrigin::Coordinator),
+            Err(LogError::InvalidRecord(_))
+        ));
+
+        let mut log = UnifiedLog::open(
+            &dir.path()
+                .join(topic::GROUP_METADATA_TOPIC_NAME.to_string() + "-3"),
+            config(false),
+            0,
+        )
+        .unwrap();
+        assert_eq!(3, log.topic_partition().partition());
+        let keyed = RecordBatch::new(0, vec![Record::new(1_000, Some(b"group"), None)]);
+        assert!(matches!(
+            log.append_as_leader(keyed.clone(), 0, AppendOrigin::Client),
+            Err(LogError::InvalidTopic(_))
+        ));
+        log.append_as_leader(keyed, 0, AppendOrigin::Coordinator)
+            .unwrap();
+        assert!(matches!(
+            log.append_as_leader(records(&[1_000]), 0, AppendOrigin::Coordinator),
+            Err(LogError::InvalidRecord(_))
+        ));
+    }
+
+    #[test]
+    fn test_first_unstable_offset_survives_reopen_and_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let transactional = |producer_id| {
+            let mut batch = records(&[1_000]).with_producer(producer_id, 0, 0);
+            batch.set_transactional(true);
+            batch
+        };
+        let marker = RecordBatch::end_transaction_marker(
+            1,
+            0,
+            EndTransactionMarker::new(ControlRecordType::Abort, 3),
+            1_000,
+        );
+
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        log.append_as_leader(records(&[1_000]), 0, AppendOrigin::Client)
+            .unwrap();
+        log.append_as_leader(transactional(1), 0, AppendOrigin::Client)
+            .unwrap();
+        log.append_as_leader(transactional(2), 0, AppendOrigin::Client)
+            .unwrap();
+        assert_eq!(Some(1), log.first_unstable_offset());
+        log.append_as_leader(marker, 0, AppendOrigin::Coordinator)
+            .unwrap();
+        assert_eq!(Some(2), log.first_unstable_offset());
+        assert_eq!(1, PartitionLog::last_stable_offset(&log, 1));
+        assert_eq!(2, PartitionLog::last_stable_offset(&log, 4));
+        let aborted = vec![AbortedTransaction {
+            producer_id: 1,
+            first_offset: 1,
+            last_offset: 3,
+        }];
+        assert_eq!(aborted, log.aborted_transactions(0, 4));
+        drop(log);
+
+        for had_clean_shutdown in [false, true] {
+            let log = UnifiedLog::load(
+                &dir.path().join("foo-0"),
+                config(false),
+                0,
+                had_clean_shutdown,
+            )
+            .unwrap();
+            assert_eq!(Some(2), log.first_unstable_offset());
+            assert_eq!(aborted, log.aborted_transactions(0, 4));
+            assert!(log.aborted_transactions(4, 5).is_empty());
+        }
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        // Truncating the marker away leaves the transaction of producer 1 ongoing again.
+        log.truncate_to(3).unwrap();
+        assert_eq!(Some(1), log.first_unstable_offset());
+        assert!(log.aborted_transactions(0, 4).is_empty());
+        log.truncate_to(1).unwrap();
+        assert_eq!(None, log.first_unstable_offset());
+    }
+
+    #[test]
+    fn test_deleted_segments_take_their_transactions_along() {
+        let dir = tempfile::tempdir().unwrap();
+        let transactional = |producer_id| {
+            let mut batch = records(&[1_000]).with_producer(producer_id, 0, 0);
+            batch.set_transactional(true);
+            batch
+        };
+        let abort = RecordBatch::end_transaction_marker(
+            1,
+            0,
+            EndTransactionMarker::new(ControlRecordType::Abort, 3),
+            1_000,
+        );
+
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        log.append_as_leader(transactional(1), 0, AppendOrigin::Client)
+            .unwrap();
+        log.append_as_leader(abort, 0, AppendOrigin::Coordinator)
+            .unwrap();
+        log.append_as_leader(transactional(2), 0, AppendOrigin::Client)
+            .unwrap();
+        for _ in 0..2 {
+            log.append_as_leader(records(&[1_000]), 0, AppendOrigin::Client)
+                .unwrap();
+        }
+        // The first segment holds the aborted transaction and its marker, the next ones a
+        // batch each.
+        assert_eq!(4, log.num_segments());
+        assert_eq!(Some(2), log.first_unstable_offset());
+        assert_eq!(1, log.aborted_transactions(0, 5).len());
+
+        log.delete_local_segments_below(2).unwrap();
+        assert_eq!(2, log.log_start_offset());
+        assert!(log.aborted_transactions(0, 5).is_empty());
+        assert_eq!(Some(2), log.first_unstable_offset());
+        // The only batch of the ongoing transaction of producer 2 goes, and with it the
+        // transaction.
+        log.delete_local_segments_below(3).unwrap();
+        assert_eq!(None, log.first_unstable_offset());
+    }
+
+    #[test]
+    fn test_append_records_as_leader() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = UnifiedLogConfig {
+            segment_bytes: 1_000,
+            max_message_bytes: 300,
+            message_timestamp_type: TimestampType::LogAppendTime,
+            ..config(false)
+        };
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config, 0).unwrap();
+        log.set_time(Arc::new(MockTime::with_start(0, 5_000, 0)));
+        log.append_as_leader(records(&[10]), 1, AppendOrigin::Client)
+            .unwrap();
+
+        let encoded = records(&[100, 200]).encode();
+        let size = log.active_segment().size();
+        let info = log
+            .append_records_as_leader(&encoded, 2, AppendOrigin::Client)
+            .unwrap();
+        assert_eq!(
+            (1, 2, 5_000),
+            (info.first_offset, info.last_offset, info.log_append_time)
+        );
+        let read = log.read(1, usize::MAX).unwrap();
+        assert_eq!(1, read.len());
+        assert_eq!(1, read[0].base_offset());
+        assert_eq!(2, read[0].partition_leader_epoch());
+        assert_eq!(5_000, read[0].max_timestamp());
+        assert!(read[0].records().iter().all(|r| r.timestamp == 5_000));
+        // The batch was appended as it was received.
+        assert_eq!(size + encoded.len() as u64, log.active_segment().size());
+
+        let too_large = records(&[1, 2, 3]).encode();
+        assert!(matches!(
+            log.append_records_as_leader(&too_large, 2, AppendOrigin::Client),
+            Err(LogError::RecordTooLarge { size, .. }) if size == too_large.len()
+        ));
+        let mut corrupt = encoded.clone();
+        corrupt.push(0);
+        assert!(matches!(
+            log.append_records_as_leader(&corrupt, 2, AppendOrigin::Client),
+            Err(LogError::Record(RecordError::Corrupt(_)))
+        ));
+        assert_eq!(3, log.log_end_offset());
+    }
+
+    #[test]
+    fn test_write_protected_log_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        let log_dir_space = Arc::new(LogDirSpace::new(dir.path().to_path_buf(), 100));
+        log.set_log_dir_space(Arc::clone(&log_dir_space));
+        log.append_as_leader(records(&[10]), 0, AppendOrigin::Client)
+            .unwrap();
+
+        log_dir_space.mark_out_of_space();
+        assert!(matches!(
+            log.append_as_leader(records(&[20]), 0, AppendOrigin::Client),
+            Err(LogError::LogDirWriteProtected { free_bytes: 0, .. })
+        ));
+        // Replicated and coordinator appends are not rejected.
+        let mut replicated = records(&[20]);
+        replicated.set_base_offset(1);
+        log.append_as_follower(replicated).unwrap();
+        assert_eq!(2, log.log_end_offset());
+    }
+
+    #[cfg(feature = "fault-injection")]
+    #[test]
+    fn test_full_disk_write_protects_log_dir() {
+        use rafka_server_common::fault_injection::{Fault, FaultPoint, inject};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo-0");
+        let mut log = UnifiedLog::open(&path, config(false), 0).unwrap();
+        let log_dir_space = Arc::new(LogDirSpace::new(dir.path().to_path_buf(), 100));
+        log.set_log_dir_space(Arc::clone(&log_dir_space));
+        let _guard = inject(
+            FaultPoint::SegmentAppend,
+            &path,
+            Fault::Error(io::ErrorKind::StorageFull),
+            Some(1),
+        );
+        assert!(matches!(
+            log.append_as_leader(records(&[10]), 0, AppendOrigin::Client),
+            Err(LogError::LogDirWriteProtected { .. })
+        ));
+        assert!(log_dir_space.is_write_protected());
+        assert_eq!(0, log.log_end_offset());
+    }
+
+    #[test]
+    fn test_parse_topic_partition_name() {
+        let parse = |name: &str| UnifiedLog::parse_topic_partition_name(Path::new(name)).ok();
+        assert_eq!(
+            Some(TopicPartition::new("foo-bar", 12)),
+            parse("/data/foo-bar-12")
+        );
+        assert_eq!(None, parse("/data/foo"));
+        assert_eq!(None, parse("/data/-1"));
+        assert_eq!(None, parse("/data/foo-x"));
+    }
+
+    #[test]
+    fn test_truncate_to_and_epoch_end_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo-0");
+        let mut log = UnifiedLog::open(&path, config(false), 0).unwrap();
+        for (epoch, ts) in [(1, 10), (1, 20), (3, 30), (5, 40)] {
+            log.append_as_leader(records(&[ts, ts + 1]), epoch, AppendOrigin::Client)
+                .unwrap();
+        }
+        assert_eq!(4, log.num_segments());
+        assert_eq!(Some((1, 4)), log.end_offset_for_epoch(2));
+        assert_eq!(Some((5, 8)), log.end_offset_for_epoch(5));
+        assert_eq!(None, log.end_offset_for_epoch(6));
+
+        let mut batch = records(&[50]);
+        batch.set_base_offset(7);
+        assert!(matches!(
+            log.append_as_follower(batch),
+            Err(LogError::UnexpectedAppendOffset {
+                expected: 8,
+                actual: 7
+            })
+        ));
+
+        assert!(log.truncate_to(5).unwrap());
+        assert_eq!(4, log.log_end_offset());
+        // The segment at offset 4 is kept, empty.
+        assert_eq!(3, log.num_segments());
+        assert_eq!(Some(1), log.latest_epoch());
+        assert_eq!(Some((1, 4)), log.end_offset_for_epoch(1));
+        assert_eq!(None, log.end_offset_for_epoch(3));
+        assert_eq!(
+            TimestampAndOffset::new(21, 3, Some(1)),
+            log.fetch_offset_by_timestamp(MAX_TIMESTAMP, None)
+                .unwrap()
+                .unwrap()
+        );
+        drop(log);
+
+        let mut log = UnifiedLog::open(&path, config(false), 0).unwrap();
+        assert_eq!(4, log.log_end_offset());
+        assert_eq!(Some(1), log.latest_epoch());
+        assert!(log.truncate_to(0).unwrap());
+        assert_eq!(0, log.log_end_offset());
+        assert_eq!(1, log.num_segments());
+        assert_eq!(None, log.latest_epoch());
+    }
+
+    #[test]
+    fn test_tail_cache_reads_match_the_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo-0");
+        let batch_size = records(&[0]).size_in_bytes();
+        let cached_config = UnifiedLogConfig {
+            tail_cache_bytes: batch_size * 2,
+            ..config(false)
+        };
+        let mut log = UnifiedLog::open(&path, cached_config.clone(), 0).unwrap();
+        for ts in 0..4 {
+            log.append_as_leader(records(&[ts]), 1, AppendOrigin::Client)
+                .unwrap();
+        }
+        let tail_cache = log.tail_cache.as_ref().unwrap();
+        assert_eq!(2, tail_cache.len());
+        assert!(tail_cache.read(1, usize::MAX).is_none());
+        let reads = |log: &UnifiedLog| {
+            (0..=log.log_end_offset())
+                .map(|offset| log.read(offset, usize::MAX).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let cached_reads = reads(&log);
+
+        // The truncated batches are no longer served.
+        log.truncate_to(3).unwrap();
+        log.append_as_leader(records(&[100]), 2, AppendOrigin::Client)
+            .unwrap();
+        let batches = log.read(3, usize::MAX).unwrap();
+        assert_eq!(100, batches[0].max_timestamp());
+        assert_eq!(2, log.tail_cache.as_ref().unwrap().len());
+        drop(log);
+
+        let log = UnifiedLog::open(&path, config(false), 0).unwrap();
+        assert!(log.tail_cache.is_none());
+        assert_eq!(cached_reads[..3], reads(&log)[..3]);
+        assert_eq!(batches, log.read(3, usize::MAX).unwrap());
+    }
+
+    #[test]
+    fn test_update_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        let batch_size = records(&[0]).size_in_bytes();
+        let cached_config = UnifiedLogConfig {
+            tail_cache_bytes: batch_size * 2,
+            ..config(false)
+        };
+        log.update_config(UnifiedLogConfig {
+            max_message_bytes: 10,
+            ..cached_config.clone()
+        });
+        assert!(matches!(
+            log.append_as_leader(records(&[0]), 1, AppendOrigin::Client),
+            Err(LogError::RecordTooLarge { .. })
+        ));
+
+        log.update_config(cached_config);
+        for ts in 0..3 {
+            log.append_as_leader(records(&[ts]), 1, AppendOrigin::Client)
+                .unwrap();
+        }
+        assert_eq!(2, log.tail_cache.as_ref().unwrap().len());
+        log.update_config(config(false));
+        assert!(log.tail_cache.is_none());
+        assert_eq!(&config(false), log.config());
+    }
+
+    #[test]
+    fn test_retention_configs() {
+        let config = UnifiedLogConfig::default()
+            .with_override(topic_config::RETENTION_MS_CONFIG, "1000")
+            .unwrap()
+            .with_override(topic_config::LOCAL_LOG_RETENTION_BYTES_CONFIG, "10")
+            .unwrap();
+        assert_eq!((1000, -1), config.local_retention());
+        let remote = config
+            .with_override(topic_config::REMOTE_LOG_STORAGE_ENABLE_CONFIG, "true")
+            .unwrap();
+        assert_eq!((1000, 10), remote.local_retention());
+
+        for (name, value) in [
+            (topic_config::RETENTION_MS_CONFIG, "-2"),
+            (topic_config::RETENTION_BYTES_CONFIG, "big"),
+            (topic_config::LOCAL_LOG_RETENTION_MS_CONFIG, "-3"),
+        ] {
+            assert!(matches!(
+                UnifiedLogConfig::default().with_override(name, value),
+                Err(LogError::InvalidConfig { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_delete_retention_breached_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = UnifiedLog::open(&dir.path().join("foo-0"), config(false), 0).unwrap();
+        log.set_time(Arc::new(MockTime::with_start(0, 1_000, 0)));
+        // A segment a batch.
+        for ts in [100, 200, 300, 400] {
+            log.append_as_leader(records(&[ts]), 1, AppendOrigin::Client)
+                .unwrap();
+        }
+        assert_eq!(4, log.num_segments());
+        assert_eq!(0, log.delete_retention_breached_segments().unwrap());
+
+        log.update_config(UnifiedLogConfig {
+            retention_ms: 750,
+            ..config(false)
+        });
+        assert_eq!(2, log.delete_retention_breached_segments().unwrap());
+        assert_eq!(2, log.log_start_offset());
+
+        // The active segment is kept, however small the retention.
+        let active_size = *log.segment_sizes().last().unwrap() as i64;
+        log.update_config(UnifiedLogConfig {
+            retention_bytes: active_size,
+            ..config(false)
+        });
+        assert_eq!(1, log.delete_retention_breached_segments().unwrap());
+        assert_eq!(3, log.log_start_offset());
+        assert_eq!(1, log.num_segments());
+        log.update_config(UnifiedLogConfig {
+            retention_bytes: 0,
+            retention_ms: 0,
+            ..config(false)
+        });
+        assert_eq!(0, log.delete_retention_breached_segments().unwrap());
+    }
+}
@@ -1 +1,2 @@
+pub(crate) mod epoch;
 pub(crate) mod log;